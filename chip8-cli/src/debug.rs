@@ -0,0 +1,330 @@
+//! Interactive terminal debugger.
+use std::{
+    cell::RefCell,
+    fmt::Write as FmtWrite,
+    io::{self, BufRead, Write},
+    rc::Rc,
+};
+
+use chip8::{constants::*, prelude::*, Flow, KeyCode, KeyState, MemoryChange};
+
+/// Maximum number of instructions executed by `continue`,
+/// so programs stuck in a loop return control to the user.
+const CONTINUE_LIMIT: usize = 1_000_000;
+
+/// Number of instructions shown in the disassembly view.
+const DISASM_LINES: usize = 10;
+
+/// Number of bytes shown before the program counter in the disassembly view.
+const DISASM_BEFORE: usize = 8;
+
+/// Number of bytes per row in the memory view.
+const MEMORY_COLUMNS: usize = 8;
+
+/// Number of rows in the memory view.
+const MEMORY_ROWS: usize = 4;
+
+static HELP: &str = "\
+commands:
+    s, step [N]       execute N instructions (default 1)
+    c, continue       run until a breakpoint, key wait or interrupt
+    b, break ADDR     set a breakpoint at the hex address
+    d, delete ADDR    remove the breakpoint at the hex address
+    m, mem [ADDR]     show memory at the hex address, or follow I
+    w, write ADDR B.. write the hex bytes to memory at the hex address
+    k, key KEY        press and release the hex key
+    h, help           show this help
+    q, quit           exit the debugger
+    <enter>           repeat the last command";
+
+/// Terminal debugger session.
+pub struct DebugSession {
+    vm: Chip8Vm,
+    /// Start address of the memory view. Follows register `I` when `None`.
+    memory_view: Option<usize>,
+    /// Result of the last command, shown below the views.
+    message: String,
+    /// Command repeated when the input is empty.
+    last_command: String,
+    /// Writes to memory by the last command, marked in the memory view.
+    changes: Rc<RefCell<Vec<MemoryChange>>>,
+}
+
+impl DebugSession {
+    pub fn new(mut vm: Chip8Vm) -> Self {
+        let changes: Rc<RefCell<Vec<MemoryChange>>> = Rc::default();
+        let sink = changes.clone();
+        vm.add_memory_listener(move |change| sink.borrow_mut().push(*change));
+
+        Self {
+            vm,
+            memory_view: None,
+            message: String::from("type 'h' for help"),
+            last_command: String::new(),
+            changes,
+        }
+    }
+
+    /// Read commands from standard input until the user quits.
+    pub fn run(&mut self) -> Chip8Result<()> {
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+
+        loop {
+            let screen = self.draw()?;
+            print!("{screen}> ");
+            io::stdout().flush()?;
+
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => break, // End of input
+            };
+
+            let line = match line.trim() {
+                "" => self.last_command.clone(),
+                line => line.to_string(),
+            };
+
+            if !self.command(&line) {
+                break;
+            }
+            self.last_command = line;
+        }
+
+        Ok(())
+    }
+
+    /// Execute a single command.
+    ///
+    /// Returns `false` when the session should end.
+    fn command(&mut self, line: &str) -> bool {
+        let mut parts = line.split_whitespace();
+        let name = parts.next().unwrap_or_default();
+        let arg = parts.next();
+        self.changes.borrow_mut().clear();
+
+        self.message = match (name, arg) {
+            ("q" | "quit", _) => return false,
+            ("h" | "help", _) => HELP.to_string(),
+            ("s" | "step", _) => match arg.map(str::parse::<usize>).unwrap_or(Ok(1)) {
+                Ok(count) => self.step(count),
+                Err(_) => format!("invalid step count: {}", arg.unwrap_or_default()),
+            },
+            ("c" | "continue", _) => self.resume(),
+            ("b" | "break", Some(arg)) => match parse_address(arg) {
+                Some(address) => {
+                    self.vm.debugger().add_breakpoint(address);
+                    format!("breakpoint set at 0x{address:04X}")
+                }
+                None => format!("invalid address: {arg}"),
+            },
+            ("d" | "delete", Some(arg)) => match parse_address(arg) {
+                Some(address) if self.vm.debugger().remove_breakpoint(address) => {
+                    format!("breakpoint removed at 0x{address:04X}")
+                }
+                Some(address) => format!("no breakpoint at 0x{address:04X}"),
+                None => format!("invalid address: {arg}"),
+            },
+            ("m" | "mem", None) => {
+                self.memory_view = None;
+                "memory view follows I".to_string()
+            }
+            ("m" | "mem", Some(arg)) => match parse_address(arg) {
+                Some(address) => {
+                    self.memory_view = Some(address);
+                    format!("memory view at 0x{address:04X}")
+                }
+                None => format!("invalid address: {arg}"),
+            },
+            ("w" | "write", Some(arg)) => self.write(arg, parts),
+            ("k" | "key", Some(arg)) => match u8::from_str_radix(arg, 16)
+                .ok()
+                .and_then(|key| KeyCode::try_from(key).ok())
+            {
+                Some(key) => {
+                    self.vm.push_key_event(key, KeyState::Pressed);
+                    self.vm.push_key_event(key, KeyState::Released);
+                    format!("pressed key {arg}")
+                }
+                None => format!("invalid key: {arg}"),
+            },
+            _ => format!("unknown command: {line}"),
+        };
+
+        true
+    }
+
+    fn step(&mut self, count: usize) -> String {
+        let mut debugger = self.vm.debugger();
+
+        for i in 0..count {
+            match debugger.step() {
+                Ok(Flow::Interrupt) => return format!("interrupted after {} steps", i + 1),
+                Ok(_) => {}
+                Err(err) => return format!("error: {err}"),
+            }
+        }
+
+        format!("stepped {count} instructions")
+    }
+
+    fn resume(&mut self) -> String {
+        let mut debugger = self.vm.debugger();
+        // Continuing is how the session gets past an interrupt or error.
+        debugger.clear_interrupt();
+
+        match debugger.resume(CONTINUE_LIMIT) {
+            Ok(Flow::Breakpoint) => format!("breakpoint at 0x{:04X}", debugger.pc()),
+            Ok(Flow::KeyWait) => "waiting for a key".to_string(),
+            Ok(Flow::Interrupt) => "interrupted".to_string(),
+            Ok(_) => format!("stopped after {CONTINUE_LIMIT} instructions"),
+            Err(err) => format!("error: {err}"),
+        }
+    }
+
+    fn write<'a>(&mut self, address: &str, bytes: impl Iterator<Item = &'a str>) -> String {
+        let Some(address) = parse_address(address) else {
+            return format!("invalid address: {address}");
+        };
+        let bytes: Result<Vec<u8>, _> = bytes
+            .map(|byte| u8::from_str_radix(byte.strip_prefix("0x").unwrap_or(byte), 16))
+            .collect();
+
+        match bytes {
+            Ok(bytes) if !bytes.is_empty() => match self.vm.memory_view().write(address, &bytes) {
+                Ok(()) => format!("wrote {} byte(s) at 0x{address:04X}", bytes.len()),
+                Err(err) => format!("error: {err}"),
+            },
+            _ => "expected hex bytes after the address".to_string(),
+        }
+    }
+
+    /// Render all views into a string, starting with an escape code that clears the terminal.
+    fn draw(&mut self) -> Chip8Result<String> {
+        let mut buf = String::new();
+
+        write!(buf, "\x1b[2J\x1b[H")?;
+
+        self.draw_registers(&mut buf)?;
+        writeln!(buf)?;
+        self.draw_display(&mut buf)?;
+        writeln!(buf)?;
+        self.draw_disassembly(&mut buf)?;
+        writeln!(buf)?;
+        self.draw_memory(&mut buf)?;
+        writeln!(buf)?;
+        writeln!(buf, "{}", self.message)?;
+
+        Ok(buf)
+    }
+
+    fn draw_registers(&mut self, buf: &mut String) -> Chip8Result<()> {
+        let debugger = self.vm.debugger();
+
+        writeln!(
+            buf,
+            "PC 0x{:04X}  I 0x{:04X}  SP {:<2}  DT {:02X}  ST {:02X}",
+            debugger.pc(),
+            debugger.address(),
+            debugger.sp(),
+            debugger.delay_timer(),
+            debugger.sound_timer()
+        )?;
+
+        for (i, values) in debugger.registers().chunks(8).enumerate() {
+            for (j, value) in values.iter().enumerate() {
+                write!(buf, "V{:X} {value:02X}  ", i * 8 + j)?;
+            }
+            writeln!(buf)?;
+        }
+
+        Ok(())
+    }
+
+    /// Two rows of pixels are packed into each line using half block characters.
+    fn draw_display(&self, buf: &mut String) -> Chip8Result<()> {
+        let mut rows = self.vm.display_rows();
+
+        while let (Some(top), Some(bottom)) = (rows.next(), rows.next()) {
+            for (top, bottom) in top.into_iter().zip(bottom) {
+                let c = match (top, bottom) {
+                    (false, false) => ' ',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (true, true) => '█',
+                };
+                buf.push(c);
+            }
+            writeln!(buf, "│")?;
+        }
+
+        Ok(())
+    }
+
+    fn draw_disassembly(&mut self, buf: &mut String) -> Chip8Result<()> {
+        let debugger = self.vm.debugger();
+        let pc = debugger.pc();
+        let program = &debugger.memory()[MEM_START..];
+
+        let mut address = pc.saturating_sub(DISASM_BEFORE).max(MEM_START);
+
+        for _ in 0..DISASM_LINES {
+            let cursor = if address == pc { '>' } else { ' ' };
+            let breakpoint = if debugger.has_breakpoint(address) {
+                '*'
+            } else {
+                ' '
+            };
+
+            match Disassembler::decode_at(program, address - MEM_START) {
+                Some(instr) => {
+                    writeln!(
+                        buf,
+                        "{cursor}{breakpoint} 0x{address:04X}  {:04X}  {}",
+                        instr.bytecode(),
+                        instr.repr()
+                    )?;
+                    address += instr.size();
+                }
+                None => break, // End of memory
+            }
+        }
+
+        Ok(())
+    }
+
+    fn draw_memory(&mut self, buf: &mut String) -> Chip8Result<()> {
+        let debugger = self.vm.debugger();
+        let start = self
+            .memory_view
+            .unwrap_or(debugger.address() as usize)
+            .min(MEM_SIZE - 1);
+        let memory = debugger.memory();
+        let changes = self.changes.borrow();
+
+        for row in 0..MEMORY_ROWS {
+            let address = start + row * MEMORY_COLUMNS;
+            if address >= MEM_SIZE {
+                break;
+            }
+            write!(buf, "0x{address:04X} ")?;
+            for (offset, byte) in memory[address..].iter().take(MEMORY_COLUMNS).enumerate() {
+                // Bytes written by the last command are marked.
+                let changed = changes
+                    .iter()
+                    .any(|change| change.contains(address + offset));
+                let mark = if changed { '*' } else { ' ' };
+                write!(buf, "{mark}{byte:02X}")?;
+            }
+            writeln!(buf)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse a hexadecimal address, with an optional `0x` prefix.
+fn parse_address(text: &str) -> Option<usize> {
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+    usize::from_str_radix(digits, 16).ok()
+}
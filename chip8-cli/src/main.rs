@@ -1,39 +1,62 @@
 //! Entrypoint for CLI
-use std::{env, error::Error, fs, io::Write, time::Instant};
+mod debug;
+mod terminal;
+mod trace;
+
+use std::{
+    error::Error,
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    thread,
+    time::Instant,
+};
 
 use chip8::{
-    asm::{Assembler, Lexer, TokenKind},
+    asm::{AsmConf, Assembler, Lexer, OutputFormat, TokenKind},
     constants::*,
+    disasm::Linter,
     prelude::*,
-    IMPL_VERSION,
+    remote::{RemoteMessage, DEFAULT_PORT},
+    Chip8AsyncVm, DisplayDiff, Hz, MemoryMap, MemoryProtection, SelfModifyPolicy, VmCommand,
+    ARCHIVE_EXT, DEBUG_INFO_EXT, IMPL_VERSION,
 };
-use log::{debug, error, info};
-
-static USAGE: &str = r#"
-usage: chip8 CMD [FILE]
+use clap::{Args, Parser, Subcommand};
+use terminal::HeadlessRender;
+use trace::TraceFormat;
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::EnvFilter;
 
-commands:
-    run     Run the target ROM file
-    asm     Compile the target assembly file into a ROM
-    dis     Disassemble the the target ROM into readable assembly
+/// Size of a Chip8 pixel in screenshots.
+const SCREENSHOT_SCALE: usize = 10;
 
-examples:
-    chip8 run breakout.rom
-    chip8 asm breakout.asm
-    chip8 dis breakout.rom
-"#;
+/// Number of hot spots listed in the profile report.
+const PROFILE_HOT_SPOTS: usize = 20;
 
-#[allow(dead_code)]
-fn run_bytecode(filepath: impl AsRef<str>) -> Chip8Result<()> {
+fn run_headless(args: &RunArgs) -> Chip8Result<()> {
     println!("Running Bytecode Interpreter");
 
-    let bytecode = fs::read(filepath.as_ref())?;
-
-    let mut vm = Chip8Vm::new(Chip8Conf::default());
-    vm.load_bytecode(bytecode.as_slice())?;
+    let mut vm = args.builder()?.build()?;
 
     let start = Instant::now();
-    let result = vm.execute();
+    let result = match args.screenshot_after()? {
+        Some((steps, filepath)) => vm.run_steps(steps).and_then(|flow| {
+            vm.display_to_image(SCREENSHOT_SCALE).save_png(&filepath)?;
+            println!("saved screenshot: {}", filepath.display());
+            Ok(flow)
+        }),
+        None if args.trace => trace::run_trace(
+            &mut vm,
+            args.steps.unwrap_or(usize::MAX),
+            TraceFormat::Text,
+            &mut io::stdout().lock(),
+        ),
+        None => match args.steps {
+            Some(steps) => vm.run_steps(steps),
+            None => vm.execute(),
+        },
+    };
     let end = Instant::now();
 
     println!(
@@ -42,21 +65,251 @@ fn run_bytecode(filepath: impl AsRef<str>) -> Chip8Result<()> {
     ); // to millis
     println!("{}", vm.dump_display()?);
 
+    if let Some(report) = vm.profile_report(PROFILE_HOT_SPOTS) {
+        println!("{report}");
+    }
+
     result?;
 
     Ok(())
 }
 
-fn run_window_application(filepath: impl AsRef<str>) -> Result<(), chip8_win::AppError> {
+fn run_tracer(args: &TraceArgs) -> Chip8Result<()> {
+    let rom = chip8::rom::load_at(&args.filepath, args.load_address)?;
+
+    let mut vm = Chip8Vm::new(Chip8Conf {
+        quirks: args.quirks,
+        load_address: Some(args.load_address),
+        ..Default::default()
+    });
+    vm.load_bytecode(&rom)?;
+
+    match args.out {
+        Some(ref path) => {
+            let mut file = io::BufWriter::new(fs::File::create(path)?);
+            trace::run_trace(&mut vm, args.steps, args.format, &mut file)?;
+            file.flush()?;
+        }
+        None => {
+            trace::run_trace(&mut vm, args.steps, args.format, &mut io::stdout().lock())?;
+        }
+    }
+
+    Ok(())
+}
+
+fn run_snapshot(args: &SnapshotArgs) -> Result<(), Box<dyn Error>> {
+    let rom = chip8::rom::load(&args.filepath)?;
+    let snapshot_path = args
+        .snapshot
+        .clone()
+        .unwrap_or_else(|| Path::new(&args.filepath).with_extension("snap"));
+
+    let conf = Chip8Conf {
+        quirks: args.quirks,
+        ..Default::default()
+    };
+    let actual = chip8::capture_display(&rom, args.steps, conf)?;
+
+    if args.update || !snapshot_path.exists() {
+        fs::write(&snapshot_path, &actual)?;
+        println!("snapshot written: {}", snapshot_path.display());
+        return Ok(());
+    }
+
+    let expected = fs::read_to_string(&snapshot_path)?;
+    match DisplayDiff::new(&expected, &actual) {
+        Some(diff) => {
+            println!("snapshot mismatch: {}", snapshot_path.display());
+            println!("{diff}");
+            std::process::exit(1);
+        }
+        None => println!("snapshot matches: {}", snapshot_path.display()),
+    }
+
+    Ok(())
+}
+
+/// Run headless, and stream the display to standard output.
+fn run_rendered(args: &RunArgs) -> Chip8Result<()> {
+    let Some(render) = args.render else {
+        return run_headless(args);
+    };
+    let mut vm = args.builder()?.build()?;
+
+    match render {
+        HeadlessRender::Ascii => terminal::run_ascii(&mut vm, args.fps, args.steps),
+    }
+}
+
+fn run_terminal(args: &RunArgs) -> Chip8Result<()> {
+    let mut vm = args.builder()?.build()?;
+
+    terminal::run_terminal(&mut vm)
+}
+
+fn run_window_application(args: &RunArgs) -> Result<(), chip8_win::AppError> {
     println!("Running Chip8 cirtual machine");
 
-    let bytecode = fs::read(filepath.as_ref())?;
+    let builder = args.builder()?;
     let input_map = chip8_win::InputMap::from_file("chip8-win/input.yaml")?;
 
-    chip8_win::run_chip8_window(&bytecode, input_map)
+    let mut theme = match args.theme {
+        Some(ref filepath) => chip8_win::Theme::from_file(filepath)?,
+        None => chip8_win::Theme::default(),
+    };
+    if let Some(foreground) = args.foreground {
+        theme.foreground = foreground;
+    }
+    if let Some(background) = args.background {
+        theme.background = background;
+    }
+    if let Some(decay) = args.phosphor_decay {
+        theme.phosphor_decay = decay;
+    }
+
+    let options = chip8_win::AppOptions {
+        vsync: !args.no_vsync,
+        timestep: args.timestep,
+        pacing_stats: args.pacing_stats,
+        filter: args.filter,
+        backend: args.backend,
+    };
+
+    let conf = builder.conf()?;
+    match args.compare {
+        Some(ref profile) => {
+            let other = Chip8Conf {
+                quirks: parse_quirks(profile).expect("profile is validated by the parser"),
+                ..conf.clone()
+            };
+            chip8_win::run_chip8_comparison(
+                builder.rom(),
+                input_map,
+                vec![(quirks_label(conf.quirks), conf), (profile.clone(), other)],
+                theme,
+                builder.rom_info().cloned(),
+                options,
+            )
+        }
+        None => chip8_win::run_chip8_window_with(
+            builder.rom(),
+            input_map,
+            conf,
+            theme,
+            builder.rom_info().cloned(),
+            options,
+        ),
+    }
 }
 
-fn run_assembler(filepath: impl AsRef<str>) -> Chip8Result<()> {
+/// Run the ROM headless, and play it over the network.
+///
+/// Clients are served one at a time, and every client starts the program over.
+fn run_server(args: &ServeArgs) -> Chip8Result<()> {
+    let mut builder = Chip8VmBuilder::new()
+        .with_conf(load_conf(args.config.as_deref())?)
+        .with_rom_file(&args.filepath)?;
+    if let Some(variant) = args.variant {
+        builder = builder.with_variant(variant);
+    }
+    if let Some(quirks) = args.quirks {
+        builder = builder.with_quirks(quirks);
+    }
+    let conf = builder.conf()?;
+
+    let listener = TcpListener::bind((args.host.as_str(), args.port))?;
+    info!("serving {} on {}", args.filepath, listener.local_addr()?);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                error!("failed to accept client: {err}");
+                continue;
+            }
+        };
+        let peer = match stream.peer_addr() {
+            Ok(peer) => peer,
+            Err(err) => {
+                error!("failed to get client address: {err}");
+                continue;
+            }
+        };
+        info!("client connected: {peer}");
+        if let Err(err) = serve_client(stream, conf.clone(), builder.rom()) {
+            error!("client failed: {err}");
+        }
+        info!("client disconnected: {peer}");
+    }
+
+    Ok(())
+}
+
+/// Stream the display to the client, and pass its keys to the VM,
+/// until either side stops.
+fn serve_client(stream: TcpStream, conf: Chip8Conf, rom: &[u8]) -> Chip8Result<()> {
+    stream.set_nodelay(true)?;
+    let vm = Chip8AsyncVm::spawn(conf, rom)?;
+
+    let commands = vm.command_sender();
+    let reader = BufReader::new(stream.try_clone()?);
+    thread::spawn(move || {
+        for line in reader.lines().map_while(Result::ok) {
+            match RemoteMessage::parse(&line) {
+                Ok(message) => match message.to_command() {
+                    Some(command) => {
+                        if commands.send(command).is_err() {
+                            return;
+                        }
+                    }
+                    None => warn!("unexpected message from client: {message}"),
+                },
+                Err(err) => warn!("{err}"),
+            }
+        }
+        // The client hung up.
+        let _ = commands.send(VmCommand::Stop);
+    });
+
+    let result = vm
+        .events()
+        .iter()
+        .try_for_each(|event| writeln!(&stream, "{}", RemoteMessage::from(event)));
+
+    // Unblock the reader thread.
+    let _ = stream.shutdown(std::net::Shutdown::Both);
+
+    Ok(result?)
+}
+
+/// Play a ROM served by `chip8 serve` in a window.
+fn run_client(addr: &str, theme: Option<&Path>) -> Result<(), chip8_win::AppError> {
+    let input_map = chip8_win::InputMap::from_file("chip8-win/input.yaml")?;
+    let theme = match theme {
+        Some(filepath) => chip8_win::Theme::from_file(filepath)?,
+        None => chip8_win::Theme::default(),
+    };
+
+    chip8_win::run_remote_window(addr, input_map, theme)
+}
+
+/// Name of the profile with the given quirks, for labelling windows.
+fn quirks_label(quirks: Quirks) -> String {
+    Quirks::PROFILES
+        .iter()
+        .find(|name| Quirks::profile(name) == Some(quirks))
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| "custom".to_string())
+}
+
+fn run_assembler(
+    filepath: impl AsRef<str>,
+    out: Option<PathBuf>,
+    format: OutputFormat,
+    load_address: usize,
+    optimize: bool,
+) -> Chip8Result<()> {
     use TokenKind as TK;
 
     info!("running Assembler");
@@ -91,16 +344,30 @@ fn run_assembler(filepath: impl AsRef<str>) -> Chip8Result<()> {
 
     {
         let lexer = Lexer::new(source_code.as_str());
-        let asm = Assembler::new(lexer);
-
-        match asm.parse() {
-            Ok(bytecode) => {
-                let mut outfile = fs::File::create("output.rom")?;
-                outfile.write_all(&bytecode)?;
-                dump_bytecode(&bytecode)
+        let conf = AsmConf {
+            load_address,
+            optimize,
+            ..Default::default()
+        };
+        let asm = Assembler::with_conf(lexer, conf);
+
+        match asm.parse_with_report() {
+            Ok((bytecode, listing, report)) => {
+                let out = out
+                    .unwrap_or_else(|| PathBuf::from("output").with_extension(format.extension()));
+                let name = out.file_stem().unwrap_or_default().to_string_lossy();
+                let mut outfile = io::BufWriter::new(fs::File::create(&out)?);
+                format.write(&bytecode, load_address, &name, &mut outfile)?;
+                outfile.flush()?;
+                fs::write(out.with_extension("lst"), listing.to_string())?;
+                DebugInfo::from(&listing).save(out.with_extension(DEBUG_INFO_EXT))?;
+                if optimize {
+                    println!("optimizer: {report}");
+                }
+                dump_bytecode(&bytecode, load_address)
             }
             Err(err) => {
-                error!("assembly error\n{err}");
+                print_diagnostics(&err, &source_code, filepath.as_ref());
                 // Exit process with error
                 return Err(err);
             }
@@ -110,19 +377,195 @@ fn run_assembler(filepath: impl AsRef<str>) -> Chip8Result<()> {
     Ok(())
 }
 
-fn run_disassemble(filepath: impl AsRef<str>) -> Chip8Result<()> {
+fn run_formatter(filepath: impl AsRef<str>, write: bool) -> Chip8Result<()> {
+    let source_code = fs::read_to_string(filepath.as_ref())?;
+
+    match chip8::asm::format_source(&source_code) {
+        Ok(formatted) if write => {
+            if formatted != source_code {
+                fs::write(filepath.as_ref(), formatted)?;
+                info!("formatted {}", filepath.as_ref());
+            }
+        }
+        Ok(formatted) => print!("{formatted}"),
+        Err(err) => {
+            print_diagnostics(&err, &source_code, filepath.as_ref());
+            return Err(err);
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the errors of an assembly file, with excerpts of the source code.
+fn print_diagnostics(err: &Chip8Error, source_code: &str, filepath: &str) {
+    let diagnostics = err.diagnostics();
+    for diagnostic in &diagnostics {
+        eprintln!(
+            "{}",
+            diagnostic.display_with_source(source_code, Some(filepath))
+        );
+    }
+    error!("assembly failed with {} error(s)", diagnostics.len());
+}
+
+fn run_debugger(filepath: impl AsRef<str>, quirks: Option<Quirks>) -> Chip8Result<()> {
+    let mut builder = Chip8VmBuilder::new().with_rom_file(filepath.as_ref())?;
+    if let Some(quirks) = quirks {
+        builder = builder.with_quirks(quirks);
+    }
+    let vm = builder.build()?;
+
+    debug::DebugSession::new(vm).run()
+}
+
+fn run_disassemble(
+    filepath: impl AsRef<str>,
+    annotate: bool,
+    load_address: usize,
+) -> Chip8Result<()> {
     debug!("disassembling: {}", filepath.as_ref());
-    let bytecode = fs::read(filepath.as_ref())?;
-    Disassembler::new(bytecode.as_slice()).print_bytecode();
+    let archive = read_rom_with_debug_info(filepath.as_ref(), load_address)?;
+
+    let memory_map = MemoryMap::new(load_address, archive.rom.len(), MemoryProtection::default());
+    let mut disasm = DisassemblerV2::new(archive.rom.as_slice())
+        .with_load_address(load_address)
+        .with_memory_map(&memory_map);
+    if let Some(ref debug_info) = archive.debug_info {
+        disasm = disasm.with_debug_info(debug_info);
+    }
+    if annotate {
+        disasm = disasm.with_annotations();
+    }
+
+    let mut buf = String::new();
+    disasm.disassemble(&mut buf)?;
+    println!("{buf}");
+
+    Ok(())
+}
+
+fn run_linter(filepath: impl AsRef<str>, quirks: Quirks, load_address: usize) -> Chip8Result<()> {
+    debug!("linting: {}", filepath.as_ref());
+    let archive = read_rom_with_debug_info(filepath.as_ref(), load_address)?;
+
+    let mut linter = Linter::new(archive.rom.as_slice())
+        .with_quirks(quirks)
+        .with_load_address(load_address);
+
+    // Debug info tells data apart from unreachable code.
+    if let Some(ref debug_info) = archive.debug_info {
+        linter = linter.with_debug_info(debug_info);
+    }
+
+    let lints = linter.lint();
+    for lint in &lints {
+        println!("{lint}");
+    }
+    println!("{} warning(s)", lints.len());
+
     Ok(())
 }
 
-fn dump_bytecode(bytecode: &[u8]) {
+fn run_analyzer(
+    filepath: impl AsRef<str>,
+    cfg: bool,
+    out: Option<PathBuf>,
+    load_address: usize,
+) -> Chip8Result<()> {
+    debug!("analyzing: {}", filepath.as_ref());
+    let archive = read_rom_with_debug_info(filepath.as_ref(), load_address)?;
+
+    let mut disasm = DisassemblerV2::new(archive.rom.as_slice()).with_load_address(load_address);
+    if let Some(ref debug_info) = archive.debug_info {
+        disasm = disasm.with_debug_info(debug_info);
+    }
+    let graph = disasm.control_flow_graph();
+
+    if cfg {
+        let mut dot = String::new();
+        graph.write_dot(&mut dot)?;
+        match out {
+            Some(out) => {
+                fs::write(&out, dot)?;
+                info!("wrote control-flow graph: {}", out.display());
+            }
+            None => print!("{dot}"),
+        }
+        return Ok(());
+    }
+
+    for subroutine in &graph.subroutines {
+        let blocks: Vec<_> = graph
+            .blocks
+            .values()
+            .filter(|block| block.subroutine == *subroutine)
+            .collect();
+        let instrs: usize = blocks.iter().map(|block| block.instrs.len()).sum();
+        println!(
+            "0x{subroutine:04X} .{}: {} block(s), {instrs} instruction(s)",
+            graph.blocks[subroutine].label,
+            blocks.len()
+        );
+    }
+    println!(
+        "{} basic block(s) in {} subroutine(s)",
+        graph.blocks.len(),
+        graph.subroutines.len()
+    );
+
+    Ok(())
+}
+
+/// Read a ROM, with the debug info emitted by the assembler
+/// when it sits next to the ROM instead of inside an archive.
+fn read_rom_with_debug_info(filepath: &str, load_address: usize) -> Chip8Result<RomArchive> {
+    let mut archive = RomArchive::read_file_at(filepath, load_address)?;
+    let debug_path = Path::new(filepath).with_extension(DEBUG_INFO_EXT);
+    if archive.debug_info.is_none() && debug_path.exists() {
+        info!("loading debug info: {}", debug_path.display());
+        archive.debug_info = Some(DebugInfo::load(&debug_path)?);
+    }
+    Ok(archive)
+}
+
+/// Bundle a ROM with its descriptor and debug info into a single archive file.
+fn run_pack(filepath: impl AsRef<str>, out: Option<PathBuf>) -> Chip8Result<()> {
+    let filepath = filepath.as_ref();
+    let mut archive = read_rom_with_debug_info(filepath, MEM_START)?;
+    if let Some(info) = RomInfo::discover(filepath)? {
+        archive.info = Some(info);
+    } else if let Some(info) = load_json_rom_info(filepath)? {
+        archive.info = Some(info);
+    }
+
+    let out = out.unwrap_or_else(|| Path::new(filepath).with_extension(ARCHIVE_EXT));
+    archive.save(&out)?;
+    println!(
+        "packed {} bytes{}{}: {}",
+        archive.rom.len(),
+        if archive.info.is_some() {
+            ", rom info"
+        } else {
+            ""
+        },
+        if archive.debug_info.is_some() {
+            ", debug info"
+        } else {
+            ""
+        },
+        out.display()
+    );
+
+    Ok(())
+}
+
+fn dump_bytecode(bytecode: &[u8], load_address: usize) {
     // Instructions are always 2 bytes.
-    assert!(bytecode.len() % 2 == 0);
+    assert!(bytecode.len().is_multiple_of(2));
 
     for (i, instr) in bytecode.chunks(2).enumerate() {
-        let offset = MEM_START + i * 2;
+        let offset = load_address + i * 2;
         let a = instr[0];
         let b = instr[1];
         println!("0x{offset:04X} {a:02X}{b:02X}");
@@ -130,58 +573,435 @@ fn dump_bytecode(bytecode: &[u8]) {
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    simple_logger::SimpleLogger::new()
-        .env()
-        .without_timestamps()
-        .init()
-        .unwrap();
-
-    match parse_args() {
-        Some(Cmd::Run { filepath }) => run_window_application(filepath)?,
-        Some(Cmd::Asm { filepath }) => run_assembler(filepath)?,
-        Some(Cmd::Dis { filepath }) => run_disassemble(filepath)?,
-        None => {
-            print_usage();
-            // FreeBSD EX_USAGE (64)
-            std::process::exit(64)
+    // Filtered with `RUST_LOG`, for example `RUST_LOG=chip8::instruction=trace`.
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()))
+        .without_time()
+        .init();
+
+    let cli = Cli::parse();
+
+    match cli.cmd {
+        Cmd::Run(args) if args.render.is_some() => run_rendered(&args)?,
+        Cmd::Run(args) if args.headless || !args.screenshot_after.is_empty() => {
+            run_headless(&args)?
         }
+        Cmd::Run(args) if args.terminal => run_terminal(&args)?,
+        Cmd::Run(args) => run_window_application(&args)?,
+        Cmd::Asm {
+            filepath,
+            out,
+            format,
+            load_address,
+            optimize,
+        } => run_assembler(filepath, out, format, load_address, optimize)?,
+        Cmd::Fmt { filepath, write } => run_formatter(filepath, write)?,
+        Cmd::Dis {
+            filepath,
+            annotate,
+            load_address,
+        } => run_disassemble(filepath, annotate, load_address)?,
+        Cmd::Debug { filepath, quirks } => run_debugger(filepath, quirks)?,
+        Cmd::Lint {
+            filepath,
+            quirks,
+            load_address,
+        } => run_linter(filepath, quirks, load_address)?,
+        Cmd::Analyze {
+            filepath,
+            cfg,
+            out,
+            load_address,
+        } => run_analyzer(filepath, cfg, out, load_address)?,
+        Cmd::Trace(args) => run_tracer(&args)?,
+        Cmd::Snapshot(args) => run_snapshot(&args)?,
+        Cmd::Pack { filepath, out } => run_pack(filepath, out)?,
+        Cmd::Serve(args) => run_server(&args)?,
+        Cmd::Connect { addr, theme } => run_client(&addr, theme.as_deref())?,
     }
 
     Ok(())
 }
 
-fn parse_args() -> Option<Cmd> {
-    let mut args = env::args().skip(1);
-    match args.next() {
-        Some(cmd) => {
-            // don't format me T.T
-            match cmd.as_str() {
-                "run" => Some(Cmd::Run {
-                    filepath: args.next()?,
-                }),
-                "asm" => Some(Cmd::Asm {
-                    filepath: args.next()?,
-                }),
-                "dis" => Some(Cmd::Dis {
-                    filepath: args.next()?,
-                }),
-                _ => None,
+/// Chip-8 virtual machine, assembler and disassembler.
+#[derive(Parser)]
+#[command(name = "chip8", version = IMPL_VERSION)]
+struct Cli {
+    #[command(subcommand)]
+    cmd: Cmd,
+}
+
+#[derive(Subcommand)]
+enum Cmd {
+    /// Run the target ROM file
+    Run(RunArgs),
+    /// Compile the target assembly file into a ROM
+    Asm {
+        filepath: String,
+        /// Path of the ROM file. The listing and debug info are written next to it.
+        /// Defaults to `output` with the extension of the format
+        #[arg(long, short, value_name = "FILE")]
+        out: Option<PathBuf>,
+        /// Format of the ROM file: rom, hex (Intel HEX), carray or rustarray
+        #[arg(long, value_name = "FORMAT", default_value_t = OutputFormat::Rom)]
+        format: OutputFormat,
+        /// Address the program is loaded at, like 0x600 for the ETI-660
+        #[arg(long, value_name = "ADDR", default_value = "0x200", value_parser = parse_address)]
+        load_address: usize,
+        /// Remove redundant moves, adds and jumps, and print the bytes saved
+        #[arg(long)]
+        optimize: bool,
+    },
+    /// Reprint the target assembly file with a canonical layout
+    Fmt {
+        filepath: String,
+        /// Overwrite the file instead of printing to stdout
+        #[arg(long, short)]
+        write: bool,
+    },
+    /// Disassemble the target ROM into readable assembly
+    Dis {
+        filepath: String,
+        /// Render the detected sprites as ASCII art comments
+        #[arg(long)]
+        annotate: bool,
+        /// Address the program is loaded at, like 0x600 for the ETI-660
+        #[arg(long, value_name = "ADDR", default_value = "0x200", value_parser = parse_address)]
+        load_address: usize,
+    },
+    /// Step through the target ROM in an interactive terminal debugger
+    Debug {
+        filepath: String,
+        /// Compatibility profile for instruction semantics. Overrides the ROM descriptor
+        #[arg(long, value_name = "PROFILE", value_parser = parse_quirks)]
+        quirks: Option<Quirks>,
+    },
+    /// Analyse the target ROM, and warn about likely mistakes
+    Lint {
+        filepath: String,
+        /// Compatibility profile for instruction semantics
+        #[arg(long, value_name = "PROFILE", default_value = "default", value_parser = parse_quirks)]
+        quirks: Quirks,
+        /// Address the program is loaded at, like 0x600 for the ETI-660
+        #[arg(long, value_name = "ADDR", default_value = "0x200", value_parser = parse_address)]
+        load_address: usize,
+    },
+    /// Find the basic blocks and subroutines of the target ROM
+    Analyze {
+        filepath: String,
+        /// Write the control-flow graph in the Graphviz DOT language,
+        /// instead of printing a summary
+        #[arg(long)]
+        cfg: bool,
+        /// Path of the graph. Defaults to standard output
+        #[arg(long, short, value_name = "FILE", requires = "cfg")]
+        out: Option<PathBuf>,
+        /// Address the program is loaded at, like 0x600 for the ETI-660
+        #[arg(long, value_name = "ADDR", default_value = "0x200", value_parser = parse_address)]
+        load_address: usize,
+    },
+    /// Run the target ROM headless, and log every executed instruction
+    Trace(TraceArgs),
+    /// Run the target ROM headless, and compare the display against a snapshot file
+    Snapshot(SnapshotArgs),
+    /// Bundle the target ROM with its descriptor and debug info into a single archive
+    Pack {
+        filepath: String,
+        /// Path of the archive. Defaults to the ROM path with a `.c8a` extension
+        #[arg(long, short, value_name = "FILE")]
+        out: Option<PathBuf>,
+    },
+    /// Run the target ROM headless, and play it over the network
+    Serve(ServeArgs),
+    /// Play a ROM served by `chip8 serve` in a window
+    Connect {
+        /// Address of the server
+        #[arg(default_value_t = format!("127.0.0.1:{DEFAULT_PORT}"))]
+        addr: String,
+        /// YAML file with the display colours and effects
+        #[arg(long, value_name = "FILE")]
+        theme: Option<PathBuf>,
+    },
+}
+
+#[derive(Args)]
+struct RunArgs {
+    filepath: String,
+    /// VM configuration file, in TOML or YAML. Overridden by the ROM descriptor and options
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// Run without a window, and print the display when the program stops
+    #[arg(long)]
+    headless: bool,
+    /// Run in the terminal instead of a window, for systems without OpenGL
+    #[arg(long, conflicts_with = "headless")]
+    terminal: bool,
+    /// CPU clock frequency, in hertz. Overrides the ROM descriptor.
+    /// Defaults to 600Hz in the window, terminal and rendered headless runs,
+    /// while other headless runs go as fast as possible
+    #[arg(long, value_name = "HZ")]
+    clock_hz: Option<u64>,
+    /// Interpreter the program is written for: chip8, chip48, schip or xochip.
+    /// Selects the instruction set and quirks
+    #[arg(long, value_name = "NAME", value_parser = parse_variant)]
+    variant: Option<Variant>,
+    /// Compatibility profile for instruction semantics. Overrides the ROM descriptor and variant
+    #[arg(long, value_name = "PROFILE", value_parser = parse_quirks)]
+    quirks: Option<Quirks>,
+    /// Handling of writes to executed code: allow, warn or trap. Default: allow
+    #[arg(long, value_name = "POLICY", value_parser = parse_self_modify)]
+    self_modify: Option<SelfModifyPolicy>,
+    /// Apply the recommended settings of well-known ROMs, recognised by their hash
+    #[arg(long)]
+    rom_db: bool,
+    /// Stop the program when it writes to the font, or executes below the program
+    #[arg(long)]
+    protect_memory: bool,
+    /// Address the program is loaded at, like 0x600 for the ETI-660
+    #[arg(long, value_name = "ADDR", value_parser = parse_address)]
+    load_address: Option<usize>,
+    /// Stop after executing the given number of instructions
+    #[arg(long, value_name = "N", requires = "headless")]
+    steps: Option<usize>,
+    /// Print every instruction as it's executed
+    #[arg(long, requires = "headless")]
+    trace: bool,
+    /// Count executed instructions, and print the hot spots when the program stops
+    #[arg(long, requires = "headless")]
+    profile: bool,
+    /// Stream the display to standard output while running headless, in real time.
+    /// Keys are read from standard input
+    #[arg(
+        long,
+        value_name = "MODE",
+        requires = "headless",
+        conflicts_with_all = ["trace", "profile"]
+    )]
+    render: Option<HeadlessRender>,
+    /// Maximum number of frames per second written by `--render`
+    #[arg(long, value_name = "N", default_value_t = 30, value_parser = clap::value_parser!(u32).range(1..=60))]
+    fps: u32,
+    /// Run headless for N instructions, and save the display as a PNG image
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["N", "FILE"],
+        conflicts_with_all = ["steps", "trace"]
+    )]
+    screenshot_after: Vec<String>,
+    /// Open a second window running the ROM with another quirks profile, to compare them
+    #[arg(
+        long,
+        value_name = "PROFILE",
+        value_parser = parse_profile_name,
+        conflicts_with_all = ["headless", "terminal", "screenshot_after"]
+    )]
+    compare: Option<String>,
+    /// YAML file with the display colours and effects
+    #[arg(long, value_name = "FILE")]
+    theme: Option<PathBuf>,
+    /// Colour of the lit pixels, as #RRGGBB or #RRGGBBAA. Overrides the theme
+    #[arg(long, value_name = "COLOR")]
+    foreground: Option<chip8_win::Color>,
+    /// Colour of the window background, as #RRGGBB or #RRGGBBAA. Overrides the theme
+    #[arg(long, value_name = "COLOR")]
+    background: Option<chip8_win::Color>,
+    /// Fraction of brightness a pixel keeps per frame after turning off. Overrides the theme
+    #[arg(long, value_name = "FACTOR")]
+    phosphor_decay: Option<f32>,
+    /// Present frames immediately in the window, instead of waiting for the vertical blank
+    #[arg(long)]
+    no_vsync: bool,
+    /// How the window advances emulation: fixed 60Hz frames, or adaptive to the elapsed time
+    #[arg(long, value_name = "MODE", default_value_t = chip8_win::Timestep::Fixed)]
+    timestep: chip8_win::Timestep,
+    /// Log the frame pacing statistics of the window, and show them in the debug panels
+    #[arg(long)]
+    pacing_stats: bool,
+    /// Post-processing of the scaled display: nearest, scanlines or dot-matrix. Cycled with F6
+    #[arg(long, value_name = "NAME", default_value_t = chip8_win::DisplayFilter::Nearest)]
+    filter: chip8_win::DisplayFilter,
+    /// How the window executes instructions: interpreter, decode-cache or jit. Cycled with F7
+    #[arg(long, value_name = "NAME", default_value_t = chip8_win::Backend::Interpreter)]
+    backend: chip8_win::Backend,
+}
+
+#[derive(Args)]
+struct ServeArgs {
+    filepath: String,
+    /// VM configuration file, in TOML or YAML. Overridden by the ROM descriptor and options
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// Interpreter the program is written for: chip8, chip48, schip or xochip.
+    /// Selects the instruction set and quirks
+    #[arg(long, value_name = "NAME", value_parser = parse_variant)]
+    variant: Option<Variant>,
+    /// Compatibility profile for instruction semantics. Overrides the ROM descriptor and variant
+    #[arg(long, value_name = "PROFILE", value_parser = parse_quirks)]
+    quirks: Option<Quirks>,
+    /// Port to listen on
+    #[arg(long, default_value_t = DEFAULT_PORT)]
+    port: u16,
+    /// Address to listen on. Use 0.0.0.0 to accept clients from other machines
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1")]
+    host: String,
+}
+
+#[derive(Args)]
+struct TraceArgs {
+    filepath: String,
+    /// Stop after executing the given number of instructions
+    #[arg(long, value_name = "N", default_value_t = 10000)]
+    steps: usize,
+    /// Output format of the trace
+    #[arg(long, value_enum, default_value_t = TraceFormat::Text)]
+    format: TraceFormat,
+    /// Write the trace to a file, instead of standard output
+    #[arg(long, short, value_name = "FILE")]
+    out: Option<PathBuf>,
+    /// Address the program is loaded at, like 0x600 for the ETI-660
+    #[arg(long, value_name = "ADDR", default_value = "0x200", value_parser = parse_address)]
+    load_address: usize,
+    /// Compatibility profile for instruction semantics
+    #[arg(long, value_name = "PROFILE", default_value = "default", value_parser = parse_quirks)]
+    quirks: Quirks,
+}
+
+#[derive(Args)]
+struct SnapshotArgs {
+    filepath: String,
+    /// Number of instructions to execute before capturing the display
+    #[arg(long, value_name = "N", default_value_t = 10000)]
+    steps: usize,
+    /// Path of the snapshot file. Defaults to the ROM path with a `.snap` extension
+    #[arg(long, value_name = "FILE")]
+    snapshot: Option<PathBuf>,
+    /// Overwrite the snapshot file with the current display
+    #[arg(long)]
+    update: bool,
+    /// Compatibility profile for instruction semantics
+    #[arg(long, value_name = "PROFILE", default_value = "default", value_parser = parse_quirks)]
+    quirks: Quirks,
+}
+
+impl RunArgs {
+    /// Number of steps and image path of the `--screenshot-after` option.
+    fn screenshot_after(&self) -> Chip8Result<Option<(usize, PathBuf)>> {
+        match self.screenshot_after.as_slice() {
+            [steps, filepath] => {
+                let steps = steps.parse().map_err(Chip8Error::NumberParse)?;
+                Ok(Some((steps, PathBuf::from(filepath))))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Configuration file, with the options taking precedence.
+    fn conf(&self) -> Chip8Result<Chip8Conf> {
+        let mut conf = load_conf(self.config.as_deref())?;
+        conf.profile |= self.profile;
+        conf.use_rom_db |= self.rom_db;
+        if let Some(policy) = self.self_modify {
+            conf.self_modify = policy;
+        }
+        if let Some(address) = self.load_address {
+            conf.load_address = Some(address);
+        }
+        if self.protect_memory {
+            conf.memory_protection = MemoryProtection::ALL;
+        }
+        Ok(conf)
+    }
+
+    /// Load the ROM and its descriptor, with the options taking precedence.
+    fn builder(&self) -> Chip8Result<Chip8VmBuilder> {
+        let mut builder = Chip8VmBuilder::new()
+            .with_conf(self.conf()?)
+            .with_rom_file(&self.filepath)?;
+        if builder.rom_info().is_none() {
+            if let Some(info) = load_json_rom_info(&self.filepath)? {
+                builder = builder.with_rom_info(info);
             }
         }
-        None => None,
+        if let Some(variant) = self.variant {
+            builder = builder.with_variant(variant);
+        }
+        if let Some(quirks) = self.quirks {
+            builder = builder.with_quirks(quirks);
+        }
+        if let Some(clock_hz) = self.clock_hz {
+            builder = builder.with_clock_frequency(Hz(clock_hz));
+        }
+        Ok(builder)
     }
 }
 
-fn print_usage() {
-    println!("Chip8 v{IMPL_VERSION}");
-    println!("{USAGE}");
+/// Load the VM configuration file, or the defaults without one.
+fn load_conf(path: Option<&Path>) -> Chip8Result<Chip8Conf> {
+    match path {
+        Some(path) => {
+            info!("loading config: {}", path.display());
+            Chip8Conf::from_file(path)
+        }
+        None => Ok(Chip8Conf::default()),
+    }
 }
 
-enum Cmd {
-    /// Run file
-    Run { filepath: String },
-    /// Assemble
-    Asm { filepath: String },
-    /// Disassemble
-    Dis { filepath: String },
+/// Load a JSON ROM descriptor, `game.ch8.json`, when there's no TOML descriptor.
+fn load_json_rom_info(rom_path: impl AsRef<Path>) -> Chip8Result<Option<RomInfo>> {
+    let path = RomInfo::sidecar_path(rom_path).with_extension("json");
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    info!("loading rom info: {}", path.display());
+    let info: RomInfo = serde_json::from_str(&fs::read_to_string(&path)?)
+        .map_err(|err| Chip8Error::RomInfo(err.to_string()))?;
+    info.validate()?;
+    Ok(Some(info))
+}
+
+fn parse_self_modify(name: &str) -> Result<SelfModifyPolicy, String> {
+    match name {
+        "allow" => Ok(SelfModifyPolicy::Allow),
+        "warn" => Ok(SelfModifyPolicy::Warn),
+        "trap" => Ok(SelfModifyPolicy::Trap),
+        _ => Err("unknown policy, expected one of: allow, warn, trap".to_string()),
+    }
+}
+
+/// Parse a memory address, in hexadecimal with a `0x` prefix, or decimal.
+fn parse_address(text: &str) -> Result<usize, String> {
+    let address = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => text.parse(),
+    }
+    .map_err(|err| err.to_string())?;
+
+    if address >= MEM_SIZE {
+        return Err(format!("address must be below 0x{MEM_SIZE:X}"));
+    }
+    Ok(address)
+}
+
+fn parse_profile_name(name: &str) -> Result<String, String> {
+    parse_quirks(name).map(|_| name.to_string())
+}
+
+fn parse_variant(name: &str) -> Result<Variant, String> {
+    Variant::from_name(name).ok_or_else(|| {
+        format!(
+            "unknown variant, expected one of: {}",
+            Variant::NAMES.join(", ")
+        )
+    })
+}
+
+fn parse_quirks(name: &str) -> Result<Quirks, String> {
+    Quirks::profile(name).ok_or_else(|| {
+        format!(
+            "unknown quirks profile, expected one of: {}",
+            Quirks::PROFILES.join(", ")
+        )
+    })
 }
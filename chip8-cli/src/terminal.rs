@@ -0,0 +1,312 @@
+//! Terminal frontend, for running programs without a window.
+//!
+//! The display is drawn with unicode half-block characters,
+//! so every character cell holds two vertically stacked pixels.
+//! Headless runs can instead stream the display to standard
+//! output as ASCII frames, see [`run_ascii`].
+use std::{
+    cell::Cell,
+    io::{self, IsTerminal, Write},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use chip8::{constants::*, prelude::*, KeyCode, KeyState, TimerEvent};
+use clap::ValueEnum;
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode as TermKey, KeyEvent, KeyEventKind, KeyModifiers},
+    queue, terminal,
+};
+
+/// Time between display ticks, at 60Hz.
+const FRAME_TIME: Duration = Duration::from_nanos(CLOCK_CYCLE_TIME);
+
+/// Number of frames a key is held down after it's pressed.
+///
+/// Most terminals don't report key releases, so
+/// releases are simulated after the key stops repeating.
+const KEY_HOLD_FRAMES: u8 = 6;
+
+/// Host keys mapped to the hex keypad, in the layout of the COSMAC VIP.
+///
+/// ```text
+/// 1 2 3 4      1 2 3 C
+/// q w e r  =>  4 5 6 D
+/// a s d f      7 8 9 E
+/// z x c v      A 0 B F
+/// ```
+const KEYMAP: [(char, KeyCode); KEY_COUNT as usize] = [
+    ('1', KeyCode::Key1),
+    ('2', KeyCode::Key2),
+    ('3', KeyCode::Key3),
+    ('4', KeyCode::KeyC),
+    ('q', KeyCode::Key4),
+    ('w', KeyCode::Key5),
+    ('e', KeyCode::Key6),
+    ('r', KeyCode::KeyD),
+    ('a', KeyCode::Key7),
+    ('s', KeyCode::Key8),
+    ('d', KeyCode::Key9),
+    ('f', KeyCode::KeyE),
+    ('z', KeyCode::KeyA),
+    ('x', KeyCode::Key0),
+    ('c', KeyCode::KeyB),
+    ('v', KeyCode::KeyF),
+];
+
+/// Display output of headless runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HeadlessRender {
+    /// ASCII frames on standard output, cleared with ANSI escape codes
+    Ascii,
+}
+
+/// Run the loaded program in the terminal, until Esc or Ctrl+C is pressed.
+pub fn run_terminal(vm: &mut Chip8Vm) -> Chip8Result<()> {
+    let _guard = TerminalGuard::enter()?;
+    let mut stdout = io::stdout().lock();
+
+    let mut keys = HeldKeys::default();
+    let mut redraw = true;
+    let mut next_frame = Instant::now();
+
+    // Terminals can't play a tone, so the buzzer rings the bell instead.
+    let bell = Rc::new(Cell::new(false));
+    let ring = bell.clone();
+    vm.set_timer_hook(move |event| {
+        if event == TimerEvent::SoundOn {
+            ring.set(true);
+        }
+    });
+
+    loop {
+        // Collect input until the next frame is due.
+        while let Some(timeout) = next_frame.checked_duration_since(Instant::now()) {
+            if !event::poll(timeout)? {
+                break;
+            }
+
+            match event::read()? {
+                Event::Key(key) if is_exit(&key) => return Ok(()),
+                Event::Key(key) => keys.handle(vm, &key),
+                Event::Resize(..) => redraw = true,
+                _ => {}
+            }
+        }
+        next_frame = advance_frame(next_frame);
+
+        let report = vm.run_frame()?;
+        keys.tick(vm);
+
+        if report.draw || redraw {
+            draw_display(&mut stdout, vm)?;
+            redraw = false;
+        }
+        if bell.take() {
+            stdout.write_all(b"\x07")?;
+            stdout.flush()?;
+        }
+
+        if report.interrupt {
+            return Ok(());
+        }
+    }
+}
+
+/// Run the loaded program at 60 frames per second, streaming the display
+/// to standard output as ASCII frames, at most `fps` times per second.
+///
+/// Keys are read from standard input in raw mode, when it's a terminal.
+/// Stops when Esc or Ctrl+C is pressed, the program is interrupted, or
+/// after the step limit is reached.
+pub fn run_ascii(vm: &mut Chip8Vm, fps: u32, step_limit: Option<usize>) -> Chip8Result<()> {
+    let interactive = io::stdin().is_terminal();
+    let _guard = interactive.then(RawModeGuard::enter).transpose()?;
+    let mut stdout = io::stdout().lock();
+
+    let frame_interval = Duration::from_secs(1) / fps.clamp(1, DELAY_FREQUENCY as u32);
+    let mut keys = HeldKeys::default();
+    let mut steps = 0;
+    let mut dirty = true;
+    let mut next_render = Instant::now();
+    let mut next_frame = Instant::now();
+
+    loop {
+        // Collect input until the next frame is due.
+        while let Some(timeout) = next_frame.checked_duration_since(Instant::now()) {
+            if !interactive {
+                std::thread::sleep(timeout);
+                break;
+            }
+            if !event::poll(timeout)? {
+                break;
+            }
+
+            match event::read()? {
+                Event::Key(key) if is_exit(&key) => return Ok(()),
+                Event::Key(key) => keys.handle(vm, &key),
+                _ => {}
+            }
+        }
+        next_frame = advance_frame(next_frame);
+
+        let report = vm.run_frame()?;
+        keys.tick(vm);
+        steps += report.instructions;
+        dirty |= report.draw;
+
+        let done = report.interrupt || step_limit.is_some_and(|limit| steps >= limit);
+        if dirty && (done || Instant::now() >= next_render) {
+            write_ascii_frame(&mut stdout, vm, interactive)?;
+            dirty = false;
+            next_render = Instant::now() + frame_interval;
+        }
+
+        if done {
+            return Ok(());
+        }
+    }
+}
+
+/// Time the frame after the given one is due.
+fn advance_frame(frame: Instant) -> Instant {
+    let next = frame + FRAME_TIME;
+
+    // Don't try to catch up after the process was suspended.
+    if next < Instant::now() {
+        Instant::now() + FRAME_TIME
+    } else {
+        next
+    }
+}
+
+/// Keypad keys held down by terminal key presses.
+#[derive(Default)]
+struct HeldKeys {
+    /// Remaining frames each keypad key is held down.
+    frames: [u8; KEY_COUNT as usize],
+}
+
+impl HeldKeys {
+    /// Press or release the keypad key mapped to the terminal key.
+    fn handle(&mut self, vm: &mut Chip8Vm, key: &KeyEvent) {
+        let TermKey::Char(c) = key.code else {
+            return;
+        };
+        if let Some(keycode) = map_key(c) {
+            let index = keycode.as_u8() as usize;
+            if key.kind == KeyEventKind::Release {
+                self.frames[index] = 0;
+                vm.push_key_event(keycode, KeyState::Released);
+            } else {
+                self.frames[index] = KEY_HOLD_FRAMES;
+                vm.push_key_event(keycode, KeyState::Pressed);
+            }
+        }
+    }
+
+    /// Release the keys that stopped repeating, once per frame.
+    fn tick(&mut self, vm: &mut Chip8Vm) {
+        for (index, frames) in self.frames.iter_mut().enumerate() {
+            if *frames > 0 {
+                *frames -= 1;
+                if *frames == 0 {
+                    let keycode = KeyCode::try_from(index as u8).expect("keypad index");
+                    vm.push_key_event(keycode, KeyState::Released);
+                }
+            }
+        }
+    }
+}
+
+fn is_exit(key: &KeyEvent) -> bool {
+    key.code == TermKey::Esc
+        || (key.code == TermKey::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL))
+}
+
+fn map_key(c: char) -> Option<KeyCode> {
+    let c = c.to_ascii_lowercase();
+    KEYMAP
+        .iter()
+        .find(|(host_key, _)| *host_key == c)
+        .map(|(_, keycode)| *keycode)
+}
+
+fn draw_display(out: &mut impl Write, vm: &Chip8Vm) -> io::Result<()> {
+    let mut rows = vm.display_rows();
+
+    queue!(out, cursor::MoveTo(0, 0))?;
+    while let (Some(top), Some(bottom)) = (rows.next(), rows.next()) {
+        let row: String = top
+            .into_iter()
+            .zip(bottom)
+            .map(|pixels| match pixels {
+                (false, false) => ' ',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (true, true) => '█',
+            })
+            .collect();
+        // Raw mode doesn't translate newlines into carriage returns.
+        write!(out, "{row}\r\n")?;
+    }
+    write!(out, "Esc to exit\r\n")?;
+
+    out.flush()
+}
+
+/// Clear the terminal, and write the display in the format of [`Chip8Vm::dump_display`].
+fn write_ascii_frame(out: &mut impl Write, vm: &Chip8Vm, raw_mode: bool) -> Chip8Result<()> {
+    let mut display = vm.dump_display()?;
+    if raw_mode {
+        // Raw mode doesn't return the cursor to the start of the line on newlines.
+        display = display.replace('\n', "\r\n");
+    }
+    write!(out, "\x1b[2J\x1b[H{display}")?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Puts the terminal into raw mode, and restores it when dropped.
+///
+/// Unlike [`TerminalGuard`], the output stays on the main screen.
+struct RawModeGuard;
+
+impl RawModeGuard {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        Ok(Self)
+    }
+}
+
+impl Drop for RawModeGuard {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Puts the terminal into raw mode on an alternate screen,
+/// and restores it when dropped, including on errors.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let guard = Self;
+        crossterm::execute!(
+            io::stdout(),
+            terminal::EnterAlternateScreen,
+            terminal::Clear(terminal::ClearType::All),
+            cursor::Hide
+        )?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = crossterm::execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
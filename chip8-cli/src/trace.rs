@@ -0,0 +1,108 @@
+//! Execution trace.
+use std::io::Write;
+
+use chip8::{constants::*, prelude::*, Flow};
+use clap::ValueEnum;
+use serde_json::json;
+
+/// Output format of the execution trace.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TraceFormat {
+    /// One human-readable line per instruction
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// Register that was changed by an instruction.
+struct Change {
+    name: String,
+    old: u16,
+    new: u16,
+}
+
+/// Execute the program one instruction at a time, writing each instruction
+/// and the registers it changed.
+///
+/// Execution stops after the step count, or when the VM is interrupted.
+pub fn run_trace(
+    vm: &mut Chip8Vm,
+    step_count: usize,
+    format: TraceFormat,
+    w: &mut impl Write,
+) -> Chip8Result<Flow> {
+    let mut debugger = vm.debugger();
+
+    for step in 0..step_count {
+        let pc = debugger.pc();
+        let instr = pc
+            .checked_sub(MEM_START)
+            .and_then(|index| Disassembler::decode_at(&debugger.memory()[MEM_START..], index));
+        let (opcode, mnemonic) = match instr {
+            Some(ref instr) => (instr.bytecode(), instr.repr().to_string()),
+            None => (0, String::from("????")),
+        };
+
+        let registers = *debugger.registers();
+        let address = debugger.address();
+
+        let flow = debugger.step()?;
+
+        // Register deltas
+        let mut changes = vec![];
+        for (i, (old, new)) in registers.iter().zip(debugger.registers()).enumerate() {
+            if old != new {
+                changes.push(Change {
+                    name: format!("v{i:x}"),
+                    old: *old as u16,
+                    new: *new as u16,
+                });
+            }
+        }
+        if address != debugger.address() {
+            changes.push(Change {
+                name: String::from("i"),
+                old: address,
+                new: debugger.address(),
+            });
+        }
+
+        match format {
+            TraceFormat::Text => {
+                write!(w, "0x{pc:04X}  {opcode:04X}  {mnemonic}")?;
+                for (i, Change { name, old, new }) in changes.iter().enumerate() {
+                    // Align the first change into a column.
+                    let pad = if i == 0 {
+                        20usize.saturating_sub(mnemonic.len())
+                    } else {
+                        0
+                    };
+                    let width = if name == "i" { 3 } else { 2 };
+                    write!(w, "{:pad$}  {name} {old:0width$X}->{new:0width$X}", "")?;
+                }
+                writeln!(w)?;
+            }
+            TraceFormat::Json => {
+                let changes = changes
+                    .iter()
+                    .map(|Change { name, old, new }| (name.clone(), json!([old, new])))
+                    .collect::<serde_json::Map<_, _>>();
+                let entry = json!({
+                    "step": step,
+                    "pc": pc,
+                    "opcode": opcode,
+                    "mnemonic": mnemonic,
+                    "changes": changes,
+                });
+                writeln!(w, "{entry}")?;
+            }
+        }
+
+        if let Flow::Interrupt = flow {
+            return Ok(flow);
+        }
+    }
+
+    Ok(Flow::Ok)
+}
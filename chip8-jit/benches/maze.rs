@@ -0,0 +1,36 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use chip8::prelude::*;
+use chip8_jit::JitVm;
+
+const MAZE: &[u8] = include_bytes!("../../chip8/programs/maze");
+
+fn criterion_benchmark(c: &mut Criterion) {
+    {
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+        vm.load_bytecode(MAZE).unwrap();
+
+        c.bench_function("maze bytecode", |b| {
+            b.iter(|| {
+                let step_count = black_box(1000_usize);
+                black_box(vm.run_steps(step_count))
+            })
+        });
+    }
+
+    {
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+        vm.load_bytecode(MAZE).unwrap();
+        let mut jit = JitVm::new(vm).unwrap();
+
+        c.bench_function("maze jit", |b| {
+            b.iter(|| {
+                let step_count = black_box(1000_usize);
+                black_box(jit.run_steps(step_count))
+            })
+        });
+    }
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
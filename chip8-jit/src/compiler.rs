@@ -0,0 +1,321 @@
+//! Translation of bytecode blocks into native code.
+use std::mem;
+
+use chip8::{
+    constants::*,
+    disasm::ir::{Instr, Op},
+    prelude::*,
+};
+use cranelift_codegen::{
+    ir::{types, AbiParam, InstBuilder, MemFlags, UserFuncName, Value},
+    settings::{self, Configurable},
+    Context,
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Module};
+
+use crate::error::{JitError, JitResult};
+
+/// Maximum number of instructions translated into a single block.
+pub const MAX_BLOCK_LEN: usize = 64;
+
+/// Byte offset of register `I` in [`JitState`].
+const ADDRESS_OFFSET: i32 = REGISTER_COUNT as i32;
+
+/// CPU state shared between the interpreter and compiled blocks.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JitState {
+    pub registers: [u8; REGISTER_COUNT],
+    pub address: Address,
+}
+
+/// Signature of a compiled block.
+pub type BlockFn = unsafe extern "C" fn(*mut JitState);
+
+/// Run of consecutive instructions translated into native code.
+///
+/// Translation stops at the first instruction that isn't supported by
+/// the compiler, which is left for the interpreter to execute.
+#[derive(Clone, Copy)]
+pub struct Block {
+    /// Address of the first instruction.
+    pub start: usize,
+    /// Number of compiled instructions.
+    pub len: usize,
+    /// Compiled code, or `None` when the first instruction isn't supported.
+    pub(crate) func: Option<BlockFn>,
+}
+
+impl Block {
+    /// Block without any compiled instructions, left to the interpreter.
+    #[inline]
+    pub(crate) fn empty(start: usize) -> Self {
+        Self {
+            start,
+            len: 0,
+            func: None,
+        }
+    }
+
+    /// Address following the last compiled instruction.
+    #[inline(always)]
+    pub fn end(&self) -> usize {
+        self.start + self.len * 2
+    }
+
+    /// Indicates whether the block overlaps the given memory range.
+    #[inline]
+    pub fn overlaps(&self, start: usize, end: usize) -> bool {
+        self.start < end && start < self.end()
+    }
+
+    /// Execute the compiled instructions on the state.
+    ///
+    /// # Safety
+    ///
+    /// The code must not have been freed by the compiler that created it.
+    #[inline]
+    pub(crate) unsafe fn call(&self, state: &mut JitState) {
+        if let Some(func) = self.func {
+            func(state);
+        }
+    }
+}
+
+/// Translates bytecode into native code using Cranelift.
+///
+/// Code memory is owned by the compiler, and released when it is dropped.
+/// Invalidated blocks are not freed individually.
+pub struct JitCompiler {
+    module: JITModule,
+    ctx: Context,
+    func_ctx: FunctionBuilderContext,
+    quirks: Quirks,
+}
+
+impl JitCompiler {
+    /// Create a compiler that emits code for the host machine.
+    pub fn new(quirks: Quirks) -> JitResult<Self> {
+        let mut flag_builder = settings::builder();
+        flag_builder
+            .set("use_colocated_libcalls", "false")
+            .map_err(JitError::codegen)?;
+        flag_builder
+            .set("is_pic", "false")
+            .map_err(JitError::codegen)?;
+        let isa = cranelift_native::builder()
+            .map_err(JitError::codegen)?
+            .finish(settings::Flags::new(flag_builder))
+            .map_err(JitError::codegen)?;
+
+        let module = JITModule::new(JITBuilder::with_isa(isa, default_libcall_names()));
+        let ctx = module.make_context();
+
+        Ok(Self {
+            module,
+            ctx,
+            func_ctx: FunctionBuilderContext::new(),
+            quirks,
+        })
+    }
+
+    /// Translate the block starting at the given address.
+    ///
+    /// The memory is the whole VM address space.
+    pub fn compile(&mut self, memory: &[u8], start: usize) -> JitResult<Block> {
+        let instrs = decode_block(memory, start);
+        if instrs.is_empty() {
+            return Ok(Block::empty(start));
+        }
+
+        let pointer_type = self.module.target_config().pointer_type();
+        let mut sig = self.module.make_signature();
+        sig.params.push(AbiParam::new(pointer_type));
+
+        let func_id = self
+            .module
+            .declare_anonymous_function(&sig)
+            .map_err(JitError::codegen)?;
+
+        self.ctx.func.signature = sig;
+        self.ctx.func.name = UserFuncName::user(0, func_id.as_u32());
+
+        {
+            let mut builder = FunctionBuilder::new(&mut self.ctx.func, &mut self.func_ctx);
+            let entry = builder.create_block();
+            builder.append_block_params_for_function_params(entry);
+            builder.switch_to_block(entry);
+
+            let state = builder.block_params(entry)[0];
+            let mut emitter = Emitter {
+                builder: &mut builder,
+                state,
+                quirks: self.quirks,
+            };
+            for instr in &instrs {
+                emitter.emit(&instr.op);
+            }
+
+            builder.ins().return_(&[]);
+            builder.seal_all_blocks();
+            builder.finalize();
+        }
+
+        self.module
+            .define_function(func_id, &mut self.ctx)
+            .map_err(JitError::codegen)?;
+        self.module.clear_context(&mut self.ctx);
+        self.module
+            .finalize_definitions()
+            .map_err(JitError::codegen)?;
+
+        let code = self.module.get_finalized_function(func_id);
+        tracing::trace!(
+            "compiled block 0x{start:04X} with {} instructions",
+            instrs.len()
+        );
+
+        Ok(Block {
+            start,
+            len: instrs.len(),
+            // SAFETY: The function was built with the `BlockFn` signature.
+            func: Some(unsafe { mem::transmute::<*const u8, BlockFn>(code) }),
+        })
+    }
+}
+
+/// Decode the run of supported instructions starting at the address.
+fn decode_block(memory: &[u8], start: usize) -> Vec<Instr> {
+    let mut instrs = vec![];
+    let mut address = start;
+
+    while instrs.len() < MAX_BLOCK_LEN && address >= MEM_START {
+        let instr = match Disassembler::decode_at(&memory[MEM_START..], address - MEM_START) {
+            Some(instr) if is_supported(&instr.op) => instr,
+            _ => break,
+        };
+        address += instr.size();
+        instrs.push(instr);
+    }
+
+    instrs
+}
+
+/// Instructions that only touch registers, and are translated into native code.
+///
+/// Control flow, memory access, timers, input and drawing are left
+/// to the interpreter.
+fn is_supported(op: &Op) -> bool {
+    matches!(
+        op,
+        Op::Load_Byte { .. }
+            | Op::Add_Byte { .. }
+            | Op::Load_Vx_Vy { .. }
+            | Op::Or_Vx_Vy { .. }
+            | Op::And_Vx_Vy { .. }
+            | Op::Xor_Vx_Vy { .. }
+            | Op::ShiftRight { .. }
+            | Op::ShiftLeft { .. }
+            | Op::Load_Address { .. }
+    )
+}
+
+/// Emits the native code for each instruction, operating on [`JitState`].
+struct Emitter<'a, 'b> {
+    builder: &'a mut FunctionBuilder<'b>,
+    state: Value,
+    quirks: Quirks,
+}
+
+impl<'a, 'b> Emitter<'a, 'b> {
+    fn emit(&mut self, op: &Op) {
+        match *op {
+            Op::Load_Byte { vx, nn } => {
+                let value = self.builder.ins().iconst(types::I8, nn as i64);
+                self.store(vx, value);
+            }
+            Op::Add_Byte { vx, nn } => {
+                let x = self.load(vx);
+                let value = self.builder.ins().iadd_imm(x, nn as i64);
+                self.store(vx, value);
+            }
+            Op::Load_Vx_Vy { vx, vy } => {
+                let y = self.load(vy);
+                self.store(vx, y);
+            }
+            Op::Or_Vx_Vy { vx, vy } => {
+                let (x, y) = (self.load(vx), self.load(vy));
+                let value = self.builder.ins().bor(x, y);
+                self.store(vx, value);
+                self.vf_reset();
+            }
+            Op::And_Vx_Vy { vx, vy } => {
+                let (x, y) = (self.load(vx), self.load(vy));
+                let value = self.builder.ins().band(x, y);
+                self.store(vx, value);
+                self.vf_reset();
+            }
+            Op::Xor_Vx_Vy { vx, vy } => {
+                let (x, y) = (self.load(vx), self.load(vy));
+                let value = self.builder.ins().bxor(x, y);
+                self.store(vx, value);
+                self.vf_reset();
+            }
+            // The flag is written after the result, like the interpreter,
+            // so `VF` holds the flag when it's also the destination register.
+            Op::ShiftRight { vx, vy } => {
+                let x = self.shift_operand(vx, vy);
+                let flag = self.builder.ins().band_imm(x, 1);
+                let value = self.builder.ins().ushr_imm(x, 1);
+                self.store(vx, value);
+                self.store(0xF, flag);
+            }
+            Op::ShiftLeft { vx, vy } => {
+                let x = self.shift_operand(vx, vy);
+                let flag = self.builder.ins().ushr_imm(x, 7);
+                let value = self.builder.ins().ishl_imm(x, 1);
+                self.store(vx, value);
+                self.store(0xF, flag);
+            }
+            Op::Load_Address { ref address } => {
+                let value = self
+                    .builder
+                    .ins()
+                    .iconst(types::I16, address.address as i64);
+                self.builder
+                    .ins()
+                    .store(MemFlags::trusted(), value, self.state, ADDRESS_OFFSET);
+            }
+            _ => unreachable!("unsupported instruction: {op:?}"),
+        }
+    }
+
+    fn load(&mut self, register: u8) -> Value {
+        self.builder
+            .ins()
+            .load(types::I8, MemFlags::trusted(), self.state, register as i32)
+    }
+
+    fn store(&mut self, register: u8, value: Value) {
+        self.builder
+            .ins()
+            .store(MemFlags::trusted(), value, self.state, register as i32);
+    }
+
+    fn shift_operand(&mut self, vx: u8, vy: u8) -> Value {
+        if self.quirks.shift_vy {
+            self.load(vy)
+        } else {
+            self.load(vx)
+        }
+    }
+
+    fn vf_reset(&mut self) {
+        if self.quirks.vf_reset {
+            let zero = self.builder.ins().iconst(types::I8, 0);
+            self.store(0xF, zero);
+        }
+    }
+}
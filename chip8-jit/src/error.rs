@@ -0,0 +1,38 @@
+use std::fmt;
+
+use chip8::Chip8Error;
+
+pub type JitResult<T> = Result<T, JitError>;
+
+#[derive(Debug)]
+pub enum JitError {
+    /// Error raised by the virtual machine while interpreting.
+    Chip8(Chip8Error),
+    /// Native code generation failed.
+    Codegen(String),
+}
+
+impl JitError {
+    #[inline(never)]
+    #[cold]
+    pub(crate) fn codegen(err: impl ToString) -> Self {
+        Self::Codegen(err.to_string())
+    }
+}
+
+impl std::error::Error for JitError {}
+
+impl fmt::Display for JitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Chip8(err) => write!(f, "{err}"),
+            Self::Codegen(err) => write!(f, "code generation error: {err}"),
+        }
+    }
+}
+
+impl From<Chip8Error> for JitError {
+    fn from(err: Chip8Error) -> Self {
+        Self::Chip8(err)
+    }
+}
@@ -0,0 +1,19 @@
+//! Dynamic recompilation backend for the Chip-8 virtual machine.
+//!
+//! Runs of register instructions are translated into native code with
+//! Cranelift, one basic block at a time. Every other instruction is
+//! executed by the interpreter in [`chip8::Chip8Vm`], which remains
+//! the owner of the machine state.
+//!
+//! Compiled blocks are invalidated when the program writes to the memory
+//! they were translated from. Code that keeps modifying itself is left
+//! to the interpreter.
+mod compiler;
+mod error;
+mod vm;
+
+pub use self::{
+    compiler::{Block, BlockFn, JitCompiler, JitState, MAX_BLOCK_LEN},
+    error::{JitError, JitResult},
+    vm::{JitEngine, JitStats, JitVm, MAX_INVALIDATIONS},
+};
@@ -0,0 +1,302 @@
+//! Virtual machine driving compiled blocks and the interpreter.
+use chip8::{constants::*, prelude::*, Flow, FrameReport};
+
+use crate::{
+    compiler::{Block, JitCompiler, JitState, MAX_BLOCK_LEN},
+    error::{JitError, JitResult},
+};
+
+/// Number of times the blocks at an address can be invalidated before
+/// the address is considered self-modifying code, and left to the interpreter.
+pub const MAX_INVALIDATIONS: u8 = 4;
+
+/// Counters for comparing the compiled and interpreted workloads.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct JitStats {
+    /// Number of blocks translated into native code.
+    pub blocks_compiled: usize,
+    /// Number of compiled blocks discarded because their memory was written to.
+    pub blocks_invalidated: usize,
+    /// Number of instructions executed as native code.
+    pub compiled_steps: usize,
+    /// Number of instructions executed by the interpreter.
+    pub interpreted_steps: usize,
+}
+
+/// Virtual machine that executes compiled blocks where possible,
+/// and falls back to the interpreter for everything else.
+///
+/// Breakpoints and rewind snapshots are only observed by instructions
+/// executed by the interpreter.
+pub struct JitVm {
+    vm: Chip8Vm,
+    engine: JitEngine,
+}
+
+impl JitVm {
+    /// Wrap a virtual machine, with its program already loaded.
+    pub fn new(vm: Chip8Vm) -> JitResult<Self> {
+        let engine = JitEngine::new(vm.config().quirks)?;
+        Ok(Self { vm, engine })
+    }
+
+    pub fn vm(&self) -> &Chip8Vm {
+        &self.vm
+    }
+
+    /// Mutable access to the virtual machine.
+    ///
+    /// The memory may be changed through the VM, so all compiled blocks are discarded.
+    pub fn vm_mut(&mut self) -> &mut Chip8Vm {
+        self.engine.clear();
+        &mut self.vm
+    }
+
+    pub fn into_inner(self) -> Chip8Vm {
+        self.vm
+    }
+
+    pub fn stats(&self) -> &JitStats {
+        self.engine.stats()
+    }
+
+    /// Execute the given number of instructions.
+    ///
+    /// Execution stops early when the VM is interrupted.
+    pub fn run_steps(&mut self, step_count: usize) -> JitResult<Flow> {
+        self.engine.run_steps(&mut self.vm, step_count)
+    }
+
+    /// Execute instructions until the next 60Hz display tick, like [`Chip8Vm::run_frame`].
+    pub fn run_frame(&mut self) -> JitResult<FrameReport> {
+        self.engine.run_frame(&mut self.vm)
+    }
+
+    /// Discard the compiled blocks that overlap the memory range.
+    pub fn invalidate(&mut self, start: usize, end: usize) {
+        self.engine.invalidate(start, end);
+    }
+}
+
+/// Compiled blocks of a program, executed against a virtual machine
+/// that's passed in, so the owner of the VM can switch backends.
+///
+/// The blocks are only invalidated by writes of the program. When memory
+/// is changed from outside, like loading a program or restoring a snapshot,
+/// the blocks must be discarded with [`JitEngine::clear`].
+pub struct JitEngine {
+    compiler: JitCompiler,
+    /// Cached blocks, by start address.
+    blocks: Box<[Option<Block>]>,
+    /// Number of times the blocks starting at each address were invalidated.
+    invalidations: Box<[u8]>,
+    stats: JitStats,
+}
+
+impl JitEngine {
+    /// Create the engine for programs running with the quirks,
+    /// which are compiled into the blocks.
+    pub fn new(quirks: Quirks) -> JitResult<Self> {
+        Ok(Self {
+            compiler: JitCompiler::new(quirks)?,
+            blocks: vec![None; MEM_SIZE].into_boxed_slice(),
+            invalidations: vec![0; MEM_SIZE].into_boxed_slice(),
+            stats: JitStats::default(),
+        })
+    }
+
+    pub fn stats(&self) -> &JitStats {
+        &self.stats
+    }
+
+    /// Discard all compiled blocks.
+    pub fn clear(&mut self) {
+        self.blocks.fill(None);
+    }
+
+    /// Execute the given number of instructions.
+    ///
+    /// Execution stops early when the VM is interrupted.
+    pub fn run_steps(&mut self, vm: &mut Chip8Vm, step_count: usize) -> JitResult<Flow> {
+        let mut report = FrameReport::default();
+        self.run(vm, step_count, &mut report, false)
+    }
+
+    /// Execute a single instruction of a frame, like [`Debugger::exec`](chip8::Debugger::exec).
+    pub fn exec(&mut self, vm: &mut Chip8Vm) -> JitResult<Flow> {
+        let mut report = FrameReport::default();
+        let flow = self.run(vm, 1, &mut report, true)?;
+        Ok(if report.draw { Flow::Draw } else { flow })
+    }
+
+    /// Execute instructions until the next 60Hz display tick, like [`Chip8Vm::run_frame`].
+    ///
+    /// The frame ends early when the program waits for a key, or is interrupted.
+    pub fn run_frame(&mut self, vm: &mut Chip8Vm) -> JitResult<FrameReport> {
+        let mut report = FrameReport::default();
+        let step_count = vm.instructions_per_frame();
+        self.run(vm, step_count, &mut report, true)?;
+        vm.finish_frame(&mut report);
+        Ok(report)
+    }
+
+    /// Execute up to the given number of instructions, recording them in the report.
+    ///
+    /// Within a frame, instructions are executed without counting down the
+    /// timers, and execution stops when the program waits for a key.
+    fn run(
+        &mut self,
+        vm: &mut Chip8Vm,
+        step_count: usize,
+        report: &mut FrameReport,
+        frame: bool,
+    ) -> JitResult<Flow> {
+        let mut steps = 0;
+
+        while steps < step_count {
+            // Blocks don't check for interrupts, so they're checked before every block.
+            if vm.debugger().is_interrupted() {
+                report.interrupt = true;
+                return Ok(Flow::Interrupt);
+            }
+
+            let pc = vm.debugger().pc();
+            let block = self.block(vm, pc)?;
+
+            // A block is all or nothing, so it can't run past the step count.
+            if block.len > 0 && block.len <= step_count - steps {
+                let mut debugger = vm.debugger();
+                let mut state = JitState {
+                    registers: *debugger.registers(),
+                    address: debugger.address(),
+                };
+
+                // SAFETY: The compiler that owns the code lives as long as the cache.
+                unsafe { block.call(&mut state) };
+
+                *debugger.registers_mut() = state.registers;
+                debugger.set_address(state.address);
+                debugger.set_pc(block.end());
+
+                steps += block.len;
+                report.instructions += block.len;
+                self.stats.compiled_steps += block.len;
+                continue;
+            }
+
+            let mut debugger = vm.debugger();
+            let written = written_range(debugger.memory(), pc, debugger.address() as usize);
+            let flow = if frame {
+                debugger.exec()?
+            } else {
+                debugger.step()?
+            };
+
+            self.stats.interpreted_steps += 1;
+
+            if let Some((start, end)) = written {
+                self.invalidate(start, end);
+            }
+
+            match flow {
+                Flow::Interrupt => {
+                    report.interrupt = true;
+                    return Ok(flow);
+                }
+                // The instruction didn't complete, and will be retried next frame.
+                Flow::KeyWait if frame => {
+                    report.key_wait = true;
+                    return Ok(flow);
+                }
+                Flow::Draw => report.draw = true,
+                _ => {}
+            }
+
+            steps += 1;
+            report.instructions += 1;
+        }
+
+        Ok(Flow::Ok)
+    }
+
+    /// Discard the compiled blocks that overlap the memory range.
+    pub fn invalidate(&mut self, start: usize, end: usize) {
+        // Blocks starting further back can't reach the range.
+        let first = start.saturating_sub(MAX_BLOCK_LEN * 2);
+        let last = end.min(MEM_SIZE);
+
+        for address in first..last {
+            let is_overlapping = matches!(
+                self.blocks[address],
+                Some(block) if block.overlaps(start, end)
+            );
+
+            if is_overlapping {
+                tracing::trace!("invalidated block 0x{address:04X}");
+                self.blocks[address] = None;
+                self.invalidations[address] = self.invalidations[address].saturating_add(1);
+                self.stats.blocks_invalidated += 1;
+            }
+        }
+    }
+
+    /// Look up the block at the address, compiling it on a cache miss.
+    fn block(&mut self, vm: &mut Chip8Vm, address: usize) -> JitResult<Block> {
+        if address >= MEM_SIZE {
+            // Out of bounds, so the interpreter can raise the error.
+            return Ok(Block::empty(address));
+        }
+
+        if let Some(block) = self.blocks[address] {
+            return Ok(block);
+        }
+
+        let block = if self.invalidations[address] >= MAX_INVALIDATIONS {
+            Block::empty(address)
+        } else {
+            self.compiler.compile(vm.debugger().memory(), address)?
+        };
+
+        if block.len > 0 {
+            self.stats.blocks_compiled += 1;
+        }
+        self.blocks[address] = Some(block);
+
+        Ok(block)
+    }
+}
+
+impl Interpreter for JitVm {
+    type Error = JitError;
+
+    fn step(&mut self) -> JitResult<Flow> {
+        JitVm::run_steps(self, 1)
+    }
+
+    fn exec(&mut self) -> JitResult<Flow> {
+        self.engine.exec(&mut self.vm)
+    }
+
+    fn run_steps(&mut self, step_count: usize) -> JitResult<Flow> {
+        JitVm::run_steps(self, step_count)
+    }
+
+    fn machine(&self) -> &Chip8Vm {
+        &self.vm
+    }
+
+    fn machine_mut(&mut self) -> &mut Chip8Vm {
+        self.vm_mut()
+    }
+}
+
+/// Memory range written by the instruction at the address, if any.
+fn written_range(memory: &[u8], pc: usize, address: usize) -> Option<(usize, usize)> {
+    match memory.get(pc..pc + 2)? {
+        // Fx33 (LD B, Vx)
+        [a, 0x33] if a >> 4 == 0xF => Some((address, address + 3)),
+        // Fx55 (LD [I], Vx)
+        [a, 0x55] if a >> 4 == 0xF => Some((address, address + (a & 0xF) as usize + 1)),
+        _ => None,
+    }
+}
@@ -0,0 +1,194 @@
+//! Compiled execution must match the interpreter.
+use chip8::{prelude::*, testing::lockstep, Flow};
+use chip8_jit::{JitEngine, JitVm, MAX_INVALIDATIONS};
+
+/// Arithmetic in a loop, drawing the font set.
+const FONT_SET: &str = "
+.main
+    LD  va, 0
+    LD  vb, 0
+    LD  vc, 0
+.loop
+    LD  v1, 0x81
+    OR  v1, vc
+    SHL v2, v1
+    SHR v3, v2
+    XOR v4, v3
+    AND v4, v1
+    LD  F,  vc
+    DRW va, vb, 5
+    ADD vc, 1
+    ADD va, 5
+    SNE vc, 0xa
+    JP  .next_row
+    SE  vc, 0x10
+    JP  .loop
+.forever
+    JP  .forever
+.next_row
+    LD  va, 0
+    ADD vb, 6
+    JP  .loop
+";
+
+/// Patches the instruction at `.patch` into `LD v5, 42`.
+const SELF_MODIFYING: &str = "
+.main
+    LD  v0, 0x65
+    LD  v1, 0x2A
+    LD  I,  .patch
+.loop
+    ADD v3, 1
+.patch
+    LD  v5, 0
+    LD  [I], v1
+    JP  .loop
+";
+
+/// Shifts with `VF` as the destination, which ends up holding the flag.
+const SHIFT_VF: &str = "
+.loop
+    LD  vf, 0x81
+    SHR vf, vf
+    LD  v0, vf
+    LD  vf, 0x40
+    SHL vf, vf
+    LD  v1, vf
+    ADD v2, 1
+    JP  .loop
+";
+
+fn load(source_code: &str, quirks: Quirks) -> Chip8Vm {
+    let mut vm = Chip8Vm::new(Chip8Conf {
+        quirks,
+        ..Default::default()
+    });
+    vm.load_bytecode(&chip8::assemble(source_code).unwrap())
+        .unwrap();
+    vm
+}
+
+/// Backends are interchangeable behind the `Interpreter` trait.
+fn run<I: Interpreter>(backend: &mut I, steps: usize) -> Flow
+where
+    I::Error: std::fmt::Debug,
+{
+    backend.run_steps(steps).unwrap()
+}
+
+fn assert_same_state(interpreter: &mut impl Interpreter, jit: &mut impl Interpreter) {
+    let (interpreter, jit) = (interpreter.machine_mut(), jit.machine_mut());
+    let (a, b) = (interpreter.debugger(), jit.debugger());
+    assert_eq!(a.pc(), b.pc(), "program counter");
+    assert_eq!(a.registers(), b.registers(), "registers");
+    assert_eq!(a.address(), b.address(), "address register");
+    assert_eq!(a.memory(), b.memory(), "memory");
+    assert_eq!(
+        interpreter.dump_display().unwrap(),
+        jit.dump_display().unwrap()
+    );
+}
+
+#[test]
+fn test_jit_matches_interpreter() {
+    for quirks in [Quirks::default(), Quirks::COSMAC_VIP] {
+        for steps in [1, 7, 100, 500] {
+            let mut interpreter = load(FONT_SET, quirks);
+            run(&mut interpreter, steps);
+
+            let mut jit = JitVm::new(load(FONT_SET, quirks)).unwrap();
+            assert_eq!(run(&mut jit, steps), Flow::Ok);
+
+            let stats = *jit.stats();
+            assert_eq!(stats.compiled_steps + stats.interpreted_steps, steps);
+            if steps >= 100 {
+                assert!(stats.compiled_steps > 0);
+            }
+
+            assert_same_state(&mut interpreter, &mut jit);
+        }
+    }
+}
+
+#[test]
+fn test_jit_self_modifying_code() {
+    let steps = 100;
+
+    let mut interpreter = load(SELF_MODIFYING, Quirks::default());
+    run(&mut interpreter, steps);
+
+    let mut jit = JitVm::new(load(SELF_MODIFYING, Quirks::default())).unwrap();
+    run(&mut jit, steps);
+
+    assert_eq!(jit.vm_mut().debugger().registers()[5], 42);
+    assert!(jit.stats().blocks_invalidated >= MAX_INVALIDATIONS as usize);
+    assert_same_state(&mut interpreter, &mut jit);
+}
+
+#[test]
+fn test_jit_frames_match_interpreter() {
+    let mut interpreter = load(FONT_SET, Quirks::default());
+    let mut jit = JitVm::new(load(FONT_SET, Quirks::default())).unwrap();
+
+    for _ in 0..20 {
+        let expected = interpreter.run_frame().unwrap();
+        assert_eq!(jit.run_frame().unwrap(), expected);
+    }
+    assert_eq!(jit.vm().frame(), interpreter.frame());
+    assert!(jit.stats().compiled_steps > 0);
+    assert_same_state(&mut interpreter, &mut jit);
+}
+
+/// The engine runs against a VM it doesn't own, and picks up where another backend left off.
+#[test]
+fn test_jit_engine_switch() {
+    let mut interpreter = load(FONT_SET, Quirks::default());
+    interpreter.run_steps(250).unwrap();
+
+    let mut vm = load(FONT_SET, Quirks::default());
+    vm.run_steps(50).unwrap();
+    let mut engine = JitEngine::new(vm.config().quirks).unwrap();
+    engine.run_steps(&mut vm, 100).unwrap();
+    vm.run_steps(100).unwrap();
+
+    assert_same_state(&mut interpreter, &mut vm);
+}
+
+#[test]
+fn test_jit_shift_vf() {
+    for quirks in [Quirks::default(), Quirks::COSMAC_VIP] {
+        let mut interpreter = load(SHIFT_VF, quirks);
+        run(&mut interpreter, 100);
+
+        let mut jit = JitVm::new(load(SHIFT_VF, quirks)).unwrap();
+        run(&mut jit, 100);
+        assert!(jit.stats().compiled_steps > 0);
+
+        // The flags of 0x81 >> 1 and 0x40 << 1.
+        assert_eq!(jit.vm_mut().debugger().registers()[0..2], [1, 0]);
+        assert_same_state(&mut interpreter, &mut jit);
+    }
+}
+
+#[test]
+fn test_jit_lockstep() {
+    for source_code in [FONT_SET, SHIFT_VF] {
+        let rom = chip8::assemble(source_code).unwrap();
+        let mut interpreter = Chip8Vm::new(Chip8Conf::default());
+        let mut jit = JitVm::new(Chip8Vm::new(Chip8Conf::default())).unwrap();
+
+        let divergence = lockstep(&mut interpreter, &mut jit, &rom, 500).unwrap();
+        assert!(divergence.is_none(), "{}", divergence.unwrap());
+        assert_eq!(jit.vm().frame(), interpreter.frame());
+        assert_same_state(&mut interpreter, &mut jit);
+    }
+}
+
+/// Execution doesn't continue past an interrupt.
+#[test]
+fn test_jit_interrupt() {
+    let mut jit = JitVm::new(load("LD v0, 1\nRET\nLD v1, 1\n", Quirks::default())).unwrap();
+    assert!(jit.run_steps(10).is_err());
+    assert_eq!(jit.run_steps(10).unwrap(), Flow::Interrupt);
+    assert_eq!(jit.vm_mut().debugger().registers()[1], 0);
+}
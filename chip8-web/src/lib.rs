@@ -0,0 +1,184 @@
+//! Browser frontend for the Chip-8 virtual machine.
+//!
+//! The VM is exported to JavaScript, which drives the frame loop with
+//! `requestAnimationFrame` and forwards keyboard events. The display is
+//! drawn to a canvas the size of the Chip-8 display, and scaled up with CSS.
+use chip8::{constants::*, prelude::*, KeyCode, KeyState, Rom, Synth};
+use wasm_bindgen::{prelude::*, Clamped};
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, ImageData};
+
+/// Host keys mapped to the hex keypad, in the layout of the COSMAC VIP.
+///
+/// Keys are identified by their physical position, as reported by
+/// `KeyboardEvent.code`, so the layout is the same on any keyboard.
+///
+/// ```text
+/// 1 2 3 4      1 2 3 C
+/// Q W E R  =>  4 5 6 D
+/// A S D F      7 8 9 E
+/// Z X C V      A 0 B F
+/// ```
+const KEYMAP: [(&str, KeyCode); KEY_COUNT as usize] = [
+    ("Digit1", KeyCode::Key1),
+    ("Digit2", KeyCode::Key2),
+    ("Digit3", KeyCode::Key3),
+    ("Digit4", KeyCode::KeyC),
+    ("KeyQ", KeyCode::Key4),
+    ("KeyW", KeyCode::Key5),
+    ("KeyE", KeyCode::Key6),
+    ("KeyR", KeyCode::KeyD),
+    ("KeyA", KeyCode::Key7),
+    ("KeyS", KeyCode::Key8),
+    ("KeyD", KeyCode::Key9),
+    ("KeyF", KeyCode::KeyE),
+    ("KeyZ", KeyCode::KeyA),
+    ("KeyX", KeyCode::Key0),
+    ("KeyC", KeyCode::KeyB),
+    ("KeyV", KeyCode::KeyF),
+];
+
+/// Sample rate of the audio until the page sets the rate of its `AudioContext`.
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+/// Virtual machine drawing to a canvas.
+#[wasm_bindgen]
+pub struct WebVm {
+    vm: Chip8Vm,
+    canvas: HtmlCanvasElement,
+    context: CanvasRenderingContext2d,
+    synth: Synth,
+}
+
+#[wasm_bindgen]
+impl WebVm {
+    /// Create a VM that draws to the given canvas.
+    ///
+    /// The canvas is resized to the Chip-8 display resolution.
+    #[wasm_bindgen(constructor)]
+    pub fn new(canvas: HtmlCanvasElement) -> Result<WebVm, JsValue> {
+        canvas.set_width(DISPLAY_WIDTH as u32);
+        canvas.set_height(DISPLAY_HEIGHT as u32);
+
+        let context = canvas
+            .get_context("2d")?
+            .ok_or_else(|| JsValue::from_str("canvas has no 2d context"))?
+            .dyn_into::<CanvasRenderingContext2d>()?;
+
+        let conf = Chip8Conf::default();
+        Ok(Self {
+            synth: Synth::new(conf.audio, DEFAULT_SAMPLE_RATE),
+            vm: Chip8Vm::new(conf),
+            canvas,
+            context,
+        })
+    }
+
+    /// Load the program into a freshly reset VM, unwrapped from its container.
+    #[wasm_bindgen(js_name = loadRom)]
+    pub fn load_rom(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        let rom =
+            Rom::from_bytes_at(bytes, self.vm.config().load_address()).map_err(to_js_error)?;
+        let mut vm = Chip8Vm::new(self.vm.config().clone());
+        vm.load_bytecode(&rom).map_err(to_js_error)?;
+        self.vm = vm;
+        self.draw()
+    }
+
+    /// Execute the instructions of one 60Hz frame, and draw the display when it changed.
+    ///
+    /// Returns whether the buzzer is on.
+    #[wasm_bindgen(js_name = runFrame)]
+    pub fn run_frame(&mut self) -> Result<bool, JsValue> {
+        let report = self.vm.run_frame().map_err(to_js_error)?;
+        if report.draw {
+            self.draw()?;
+        }
+        Ok(report.sound)
+    }
+
+    /// Sample rate of the buffers filled by [`WebVm::render_audio`].
+    #[wasm_bindgen(js_name = setSampleRate)]
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.synth = Synth::new(self.vm.config().audio, sample_rate);
+    }
+
+    /// Fill the buffer with mono samples of the buzzer, and of XO-CHIP audio patterns.
+    #[wasm_bindgen(js_name = renderAudio)]
+    pub fn render_audio(&mut self, buffer: &mut [f32]) {
+        self.synth.render(&self.vm.audio_state(), buffer);
+    }
+
+    /// Forward a `keydown` event.
+    ///
+    /// Returns whether the key is mapped to the keypad,
+    /// so the caller can prevent the browser's default action.
+    #[wasm_bindgen(js_name = keyDown)]
+    pub fn key_down(&mut self, code: &str) -> bool {
+        self.push_key(code, KeyState::Pressed)
+    }
+
+    /// Forward a `keyup` event.
+    #[wasm_bindgen(js_name = keyUp)]
+    pub fn key_up(&mut self, code: &str) -> bool {
+        self.push_key(code, KeyState::Released)
+    }
+
+    /// Draw the display to the canvas.
+    pub fn draw(&self) -> Result<(), JsValue> {
+        let image = self.vm.display_to_image(1);
+        let data = ImageData::new_with_u8_clamped_array_and_sh(
+            Clamped(image.pixels()),
+            image.width() as u32,
+            image.height() as u32,
+        )?;
+        self.context.put_image_data(&data, 0.0, 0.0)
+    }
+
+    pub fn canvas(&self) -> HtmlCanvasElement {
+        self.canvas.clone()
+    }
+}
+
+impl WebVm {
+    fn push_key(&mut self, code: &str, state: KeyState) -> bool {
+        match map_key(code) {
+            Some(key) => {
+                self.vm.push_key_event(key, state);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn map_key(code: &str) -> Option<KeyCode> {
+    KEYMAP
+        .iter()
+        .find(|(host_key, _)| *host_key == code)
+        .map(|(_, key)| *key)
+}
+
+fn to_js_error(err: Chip8Error) -> JsValue {
+    JsValue::from_str(&err.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_keymap() {
+        assert_eq!(map_key("KeyQ"), Some(KeyCode::Key4));
+        assert_eq!(map_key("KeyX"), Some(KeyCode::Key0));
+        assert_eq!(map_key("Escape"), None);
+
+        // Every keypad key is mapped exactly once.
+        for index in 0..KEY_COUNT {
+            let count = KEYMAP
+                .iter()
+                .filter(|(_, key)| key.as_u8() == index)
+                .count();
+            assert_eq!(count, 1, "key {index:X}");
+        }
+    }
+}
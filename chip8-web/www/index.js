@@ -0,0 +1,83 @@
+// Built with `wasm-pack build chip8-web --target web`
+import init, { WebVm } from "../pkg/chip8_web.js";
+
+await init();
+
+const vm = new WebVm(document.getElementById("display"));
+const audio = new AudioContext();
+let running = false;
+
+// The VM renders the buzzer, and XO-CHIP audio patterns, into the output buffer.
+vm.setSampleRate(audio.sampleRate);
+const speaker = audio.createScriptProcessor(1024, 0, 1);
+speaker.onaudioprocess = (event) => {
+    const buffer = event.outputBuffer.getChannelData(0);
+    if (running) {
+        vm.renderAudio(buffer);
+    } else {
+        buffer.fill(0);
+    }
+};
+speaker.connect(audio.destination);
+
+// Emulation runs at 60Hz, regardless of the display refresh rate.
+const FRAME_TIME = 1000 / 60;
+let lastTime = performance.now();
+let pending = 0;
+
+async function loadRom(file) {
+    const bytecode = new Uint8Array(await file.arrayBuffer());
+    try {
+        vm.loadRom(bytecode);
+        running = true;
+    } catch (err) {
+        console.error(`failed to load rom: ${err}`);
+    }
+}
+
+function frame(time) {
+    // Don't try to catch up after the tab was in the background.
+    pending = Math.min(pending + time - lastTime, FRAME_TIME * 4);
+    lastTime = time;
+
+    while (running && pending >= FRAME_TIME) {
+        pending -= FRAME_TIME;
+        try {
+            vm.runFrame();
+        } catch (err) {
+            console.error(`VM error: ${err}`);
+            running = false;
+        }
+    }
+    requestAnimationFrame(frame);
+}
+
+document.addEventListener("keydown", (event) => {
+    // Browsers only allow audio after user interaction.
+    audio.resume();
+    if (vm.keyDown(event.code)) {
+        event.preventDefault();
+    }
+});
+
+document.addEventListener("keyup", (event) => {
+    if (vm.keyUp(event.code)) {
+        event.preventDefault();
+    }
+});
+
+document.getElementById("rom").addEventListener("change", (event) => {
+    if (event.target.files.length > 0) {
+        loadRom(event.target.files[0]);
+    }
+});
+
+document.addEventListener("dragover", (event) => event.preventDefault());
+document.addEventListener("drop", (event) => {
+    event.preventDefault();
+    if (event.dataTransfer.files.length > 0) {
+        loadRom(event.dataTransfer.files[0]);
+    }
+});
+
+requestAnimationFrame(frame);
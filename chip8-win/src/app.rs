@@ -1,23 +1,86 @@
-use std::io::Read;
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
-use chip8::{prelude::*, Flow};
-use log::info;
+use chip8::prelude::*;
+use native_dialog::FileDialog;
 use winit::{
     event::{Event as EV, WindowEvent as WE},
-    event_loop::EventLoopBuilder,
+    event_loop::{ControlFlow, EventLoopBuilder},
     platform::run_return::EventLoopExtRunReturn,
+    window::WindowId,
 };
 
 use crate::{
-    actions::*, error::AppError, render::Render, window::WindowContext, EventLoop, InputMap,
+    actions::*,
+    backend::Backend,
+    error::AppError,
+    frame_clock::{FrameClock, Timestep},
+    render::DisplayFilter,
+    theme::Theme,
+    vm_window::VmWindow,
+    window::WindowContext,
+    EventLoop, InputMap,
 };
 
+/// Time between checks whether the input map file has changed.
+const INPUT_WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
 /// Chip8 Application
+///
+/// Runs one or more windows, each with its own VM, in a single event loop.
+/// Window events are routed by their window ID. The keypad and the actions
+/// of the input map apply to every window, so windows running the same
+/// program with different configurations stay comparable.
 pub struct Chip8App {
-    window_ctx: WindowContext,
-    render: Render,
-    vm: Chip8Vm,
+    windows: Vec<VmWindow>,
     input_map: InputMap,
+    /// Last time the input map file was checked for changes.
+    input_checked: Instant,
+    /// Window with the keyboard focus, which opened ROMs are loaded into.
+    focused: Option<WindowId>,
+    /// Paces emulation at 60 frames per second, shared by the
+    /// windows so comparisons stay in lockstep.
+    clock: FrameClock,
+}
+
+/// Presentation and pacing options, applied to every window.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct AppOptions {
+    /// Wait for the vertical blank when presenting.
+    ///
+    /// Default: `true`
+    pub vsync: bool,
+    /// How emulation advances on every update.
+    ///
+    /// Default: [`Timestep::Fixed`]
+    pub timestep: Timestep,
+    /// Collect frame pacing statistics, logged periodically
+    /// and shown in the debug panels.
+    ///
+    /// Default: `false`
+    pub pacing_stats: bool,
+    /// Post-processing of the scaled display, cycled by the `cycle_filter` action.
+    ///
+    /// Default: [`DisplayFilter::Nearest`]
+    pub filter: DisplayFilter,
+    /// How the VMs execute instructions, cycled by the `cycle_backend` action.
+    ///
+    /// Default: [`Backend::Interpreter`]
+    pub backend: Backend,
+}
+
+impl Default for AppOptions {
+    fn default() -> Self {
+        Self {
+            vsync: true,
+            timestep: Timestep::default(),
+            pacing_stats: false,
+            filter: DisplayFilter::default(),
+            backend: Backend::default(),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -31,61 +94,195 @@ pub enum AppControl {
 impl Chip8App {
     /// Create the Chip8 window app.
     pub fn from_window(window_ctx: WindowContext, input_map: InputMap) -> Self {
-        // Create an application specific renderer.
-        let render = Render::new(window_ctx.gl.clone());
-        log::info!("OpenGL renderer created:\n{}", render.opengl_info());
-
-        // Create Chip8 emulated
-        let vm = Chip8Vm::new(Chip8Conf {
-            clock_frequency: None,
-        });
+        Self::with_conf(
+            window_ctx,
+            input_map,
+            Chip8Conf {
+                clock_frequency: None,
+                ..Default::default()
+            },
+        )
+    }
 
+    /// Create the Chip8 window app, with the given VM configuration.
+    ///
+    /// Rewinding is always configured by the app.
+    pub fn with_conf(window_ctx: WindowContext, input_map: InputMap, conf: Chip8Conf) -> Self {
+        let window = VmWindow::new(window_ctx, conf);
         Self {
-            window_ctx,
-            render,
+            focused: Some(window.window_id()),
+            windows: vec![window],
             input_map,
-            vm,
+            input_checked: Instant::now(),
+            clock: FrameClock::new(),
         }
     }
 
-    pub fn create_event_loop() -> EventLoop {
-        EventLoopBuilder::new().build()
+    /// Add another window with its own VM, with the given configuration.
+    pub fn add_window(&mut self, window_ctx: WindowContext, conf: Chip8Conf) -> WindowId {
+        let window = VmWindow::new(window_ctx, conf);
+        let window_id = window.window_id();
+        self.windows.push(window);
+        window_id
     }
 
-    /// Load ROM file into VM
-    pub fn load_rom_file(&mut self, filepath: &str) -> Result<(), AppError> {
-        info!("load rom: {filepath}");
+    pub fn windows(&self) -> &[VmWindow] {
+        &self.windows
+    }
 
-        let mut buf = vec![];
+    pub fn windows_mut(&mut self) -> &mut [VmWindow] {
+        &mut self.windows
+    }
 
-        let mut file = std::fs::File::open(filepath)?;
-        file.read_to_end(&mut buf)?;
+    pub fn window_mut(&mut self, window_id: WindowId) -> Option<&mut VmWindow> {
+        self.windows
+            .iter_mut()
+            .find(|window| window.window_id() == window_id)
+    }
 
-        self.vm.load_bytecode(&buf)?;
+    /// Change the colours and effects of the display of every window.
+    pub fn set_theme(&mut self, theme: Theme) {
+        for window in &mut self.windows {
+            window.set_theme(theme.clone());
+        }
+    }
+
+    /// Change the presentation and pacing of every window.
+    ///
+    /// Must be set again after adding windows.
+    pub fn set_options(&mut self, options: AppOptions) {
+        self.clock.set_timestep(options.timestep);
+        for window in &mut self.windows {
+            window.set_vsync(options.vsync);
+            window.set_pacing_stats(options.pacing_stats);
+            window.set_filter(options.filter);
+            window.set_backend(options.backend);
+        }
+    }
 
+    pub fn create_event_loop() -> EventLoop {
+        EventLoopBuilder::new().build()
+    }
+
+    /// Load ROM file into the VM of every window.
+    pub fn load_rom_file(&mut self, filepath: impl AsRef<Path>) -> Result<(), AppError> {
+        for window in &mut self.windows {
+            window.load_rom_file(filepath.as_ref())?;
+        }
         Ok(())
     }
 
     pub fn load_rom_asm(&mut self, source_code: &str) -> Result<(), AppError> {
         let bytecode = chip8::assemble(source_code)?;
-        self.vm.load_bytecode(&bytecode)?;
-        Ok(())
+        self.load_rom_bytecode(&bytecode)
     }
 
+    /// Load the program into the freshly reset VM of every window.
     pub fn load_rom_bytecode(&mut self, bytecode: &[u8]) -> Result<(), AppError> {
-        self.vm.load_bytecode(bytecode)?;
+        for window in &mut self.windows {
+            window.load_rom_bytecode(bytecode)?;
+        }
+        Ok(())
+    }
+
+    /// Descriptor of the loaded program, which names the windows.
+    pub fn set_rom_info(&mut self, rom_info: Option<RomInfo>) {
+        for window in &mut self.windows {
+            window.set_rom_info(rom_info.clone());
+        }
+    }
+
+    /// Reset the VMs, and load their current programs again.
+    pub fn reload_rom(&mut self) -> Result<(), AppError> {
+        for window in &mut self.windows {
+            window.reload_rom()?;
+        }
         Ok(())
     }
+
+    /// Pick a ROM file with the system file dialog, and load it
+    /// into the focused window.
+    ///
+    /// Emulation is blocked while the dialog is open.
+    fn open_rom_dialog(&mut self) {
+        let index = self
+            .windows
+            .iter()
+            .position(|window| Some(window.window_id()) == self.focused)
+            .unwrap_or(0);
+        let Some(window) = self.windows.get_mut(index) else {
+            return;
+        };
+
+        let result = FileDialog::new()
+            .set_title("Open ROM")
+            .add_filter("Chip-8 ROM", &["ch8", "rom", chip8::ARCHIVE_EXT])
+            .set_owner(window.window())
+            .show_open_single_file();
+
+        match result {
+            Ok(Some(path)) => window.load_rom_logged(&path),
+            Ok(None) => tracing::info!("no rom selected"),
+            Err(err) => tracing::error!("failed to open file dialog: {err}"),
+        }
+    }
+
+    /// Freeze or resume emulation in every window.
+    pub fn set_paused(&mut self, paused: bool) {
+        for window in &mut self.windows {
+            window.set_paused(paused);
+        }
+    }
+
+    /// Indicates whether emulation is frozen in every window.
+    pub fn is_paused(&self) -> bool {
+        self.windows.iter().all(VmWindow::is_paused)
+    }
+
+    /// Execute the step actions, which pause emulation if it's running.
+    ///
+    /// Returns `true` when a step was taken.
+    fn step_paused(&mut self) -> bool {
+        if self.input_map.is_action_released(STEP_FRAME) {
+            self.windows.iter_mut().for_each(VmWindow::step_frame);
+        } else if self.input_map.is_action_released(STEP_INSTR) {
+            self.windows.iter_mut().for_each(VmWindow::step_instr);
+        } else {
+            return false;
+        }
+
+        true
+    }
+
+    /// Save the display of every window as a PNG image in the working directory.
+    pub fn save_screenshots(&self) -> Result<Vec<PathBuf>, AppError> {
+        let single = self.windows.len() == 1;
+        self.windows
+            .iter()
+            .enumerate()
+            .map(|(index, window)| {
+                let suffix = if single {
+                    String::new()
+                } else {
+                    format!("-{index}")
+                };
+                window.save_screenshot(&suffix)
+            })
+            .collect()
+    }
 }
 
 /// Event Loop.
 impl Chip8App {
     pub fn run(&mut self, event_loop: &mut EventLoop) -> Result<AppControl, AppError> {
-        let main_window_id = self.window_ctx.window.id();
         let mut app_control = AppControl::Exit;
 
         event_loop.run_return(|event, _, control_flow| {
-            control_flow.set_poll();
+            // Sleep until the next emulation frame, unless events arrive first.
+            match self.clock.next_frame() {
+                Some(instant) => control_flow.set_wait_until(instant),
+                None => control_flow.set_poll(),
+            }
 
             match event {
                 EV::NewEvents(_) => {
@@ -95,94 +292,176 @@ impl Chip8App {
                 EV::MainEventsCleared => {
                     // Frame Update
 
-                    if let Some(input) = self.input_map.action_state(DEV_CONSOLE) {
-                        log::info!("Developer Console: {}", input.key_state);
+                    // Frames due are spent even when emulation doesn't run, so
+                    // pausing or rewinding isn't followed by a burst of catching up.
+                    let step = self.clock.update();
+
+                    if self.input_map.is_action_released(DEV_CONSOLE) {
+                        if let Some(window) = self.focused.and_then(|id| self.window_mut(id)) {
+                            window.toggle_panels();
+                        }
+                    }
+
+                    if self.input_map.is_action_released(KEYPAD) {
+                        self.windows.iter_mut().for_each(VmWindow::toggle_keypad);
+                    }
+
+                    if self.input_map.is_action_released(CYCLE_FILTER) {
+                        self.windows.iter_mut().for_each(VmWindow::cycle_filter);
+                    }
+
+                    if self.input_map.is_action_released(CYCLE_BACKEND) {
+                        self.windows.iter_mut().for_each(VmWindow::cycle_backend);
                     }
 
                     if self.input_map.is_action_released(EXIT) {
-                        log::info!("exit pressed");
+                        tracing::info!("exit pressed");
                         control_flow.set_exit();
                     } else if self.input_map.is_action_released(RESET) {
-                        log::info!("reset pressed");
+                        tracing::info!("reset pressed");
                         app_control = AppControl::Reset;
                         control_flow.set_exit();
                     }
 
-                    // Merge input stream into VM
-                    self.input_map.write_keys(&mut self.vm);
-
-                    // Inner VM loop.
-                    //
-                    // The outer event loop, and inner VM loop, have to yield control
-                    // between each other cooperatively.
-                    //
-                    // 1. Process as many bytecode instructions as we can.
-                    // 2. Jumps can stall the VM in infinite or long running loops,
-                    //    blocking the event loop.
-                    // 3. V-sync blocks the main thread and can slow down the interpreter.
-                    'vm: loop {
-                        match self.vm.tick() {
-                            Ok(flow) => {
-                                match flow {
-                                    // Queue a RedrawRequested event.
-                                    //
-                                    // We only need to call this if we've determined that we need to redraw.
-                                    Flow::Draw => {
-                                        self.window_ctx.request_redraw();
-                                        break 'vm;
-                                    }
-                                    // Yield control back to outer loop.
-                                    Flow::Jump | Flow::KeyWait | Flow::Interrupt => {
-                                        break 'vm;
-                                    }
-                                    _ => {}
+                    if self.input_map.is_action_released(OPEN_ROM) {
+                        self.open_rom_dialog();
+                    }
+
+                    if self.input_map.is_action_released(SCREENSHOT) {
+                        match self.save_screenshots() {
+                            Ok(filepaths) => {
+                                for filepath in filepaths {
+                                    tracing::info!("saved screenshot: {}", filepath.display());
                                 }
                             }
-                            Err(err) => {
-                                eprintln!("VM error: {err}")
-                                // TODO: graceful error reporting to user
-                            }
+                            Err(err) => tracing::error!("failed to save screenshot: {err}"),
                         }
                     }
-                }
-                EV::RedrawRequested(_) => {
-                    // Redraw the application.
-                    if self.window_ctx.make_context_current().is_ok() {
-                        self.render
-                            .clear_window(29.0 / 255.0, 33.0 / 255.0, 40.0 / 255.0, 0.9);
-
-                        self.render.draw_chip8_display(self.vm.display_buffer());
-                        // self.render.draw_demo_pattern();
 
-                        self.window_ctx.swap_buffers().unwrap();
+                    if self.input_map.is_action_released(SAVE_STATE) {
+                        tracing::info!("save state");
+                        self.windows.iter_mut().for_each(VmWindow::save_state);
+                    } else if self.input_map.is_action_released(LOAD_STATE) {
+                        self.windows.iter_mut().for_each(VmWindow::load_state);
                     }
-                }
-                EV::WindowEvent { window_id, event } if window_id == main_window_id => {
-                    match event {
-                        WE::Resized(size) => {
-                            // Some platforms like EGL require resizing GL surface to update the size.
-                            // Notable platforms here are Wayland and macOS, others don't require it
-                            // and the function is no-op, but it's wise to resize it for portability
-                            // reasons.
-                            // Zero sized surface is invalid.
-                            self.window_ctx.resize_surface(size);
-                        }
-                        WE::KeyboardInput { input, .. } => {
-                            if let Some(virtual_keycode) = input.virtual_keycode {
-                                self.input_map.emit_key(virtual_keycode, input.state);
+
+                    // Hot-reload the input map when the file changes on disk.
+                    let input_changed = if self.input_checked.elapsed() >= INPUT_WATCH_INTERVAL {
+                        self.input_checked = Instant::now();
+                        self.input_map.is_source_modified()
+                    } else {
+                        false
+                    };
+                    if input_changed || self.input_map.is_action_released(RELOAD_INPUT) {
+                        match self.input_map.reload() {
+                            Ok(_) => {
+                                tracing::info!("input map reloaded");
+                                // Held keys were forgotten by the input map.
+                                for window in &mut self.windows {
+                                    window.vm_mut().clear_keys();
+                                }
                             }
+                            Err(err) => tracing::error!("failed to reload input map: {err}"),
                         }
-                        WE::CloseRequested => {
-                            control_flow.set_exit();
-                            app_control = AppControl::Exit;
+                    }
+
+                    // Merge input stream into VMs
+                    for window in &mut self.windows {
+                        self.input_map.write_keys(window.vm_mut());
+                        window.update_keypad(&self.input_map);
+                    }
+
+                    // Rewinding replaces execution while the action is held down.
+                    if self.input_map.is_action_pressed(REWIND) {
+                        for _ in 0..step.frames {
+                            self.windows.iter_mut().for_each(VmWindow::rewind);
                         }
-                        _ => { /* blank */ }
+                        return;
+                    }
+
+                    if self.input_map.is_action_released(PAUSE) {
+                        self.set_paused(!self.is_paused());
+                    }
+                    if self.step_paused() {
+                        return;
+                    }
+
+                    let timestep = self.clock.timestep();
+                    for window in &mut self.windows {
+                        window.update(step, timestep);
+                    }
+                }
+                EV::RedrawRequested(window_id) => {
+                    // Redraw the application.
+                    if let Some(window) = self.window_mut(window_id) {
+                        window.redraw();
                     }
                 }
+                EV::WindowEvent { window_id, event } => {
+                    // The debug panels get the first look at input.
+                    let consumed = self
+                        .window_mut(window_id)
+                        .is_some_and(|window| window.on_window_event(&event));
+                    if consumed {
+                        return;
+                    }
+                    self.handle_window_event(window_id, event, control_flow, &mut app_control);
+                }
                 _ => { /* blank */ }
             }
         });
 
         Ok(app_control)
     }
+
+    fn handle_window_event(
+        &mut self,
+        window_id: WindowId,
+        event: WE,
+        control_flow: &mut ControlFlow,
+        app_control: &mut AppControl,
+    ) {
+        match event {
+            WE::Resized(size) => {
+                if let Some(window) = self.window_mut(window_id) {
+                    window.resize(size);
+                }
+            }
+            WE::Focused(true) => self.focused = Some(window_id),
+            WE::CursorMoved { position, .. } => {
+                if let Some(window) = self.window_mut(window_id) {
+                    window.mouse_moved(Some(position));
+                }
+            }
+            WE::CursorLeft { .. } => {
+                if let Some(window) = self.window_mut(window_id) {
+                    window.mouse_moved(None);
+                }
+            }
+            WE::MouseInput { state, button, .. } => {
+                if let Some(window) = self.window_mut(window_id) {
+                    window.mouse_input(button, state);
+                }
+            }
+            WE::KeyboardInput { input, .. } => {
+                if let Some(virtual_keycode) = input.virtual_keycode {
+                    self.input_map.emit_key(virtual_keycode, input.state);
+                }
+            }
+            WE::DroppedFile(path) => {
+                if let Some(window) = self.window_mut(window_id) {
+                    window.load_rom_logged(&path);
+                }
+            }
+            WE::CloseRequested => {
+                self.windows
+                    .retain(|window| window.window_id() != window_id);
+                if self.windows.is_empty() {
+                    control_flow.set_exit();
+                    *app_control = AppControl::Exit;
+                }
+            }
+            _ => { /* blank */ }
+        }
+    }
 }
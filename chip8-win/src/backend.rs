@@ -0,0 +1,151 @@
+//! Execution backends, switchable while a program runs.
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+/// Time between updates of the measured speed.
+const MEASURE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How the VM of a window executes instructions.
+///
+/// Every backend works on the same machine state, so switching
+/// carries over the registers, memory, timers and display.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// Instructions are decoded and dispatched every time they're executed.
+    #[default]
+    Interpreter,
+    /// The interpreter, keeping the decoded handlers of executed instructions.
+    DecodeCache,
+    /// Runs of register instructions are compiled into native code,
+    /// and the rest are interpreted.
+    Jit,
+}
+
+impl Backend {
+    pub const ALL: &'static [Self] = &[Self::Interpreter, Self::DecodeCache, Self::Jit];
+
+    pub const NAMES: &'static [&'static str] = &["interpreter", "decode-cache", "jit"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Interpreter => "interpreter",
+            Self::DecodeCache => "decode-cache",
+            Self::Jit => "jit",
+        }
+    }
+
+    /// The backend after this one, wrapping around to the first.
+    pub fn next(self) -> Self {
+        let index = Self::ALL
+            .iter()
+            .position(|backend| *backend == self)
+            .unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "interpreter" => Ok(Self::Interpreter),
+            "decode-cache" => Ok(Self::DecodeCache),
+            "jit" => Ok(Self::Jit),
+            _ => Err(format!(
+                "unknown backend '{s}', expected one of: {}",
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Speed of the backend in millions of instructions per second,
+/// over the time spent executing rather than the time between frames,
+/// so backends are comparable even while emulation is paced.
+#[derive(Debug)]
+pub(crate) struct MipsMeter {
+    steps: u64,
+    busy: Duration,
+    started: Instant,
+    mips: Option<f64>,
+}
+
+impl Default for MipsMeter {
+    fn default() -> Self {
+        Self {
+            steps: 0,
+            busy: Duration::ZERO,
+            started: Instant::now(),
+            mips: None,
+        }
+    }
+}
+
+impl MipsMeter {
+    /// Record instructions executed in the given time.
+    ///
+    /// Returns `true` when the measured speed was updated.
+    pub fn record(&mut self, steps: u32, busy: Duration) -> bool {
+        self.steps += steps as u64;
+        self.busy += busy;
+        if self.started.elapsed() < MEASURE_INTERVAL {
+            return false;
+        }
+
+        self.mips = mips(self.steps, self.busy);
+        self.steps = 0;
+        self.busy = Duration::ZERO;
+        self.started = Instant::now();
+        true
+    }
+
+    /// Measured speed, once enough time has passed.
+    pub fn mips(&self) -> Option<f64> {
+        self.mips
+    }
+
+    /// Start measuring again, after switching backends.
+    pub fn restart(&mut self) {
+        *self = Self::default();
+    }
+}
+
+fn mips(steps: u64, busy: Duration) -> Option<f64> {
+    let secs = busy.as_secs_f64();
+    (secs > 0.0).then(|| steps as f64 / secs / 1_000_000.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_backend_names() {
+        for (backend, name) in Backend::ALL.iter().zip(Backend::NAMES) {
+            assert_eq!(backend.name(), *name);
+            assert_eq!(name.parse::<Backend>(), Ok(*backend));
+        }
+        assert!("tree".parse::<Backend>().is_err());
+        assert_eq!(Backend::Jit.next(), Backend::Interpreter);
+    }
+
+    #[test]
+    fn test_mips() {
+        assert_eq!(mips(3_000_000, Duration::from_millis(500)), Some(6.0));
+        assert_eq!(mips(0, Duration::ZERO), None);
+
+        let mut meter = MipsMeter::default();
+        assert!(!meter.record(1000, Duration::from_millis(1)));
+        assert_eq!(meter.mips(), None);
+    }
+}
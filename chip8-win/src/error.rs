@@ -11,6 +11,7 @@ impl std::error::Error for AppError {}
 #[derive(Debug)]
 pub enum ErrorKind {
     Chip8(chip8::Chip8Error),
+    Jit(chip8_jit::JitError),
     Io(std::io::Error),
     Window(winit::error::OsError),
 }
@@ -25,6 +26,7 @@ impl fmt::Display for ErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Chip8(err) => write!(f, "{err}"),
+            Self::Jit(err) => write!(f, "{err}"),
             Self::Io(err) => write!(f, "{err}"),
             Self::Window(err) => write!(f, "{err}"),
         }
@@ -39,6 +41,14 @@ impl From<chip8::Chip8Error> for AppError {
     }
 }
 
+impl From<chip8_jit::JitError> for AppError {
+    fn from(err: chip8_jit::JitError) -> Self {
+        Self {
+            kind: ErrorKind::Jit(err),
+        }
+    }
+}
+
 impl From<std::io::Error> for AppError {
     fn from(err: std::io::Error) -> Self {
         Self {
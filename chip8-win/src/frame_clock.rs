@@ -0,0 +1,182 @@
+//! Fixed rate emulation frames, independent of the host frame rate.
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use chip8::constants::CLOCK_CYCLE_TIME;
+
+/// Time between emulation frames, at 60Hz.
+const FRAME_TIME: Duration = Duration::from_nanos(CLOCK_CYCLE_TIME);
+
+/// Most frames run to catch up in one update.
+///
+/// When the host can't keep up, or the event loop was blocked by a
+/// dialog, the backlog is dropped instead of running at full speed.
+const MAX_CATCH_UP_FRAMES: u32 = 4;
+
+/// How the windows advance emulation on every update.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Timestep {
+    /// Whole 60Hz frames, each executing the instructions of one display
+    /// tick and counting down the timers once.
+    ///
+    /// Emulation is deterministic, and in step with the display.
+    #[default]
+    Fixed,
+    /// Instructions in proportion to the time elapsed since the last update,
+    /// with the timers counted down in real time.
+    ///
+    /// Input is picked up sooner, at the cost of waking up more often.
+    Adaptive,
+}
+
+impl Timestep {
+    pub const NAMES: &'static [&'static str] = &["fixed", "adaptive"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Fixed => "fixed",
+            Self::Adaptive => "adaptive",
+        }
+    }
+}
+
+impl FromStr for Timestep {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fixed" => Ok(Self::Fixed),
+            "adaptive" => Ok(Self::Adaptive),
+            _ => Err(format!(
+                "unknown timestep '{s}', expected one of: {}",
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Timestep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Emulation due in an update of the [`FrameClock`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Step {
+    /// Whole 60Hz frames due.
+    pub frames: u32,
+    /// Time since the last update, limited like the frames.
+    pub elapsed: Duration,
+}
+
+/// Accumulator of elapsed time, paid out in whole 60Hz frames.
+///
+/// Every emulation frame executes the instructions of one display tick, and
+/// counts down the timers once, so the VM runs at the same speed however
+/// often the event loop wakes up.
+#[derive(Debug)]
+pub struct FrameClock {
+    last: Instant,
+    /// Elapsed time not yet paid out as frames.
+    accumulator: Duration,
+    timestep: Timestep,
+}
+
+impl Default for FrameClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameClock {
+    pub fn new() -> Self {
+        Self {
+            last: Instant::now(),
+            accumulator: Duration::ZERO,
+            timestep: Timestep::default(),
+        }
+    }
+
+    pub fn timestep(&self) -> Timestep {
+        self.timestep
+    }
+
+    pub fn set_timestep(&mut self, timestep: Timestep) {
+        self.timestep = timestep;
+    }
+
+    /// Frames and time due since the last update.
+    pub fn update(&mut self) -> Step {
+        let now = Instant::now();
+        let elapsed = (now - self.last).min(FRAME_TIME * MAX_CATCH_UP_FRAMES);
+        self.last = now;
+        Step {
+            frames: self.advance(elapsed),
+            elapsed,
+        }
+    }
+
+    /// Add the elapsed time, and take the whole frames out of it.
+    fn advance(&mut self, elapsed: Duration) -> u32 {
+        self.accumulator = (self.accumulator + elapsed).min(FRAME_TIME * MAX_CATCH_UP_FRAMES);
+
+        let mut frames = 0;
+        while self.accumulator >= FRAME_TIME {
+            self.accumulator -= FRAME_TIME;
+            frames += 1;
+        }
+        frames
+    }
+
+    /// Time when the next frame is due, for the event loop to wait until.
+    ///
+    /// The adaptive timestep doesn't wait, and updates as often as the host allows.
+    pub fn next_frame(&self) -> Option<Instant> {
+        match self.timestep {
+            Timestep::Fixed => Some(self.last + (FRAME_TIME - self.accumulator)),
+            Timestep::Adaptive => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_frame_clock() {
+        let mut clock = FrameClock::new();
+        assert_eq!(clock.advance(Duration::ZERO), 0);
+
+        // Partial frames carry over into the next update.
+        assert_eq!(clock.advance(FRAME_TIME / 2), 0);
+        assert_eq!(clock.advance(FRAME_TIME), 1);
+        assert_eq!(clock.advance(FRAME_TIME / 2), 1);
+        assert_eq!(clock.accumulator, Duration::ZERO);
+
+        // Fast and slow hosts run the same frames over time.
+        let fast: u32 = (0..120).map(|_| clock.advance(FRAME_TIME / 2)).sum();
+        let slow: u32 = (0..30).map(|_| clock.advance(FRAME_TIME * 2)).sum();
+        assert_eq!(fast, 60);
+        assert_eq!(slow, 60);
+
+        // Long stalls are dropped instead of caught up.
+        assert_eq!(clock.advance(Duration::from_secs(5)), MAX_CATCH_UP_FRAMES);
+
+        assert!(clock.next_frame().is_some());
+        clock.set_timestep(Timestep::Adaptive);
+        assert_eq!(clock.next_frame(), None);
+    }
+
+    #[test]
+    fn test_timestep_names() {
+        for name in Timestep::NAMES {
+            assert_eq!(name.parse::<Timestep>().unwrap().name(), *name);
+        }
+        assert!("variable".parse::<Timestep>().is_err());
+    }
+}
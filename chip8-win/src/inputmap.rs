@@ -1,9 +1,11 @@
 use std::collections::VecDeque;
 use std::fmt;
 use std::iter::Iterator;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 use chip8::{Chip8Vm, KeyCode};
-use serde::Deserialize;
+use serde::{ser::SerializeSeq, Deserialize, Deserializer, Serialize, Serializer};
 use smol_str::SmolStr;
 use winit::event::{ElementState, VirtualKeyCode};
 
@@ -16,6 +18,9 @@ use winit::event::{ElementState, VirtualKeyCode};
 ///   Stored in 8-bit integers and suitable to be passed to the virtual machine.
 /// - *Named Action*: These are application specific input events that are
 ///   identified by a readable string.
+///
+/// Serialized as the list of definitions of the YAML file, so it can
+/// be embedded in other configuration files.
 #[derive(Debug)]
 pub struct InputMap {
     actions: Box<[ActionInfo]>,
@@ -27,13 +32,16 @@ pub struct InputMap {
     events: VecDeque<InputKind>,
     /// Current state of the key. Whether it is pressed down.
     state: Vec<InputState>,
+    /// File the input map was loaded from, for reloading.
+    source: Option<PathBuf>,
+    /// Modification time of the source file when it was last loaded.
+    modified: Option<SystemTime>,
 }
 
 #[derive(Debug)]
 struct ActionInfo {
     chip8: Option<KeyCode>,
     action: Option<SmolStr>,
-    #[allow(dead_code)]
     keyboard_keys: Vec<VirtualKeyCode>,
 }
 
@@ -48,17 +56,52 @@ impl From<InputDef> for ActionInfo {
     }
 }
 
-#[derive(Debug, Deserialize)]
+impl From<&ActionInfo> for InputDef {
+    fn from(info: &ActionInfo) -> Self {
+        Self {
+            chip8: info.chip8,
+            action: info.action.clone(),
+            keyboard_keys: Some(info.keyboard_keys.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 struct InputDef {
+    #[serde(skip_serializing_if = "Option::is_none")]
     chip8: Option<KeyCode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     action: Option<SmolStr>,
     keyboard_keys: Option<Vec<VirtualKeyCode>>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct InputEvent {
-    pub kind: InputKind,
-    pub state: ElementState,
+/// Check that every definition maps to one thing,
+/// and that keyboard keys aren't mapped twice.
+fn validate_defs(defs: &[InputDef]) -> Result<(), String> {
+    let mut keys = Vec::new();
+    for (index, def) in defs.iter().enumerate() {
+        match (def.chip8, &def.action) {
+            (Some(_), Some(_)) => {
+                return Err(format!(
+                    "input definition {index} maps to both a chip8 key and an action"
+                ))
+            }
+            (None, None) => {
+                return Err(format!(
+                    "input definition {index} maps to neither a chip8 key nor an action"
+                ))
+            }
+            _ => {}
+        }
+        for key in def.keyboard_keys.iter().flatten() {
+            if keys.contains(key) {
+                return Err(format!("keyboard key {key:?} is mapped twice"));
+            }
+            keys.push(*key);
+        }
+    }
+    Ok(())
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -70,10 +113,7 @@ pub enum InputKind {
 impl InputKind {
     pub fn as_chip8(&self) -> Option<KeyCode> {
         match self {
-            Self::Chip8(key_id) => match KeyCode::try_from(*key_id) {
-                Ok(keycode) => Some(keycode),
-                Err(_) => None,
-            },
+            Self::Chip8(key_id) => KeyCode::try_from(*key_id).ok(),
             _ => None,
         }
     }
@@ -127,26 +167,67 @@ impl fmt::Display for KeyState {
 }
 
 impl InputMap {
-    /// Load an input map from a YAML file.
-    pub fn from_file(filepath: &str) -> std::io::Result<Self> {
-        let mut file = std::fs::File::open(filepath)?;
-
-        let defs: Vec<InputDef> = serde_yaml::from_reader(&mut file).unwrap();
-        log::debug!("loaded input definitions: {:#?}", defs);
-
-        let mut inputmap = InputMap {
-            actions: defs.into_iter().map(ActionInfo::from).collect(),
+    fn new(source: Option<PathBuf>) -> Self {
+        InputMap {
+            actions: Box::new([]),
             keymap: Box::new([]),
             namemap: Box::new([]),
             events: VecDeque::new(),
             state: Vec::new(),
-        };
+            source,
+            modified: None,
+        }
+    }
+
+    /// Load an input map from a YAML file.
+    pub fn from_file(filepath: &str) -> std::io::Result<Self> {
+        let mut inputmap = InputMap::new(Some(PathBuf::from(filepath)));
 
-        inputmap.rebuild_mappings();
+        inputmap.reload()?;
 
         Ok(inputmap)
     }
 
+    /// Replace the input definitions, after checking them.
+    fn set_defs(&mut self, defs: Vec<InputDef>) -> Result<(), String> {
+        validate_defs(&defs)?;
+        tracing::debug!("loaded input definitions: {:#?}", defs);
+
+        self.actions = defs.into_iter().map(ActionInfo::from).collect();
+        self.events.clear();
+        self.state.clear();
+        self.rebuild_mappings();
+        Ok(())
+    }
+
+    /// Load the input definitions again from the source file.
+    ///
+    /// The input state is cleared, because held keys may no longer be mapped.
+    /// On error the current mappings are kept.
+    pub fn reload(&mut self) -> std::io::Result<()> {
+        let filepath = match self.source {
+            Some(ref filepath) => filepath,
+            None => return Ok(()),
+        };
+
+        // A broken file is only reported once, until it changes again.
+        self.modified = modified_time(filepath);
+        let mut file = std::fs::File::open(filepath)?;
+
+        let defs: Vec<InputDef> = serde_yaml::from_reader(&mut file)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        self.set_defs(defs)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+    }
+
+    /// Check whether the source file was changed since it was last loaded.
+    pub fn is_source_modified(&self) -> bool {
+        match self.source {
+            Some(ref filepath) => modified_time(filepath) != self.modified,
+            None => false,
+        }
+    }
+
     /// Rebuild the input mappings to actions,
     /// for when the actions have been changed.
     fn rebuild_mappings(&mut self) {
@@ -225,7 +306,7 @@ impl InputMap {
                 self.set_state(kind, KeyState::from(element_state));
             }
             None => {
-                log::trace!("no input mapping for {keycode:?}");
+                tracing::trace!("no input mapping for {keycode:?}");
             }
         }
     }
@@ -279,17 +360,146 @@ impl InputMap {
             .filter_map(|ev| ev.kind.as_chip8())
     }
 
-    // Write keyboard input into Chip8 VM.
+    /// Host keyboard keys mapped to the Chip8 key.
+    pub fn chip8_bindings(&self, key: KeyCode) -> impl Iterator<Item = VirtualKeyCode> + '_ {
+        self.actions
+            .iter()
+            .filter(move |action| action.chip8 == Some(key))
+            .flat_map(|action| action.keyboard_keys.iter().copied())
+    }
+
+    /// Chip8 keys pressed or released this frame.
+    pub fn key_transitions(&self) -> impl Iterator<Item = (KeyCode, chip8::KeyState)> + '_ {
+        self.state.iter().filter_map(|state| {
+            let keycode = state.kind.as_chip8()?;
+            match state.key_state {
+                KeyState::Pressed => Some((keycode, chip8::KeyState::Pressed)),
+                KeyState::Released => Some((keycode, chip8::KeyState::Released)),
+                KeyState::Down => None, // no transition
+            }
+        })
+    }
+
+    // Write keyboard transitions into Chip8 VM.
     pub fn write_keys(&mut self, vm: &mut Chip8Vm) {
-        vm.clear_keys();
-        for keycode in self.iter_chip8() {
-            vm.set_key(keycode, true);
+        for (keycode, state) in self.key_transitions() {
+            vm.push_key_event(keycode, state);
+        }
+    }
+}
+
+impl Serialize for InputMap {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.actions.len()))?;
+        for action in self.actions.iter() {
+            seq.serialize_element(&InputDef::from(action))?;
         }
+        seq.end()
     }
 }
 
+/// Input maps deserialized from other configuration files can't be reloaded.
+impl<'de> Deserialize<'de> for InputMap {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let defs = Vec::<InputDef>::deserialize(deserializer)?;
+        let mut inputmap = InputMap::new(None);
+        inputmap.set_defs(defs).map_err(serde::de::Error::custom)?;
+        Ok(inputmap)
+    }
+}
+
+fn modified_time(filepath: &Path) -> Option<SystemTime> {
+    std::fs::metadata(filepath)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
 #[cfg(test)]
 mod test {
+    use super::*;
+
     #[test]
     fn test_actions() {}
+
+    #[test]
+    fn test_reload() {
+        let filepath = std::env::temp_dir().join("chip8_test_reload_input.yaml");
+        std::fs::write(&filepath, "- chip8: 0x1\n  keyboard_keys:\n  - Key1\n").unwrap();
+
+        let mut input_map = InputMap::from_file(filepath.to_str().unwrap()).unwrap();
+        assert_eq!(
+            input_map.map_key(VirtualKeyCode::Key1),
+            Some(InputKind::Chip8(1))
+        );
+        assert!(!input_map.is_source_modified());
+
+        std::fs::write(&filepath, "- action: exit\n  keyboard_keys:\n  - Key1\n").unwrap();
+        input_map.reload().unwrap();
+        assert_eq!(
+            input_map.map_key(VirtualKeyCode::Key1),
+            Some(InputKind::Action("exit".into()))
+        );
+
+        // Invalid files keep the current mappings.
+        std::fs::write(&filepath, "- chip8: [").unwrap();
+        assert!(input_map.reload().is_err());
+        assert!(input_map.map_key(VirtualKeyCode::Key1).is_some());
+
+        std::fs::remove_file(&filepath).unwrap();
+    }
+
+    #[test]
+    fn test_serde() {
+        let yaml =
+            "- chip8: 0x5\n  keyboard_keys:\n  - W\n- action: exit\n  keyboard_keys:\n  - Escape\n";
+        let input_map: InputMap = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            input_map.map_key(VirtualKeyCode::Escape),
+            Some(InputKind::Action("exit".into()))
+        );
+        let yaml = serde_yaml::to_string(&input_map).unwrap();
+        let input_map: InputMap = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(
+            input_map.map_key(VirtualKeyCode::W),
+            Some(InputKind::Chip8(5))
+        );
+
+        for (yaml, message) in [
+            ("- chip8: 0x5\n  action: exit\n", "both"),
+            ("- keyboard_keys:\n  - W\n", "neither"),
+            (
+                "- chip8: 0x5\n  keyboard_keys: [W]\n- chip8: 0x6\n  keyboard_keys: [W]\n",
+                "twice",
+            ),
+            ("- chip8: 0x5\n  keys: [W]\n", "unknown field"),
+        ] {
+            let err = serde_yaml::from_str::<InputMap>(yaml).unwrap_err();
+            assert!(err.to_string().contains(message), "{err}");
+        }
+
+        // The shipped input map is valid.
+        InputMap::from_file(concat!(env!("CARGO_MANIFEST_DIR"), "/input.yaml")).unwrap();
+    }
+
+    #[test]
+    fn test_chip8_bindings() {
+        let filepath = std::env::temp_dir().join("chip8_test_chip8_bindings.yaml");
+        std::fs::write(
+            &filepath,
+            "- chip8: 0x5\n  keyboard_keys:\n  - W\n  - Numpad5\n- action: exit\n  keyboard_keys:\n  - Escape\n",
+        )
+        .unwrap();
+
+        let mut input_map = InputMap::from_file(filepath.to_str().unwrap()).unwrap();
+        assert_eq!(
+            input_map.chip8_bindings(KeyCode::Key5).collect::<Vec<_>>(),
+            [VirtualKeyCode::W, VirtualKeyCode::Numpad5]
+        );
+        assert_eq!(input_map.chip8_bindings(KeyCode::Key0).count(), 0);
+
+        input_map.emit_key(VirtualKeyCode::Numpad5, ElementState::Pressed);
+        assert_eq!(input_map.iter_chip8().collect::<Vec<_>>(), [KeyCode::Key5]);
+
+        std::fs::remove_file(&filepath).unwrap();
+    }
 }
@@ -0,0 +1,97 @@
+//! Overlay of the hex keypad, drawn with egui over the display.
+//!
+//! Shows which Chip-8 keys are down, and the host keys mapped to them,
+//! so the layout of the COSMAC VIP keypad can be discovered while playing.
+use chip8::{KeyCode, RomInfo};
+
+use crate::inputmap::InputMap;
+
+/// Keys in the layout of the COSMAC VIP keypad.
+const KEYPAD_LAYOUT: [[KeyCode; 4]; 4] = [
+    [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::KeyC],
+    [KeyCode::Key4, KeyCode::Key5, KeyCode::Key6, KeyCode::KeyD],
+    [KeyCode::Key7, KeyCode::Key8, KeyCode::Key9, KeyCode::KeyE],
+    [KeyCode::KeyA, KeyCode::Key0, KeyCode::KeyB, KeyCode::KeyF],
+];
+
+/// Size of a key button, in points.
+const KEY_SIZE: egui::Vec2 = egui::vec2(56.0, 40.0);
+
+/// Colour of the keys that are down.
+const DOWN_COLOR: egui::Color32 = egui::Color32::from_rgb(0x33, 0x99, 0x33);
+
+/// State of the keypad overlay.
+#[derive(Default)]
+pub struct KeypadOverlay {
+    /// The overlay is shown.
+    pub visible: bool,
+    /// Bit mask of the keys that are down, by key value.
+    down: u16,
+    /// Host keys mapped to each Chip-8 key, by key value.
+    bindings: [String; 16],
+}
+
+impl KeypadOverlay {
+    /// Copy the state of the keys and their bindings from the input map.
+    pub fn update(&mut self, input_map: &InputMap) {
+        self.down = input_map
+            .iter_chip8()
+            .fold(0, |mask, key| mask | 1 << key.as_u8());
+        for (index, binding) in self.bindings.iter_mut().enumerate() {
+            let key = KeyCode::try_from(index as u8).expect("keypad index");
+            *binding = input_map
+                .chip8_bindings(key)
+                .map(|keycode| key_label(&format!("{keycode:?}")))
+                .collect::<Vec<_>>()
+                .join(" ");
+        }
+    }
+
+    /// Draw the keypad in the bottom right corner.
+    ///
+    /// Hovering over a key shows what the program uses it for,
+    /// when the ROM descriptor says so.
+    pub fn ui(&self, ctx: &egui::Context, rom_info: Option<&RomInfo>) {
+        egui::Area::new("keypad")
+            .anchor(egui::Align2::RIGHT_BOTTOM, [-8.0, -8.0])
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    egui::Grid::new("keypad_grid")
+                        .spacing([4.0, 4.0])
+                        .show(ui, |ui| {
+                            for row in KEYPAD_LAYOUT {
+                                for key in row {
+                                    self.key_ui(ui, key, rom_info);
+                                }
+                                ui.end_row();
+                            }
+                        });
+                });
+            });
+    }
+
+    fn key_ui(&self, ui: &mut egui::Ui, key: KeyCode, rom_info: Option<&RomInfo>) {
+        let index = key.as_u8() as usize;
+        let text = egui::RichText::new(format!("{:X}\n{}", index, self.bindings[index]))
+            .monospace()
+            .small();
+
+        let mut button = egui::Button::new(text).min_size(KEY_SIZE);
+        if self.down & (1 << index) != 0 {
+            button = button.fill(DOWN_COLOR);
+        }
+
+        let response = ui.add(button);
+        if let Some(hint) = rom_info.and_then(|info| info.key_hint(key)) {
+            response.on_hover_text(hint);
+        }
+    }
+}
+
+/// Short name of a host key, to fit on a keypad button.
+fn key_label(name: &str) -> String {
+    match name.strip_prefix("Numpad") {
+        Some(rest) => format!("Num{rest}"),
+        None => name.strip_prefix("Key").unwrap_or(name).to_string(),
+    }
+}
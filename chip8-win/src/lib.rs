@@ -1,44 +1,146 @@
 mod app;
+mod backend;
 mod error;
+mod frame_clock;
 mod inputmap;
+mod keypad;
+mod pacing;
+mod panels;
+mod remote;
 mod render;
+mod theme;
+mod vm_window;
 mod window;
 
 /// Hardcoded input action names.
 pub(crate) mod actions {
-    /// Open or close the dev console
+    /// Show or hide the debug panels
     pub const DEV_CONSOLE: &str = "devconsole";
     /// Exit the application
     pub const EXIT: &str = "exit";
     /// Reset the VM and reload the ROM
     pub const RESET: &str = "reset";
+    /// Save the VM state into the in-memory slot
+    pub const SAVE_STATE: &str = "savestate";
+    /// Restore the VM state from the in-memory slot
+    pub const LOAD_STATE: &str = "loadstate";
+    /// Go back in time while held down
+    pub const REWIND: &str = "rewind";
+    /// Reload the input map from disk
+    pub const RELOAD_INPUT: &str = "reloadinput";
+    /// Pick a ROM file to load
+    pub const OPEN_ROM: &str = "openrom";
+    /// Freeze or resume emulation
+    pub const PAUSE: &str = "pause";
+    /// Execute one frame while paused
+    pub const STEP_FRAME: &str = "step_frame";
+    /// Execute one instruction while paused
+    pub const STEP_INSTR: &str = "step_instr";
+    /// Save the display as a PNG image
+    pub const SCREENSHOT: &str = "screenshot";
+    /// Show or hide the keypad overlay
+    pub const KEYPAD: &str = "keypad";
+    /// Switch to the next display filter
+    pub const CYCLE_FILTER: &str = "cycle_filter";
+    /// Switch to the next execution backend
+    pub const CYCLE_BACKEND: &str = "cycle_backend";
 }
 
 pub type EventLoop = winit::event_loop::EventLoop<()>;
 
 pub use self::{
-    app::{AppControl, Chip8App},
+    app::{AppControl, AppOptions, Chip8App},
+    backend::Backend,
     error::{AppError, ErrorKind},
+    frame_clock::Timestep,
     inputmap::{InputKind, InputMap},
+    remote::run_remote_window,
+    render::DisplayFilter,
+    theme::{Color, Theme},
+    vm_window::VmWindow,
     window::WindowContext,
 };
 
 pub fn run_chip8_window(rom: &[u8], input_map: InputMap) -> Result<(), AppError> {
-    log::info!("creating chip8 main window...");
+    run_chip8_window_with(
+        rom,
+        input_map,
+        chip8::Chip8Conf {
+            clock_frequency: None,
+            ..Default::default()
+        },
+        Theme::default(),
+        None,
+        AppOptions::default(),
+    )
+}
+
+pub fn run_chip8_window_with(
+    rom: &[u8],
+    input_map: InputMap,
+    conf: chip8::Chip8Conf,
+    theme: Theme,
+    rom_info: Option<chip8::RomInfo>,
+    options: AppOptions,
+) -> Result<(), AppError> {
+    run_chip8_windows(rom, input_map, vec![(None, conf)], theme, rom_info, options)
+}
+
+/// Run the program side by side in a window for each configuration,
+/// to compare their behaviour, like two quirk profiles.
+///
+/// Every window is labelled in its title. The keypad and actions apply to all windows.
+pub fn run_chip8_comparison(
+    rom: &[u8],
+    input_map: InputMap,
+    confs: Vec<(String, chip8::Chip8Conf)>,
+    theme: Theme,
+    rom_info: Option<chip8::RomInfo>,
+    options: AppOptions,
+) -> Result<(), AppError> {
+    let windows = confs
+        .into_iter()
+        .map(|(label, conf)| (Some(label), conf))
+        .collect();
+    run_chip8_windows(rom, input_map, windows, theme, rom_info, options)
+}
+
+fn run_chip8_windows(
+    rom: &[u8],
+    input_map: InputMap,
+    windows: Vec<(Option<String>, chip8::Chip8Conf)>,
+    theme: Theme,
+    rom_info: Option<chip8::RomInfo>,
+    options: AppOptions,
+) -> Result<(), AppError> {
+    tracing::info!("creating chip8 windows...");
 
     // Event loop can only be created once per process.
     let mut event_loop = Chip8App::create_event_loop();
-    let window_ctx = WindowContext::new(&event_loop);
-    let mut app = Chip8App::from_window(window_ctx, input_map);
+    let mut windows = windows.into_iter();
+    let Some((label, conf)) = windows.next() else {
+        return Ok(());
+    };
+    let mut app = Chip8App::with_conf(WindowContext::new(&event_loop), input_map, conf);
+    let mut labels = vec![label];
+    for (label, conf) in windows {
+        app.add_window(WindowContext::new(&event_loop), conf);
+        labels.push(label);
+    }
 
-    loop {
-        app.load_rom_bytecode(rom)?;
+    app.set_theme(theme);
+    app.set_options(options);
+    app.load_rom_bytecode(rom)?;
+    app.set_rom_info(rom_info);
+    for (window, label) in app.windows_mut().iter_mut().zip(labels) {
+        window.set_label(label);
+    }
 
-        if let AppControl::Exit = app.run(&mut event_loop)? {
-            break;
-        }
+    // The ROM may have been replaced while running, so resetting reloads the current one.
+    while let AppControl::Reset = app.run(&mut event_loop)? {
+        app.reload_rom()?;
     }
 
-    log::info!("closed chip8 main window");
+    tracing::info!("closed chip8 windows");
     Ok(())
 }
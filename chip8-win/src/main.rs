@@ -1,30 +1,59 @@
 use std::error::Error;
 
-#[macro_use]
-extern crate slog;
-use chip8_win::{Chip8App, InputMap, WindowContext};
-use log::{error, info};
-use slog::Drain;
+use chip8::{Chip8Conf, Variant};
+use chip8_win::{AppOptions, Chip8App, InputMap, WindowContext};
+use tracing::{error, info};
+use tracing_subscriber::EnvFilter;
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let decorator = slog_term::PlainDecorator::new(std::io::stdout());
-    let drain = slog_term::CompactFormat::new(decorator).build().fuse();
-    let drain = slog_async::Async::new(drain).build().fuse();
-    let logger = slog::Logger::root(drain, o!("version" => "0.5"));
-
-    let _scope_guard = slog_scope::set_global_logger(logger);
-    slog_stdlog::init_with_level(log::Level::Trace).unwrap();
+    // Filtered with `RUST_LOG`, for example `RUST_LOG=chip8::instruction=trace`.
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| "debug".into()))
+        .compact()
+        .init();
 
     info!("starting...");
 
+    // Configuration file, like `--config chip8.toml`, and the interpreter
+    // the program is written for, like `--variant schip`. Later options win.
+    let mut args = std::env::args().skip(1);
+    let mut conf = Chip8Conf::default();
+    let mut options = AppOptions::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--variant" => {
+                let name = args.next().unwrap_or_default();
+                let variant = Variant::from_name(&name).ok_or_else(|| {
+                    format!(
+                        "unknown variant '{name}', expected one of: {}",
+                        Variant::NAMES.join(", ")
+                    )
+                })?;
+                conf.quirks = variant.quirks();
+                conf.variant = Some(variant);
+            }
+            "--config" => {
+                let filepath = args.next().unwrap_or_default();
+                conf = Chip8Conf::from_file(&filepath)?;
+            }
+            "--no-vsync" => options.vsync = false,
+            "--timestep" => options.timestep = args.next().unwrap_or_default().parse()?,
+            "--pacing-stats" => options.pacing_stats = true,
+            "--filter" => options.filter = args.next().unwrap_or_default().parse()?,
+            "--backend" => options.backend = args.next().unwrap_or_default().parse()?,
+            _ => return Err(format!("unexpected argument '{arg}'").into()),
+        }
+    }
+
     // Load input configuration
     let input_map = InputMap::from_file("chip8-win/input.yaml")?;
-    log::debug!("loaded input map");
+    tracing::debug!("loaded input map");
 
     // Event loop can only be created once per process.
     let mut event_loop = Chip8App::create_event_loop();
     let window_ctx = WindowContext::new(&event_loop);
-    let mut app = Chip8App::from_window(window_ctx, input_map);
+    let mut app = Chip8App::with_conf(window_ctx, input_map, conf);
+    app.set_options(options);
 
     // app.load_rom_file("chip8/programs/maze")?;
     // app.load_rom_file("chip8/programs/BREAKOUT")?;
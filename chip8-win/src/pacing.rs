@@ -0,0 +1,160 @@
+//! Frame pacing statistics, for investigating the performance
+//! of the renderer and the interpreter.
+use std::{
+    collections::VecDeque,
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// Number of recent samples the statistics are taken over.
+const SAMPLE_COUNT: usize = 240;
+
+/// Time between pacing summaries in the log.
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Recent VM steps per update, and time between redraws.
+#[derive(Debug)]
+pub struct PacingStats {
+    /// Instructions executed by each update of the window.
+    steps: VecDeque<u32>,
+    /// Time between consecutive redraws.
+    frame_times: VecDeque<Duration>,
+    last_redraw: Option<Instant>,
+    last_log: Instant,
+}
+
+/// Summary of the recent samples.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PacingSummary {
+    pub steps_mean: u32,
+    pub steps_max: u32,
+    pub frame_p50: Duration,
+    pub frame_p95: Duration,
+    pub frame_p99: Duration,
+    pub frame_max: Duration,
+}
+
+impl Default for PacingStats {
+    fn default() -> Self {
+        Self {
+            steps: VecDeque::with_capacity(SAMPLE_COUNT),
+            frame_times: VecDeque::with_capacity(SAMPLE_COUNT),
+            last_redraw: None,
+            last_log: Instant::now(),
+        }
+    }
+}
+
+impl PacingStats {
+    /// Record the number of instructions executed by an update.
+    pub fn record_steps(&mut self, steps: u32) {
+        push_sample(&mut self.steps, steps);
+    }
+
+    /// Record a redraw, timing it against the previous one.
+    pub fn record_redraw(&mut self) {
+        let now = Instant::now();
+        if let Some(last) = self.last_redraw.replace(now) {
+            push_sample(&mut self.frame_times, now - last);
+        }
+    }
+
+    /// Forget the time of the last redraw, after a pause in drawing
+    /// that isn't the renderer's doing.
+    pub fn restart(&mut self) {
+        self.last_redraw = None;
+    }
+
+    pub fn summary(&self) -> PacingSummary {
+        let mut frame_times: Vec<_> = self.frame_times.iter().copied().collect();
+        frame_times.sort_unstable();
+        let steps_total: u64 = self.steps.iter().map(|steps| *steps as u64).sum();
+
+        PacingSummary {
+            steps_mean: steps_total
+                .checked_div(self.steps.len() as u64)
+                .unwrap_or(0) as u32,
+            steps_max: self.steps.iter().copied().max().unwrap_or(0),
+            frame_p50: percentile(&frame_times, 50),
+            frame_p95: percentile(&frame_times, 95),
+            frame_p99: percentile(&frame_times, 99),
+            frame_max: frame_times.last().copied().unwrap_or_default(),
+        }
+    }
+
+    /// Summary to log, when it's due.
+    pub fn poll_log(&mut self) -> Option<PacingSummary> {
+        if self.last_log.elapsed() < LOG_INTERVAL {
+            return None;
+        }
+        self.last_log = Instant::now();
+        Some(self.summary())
+    }
+}
+
+impl fmt::Display for PacingSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "steps/update mean {} max {}, frame time p50 {:.2}ms p95 {:.2}ms p99 {:.2}ms max {:.2}ms",
+            self.steps_mean,
+            self.steps_max,
+            millis(self.frame_p50),
+            millis(self.frame_p95),
+            millis(self.frame_p99),
+            millis(self.frame_max),
+        )
+    }
+}
+
+fn push_sample<T>(samples: &mut VecDeque<T>, sample: T) {
+    if samples.len() == SAMPLE_COUNT {
+        samples.pop_front();
+    }
+    samples.push_back(sample);
+}
+
+/// Nearest-rank percentile of sorted samples.
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+/// Duration in fractional milliseconds, for showing frame times.
+pub(crate) fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pacing_summary() {
+        let mut stats = PacingStats::default();
+        assert_eq!(stats.summary(), PacingSummary::default());
+
+        for steps in [10, 10, 10, 30] {
+            stats.record_steps(steps);
+        }
+        for millis in 1..=100 {
+            push_sample(&mut stats.frame_times, Duration::from_millis(millis));
+        }
+
+        let summary = stats.summary();
+        assert_eq!(summary.steps_mean, 15);
+        assert_eq!(summary.steps_max, 30);
+        assert_eq!(summary.frame_p50, Duration::from_millis(50));
+        assert_eq!(summary.frame_p95, Duration::from_millis(95));
+        assert_eq!(summary.frame_max, Duration::from_millis(100));
+
+        // Only the recent samples count.
+        for _ in 0..SAMPLE_COUNT {
+            stats.record_steps(1);
+        }
+        assert_eq!(stats.summary().steps_max, 1);
+    }
+}
@@ -0,0 +1,356 @@
+//! Debug panels, drawn with egui over the display.
+//!
+//! The GUI counterpart of the terminal debugger. Panels are floating
+//! windows that can be moved, collapsed, and closed from the menu bar.
+use std::{cell::RefCell, mem, rc::Rc};
+
+use chip8::{
+    constants::{MEM_SIZE, MEM_START, REGISTER_COUNT},
+    disasm::Disassembler,
+    Chip8Vm, MemoryChange,
+};
+
+use crate::pacing::{millis, PacingSummary};
+
+/// Number of instructions shown before the program counter.
+const DISASM_BEFORE: usize = 8 * 2;
+
+/// Number of instructions shown in the disassembly.
+const DISASM_LINES: usize = 24;
+
+/// Number of bytes on a row of the memory view.
+const MEMORY_COLUMNS: usize = 8;
+
+/// Number of rows in the memory view.
+const MEMORY_ROWS: usize = 16;
+
+/// Colour of bytes changed by the last write to memory.
+const CHANGED_COLOR: egui::Color32 = egui::Color32::YELLOW;
+
+/// Request from the panels, which the window applies after drawing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanelAction {
+    /// Freeze or resume emulation.
+    Pause(bool),
+    /// Execute one instruction.
+    StepInstr,
+    /// Execute one frame.
+    StepFrame,
+}
+
+/// State of the debug panels.
+pub struct DebugPanels {
+    /// The panels are shown.
+    pub visible: bool,
+    registers: bool,
+    stack: bool,
+    timers: bool,
+    memory: bool,
+    disassembly: bool,
+    breakpoints: bool,
+    pacing: bool,
+    /// First address of the memory view.
+    memory_address: usize,
+    /// The memory view follows the `I` register.
+    memory_follow_i: bool,
+    /// Address typed into the breakpoints panel, in hexadecimal.
+    breakpoint_input: String,
+    /// Writes to memory since the last frame, filled by the VM's listener.
+    changes: Rc<RefCell<Vec<MemoryChange>>>,
+    /// Writes highlighted in the memory view.
+    highlight: Vec<MemoryChange>,
+}
+
+impl Default for DebugPanels {
+    fn default() -> Self {
+        Self {
+            visible: false,
+            registers: true,
+            stack: true,
+            timers: true,
+            memory: true,
+            disassembly: true,
+            breakpoints: true,
+            pacing: true,
+            memory_address: MEM_START,
+            memory_follow_i: true,
+            breakpoint_input: String::new(),
+            changes: Rc::default(),
+            highlight: Vec::new(),
+        }
+    }
+}
+
+impl DebugPanels {
+    /// Listen for writes to the memory of the VM, to highlight them.
+    ///
+    /// Must be called again when the VM is replaced.
+    pub fn watch(&mut self, vm: &mut Chip8Vm) {
+        self.changes.borrow_mut().clear();
+        self.highlight.clear();
+
+        let changes = self.changes.clone();
+        vm.add_memory_listener(move |change| changes.borrow_mut().push(*change));
+    }
+
+    /// Draw the panels, inspecting and editing the VM.
+    ///
+    /// The pacing panel is only available while frame pacing statistics are collected.
+    pub fn ui(
+        &mut self,
+        ctx: &egui::Context,
+        vm: &mut Chip8Vm,
+        paused: bool,
+        pacing: Option<&PacingSummary>,
+    ) -> Option<PanelAction> {
+        let mut action = None;
+
+        egui::TopBottomPanel::top("debug_menu").show(ctx, |ui| {
+            egui::menu::bar(ui, |ui| {
+                ui.menu_button("View", |ui| {
+                    ui.checkbox(&mut self.registers, "Registers");
+                    ui.checkbox(&mut self.stack, "Stack");
+                    ui.checkbox(&mut self.timers, "Timers");
+                    ui.checkbox(&mut self.memory, "Memory");
+                    ui.checkbox(&mut self.disassembly, "Disassembly");
+                    ui.checkbox(&mut self.breakpoints, "Breakpoints");
+                    if pacing.is_some() {
+                        ui.checkbox(&mut self.pacing, "Pacing");
+                    }
+                });
+                ui.separator();
+                let label = if paused { "Resume" } else { "Pause" };
+                if ui.button(label).clicked() {
+                    action = Some(PanelAction::Pause(!paused));
+                }
+                if ui.button("Step").clicked() {
+                    action = Some(PanelAction::StepInstr);
+                }
+                if ui.button("Step frame").clicked() {
+                    action = Some(PanelAction::StepFrame);
+                }
+            });
+        });
+
+        egui::Window::new("Registers")
+            .open(&mut self.registers)
+            .resizable(false)
+            .show(ctx, |ui| registers_ui(ui, vm));
+        egui::Window::new("Stack")
+            .open(&mut self.stack)
+            .resizable(false)
+            .show(ctx, |ui| stack_ui(ui, vm));
+        egui::Window::new("Timers")
+            .open(&mut self.timers)
+            .resizable(false)
+            .show(ctx, |ui| timers_ui(ui, vm));
+
+        let mut memory = self.memory;
+        egui::Window::new("Memory")
+            .open(&mut memory)
+            .resizable(false)
+            .show(ctx, |ui| self.memory_ui(ui, vm));
+        self.memory = memory;
+
+        egui::Window::new("Disassembly")
+            .open(&mut self.disassembly)
+            .resizable(false)
+            .show(ctx, |ui| disassembly_ui(ui, vm));
+
+        let mut breakpoints = self.breakpoints;
+        egui::Window::new("Breakpoints")
+            .open(&mut breakpoints)
+            .resizable(false)
+            .show(ctx, |ui| self.breakpoints_ui(ui, vm));
+        self.breakpoints = breakpoints;
+
+        if let Some(summary) = pacing {
+            egui::Window::new("Pacing")
+                .open(&mut self.pacing)
+                .resizable(false)
+                .show(ctx, |ui| pacing_ui(ui, summary));
+        }
+
+        action
+    }
+
+    /// Hex editor of memory. Bytes can be dragged or typed into.
+    fn memory_ui(&mut self, ui: &mut egui::Ui, vm: &mut Chip8Vm) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.memory_follow_i, "Follow I");
+            ui.add_enabled(
+                !self.memory_follow_i,
+                egui::DragValue::new(&mut self.memory_address)
+                    .hexadecimal(4, false, true)
+                    .clamp_range(0..=MEM_SIZE - 1),
+            );
+        });
+        if self.memory_follow_i {
+            self.memory_address = vm.debugger().address() as usize;
+        }
+
+        // The last write stays highlighted until the next one.
+        let changes = mem::take(&mut *self.changes.borrow_mut());
+        if !changes.is_empty() {
+            self.highlight = changes;
+        }
+
+        let start = self.memory_address.min(MEM_SIZE - 1) / MEMORY_COLUMNS * MEMORY_COLUMNS;
+        let mut view = vm.memory_view();
+        egui::Grid::new("memory")
+            .spacing([4.0, 2.0])
+            .show(ui, |ui| {
+                for row in (start..MEM_SIZE).step_by(MEMORY_COLUMNS).take(MEMORY_ROWS) {
+                    ui.monospace(format!("0x{row:04X}"));
+                    for address in row..(row + MEMORY_COLUMNS).min(MEM_SIZE) {
+                        let Ok(mut value) = view.read_byte(address) else {
+                            break;
+                        };
+                        let changed = self.highlight.iter().any(|change| change.contains(address));
+                        let response = ui
+                            .scope(|ui| {
+                                if changed {
+                                    ui.visuals_mut().override_text_color = Some(CHANGED_COLOR);
+                                }
+                                ui.add(
+                                    egui::DragValue::new(&mut value)
+                                        .hexadecimal(2, false, true)
+                                        .speed(0.1),
+                                )
+                            })
+                            .inner;
+                        if response.changed() {
+                            if let Err(err) = view.write_byte(address, value) {
+                                tracing::error!("failed to write memory: {err}");
+                            }
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+    }
+
+    fn breakpoints_ui(&mut self, ui: &mut egui::Ui, vm: &mut Chip8Vm) {
+        let mut debugger = vm.debugger();
+
+        ui.horizontal(|ui| {
+            ui.label("0x");
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.breakpoint_input)
+                    .desired_width(48.0)
+                    .font(egui::TextStyle::Monospace),
+            );
+            let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if ui.button("Add").clicked() || submitted {
+                match usize::from_str_radix(self.breakpoint_input.trim(), 16) {
+                    Ok(address) if address < MEM_SIZE => {
+                        debugger.add_breakpoint(address);
+                        self.breakpoint_input.clear();
+                    }
+                    _ => tracing::warn!("invalid breakpoint address: {}", self.breakpoint_input),
+                }
+            }
+        });
+
+        let addresses: Vec<_> = debugger.breakpoints().collect();
+        if addresses.is_empty() {
+            ui.label("No breakpoints");
+        }
+        for address in addresses {
+            ui.horizontal(|ui| {
+                ui.monospace(format!("0x{address:04X}"));
+                if ui.small_button("Remove").clicked() {
+                    debugger.remove_breakpoint(address);
+                }
+            });
+        }
+    }
+}
+
+fn registers_ui(ui: &mut egui::Ui, vm: &mut Chip8Vm) {
+    let debugger = vm.debugger();
+
+    egui::Grid::new("registers").striped(true).show(ui, |ui| {
+        for (index, value) in debugger.registers().iter().enumerate() {
+            ui.monospace(format!("V{index:X}"));
+            ui.monospace(format!("{value:02X}"));
+            if index % 4 == 3 && index + 1 < REGISTER_COUNT {
+                ui.end_row();
+            }
+        }
+        ui.end_row();
+    });
+    ui.separator();
+    ui.monospace(format!(
+        "PC 0x{:04X}  I 0x{:04X}  SP {}",
+        debugger.pc(),
+        debugger.address(),
+        debugger.sp()
+    ));
+}
+
+fn stack_ui(ui: &mut egui::Ui, vm: &mut Chip8Vm) {
+    let stack = vm.call_stack();
+    if stack.is_empty() {
+        ui.label("No calls in progress");
+    }
+    // Innermost call first, like a backtrace.
+    for (depth, address) in stack.iter().enumerate().rev() {
+        ui.monospace(format!("#{depth}  return to 0x{address:04X}"));
+    }
+}
+
+fn timers_ui(ui: &mut egui::Ui, vm: &mut Chip8Vm) {
+    let debugger = vm.debugger();
+    ui.monospace(format!("DT {:02X}", debugger.delay_timer()));
+    ui.monospace(format!("ST {:02X}", debugger.sound_timer()));
+}
+
+/// Frame pacing statistics over the recent frames.
+fn pacing_ui(ui: &mut egui::Ui, summary: &PacingSummary) {
+    ui.monospace(format!("steps mean {:>6}", summary.steps_mean));
+    ui.monospace(format!("steps max  {:>6}", summary.steps_max));
+    ui.monospace(format!("frame p50  {:>6.2}ms", millis(summary.frame_p50)));
+    ui.monospace(format!("frame p95  {:>6.2}ms", millis(summary.frame_p95)));
+    ui.monospace(format!("frame p99  {:>6.2}ms", millis(summary.frame_p99)));
+    ui.monospace(format!("frame max  {:>6.2}ms", millis(summary.frame_max)));
+}
+
+/// Instructions around the program counter. Clicking an instruction
+/// toggles its breakpoint.
+fn disassembly_ui(ui: &mut egui::Ui, vm: &mut Chip8Vm) {
+    let mut debugger = vm.debugger();
+    let pc = debugger.pc();
+    let mut address = pc.saturating_sub(DISASM_BEFORE).max(MEM_START);
+    let mut toggled = None;
+
+    for _ in 0..DISASM_LINES {
+        let program = &debugger.memory()[MEM_START..];
+        let Some(instr) = Disassembler::decode_at(program, address - MEM_START) else {
+            break; // End of memory
+        };
+
+        let breakpoint = if debugger.has_breakpoint(address) {
+            '*'
+        } else {
+            ' '
+        };
+        let text = format!(
+            "{breakpoint} 0x{address:04X}  {:04X}  {}",
+            instr.bytecode(),
+            instr.repr()
+        );
+        let label =
+            egui::SelectableLabel::new(address == pc, egui::RichText::new(text).monospace());
+        if ui.add(label).clicked() {
+            toggled = Some(address);
+        }
+        address += instr.size();
+    }
+
+    if let Some(address) = toggled {
+        if !debugger.remove_breakpoint(address) {
+            debugger.add_breakpoint(address);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! Window showing a VM running on a remote server.
+//!
+//! The server runs the program, see `chip8 serve`, and the window only
+//! draws the frames it receives and sends the keys that change.
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpStream,
+    sync::mpsc::{self, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use chip8::{remote::RemoteMessage, DisplayPlane};
+use winit::{
+    event::{Event as EV, WindowEvent as WE},
+    platform::run_return::EventLoopExtRunReturn,
+};
+
+use crate::{
+    actions::{EXIT, PAUSE},
+    error::AppError,
+    render::Render,
+    theme::Theme,
+    window::WindowContext,
+    Chip8App, InputMap,
+};
+
+/// Time between checks for messages from the server.
+const POLL_INTERVAL: Duration = Duration::from_millis(4);
+
+/// Connect to a server, and play the program it runs in a window.
+///
+/// Returns when the window is closed, or the server stops the program.
+pub fn run_remote_window(
+    addr: &str,
+    mut input_map: InputMap,
+    theme: Theme,
+) -> Result<(), AppError> {
+    tracing::info!("connecting to {addr}...");
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_nodelay(true)?;
+
+    // Lines are read on their own thread, so the window stays responsive.
+    let (message_tx, message_rx) = mpsc::channel();
+    let reader = BufReader::new(stream.try_clone()?);
+    thread::spawn(move || {
+        for line in reader.lines() {
+            let message = match line.map(|line| RemoteMessage::parse(&line)) {
+                Ok(Ok(message)) => message,
+                Ok(Err(err)) => {
+                    tracing::warn!("{err}");
+                    continue;
+                }
+                Err(err) => {
+                    tracing::error!("connection failed: {err}");
+                    break;
+                }
+            };
+            if message_tx.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut event_loop = Chip8App::create_event_loop();
+
+    // The renderer must be dropped before the window, which owns the context.
+    let window_ctx = WindowContext::new(&event_loop);
+    window_ctx.set_title(&format!("chip8 - {addr}"));
+    if let Err(err) = window_ctx.make_context_current() {
+        tracing::error!("failed to make OpenGL context current: {err}");
+    }
+    let mut render = Render::new(window_ctx.gl.clone(), &window_ctx.window);
+    render.resize(window_ctx.window.inner_size());
+    render.set_theme(theme);
+
+    let mut display = DisplayPlane::new();
+    let mut paused = false;
+    let mut result = Ok(());
+
+    event_loop.run_return(|event, _, control_flow| {
+        control_flow.set_wait_until(Instant::now() + POLL_INTERVAL);
+
+        match event {
+            EV::NewEvents(_) => {
+                // Frame start
+                input_map.process();
+            }
+            EV::MainEventsCleared => {
+                if input_map.is_action_released(EXIT) {
+                    control_flow.set_exit();
+                    return;
+                }
+
+                let mut outgoing: Vec<RemoteMessage> = input_map
+                    .key_transitions()
+                    .map(|(key, state)| RemoteMessage::Key(key, state))
+                    .collect();
+                if input_map.is_action_released(PAUSE) {
+                    paused = !paused;
+                    outgoing.push(if paused {
+                        RemoteMessage::Pause
+                    } else {
+                        RemoteMessage::Resume
+                    });
+                }
+                for message in outgoing {
+                    if let Err(err) = writeln!(stream, "{message}") {
+                        result = Err(err.into());
+                        control_flow.set_exit();
+                        return;
+                    }
+                }
+
+                loop {
+                    match message_rx.try_recv() {
+                        Ok(RemoteMessage::Frame(frame)) => {
+                            display = DisplayPlane::from_rows(frame.rows);
+                            window_ctx.request_redraw();
+                        }
+                        Ok(RemoteMessage::Sound(on)) => tracing::debug!("sound: {on}"),
+                        Ok(RemoteMessage::Stopped(err)) => {
+                            match err {
+                                Some(err) => tracing::error!("server stopped: {err}"),
+                                None => tracing::info!("server stopped"),
+                            }
+                            control_flow.set_exit();
+                            return;
+                        }
+                        Ok(message) => tracing::warn!("unexpected message: {message}"),
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Disconnected) => {
+                            tracing::info!("server closed the connection");
+                            control_flow.set_exit();
+                            return;
+                        }
+                    }
+                }
+
+                if render.is_fading() {
+                    window_ctx.request_redraw();
+                }
+            }
+            EV::RedrawRequested(_) => {
+                render.clear_background();
+                render.draw_chip8_display(&display);
+                window_ctx.swap_buffers().unwrap();
+            }
+            EV::WindowEvent { event, .. } => match event {
+                WE::Resized(size) => {
+                    window_ctx.resize_surface(size);
+                    render.resize(size);
+                    window_ctx.request_redraw();
+                }
+                WE::KeyboardInput { input, .. } => {
+                    if let Some(virtual_keycode) = input.virtual_keycode {
+                        input_map.emit_key(virtual_keycode, input.state);
+                    }
+                }
+                WE::CloseRequested => control_flow.set_exit(),
+                _ => { /* blank */ }
+            },
+            _ => { /* blank */ }
+        }
+    });
+
+    // Unblock the reader thread.
+    let _ = stream.shutdown(std::net::Shutdown::Both);
+
+    result
+}
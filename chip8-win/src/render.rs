@@ -1,10 +1,27 @@
-use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
 use std::{fmt, marker::PhantomData};
 
 use chip8::constants::{DISPLAY_BUFFER_SIZE, DISPLAY_HEIGHT, DISPLAY_WIDTH};
-use chip8::Chip8DisplayBuffer;
+use chip8::{Chip8DisplayBuffer, DisplayPlane};
 use glow::{Context as GlowContext, HasContext};
-use winit::dpi::PhysicalSize;
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::WindowEvent,
+    window::Window,
+};
+
+use crate::theme::{Color, Theme};
+
+/// Duration of a frame on the original hardware, in seconds,
+/// which is the unit of the phosphor decay.
+const DECAY_FRAME_SECS: f32 = 1.0 / 60.0;
+
+/// Brightness below which a decaying pixel is considered off.
+///
+/// Matches the cut-off in the fragment shader.
+const DECAY_CUTOFF: f32 = 0.1;
 
 macro_rules! gl_error {
     ($gl:expr) => {
@@ -20,7 +37,7 @@ macro_rules! gl_error {
                     break;
                 }
                 has_error = true;
-                log::error!("OpenGL error [{file}:{line}]: 0x{err:04x}");
+                tracing::error!("OpenGL error [{file}:{line}]: 0x{err:04x}");
             }
             if has_error {
                 panic!("OpenGL Errors. See logs.");
@@ -36,7 +53,7 @@ macro_rules! shader_error {
         let _: &glow::Context = &$gl; // type assert
         let _: &glow::NativeShader = &$shader;
         if !$gl.get_shader_compile_status($shader) {
-            log::error!(
+            tracing::error!(
                 "failed to compile {} [{file}:{line}]: {}",
                 $name,
                 $gl.get_shader_info_log($shader)
@@ -48,29 +65,84 @@ macro_rules! shader_error {
 
 pub struct Render {
     /// The interface to the loaded OpenGL function.
-    gl: Rc<GlowContext>,
+    gl: Arc<GlowContext>,
     info: OpenGLInfo,
     chip8_display: Chip8Display,
     framebuffer: Framebuffer,
-    demo_pattern: Box<[bool; DISPLAY_BUFFER_SIZE]>,
+    demo_pattern: Box<DisplayPlane>,
+    theme: Theme,
+    /// Size of the window surface.
+    window_size: PhysicalSize<u32>,
+    /// Area of the window the Chip8 display is drawn to.
+    viewport: Viewport,
+    /// User interface drawn over the display.
+    gui: Gui,
 }
 
 impl Render {
-    pub fn new(gl: Rc<GlowContext>) -> Self {
+    /// Create the renderer of a window.
+    ///
+    /// The OpenGL context of the window must be current.
+    pub fn new(gl: Arc<GlowContext>, window: &Window) -> Self {
         let info = OpenGLInfo::new(gl.as_ref());
         let chip8_display = Self::create_chip8_display(gl.as_ref());
         let framebuffer = Self::create_framebuffer(gl.as_ref());
+        let gui = Gui::new(gl.clone(), window);
         Self {
             gl,
+            gui,
             info,
             chip8_display,
             framebuffer,
             demo_pattern: demo_display_pattern(),
+            theme: Theme::default(),
+            window_size: PhysicalSize::new(DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32),
+            viewport: Viewport::letterbox(PhysicalSize::new(
+                DISPLAY_WIDTH as u32,
+                DISPLAY_HEIGHT as u32,
+            )),
         }
     }
 
+    /// Fit the Chip8 display to the window size.
+    ///
+    /// The display is scaled by a whole number, to keep the pixels square
+    /// and equally sized, and centred with borders filling the remainder.
+    pub fn resize(&mut self, window_size: PhysicalSize<u32>) {
+        self.window_size = window_size;
+        self.viewport = Viewport::letterbox(window_size);
+        tracing::debug!("display viewport: {:?}", self.viewport);
+    }
+
+    /// Pixel of the Chip8 display under a position in the window,
+    /// or `None` when it's in the borders around the display.
+    pub fn hit_test(&self, position: PhysicalPosition<f64>) -> Option<(u8, u8)> {
+        self.viewport.pixel_at(self.window_size, position)
+    }
+
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.chip8_display.foreground = theme.foreground;
+        self.chip8_display.decay = theme.phosphor_decay.clamp(0.0, 1.0);
+        self.theme = theme;
+    }
+
+    pub fn filter(&self) -> DisplayFilter {
+        self.chip8_display.filter
+    }
+
+    /// Change the post-processing of the scaled display.
+    pub fn set_filter(&mut self, filter: DisplayFilter) {
+        self.chip8_display.filter = filter;
+    }
+
+    /// Indicates whether pixels are still fading out,
+    /// and the display needs to be redrawn even if it didn't change.
+    pub fn is_fading(&self) -> bool {
+        self.chip8_display.is_fading()
+    }
+
     fn create_framebuffer(gl: &GlowContext) -> Framebuffer {
-        log::debug!("creating framebuffer");
+        tracing::debug!("creating framebuffer");
         let width = 800;
         let height = 400;
         let size = PhysicalSize::new(width, height).cast();
@@ -164,7 +236,7 @@ impl Render {
     }
 
     fn compile_shaders(gl: &GlowContext) -> ShaderProgram {
-        log::debug!("compiling shaders");
+        tracing::debug!("compiling shaders");
         unsafe {
             let vert_shader = gl.create_shader(glow::VERTEX_SHADER).unwrap();
             gl.shader_source(vert_shader, include_str!("shaders/chip8.vert"));
@@ -188,7 +260,7 @@ impl Render {
             gl.link_program(program);
             if !gl.get_program_link_status(program) {
                 let message = gl.get_program_info_log(program);
-                log::error!("failed to link shader program: {message}");
+                tracing::error!("failed to link shader program: {message}");
             }
 
             // Flag the shader objects for deletion. They will be deleted later
@@ -205,7 +277,12 @@ impl Render {
             if let Some(u_matrix_loc) = gl.get_uniform_location(program, "u_Matrix") {
                 uniforms.push(("u_Matrix", u_matrix_loc));
             } else {
-                log::warn!("could not get location of uniform 'u_Matrix'");
+                tracing::warn!("could not get location of uniform 'u_Matrix'");
+            }
+            if let Some(u_filter_loc) = gl.get_uniform_location(program, "u_Filter") {
+                uniforms.push(("u_Filter", u_filter_loc));
+            } else {
+                tracing::warn!("could not get location of uniform 'u_Filter'");
             }
 
             ShaderProgram {
@@ -312,13 +389,17 @@ impl Render {
                     _vertex: PhantomData,
                 },
                 matrix,
+                foreground: Theme::default().foreground,
+                decay: 0.0,
+                filter: DisplayFilter::default(),
+                updated: Instant::now(),
             }
         }
     }
 
     pub fn draw_chip8_display(&mut self, chip8_buf: Chip8DisplayBuffer) {
         self.chip8_display.copy_points(chip8_buf);
-        self.chip8_display.draw(&self.gl);
+        self.draw_display();
     }
 
     /// Draw a test pattern.
@@ -328,7 +409,38 @@ impl Render {
     #[allow(dead_code)]
     pub fn draw_demo_pattern(&mut self) {
         self.chip8_display.copy_points(&self.demo_pattern);
-        self.chip8_display.draw(&self.gl);
+        self.draw_display();
+    }
+
+    fn draw_display(&self) {
+        let Viewport {
+            x,
+            y,
+            width,
+            height,
+        } = self.viewport;
+
+        unsafe {
+            self.gl.viewport(x, y, width, height);
+            self.chip8_display.draw(&self.gl);
+            self.gl.viewport(
+                0,
+                0,
+                self.window_size.width as i32,
+                self.window_size.height as i32,
+            );
+        }
+    }
+
+    /// Clear the window to the background colour of the theme.
+    pub fn clear_background(&mut self) {
+        let Color {
+            red,
+            green,
+            blue,
+            alpha,
+        } = self.theme.background;
+        self.clear_window(red, green, blue, alpha);
     }
 
     pub fn clear_window(&mut self, red: f32, green: f32, blue: f32, alpha: f32) {
@@ -341,6 +453,71 @@ impl Render {
     pub fn opengl_info(&self) -> &OpenGLInfo {
         &self.info
     }
+
+    /// Pass a window event to the user interface.
+    ///
+    /// Returns `true` when the interface used the event, like
+    /// typing into a text field, and it shouldn't be handled further.
+    pub fn gui_event(&mut self, event: &WindowEvent) -> bool {
+        self.gui.on_event(event)
+    }
+
+    /// Draw the user interface built by the closure over the display.
+    pub fn draw_gui(&mut self, window: &Window, run_ui: impl FnOnce(&egui::Context)) {
+        self.gui.draw(window, run_ui);
+    }
+}
+
+/// Immediate mode user interface, painted with egui.
+struct Gui {
+    ctx: egui::Context,
+    state: egui_winit::State,
+    painter: egui_glow::Painter,
+}
+
+impl Gui {
+    fn new(gl: Arc<GlowContext>, window: &Window) -> Self {
+        let painter = egui_glow::Painter::new(gl, "", None)
+            .unwrap_or_else(|err| panic!("failed to create egui painter: {err}"));
+
+        // The Wayland display is only used for the clipboard.
+        let mut state = egui_winit::State::new_with_wayland_display(None);
+        state.set_pixels_per_point(window.scale_factor() as f32);
+
+        Self {
+            ctx: egui::Context::default(),
+            state,
+            painter,
+        }
+    }
+
+    fn on_event(&mut self, event: &WindowEvent) -> bool {
+        self.state.on_event(&self.ctx, event).consumed
+    }
+
+    fn draw(&mut self, window: &Window, run_ui: impl FnOnce(&egui::Context)) {
+        let raw_input = self.state.take_egui_input(window);
+        let output = self.ctx.run(raw_input, run_ui);
+        self.state
+            .handle_platform_output(window, &self.ctx, output.platform_output);
+
+        for (id, image_delta) in &output.textures_delta.set {
+            self.painter.set_texture(*id, image_delta);
+        }
+        let primitives = self.ctx.tessellate(output.shapes);
+        let size: [u32; 2] = window.inner_size().into();
+        self.painter
+            .paint_primitives(size, self.ctx.pixels_per_point(), &primitives);
+        for id in &output.textures_delta.free {
+            self.painter.free_texture(*id);
+        }
+    }
+}
+
+impl Drop for Gui {
+    fn drop(&mut self) {
+        self.painter.destroy();
+    }
 }
 
 impl Drop for Render {
@@ -398,8 +575,8 @@ pub const DEMO_DISPLAY: &[u32; 64] = &[
 ];
 
 #[allow(dead_code)]
-pub fn demo_display_pattern() -> Box<[bool; DISPLAY_BUFFER_SIZE]> {
-    let buf = &mut [false; DISPLAY_BUFFER_SIZE];
+pub fn demo_display_pattern() -> Box<DisplayPlane> {
+    let mut buf = Box::new(DisplayPlane::new());
     const U32_BITS: usize = u32::BITS as usize;
 
     for y in 0..DISPLAY_HEIGHT {
@@ -410,12 +587,12 @@ pub fn demo_display_pattern() -> Box<[bool; DISPLAY_BUFFER_SIZE]> {
             // print!("|{dst_index} {index_a} {index_b}|");
             let bit = (DEMO_DISPLAY[index_a] >> index_b) & 1;
             // print!("{}", if bit == 1 { '#' } else { '.' });
-            buf[dst_index] = bit == 1;
+            buf.set(x, y, bit == 1);
         }
         println!();
     }
 
-    Box::new(*buf)
+    buf
 }
 
 struct Framebuffer {
@@ -470,35 +647,176 @@ impl ShaderProgram {
     }
 }
 
+/// Area of the window in pixels, with the origin at the bottom-left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Viewport {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+impl Viewport {
+    /// Largest whole number scale of the Chip8 display that fits the window, centred.
+    ///
+    /// Windows smaller than the display still get a scale of one.
+    fn letterbox(window_size: PhysicalSize<u32>) -> Self {
+        let (window_width, window_height) = (window_size.width as i32, window_size.height as i32);
+        let scale = (window_width / DISPLAY_WIDTH as i32)
+            .min(window_height / DISPLAY_HEIGHT as i32)
+            .max(1);
+        let width = DISPLAY_WIDTH as i32 * scale;
+        let height = DISPLAY_HEIGHT as i32 * scale;
+
+        Self {
+            x: (window_width - width) / 2,
+            y: (window_height - height) / 2,
+            width,
+            height,
+        }
+    }
+
+    /// Pixel of the Chip8 display at a position in a window of the given size,
+    /// with the origin of the position at the top-left of the window.
+    fn pixel_at(
+        &self,
+        window_size: PhysicalSize<u32>,
+        position: PhysicalPosition<f64>,
+    ) -> Option<(u8, u8)> {
+        // The viewport's origin is at the bottom-left.
+        let top = window_size.height as i32 - (self.y + self.height);
+        let x = (position.x - self.x as f64) / self.width as f64 * DISPLAY_WIDTH as f64;
+        let y = (position.y - top as f64) / self.height as f64 * DISPLAY_HEIGHT as f64;
+        let inside =
+            (0.0..DISPLAY_WIDTH as f64).contains(&x) && (0.0..DISPLAY_HEIGHT as f64).contains(&y);
+        inside.then_some((x as u8, y as u8))
+    }
+}
+
+/// Post-processing of the scaled Chip8 display.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayFilter {
+    /// Plain square pixels, scaled by the nearest neighbour.
+    #[default]
+    Nearest,
+    /// Dark lines between the rows of pixels, like a CRT.
+    Scanlines,
+    /// Round dots with gaps between them, like an LED matrix.
+    DotMatrix,
+}
+
+impl DisplayFilter {
+    pub const ALL: &'static [Self] = &[Self::Nearest, Self::Scanlines, Self::DotMatrix];
+
+    pub const NAMES: &'static [&'static str] = &["nearest", "scanlines", "dot-matrix"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Nearest => "nearest",
+            Self::Scanlines => "scanlines",
+            Self::DotMatrix => "dot-matrix",
+        }
+    }
+
+    /// The filter after this one, wrapping around to the first.
+    pub fn next(self) -> Self {
+        let index = Self::ALL
+            .iter()
+            .position(|filter| *filter == self)
+            .unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    /// Value of the `u_Filter` uniform, matching the constants in the shaders.
+    fn uniform(self) -> i32 {
+        match self {
+            Self::Nearest => 0,
+            Self::Scanlines => 1,
+            Self::DotMatrix => 2,
+        }
+    }
+}
+
+impl FromStr for DisplayFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(Self::Nearest),
+            "scanlines" => Ok(Self::Scanlines),
+            "dot-matrix" => Ok(Self::DotMatrix),
+            _ => Err(format!(
+                "unknown filter '{s}', expected one of: {}",
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DisplayFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
 /// Vertex points that represent the pixels on the Chip8 display.
 struct Chip8Display {
     shader: ShaderProgram,
     points: Box<[Point; DISPLAY_BUFFER_SIZE]>,
     vertex_array: VertexArray<Point>,
     matrix: [f32; 16],
+    foreground: Color,
+    /// Fraction of brightness an unlit pixel keeps per frame.
+    decay: f32,
+    /// Time the points were last copied, for decaying by elapsed time.
+    updated: Instant,
+    filter: DisplayFilter,
 }
 
 impl Chip8Display {
     fn copy_points(&mut self, chip8_buf: Chip8DisplayBuffer) {
-        assert_eq!(chip8_buf.len(), self.points.len());
+        assert_eq!(chip8_buf.iter().len(), self.points.len());
+
+        // Redraws don't happen at a fixed rate, so the decay is scaled by the elapsed time.
+        let now = Instant::now();
+        let frames = now.duration_since(self.updated).as_secs_f32() / DECAY_FRAME_SECS;
+        let fade = self.decay.powf(frames);
+        self.updated = now;
 
         // Build points from given buffer
         for (index, pixel_state) in chip8_buf.iter().enumerate() {
-            self.points[index].alpha = if *pixel_state { 1.0 } else { 0.0 };
+            let point = &mut self.points[index];
+            point.alpha = if pixel_state {
+                1.0
+            } else if point.alpha * fade >= DECAY_CUTOFF {
+                point.alpha * fade
+            } else {
+                0.0
+            };
         }
     }
 
+    fn is_fading(&self) -> bool {
+        self.points
+            .iter()
+            .any(|point| point.alpha > 0.0 && point.alpha < 1.0)
+    }
+
     fn draw(&self, gl: &GlowContext) {
         let Self {
             shader,
             points,
             vertex_array,
             matrix,
+            foreground,
+            filter,
+            ..
         } = self;
 
         unsafe {
             gl.disable(glow::CULL_FACE);
             gl.enable(glow::BLEND);
+            gl.blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA);
 
             gl.bind_framebuffer(glow::FRAMEBUFFER, None);
 
@@ -515,7 +833,13 @@ impl Chip8Display {
 
             let u_color_loc = shader.uniform_location("u_Color");
             assert!(u_color_loc.is_some());
-            gl.uniform_4_f32(u_color_loc, 0.8, 0.9, 1.0, 1.0);
+            gl.uniform_4_f32(
+                u_color_loc,
+                foreground.red,
+                foreground.green,
+                foreground.blue,
+                foreground.alpha,
+            );
 
             let u_matrix_loc = shader.uniform_location("u_Matrix");
             assert!(u_matrix_loc.is_some());
@@ -526,6 +850,9 @@ impl Chip8Display {
                 bytemuck::cast_slice(matrix.as_slice()),
             );
 
+            let u_filter_loc = shader.uniform_location("u_Filter");
+            gl.uniform_1_i32(u_filter_loc, filter.uniform());
+
             gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(vertex_array.index_buffer));
             gl.draw_elements(glow::POINTS, points.len() as i32, glow::UNSIGNED_SHORT, 0);
 
@@ -653,3 +980,89 @@ fn flatten_matrix(m: &[[f32; 4]; 4]) -> [f32; 16] {
         m[3][0], m[3][1], m[3][2], m[3][3],
     ]
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_display_filter() {
+        for (filter, name) in DisplayFilter::ALL.iter().zip(DisplayFilter::NAMES) {
+            assert_eq!(filter.name(), *name);
+            assert_eq!(name.parse::<DisplayFilter>(), Ok(*filter));
+        }
+        assert!("blur".parse::<DisplayFilter>().is_err());
+
+        // Cycling visits every filter, and wraps around.
+        let mut filter = DisplayFilter::default();
+        for expected in [
+            DisplayFilter::Scanlines,
+            DisplayFilter::DotMatrix,
+            DisplayFilter::Nearest,
+        ] {
+            filter = filter.next();
+            assert_eq!(filter, expected);
+        }
+    }
+
+    #[test]
+    fn test_letterbox() {
+        let viewport = |width, height| Viewport::letterbox(PhysicalSize::new(width, height));
+
+        assert_eq!(
+            viewport(640, 320),
+            Viewport {
+                x: 0,
+                y: 0,
+                width: 640,
+                height: 320
+            }
+        );
+        assert_eq!(
+            viewport(800, 400),
+            Viewport {
+                x: 16,
+                y: 8,
+                width: 768,
+                height: 384
+            }
+        );
+        // Tall window has borders at the top and bottom.
+        assert_eq!(
+            viewport(640, 600),
+            Viewport {
+                x: 0,
+                y: 140,
+                width: 640,
+                height: 320
+            }
+        );
+        // Too small to fit, so it's cropped.
+        assert_eq!(
+            viewport(32, 32),
+            Viewport {
+                x: -16,
+                y: 0,
+                width: 64,
+                height: 32
+            }
+        );
+    }
+
+    #[test]
+    fn test_pixel_at() {
+        let size = PhysicalSize::new(800, 400);
+        let viewport = Viewport::letterbox(size);
+        let pixel_at = |x, y| viewport.pixel_at(size, PhysicalPosition::new(x, y));
+
+        // Scaled by 12, with borders of 16 on the sides and 8 at the top and bottom.
+        assert_eq!(pixel_at(16.0, 8.0), Some((0, 0)));
+        assert_eq!(pixel_at(27.9, 19.9), Some((0, 0)));
+        assert_eq!(pixel_at(28.0, 20.0), Some((1, 1)));
+        assert_eq!(pixel_at(783.9, 391.9), Some((63, 31)));
+        assert_eq!(pixel_at(15.9, 100.0), None);
+        assert_eq!(pixel_at(784.0, 100.0), None);
+        assert_eq!(pixel_at(100.0, 7.9), None);
+        assert_eq!(pixel_at(100.0, 392.0), None);
+    }
+}
@@ -1,9 +1,16 @@
 #version 330
 
-// Foreground color of the 
+// Foreground color of the lit pixels
 uniform vec4 u_Color;
 
+// Post-processing filter, see `DisplayFilter` in render.rs
+uniform int u_Filter;
+
+const int FILTER_SCANLINES = 1;
+const int FILTER_DOT_MATRIX = 2;
+
 in float state;
+in vec2 uv;
 
 out vec4 frag_color;
 
@@ -11,5 +18,22 @@ void main() {
     if (state < 0.1) {
         discard;
     }
-    frag_color = u_Color * state;
+    // Decaying pixels fade into the background.
+    float alpha = u_Color.a * state;
+
+    if (u_Filter == FILTER_SCANLINES) {
+        // Dark gap at the bottom of every row, like the lines of a CRT.
+        if (uv.y > 0.7) {
+            alpha *= 0.35;
+        }
+    } else if (u_Filter == FILTER_DOT_MATRIX) {
+        // Round dots with a soft edge.
+        float dist = length(uv - vec2(0.5));
+        if (dist > 0.5) {
+            discard;
+        }
+        alpha *= smoothstep(0.5, 0.4, dist);
+    }
+
+    frag_color = vec4(u_Color.rgb, alpha);
 }
@@ -0,0 +1,143 @@
+//! Display colours and effects
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+/// Appearance of the Chip8 display.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    /// Colour of the lit pixels.
+    pub foreground: Color,
+    /// Colour of the unlit pixels, and the rest of the window.
+    pub background: Color,
+    /// Fraction of brightness a pixel keeps per frame after it's turned off,
+    /// mimicking the persistence of CRT phosphor.
+    ///
+    /// Zero turns pixels off immediately.
+    pub phosphor_decay: f32,
+}
+
+impl Theme {
+    /// Load a theme from a YAML file.
+    ///
+    /// Missing fields keep their default value.
+    pub fn from_file(filepath: impl AsRef<Path>) -> std::io::Result<Self> {
+        let file = std::fs::File::open(filepath)?;
+        let theme: Theme = serde_yaml::from_reader(file)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        tracing::debug!("loaded theme: {:?}", theme);
+
+        Ok(theme)
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            foreground: Color::rgb(0xCC, 0xE6, 0xFF),
+            background: Color::rgba(0x1D, 0x21, 0x28, 0xE6),
+            phosphor_decay: 0.0,
+        }
+    }
+}
+
+/// RGBA colour with normalised components.
+///
+/// Written as a hex string, either `#RRGGBB` or `#RRGGBBAA`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Color {
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32,
+    pub alpha: f32,
+}
+
+impl Color {
+    pub fn rgb(red: u8, green: u8, blue: u8) -> Self {
+        Self::rgba(red, green, blue, 0xFF)
+    }
+
+    pub fn rgba(red: u8, green: u8, blue: u8, alpha: u8) -> Self {
+        Self {
+            red: red as f32 / 255.0,
+            green: green as f32 / 255.0,
+            blue: blue as f32 / 255.0,
+            alpha: alpha as f32 / 255.0,
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.trim().trim_start_matches('#');
+        let component = |index: usize| {
+            hex.get(index * 2..index * 2 + 2)
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .ok_or_else(|| format!("invalid colour '{s}', expected #RRGGBB or #RRGGBBAA"))
+        };
+
+        match hex.len() {
+            6 => Ok(Self::rgb(component(0)?, component(1)?, component(2)?)),
+            8 => Ok(Self::rgba(
+                component(0)?,
+                component(1)?,
+                component(2)?,
+                component(3)?,
+            )),
+            _ => Err(format!(
+                "invalid colour '{s}', expected #RRGGBB or #RRGGBBAA"
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let to_u8 = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        write!(
+            f,
+            "#{:02X}{:02X}{:02X}{:02X}",
+            to_u8(self.red),
+            to_u8(self.green),
+            to_u8(self.blue),
+            to_u8(self.alpha)
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_color_parse() {
+        assert_eq!("#CCE6FF".parse(), Ok(Color::rgb(0xCC, 0xE6, 0xFF)));
+        assert_eq!("1d2128e6".parse(), Ok(Color::rgba(0x1D, 0x21, 0x28, 0xE6)));
+        assert!("#CCE6F".parse::<Color>().is_err());
+        assert!("#GGGGGG".parse::<Color>().is_err());
+        assert_eq!(Color::rgb(0x12, 0x34, 0x56).to_string(), "#123456FF");
+    }
+
+    #[test]
+    fn test_theme_yaml() {
+        let theme: Theme =
+            serde_yaml::from_str("foreground: '#33FF33'\nphosphor_decay: 0.5\n").unwrap();
+        assert_eq!(theme.foreground, Color::rgb(0x33, 0xFF, 0x33));
+        assert_eq!(theme.background, Theme::default().background);
+        assert_eq!(theme.phosphor_decay, 0.5);
+    }
+}
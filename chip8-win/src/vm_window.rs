@@ -0,0 +1,687 @@
+//! Window running a virtual machine.
+use std::{
+    cell::RefCell,
+    mem,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use chip8::{
+    constants::DELAY_FREQUENCY, prelude::*, Flow, FrameReport, KeyState, MemoryChange, Mouse,
+    RewindConf,
+};
+use chip8_jit::JitEngine;
+use tracing::info;
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{ElementState, MouseButton, WindowEvent},
+    window::WindowId,
+};
+
+use crate::{
+    backend::{Backend, MipsMeter},
+    error::AppError,
+    frame_clock::{Step, Timestep},
+    inputmap::InputMap,
+    keypad::KeypadOverlay,
+    pacing::PacingStats,
+    panels::{DebugPanels, PanelAction},
+    render::{DisplayFilter, Render},
+    theme::Theme,
+    window::WindowContext,
+};
+
+/// Maximum number of rewind snapshots kept by the VM.
+const REWIND_CAPACITY: usize = 600;
+
+/// Number of VM instructions between rewind snapshots.
+const REWIND_INTERVAL: usize = 200;
+
+/// Size of a Chip8 pixel in screenshots.
+const SCREENSHOT_SCALE: usize = 10;
+
+/// Window with its own OpenGL context, renderer and VM.
+///
+/// Every window has a separate OpenGL context, so the context is made
+/// current before the renderer touches OpenGL.
+pub struct VmWindow {
+    // The renderer must be dropped before the window, which owns the context.
+    render: Render,
+    window_ctx: WindowContext,
+    vm: Chip8Vm,
+    /// Configuration before the settings of a ROM descriptor are applied.
+    conf: Chip8Conf,
+    /// Bytecode of the loaded program, for resetting.
+    rom: Vec<u8>,
+    /// Descriptor of the loaded program.
+    rom_info: Option<RomInfo>,
+    /// Distinguishes windows running the same program, in the title.
+    label: Option<String>,
+    /// Saved VM state that can be restored.
+    save_slot: Option<Vec<u8>>,
+    /// Emulation is frozen, and only advanced by the step actions.
+    paused: bool,
+    /// Debug panels drawn over the display.
+    panels: DebugPanels,
+    /// Overlay of the keypad, showing the keys that are down.
+    keypad: KeypadOverlay,
+    /// Fraction of an instruction left over by the adaptive timestep.
+    instr_carry: f64,
+    /// Frame pacing statistics, when they're collected.
+    pacing: Option<PacingStats>,
+    /// Pointer and buttons over the display, for the mouse extension.
+    mouse: Mouse,
+    /// How the VM executes instructions.
+    backend: Backend,
+    /// Compiled blocks of the program, while the JIT backend is used.
+    jit: Option<JitEngine>,
+    /// Writes to memory since the JIT last ran, which may
+    /// have changed code it compiled.
+    memory_changes: Rc<RefCell<Vec<MemoryChange>>>,
+    /// Speed of the backend, shown in the title.
+    mips: MipsMeter,
+}
+
+impl VmWindow {
+    /// Create the window's renderer and VM, with the given configuration.
+    ///
+    /// Rewinding is always configured by the app.
+    pub fn new(window_ctx: WindowContext, conf: Chip8Conf) -> Self {
+        // Create an application specific renderer.
+        if let Err(err) = window_ctx.make_context_current() {
+            tracing::error!("failed to make OpenGL context current: {err}");
+        }
+        let mut render = Render::new(window_ctx.gl.clone(), &window_ctx.window);
+        render.resize(window_ctx.window.inner_size());
+        tracing::info!("OpenGL renderer created:\n{}", render.opengl_info());
+
+        // Create Chip8 emulated
+        let conf = Chip8Conf {
+            rewind: RewindConf {
+                capacity: REWIND_CAPACITY,
+                interval: REWIND_INTERVAL,
+            },
+            ..conf
+        };
+        let vm = Chip8Vm::new(conf.clone());
+
+        let mut window = Self {
+            render,
+            window_ctx,
+            vm,
+            conf,
+            rom: vec![],
+            rom_info: None,
+            label: None,
+            save_slot: None,
+            paused: false,
+            panels: DebugPanels::default(),
+            keypad: KeypadOverlay::default(),
+            instr_carry: 0.0,
+            pacing: None,
+            mouse: Mouse::default(),
+            backend: Backend::default(),
+            jit: None,
+            memory_changes: Rc::default(),
+            mips: MipsMeter::default(),
+        };
+        window.watch_vm();
+        window
+    }
+
+    /// Listen for writes to the memory of the VM.
+    ///
+    /// Must be called again when the VM is replaced.
+    fn watch_vm(&mut self) {
+        self.panels.watch(&mut self.vm);
+
+        self.memory_changes.borrow_mut().clear();
+        let changes = self.memory_changes.clone();
+        self.vm
+            .add_memory_listener(move |change| changes.borrow_mut().push(*change));
+    }
+
+    #[inline]
+    pub fn window_id(&self) -> WindowId {
+        self.window_ctx.window_id()
+    }
+
+    pub fn vm(&self) -> &Chip8Vm {
+        &self.vm
+    }
+
+    pub fn vm_mut(&mut self) -> &mut Chip8Vm {
+        &mut self.vm
+    }
+
+    /// Change the colours and effects of the display.
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.render.set_theme(theme);
+        self.window_ctx.request_redraw();
+    }
+
+    /// Change the post-processing of the scaled display.
+    pub fn set_filter(&mut self, filter: DisplayFilter) {
+        self.render.set_filter(filter);
+        self.window_ctx.request_redraw();
+    }
+
+    /// Switch to the next display filter.
+    pub fn cycle_filter(&mut self) {
+        let filter = self.render.filter().next();
+        tracing::info!("display filter: {filter}");
+        self.set_filter(filter);
+    }
+
+    /// Change how the VM executes instructions, keeping its state.
+    ///
+    /// Replaces the `decode_cache` setting of the configuration. When the
+    /// JIT isn't available on this platform, the interpreter is used instead.
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+        self.vm.set_decode_cache(backend == Backend::DecodeCache);
+        self.jit = None;
+        if backend == Backend::Jit {
+            // The quirks are compiled into the blocks.
+            match JitEngine::new(self.vm.config().quirks) {
+                Ok(engine) => self.jit = Some(engine),
+                Err(err) => {
+                    tracing::error!("failed to create JIT: {err}");
+                    self.backend = Backend::Interpreter;
+                }
+            }
+        }
+        self.memory_changes.borrow_mut().clear();
+        self.mips.restart();
+        self.update_title();
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Switch to the next execution backend.
+    pub fn cycle_backend(&mut self) {
+        let backend = self.backend.next();
+        tracing::info!("backend: {backend}");
+        self.set_backend(backend);
+    }
+
+    /// Wait for the vertical blank when presenting, or present immediately.
+    pub fn set_vsync(&self, vsync: bool) {
+        self.window_ctx.set_vsync(vsync);
+    }
+
+    /// Collect frame pacing statistics, logged periodically and shown in the debug panels.
+    pub fn set_pacing_stats(&mut self, enabled: bool) {
+        self.pacing = enabled.then(PacingStats::default);
+    }
+
+    /// Load ROM file into VM, configured by its descriptor when one sits next to it.
+    pub fn load_rom_file(&mut self, filepath: impl AsRef<Path>) -> Result<(), AppError> {
+        let filepath = filepath.as_ref();
+        info!("load rom: {}", filepath.display());
+
+        let builder = Chip8VmBuilder::new()
+            .with_conf(self.conf.clone())
+            .with_rom_file(filepath)?;
+        let rom = builder.rom().to_vec();
+        let rom_info = builder.rom_info().cloned();
+
+        self.vm = builder.build()?;
+        self.watch_vm();
+        self.rom = rom;
+        self.set_rom_info(rom_info);
+        // The descriptor may have changed the quirks.
+        self.set_backend(self.backend);
+        self.window_ctx.request_redraw();
+
+        Ok(())
+    }
+
+    pub fn load_rom_asm(&mut self, source_code: &str) -> Result<(), AppError> {
+        let bytecode = chip8::assemble(source_code)?;
+        self.load_rom_bytecode(&bytecode)
+    }
+
+    /// Load the program into a freshly reset VM.
+    ///
+    /// The registers, timers, keys and display of the previous program are cleared.
+    pub fn load_rom_bytecode(&mut self, bytecode: &[u8]) -> Result<(), AppError> {
+        let mut vm = Chip8Vm::new(self.vm.config().clone());
+        vm.load_bytecode(bytecode)?;
+
+        self.vm = vm;
+        self.watch_vm();
+        self.rom = bytecode.to_vec();
+        self.set_backend(self.backend);
+        self.window_ctx.request_redraw();
+
+        Ok(())
+    }
+
+    /// Load a ROM file, keeping the current program on failure.
+    pub fn load_rom_logged(&mut self, filepath: &Path) {
+        if let Err(err) = self.load_rom_file(filepath) {
+            tracing::error!("failed to load rom {}: {err}", filepath.display());
+        }
+    }
+
+    /// Reset the VM, and load the current program again.
+    pub fn reload_rom(&mut self) -> Result<(), AppError> {
+        let rom = mem::take(&mut self.rom);
+        self.load_rom_bytecode(&rom)
+    }
+
+    /// Descriptor of the loaded program, which names the window.
+    pub fn set_rom_info(&mut self, rom_info: Option<RomInfo>) {
+        self.rom_info = rom_info;
+        self.update_title();
+    }
+
+    pub fn rom_info(&self) -> Option<&RomInfo> {
+        self.rom_info.as_ref()
+    }
+
+    /// Text shown in the title, to tell apart windows running the same program.
+    pub fn set_label(&mut self, label: Option<String>) {
+        self.label = label;
+        self.update_title();
+    }
+
+    fn update_title(&self) {
+        let mut title = match self.rom_info.as_ref().and_then(|info| info.title.as_ref()) {
+            Some(title) => format!("chip8 - {title}"),
+            None => "chip8".to_string(),
+        };
+        if let Some(label) = &self.label {
+            title.push_str(&format!(" [{label}]"));
+        }
+        title.push_str(&format!(" - {}", self.backend));
+        if let Some(mips) = self.mips.mips() {
+            title.push_str(&format!(" {mips:.4} MIPS"));
+        }
+        if self.paused {
+            title.push_str(" (paused)");
+        }
+        self.window_ctx.set_title(&title);
+    }
+
+    /// Freeze or resume emulation.
+    pub fn set_paused(&mut self, paused: bool) {
+        if self.paused == paused {
+            return;
+        }
+
+        self.paused = paused;
+        self.update_title();
+        if let Some(pacing) = self.pacing.as_mut() {
+            pacing.restart();
+        }
+        if paused {
+            tracing::info!("paused");
+            self.log_state();
+        } else {
+            tracing::info!("resumed");
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Execute one frame, which pauses emulation if it's running.
+    pub fn step_frame(&mut self) {
+        let result = self.run_frame().map(|report| {
+            tracing::info!("frame: {} instructions", report.instructions);
+        });
+        self.after_step(result);
+    }
+
+    /// Execute one instruction, which pauses emulation if it's running.
+    pub fn step_instr(&mut self) {
+        let result = self.vm.tick().map(|_| ()).map_err(AppError::from);
+        self.after_step(result);
+    }
+
+    fn after_step(&mut self, result: Result<(), AppError>) {
+        self.set_paused(true);
+        if let Err(err) = result {
+            tracing::error!("VM error: {err}");
+        }
+        self.log_state();
+        self.window_ctx.request_redraw();
+    }
+
+    /// Log the registers, so the state can be inspected while paused.
+    fn log_state(&mut self) {
+        let debugger = self.vm.debugger();
+        let registers = debugger
+            .registers()
+            .iter()
+            .map(|value| format!("{value:02X}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        tracing::info!(
+            "PC 0x{:04X}  I 0x{:04X}  DT {:02X}  ST {:02X}  V {registers}",
+            debugger.pc(),
+            debugger.address(),
+            debugger.delay_timer(),
+            debugger.sound_timer(),
+        );
+    }
+
+    /// Save the VM state into the in-memory slot.
+    pub fn save_state(&mut self) {
+        self.save_slot = Some(self.vm.save_state());
+    }
+
+    /// Restore the VM state from the in-memory slot.
+    pub fn load_state(&mut self) {
+        match self.save_slot {
+            Some(ref state) => match self.vm.load_state(state) {
+                Ok(_) => {
+                    tracing::info!("load state");
+                    self.clear_jit();
+                    self.window_ctx.request_redraw();
+                }
+                Err(err) => tracing::error!("failed to load state: {err}"),
+            },
+            None => tracing::info!("no saved state to load"),
+        }
+    }
+
+    /// Go back in time by one snapshot.
+    pub fn rewind(&mut self) {
+        match self.vm.rewind(1) {
+            Ok(0) => {}
+            Ok(_) => {
+                self.clear_jit();
+                self.window_ctx.request_redraw();
+            }
+            Err(err) => tracing::error!("failed to rewind: {err}"),
+        }
+    }
+
+    /// Discard the code compiled by the JIT, after memory was restored.
+    fn clear_jit(&mut self) {
+        if let Some(jit) = self.jit.as_mut() {
+            jit.clear();
+        }
+    }
+
+    /// Save the display as a PNG image in the working directory.
+    ///
+    /// The suffix tells apart screenshots of windows taken at the same time.
+    pub fn save_screenshot(&self, suffix: &str) -> Result<PathBuf, AppError> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let filepath = PathBuf::from(format!("screenshot-{timestamp}{suffix}.png"));
+
+        self.vm
+            .display_to_image(SCREENSHOT_SCALE)
+            .save_png(&filepath)?;
+
+        Ok(filepath)
+    }
+
+    /// Show or hide the debug panels.
+    pub fn toggle_panels(&mut self) {
+        self.panels.visible = !self.panels.visible;
+        self.window_ctx.request_redraw();
+    }
+
+    /// Show or hide the keypad overlay.
+    pub fn toggle_keypad(&mut self) {
+        self.keypad.visible = !self.keypad.visible;
+        self.window_ctx.request_redraw();
+    }
+
+    /// Update the keypad overlay with the keys of the input map, while it's shown.
+    pub fn update_keypad(&mut self, input_map: &InputMap) {
+        if self.keypad.visible {
+            self.keypad.update(input_map);
+        }
+    }
+
+    /// Move the mouse pointer of the VM to the display pixel under the cursor.
+    ///
+    /// The VM ignores the mouse, unless its program enabled the extension.
+    pub fn mouse_moved(&mut self, position: Option<PhysicalPosition<f64>>) {
+        let pixel = position.and_then(|position| self.render.hit_test(position));
+        self.mouse.set_position(pixel);
+        self.vm.set_mouse(self.mouse);
+    }
+
+    pub fn mouse_input(&mut self, button: MouseButton, state: ElementState) {
+        let mask = match button {
+            MouseButton::Left => Mouse::LEFT,
+            MouseButton::Right => Mouse::RIGHT,
+            MouseButton::Middle => Mouse::MIDDLE,
+            MouseButton::Other(_) => return,
+        };
+        let state = match state {
+            ElementState::Pressed => KeyState::Pressed,
+            ElementState::Released => KeyState::Released,
+        };
+        self.mouse.set_button(mask, state);
+        self.vm.set_mouse(self.mouse);
+    }
+
+    /// Pass a window event to the debug panels, while they're shown.
+    ///
+    /// Returns `true` when the panels used the event, and it shouldn't be handled further.
+    pub fn on_window_event(&mut self, event: &WindowEvent) -> bool {
+        self.panels.visible && self.render.gui_event(event)
+    }
+
+    /// Run the emulation due, and request a redraw when the display changed.
+    ///
+    /// With the fixed timestep, each of the due 60Hz frames executes the instructions
+    /// of one display tick at the configured clock frequency, and counts down the
+    /// timers once. With the adaptive timestep, the instructions of the elapsed time
+    /// are executed, and the timers count down in real time. Either way the updates
+    /// are paced by the [`FrameClock`](crate::frame_clock::FrameClock) of the app, so
+    /// emulation speed doesn't depend on how often the event loop wakes up.
+    ///
+    /// Errors are logged, and pause emulation so they aren't repeated every frame.
+    pub fn update(&mut self, step: Step, timestep: Timestep) {
+        // Keep redrawing until the phosphor has faded out, and while
+        // the panels or keypad are shown, so they stay up to date.
+        if self.render.is_fading() || self.panels.visible || self.keypad.visible {
+            self.window_ctx.request_redraw();
+        }
+
+        if let Some(summary) = self.pacing.as_mut().and_then(PacingStats::poll_log) {
+            tracing::info!("pacing: {summary}");
+        }
+
+        if self.paused {
+            return;
+        }
+
+        let result = match timestep {
+            Timestep::Fixed => self.run_frames(step.frames),
+            Timestep::Adaptive => self.run_elapsed(step),
+        };
+        match result {
+            Ok(steps) => {
+                if let Some(pacing) = self.pacing.as_mut() {
+                    pacing.record_steps(steps);
+                }
+            }
+            Err(err) => {
+                tracing::error!("VM error: {err:#}");
+                self.set_paused(true);
+            }
+        }
+    }
+
+    /// Execute whole frames, returning the number of instructions executed.
+    fn run_frames(&mut self, frames: u32) -> Result<u32, AppError> {
+        let mut steps = 0;
+        for _ in 0..frames {
+            let report = self.run_frame()?;
+            steps += report.instructions as u32;
+
+            // Queue a RedrawRequested event, only when the display changed.
+            if report.draw {
+                self.window_ctx.request_redraw();
+            }
+            if report.interrupt {
+                break;
+            }
+        }
+        Ok(steps)
+    }
+
+    /// Execute the instructions of the elapsed time, returning the number executed.
+    fn run_elapsed(&mut self, step: Step) -> Result<u32, AppError> {
+        let frequency = self.vm.instructions_per_frame() as f64 * DELAY_FREQUENCY as f64;
+        let due = step.elapsed.as_secs_f64() * frequency + self.instr_carry;
+        let count = due as u32;
+        self.instr_carry = due.fract();
+
+        let started = Instant::now();
+        let steps = match self.jit.as_mut() {
+            Some(jit) => {
+                Self::invalidate_changes(jit, &self.memory_changes);
+                let total_steps =
+                    |jit: &JitEngine| jit.stats().compiled_steps + jit.stats().interpreted_steps;
+                let before = total_steps(jit);
+                jit.run_steps(&mut self.vm, count as usize)?;
+                // Draws aren't reported by the blocks.
+                self.window_ctx.request_redraw();
+                (total_steps(jit) - before) as u32
+            }
+            None => self.tick_elapsed(count)?,
+        };
+        self.record_mips(steps, started);
+        Ok(steps)
+    }
+
+    /// Interpret the instructions, returning the number executed.
+    fn tick_elapsed(&mut self, count: u32) -> Chip8Result<u32> {
+        let mut steps = 0;
+        for _ in 0..count {
+            match self.vm.tick()? {
+                Flow::Draw => self.window_ctx.request_redraw(),
+                // The instruction didn't complete, and will be retried next update.
+                Flow::Interrupt | Flow::KeyWait => break,
+                _ => {}
+            }
+            steps += 1;
+        }
+        Ok(steps)
+    }
+
+    /// Execute one frame with the backend.
+    fn run_frame(&mut self) -> Result<FrameReport, AppError> {
+        let started = Instant::now();
+        let report = match self.jit.as_mut() {
+            Some(jit) => {
+                Self::invalidate_changes(jit, &self.memory_changes);
+                jit.run_frame(&mut self.vm)?
+            }
+            None => {
+                self.memory_changes.borrow_mut().clear();
+                self.vm.run_frame()?
+            }
+        };
+        self.record_mips(report.instructions as u32, started);
+        Ok(report)
+    }
+
+    /// Discard the compiled code overwritten since the JIT last ran, like
+    /// by edits in the debug panels, or instructions stepped by the interpreter.
+    fn invalidate_changes(jit: &mut JitEngine, changes: &RefCell<Vec<MemoryChange>>) {
+        for change in changes.borrow_mut().drain(..) {
+            let start = change.address as usize;
+            jit.invalidate(start, start + change.len);
+        }
+    }
+
+    fn record_mips(&mut self, steps: u32, started: Instant) {
+        if self.mips.record(steps, started.elapsed()) {
+            self.update_title();
+        }
+    }
+
+    /// Draw the display into the window.
+    pub fn redraw(&mut self) {
+        // The renderer draws with whichever context is current.
+        if self.window_ctx.make_context_current().is_err() {
+            return;
+        }
+
+        self.render.clear_background();
+
+        self.render.draw_chip8_display(self.vm.display_buffer());
+        // self.render.draw_demo_pattern();
+
+        let mut action = None;
+        if self.panels.visible || self.keypad.visible {
+            let paused = self.paused;
+            self.render.draw_gui(&self.window_ctx.window, |ctx| {
+                if self.panels.visible {
+                    let pacing = self.pacing.as_ref().map(PacingStats::summary);
+                    action = self.panels.ui(ctx, &mut self.vm, paused, pacing.as_ref());
+                }
+                if self.keypad.visible {
+                    self.keypad.ui(ctx, self.rom_info.as_ref());
+                }
+            });
+        }
+
+        self.window_ctx.swap_buffers().unwrap();
+        if let Some(pacing) = self.pacing.as_mut() {
+            pacing.record_redraw();
+        }
+
+        match action {
+            Some(PanelAction::Pause(paused)) => self.set_paused(paused),
+            Some(PanelAction::StepInstr) => self.step_instr(),
+            Some(PanelAction::StepFrame) => self.step_frame(),
+            None => {}
+        }
+    }
+
+    pub fn resize(&mut self, size: PhysicalSize<u32>) {
+        if self.window_ctx.make_context_current().is_err() {
+            return;
+        }
+
+        // Some platforms like EGL require resizing GL surface to update the size.
+        // Notable platforms here are Wayland and macOS, others don't require it
+        // and the function is no-op, but it's wise to resize it for portability
+        // reasons.
+        // Zero sized surface is invalid.
+        self.window_ctx.resize_surface(size);
+        self.render.resize(size);
+        self.window_ctx.request_redraw();
+    }
+
+    #[inline]
+    pub fn request_redraw(&self) {
+        self.window_ctx.request_redraw()
+    }
+
+    /// Window for parenting dialogs.
+    pub(crate) fn window(&self) -> &winit::window::Window {
+        &self.window_ctx.window
+    }
+}
+
+impl Drop for VmWindow {
+    fn drop(&mut self) {
+        // The renderer deletes its OpenGL objects when dropped, which
+        // must happen in its own context instead of the last one used.
+        if let Err(err) = self.window_ctx.make_context_current() {
+            tracing::error!("failed to make OpenGL context current: {err}");
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use std::num::NonZeroU32;
-use std::rc::Rc;
+use std::sync::Arc;
 
 use glow::HasContext;
 use glutin::config::{Config as GlutinConfig, ConfigTemplateBuilder};
@@ -20,7 +20,8 @@ pub struct WindowContext {
     pub(crate) gl_context: glutin::context::PossiblyCurrentContext,
     pub(crate) gl_display: glutin::display::Display,
     pub(crate) gl_surface: glutin::surface::Surface<WindowSurface>,
-    pub(crate) gl: Rc<glow::Context>,
+    /// Shared with the egui painter, which requires an `Arc`.
+    pub(crate) gl: Arc<glow::Context>,
 }
 
 impl WindowContext {
@@ -62,8 +63,8 @@ impl WindowContext {
                 let mut config: Option<GlutinConfig> = None;
 
                 for c in configs {
-                    if log::max_level() >= log::Level::Debug {
-                        log::debug!(
+                    if tracing::enabled!(tracing::Level::DEBUG) {
+                        tracing::debug!(
                             "consider config: num_samples={}, supports_transparency={}",
                             c.num_samples(),
                             c.supports_transparency().unwrap_or(false)
@@ -97,8 +98,8 @@ impl WindowContext {
             })
             .unwrap();
 
-        if log::max_level() >= log::Level::Info {
-            log::info!(
+        if tracing::enabled!(tracing::Level::INFO) {
+            tracing::info!(
                 "picked GL config with {} samples and {} transparency",
                 gl_config.num_samples(),
                 if gl_config.supports_transparency().unwrap_or(false) {
@@ -112,7 +113,7 @@ impl WindowContext {
         // On Android, the window is not available when the OpenGL display has to be created.
         // However, on Windows the main window must first exist before OpenGL can be initialized.
         let window = window.unwrap_or_else(|| {
-            log::info!("creating window with finalize_window");
+            tracing::info!("creating window with finalize_window");
             glutin_winit::finalize_window(event_loop, window_builder.clone(), &gl_config)
                 .expect("failed to finalize window")
         });
@@ -146,7 +147,7 @@ impl WindowContext {
             gl_display
                 .create_context(&gl_config, &context_attributes)
                 .unwrap_or_else(|_| {
-                    log::warn!("falling back to OpenGL ES");
+                    tracing::warn!("falling back to OpenGL ES");
                     gl_display
                         .create_context(&gl_config, &fallback_context_attributes)
                         .expect("failed to create context")
@@ -168,11 +169,11 @@ impl WindowContext {
         let gl_context = not_current_gl_context.make_current(&gl_surface).unwrap();
 
         // Attempt setting VSync
-        log::debug!("attempt to set vsync");
+        tracing::debug!("attempt to set vsync");
         if let Err(err) = gl_surface
             .set_swap_interval(&gl_context, SwapInterval::Wait(NonZeroU32::new(1).unwrap()))
         {
-            log::error!("error setting vsync: {err:?}");
+            tracing::error!("error setting vsync: {err:?}");
         }
 
         // --------------------------------------------------------------------
@@ -188,7 +189,7 @@ impl WindowContext {
         }
 
         let gl = unsafe {
-            Rc::new(glow::Context::from_loader_function_cstr(|symbol| {
+            Arc::new(glow::Context::from_loader_function_cstr(|symbol| {
                 gl_display.get_proc_address(symbol)
             }))
         };
@@ -238,6 +239,11 @@ impl WindowContext {
         self.window.request_redraw()
     }
 
+    #[inline]
+    pub fn set_title(&self, title: &str) {
+        self.window.set_title(title)
+    }
+
     /// Swaps the underlying back buffers when the surface is not single buffered.
     #[inline]
     pub fn swap_buffers(&self) -> glutin::error::Result<()> {
@@ -250,6 +256,28 @@ impl WindowContext {
         self.gl_context.make_current(&self.gl_surface)
     }
 
+    /// Wait for the vertical blank when swapping buffers, or present immediately.
+    ///
+    /// Windows are created with vsync on. Not every platform honours the setting.
+    pub fn set_vsync(&self, vsync: bool) {
+        if let Err(err) = self.make_context_current() {
+            tracing::error!("failed to make OpenGL context current: {err}");
+            return;
+        }
+
+        let interval = if vsync {
+            SwapInterval::Wait(NonZeroU32::new(1).unwrap())
+        } else {
+            SwapInterval::DontWait
+        };
+        if let Err(err) = self
+            .gl_surface
+            .set_swap_interval(&self.gl_context, interval)
+        {
+            tracing::error!("error setting vsync: {err:?}");
+        }
+    }
+
     /// Resize the surface to a new size.
     ///
     /// Does not resize the window.
@@ -276,8 +304,8 @@ impl WindowContext {
 
 fn debug_message_callback(_source: u32, ty: u32, _id: u32, severity: u32, message: &str) {
     if ty == glow::DEBUG_TYPE_ERROR {
-        log::error!("OpenGL error 0x{ty:04x} 0x{severity:x}: {message}");
+        tracing::error!("OpenGL error 0x{ty:04x} 0x{severity:x}: {message}");
     } else {
-        log::warn!("OpenGL 0x{ty:04x} 0x{severity:x}: {message}");
+        tracing::warn!("OpenGL 0x{ty:04x} 0x{severity:x}: {message}");
     }
 }
@@ -0,0 +1,52 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use chip8::prelude::*;
+
+/// Loop touching every group of instructions, so the cost
+/// of dispatching dominates over drawing.
+const INSTRUCTION_MIX: &str = "
+    LD  I, 0x300
+.loop
+    ADD v0, 1
+    LD  v1, v0
+    OR  v1, v2
+    XOR v2, v0
+    SHR v1
+    SUB v1, v2
+    SE  v3, 4
+    SNE v4, v5
+    RAND v6, 0xFF
+    LD  B, v0
+    LD  v2, [I]
+    SKP v7
+    LD  DT, v0
+    CALL .sub
+    JP  .loop
+.sub
+    LD  v8, DT
+    ADD I, v9
+    RET
+";
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let rom = chip8::assemble(INSTRUCTION_MIX).unwrap();
+
+    for (name, decode_cache) in [
+        ("instruction mix", false),
+        ("instruction mix decode cache", true),
+    ] {
+        let mut vm = Chip8Vm::new(Chip8Conf {
+            seed: Some(0),
+            decode_cache,
+            ..Default::default()
+        });
+        vm.load_bytecode(&rom).unwrap();
+
+        c.bench_function(name, |b| {
+            b.iter(|| black_box(vm.run_steps(black_box(1000_usize))))
+        });
+    }
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -16,6 +16,19 @@ fn criterion_benchmark(c: &mut Criterion) {
             })
         });
     }
+
+    {
+        let mut vm = Chip8Vm::new(Chip8Conf {
+            decode_cache: true,
+            ..Default::default()
+        });
+        vm.load_bytecode(include_bytes!("../programs/maze"))
+            .unwrap();
+
+        c.bench_function("maze bytecode decode cache", |b| {
+            b.iter(|| black_box(vm.run_steps(black_box(1000_usize))))
+        });
+    }
 }
 
 criterion_group!(benches, criterion_benchmark);
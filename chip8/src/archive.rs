@@ -0,0 +1,275 @@
+//! ROM archives.
+//!
+//! A single file bundling the bytecode of a program with its descriptor
+//! and debug information, so a ROM can be shared without its sidecar files.
+//!
+//! The archive starts with a header, followed by tagged entries:
+//!
+//! ```text
+//! "C8AR" version:u16
+//! tag:[u8; 4] len:u32 payload:[u8; len]
+//! ...
+//! ```
+//!
+//! Multi-byte values are stored big-endian, like Chip-8 bytecode. The
+//! descriptor and debug information are stored in their text formats.
+//! Entries with unknown tags are skipped, so newer archives stay readable.
+use std::{fs, path::Path};
+
+use crate::{
+    asm::{AsmConf, Assembler, Lexer},
+    constants::MEM_START,
+    debug_info::DebugInfo,
+    error::{Chip8Error, Chip8Result},
+    rom,
+    rom_info::RomInfo,
+};
+
+/// Magic bytes identifying a ROM archive.
+const MAGIC: &[u8; 4] = b"C8AR";
+
+/// Version of the ROM archive binary format.
+///
+/// Must be incremented when the layout changes.
+pub const ARCHIVE_VERSION: u16 = 1;
+
+/// File extension of ROM archives.
+pub const ARCHIVE_EXT: &str = "c8a";
+
+/// Bytecode of the program.
+const TAG_ROM: &[u8; 4] = b"ROM ";
+/// Descriptor of the program, see [`RomInfo`].
+const TAG_INFO: &[u8; 4] = b"INFO";
+/// Debug information emitted by the assembler, see [`DebugInfo`].
+const TAG_DEBUG: &[u8; 4] = b"DBUG";
+
+/// Program bundled with its descriptor and debug information.
+///
+/// ```
+/// # use chip8::{RomArchive, RomInfo};
+/// let archive = RomArchive {
+///     rom: vec![0x12, 0x00],
+///     info: Some(RomInfo::parse("title = \"Loop\"\n").unwrap()),
+///     debug_info: None,
+/// };
+/// let bytes = archive.to_bytes();
+/// assert!(RomArchive::is_archive(&bytes));
+/// assert_eq!(RomArchive::from_bytes(&bytes).unwrap(), archive);
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RomArchive {
+    pub rom: Vec<u8>,
+    pub info: Option<RomInfo>,
+    pub debug_info: Option<DebugInfo>,
+}
+
+impl RomArchive {
+    /// Archive with only the bytecode of the program.
+    pub fn new(rom: &[u8]) -> Self {
+        Self {
+            rom: rom.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    /// Indicates whether the bytes start with the header of an archive.
+    pub fn is_archive(bytes: &[u8]) -> bool {
+        bytes.starts_with(MAGIC)
+    }
+
+    /// Serialize the archive into its binary format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(MAGIC);
+        buf.extend_from_slice(&ARCHIVE_VERSION.to_be_bytes());
+
+        write_entry(&mut buf, TAG_ROM, &self.rom);
+        if let Some(info) = &self.info {
+            write_entry(&mut buf, TAG_INFO, info.to_string().as_bytes());
+        }
+        if let Some(debug_info) = &self.debug_info {
+            write_entry(&mut buf, TAG_DEBUG, debug_info.to_string().as_bytes());
+        }
+
+        buf
+    }
+
+    /// Deserialize an archive from its binary format.
+    pub fn from_bytes(bytes: &[u8]) -> Chip8Result<Self> {
+        let mut reader = ArchiveReader { bytes, cursor: 0 };
+
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            return Err(archive_error("not a chip8 rom archive"));
+        }
+
+        let version = reader.u16()?;
+        if version != ARCHIVE_VERSION {
+            return Err(Chip8Error::Archive(format!(
+                "unsupported archive version {version}, expected {ARCHIVE_VERSION}"
+            )));
+        }
+
+        let mut rom = None;
+        let mut info = None;
+        let mut debug_info = None;
+
+        while reader.cursor < bytes.len() {
+            let tag = reader.bytes(4)?;
+            let len = reader.u32()? as usize;
+            let payload = reader.bytes(len)?;
+
+            match tag {
+                _ if tag == TAG_ROM => rom = Some(payload.to_vec()),
+                _ if tag == TAG_INFO => {
+                    info = Some(RomInfo::parse(&String::from_utf8(payload.to_vec())?)?)
+                }
+                _ if tag == TAG_DEBUG => {
+                    debug_info = Some(DebugInfo::parse(&String::from_utf8(payload.to_vec())?)?)
+                }
+                _ => tracing::debug!(
+                    "skipping unknown archive entry: {}",
+                    String::from_utf8_lossy(tag)
+                ),
+            }
+        }
+
+        Ok(Self {
+            rom: rom.ok_or_else(|| archive_error("missing rom entry"))?,
+            info,
+            debug_info,
+        })
+    }
+
+    /// Load an archive from a file.
+    pub fn load(path: impl AsRef<Path>) -> Chip8Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// Write the archive to a file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Chip8Result<()> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    /// Read a program from any supported file.
+    ///
+    /// Files with the `.asm` extension are assembled, and archives are
+    /// unpacked. All other files are treated as bytecode, without a
+    /// descriptor or debug information, after stripping the headers
+    /// of the containers known by [`rom::load`](crate::rom::load).
+    pub fn read_file(path: impl AsRef<Path>) -> Chip8Result<Self> {
+        Self::read_file_at(path, MEM_START)
+    }
+
+    /// Read a program from any supported file, like [`RomArchive::read_file`],
+    /// assembling source files for the given load address.
+    pub fn read_file_at(path: impl AsRef<Path>, load_address: usize) -> Chip8Result<Self> {
+        let path = path.as_ref();
+        let is_asm = path
+            .extension()
+            .map(|ext| ext.eq_ignore_ascii_case("asm"))
+            .unwrap_or(false);
+
+        if is_asm {
+            let source = String::from_utf8(fs::read(path)?)?;
+            let conf = AsmConf {
+                load_address,
+                ..Default::default()
+            };
+            let (rom, listing) =
+                Assembler::with_conf(Lexer::new(&source), conf).parse_with_listing()?;
+            return Ok(Self {
+                rom,
+                info: None,
+                debug_info: Some(DebugInfo::from(&listing)),
+            });
+        }
+
+        let bytes = fs::read(path)?;
+        if Self::is_archive(&bytes) {
+            Self::from_bytes(&bytes)
+        } else {
+            Ok(Self {
+                rom: rom::strip_header(&bytes, load_address)?,
+                ..Default::default()
+            })
+        }
+    }
+}
+
+fn write_entry(buf: &mut Vec<u8>, tag: &[u8; 4], payload: &[u8]) {
+    buf.extend_from_slice(tag);
+    buf.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    buf.extend_from_slice(payload);
+}
+
+#[inline(never)]
+#[cold]
+fn archive_error(message: &str) -> Chip8Error {
+    Chip8Error::Archive(message.to_string())
+}
+
+/// Cursor over an archive buffer.
+struct ArchiveReader<'a> {
+    bytes: &'a [u8],
+    cursor: usize,
+}
+
+impl<'a> ArchiveReader<'a> {
+    fn bytes(&mut self, count: usize) -> Chip8Result<&'a [u8]> {
+        let bytes = self
+            .cursor
+            .checked_add(count)
+            .and_then(|end| self.bytes.get(self.cursor..end))
+            .ok_or_else(|| archive_error("unexpected end of archive"))?;
+        self.cursor += count;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Chip8Result<u16> {
+        self.bytes(2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Chip8Result<u32> {
+        self.bytes(4)
+            .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_archive_roundtrip() {
+        let archive = RomArchive {
+            rom: vec![0x60, 0x07, 0x12, 0x02],
+            info: Some(RomInfo::parse("title = \"Test\"\nprofile = \"vip\"\n").unwrap()),
+            debug_info: Some(DebugInfo {
+                labels: vec![(0x202, "loop".to_string())],
+                ..Default::default()
+            }),
+        };
+
+        let mut bytes = archive.to_bytes();
+        assert_eq!(RomArchive::from_bytes(&bytes).unwrap(), archive);
+
+        // Unknown entries are skipped.
+        write_entry(&mut bytes, b"NEXT", &[1, 2, 3]);
+        assert_eq!(RomArchive::from_bytes(&bytes).unwrap(), archive);
+
+        // Truncated entries are rejected.
+        bytes.pop();
+        assert!(matches!(
+            RomArchive::from_bytes(&bytes),
+            Err(Chip8Error::Archive(_))
+        ));
+
+        // The rom entry is required.
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&ARCHIVE_VERSION.to_be_bytes());
+        assert!(RomArchive::from_bytes(&bytes).is_err());
+        assert!(RomArchive::from_bytes(&[0x12, 0x00]).is_err());
+    }
+}
@@ -1,5 +1,7 @@
 //! Assembler
-use log::{debug, info, trace};
+use std::collections::HashMap;
+
+use tracing::{debug, info, trace};
 
 use crate::{
     asm::tokens::VReg,
@@ -10,8 +12,12 @@ use crate::{
 
 use super::{
     lexer::Lexer,
+    listing::{Listing, ListingEntry},
+    optimizer::{self, Candidate, OptimizeReport, Rewrite},
     token_stream::TokenStream,
-    tokens::{Addr, Cmp, Keyword as KW, NumFormat, Number, Span, Token, TokenKind as TK},
+    tokens::{
+        Addr, Cmp, Keyword as KW, LabelTerm, NumFormat, Number, Span, Token, TokenKind as TK,
+    },
 };
 
 /// Chip-8 assembler.
@@ -25,6 +31,11 @@ pub struct Assembler<'a> {
     ///
     /// The address is stored as the proper nnn format used in Chip-8.
     labels: Vec<(String, u16)>,
+    /// Symbol table of named numeric constants.
+    ///
+    /// Constants live in a separate namespace from labels, and must
+    /// be defined before they are used.
+    constants: Vec<(String, u16)>,
     /// Record of attempts to access a label that hasn't been defined yet.
     ///
     /// Includes the token (and span) that attempted the access, as well
@@ -38,42 +49,121 @@ pub struct Assembler<'a> {
     defer: Vec<LabelAccess>,
     /// Result buffer of generated bytecode.
     bytecode: Vec<u8>,
+    /// Record of statements that emitted bytecode, for building a [`Listing`].
+    statements: Vec<Statement>,
     /// Collected errors.
     ///
     /// If an error is encountered in a statement, it is pushed onto this container.
     /// Parsing continues to collect further possible errors, but it
     /// has effectively failed the assembling run.
     errors: Vec<Chip8Error>,
+    /// Offsets into the bytecode that were patched with labels defined later.
+    patched: Vec<usize>,
+    /// Changes to statements planned by the optimizer, by the source index
+    /// of their first token.
+    rewrites: HashMap<u32, Rewrite>,
     /// Assembler configuration parameters.
     conf: AsmConf,
 }
 
 /// Assembler configuration parameters.
+#[derive(Debug, Clone)]
 pub struct AsmConf {
-    /// Pad data sections with zero so that the number of bytes are even.
+    /// Pad data sections with zero so that they end on an even address.
     /// Default: `true`
     ///
     /// The Chip-8 interpreter increases the program counter by 2 bytes
     /// every iteration. Thus instructions must be aligned to 2 bytes.
     ///
     /// There are special cases, like loading fonts, which need data to
-    /// be packed even though the byte count will be odd.
+    /// be packed even though the byte count will be odd. Without padding,
+    /// instructions following the data can be aligned with `.align 2`.
     pub pad_data: bool,
+    /// Address the program is loaded at, which labels are relative to.
+    /// Default: [`MEM_START`]
+    pub load_address: usize,
+    /// Remove wasteful instructions with a peephole optimizer.
+    /// Default: `false`
+    ///
+    /// Useful for generated code. See [`OptimizeReport`] for the patterns.
+    pub optimize: bool,
 }
 
 impl Default for AsmConf {
     fn default() -> Self {
-        Self { pad_data: true }
+        Self {
+            pad_data: true,
+            load_address: MEM_START,
+            optimize: false,
+        }
     }
 }
 
+/// Statement that emitted bytecode.
+struct Statement {
+    /// Source of the first token in the statement.
+    span: Span,
+    /// Range of the emitted bytes in the bytecode buffer.
+    start: usize,
+    end: usize,
+    /// The statement emitted data, rather than instructions.
+    data: bool,
+}
+
 /// Access to a label that hasn't been defined yet.
 struct LabelAccess {
-    /// The token where the label was accessed.
-    token: Token,
+    /// The labels accessed, at least one of which isn't defined yet.
+    labels: Vec<LabelTerm>,
     /// Index into the generated bytecode where the placeholder
     /// instruction was emitted.
     offset: usize,
+    /// How the address is encoded in the placeholder.
+    encoding: AddrEncoding,
+    /// Constant added to the label address.
+    addend: i32,
+}
+
+/// Operand expression, evaluated at assemble time.
+struct Expr {
+    /// Source of the whole expression, for error reporting.
+    span: Span,
+    /// Constant value, or offset to the label.
+    value: i32,
+    /// Number format of the leading operand.
+    format: NumFormat,
+    /// Labels that haven't been defined yet, which the value is relative to.
+    labels: Vec<LabelTerm>,
+}
+
+impl Expr {
+    fn num(span: Span, value: i32, format: NumFormat) -> Self {
+        Self {
+            span,
+            value,
+            format,
+            labels: vec![],
+        }
+    }
+}
+
+/// Binding power of binary operators in expressions.
+fn infix_binding_power(kind: TK) -> Option<(u8, u8)> {
+    match kind {
+        TK::Plus | TK::Minus => Some((1, 2)),
+        TK::Star | TK::Slash => Some((3, 4)),
+        _ => None,
+    }
+}
+
+/// Encoding of a label address in the bytecode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AddrEncoding {
+    /// 12-bit address in the lower bits of an instruction.
+    Nnn,
+    /// XO-CHIP 16-bit address trailing an `F000` instruction.
+    Long,
+    /// Whole byte, like the `nn` of an instruction or data.
+    Byte,
 }
 
 macro_rules! debug_assert_match {
@@ -91,51 +181,172 @@ impl<'a> Assembler<'a> {
         Self {
             stream: TokenStream::new(lexer),
             labels: vec![],
+            constants: vec![],
             defer: vec![],
             bytecode: vec![],
+            statements: vec![],
             errors: vec![],
+            patched: vec![],
+            rewrites: HashMap::new(),
             conf,
         }
     }
 
     /// Consume this assembler, as well as the contained lexer, to produce
     /// a buffer of executable Chip8 bytecode.
-    pub fn parse(mut self) -> Chip8Result<Vec<u8>> {
+    pub fn parse(self) -> Chip8Result<Vec<u8>> {
+        let (asm, _) = self.assemble_optimized()?;
+        Ok(asm.bytecode)
+    }
+
+    /// Consume this assembler to produce a buffer of executable Chip8 bytecode,
+    /// along with a listing that maps the bytecode back to the source code.
+    pub fn parse_with_listing(self) -> Chip8Result<(Vec<u8>, Listing)> {
+        let (bytecode, listing, _) = self.parse_with_report()?;
+        Ok((bytecode, listing))
+    }
+
+    /// Consume this assembler to produce a buffer of executable Chip8 bytecode,
+    /// a listing, and a report of the optimizations when [`AsmConf::optimize`] is enabled.
+    pub fn parse_with_report(self) -> Chip8Result<(Vec<u8>, Listing, OptimizeReport)> {
+        let (asm, report) = self.assemble_optimized()?;
+        let listing = asm.build_listing();
+        Ok((asm.bytecode, listing, report))
+    }
+
+    /// Assemble the source code, and again with the rewrites
+    /// planned by the optimizer, when it's enabled.
+    fn assemble_optimized(mut self) -> Chip8Result<(Self, OptimizeReport)> {
+        self.assemble()?;
+        if !self.conf.optimize {
+            return Ok((self, OptimizeReport::default()));
+        }
+
+        let mut report = OptimizeReport {
+            bytes_before: self.bytecode.len(),
+            bytes_after: self.bytecode.len(),
+            ..OptimizeReport::default()
+        };
+        let rewrites = optimizer::plan(&self.candidates(), &mut report);
+        if rewrites.is_empty() {
+            return Ok((self, report));
+        }
+
+        let lexer = Lexer::new(self.stream.source_code());
+        let mut optimized = Self::with_conf(lexer, self.conf.clone());
+        optimized.rewrites = rewrites;
+        optimized.assemble()?;
+        report.bytes_after = optimized.bytecode.len();
+        info!("optimized: {report}");
+
+        Ok((optimized, report))
+    }
+
+    fn assemble(&mut self) -> Chip8Result<()> {
         info!("assembling");
         while let Some(token_kind) = self.stream.peek_kind() {
+            // Track the bytes emitted by each statement for the listing.
+            let start = self.next_offset();
+            let span = self.stream.peek().map(|token| token.span.clone());
+            // Directives after a dot only emit padding.
+            let data = matches!(
+                token_kind,
+                TK::Dot | TK::Number | TK::Keyword(KW::Db | KW::Dw | KW::Sprite)
+            );
+
             match token_kind {
                 TK::Newline => {
                     /* Skip empty line */
                     self.stream.consume(TK::Newline)?;
                     continue;
                 }
-                TK::Dot => self.parse_label()?,
-                TK::Number => self.parse_data_block()?,
+                TK::Dot => self.parse_label().or_else(|err| self.swallow_error(err))?,
+                TK::Number => self
+                    .parse_data_block()
+                    .or_else(|err| self.swallow_error(err))?,
+                TK::Ident => self
+                    .parse_constant()
+                    .or_else(|err| self.swallow_error(err))?,
                 TK::Keyword(_) => self
                     .parse_mnemonic()
                     .or_else(|err| self.swallow_error(err))?,
                 TK::Unknown => {
                     let token = self.stream.next_token().unwrap();
                     let message = format!("unknown token {:?}", token.kind);
-                    return Err(self.error(token, message));
+                    let err = self.error(token, message);
+                    self.swallow_error(err)?
                 }
                 TK::EOF => break,
                 _ => {
                     let token = self.stream.next_token().unwrap();
                     let message = format!("expected opcode, found {:?}", token.kind);
-                    return Err(self.error(token, message));
+                    let err = self.error(token, message);
+                    self.swallow_error(err)?
                 }
             }
+
+            if let Some(rewrite) = span
+                .as_ref()
+                .and_then(|span| self.rewrites.get(&span.index))
+            {
+                self.rewrite(*rewrite, start);
+            }
+
+            let end = self.next_offset();
+            if let Some(span) = span.filter(|_| end > start) {
+                self.statements.push(Statement {
+                    span,
+                    start,
+                    end,
+                    data,
+                });
+            }
         }
 
+        // Labels are patched even when statements failed,
+        // so all errors in the file are reported at once.
+        self.patched = self.defer.iter().map(|access| access.offset).collect();
+        let label_count = self.fix_labels();
+        trace!("fixed {label_count} deferred labels");
+
         if self.has_errors() {
             return Err(Chip8Error::Multi(self.errors.drain(..).collect()));
         }
 
-        let label_count = self.fix_labels()?;
-        trace!("fixed {label_count} deferred labels");
+        Ok(())
+    }
+
+    /// Build a listing from the recorded statements.
+    ///
+    /// Must be called after the label pass, so the listed bytes are final.
+    fn build_listing(&self) -> Listing {
+        let source_code = self.stream.source_code();
+        let mut listing = Listing::default();
+
+        for statement in &self.statements {
+            let address = (self.conf.load_address + statement.start) as u16;
+            let line = source_code[..statement.span.index as usize]
+                .matches('\n')
+                .count()
+                + 1;
+            let (source, _) = statement.span.surrounding_line(source_code);
+            let label = self
+                .labels
+                .iter()
+                .find(|(_, nnn)| *nnn == address)
+                .map(|(name, _)| name.clone());
+
+            listing.push(ListingEntry {
+                address,
+                line,
+                label,
+                source: source.trim_end().to_owned(),
+                bytes: self.bytecode[statement.start..statement.end].to_vec(),
+                data: statement.data,
+            });
+        }
 
-        Ok(self.bytecode)
+        listing
     }
 
     /// Build an assembly error.
@@ -155,7 +366,7 @@ impl<'a> Assembler<'a> {
             .previous_token()
             .map(|t| t.span.clone())
             .unwrap_or_else(|| Span::new(0, 1));
-        log::warn!("previous token: {:?}", self.stream.previous_token());
+        tracing::warn!("previous token: {:?}", self.stream.previous_token());
         let expected = expected.as_ref();
         let message = format!("expected {expected}, but found end-of-file");
         AsmError::new(self.stream.source_code(), span, message).into()
@@ -207,7 +418,7 @@ impl<'a> Assembler<'a> {
         );
 
         // Target address that is being labeled.
-        let address = (MEM_START + self.next_offset()) as u16;
+        let address = (self.conf.load_address + self.next_offset()) as u16;
         let fragment = name.span.fragment(self.stream.source_code()).to_owned();
 
         self.labels.push((fragment, address));
@@ -221,34 +432,88 @@ impl<'a> Assembler<'a> {
             .cloned()
     }
 
+    fn push_constant(&mut self, name: Token, value: u16) -> Chip8Result<()> {
+        debug_assert_eq!(
+            name.kind,
+            TK::Ident,
+            "only identifiers may be used as constant names"
+        );
+
+        let fragment = self.stream.span_fragment(&name.span).to_owned();
+        if self.lookup_constant(&fragment).is_some() {
+            let message = format!("constant '{fragment}' is already defined");
+            return Err(self.error(name, message));
+        }
+
+        self.constants.push((fragment, value));
+        Ok(())
+    }
+
+    fn lookup_constant(&self, name: &str) -> Option<u16> {
+        self.constants
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| *value)
+    }
+
     /// Will store a deferred label access if the label cannot be found.
     ///
     /// IMPORTANT: The caller must emit a bytecode instruction immediately
     ///     after attempting to resolve a label.
-    fn resolve_label(&mut self, label: Token) -> Option<u16> {
-        debug_assert_eq!(
-            label.kind,
-            TK::Label,
+    fn resolve_label(&mut self, labels: Vec<LabelTerm>, addend: i32) -> Option<u16> {
+        self.resolve_label_with(labels, addend, AddrEncoding::Nnn)
+    }
+
+    /// Resolve a label with the given address encoding.
+    ///
+    /// See [`Assembler::resolve_label()`]
+    fn resolve_label_with(
+        &mut self,
+        labels: Vec<LabelTerm>,
+        addend: i32,
+        encoding: AddrEncoding,
+    ) -> Option<u16> {
+        let next_offset = self.next_offset();
+        self.resolve_label_at(labels, addend, encoding, next_offset)
+    }
+
+    /// Resolve a label that is encoded at the given index into the bytecode,
+    /// instead of the next instruction.
+    ///
+    /// See [`Assembler::resolve_label()`]
+    fn resolve_label_at(
+        &mut self,
+        labels: Vec<LabelTerm>,
+        addend: i32,
+        encoding: AddrEncoding,
+        offset: usize,
+    ) -> Option<u16> {
+        debug_assert!(
+            labels.iter().all(|term| term.token.kind == TK::Label),
             "label must be resolved with a label token"
         );
 
-        let name = self.stream.span_fragment(&label.span);
-        let maybe_nnn = self.lookup_label(name);
+        let maybe_value = labels.iter().try_fold(addend, |value, term| {
+            let name = self.stream.span_fragment(&term.token.span);
+            self.lookup_label(name)
+                .map(|nnn| value + nnn as i32 * term.factor)
+        });
 
         // If the label is accessed before it's defined, then
         // the caller is expected to emit a placeholder instruction.
         //
         // The access to the label is stored with enough bookkeeping
         // to replace the instruction later.
-        if maybe_nnn.is_none() {
-            let next_offset = self.next_offset();
+        if maybe_value.is_none() {
             self.defer.push(LabelAccess {
-                token: label,
-                offset: next_offset,
+                labels,
+                offset,
+                encoding,
+                addend,
             });
         }
 
-        maybe_nnn
+        maybe_value.map(|value| value as u16)
     }
 
     /// Emit two bytes as an instruction.
@@ -274,9 +539,9 @@ impl<'a> Assembler<'a> {
         // Instructions are always 2 bytes.
         if self.conf.pad_data {
             println!("bytecode length: {}", self.bytecode.len());
-            // log::warn!("bytecode length: {}", self.bytecode.len());
+            // tracing::warn!("bytecode length: {}", self.bytecode.len());
             assert!(
-                self.bytecode.len() % 2 == 0,
+                self.bytecode.len().is_multiple_of(2),
                 "bytecode length: {}",
                 self.bytecode.len()
             );
@@ -297,7 +562,9 @@ impl<'a> Assembler<'a> {
 impl<'a> Assembler<'a> {
     /// Pass over the generated bytecode to replace placeholder
     /// instructions with the actual address of the defined label.
-    fn fix_labels(&mut self) -> Chip8Result<usize> {
+    ///
+    /// Errors are collected, and the accesses to labels that can't be resolved are skipped.
+    fn fix_labels(&mut self) -> usize {
         info!("patching label addresses");
 
         let mut count = 0;
@@ -312,20 +579,73 @@ impl<'a> Assembler<'a> {
         let deferred_access: Vec<_> = self.defer.drain(..).collect();
 
         for access in deferred_access {
-            debug_assert_eq!(access.token.kind, TK::Label);
+            let mut address = Some(access.addend);
+            for term in &access.labels {
+                debug_assert_eq!(term.token.kind, TK::Label);
+
+                let name = self.stream.span_fragment(&term.token.span);
+                let Some(nnn) = self.lookup_label(name) else {
+                    let message = format!("label '{name}' is undefined");
+                    let mut err =
+                        AsmError::new(self.stream.source_code(), term.token.span.clone(), message);
+                    if let Some(similar) = self.similar_label(name) {
+                        err = err.with_help(format!("did you mean '.{similar}'?"));
+                    }
+                    self.errors.push(err.into());
+                    address = None;
+                    continue;
+                };
+                address = address.map(|address| address + nnn as i32 * term.factor);
+            }
+            let Some(address) = address else {
+                continue;
+            };
 
-            let name = self.stream.span_fragment(&access.token.span);
-            let nnn = self.lookup_label(name).ok_or_else(|| {
-                let message = format!("label '{name}' is undefined");
-                self.error(access.token, message)
-            })?;
+            let max = match access.encoding {
+                AddrEncoding::Nnn => 0xFFF,
+                AddrEncoding::Long => 0xFFFF,
+                AddrEncoding::Byte => 0xFF,
+            };
+            if !(0..=max).contains(&address) {
+                let token = access.labels[0].token.clone();
+                let message = match access.labels.as_slice() {
+                    [term] if access.encoding != AddrEncoding::Byte => {
+                        let name = self.stream.span_fragment(&term.token.span);
+                        format!("address 0x{address:X} of label '{name}' is out of range")
+                    }
+                    _ => format!("value {address} of label expression is out of range 0 to {max}"),
+                };
+                let err = self.error(token, message);
+                self.errors.push(err);
+                continue;
+            }
 
-            self.patch_nnn(access.offset, nnn)?;
+            let patched = match access.encoding {
+                AddrEncoding::Nnn => self.patch_nnn(access.offset, address as u16),
+                AddrEncoding::Long => self.patch_long(access.offset, address as u16),
+                AddrEncoding::Byte => self.patch_byte(access.offset, address as u8),
+            };
+            if let Err(err) = patched {
+                self.errors.push(err);
+                continue;
+            }
 
             count += 1;
         }
 
-        Ok(count)
+        count
+    }
+
+    /// Find a defined label with a name close to the given misspelled name.
+    fn similar_label(&self, name: &str) -> Option<&str> {
+        // Allow about one typo for every three characters.
+        let max_distance = (name.len() / 3).max(1);
+        self.labels
+            .iter()
+            .map(|(label, _)| (label.as_str(), edit_distance(name, label)))
+            .filter(|(_, distance)| *distance <= max_distance)
+            .min_by_key(|(_, distance)| *distance)
+            .map(|(label, _)| label)
     }
 
     /// Replace the placeholder nnn in the instruction at the given index.
@@ -344,6 +664,79 @@ impl<'a> Assembler<'a> {
 
         Ok(())
     }
+
+    /// Replace the placeholder 16-bit address at the given index.
+    fn patch_long(&mut self, index: usize, nnnn: u16) -> Chip8Result<()> {
+        trace!("patch_long: replacing address at {index} with 0x{nnnn:X}");
+        assert!(
+            index + 1 < self.bytecode.len(),
+            "out-of-range attempt to patch bytecode"
+        );
+
+        self.bytecode[index] = (nnnn >> 8) as u8;
+        self.bytecode[index + 1] = (nnnn & 0b11111111) as u8;
+
+        Ok(())
+    }
+
+    /// Replace the placeholder byte at the given index.
+    fn patch_byte(&mut self, index: usize, nn: u8) -> Chip8Result<()> {
+        trace!("patch_byte: replacing byte at {index} with 0x{nn:X}");
+        assert!(
+            index < self.bytecode.len(),
+            "out-of-range attempt to patch bytecode"
+        );
+
+        self.bytecode[index] = nn;
+
+        Ok(())
+    }
+}
+
+/// Peephole optimization pass.
+///
+/// See [`optimizer`] for how the rewrites are planned.
+impl<'a> Assembler<'a> {
+    /// Statements of the first pass, for the optimizer to plan rewrites.
+    fn candidates(&self) -> Vec<Candidate> {
+        let load_address = self.conf.load_address;
+        self.statements
+            .iter()
+            .map(|statement| {
+                let address = load_address + statement.start;
+                let instr = (!statement.data && statement.end - statement.start == 2).then(|| {
+                    [
+                        self.bytecode[statement.start],
+                        self.bytecode[statement.start + 1],
+                    ]
+                });
+                Candidate {
+                    key: statement.span.index,
+                    start: address,
+                    end: load_address + statement.end,
+                    instr,
+                    labelled: self.labels.iter().any(|(_, nnn)| *nnn as usize == address),
+                    patched: self
+                        .patched
+                        .iter()
+                        .any(|offset| (statement.start..statement.end).contains(offset)),
+                }
+            })
+            .collect()
+    }
+
+    /// Apply the rewrite to the statement that was just parsed, starting at the given offset.
+    fn rewrite(&mut self, rewrite: Rewrite, start: usize) {
+        trace!("rewrite: {rewrite:?} at {start}");
+        if rewrite == Rewrite::MergeAdd {
+            // 7xnn (ADD Vx, byte) directly follows the one it merges into.
+            let nn = self.bytecode[start + 1];
+            self.bytecode[start - 1] = self.bytecode[start - 1].wrapping_add(nn);
+        }
+
+        self.bytecode.truncate(start);
+        self.defer.retain(|access| access.offset < start);
+    }
 }
 
 /// Parsing functions.
@@ -391,31 +784,7 @@ impl<'a> Assembler<'a> {
             .next_token()
             .ok_or_else(|| self.eof_error("an address as either a number literal or label"))?;
 
-        match token.kind {
-            TK::Number => {
-                let number = self.parse_number(token)?;
-
-                Ok(Addr::Num(number))
-            }
-            TK::Dot => {
-                let ident = self.stream.consume(TK::Ident)?;
-
-                // Transform the identifier into a label for ease of use.
-                // Technically the grammar is now no longer context-free.
-                let label = Token {
-                    kind: TK::Label,
-                    // FIXME: merging spans breaks label lookup later.
-                    // span: nnn.span + ident.span,
-                    span: ident.span,
-                };
-
-                Ok(Addr::Label(label))
-            }
-            _ => {
-                let kind = token.kind;
-                Err(self.error(token, format!("expected an address as either a number literal or label, but found {kind:?}")))
-            }
-        }
+        self.parse_expr(token)
     }
 
     /// Parse two arguments.
@@ -449,13 +818,15 @@ impl<'a> Assembler<'a> {
         }
 
         let _comma = self.stream.consume(TK::Comma)?;
-        let src = self.stream.next_token().ok_or_else(|| Chip8Error::EOF)?;
+        let src = self.stream.next_token().ok_or(Chip8Error::EOF)?;
 
         match src.kind {
-            TK::Number | TK::Keyword(_) | TK::Register(_) => Ok([dst, src]),
+            TK::Number | TK::Ident | TK::LeftParen | TK::Keyword(_) | TK::Register(_) => {
+                Ok([dst, src])
+            }
             // Label
             TK::Dot => {
-                let ident = self.stream.consume(TK::Ident)?;
+                let ident = self.consume_label_name()?;
 
                 // Transform the identifier into a label for ease of use.
                 // Technically the grammar is now no longer context-free.
@@ -490,13 +861,14 @@ impl<'a> Assembler<'a> {
         let vx = self
             .stream
             .next_token()
-            .ok_or_else(|| Chip8Error::EOF)
+            .ok_or(Chip8Error::EOF)
             .and_then(|t| self.parse_vregister(t))?;
         let _comma = self.stream.consume(TK::Comma)?;
         let nn = self
             .stream
-            .consume(TK::Number)
-            .and_then(|t| self.parse_number(t))?;
+            .next_token()
+            .ok_or_else(|| self.eof_error("number literal or constant"))
+            .and_then(|t| self.parse_byte(t, self.next_offset() + 1))?;
 
         Ok((vx, nn))
     }
@@ -505,18 +877,19 @@ impl<'a> Assembler<'a> {
         let vx = self
             .stream
             .next_token()
-            .ok_or_else(|| Chip8Error::EOF)
+            .ok_or(Chip8Error::EOF)
             .and_then(|t| self.parse_vregister(t))?;
         let _comma = self.stream.consume(TK::Comma)?;
         let vy = self
             .stream
             .next_token()
-            .ok_or_else(|| Chip8Error::EOF)
+            .ok_or(Chip8Error::EOF)
             .and_then(|t| self.parse_vregister(t))?;
         let _comma = self.stream.consume(TK::Comma)?;
         let n = self
             .stream
-            .consume(TK::Number)
+            .next_token()
+            .ok_or_else(|| self.eof_error("number literal or constant"))
             .and_then(|t| self.parse_number(t))?;
 
         Ok((vx, vy, n))
@@ -537,13 +910,250 @@ impl<'a> Assembler<'a> {
         }
     }
 
-    /// Parse a number literal.
+    /// Parse a constant expression, starting with the given operand token.
+    ///
+    /// See [`Assembler::parse_expr()`]
+    fn parse_number(&mut self, token: Token) -> Chip8Result<Number> {
+        trace!("parse_number");
+
+        match self.parse_expr(token)? {
+            Addr::Num(number) => Ok(number),
+            Addr::Label { labels, .. } => {
+                let token = labels[0].token.clone();
+                let name = self.stream.span_fragment(&token.span);
+                let message = format!(
+                    "label '{name}' must be defined before it's used in a constant expression"
+                );
+                Err(self.error(token, message))
+            }
+        }
+    }
+
+    /// Parse the byte operand of an instruction or data, encoded at
+    /// the given index into the bytecode.
+    ///
+    /// Unlike constant expressions, bytes can refer to labels that are
+    /// defined later, like the size of a block of data:
+    ///
+    /// ```asm
+    /// LD v0, .sprite_end - .sprite_start
+    /// ```
+    ///
+    /// Values above 0xFF are errors, whether they're known now, or
+    /// only once the labels are patched.
+    fn parse_byte(&mut self, token: Token, index: usize) -> Chip8Result<Number> {
+        trace!("parse_byte");
+
+        match self.parse_expr(token)? {
+            Addr::Num(number) if number.value > 0xFF => {
+                Err(self.error(number.token, "byte must be 255 or less (<= 0xFF)"))
+            }
+            Addr::Num(number) => Ok(number),
+            Addr::Label { labels, offset } => {
+                let token = Token {
+                    kind: TK::Number,
+                    span: labels[0].token.span.clone(),
+                };
+                // NOTE: If a label is not defined yet, 0x00 is inserted as a placeholder.
+                //       Error handling is in the fix_labels pass.
+                let value = self
+                    .resolve_label_at(labels, offset, AddrEncoding::Byte, index)
+                    .unwrap_or_default();
+                Ok(Number {
+                    token,
+                    value,
+                    format: NumFormat::Hex,
+                })
+            }
+        }
+    }
+
+    /// Parse an operand expression, starting with the given operand token.
+    ///
+    /// Operands are number literals, named constants, or labels, which
+    /// can be combined with `+`, `-`, `*`, `/` and grouped with parentheses.
+    /// Expressions are evaluated at assemble time.
     ///
-    /// Numbers can be decimal, binary or hexadecimal.
-    fn parse_number(&self, token: Token) -> Chip8Result<Number> {
+    /// ```asm
+    /// BASE   EQU 0x300
+    /// define HEIGHT 5
+    ///
+    /// LD I,  BASE + 4
+    /// LD v0, (HEIGHT - 1) * 2
+    /// LD I,  .sprites + HEIGHT
+    /// LD v1, .sprites_end - .sprites
+    /// ```
+    ///
+    /// Labels that are not defined yet can only be added or subtracted,
+    /// and are patched in the [`Assembler::fix_labels()`] pass.
+    fn parse_expr(&mut self, token: Token) -> Chip8Result<Addr> {
+        trace!("parse_expr");
+
+        let expr = self.parse_expr_bp(token, 0)?;
+        let token = Token {
+            kind: TK::Number,
+            span: expr.span,
+        };
+
+        if !expr.labels.is_empty() {
+            return Ok(Addr::Label {
+                labels: expr.labels,
+                offset: expr.value,
+            });
+        }
+
+        match u16::try_from(expr.value) {
+            Ok(value) => Ok(Addr::Num(Number {
+                token,
+                value,
+                format: expr.format,
+            })),
+            Err(_) => Err(self.error(token, "expression is out of range for 16-bits")),
+        }
+    }
+
+    /// Pratt parser for operand expressions.
+    fn parse_expr_bp(&mut self, token: Token, min_bp: u8) -> Chip8Result<Expr> {
+        let mut lhs = self.parse_operand(token)?;
+
+        while let Some(op) = self.stream.peek_kind() {
+            let (left_bp, right_bp) = match infix_binding_power(op) {
+                Some(bp) => bp,
+                None => break,
+            };
+            if left_bp < min_bp {
+                break;
+            }
+
+            let op = self.stream.next_token().unwrap();
+            let token = self
+                .stream
+                .next_token()
+                .ok_or_else(|| self.eof_error("number literal, constant or label"))?;
+            let rhs = self.parse_expr_bp(token, right_bp)?;
+
+            lhs = self.eval_binary(op, lhs, rhs)?;
+        }
+
+        Ok(lhs)
+    }
+
+    /// Parse a single operand of an expression.
+    fn parse_operand(&mut self, token: Token) -> Chip8Result<Expr> {
+        match token.kind {
+            TK::Number => {
+                let number = self.parse_number_literal(token)?;
+                Ok(Expr::num(
+                    number.token.span,
+                    number.value as i32,
+                    number.format,
+                ))
+            }
+            TK::Ident => {
+                let name = self.stream.span_fragment(&token.span);
+                match self.lookup_constant(name) {
+                    Some(value) => Ok(Expr::num(token.span, value as i32, NumFormat::Dec)),
+                    None => {
+                        let message = format!("constant '{name}' is undefined");
+                        Err(self.error(token, message))
+                    }
+                }
+            }
+            TK::Dot => {
+                let ident = self.consume_label_name()?;
+
+                // Transform the identifier into a label for ease of use.
+                // Technically the grammar is now no longer context-free.
+                let label = Token {
+                    kind: TK::Label,
+                    // FIXME: merging spans breaks label lookup later.
+                    // span: nnn.span + ident.span,
+                    span: ident.span,
+                };
+
+                self.parse_operand(label)
+            }
+            TK::Label => {
+                let name = self.stream.span_fragment(&token.span);
+
+                // Labels that are already defined are folded into the value,
+                // so only forward declared labels have to be patched later.
+                match self.lookup_label(name) {
+                    Some(address) => Ok(Expr::num(token.span, address as i32, NumFormat::Hex)),
+                    None => Ok(Expr {
+                        span: token.span.clone(),
+                        value: 0,
+                        format: NumFormat::Hex,
+                        labels: vec![LabelTerm { token, factor: 1 }],
+                    }),
+                }
+            }
+            TK::LeftParen => {
+                let token = self
+                    .stream
+                    .next_token()
+                    .ok_or_else(|| self.eof_error("number literal, constant or label"))?;
+                let mut expr = self.parse_expr_bp(token, 0)?;
+                let right_paren = self.stream.consume(TK::RightParen)?;
+                expr.span = expr.span.merge(&right_paren.span);
+                Ok(expr)
+            }
+            _ => {
+                let kind = token.kind;
+                let message =
+                    format!("expected number literal, constant or label, but found {kind:?}");
+                Err(self.error(token, message))
+            }
+        }
+    }
+
+    /// Evaluate a binary operation of an expression.
+    fn eval_binary(&self, op: Token, lhs: Expr, rhs: Expr) -> Chip8Result<Expr> {
+        let span = lhs.span.merge(&rhs.span);
+
+        // A forward declared label is an unknown address, so it can only
+        // be added to or subtracted from constants and other labels.
+        let labels = match op.kind {
+            _ if lhs.labels.is_empty() && rhs.labels.is_empty() => vec![],
+            TK::Plus => [lhs.labels, rhs.labels].concat(),
+            TK::Minus => {
+                let negated = rhs.labels.into_iter().map(|term| LabelTerm {
+                    factor: -term.factor,
+                    ..term
+                });
+                lhs.labels.into_iter().chain(negated).collect()
+            }
+            _ => {
+                let message = "labels that are not defined yet can only be added or subtracted";
+                return Err(self.error(op, message));
+            }
+        };
+
+        let value = match op.kind {
+            TK::Plus => lhs.value.checked_add(rhs.value),
+            TK::Minus => lhs.value.checked_sub(rhs.value),
+            TK::Star => lhs.value.checked_mul(rhs.value),
+            TK::Slash => lhs.value.checked_div(rhs.value),
+            _ => unreachable!("unexpected binary operator {:?}", op.kind),
+        };
+        let value = match value {
+            Some(value) => value,
+            None if op.kind == TK::Slash => return Err(self.error(op, "division by zero")),
+            None => return Err(self.error(op, "expression is out of range")),
+        };
+
+        Ok(Expr {
+            span,
+            value,
+            format: lhs.format,
+            labels,
+        })
+    }
+
+    fn parse_number_literal(&self, token: Token) -> Chip8Result<Number> {
         use NumFormat as NF;
 
-        trace!("parse_number");
+        trace!("parse_number_literal");
         debug_assert_match!(token.kind, TK::Number);
 
         let fragment = self.stream.span_fragment(&token.span);
@@ -572,6 +1182,28 @@ impl<'a> Assembler<'a> {
         })
     }
 
+    /// Consume the name of a label following a dot.
+    ///
+    /// Keywords are allowed as label names, because the dot
+    /// already marks the name as a label.
+    fn consume_label_name(&mut self) -> Chip8Result<Token> {
+        let token = self
+            .stream
+            .next_token()
+            .ok_or_else(|| self.eof_error("label name"))?;
+
+        match token.kind {
+            TK::Ident | TK::Keyword(_) => Ok(Token {
+                kind: TK::Ident,
+                span: token.span,
+            }),
+            _ => {
+                let kind = token.kind;
+                Err(self.error(token, format!("expected label name, but found {kind:?}")))
+            }
+        }
+    }
+
     /// Parse a label identifier.
     ///
     /// Labels start with a dot.
@@ -580,16 +1212,31 @@ impl<'a> Assembler<'a> {
     /// .foobar
     ///     ...
     /// ```
+    ///
+    /// The `.align` and `.org` directives share the syntax, and are told
+    /// apart from labels by their argument.
     fn parse_label(&mut self) -> Chip8Result<()> {
         trace!("parse_label");
         debug_assert_match!(self.stream.peek_kind(), Some(TK::Dot));
 
         let _dot = self.stream.consume(TK::Dot)?;
-        let name = self.stream.consume(TK::Ident)?;
+        let name = self.consume_label_name()?;
         if name.kind != TK::Ident {
             return Err(self.error(name, "expected label name"));
         }
 
+        let is_eos = self
+            .stream
+            .peek_kind()
+            .is_none_or(|kind| Self::STATEMENT_END.contains(&kind));
+        if !is_eos {
+            match self.stream.span_fragment(&name.span) {
+                "align" => return self.parse_align(),
+                "org" => return self.parse_org(),
+                _ => {}
+            }
+        }
+
         self.consume_eos()?;
 
         self.push_label(&name);
@@ -597,12 +1244,105 @@ impl<'a> Assembler<'a> {
         Ok(())
     }
 
-    /// Emit raw data into bytecode.
-    fn parse_data_block(&mut self) -> Chip8Result<()> {
-        trace!("parse data block");
-        if self.conf.pad_data {
-            assert!(self.bytecode.len() % 2 == 0);
-        }
+    /// Pad with zeros up to the next address that is a multiple of the alignment.
+    ///
+    /// ```asm
+    /// .align 2
+    /// ```
+    fn parse_align(&mut self) -> Chip8Result<()> {
+        trace!("parse_align");
+
+        let alignment = self
+            .stream
+            .next_token()
+            .ok_or_else(|| self.eof_error("number literal or constant"))
+            .and_then(|t| self.parse_number(t))?;
+        if alignment.value == 0 {
+            return Err(self.error(alignment.token, "alignment must be greater than zero"));
+        }
+        self.consume_eos()?;
+
+        self.align(alignment.value as usize);
+
+        Ok(())
+    }
+
+    /// Pad with zeros up to the given address.
+    ///
+    /// The address can't be behind the current one, since the
+    /// code that was already emitted would be overwritten.
+    ///
+    /// ```asm
+    /// .org 0x300
+    /// ```
+    fn parse_org(&mut self) -> Chip8Result<()> {
+        trace!("parse_org");
+
+        let target = self
+            .stream
+            .next_token()
+            .ok_or_else(|| self.eof_error("an address as a number literal or constant"))
+            .and_then(|t| self.parse_number(t))?;
+        let address = self.conf.load_address + self.next_offset();
+        if (target.value as usize) < address {
+            let message = format!(
+                "origin 0x{:03X} is behind the current address 0x{address:03X}, and would overlap the code before it",
+                target.value
+            );
+            return Err(self.error(target.token, message));
+        }
+        self.consume_eos()?;
+
+        let padding = target.value as usize - address;
+        self.bytecode.resize(self.bytecode.len() + padding, 0);
+
+        Ok(())
+    }
+
+    /// Parse a constant definition.
+    ///
+    /// ```asm
+    /// SPRITE_HEIGHT EQU 5
+    /// ```
+    fn parse_constant(&mut self) -> Chip8Result<()> {
+        trace!("parse_constant");
+        debug_assert_match!(self.stream.peek_kind(), Some(TK::Ident));
+
+        let name = self.stream.consume(TK::Ident)?;
+        let _equ = self.stream.consume(TK::Keyword(KW::Equ))?;
+        let value = self
+            .stream
+            .next_token()
+            .ok_or_else(|| self.eof_error("number literal or constant"))
+            .and_then(|t| self.parse_number(t))?;
+        self.consume_eos()?;
+
+        self.push_constant(name, value.value)
+    }
+
+    /// Parse a constant definition.
+    ///
+    /// ```asm
+    /// define SPRITE_HEIGHT 5
+    /// ```
+    fn parse_define(&mut self, name: Token) -> Chip8Result<()> {
+        trace!("parse_define");
+        debug_assert_eq!(name.kind, TK::Keyword(KW::Define));
+
+        let name = self.stream.consume(TK::Ident)?;
+        let value = self
+            .stream
+            .next_token()
+            .ok_or_else(|| self.eof_error("number literal or constant"))
+            .and_then(|t| self.parse_number(t))?;
+        self.consume_eos()?;
+
+        self.push_constant(name, value.value)
+    }
+
+    /// Emit raw data into bytecode.
+    fn parse_data_block(&mut self) -> Chip8Result<()> {
+        trace!("parse data block");
 
         let mut count = 0;
 
@@ -610,7 +1350,7 @@ impl<'a> Assembler<'a> {
             let token = self.stream.consume(TK::Number)?;
             let nn = self.parse_number(token)?;
             if nn.value > u8::MAX as u16 {
-                panic!("only 8-bit literals are currently supported");
+                return Err(self.error(nn.token, "byte must be 255 or less (<= 0xFF)"));
             }
             self.emit(nn.value as u8);
             count += 1;
@@ -623,10 +1363,7 @@ impl<'a> Assembler<'a> {
         trace!("data count: {count}");
 
         // Stride of bytecode must be 2 for program counter to increment correctly.
-        if self.conf.pad_data && count % 2 != 0 {
-            // Pad with an unused zero
-            self.emit(0);
-        }
+        self.pad_data();
 
         Ok(())
     }
@@ -636,7 +1373,7 @@ impl<'a> Assembler<'a> {
         trace!("parse mnemonic");
         debug_assert_match!(self.stream.peek_kind(), Some(TK::Keyword(_)));
 
-        let name = self.stream.next_token().ok_or_else(|| Chip8Error::EOF)?;
+        let name = self.stream.next_token().ok_or(Chip8Error::EOF)?;
 
         if let TK::Keyword(keyword) = name.kind {
             match keyword {
@@ -658,7 +1395,15 @@ impl<'a> Assembler<'a> {
                 KW::ShiftRight => self.parse_arithmetic_shr(name)?,
                 KW::Sub    => self.parse_arithmetic_sub(name)?,
                 KW::SubN   => self.parse_arithmetic_subn(name)?,
+                KW::System => self.parse_system(name)?,
                 KW::Xor    => self.parse_arithmetic_xor(name)?,
+                KW::Plane  => self.parse_plane(name)?,
+                KW::Audio  => self.parse_audio(name)?,
+                KW::Pitch  => self.parse_pitch(name)?,
+                KW::Define => self.parse_define(name)?,
+                KW::Db     => self.parse_db(name)?,
+                KW::Dw     => self.parse_dw(name)?,
+                KW::Sprite => self.parse_sprite(name)?,
                 _ => {
                     let fragment = self.stream.span_fragment(&name.span);
                     return Err(self.error(name, format!("unsupported opcode {:?}", fragment)));
@@ -685,15 +1430,54 @@ impl<'a> Assembler<'a> {
         Ok(())
     }
 
+    /// Parse System
+    ///
+    /// 0nnn (SYS addr)
+    fn parse_system(&mut self, name: Token) -> Chip8Result<()> {
+        trace!("parse_system");
+        debug_assert_eq!(name.kind, TK::Keyword(KW::System));
+
+        let nnn = self.parse_nnn()?;
+
+        match nnn {
+            Addr::Num(number) => {
+                if number.value > 0xFFF {
+                    return Err(
+                        self.error(number.token, "argument for system address must be 12-bits")
+                    );
+                }
+                self.emit2(encode_nnn(SYS_ADDR, number.value));
+            }
+            Addr::Label { labels, offset } => {
+                // NOTE: If label is not defined yet,address 0x000 is inserted as a placeholder.
+                //       Error handling is in the fix_labels pass.
+                let number = self.resolve_label(labels, offset).unwrap_or_default() & 0xFFF;
+                self.emit2(encode_nnn(SYS_ADDR, number));
+            }
+        }
+
+        self.consume_eos()?;
+
+        Ok(())
+    }
+
     /// Parse Jump
     ///
     /// 1nnn (JP addr)
     /// Bnnn (JP V0, addr)
+    /// Bxnn (JP Vx, addr)
+    ///
+    /// The SUPER-CHIP form with a register other than V0 is only accepted
+    /// with a literal address, whose high nibble must select the register,
+    /// because both forms share the same encoding.
     fn parse_jump(&mut self, name: Token) -> Chip8Result<()> {
         trace!("parse_jump");
         debug_assert_eq!(name.kind, TK::Keyword(KW::Jump));
 
-        // Jump can optionally take the V0 register as an offset.
+        // Register other than V0 used as the offset.
+        let mut offset_register = None;
+
+        // Jump can optionally take a register as an offset.
         let opcode: u8 = {
             match self
                 .stream
@@ -708,9 +1492,9 @@ impl<'a> Assembler<'a> {
                 TK::Register(other) => {
                     // V1-VF
                     let token = self.stream.next_token().unwrap();
-                    let message =
-                        format!("only register v0 is supported as a jump offset, not {other}");
-                    return Err(self.error(token, message));
+                    let _comma = self.stream.consume(TK::Comma)?;
+                    offset_register = Some((token, other));
+                    JP_V0_ADDR
                 }
                 _ => JP_ADDR,
             }
@@ -725,12 +1509,27 @@ impl<'a> Assembler<'a> {
                         self.error(number.token, "argument for jump address must be 12-bits")
                     );
                 }
+                if let Some((token, register)) = offset_register {
+                    if (number.value >> 8) as u8 != register.as_index() {
+                        let message = format!(
+                            "high nibble of the jump address 0x{:03X} must select the offset register {register}",
+                            number.value
+                        );
+                        return Err(self.error(token, message));
+                    }
+                }
                 self.emit2(encode_nnn(opcode, number.value));
             }
-            Addr::Label(label) => {
+            Addr::Label { labels, offset } => {
+                if let Some((register_token, register)) = offset_register {
+                    let message = format!(
+                        "jump with offset register {register} requires a literal address, not a label"
+                    );
+                    return Err(self.error(register_token, message));
+                }
                 // NOTE: If label is not defined yet,address 0x000 is inserted as a placeholder.
                 //       Error handling is in the fix_labels pass.
-                let number = self.resolve_label(label).unwrap_or_default() & 0xFFF;
+                let number = self.resolve_label(labels, offset).unwrap_or_default() & 0xFFF;
                 self.emit2(encode_nnn(opcode, number));
             }
         }
@@ -755,10 +1554,10 @@ impl<'a> Assembler<'a> {
                 }
                 self.emit2(encode_nnn(CALL_ADDR, number.value));
             }
-            Addr::Label(label) => {
+            Addr::Label { labels, offset } => {
                 // NOTE: If label is not defined yet,address 0x000 is inserted as a placeholder.
                 //       Error handling is in the fix_labels pass.
-                let number = self.resolve_label(label).unwrap_or_default() & 0xFFF;
+                let number = self.resolve_label(labels, offset).unwrap_or_default() & 0xFFF;
                 self.emit2(encode_nnn(CALL_ADDR, number));
             }
         }
@@ -781,9 +1580,9 @@ impl<'a> Assembler<'a> {
         match signature {
             // 3xnn (SE Vx, byte)
             // 4xnn (SNE Vx, byte)
-            [TK::Register(vx), TK::Number] => {
+            [TK::Register(vx), TK::Number | TK::Ident | TK::LeftParen | TK::Label] => {
                 let vx = vx.as_index();
-                let nn = self.parse_number(rhs)?;
+                let nn = self.parse_byte(rhs, self.next_offset() + 1)?;
                 let opcode = match cmp {
                     Cmp::Eq => SE_VX_NN,
                     Cmp::NotEq => SNE_VX_NN,
@@ -843,6 +1642,8 @@ impl<'a> Assembler<'a> {
             Cmp::NotEq => self.emit2(encode_xnn(SKNP_VX[0], vx, SKNP_VX[1])),
         }
 
+        self.consume_eos()?;
+
         Ok(())
     }
 
@@ -851,19 +1652,20 @@ impl<'a> Assembler<'a> {
     /// - `6xnn (LD Vx,  byte)`
     /// - `8xy0 (LD Vx, byte)`
     /// - `Annn (LD I,   addr)`
+    /// - `F000 nnnn (LD I, LONG addr)`
     /// - `Fx07 (LD Vx,  DT)`
     /// - `Fx0A (LD Vx,  K)`
     /// - `Fx15 (LD DT,  Vx)`
     /// - `Fx18 (LD ST,  Vx)`
     /// - `Fx29 (LD F,   Vx)`
-    /// - `Fx33 (LD B,   Vx)`
+    /// - `Fx33 (LD B,   Vx)`, or `LD BCD, Vx`
     /// - `Fx55 (LD [I], Vx)`
     /// - `Fx65 (LD Vx,  [I])`
     fn parse_load(&mut self, name: Token) -> Chip8Result<()> {
         trace!("parse_load");
         debug_assert_eq!(name.kind, TK::Keyword(KW::Load));
 
-        // let dst = self.stream.next_token().ok_or_else(|| Chip8Error::EOF)?;
+        // let dst = self.stream.next_token().ok_or(Chip8Error::EOF)?;
         let [dst, src] = self.parse_arg2()?;
 
         let signature = [dst.kind, src.kind];
@@ -872,9 +1674,9 @@ impl<'a> Assembler<'a> {
             // 6xnn (LD Vx, byte)
             //
             // Load byte literal into Vx register
-            [TK::Register(vx), TK::Number] => {
+            [TK::Register(vx), TK::Number | TK::Ident | TK::LeftParen | TK::Label] => {
                 let vx = vx.as_index();
-                let nn = self.parse_number(src)?;
+                let nn = self.parse_byte(src, self.next_offset() + 1)?;
                 self.emit2(encode_xnn(LD_VX_NN, vx, nn.as_u8()))
             }
             // 8xy0 (LD Vx, byte)
@@ -884,19 +1686,43 @@ impl<'a> Assembler<'a> {
                 self.emit2(encode_xyn(LD_VX_VY[0], vx, vy, LD_VX_VY[1]));
             }
             // Annn (LD I, addr)
+            // Annn (LD I, label)
             //
             // Load memory address into index register.
-            [TK::Keyword(KW::Index), TK::Number] => {
-                let nnn = self.parse_number(src)?;
-                self.emit2(encode_nnn(LD_I_NNN, nnn.value));
+            [TK::Keyword(KW::Index), TK::Number | TK::Ident | TK::LeftParen | TK::Label] => {
+                match self.parse_expr(src)? {
+                    Addr::Num(number) => {
+                        if number.value > 0xFFF {
+                            return Err(self
+                                .error(number.token, "argument for load address must be 12-bits"));
+                        }
+                        self.emit2(encode_nnn(LD_I_NNN, number.value));
+                    }
+                    Addr::Label { labels, offset } => {
+                        // NOTE: If label is not defined yet, we default to 0x000
+                        let nnn = self.resolve_label(labels, offset).unwrap_or_default() & 0xFFF;
+                        self.emit2(encode_nnn(LD_I_NNN, nnn));
+                    }
+                }
             }
-            // Annn (LD I, label)
+            // F000 nnnn (LD I, LONG addr)
             //
-            // Load memory address into index register.
-            [TK::Keyword(KW::Index), TK::Label] => {
-                // NOTE: If label is not defined yet, we default to 0x000
-                let nnn = self.resolve_label(src).unwrap_or_default() & 0xFFF;
-                self.emit2(encode_nnn(LD_I_NNN, nnn));
+            // XO-CHIP: Load 16-bit memory address into index register.
+            [TK::Keyword(KW::Index), TK::Keyword(KW::Long)] => {
+                self.emit2(LD_I_LONG);
+
+                match self.parse_nnn()? {
+                    Addr::Num(number) => {
+                        self.emit2([(number.value >> 8) as u8, number.value as u8]);
+                    }
+                    Addr::Label { labels, offset } => {
+                        // NOTE: If label is not defined yet, we default to 0x0000
+                        let nnnn = self
+                            .resolve_label_with(labels, offset, AddrEncoding::Long)
+                            .unwrap_or_default();
+                        self.emit2([(nnnn >> 8) as u8, nnnn as u8]);
+                    }
+                }
             }
             // Fx07 (LD Vx,  DT)
             //
@@ -933,7 +1759,7 @@ impl<'a> Assembler<'a> {
                 let vx = vx.as_index();
                 self.emit2(encode_xnn(LD_F_VX[0], vx, LD_F_VX[1]));
             }
-            // Fx33 (LD BCD, Vx)
+            // Fx33 (LD B, Vx)
             //
             // Store BCD representation of Vx in memory locations I, I+1, and I+2
             [TK::Keyword(KW::Decimal), TK::Register(vx)] => {
@@ -954,15 +1780,34 @@ impl<'a> Assembler<'a> {
                 let vx = vx.as_index();
                 self.emit2(encode_xnn(LD_VX_ARR[0], vx, LD_VX_ARR[1]));
             }
-            [TK::Register(vx), _] => {
-                let message = format!("expected byte literal, but found {vx}");
+            [TK::Register(_), _] => {
+                let message = format!(
+                    "expected byte literal, register, 'DT', 'K' or '[I]', but found {:?}",
+                    src.kind
+                );
                 return Err(self.error(src, message));
             }
-            [TK::Keyword(keyword), _] => {
-                let message =
-                    format!("expected address, label, register 'DT' or 'K', but found {keyword}");
+            [TK::Keyword(KW::Index), _] => {
+                let message = format!(
+                    "expected address, label or 'LONG' address, but found {:?}",
+                    src.kind
+                );
                 return Err(self.error(src, message));
             }
+            [TK::Keyword(keyword @ (KW::Delay | KW::Sound | KW::Char | KW::Decimal | KW::Array)), _] =>
+            {
+                let message = format!(
+                    "expected register after '{keyword}', but found {:?}",
+                    src.kind
+                );
+                return Err(self.error(src, message));
+            }
+            [TK::Keyword(keyword), _] => {
+                let message = format!(
+                    "expected register, 'I', 'DT', 'ST', 'F', 'B' or '[I]', but found {keyword}"
+                );
+                return Err(self.error(dst, message));
+            }
             _ => {
                 let message = format!(
                     "unsupported arguments, found {:?}, {:?}",
@@ -988,9 +1833,9 @@ impl<'a> Assembler<'a> {
         let signature = [lhs.kind, rhs.kind];
         match signature {
             // 7xnn (ADD Vx, byte)
-            [TK::Register(vx), TK::Number] => {
+            [TK::Register(vx), TK::Number | TK::Ident | TK::LeftParen | TK::Label] => {
                 let vx = vx.as_index();
-                let nn = self.parse_number(rhs)?;
+                let nn = self.parse_byte(rhs, self.next_offset() + 1)?;
                 self.emit2(encode_xnn(ADD_VX_NN, vx, nn.as_u8()));
             }
             // 8xy4 (ADD Vx, Vy)
@@ -1033,9 +1878,9 @@ impl<'a> Assembler<'a> {
     /// - 8xy2 (AND Vx, Vy)
     /// - 8xy3 (XOR Vx, Vy)
     /// - 8xy5 (SUB Vx, Vy)
-    /// - 8xy6 (SHR Vx {, Vy})
     /// - 8xy7 (SUBN Vx, Vy)
-    /// - 8xyE (SHL Vx {, Vy})
+    ///
+    /// See [`Assembler::parse_shift`] for 8xy6 (SHR) and 8xyE (SHL).
     fn parse_arithmetic(&mut self, opcode: [u8; 2]) -> Chip8Result<()> {
         trace!("parse_arithmetic");
         let [dst, src] = self.parse_arg2()?;
@@ -1074,11 +1919,38 @@ impl<'a> Assembler<'a> {
         self.parse_arithmetic(SUB_VX_VY)
     }
 
+    /// Parse shift mnemonic.
+    ///
+    /// The `Vy` operand is optional, and defaults to `Vx`, so the
+    /// instruction behaves the same regardless of the `shift_vy` quirk.
+    ///
+    /// - 8xy6 (SHR Vx {, Vy})
+    /// - 8xyE (SHL Vx {, Vy})
+    fn parse_shift(&mut self, opcode: [u8; 2]) -> Chip8Result<()> {
+        trace!("parse_shift");
+        let vx = self
+            .stream
+            .next_token()
+            .ok_or_else(|| self.eof_error("Vx register"))
+            .and_then(|t| self.parse_vregister(t))?;
+        let vy = if self.stream.match_token(TK::Comma) {
+            self.stream
+                .next_token()
+                .ok_or_else(|| self.eof_error("Vy register"))
+                .and_then(|t| self.parse_vregister(t))?
+        } else {
+            vx
+        };
+        self.emit2(encode_xyn(opcode[0], vx, vy, opcode[1]));
+        self.consume_eos()?;
+        Ok(())
+    }
+
     /// 8xy6 (SHR Vx {, Vy})
     fn parse_arithmetic_shr(&mut self, name: Token) -> Chip8Result<()> {
         trace!("parse_arithmetic_shr");
         debug_assert_eq!(name.kind, TK::Keyword(KW::ShiftRight));
-        self.parse_arithmetic(SHR_VX_VY)
+        self.parse_shift(SHR_VX_VY)
     }
 
     /// 8xy7 (SUBN Vx, Vy)
@@ -1092,7 +1964,7 @@ impl<'a> Assembler<'a> {
     fn parse_arithmetic_shl(&mut self, name: Token) -> Chip8Result<()> {
         trace!("parse_arithmetic_shl");
         debug_assert_eq!(name.kind, TK::Keyword(KW::ShiftLeft));
-        self.parse_arithmetic(SHL_VX_VY)
+        self.parse_shift(SHL_VX_VY)
     }
 
     /// Cxnn (RND Vx, byte)
@@ -1117,6 +1989,252 @@ impl<'a> Assembler<'a> {
     }
 }
 
+/// XO-CHIP extensions.
+impl<'a> Assembler<'a> {
+    /// Fn01 (PLANE n)
+    fn parse_plane(&mut self, name: Token) -> Chip8Result<()> {
+        trace!("parse_plane");
+        debug_assert_eq!(name.kind, TK::Keyword(KW::Plane));
+
+        let n = self
+            .stream
+            .next_token()
+            .ok_or_else(|| self.eof_error("number literal or constant"))
+            .and_then(|t| self.parse_number(t))?;
+        if n.value > 0x3 {
+            return Err(self.error(n.token, "plane mask must be 3 or less (<= 0x3)"));
+        }
+        self.consume_eos()?;
+        self.emit2(encode_xnn(PLANE_N[0], n.as_u8(), PLANE_N[1]));
+        Ok(())
+    }
+
+    /// F002 (AUDIO)
+    fn parse_audio(&mut self, name: Token) -> Chip8Result<()> {
+        trace!("parse_audio");
+        debug_assert_eq!(name.kind, TK::Keyword(KW::Audio));
+        self.consume_eos()?;
+        self.emit2(AUDIO);
+        Ok(())
+    }
+
+    /// Fx3A (PITCH Vx)
+    fn parse_pitch(&mut self, name: Token) -> Chip8Result<()> {
+        trace!("parse_pitch");
+        debug_assert_eq!(name.kind, TK::Keyword(KW::Pitch));
+
+        let arg = self
+            .stream
+            .next_token()
+            .ok_or_else(|| self.eof_error("Vx register"))?;
+        let vx = self.parse_vregister(arg)?;
+        self.consume_eos()?;
+        self.emit2(encode_xnn(PITCH_VX[0], vx, PITCH_VX[1]));
+        Ok(())
+    }
+}
+
+/// Data directives.
+impl<'a> Assembler<'a> {
+    /// Maximum number of rows in a sprite, limited by `Dxyn (DRW Vx, Vy, n)`.
+    const SPRITE_MAX_ROWS: usize = 15;
+
+    /// Consume the separator between data items.
+    ///
+    /// Items are separated by commas, and a trailing
+    /// comma continues the data on the next line.
+    fn parse_data_separator(&mut self) -> bool {
+        if self.stream.match_token(TK::Comma) {
+            self.stream.ignore_many(TK::Newline);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Pad data with an unused zero so instructions stay aligned to 2 bytes.
+    fn pad_data(&mut self) {
+        if self.conf.pad_data {
+            self.align(2);
+        }
+    }
+
+    /// Pad with zeros up to the next address that is a multiple of the alignment.
+    fn align(&mut self, alignment: usize) {
+        let address = self.conf.load_address + self.next_offset();
+        let padding = address.next_multiple_of(alignment) - address;
+        trace!("align: padding {padding} bytes to a multiple of {alignment}");
+        self.bytecode.resize(self.bytecode.len() + padding, 0);
+    }
+
+    /// Emit bytes into bytecode.
+    ///
+    /// ```asm
+    /// db 0x01, 2, BASE + 3, "HELLO"
+    /// ```
+    fn parse_db(&mut self, name: Token) -> Chip8Result<()> {
+        trace!("parse_db");
+        debug_assert_eq!(name.kind, TK::Keyword(KW::Db));
+
+        loop {
+            let token = self
+                .stream
+                .next_token()
+                .ok_or_else(|| self.eof_error("number literal, constant or string"))?;
+
+            if token.kind == TK::String {
+                let bytes = slice_string(self.stream.span_fragment(&token.span))
+                    .as_bytes()
+                    .to_vec();
+                bytes.into_iter().for_each(|b| self.emit(b));
+            } else {
+                let nn = self.parse_byte(token, self.next_offset())?;
+                self.emit(nn.as_u8());
+            }
+
+            if !self.parse_data_separator() {
+                break;
+            }
+        }
+
+        self.pad_data();
+        self.consume_eos()
+    }
+
+    /// Emit 16-bit big-endian words into bytecode.
+    ///
+    /// ```asm
+    /// dw 0x1234, .sprites + 5
+    /// ```
+    fn parse_dw(&mut self, name: Token) -> Chip8Result<()> {
+        trace!("parse_dw");
+        debug_assert_eq!(name.kind, TK::Keyword(KW::Dw));
+
+        loop {
+            match self.parse_nnn()? {
+                Addr::Num(number) => {
+                    self.emit2([(number.value >> 8) as u8, number.value as u8]);
+                }
+                Addr::Label { labels, offset } => {
+                    // NOTE: If label is not defined yet, we default to 0x0000
+                    let nnnn = self
+                        .resolve_label_with(labels, offset, AddrEncoding::Long)
+                        .unwrap_or_default();
+                    self.emit2([(nnnn >> 8) as u8, nnnn as u8]);
+                }
+            }
+
+            if !self.parse_data_separator() {
+                break;
+            }
+        }
+
+        self.consume_eos()
+    }
+
+    /// Emit the rows of a sprite into bytecode.
+    ///
+    /// Rows are either strings of 8 pixels, where `X` or `#` is set and
+    /// `.` is unset, or byte literals.
+    ///
+    /// ```asm
+    /// sprite "..XXXX..",
+    ///        ".X....X.",
+    ///        0b10000001
+    /// ```
+    fn parse_sprite(&mut self, name: Token) -> Chip8Result<()> {
+        trace!("parse_sprite");
+        debug_assert_eq!(name.kind, TK::Keyword(KW::Sprite));
+
+        let mut count = 0;
+
+        loop {
+            let token = self
+                .stream
+                .next_token()
+                .ok_or_else(|| self.eof_error("sprite row as a string or number literal"))?;
+
+            let row = if token.kind == TK::String {
+                self.parse_sprite_row(token)?
+            } else {
+                let nn = self.parse_number(token)?;
+                if nn.value > 0xFF {
+                    return Err(self.error(nn.token, "sprite row must be 8 pixels wide (<= 0xFF)"));
+                }
+                nn.as_u8()
+            };
+            self.emit(row);
+            count += 1;
+
+            if !self.parse_data_separator() {
+                break;
+            }
+        }
+
+        if count > Self::SPRITE_MAX_ROWS {
+            let message = format!(
+                "sprite must have {} rows or less, but found {count}",
+                Self::SPRITE_MAX_ROWS
+            );
+            return Err(self.error(name, message));
+        }
+
+        self.pad_data();
+        self.consume_eos()
+    }
+
+    /// Convert a visual sprite row into a byte.
+    fn parse_sprite_row(&self, token: Token) -> Chip8Result<u8> {
+        let text = slice_string(self.stream.span_fragment(&token.span));
+
+        let width = text.chars().count();
+        if width != 8 {
+            let message = format!("sprite row must be 8 pixels wide, but found {width}");
+            return Err(self.error(token, message));
+        }
+
+        let mut row = 0;
+        for c in text.chars() {
+            row <<= 1;
+            match c {
+                'X' | 'x' | '#' | '1' => row |= 1,
+                '.' | ' ' | '_' | '0' => {}
+                _ => {
+                    let message = format!("unexpected sprite pixel '{c}', expected 'X' or '.'");
+                    return Err(self.error(token, message));
+                }
+            }
+        }
+
+        Ok(row)
+    }
+}
+
+/// Number of single character insertions, deletions and
+/// substitutions needed to turn one string into the other.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + (ca != *cb) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+/// Contents of a string literal fragment, without the quotes.
+fn slice_string(fragment: &str) -> &str {
+    debug_assert!(fragment.len() >= 2 && fragment.starts_with('"') && fragment.ends_with('"'));
+    &fragment[1..fragment.len() - 1]
+}
+
 fn slice_number(fragment: &str) -> &str {
     let rest = &fragment[2..];
     trace!("slice_number: fragment {fragment} rest {rest}");
@@ -1136,6 +2254,8 @@ mod test {
     const CASES: &[(u16, &str)] = &[
         (0x00E0, "CLS"),
         (0x00EE, "RET"),
+        (0x0123, "SYS  0x123"),
+        (0x0ABC, "SYS  0xABC"),
         (0x1201, "JP   0x201"),
         (0x1ABC, "JP   0xABC"),
         (0x2204, "CALL 0x204"),
@@ -1162,10 +2282,12 @@ mod test {
         (0x8345, "SUB  v3, v4"),
         (0x8126, "SHR  v1, v2"),
         (0x8346, "SHR  v3, v4"),
+        (0x8556, "SHR  v5"),
         (0x8127, "SUBN v1, v2"),
         (0x8347, "SUBN v3, v4"),
         (0x812E, "SHL  v1, v2"),
         (0x834E, "SHL  v3, v4"),
+        (0x855E, "SHL  v5"),
         (0x9120, "SNE  v1, v2"),
         (0x9340, "SNE  v3, v4"),
         (0xA204, "LD   I, 0x204"),
@@ -1193,11 +2315,16 @@ mod test {
         (0xF129, "LD   F, v1"),
         (0xF229, "LD   F, v2"),
         (0xF133, "LD   BCD, v1"),
+        (0xF333, "LD   B, v3"),
         (0xF233, "LD   BCD, v2"),
         (0xF155, "LD   [I], v1"),
         (0xF255, "LD   [I], v2"),
         (0xF165, "LD   v1, [I]"),
         (0xF265, "LD   v2, [I]"),
+        (0xF001, "PLANE 0"),
+        (0xF301, "PLANE 3"),
+        (0xF002, "AUDIO"),
+        (0xF13A, "PITCH v1"),
     ];
 
     #[test]
@@ -1228,10 +2355,10 @@ mod test {
         (parts[0] as u16) << 8 | (parts[1] as u16)
     }
 
-    /// SYS opcode is unsupported.
+    /// SYS address must fit in 12-bits.
     #[test]
     fn test_sys_call() {
-        let source_code = "SYS 0x123";
+        let source_code = "SYS 0x1234";
         let lexer = Lexer::new(source_code);
         let assembler = Assembler::new(lexer);
         let result = assembler.parse();
@@ -1239,10 +2366,73 @@ mod test {
         match result {
             Err(Chip8Error::Multi(_) | Chip8Error::Asm(_)) => {}
             Err(err) => panic!("unexpected error type: {:?}", err),
-            _ => panic!("parsing a SYS call must result in an assembly error"),
+            _ => {
+                panic!("parsing a SYS call with a 16-bit address must result in an assembly error")
+            }
         }
     }
 
+    /// Every LD form must reject operands that don't belong to it.
+    #[test]
+    fn test_load_invalid() {
+        for source_code in [
+            "LD DT, 0x12",
+            "LD ST, K",
+            "LD F, DT",
+            "LD B, [I]",
+            "LD [I], 0x200",
+            "LD I, v1",
+            "LD K, v1",
+            "LD v1, F",
+        ] {
+            let lexer = Lexer::new(source_code);
+            let assembler = Assembler::new(lexer);
+            assert!(
+                assembler.parse().is_err(),
+                "invalid load must fail: {source_code}"
+            );
+        }
+    }
+
+    /// The SUPER-CHIP jump with an offset register shares the encoding of
+    /// `JP v0, addr`, so the high nibble of the address must select the register.
+    #[test]
+    fn test_jump_offset_register() {
+        let assemble = |source_code: &str| Assembler::new(Lexer::new(source_code)).parse();
+
+        assert_eq!(assemble("JP v3, 0x345").unwrap(), vec![0xB3, 0x45]);
+        assert_eq!(assemble("JP vf, 0xF00").unwrap(), vec![0xBF, 0x00]);
+        assert!(assemble("JP v3, 0x245").is_err());
+        assert!(assemble("JP v3, .label\n.label").is_err());
+    }
+
+    /// Trailing arguments must be rejected.
+    #[test]
+    fn test_skip_key_trailing() {
+        let source_code = "SKP v1, v2";
+        let lexer = Lexer::new(source_code);
+        let assembler = Assembler::new(lexer);
+        assert!(assembler.parse().is_err());
+    }
+
+    /// XO-CHIP long load is four bytes, and labels are patched as 16-bit addresses.
+    #[test]
+    fn test_load_long() {
+        let source_code = r#"
+            LD   I,  LONG 0xABCD ; 000 0x200
+            LD   I,  LONG .data  ; 004 0x204
+        .data                    ;     0x208
+            0x01 0x02            ;     0x208
+        "#;
+        let lexer = Lexer::new(source_code);
+        let assembler = Assembler::new(lexer);
+        let bytecode = assembler
+            .parse()
+            .unwrap_or_else(|err| panic!("failed to parse: {err}"));
+        assert_eq!(&bytecode[0..4], &[0xF0, 0x00, 0xAB, 0xCD]);
+        assert_eq!(&bytecode[4..8], &[0xF0, 0x00, 0x02, 0x08]);
+    }
+
     /// Test that labels are being correctly patched into the bytecode.
     #[test]
     fn test_label_patch() {
@@ -1273,4 +2463,406 @@ mod test {
         assert_eq!([bytecode[10], bytecode[11]], encode_nnn(LD_I_NNN, 0x210));
         assert_eq!([bytecode[14], bytecode[15]], encode_nnn(JP_ADDR, 0x204));
     }
+
+    /// Test that named constants are substituted into operands.
+    #[test]
+    fn test_constants() {
+        let source_code = r#"
+        SPRITE_HEIGHT EQU 5
+        BASE          EQU 0x300
+        define LAST   BASE + SPRITE_HEIGHT - 1
+
+            LD   v0,  SPRITE_HEIGHT  ; 000
+            LD   I,   BASE + 4       ; 002
+            JP   LAST                ; 004
+            DRW  v0, v1, SPRITE_HEIGHT ; 006
+            SE   v0,  SPRITE_HEIGHT - 5 ; 008
+        "#;
+        let lexer = Lexer::new(source_code);
+        let assembler = Assembler::new(lexer);
+        let bytecode = assembler
+            .parse()
+            .unwrap_or_else(|err| panic!("failed to parse: {err}"));
+        assert_eq!([bytecode[0], bytecode[1]], encode_xnn(LD_VX_NN, 0, 5));
+        assert_eq!([bytecode[2], bytecode[3]], encode_nnn(LD_I_NNN, 0x304));
+        assert_eq!([bytecode[4], bytecode[5]], encode_nnn(JP_ADDR, 0x304));
+        assert_eq!([bytecode[6], bytecode[7]], encode_xyn(DRW_VX_VY_N, 0, 1, 5));
+        assert_eq!([bytecode[8], bytecode[9]], encode_xnn(SE_VX_NN, 0, 0));
+    }
+
+    /// Constants must be defined once, before they are used.
+    #[test]
+    fn test_constants_invalid() {
+        for source_code in [
+            "LD v0, HEIGHT\nHEIGHT EQU 5",
+            "HEIGHT EQU 5\nHEIGHT EQU 6",
+            "HEIGHT EQU 0 - 1",
+        ] {
+            let lexer = Lexer::new(source_code);
+            let assembler = Assembler::new(lexer);
+            assert!(
+                assembler.parse().is_err(),
+                "invalid constant must fail: {source_code}"
+            );
+        }
+    }
+
+    /// Test that operand expressions are evaluated, with operator precedence
+    /// and labels offset by constants.
+    #[test]
+    fn test_expressions() {
+        let source_code = r#"
+        HEIGHT EQU 5
+            LD   v0,  2 + 3 * 4          ; 000 0x200
+            LD   v1,  (2 + 3) * 4        ; 002 0x202
+            LD   v2,  HEIGHT * 2 / 5 - 1 ; 004 0x204
+            LD   I,   .sprites + HEIGHT  ; 006 0x206
+            JP   .sprites - 2            ; 008 0x208
+        .before                          ;     0x20A
+            LD   I,   .before + 2 * 3    ; 00A 0x20A
+            CALL 1 + .sprites            ; 00C 0x20C
+        .sprites                         ;     0x20E
+            0x80 0x40
+        "#;
+        let lexer = Lexer::new(source_code);
+        let assembler = Assembler::new(lexer);
+        let bytecode = assembler
+            .parse()
+            .unwrap_or_else(|err| panic!("failed to parse: {err}"));
+        assert_eq!([bytecode[0], bytecode[1]], encode_xnn(LD_VX_NN, 0, 14));
+        assert_eq!([bytecode[2], bytecode[3]], encode_xnn(LD_VX_NN, 1, 20));
+        assert_eq!([bytecode[4], bytecode[5]], encode_xnn(LD_VX_NN, 2, 1));
+        assert_eq!([bytecode[6], bytecode[7]], encode_nnn(LD_I_NNN, 0x213));
+        assert_eq!([bytecode[8], bytecode[9]], encode_nnn(JP_ADDR, 0x20C));
+        assert_eq!([bytecode[10], bytecode[11]], encode_nnn(LD_I_NNN, 0x210));
+        assert_eq!([bytecode[12], bytecode[13]], encode_nnn(CALL_ADDR, 0x20F));
+    }
+
+    /// Expressions that can't be evaluated must be rejected.
+    #[test]
+    fn test_expressions_invalid() {
+        for source_code in [
+            "LD v0, 1 / 0",
+            "LD v0, (1 + 2",
+            "JP .later * 2\n.later",
+            "JP 2 - .later\n.later",
+            "LD v0, .later\n.later",
+            "JP .before - 0x300\n.before",
+            "LD v0, .start - .end\n.start\nCLS\n.end",
+            "LD v0, .later / 2\n.later",
+            "LD v0, .later - .missing\n.later",
+        ] {
+            let lexer = Lexer::new(source_code);
+            let assembler = Assembler::new(lexer);
+            assert!(
+                assembler.parse().is_err(),
+                "invalid expression must fail: {source_code}"
+            );
+        }
+    }
+
+    /// Test that labels defined later can be subtracted from each other,
+    /// like the size of a block of data.
+    #[test]
+    #[rustfmt::skip]
+    fn test_label_arithmetic() {
+        let source_code = r#"
+            LD   v0,  .sprite_end - .sprite_start      ; 000 0x200
+            ADD  v1,  .sprite_end - .sprite_start + 1  ; 002 0x202
+            LD   I,   .sprite_start + .sprite_end - .sprite_end ; 004 0x204
+            db   .sprite_end - .sprite_start - 1       ; 006 0x206
+        .sprite_start                                  ;     0x207
+            db   0x80, 0x40, 0x20
+        .sprite_end                                    ;     0x20A
+        "#;
+        let lexer = Lexer::new(source_code);
+        let assembler = Assembler::with_conf(lexer, AsmConf { pad_data: false, ..AsmConf::default() });
+        let bytecode = assembler
+            .parse()
+            .unwrap_or_else(|err| panic!("failed to parse: {err}"));
+        assert_eq!(
+            bytecode.as_slice(),
+            &[
+                0x60, 0x03,
+                0x71, 0x04,
+                0xA2, 0x07,
+                0x02,
+                0x80, 0x40, 0x20,
+            ]
+        );
+    }
+
+    /// Test that `.align` and `.org` pad with zeros, and plain labels
+    /// with the same names still work.
+    #[test]
+    #[rustfmt::skip]
+    fn test_align_org() {
+        let source_code = r#"
+            db   0x01                  ; 000 0x200
+        .align 4                       ;     0x201
+            db   0x02                  ; 004 0x204
+        .align 2                       ;     0x205
+            CLS                        ; 006 0x206
+        .org 0x20C
+        .org                           ;     0x20C
+            JP   .org                  ; 00C 0x20C
+        .org 0x20E
+        "#;
+        let lexer = Lexer::new(source_code);
+        let assembler = Assembler::with_conf(lexer, AsmConf { pad_data: false, ..AsmConf::default() });
+        let bytecode = assembler
+            .parse()
+            .unwrap_or_else(|err| panic!("failed to parse: {err}"));
+        assert_eq!(
+            bytecode.as_slice(),
+            &[
+                0x01, 0x00, 0x00, 0x00,
+                0x02, 0x00,
+                0x00, 0xE0,
+                0x00, 0x00, 0x00, 0x00,
+                0x12, 0x0C,
+            ]
+        );
+
+        // Padded data ends on an even address, even when it starts on an odd one.
+        let source_code = ".org 0x201
+    db 1, 2
+    CLS
+";
+        let bytecode = Assembler::new(Lexer::new(source_code))
+            .parse()
+            .unwrap_or_else(|err| panic!("failed to parse: {err}"));
+        assert_eq!(bytecode.as_slice(), &[0x00, 0x01, 0x02, 0x00, 0x00, 0xE0]);
+
+        for source_code in [
+            "CLS
+.org 0x200",
+            ".org 0x100",
+            ".align 0",
+            ".align .later
+.later",
+        ] {
+            let lexer = Lexer::new(source_code);
+            let assembler = Assembler::new(lexer);
+            assert!(
+                assembler.parse().is_err(),
+                "invalid directive must fail: {source_code}"
+            );
+        }
+    }
+
+    /// Test that the optimizer removes redundant instructions, and
+    /// moves the labels after them.
+    #[test]
+    #[rustfmt::skip]
+    fn test_optimize() {
+        let source_code = r#"
+        .main
+            LD   v0,  v0            ; removed
+            ADD  v1,  1
+            ADD  v1,  2             ; merged
+            ADD  v1,  0xFF          ; merged
+            JP   .next              ; removed
+        .next
+            SE   v2,  0
+            LD   v3,  v3            ; skipped, kept
+            ADD  v4,  1
+        .loop
+            ADD  v4,  1             ; labelled, kept
+            JP   .loop
+        "#;
+        let conf = AsmConf { optimize: true, ..AsmConf::default() };
+        let assembler = Assembler::with_conf(Lexer::new(source_code), conf);
+        let (bytecode, listing, report) = assembler
+            .parse_with_report()
+            .unwrap_or_else(|err| panic!("failed to parse: {err}"));
+        assert_eq!(
+            bytecode.as_slice(),
+            &[
+                0x71, 0x02,
+                0x32, 0x00,
+                0x83, 0x30,
+                0x74, 0x01,
+                0x74, 0x01,
+                0x12, 0x08,
+            ]
+        );
+        assert_eq!(
+            report,
+            OptimizeReport {
+                moves_removed: 1,
+                adds_merged: 2,
+                jumps_removed: 1,
+                bytes_before: 20,
+                bytes_after: 12,
+            }
+        );
+        assert_eq!(report.bytes_saved(), 8);
+        assert_eq!(listing.entries().len(), 6);
+        assert_eq!(listing.entries()[5].label, None);
+        assert_eq!(listing.entries()[4].label.as_deref(), Some("loop"));
+
+        // Disabled by default.
+        let bytecode = Assembler::new(Lexer::new(source_code)).parse().unwrap();
+        assert_eq!(bytecode.len(), 20);
+    }
+
+    /// Test that data directives emit bytes, words and sprites.
+    #[test]
+    #[rustfmt::skip]
+    fn test_data_directives() {
+        let source_code = r#"
+            db   0x01, 2 + 1, "AB"     ; 000 0x200
+            dw   0x1234, .sprite       ; 004 0x204
+        .sprite                        ;     0x208
+            sprite "X......X",
+                   "..#..#..",
+                   0b00011000          ; 008 0x208 (padded)
+            db   0x0F                  ; 00C 0x20C (padded)
+        "#;
+        let lexer = Lexer::new(source_code);
+        let assembler = Assembler::new(lexer);
+        let bytecode = assembler
+            .parse()
+            .unwrap_or_else(|err| panic!("failed to parse: {err}"));
+        assert_eq!(
+            bytecode.as_slice(),
+            &[
+                0x01, 0x03, b'A', b'B',
+                0x12, 0x34, 0x02, 0x08,
+                0b10000001, 0b00100100, 0b00011000, 0x00,
+                0x0F, 0x00,
+            ]
+        );
+    }
+
+    /// Sprite rows must be exactly 8 pixels wide.
+    #[test]
+    fn test_sprite_invalid() {
+        for source_code in [
+            r#"sprite "X..X""#,
+            r#"sprite "X..X....X""#,
+            r#"sprite "X..?....""#,
+            "sprite 0x100",
+            r#"sprite "X......."#,
+            "db 0x100",
+        ] {
+            let lexer = Lexer::new(source_code);
+            let assembler = Assembler::new(lexer);
+            assert!(
+                assembler.parse().is_err(),
+                "invalid data must fail: {source_code}"
+            );
+        }
+    }
+
+    /// Test that the listing maps addresses to source lines and patched bytes.
+    #[test]
+    fn test_listing() {
+        let source_code = "LD v0, 5\n.main\n    JP .main ; loop\n    db 1, 2, 3\n";
+        let lexer = Lexer::new(source_code);
+        let assembler = Assembler::new(lexer);
+        let (bytecode, listing) = assembler
+            .parse_with_listing()
+            .unwrap_or_else(|err| panic!("failed to parse: {err}"));
+        assert_eq!(bytecode.len(), 8);
+
+        let entries = listing.entries();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries[1],
+            ListingEntry {
+                address: 0x202,
+                line: 3,
+                label: Some("main".to_owned()),
+                source: "    JP .main ; loop".to_owned(),
+                bytes: vec![0x12, 0x02],
+                data: false,
+            }
+        );
+        assert_eq!(entries[2].bytes, vec![1, 2, 3, 0]);
+        assert!(entries[2].data);
+        assert_eq!(listing.find_address(0x205), Some(&entries[2]));
+        assert_eq!(listing.find_address(0x208), None);
+    }
+
+    /// All errors in a file are reported, not only the first.
+    #[test]
+    fn test_multiple_errors() {
+        let source_code = ".loop\n    LD v0, $\n    JP .lop\n    CALL .draw\n";
+        let lexer = Lexer::new(source_code);
+        let assembler = Assembler::new(lexer);
+        let err = assembler.parse().expect_err("source code has errors");
+
+        let diagnostics = err.diagnostics();
+        assert_eq!(diagnostics.len(), 3);
+        assert_eq!(diagnostics[1].message, "label 'lop' is undefined");
+        assert_eq!(
+            diagnostics[1].help.as_deref(),
+            Some("did you mean '.loop'?")
+        );
+        assert_eq!(diagnostics[2].message, "label 'draw' is undefined");
+        assert_eq!(diagnostics[2].help, None);
+    }
+
+    /// Byte operands must fit in a byte, whether their labels are defined before or after them.
+    #[test]
+    fn test_byte_out_of_range() {
+        for source_code in [
+            ".start\n    LD v0, .start\n",
+            "    LD v0, .start\n.start\n",
+            "    ADD v2, 2 * 200\n",
+        ] {
+            let lexer = Lexer::new(source_code);
+            let err = Assembler::new(lexer)
+                .parse()
+                .expect_err("byte is out of range");
+            assert_eq!(err.diagnostics().len(), 1, "{source_code}");
+        }
+
+        let lexer = Lexer::new("    ADD v2, 2 * 100\n");
+        assert_eq!(Assembler::new(lexer).parse().unwrap(), [0x72, 200]);
+    }
+
+    /// Constant expressions of byte operands are checked after they're evaluated.
+    #[test]
+    fn test_byte_expression_overflow() {
+        let source_code = "
+        define COUNT 100
+            SE  v0, 3 * COUNT
+            SNE v0, (COUNT + 1) * 3
+            LD  v1, 3 * 100
+            ADD v2, COUNT * COUNT
+            RAND v3, 0xFF + 1
+            RAND v4, 0xF0 + 0xF
+        ";
+        let lexer = Lexer::new(source_code);
+        let err = Assembler::new(lexer).parse().expect_err("bytes overflow");
+
+        let diagnostics = err.diagnostics();
+        assert_eq!(diagnostics.len(), 5, "{diagnostics:?}");
+        assert!(diagnostics
+            .iter()
+            .all(|diagnostic| diagnostic.message == "byte must be 255 or less (<= 0xFF)"));
+
+        // The error points at the whole expression.
+        let lexer = Lexer::new("LD v1, 3 * 100\n");
+        let err = Assembler::new(lexer).parse().unwrap_err();
+        let span = err.diagnostics()[0].span.clone().unwrap();
+        assert_eq!(span.fragment("LD v1, 3 * 100\n"), "3 * 100");
+    }
+
+    /// Data bytes out of range are reported, and the following lines still assembled.
+    #[test]
+    fn test_data_block_errors() {
+        let source_code = "0x1FF\n0x01 + 0x2FF\n    LD v0, $\n";
+        let lexer = Lexer::new(source_code);
+        let assembler = Assembler::new(lexer);
+        let err = assembler.parse().expect_err("source code has errors");
+
+        let diagnostics = err.diagnostics();
+        assert_eq!(diagnostics.len(), 3);
+        assert_eq!(diagnostics[0].message, "byte must be 255 or less (<= 0xFF)");
+        assert_eq!(diagnostics[1].message, "byte must be 255 or less (<= 0xFF)");
+    }
 }
@@ -142,8 +142,8 @@ mod test {
 
     #[test]
     fn test_eof() {
-        assert_eq!(Cursor::new("").at_end(), true);
-        assert_eq!(Cursor::new("abc").at_end(), false);
+        assert!(Cursor::new("").at_end());
+        assert!(!Cursor::new("abc").at_end());
 
         // Exhausted cursor must return EOF
         let mut cursor = Cursor::new("a");
@@ -0,0 +1,266 @@
+//! Source code formatter.
+//!
+//! Reprints assembly with a canonical layout:
+//!
+//! ```asm
+//! HEIGHT EQU 4
+//!
+//! ; draw the maze
+//! .loop
+//!     LD      I,   .right   ; sprite
+//!     DRW     v0,  v1,  HEIGHT
+//!     JP      .loop
+//! ```
+use crate::error::Chip8Result;
+
+use super::{assemble, Lexer, Token, TokenKind};
+
+/// Indentation of instructions and data.
+const INDENT: &str = "    ";
+
+/// Column width of mnemonics, after the indentation.
+const MNEMONIC_WIDTH: usize = 8;
+
+/// Column width of each operand, including its comma.
+const OPERAND_WIDTH: usize = 5;
+
+/// Minimum column of trailing comments, which fits an instruction with three short operands.
+const COMMENT_COLUMN: usize = 25;
+
+/// Spaces between the code and a trailing comment.
+const COMMENT_GAP: usize = 2;
+
+/// Format assembly source code.
+///
+/// - Labels, constants and defines start in the first column.
+/// - Instructions and data are indented, with mnemonics and operands aligned in columns.
+/// - Hexadecimal digits are uppercase, and registers are lowercase.
+/// - Trailing comments are aligned within each block of lines.
+/// - Consecutive blank lines are collapsed into one.
+///
+/// The source code must assemble, so formatting can't change the meaning of a program.
+pub fn format_source(source_code: &str) -> Chip8Result<String> {
+    // Errors are reported against the original source.
+    assemble(source_code)?;
+
+    let lines = split_lines(source_code);
+    let mut output = String::with_capacity(source_code.len());
+
+    for block in lines.split(|line| line.is_blank()) {
+        if block.is_empty() {
+            continue;
+        }
+        if !output.is_empty() {
+            output.push('\n');
+        }
+
+        let column = block
+            .iter()
+            .filter(|line| !line.code.is_empty() && line.comment.is_some())
+            .map(|line| line.code.chars().count() + COMMENT_GAP)
+            .fold(COMMENT_COLUMN, usize::max);
+
+        for line in block {
+            match &line.comment {
+                Some(comment) if line.code.is_empty() => {
+                    let indent = if line.indented { INDENT } else { "" };
+                    output.push_str(&format!("{indent}{comment}\n"));
+                }
+                Some(comment) => {
+                    output.push_str(&format!("{:column$}{comment}\n", line.code));
+                }
+                None => {
+                    output.push_str(&line.code);
+                    output.push('\n');
+                }
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+/// Formatted line of source code.
+struct Line {
+    code: String,
+    comment: Option<String>,
+    /// Whether the original line started with whitespace.
+    indented: bool,
+}
+
+impl Line {
+    fn is_blank(&self) -> bool {
+        self.code.is_empty() && self.comment.is_none()
+    }
+}
+
+fn split_lines(source_code: &str) -> Vec<Line> {
+    let mut lines = vec![];
+    let mut tokens = vec![];
+    let mut comment = None;
+    let mut line_start = 0;
+
+    for token in Lexer::new(source_code) {
+        match token.kind {
+            TokenKind::Comment => {
+                let fragment = token.span.fragment(source_code).trim_end();
+                comment = Some(fragment.to_owned());
+            }
+            TokenKind::Newline | TokenKind::EOF => {
+                let first = tokens
+                    .first()
+                    .map(|token: &Token| token.span.index)
+                    .unwrap_or(token.span.index) as usize;
+                let indented = first > line_start
+                    && source_code[line_start..first].starts_with(char::is_whitespace);
+                lines.push(Line {
+                    code: format_code(source_code, &tokens),
+                    comment: comment.take(),
+                    indented,
+                });
+                tokens.clear();
+                line_start = token.span.end() as usize;
+            }
+            _ => tokens.push(token),
+        }
+    }
+
+    // The end-of-file produces a blank line, which must not
+    // turn into a trailing newline.
+    while lines.last().is_some_and(Line::is_blank) {
+        lines.pop();
+    }
+
+    lines
+}
+
+/// Format the tokens of a single line, without the comment.
+fn format_code(source_code: &str, tokens: &[Token]) -> String {
+    use TokenKind as TK;
+
+    let Some(first) = tokens.first() else {
+        return String::new();
+    };
+
+    match first.kind {
+        // Labels, constants and defines
+        TK::Dot | TK::Ident | TK::Keyword(super::Keyword::Define) => {
+            format_operand(source_code, tokens)
+        }
+        // Mnemonics and directives
+        TK::Keyword(keyword) => {
+            let operands: Vec<String> = tokens[1..]
+                .split(|token| token.kind == TK::Comma)
+                .map(|operand| format_operand(source_code, operand))
+                .filter(|operand| !operand.is_empty())
+                .collect();
+
+            let mut code = format!("{INDENT}{keyword}");
+            if let Some((last, rest)) = operands.split_last() {
+                pad(&mut code, INDENT.len() + MNEMONIC_WIDTH);
+                for operand in rest {
+                    let start = code.len();
+                    code.push_str(operand);
+                    code.push(',');
+                    pad(&mut code, start + OPERAND_WIDTH);
+                }
+                code.push_str(last);
+            }
+            code
+        }
+        // Data
+        _ => format!("{INDENT}{}", format_operand(source_code, tokens)),
+    }
+}
+
+/// Pad the code with spaces up to the given width, or a single space
+/// if the code is already wider.
+fn pad(code: &mut String, width: usize) {
+    let count = width.saturating_sub(code.chars().count()).max(1);
+    code.extend(std::iter::repeat_n(' ', count));
+}
+
+/// Format the tokens of an operand, or expression, separated by spaces.
+///
+/// No spaces are put inside brackets and parentheses, or after a label's dot.
+fn format_operand(source_code: &str, tokens: &[Token]) -> String {
+    use TokenKind as TK;
+
+    let mut text = String::new();
+    // Whether the previous token is joined to the current.
+    let mut join = true;
+
+    for token in tokens {
+        let fragment = token.span.fragment(source_code);
+        let closing = matches!(token.kind, TK::RightParen | TK::RightBracket);
+        if !join && !closing {
+            text.push(' ');
+        }
+
+        match token.kind {
+            // Label names are kept as written, even when they look like keywords.
+            _ if text.ends_with('.') => text.push_str(fragment),
+            TK::Register(vreg) => text.push_str(&format!("v{:x}", vreg.as_index())),
+            TK::Keyword(keyword) => text.push_str(&keyword.to_string()),
+            TK::Number => text.push_str(&format_number(fragment)),
+            _ => text.push_str(fragment),
+        }
+
+        join = matches!(token.kind, TK::Dot | TK::LeftParen | TK::LeftBracket);
+    }
+
+    text
+}
+
+/// Uppercase hexadecimal digits, with a lowercase prefix.
+fn format_number(fragment: &str) -> String {
+    match fragment.strip_prefix("0x") {
+        Some(digits) => format!("0x{}", digits.to_uppercase()),
+        None => fragment.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_source() {
+        let source_code = r#"
+; maze
+height equ 0x0a
+
+
+.loop  ; again
+  ld I,.right
+      rand V2,0xff ; random
+DRW   v0,v1,(height-1)/2
+  LD [I], VA
+    jp .loop+2
+.right
+    0x1f
+"#;
+        let expected = r#"; maze
+height EQU 0x0A
+
+.loop                    ; again
+    LD      I,   .right
+    RAND    v2,  0xFF    ; random
+    DRW     v0,  v1,  (height - 1) / 2
+    LD      [I], va
+    JP      .loop + 2
+.right
+    0x1F
+"#;
+        let formatted = format_source(source_code).unwrap();
+        assert_eq!(formatted, expected);
+
+        // Formatting is stable.
+        assert_eq!(format_source(&formatted).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_format_invalid() {
+        assert!(format_source("LD v0, $").is_err());
+    }
+}
@@ -68,11 +68,6 @@ impl<'a> Lexer<'a> {
             self.cursor.next_char();
         }
 
-        // Erase comment line.
-        if matches!(self.cursor.current(), ';') {
-            self.erase_comment();
-        }
-
         // Assume that lexer initialization, or previous iteration,
         // leaves the cursor at the next character.
         self.start_token();
@@ -81,9 +76,15 @@ impl<'a> Lexer<'a> {
             ',' => self.make_token(TK::Comma),
             '.' => self.make_token(TK::Dot),
             ':' => self.make_token(TK::Colon),
-            ';' => self.make_token(TK::Semicolon),
+            ';' => self.consume_comment(),
             '[' => self.make_token(TK::LeftBracket),
             ']' => self.make_token(TK::RightBracket),
+            '(' => self.make_token(TK::LeftParen),
+            ')' => self.make_token(TK::RightParen),
+            '+' => self.make_token(TK::Plus),
+            '-' => self.make_token(TK::Minus),
+            '*' => self.make_token(TK::Star),
+            '/' => self.make_token(TK::Slash),
             '\r' => {
                 // Windows :(
                 if self.cursor.peek() == '\n' {
@@ -94,6 +95,7 @@ impl<'a> Lexer<'a> {
             '\n' => self.make_token(TK::Newline),
             '_' | 'a'..='z' | 'A'..='Z' => self.consume_ident(),
             '0'..='9' => self.consume_number(),
+            '"' => self.consume_string(),
 
             EOF_CHAR => self.make_token(TK::EOF),
             _ => self.make_token(TK::Unknown),
@@ -157,13 +159,17 @@ impl<'a> Lexer<'a> {
 
 /// Specialised tokens.
 impl<'a> Lexer<'a> {
-    /// Erase comment line up to, but not including, the trailing newline.
-    fn erase_comment(&mut self) {
+    /// Make a comment token up to, but not including, the trailing newline.
+    ///
+    /// Comments are trivia, skipped by the parser but kept for the formatter.
+    fn consume_comment(&mut self) -> Token {
         debug_assert_eq!(self.cursor.current(), ';');
 
-        while !is_newline(self.cursor.current()) {
+        while !is_newline(self.cursor.peek()) && self.cursor.peek() != EOF_CHAR {
             self.cursor.next();
         }
+
+        self.make_token(TokenKind::Comment)
     }
 
     /// Make an identifier token.
@@ -201,6 +207,27 @@ impl<'a> Lexer<'a> {
 
         self.make_token(TokenKind::Number)
     }
+
+    /// Make a string literal token, including the quotes.
+    ///
+    /// Strings can't span multiple lines, and escape sequences
+    /// are not supported. An unterminated string is unknown.
+    fn consume_string(&mut self) -> Token {
+        debug_assert_eq!(self.cursor.current(), '"');
+
+        loop {
+            match self.cursor.peek() {
+                '"' => {
+                    self.cursor.next();
+                    return self.make_token(TokenKind::String);
+                }
+                '\r' | '\n' | EOF_CHAR => return self.make_token(TokenKind::Unknown),
+                _ => {
+                    self.cursor.next();
+                }
+            }
+        }
+    }
 }
 
 /// Test whether the character is considered whitespace
@@ -0,0 +1,94 @@
+//! Assembler listing
+use std::fmt;
+
+/// Listing of assembled source code.
+///
+/// Maps each statement that emitted bytecode to its address in
+/// memory, the line in the source code, and the encoded bytes.
+#[derive(Debug, Default, Clone)]
+pub struct Listing {
+    entries: Vec<ListingEntry>,
+}
+
+/// A single statement in a [`Listing`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListingEntry {
+    /// Address in memory where the bytes are loaded.
+    pub address: u16,
+    /// Line number in the source code, starting at 1.
+    pub line: usize,
+    /// Label that points to the address, if any.
+    pub label: Option<String>,
+    /// Source code of the line, without the trailing newline.
+    pub source: String,
+    /// Encoded bytes, with label addresses patched in.
+    pub bytes: Vec<u8>,
+    /// The bytes are data, rather than instructions.
+    pub data: bool,
+}
+
+impl Listing {
+    pub(crate) fn push(&mut self, entry: ListingEntry) {
+        self.entries.push(entry);
+    }
+
+    pub fn entries(&self) -> &[ListingEntry] {
+        &self.entries
+    }
+
+    /// Find the entry that contains the given address.
+    pub fn find_address(&self, address: u16) -> Option<&ListingEntry> {
+        self.entries.iter().find(|entry| {
+            let start = entry.address as usize;
+            let end = start + entry.bytes.len();
+            (start..end).contains(&(address as usize))
+        })
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for entry in &self.entries {
+            writeln!(f, "{entry}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Each entry is listed with its address, bytes, line number and source,
+/// preceded by its label. Long data blocks are wrapped over multiple lines.
+///
+/// ```text
+///                          .main
+/// 0x0200  6005          3  LD v0, 5
+/// ```
+impl fmt::Display for ListingEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const BYTES_PER_ROW: usize = 4;
+
+        let mut rows = self.bytes.chunks(BYTES_PER_ROW);
+        let first = rows.next().unwrap_or_default();
+
+        let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{b:02X}")).collect::<String>();
+
+        if let Some(ref label) = self.label {
+            writeln!(f, "{:<25}.{label}", "")?;
+        }
+
+        write!(
+            f,
+            "0x{:04X}  {:<8}  {:>5}  {}",
+            self.address,
+            hex(first),
+            self.line,
+            self.source.trim()
+        )?;
+
+        for (i, row) in rows.enumerate() {
+            let address = self.address as usize + (i + 1) * BYTES_PER_ROW;
+            write!(f, "\n0x{address:04X}  {}", hex(row))?;
+        }
+
+        Ok(())
+    }
+}
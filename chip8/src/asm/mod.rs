@@ -1,7 +1,11 @@
 //! Assembler
 mod assembler;
 mod cursor;
+mod formatter;
 mod lexer;
+mod listing;
+mod optimizer;
+mod output;
 mod token_stream;
 mod tokens;
 
@@ -21,6 +25,10 @@ pub fn assemble_with(source_code: impl AsRef<str>, conf: AsmConf) -> Chip8Result
 
 pub use self::{
     assembler::{AsmConf, Assembler},
+    formatter::format_source,
     lexer::Lexer,
+    listing::{Listing, ListingEntry},
+    optimizer::OptimizeReport,
+    output::OutputFormat,
     tokens::{Keyword, Span, Token, TokenKind},
 };
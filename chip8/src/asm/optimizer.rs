@@ -0,0 +1,150 @@
+//! Peephole optimizer.
+//!
+//! Looks for wasteful instructions in assembled statements, and plans
+//! their removal for a second assembler pass. Reassembling, rather than
+//! editing the bytecode, moves the labels after removed instructions
+//! along with the code they point to.
+//!
+//! Addresses written as number literals aren't moved, so programs
+//! should refer to code by labels when they're optimized.
+use std::{collections::HashMap, fmt};
+
+use crate::bytecode::opcodes::*;
+
+/// Summary of the instructions removed by the optimizer.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OptimizeReport {
+    /// `LD Vx, Vx` instructions, which have no effect.
+    pub moves_removed: usize,
+    /// `ADD Vx, nn` instructions, folded into the `ADD Vx, nn` before them.
+    pub adds_merged: usize,
+    /// `JP addr` instructions to the instruction that follows them.
+    pub jumps_removed: usize,
+    /// Size of the program before optimizing.
+    pub bytes_before: usize,
+    /// Size of the program after optimizing.
+    pub bytes_after: usize,
+}
+
+impl OptimizeReport {
+    pub fn bytes_saved(&self) -> usize {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+}
+
+impl fmt::Display for OptimizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "removed {} moves and {} jumps, merged {} adds, saved {} of {} bytes",
+            self.moves_removed,
+            self.jumps_removed,
+            self.adds_merged,
+            self.bytes_saved(),
+            self.bytes_before
+        )
+    }
+}
+
+/// Statement of the first assembler pass, as seen by the optimizer.
+pub(crate) struct Candidate {
+    /// Source index of the statement's first token, which identifies
+    /// the statement in the second pass.
+    pub key: u32,
+    /// Address range of the emitted bytes.
+    pub start: usize,
+    pub end: usize,
+    /// The single instruction emitted by the statement, or `None`
+    /// for data and longer statements.
+    pub instr: Option<[u8; 2]>,
+    /// A label points to the statement.
+    pub labelled: bool,
+    /// The statement refers to a label that was defined after it.
+    pub patched: bool,
+}
+
+/// Change to a statement in the second assembler pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Rewrite {
+    /// Leave the statement out.
+    Remove,
+    /// Add the byte of this `ADD Vx, nn` to the instruction before it,
+    /// and leave the statement out.
+    MergeAdd,
+}
+
+/// Plan the rewrites of the statements, in order of address.
+///
+/// Instructions following a skip are left alone, since the
+/// skip would jump over a different instruction without them.
+pub(crate) fn plan(candidates: &[Candidate], report: &mut OptimizeReport) -> HashMap<u32, Rewrite> {
+    let mut rewrites = HashMap::new();
+    // Register of the `ADD Vx, nn` that following adds can merge into.
+    let mut add_target: Option<u8> = None;
+
+    for (index, candidate) in candidates.iter().enumerate() {
+        let previous = index
+            .checked_sub(1)
+            .map(|index| &candidates[index])
+            .filter(|previous| previous.end == candidate.start);
+        let after_skip = previous.and_then(|p| p.instr).is_some_and(is_skip);
+        if previous.is_none() {
+            add_target = None;
+        }
+
+        let Some(instr @ [hi, lo]) = candidate.instr else {
+            add_target = None;
+            continue;
+        };
+        let op = hi >> 4;
+        let vx = hi & 0xF;
+        let vy = lo >> 4;
+        let nnn = u16::from_be_bytes(instr) & 0xFFF;
+
+        if after_skip {
+            add_target = None;
+            continue;
+        }
+
+        // 8xx0 (LD Vx, Vx)
+        if op == LD_VX_VY[0] && lo & 0xF == LD_VX_VY[1] && vx == vy {
+            report.moves_removed += 1;
+            rewrites.insert(candidate.key, Rewrite::Remove);
+            add_target = None;
+            continue;
+        }
+
+        // 1nnn (JP addr) to the next instruction
+        if op == JP_ADDR && nnn as usize == candidate.end {
+            report.jumps_removed += 1;
+            rewrites.insert(candidate.key, Rewrite::Remove);
+            add_target = None;
+            continue;
+        }
+
+        // 7xnn (ADD Vx, byte)
+        if op == ADD_VX_NN && !candidate.patched {
+            if add_target == Some(vx) && !candidate.labelled {
+                report.adds_merged += 1;
+                rewrites.insert(candidate.key, Rewrite::MergeAdd);
+            } else {
+                add_target = Some(vx);
+            }
+            continue;
+        }
+
+        add_target = None;
+    }
+
+    rewrites
+}
+
+/// Instructions that conditionally skip the next instruction.
+fn is_skip([hi, lo]: [u8; 2]) -> bool {
+    match hi >> 4 {
+        SE_VX_NN | SNE_VX_NN => true,
+        SE_VX_VY | SNE_VX_VY => lo & 0xF == 0,
+        0xE => lo == SKP_VX[1] || lo == SKNP_VX[1],
+        _ => false,
+    }
+}
@@ -0,0 +1,262 @@
+//! Output formats of assembled programs.
+//!
+//! Besides the raw ROM, programs can be written as Intel HEX for flashing
+//! embedded targets, or as arrays to embed in C and Rust code.
+use std::{fmt, io, io::Write, str::FromStr};
+
+/// Number of bytes in each Intel HEX data record.
+const HEX_RECORD_SIZE: usize = 16;
+
+/// Number of bytes on each line of the C and Rust arrays.
+const ARRAY_LINE_SIZE: usize = 12;
+
+/// File format of an assembled program.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The bytecode as is.
+    #[default]
+    Rom,
+    /// Intel HEX records, with the bytecode at its load address.
+    Hex,
+    /// C source code, with the bytecode in a `uint8_t` array.
+    CArray,
+    /// Rust source code, with the bytecode in a `u8` array constant.
+    RustArray,
+}
+
+impl OutputFormat {
+    pub const ALL: [Self; 4] = [Self::Rom, Self::Hex, Self::CArray, Self::RustArray];
+
+    /// Names of the formats, accepted by [`OutputFormat::from_str`].
+    pub const NAMES: &'static [&'static str] = &["rom", "hex", "carray", "rustarray"];
+
+    pub fn name(self) -> &'static str {
+        Self::NAMES[self as usize]
+    }
+
+    /// Conventional file extension of the format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Rom => "rom",
+            Self::Hex => "hex",
+            Self::CArray => "h",
+            Self::RustArray => "rs",
+        }
+    }
+
+    /// Write the bytecode, loaded at the given address, in the format.
+    ///
+    /// The arrays are named after `name`, typically the stem of the output
+    /// file, with the characters that aren't valid in identifiers replaced.
+    ///
+    /// ```
+    /// # use chip8::asm::OutputFormat;
+    /// let mut out = vec![];
+    /// OutputFormat::Hex.write(&[0x00, 0xE0], 0x200, "cls", &mut out).unwrap();
+    /// assert_eq!(String::from_utf8(out).unwrap(), ":0202000000E01C\n:00000001FF\n");
+    /// ```
+    pub fn write(
+        self,
+        bytecode: &[u8],
+        load_address: usize,
+        name: &str,
+        w: &mut impl Write,
+    ) -> io::Result<()> {
+        match self {
+            Self::Rom => w.write_all(bytecode),
+            Self::Hex => write_hex(bytecode, load_address, w),
+            Self::CArray => write_c_array(bytecode, load_address, name, w),
+            Self::RustArray => write_rust_array(bytecode, load_address, name, w),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::NAMES
+            .iter()
+            .position(|name| *name == s)
+            .map(|index| Self::ALL[index])
+            .ok_or_else(|| {
+                format!(
+                    "unknown output format '{s}', expected one of: {}",
+                    Self::NAMES.join(", ")
+                )
+            })
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Data records, preceded by an extended linear address record whenever
+/// the upper 16 bits of the address change, and the end of file record.
+fn write_hex(bytecode: &[u8], load_address: usize, w: &mut impl Write) -> io::Result<()> {
+    let mut upper = 0;
+
+    // Records can't cross a 64KiB boundary, so the chunks are aligned to their size.
+    let mut address = load_address;
+    let mut rest = bytecode;
+    while !rest.is_empty() {
+        let size = (HEX_RECORD_SIZE - address % HEX_RECORD_SIZE).min(rest.len());
+        let (chunk, tail) = rest.split_at(size);
+
+        if address >> 16 != upper {
+            upper = address >> 16;
+            write_hex_record(w, 0, 0x04, &(upper as u16).to_be_bytes())?;
+        }
+        write_hex_record(w, address as u16, 0x00, chunk)?;
+
+        address += size;
+        rest = tail;
+    }
+
+    write_hex_record(w, 0, 0x01, &[])
+}
+
+fn write_hex_record(w: &mut impl Write, address: u16, kind: u8, data: &[u8]) -> io::Result<()> {
+    let [hi, lo] = address.to_be_bytes();
+    let header = [data.len() as u8, hi, lo, kind];
+
+    write!(w, ":")?;
+    let mut sum = 0_u8;
+    for byte in header.iter().chain(data) {
+        write!(w, "{byte:02X}")?;
+        sum = sum.wrapping_add(*byte);
+    }
+    writeln!(w, "{:02X}", sum.wrapping_neg())
+}
+
+fn write_c_array(
+    bytecode: &[u8],
+    load_address: usize,
+    name: &str,
+    w: &mut impl Write,
+) -> io::Result<()> {
+    let name = identifier(name).to_lowercase();
+
+    writeln!(w, "#include <stdint.h>")?;
+    writeln!(w)?;
+    writeln!(
+        w,
+        "const uint16_t {name}_load_address = 0x{load_address:03X};"
+    )?;
+    writeln!(w, "const unsigned int {name}_len = {};", bytecode.len())?;
+    writeln!(w, "const uint8_t {name}[] = {{")?;
+    write_array_bytes(bytecode, w)?;
+    writeln!(w, "}};")
+}
+
+fn write_rust_array(
+    bytecode: &[u8],
+    load_address: usize,
+    name: &str,
+    w: &mut impl Write,
+) -> io::Result<()> {
+    let name = identifier(name).to_uppercase();
+
+    writeln!(
+        w,
+        "pub const {name}_LOAD_ADDRESS: u16 = 0x{load_address:03X};"
+    )?;
+    writeln!(w, "pub const {name}: [u8; {}] = [", bytecode.len())?;
+    write_array_bytes(bytecode, w)?;
+    writeln!(w, "];")
+}
+
+/// Indented lines of comma separated hex bytes, shared by C and Rust.
+fn write_array_bytes(bytecode: &[u8], w: &mut impl Write) -> io::Result<()> {
+    for line in bytecode.chunks(ARRAY_LINE_SIZE) {
+        let bytes: Vec<String> = line.iter().map(|byte| format!("0x{byte:02X},")).collect();
+        writeln!(w, "    {}", bytes.join(" "))?;
+    }
+    Ok(())
+}
+
+/// Turn the name into a valid C and Rust identifier.
+fn identifier(name: &str) -> String {
+    let mut ident: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if !ident.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        ident.insert_str(0, "rom_");
+    }
+    ident
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write(format: OutputFormat, bytecode: &[u8], load_address: usize, name: &str) -> String {
+        let mut out = vec![];
+        format
+            .write(bytecode, load_address, name, &mut out)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_output_hex() {
+        let bytecode: Vec<u8> = (0..20).collect();
+        assert_eq!(
+            write(OutputFormat::Hex, &bytecode, 0x204, "test"),
+            "\
+:0C020400000102030405060708090A0BAC
+:080210000C0D0E0F101112136A
+:00000001FF
+"
+        );
+
+        // Programs past 64KiB switch the upper address bits.
+        assert_eq!(
+            write(OutputFormat::Hex, &[0xAA, 0xBB], 0xFFFF, "test"),
+            "\
+:01FFFF00AA57
+:020000040001F9
+:01000000BB44
+:00000001FF
+"
+        );
+    }
+
+    #[test]
+    fn test_output_arrays() {
+        let bytecode = [0x00, 0xE0, 0x12, 0x00];
+        assert_eq!(
+            write(OutputFormat::CArray, &bytecode, 0x200, "my-game"),
+            "\
+#include <stdint.h>
+
+const uint16_t my_game_load_address = 0x200;
+const unsigned int my_game_len = 4;
+const uint8_t my_game[] = {
+    0x00, 0xE0, 0x12, 0x00,
+};
+"
+        );
+        assert_eq!(
+            write(OutputFormat::RustArray, &bytecode, 0x600, "15puzzle"),
+            "\
+pub const ROM_15PUZZLE_LOAD_ADDRESS: u16 = 0x600;
+pub const ROM_15PUZZLE: [u8; 4] = [
+    0x00, 0xE0, 0x12, 0x00,
+];
+"
+        );
+    }
+
+    #[test]
+    fn test_output_names() {
+        for format in OutputFormat::ALL {
+            assert_eq!(format.name().parse(), Ok(format));
+        }
+        assert!("ihex".parse::<OutputFormat>().is_err());
+    }
+}
@@ -1,16 +1,22 @@
 //! Peekable token stream.
-use std::{iter::Peekable, slice::SliceIndex};
+use std::{
+    iter::{Filter, Peekable},
+    slice::SliceIndex,
+};
 
-use crate::error::{Chip8Error, Chip8Result, TokenError};
+use crate::error::{AsmError, Chip8Result};
 
 use super::{lexer::LexerIter, Lexer, Span, Token, TokenKind};
 
+/// Lexed tokens, without trivia like comments.
+type Tokens<'a> = Filter<LexerIter<'a>, fn(&Token) -> bool>;
+
 /// Buffered stream of tokens that allows arbitrary look ahead.
 ///
 /// Tokens are lazily lexed. Peeking or consuming the next token
-/// triggers the internal lexer.
+/// triggers the internal lexer. Trivia tokens are skipped.
 pub struct TokenStream<'a> {
-    lexer: Peekable<LexerIter<'a>>,
+    lexer: Peekable<Tokens<'a>>,
     /// Keep reference to the source so the parser can
     /// slice fragments from it.
     original: &'a str,
@@ -25,12 +31,15 @@ impl<'a> TokenStream<'a> {
     pub fn new(lexer: Lexer<'a>) -> Self {
         Self {
             original: lexer.source_code(),
-            lexer: lexer.into_iter().peekable(),
+            lexer: lexer
+                .into_iter()
+                .filter((|token| !token.kind.is_trivia()) as fn(&Token) -> bool)
+                .peekable(),
             prev: None,
         }
     }
 
-    pub fn source_code(&self) -> &str {
+    pub fn source_code(&self) -> &'a str {
         self.original
     }
 
@@ -108,10 +117,8 @@ impl<'a> TokenStream<'a> {
         match self.lexer.peek() {
             Some(token) => {
                 if token.kind != token_kind {
-                    Err(Chip8Error::from(TokenError {
-                        expected: token_kind,
-                        encountered: token.kind,
-                    }))
+                    let message = format!("expected {:?}, found {:?}", token_kind, token.kind);
+                    Err(AsmError::new(self.original, token.span.clone(), message).into())
                 } else {
                     self.next_token().ok_or_else(|| {
                         // TODO: Change from panic to error
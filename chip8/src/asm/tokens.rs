@@ -19,6 +19,12 @@ pub enum TokenKind {
     Semicolon, // ;
     LeftBracket,  // [
     RightBracket, // ]
+    LeftParen,    // (
+    RightParen,   // )
+    Plus,      // +
+    Minus,     // -
+    Star,      // *
+    Slash,     // /
     /// Line-feed and optionally a carriage return
     Newline,
 
@@ -35,6 +41,8 @@ pub enum TokenKind {
     Number,
     /// Address offset label
     Label,
+    /// Line comment, from the semicolon up to the end of the line
+    Comment,
 
     // ------------------------------------------------------------------------
     // Special
@@ -45,6 +53,12 @@ pub enum TokenKind {
 }
 
 impl TokenKind {
+    /// Tokens that carry no meaning for the parser.
+    #[inline]
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, TokenKind::Comment)
+    }
+
     #[inline]
     pub fn is_vregister(&self) -> bool {
         matches!(self, TokenKind::Register(_))
@@ -257,16 +271,28 @@ pub enum Keyword {
     Random,       // RND
     Return,       // RET
     Xor,          // XOR
+    Plane,        // PLANE
+    Audio,        // AUDIO
+    Pitch,        // PITCH
 
     // ------------------------------------------------------------------------
     // Registers
     Char,      // F
-    Decimal,   // BCD
+    Decimal,   // B, BCD
     Delay,     // DT
     Index,     // I
     Array,     // [I]
     Key,       // K
     Sound,     // ST
+    Long,      // LONG
+
+    // ------------------------------------------------------------------------
+    // Directives
+    Define,    // define
+    Equ,       // EQU
+    Db,        // db
+    Dw,        // dw
+    Sprite,    // sprite
 }
 
 impl Keyword {
@@ -293,13 +319,23 @@ impl Keyword {
             "rand" | "RAND" => Some(Self::Random),
             "ret"  | "RET"  => Some(Self::Return),
             "xor"  | "XOR"  => Some(Self::Xor),
+            "plane" | "PLANE" => Some(Self::Plane),
+            "audio" | "AUDIO" => Some(Self::Audio),
+            "pitch" | "PITCH" => Some(Self::Pitch),
             // ----------------------------------------------------------------
             "F"   => Some(Self::Char),
-            "BCD" => Some(Self::Decimal),
+            "B" | "BCD" => Some(Self::Decimal),
             "DT"  => Some(Self::Delay),
             "I"   => Some(Self::Index),
             "K"   => Some(Self::Key),
             "ST"  => Some(Self::Sound),
+            "LONG" => Some(Self::Long),
+            // ----------------------------------------------------------------
+            "define" | "DEFINE" => Some(Self::Define),
+            "equ"    | "EQU"    => Some(Self::Equ),
+            "db"     | "DB"     => Some(Self::Db),
+            "dw"     | "DW"     => Some(Self::Dw),
+            "sprite" | "SPRITE" => Some(Self::Sprite),
             // ----------------------------------------------------------------
             _ => None,
         }
@@ -330,6 +366,9 @@ impl fmt::Display for Keyword {
             Self::Random => write!(f, "RAND"),
             Self::Return => write!(f, "RET"),
             Self::Xor    => write!(f, "XOR"),
+            Self::Plane  => write!(f, "PLANE"),
+            Self::Audio  => write!(f, "AUDIO"),
+            Self::Pitch  => write!(f, "PITCH"),
             // ----------------------------------------------------------------
             Self::Char   => write!(f, "F"),
             Self::Decimal    => write!(f, "BCD"),
@@ -337,6 +376,13 @@ impl fmt::Display for Keyword {
             Self::Index  => write!(f, "I"),
             Self::Key    => write!(f, "K"),
             Self::Sound  => write!(f, "ST"),
+            Self::Long   => write!(f, "LONG"),
+            // ----------------------------------------------------------------
+            Self::Define => write!(f, "define"),
+            Self::Equ    => write!(f, "EQU"),
+            Self::Db     => write!(f, "db"),
+            Self::Dw     => write!(f, "dw"),
+            Self::Sprite => write!(f, "sprite"),
             // ----------------------------------------------------------------
             _ => Ok(())
         }
@@ -368,8 +414,18 @@ impl Number {
 pub enum Addr {
     /// 12-bit number literal.
     Num(Number),
-    /// Line label.
-    Label(Token),
+    /// Line labels, added or subtracted, and offset by a constant.
+    Label { labels: Vec<LabelTerm>, offset: i32 },
+}
+
+/// Label in an expression, with the sign it's added with.
+///
+/// The difference between two labels, like `.end - .start`, has
+/// a term with a factor of `1` and one with a factor of `-1`.
+#[derive(Debug, Clone)]
+pub struct LabelTerm {
+    pub token: Token,
+    pub factor: i32,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -0,0 +1,265 @@
+//! VM running on its own thread, driven through channels.
+//!
+//! Frontends that can't block on the emulation, like async GUI frameworks
+//! and network servers, send [`VmCommand`]s and poll for [`VmEvent`]s
+//! instead of calling into the VM directly.
+//!
+//! ```
+//! use std::time::Duration;
+//! use chip8::{Chip8AsyncVm, Chip8Conf, VmEvent};
+//!
+//! let rom = chip8::assemble("
+//!     LD  I, 0x200
+//!     DRW v0, v0, 1
+//! .end
+//!     JP  .end
+//! ").unwrap();
+//!
+//! let vm = Chip8AsyncVm::spawn(Chip8Conf::default(), &rom).unwrap();
+//! match vm.recv_event_timeout(Duration::from_secs(5)) {
+//!     Some(VmEvent::Frame(frame)) => assert_ne!(frame.rows[0], 0),
+//!     event => panic!("expected a frame, got {event:?}"),
+//! }
+//! ```
+use std::{
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    constants::{CLOCK_CYCLE_TIME, DISPLAY_HEIGHT},
+    devices::{KeyCode, KeyState},
+    error::{Chip8Error, Chip8Result},
+    vm::{Chip8Conf, Chip8Vm, Flow, TimerEvent},
+};
+
+/// Time between frames, at 60Hz.
+const FRAME_TIME: Duration = Duration::from_nanos(CLOCK_CYCLE_TIME);
+
+/// Request to the VM thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmCommand {
+    /// Press or release a key of the keypad.
+    Key(KeyCode, KeyState),
+    /// Freeze emulation, until resumed or stepped.
+    Pause,
+    Resume,
+    /// Execute one frame while paused.
+    StepFrame,
+    /// Execute one instruction while paused.
+    StepInstr,
+    /// End the thread.
+    Stop,
+}
+
+/// Notification from the VM thread.
+#[derive(Debug)]
+pub enum VmEvent {
+    /// The display was drawn to.
+    Frame(DisplayFrame),
+    /// The buzzer turned on or off.
+    Sound(bool),
+    /// The program stopped, with the error that stopped it, if any.
+    /// No more events are sent.
+    Stopped(Option<Chip8Error>),
+}
+
+/// Contents of the display after a frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayFrame {
+    /// Number of frames executed by the VM.
+    pub number: u64,
+    /// Rows of pixels, packed with the leftmost pixel in the most significant bit,
+    /// see [`Chip8Vm::to_packed_u64_rows`].
+    pub rows: [u64; DISPLAY_HEIGHT],
+}
+
+/// Handle to a VM running on a dedicated thread.
+///
+/// The thread paces frames at 60Hz, and stops when the program is
+/// interrupted or fails, when [`VmCommand::Stop`] is sent, or when
+/// the handle is dropped.
+pub struct Chip8AsyncVm {
+    commands: Sender<VmCommand>,
+    events: Receiver<VmEvent>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Chip8AsyncVm {
+    /// Start a thread running the program.
+    ///
+    /// The VM is created on the thread, so its callbacks don't have to be `Send`.
+    /// Fails when the program can't be loaded.
+    pub fn spawn(conf: Chip8Conf, rom: &[u8]) -> Chip8Result<Self> {
+        let (command_tx, command_rx) = mpsc::channel();
+        let (event_tx, event_rx) = mpsc::channel();
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let rom = rom.to_vec();
+
+        let thread = thread::Builder::new()
+            .name("chip8-vm".to_string())
+            .spawn(move || {
+                let mut vm = Chip8Vm::new(conf);
+                if let Err(err) = vm.load_bytecode(&rom) {
+                    let _ = ready_tx.send(Err(err));
+                    return;
+                }
+                let _ = ready_tx.send(Ok(()));
+                run(vm, command_rx, event_tx);
+            })?;
+
+        // The thread only hangs up without a result if it panicked.
+        ready_rx
+            .recv()
+            .unwrap_or_else(|_| panic!("chip8 vm thread failed to start"))?;
+
+        Ok(Self {
+            commands: command_tx,
+            events: event_rx,
+            thread: Some(thread),
+        })
+    }
+
+    /// Send a command to the VM thread.
+    ///
+    /// Commands sent after the thread stopped are ignored.
+    pub fn send(&self, command: VmCommand) {
+        let _ = self.commands.send(command);
+    }
+
+    pub fn push_key_event(&self, key: KeyCode, state: KeyState) {
+        self.send(VmCommand::Key(key, state));
+    }
+
+    pub fn pause(&self) {
+        self.send(VmCommand::Pause);
+    }
+
+    pub fn resume(&self) {
+        self.send(VmCommand::Resume);
+    }
+
+    /// Next event, without blocking.
+    pub fn try_event(&self) -> Option<VmEvent> {
+        self.events.try_recv().ok()
+    }
+
+    /// Next event, blocking until one arrives or the time runs out.
+    pub fn recv_event_timeout(&self, timeout: Duration) -> Option<VmEvent> {
+        self.events.recv_timeout(timeout).ok()
+    }
+
+    /// Sender of commands, for sending from other threads.
+    pub fn command_sender(&self) -> Sender<VmCommand> {
+        self.commands.clone()
+    }
+
+    /// Receiver of the events, for frontends that select over channels.
+    pub fn events(&self) -> &Receiver<VmEvent> {
+        &self.events
+    }
+
+    /// Stop the thread, and wait for it to finish.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.send(VmCommand::Stop);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                tracing::error!("chip8 vm thread panicked");
+            }
+        }
+    }
+}
+
+impl Drop for Chip8AsyncVm {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Loop of the VM thread.
+fn run(mut vm: Chip8Vm, commands: Receiver<VmCommand>, events: Sender<VmEvent>) {
+    let sound = events.clone();
+    vm.set_timer_hook(move |event| match event {
+        TimerEvent::SoundOn => drop(sound.send(VmEvent::Sound(true))),
+        TimerEvent::SoundOff => drop(sound.send(VmEvent::Sound(false))),
+        TimerEvent::DelayExpired => {}
+    });
+
+    let mut paused = false;
+    let mut next_frame = Instant::now();
+
+    loop {
+        // Wait for commands until the next frame is due, or indefinitely while paused.
+        let command = if paused {
+            commands.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            let timeout = next_frame.saturating_duration_since(Instant::now());
+            commands.recv_timeout(timeout)
+        };
+
+        let stepped = match command {
+            Ok(VmCommand::Key(key, state)) => {
+                vm.push_key_event(key, state);
+                continue;
+            }
+            Ok(VmCommand::Pause) => {
+                paused = true;
+                continue;
+            }
+            Ok(VmCommand::Resume) => {
+                paused = false;
+                next_frame = Instant::now();
+                continue;
+            }
+            Ok(VmCommand::StepFrame) if paused => vm.run_frame().map(|report| report.interrupt),
+            Ok(VmCommand::StepInstr) if paused => {
+                vm.debugger().step().map(|flow| flow == Flow::Interrupt)
+            }
+            Ok(VmCommand::StepFrame | VmCommand::StepInstr) => continue,
+            Ok(VmCommand::Stop) | Err(RecvTimeoutError::Disconnected) => return,
+            Err(RecvTimeoutError::Timeout) => {
+                next_frame += FRAME_TIME;
+                // Don't try to catch up after falling behind.
+                if next_frame < Instant::now() {
+                    next_frame = Instant::now() + FRAME_TIME;
+                }
+
+                vm.run_frame().map(|report| {
+                    if report.draw {
+                        send_frame(&vm, &events);
+                    }
+                    report.interrupt
+                })
+            }
+        };
+
+        match stepped {
+            Ok(false) => {
+                // Steps always show their result.
+                if paused {
+                    send_frame(&vm, &events);
+                }
+            }
+            Ok(true) => {
+                let _ = events.send(VmEvent::Stopped(None));
+                return;
+            }
+            Err(err) => {
+                let _ = events.send(VmEvent::Stopped(Some(err)));
+                return;
+            }
+        }
+    }
+}
+
+fn send_frame(vm: &Chip8Vm, events: &Sender<VmEvent>) {
+    let _ = events.send(VmEvent::Frame(DisplayFrame {
+        number: vm.frame(),
+        rows: vm.to_packed_u64_rows(),
+    }));
+}
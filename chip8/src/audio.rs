@@ -0,0 +1,277 @@
+//! Sound synthesis of the buzzer, and playback of the XO-CHIP audio pattern.
+//!
+//! The VM only keeps the state of its sound hardware, see [`Chip8Vm::audio_state`].
+//! Frontends turn the state into samples with a [`Synth`], configured by
+//! [`Chip8Conf::audio`], and feed them to their audio device.
+//!
+//! [`Chip8Vm::audio_state`]: crate::Chip8Vm::audio_state
+//! [`Chip8Conf::audio`]: crate::Chip8Conf::audio
+use crate::{
+    constants::{AUDIO_PATTERN_SIZE, DEFAULT_PITCH},
+    vm::Hz,
+};
+
+/// Number of bits in the audio pattern, played back one bit per step.
+const PATTERN_BITS: usize = AUDIO_PATTERN_SIZE * 8;
+
+/// Playback rate of the audio pattern at the default pitch, in bits per second.
+const PATTERN_RATE: f64 = 4000.0;
+
+/// Shape of the buzzer tone.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Waveform {
+    #[default]
+    Square,
+    Triangle,
+    /// Random levels, changing twice per period of the tone.
+    Noise,
+}
+
+/// Sound of the buzzer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct AudioConf {
+    /// Shape of the tone played while the sound timer counts down.
+    /// Default: [`Waveform::Square`]
+    pub waveform: Waveform,
+    /// Pitch of the tone.
+    /// Default: 440Hz
+    pub tone: Hz,
+    /// Loudness, from `0.0` for silence to `1.0` for full scale.
+    /// Default: `0.25`
+    pub volume: f32,
+    /// Play the XO-CHIP audio pattern instead of the tone, once a program
+    /// loads one with `F002 (AUDIO)`.
+    /// Default: `true`
+    pub patterns: bool,
+}
+
+impl Default for AudioConf {
+    fn default() -> Self {
+        Self {
+            waveform: Waveform::default(),
+            tone: Hz(440),
+            volume: 0.25,
+            patterns: true,
+        }
+    }
+}
+
+/// State of the sound hardware of the VM.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AudioState {
+    /// The sound timer is counting down.
+    pub buzzer: bool,
+    /// XO-CHIP audio pattern, when one was loaded by `F002 (AUDIO)`.
+    pub pattern: Option<[u8; AUDIO_PATTERN_SIZE]>,
+    /// XO-CHIP pitch register, set by `Fx3A (PITCH Vx)`.
+    pub pitch: u8,
+}
+
+impl AudioState {
+    /// Playback rate of the audio pattern at the pitch, in bits per second.
+    pub fn pattern_rate(&self) -> f64 {
+        PATTERN_RATE * 2f64.powf((self.pitch as f64 - DEFAULT_PITCH as f64) / 48.0)
+    }
+}
+
+/// Generator of the samples of the buzzer.
+///
+/// The position in the waveform carries over between calls to
+/// [`Synth::render`], so buffers can be rendered back to back.
+#[derive(Debug, Clone)]
+pub struct Synth {
+    conf: AudioConf,
+    sample_rate: u32,
+    /// Position in the tone, in periods, or in the pattern, in bits.
+    phase: f64,
+    /// State of the noise generator.
+    noise: u32,
+    /// Current level of the noise.
+    noise_level: f32,
+}
+
+impl Synth {
+    pub fn new(conf: AudioConf, sample_rate: u32) -> Self {
+        Self {
+            conf,
+            sample_rate: sample_rate.max(1),
+            phase: 0.0,
+            noise: 0x9E37_79B9,
+            noise_level: 1.0,
+        }
+    }
+
+    pub fn conf(&self) -> &AudioConf {
+        &self.conf
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Fill the buffer with mono samples between `-1.0` and `1.0`,
+    /// playing the sound hardware in the given state.
+    pub fn render(&mut self, state: &AudioState, buffer: &mut [f32]) {
+        if !state.buzzer {
+            buffer.fill(0.0);
+            return;
+        }
+
+        match state.pattern.filter(|_| self.conf.patterns) {
+            Some(pattern) => self.render_pattern(&pattern, state.pattern_rate(), buffer),
+            None => self.render_tone(buffer),
+        }
+    }
+
+    /// The pattern is a 1-bit waveform, played from the most significant bit of the first byte.
+    fn render_pattern(
+        &mut self,
+        pattern: &[u8; AUDIO_PATTERN_SIZE],
+        rate: f64,
+        buffer: &mut [f32],
+    ) {
+        let step = rate / self.sample_rate as f64;
+        for sample in buffer {
+            let bit = self.phase as usize % PATTERN_BITS;
+            let on = pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+            *sample = if on {
+                self.conf.volume
+            } else {
+                -self.conf.volume
+            };
+            self.phase = (self.phase + step) % PATTERN_BITS as f64;
+        }
+    }
+
+    fn render_tone(&mut self, buffer: &mut [f32]) {
+        let step = self.conf.tone.0 as f64 / self.sample_rate as f64;
+        for sample in buffer {
+            let level = match self.conf.waveform {
+                Waveform::Square if self.phase < 0.5 => 1.0,
+                Waveform::Square => -1.0,
+                Waveform::Triangle => (4.0 * (self.phase - 0.5).abs() - 1.0) as f32,
+                Waveform::Noise => self.noise_level,
+            };
+            *sample = level * self.conf.volume;
+
+            let half = self.phase < 0.5;
+            self.phase = (self.phase + step).fract();
+            if half != (self.phase < 0.5) {
+                self.next_noise();
+            }
+        }
+    }
+
+    fn next_noise(&mut self) {
+        // xorshift32
+        self.noise ^= self.noise << 13;
+        self.noise ^= self.noise >> 17;
+        self.noise ^= self.noise << 5;
+        self.noise_level = (self.noise >> 8) as f32 / (1 << 23) as f32 - 1.0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn state(pattern: Option<[u8; AUDIO_PATTERN_SIZE]>, pitch: u8) -> AudioState {
+        AudioState {
+            buzzer: true,
+            pattern,
+            pitch,
+        }
+    }
+
+    #[test]
+    fn test_pattern_rate() {
+        assert_eq!(state(None, DEFAULT_PITCH).pattern_rate(), 4000.0);
+        assert_eq!(state(None, DEFAULT_PITCH + 48).pattern_rate(), 8000.0);
+        assert_eq!(state(None, DEFAULT_PITCH - 48).pattern_rate(), 2000.0);
+    }
+
+    #[test]
+    fn test_synth_pattern() {
+        let conf = AudioConf {
+            volume: 1.0,
+            ..Default::default()
+        };
+        // One sample per bit.
+        let mut synth = Synth::new(conf, 4000);
+        let mut pattern = [0; AUDIO_PATTERN_SIZE];
+        pattern[0] = 0b1010_0000;
+
+        let mut buffer = [0.0; PATTERN_BITS + 4];
+        synth.render(&state(Some(pattern), DEFAULT_PITCH), &mut buffer);
+        assert_eq!(buffer[..4], [1.0, -1.0, 1.0, -1.0]);
+        assert!(buffer[4..PATTERN_BITS].iter().all(|sample| *sample == -1.0));
+        // The pattern loops.
+        assert_eq!(buffer[PATTERN_BITS..], [1.0, -1.0, 1.0, -1.0]);
+
+        // Without the pattern, the tone is played instead.
+        let mut synth = Synth::new(
+            AudioConf {
+                patterns: false,
+                ..conf
+            },
+            4000,
+        );
+        synth.render(&state(Some(pattern), DEFAULT_PITCH), &mut buffer);
+        assert!(buffer[..4].iter().all(|sample| *sample == 1.0));
+
+        // Silent while the buzzer is off.
+        let off = AudioState {
+            buzzer: false,
+            ..state(Some(pattern), DEFAULT_PITCH)
+        };
+        synth.render(&off, &mut buffer);
+        assert!(buffer.iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn test_synth_waveforms() {
+        for waveform in [Waveform::Square, Waveform::Triangle, Waveform::Noise] {
+            let conf = AudioConf {
+                waveform,
+                tone: Hz(100),
+                volume: 0.5,
+                patterns: true,
+            };
+            let mut synth = Synth::new(conf, 8000);
+            let mut buffer = [0.0; 800];
+            synth.render(&state(None, DEFAULT_PITCH), &mut buffer);
+
+            assert!(
+                buffer.iter().all(|sample| sample.abs() <= 0.5),
+                "{waveform:?}"
+            );
+            assert!(buffer.iter().any(|sample| *sample > 0.0), "{waveform:?}");
+            assert!(buffer.iter().any(|sample| *sample < 0.0), "{waveform:?}");
+        }
+
+        // A square wave of 1kHz changes level every 4 samples at 8kHz.
+        let mut synth = Synth::new(
+            AudioConf {
+                tone: Hz(1000),
+                volume: 1.0,
+                ..Default::default()
+            },
+            8000,
+        );
+        let mut buffer = [0.0; 12];
+        synth.render(&state(None, DEFAULT_PITCH), &mut buffer);
+        assert_eq!(buffer[..4], [1.0; 4]);
+        assert_eq!(buffer[4..8], [-1.0; 4]);
+        assert_eq!(buffer[8..], [1.0; 4]);
+    }
+}
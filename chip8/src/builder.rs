@@ -0,0 +1,219 @@
+//! Construction of a VM with a loaded ROM.
+use std::path::Path;
+
+use crate::{
+    archive::RomArchive,
+    debug_info::DebugInfo,
+    error::Chip8Result,
+    quirks::Quirks,
+    rom_db,
+    rom_info::RomInfo,
+    variant::Variant,
+    vm::{Chip8Conf, Chip8Vm, Hz},
+};
+
+/// Builder of a VM with a ROM loaded, configured by the ROM's descriptor.
+///
+/// Settings are layered, with later layers taking precedence:
+///
+/// 1. The base configuration, see [`Chip8VmBuilder::with_conf`].
+/// 2. The ROM database, when enabled by [`Chip8Conf::use_rom_db`].
+/// 3. The ROM descriptor, see [`RomInfo`].
+/// 4. Explicit variant, quirks and clock frequency, like command line options.
+///    Explicit quirks take precedence over the quirks of the variant.
+///
+/// ```
+/// # use chip8::{Chip8VmBuilder, RomInfo, Quirks};
+/// let info = RomInfo::parse("profile = \"vip\"\nclock_hz = 700\n").unwrap();
+/// let vm = Chip8VmBuilder::new()
+///     .with_rom(&[0x12, 0x00])
+///     .with_rom_info(info)
+///     .build()
+///     .unwrap();
+/// assert_eq!(vm.config().quirks, Quirks::COSMAC_VIP);
+/// ```
+#[derive(Default)]
+pub struct Chip8VmBuilder {
+    conf: Chip8Conf,
+    rom: Vec<u8>,
+    info: Option<RomInfo>,
+    debug_info: Option<DebugInfo>,
+    variant: Option<Variant>,
+    quirks: Option<Quirks>,
+    clock_frequency: Option<Hz>,
+}
+
+impl Chip8VmBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Base configuration, overridden by the ROM descriptor.
+    pub fn with_conf(mut self, conf: Chip8Conf) -> Self {
+        self.conf = conf;
+        self
+    }
+
+    /// Bytecode of the program.
+    pub fn with_rom(mut self, bytecode: &[u8]) -> Self {
+        self.rom = bytecode.to_vec();
+        self
+    }
+
+    /// Load the program from a file, and its descriptor when one sits next to it.
+    ///
+    /// Files with the `.asm` extension are assembled, and archives bring
+    /// their own descriptor and debug information, see [`RomArchive`].
+    /// The descriptor of `game.ch8` is `game.ch8.toml`, see
+    /// [`RomInfo::sidecar_path`], and takes precedence over an archived one.
+    ///
+    /// Source files are assembled for the load address of the base configuration,
+    /// so it must be set before.
+    pub fn with_rom_file(mut self, path: impl AsRef<Path>) -> Chip8Result<Self> {
+        let path = path.as_ref();
+        let archive = RomArchive::read_file_at(path, self.conf.load_address())?;
+        self.rom = archive.rom;
+        self.info = archive.info.or(self.info);
+        self.debug_info = archive.debug_info;
+        if let Some(info) = RomInfo::discover(path)? {
+            tracing::info!(
+                "loading rom info: {}",
+                RomInfo::sidecar_path(path).display()
+            );
+            self.info = Some(info);
+        }
+        Ok(self)
+    }
+
+    /// Descriptor of the program, replacing a discovered one.
+    pub fn with_rom_info(mut self, info: RomInfo) -> Self {
+        self.info = Some(info);
+        self
+    }
+
+    /// Variant, with its instruction set and quirks, that takes precedence
+    /// over the ROM descriptor.
+    pub fn with_variant(mut self, variant: Variant) -> Self {
+        self.variant = Some(variant);
+        self
+    }
+
+    /// Quirks that take precedence over the ROM descriptor.
+    pub fn with_quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = Some(quirks);
+        self
+    }
+
+    /// Clock frequency that takes precedence over the ROM descriptor.
+    pub fn with_clock_frequency(mut self, clock_frequency: Hz) -> Self {
+        self.clock_frequency = Some(clock_frequency);
+        self
+    }
+
+    /// Bytecode of the program.
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    /// Descriptor of the program, if any.
+    pub fn rom_info(&self) -> Option<&RomInfo> {
+        self.info.as_ref()
+    }
+
+    /// Debug information of the program, when it was assembled or archived.
+    pub fn debug_info(&self) -> Option<&DebugInfo> {
+        self.debug_info.as_ref()
+    }
+
+    /// Configuration with all the layers applied.
+    pub fn conf(&self) -> Chip8Result<Chip8Conf> {
+        let mut conf = self.conf.clone();
+
+        // Applied here instead of when loading, so the descriptor takes precedence.
+        if conf.use_rom_db {
+            if let Some(entry) = rom_db::lookup(&self.rom) {
+                entry.apply(&mut conf);
+            }
+            conf.use_rom_db = false;
+        }
+
+        if let Some(info) = &self.info {
+            if let Some(quirks) = info.quirks()? {
+                conf.quirks = quirks;
+            }
+            if let Some(clock_hz) = info.clock_hz {
+                conf.clock_frequency = Some(Hz(clock_hz));
+            }
+        }
+        if let Some(variant) = self.variant {
+            conf.variant = Some(variant);
+            conf.quirks = variant.quirks();
+        }
+        if let Some(quirks) = self.quirks {
+            conf.quirks = quirks;
+        }
+        if let Some(clock_frequency) = self.clock_frequency {
+            conf.clock_frequency = Some(clock_frequency);
+        }
+
+        Ok(conf)
+    }
+
+    /// Create the VM and load the program.
+    pub fn build(self) -> Chip8Result<Chip8Vm> {
+        let mut vm = Chip8Vm::new(self.conf()?);
+        vm.load_bytecode(&self.rom)?;
+        Ok(vm)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_builder_layers() {
+        let info = RomInfo {
+            profile: Some("schip".to_string()),
+            clock_hz: Some(700),
+            ..Default::default()
+        };
+        let builder = Chip8VmBuilder::new()
+            .with_conf(Chip8Conf {
+                quirks: Quirks::COSMAC_VIP,
+                clock_frequency: Some(Hz(500)),
+                ..Default::default()
+            })
+            .with_rom_info(info.clone());
+
+        let conf = builder.conf().unwrap();
+        assert_eq!(conf.quirks, Quirks::SUPER_CHIP);
+        assert_eq!(conf.clock_frequency, Some(Hz(700)));
+
+        // Explicit settings win over the descriptor, and explicit quirks over the variant.
+        let conf = Chip8VmBuilder::new()
+            .with_rom_info(info.clone())
+            .with_variant(Variant::XoChip)
+            .conf()
+            .unwrap();
+        assert_eq!(conf.variant, Some(Variant::XoChip));
+        assert_eq!(conf.quirks, Quirks::XO_CHIP);
+
+        let conf = builder
+            .with_variant(Variant::Chip8)
+            .with_quirks(Quirks::CHIP48)
+            .with_clock_frequency(Hz(1000))
+            .conf()
+            .unwrap();
+        assert_eq!(conf.variant, Some(Variant::Chip8));
+        assert_eq!(conf.quirks, Quirks::CHIP48);
+        assert_eq!(conf.clock_frequency, Some(Hz(1000)));
+
+        // Invalid descriptors fail to build.
+        let info = RomInfo {
+            profile: Some("xo".to_string()),
+            ..info
+        };
+        assert!(Chip8VmBuilder::new().with_rom_info(info).build().is_err());
+    }
+}
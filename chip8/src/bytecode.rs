@@ -1,10 +1,15 @@
-use log::trace;
+use tracing::trace;
 
 /// Helpers for extracting data from opcodes.
 use crate::constants::*;
 
 #[rustfmt::skip]
 pub mod opcodes {
+    /// 0nnn (SYS addr)
+    ///
+    /// Jump to a machine code routine at `nnn`.
+    /// Ignored by modern interpreters.
+    pub const SYS_ADDR: u8   = 0x0;
     /// 00E0 (CLS)
     ///
     /// Clear the screen.
@@ -89,12 +94,28 @@ pub mod opcodes {
     pub const LD_ARR_VX: [u8; 2]    = [0xF, 0x55];
     /// Fx65 (LD Vx, [I])
     pub const LD_VX_ARR: [u8; 2]    = [0xF, 0x65];
+    /// F000 nnnn (LD I, LONG addr)
+    ///
+    /// XO-CHIP: Followed by a 16-bit address.
+    pub const LD_I_LONG: [u8; 2]    = [0xF0, 0x00];
+    /// Fn01 (PLANE n)
+    ///
+    /// XO-CHIP: Select display planes.
+    pub const PLANE_N: [u8; 2]      = [0xF, 0x01];
+    /// F002 (AUDIO)
+    ///
+    /// XO-CHIP: Load audio pattern buffer from I.
+    pub const AUDIO: [u8; 2]        = [0xF0, 0x02];
+    /// Fx3A (PITCH Vx)
+    ///
+    /// XO-CHIP: Set the playback rate of the audio pattern.
+    pub const PITCH_VX: [u8; 2]     = [0xF, 0x3A];
 }
 
-/// Returns true if the program can fit in VM memory.
+/// Returns true if the program can fit in VM memory, loaded at the address.
 #[inline]
-pub(crate) fn check_program_size(program: &[u8]) -> bool {
-    program.len() <= (MEM_SIZE - MEM_START)
+pub(crate) fn check_program_size(program: &[u8], load_address: usize) -> bool {
+    load_address <= MEM_SIZE && program.len() <= (MEM_SIZE - load_address)
 }
 
 /// Extract opcode from the buffer at the cursor.
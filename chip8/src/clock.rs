@@ -1,8 +1,11 @@
 //! CPU Clock.
-use std::{
-    thread,
-    time::{Duration, Instant},
-};
+use std::{thread, time::Duration};
+
+// The standard clock panics in the browser.
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
 
 /// Timer to synchronize thread with the software clock of the virtual CPU.
 ///
@@ -0,0 +1,224 @@
+//! Configuration files of the VM.
+//!
+//! Frontends share one file format for the settings of [`Chip8Conf`],
+//! written in TOML or YAML with the names of its fields:
+//!
+//! ```toml
+//! clock_frequency = 700
+//! variant = "schip"
+//! self_modify = "warn"
+//!
+//! [quirks]            # a profile, and overrides of it
+//! profile = "vip"
+//! clip_sprites = false
+//!
+//! [rewind]
+//! capacity = 600
+//! interval = 10
+//! ```
+//!
+//! Missing fields keep their default value, while unknown fields
+//! and quirks are reported as errors.
+use std::{fs, path::Path};
+
+use crate::{
+    constants::{MEM_SIZE, STACK_SIZE},
+    error::{Chip8Error, Chip8Result},
+    vm::Chip8Conf,
+};
+
+/// Format of a configuration file, by its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    fn of(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "toml" => Some(Self::Toml),
+            "yaml" | "yml" => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+}
+
+impl Chip8Conf {
+    /// Load a configuration file, in TOML or YAML by its extension.
+    ///
+    /// ```
+    /// # use chip8::{Chip8Conf, Hz, Quirks, Variant};
+    /// let path = std::env::temp_dir().join("chip8_doc_conf.yaml");
+    /// std::fs::write(&path, "clock_frequency: 700\nvariant: chip48\nquirks: chip48\n").unwrap();
+    ///
+    /// let conf = Chip8Conf::from_file(&path).unwrap();
+    /// assert_eq!(conf.clock_frequency, Some(Hz(700)));
+    /// assert_eq!(conf.variant, Some(Variant::Chip48));
+    /// assert_eq!(conf.quirks, Quirks::CHIP48);
+    /// # std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn from_file(path: impl AsRef<Path>) -> Chip8Result<Self> {
+        let path = path.as_ref();
+        let format = Format::of(path).ok_or_else(|| {
+            config_error(format!(
+                "unknown format of '{}', expected a .toml, .yaml or .yml file",
+                path.display()
+            ))
+        })?;
+
+        let text = fs::read_to_string(path)?;
+        match format {
+            Format::Toml => Self::from_toml(&text),
+            Format::Yaml => Self::from_yaml(&text),
+        }
+    }
+
+    pub fn from_toml(text: &str) -> Chip8Result<Self> {
+        let conf: Self = toml::from_str(text).map_err(config_error)?;
+        conf.validate()?;
+        Ok(conf)
+    }
+
+    pub fn from_yaml(text: &str) -> Chip8Result<Self> {
+        let conf: Self = serde_yaml::from_str(text).map_err(config_error)?;
+        conf.validate()?;
+        Ok(conf)
+    }
+
+    /// Write the configuration in TOML.
+    pub fn to_toml(&self) -> Chip8Result<String> {
+        toml::to_string(self).map_err(config_error)
+    }
+
+    /// Check the values that the types of the fields can't.
+    pub fn validate(&self) -> Chip8Result<()> {
+        if let Some(address) = self.load_address {
+            if address >= MEM_SIZE {
+                return Err(config_error(format!(
+                    "load address 0x{address:X} is outside of memory"
+                )));
+            }
+        }
+        if let Some(stack_size) = self.stack_size {
+            if stack_size == 0 || stack_size >= STACK_SIZE {
+                return Err(config_error(format!(
+                    "stack size {stack_size} must be between 1 and {}",
+                    STACK_SIZE - 1
+                )));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.audio.volume) {
+            return Err(config_error(format!(
+                "volume {} must be between 0.0 and 1.0",
+                self.audio.volume
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[inline(never)]
+#[cold]
+fn config_error(message: impl ToString) -> Chip8Error {
+    Chip8Error::Config(message.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        audio::Waveform,
+        devices::{HeldKeys, KeyWait},
+        quirks::{Quirks, SpriteEdge},
+        variant::Variant,
+        vm::{Hz, SelfModifyPolicy},
+    };
+
+    #[test]
+    fn test_conf_formats() {
+        let toml = r#"
+clock_frequency = 700
+variant = "schip"
+self_modify = "warn"
+
+[quirks]
+clip_sprites = false
+profile = "vip"
+
+[rewind]
+capacity = 600
+
+[audio]
+waveform = "noise"
+
+[keys]
+held = "latest"
+"#;
+        let yaml = "
+clock_frequency: 700
+variant: schip
+self_modify: warn
+quirks:
+  clip_sprites: false
+  profile: vip
+rewind:
+  capacity: 600
+audio:
+  waveform: noise
+keys:
+  held: latest
+";
+        for conf in [Chip8Conf::from_toml(toml), Chip8Conf::from_yaml(yaml)] {
+            let conf = conf.unwrap();
+            assert_eq!(conf.clock_frequency, Some(Hz(700)));
+            assert_eq!(conf.variant, Some(Variant::SuperChip));
+            assert_eq!(conf.self_modify, SelfModifyPolicy::Warn);
+            // The profile applies first, wherever it's written.
+            assert_eq!(
+                conf.quirks,
+                Quirks {
+                    sprite_edge: SpriteEdge::Wrap,
+                    ..Quirks::COSMAC_VIP
+                }
+            );
+            assert_eq!(conf.rewind.capacity, 600);
+            assert_eq!(conf.rewind.interval, 0);
+            assert_eq!(conf.audio.waveform, Waveform::Noise);
+            assert_eq!(conf.audio.tone, Hz(440));
+            assert_eq!(conf.keys.held, HeldKeys::Latest);
+            assert_eq!(conf.keys.wait, KeyWait::Release);
+
+            let again = Chip8Conf::from_toml(&conf.to_toml().unwrap()).unwrap();
+            assert_eq!(again.quirks, conf.quirks);
+            assert_eq!(again.variant, conf.variant);
+        }
+    }
+
+    #[test]
+    fn test_conf_invalid() {
+        for (text, message) in [
+            ("speed = 700", "unknown field `speed`"),
+            ("quirks = \"xo\"", "unknown quirks profile 'xo'"),
+            (
+                "[quirks]\nwrap_sprites = true",
+                "unknown quirk 'wrap_sprites'",
+            ),
+            ("variant = \"chip-8\"", "unknown variant `chip-8`"),
+            ("load_address = 0x1000", "outside of memory"),
+            ("stack_size = 0", "stack size 0"),
+            ("[audio]\nvolume = 2.0", "volume 2 must be"),
+        ] {
+            match Chip8Conf::from_toml(text) {
+                Err(Chip8Error::Config(err)) => assert!(err.contains(message), "{err}"),
+                Err(err) => panic!("{text:?}: {err}"),
+                Ok(_) => panic!("invalid config must fail: {text:?}"),
+            }
+        }
+
+        assert!(matches!(
+            Chip8Conf::from_file("chip8.ini"),
+            Err(Chip8Error::Config(_))
+        ));
+    }
+}
@@ -8,6 +8,10 @@ pub const REGISTER_COUNT: usize = 0x10; // 16
 pub const MEM_START: usize = 0x200; // 512
 pub const MEM_SIZE: usize = 0x1000; // 4096
 
+/// Programs for the ETI-660 load at a higher address, after
+/// its larger interpreter.
+pub const ETI660_START: usize = 0x600; // 1536
+
 /// Levels of nesting allowed in the call stack.
 ///
 /// The original RCA 1802 implementation allocated 48 bytes
@@ -20,6 +24,11 @@ pub const MEM_SIZE: usize = 0x1000; // 4096
 /// the stack pointer.
 pub const STACK_SIZE: usize = 0xFF;
 
+/// Default maximum depth of nested subroutine calls, as in modern interpreters.
+///
+/// The original COSMAC VIP interpreter had room for 12 levels.
+pub const DEFAULT_STACK_DEPTH: usize = 16;
+
 pub const DISPLAY_WIDTH: usize = 64;
 pub const DISPLAY_HEIGHT: usize = 32;
 pub const DISPLAY_SIZE: [usize; 2] = [DISPLAY_WIDTH, DISPLAY_HEIGHT];
@@ -27,6 +36,18 @@ pub const DISPLAY_BUFFER_SIZE: usize = DISPLAY_WIDTH * DISPLAY_HEIGHT;
 pub const DISPLAY_WIDTH_MASK: usize = DISPLAY_WIDTH - 1;
 pub const DISPLAY_HEIGHT_MASK: usize = DISPLAY_HEIGHT - 1;
 
+/// Number of display bitplanes, as introduced by XO-CHIP.
+pub const PLANE_COUNT: usize = 2;
+
+/// Bitmask of the planes selected by default, which is only the first plane.
+pub const PLANE_DEFAULT: u8 = 0b01;
+
+/// Size of the XO-CHIP audio pattern buffer in bytes.
+pub const AUDIO_PATTERN_SIZE: usize = 16;
+/// Initial value of the XO-CHIP pitch register, which plays
+/// the audio pattern at 4000 bits per second.
+pub const DEFAULT_PITCH: u8 = 64;
+
 /// Number of clock cycles in a second that delay timers count down.
 pub const DELAY_FREQUENCY: u64 = 60;
 
@@ -34,12 +55,22 @@ pub const DELAY_FREQUENCY: u64 = 60;
 #[doc(hidden)]
 pub const NANOS_IN_SECOND: u64 = 1_000_000_000;
 
+/// Number of instructions executed per 60Hz frame,
+/// when no clock frequency is configured.
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: usize = 10;
+
 /// Time in nanoseconds a single clock cycle takes, precalculated.
 pub const CLOCK_CYCLE_TIME: u64 = NANOS_IN_SECOND / DELAY_FREQUENCY;
 
 /// Number of keys ob the keyboard (0x0-0xF)
 pub const KEY_COUNT: u8 = 16;
 
+/// Maximum number of queued key events.
+///
+/// The oldest events are discarded when the program
+/// doesn't consume them fast enough.
+pub const KEY_EVENT_CAPACITY: usize = 32;
+
 /// Memory location where the fontset starts.
 pub const FONTSET_START: u16 = 0x0;
 
@@ -52,5 +83,23 @@ pub const FONTSET_HEIGHT: usize = 5;
 /// Total length of fontset in bytes.
 pub const FONTSET_DATA_LENGTH: usize = FONTSET_COUNT * FONTSET_HEIGHT;
 
+/// Memory location where the SUPER-CHIP large fontset starts, after the small fontset.
+pub const LARGE_FONTSET_START: u16 = FONTSET_START + FONTSET_DATA_LENGTH as u16;
+
+/// Large character height in bytes.
+pub const LARGE_FONTSET_HEIGHT: usize = 10;
+
+/// Maximum length of the large fontset in bytes.
+pub const LARGE_FONTSET_DATA_LENGTH: usize = FONTSET_COUNT * LARGE_FONTSET_HEIGHT;
+
+/// Memory location of the mouse extension, enabled by [`Quirks::mouse`](crate::Quirks::mouse).
+///
+/// Three bytes in the interpreter area, below the program: the column and
+/// row of the pointer on the display, and the state of the mouse buttons.
+pub const MOUSE_START: usize = 0x1F0;
+
+/// Length of the mouse extension's memory in bytes.
+pub const MOUSE_SIZE: usize = 3;
+
 /// Type for storing the 12-bit memory addresses.
 pub type Address = u16;
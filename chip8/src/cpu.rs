@@ -1,7 +1,15 @@
 //! CPU and memory state.
-use crate::{bytecode::*, constants::*};
+use std::{collections::VecDeque, fmt};
 
-pub type Chip8DisplayBuffer<'a> = &'a [bool; DISPLAY_BUFFER_SIZE];
+use crate::{
+    bytecode::*,
+    constants::*,
+    devices::{HeldKeys, KeyCode, KeyEvent},
+    display::DisplayPlane,
+    error::RuntimeError,
+};
+
+pub type Chip8DisplayBuffer<'a> = &'a DisplayPlane;
 
 /// Core state for a chip8 interpreter.
 #[allow(dead_code)]
@@ -29,6 +37,17 @@ pub struct Chip8Cpu {
     pub(crate) key_wait: bool,
     /// Keyboard input state. Pressed is a 1 bit, released is a 0 bit.
     pub(crate) key_state: u16,
+    /// Queue of key transitions, consumed by `Fx0A` (LD Vx, K).
+    pub(crate) key_events: VecDeque<KeyEvent>,
+    /// Key pressed while waiting in `Fx0A`, which resolves the wait when released.
+    pub(crate) key_wait_press: Option<KeyCode>,
+    /// Keys released during the frame, with the release held back until
+    /// the end of the frame to suppress auto-repeat.
+    pub(crate) key_pending_release: u16,
+    /// Order in which the keys were last pressed, to find the latest.
+    pub(crate) key_stamps: [u32; KEY_COUNT as usize],
+    /// Stamp of the latest key press.
+    pub(crate) key_stamp: u32,
 
     // ------------------------------------------------------------------------
     // Memory
@@ -36,15 +55,25 @@ pub struct Chip8Cpu {
     pub(crate) ram: Box<[u8; MEM_SIZE]>,
     /// Stack of return pointers used for jumping when a routine call finishes.
     pub(crate) stack: Box<[Address; STACK_SIZE]>,
-    /// Screen buffer that is drawn too.
-    pub(crate) display: Box<[bool; DISPLAY_BUFFER_SIZE]>,
+    /// Maximum number of return addresses on the stack.
+    pub(crate) stack_depth: usize,
+    /// Screen buffers that are drawn too, one for each bitplane.
+    pub(crate) display: Box<[DisplayPlane; PLANE_COUNT]>,
+    /// Bitmask of the display planes affected by drawing and clearing.
+    pub(crate) planes: u8,
+    /// XO-CHIP audio pattern, played back as a 1-bit waveform.
+    pub(crate) audio_pattern: [u8; AUDIO_PATTERN_SIZE],
+    /// The audio pattern was loaded by `F002` (AUDIO), and replaces the buzzer tone.
+    pub(crate) audio_pattern_loaded: bool,
+    /// XO-CHIP pitch register, setting the playback rate of the audio pattern.
+    pub(crate) pitch: u8,
 
     // ------------------------------------------------------------------------
     // Control
     /// Interrupt for VM loop.
     pub(crate) trap: bool,
-    /// Error message if the VM is in an error state.
-    pub(crate) error: Option<&'static str>,
+    /// Error if the VM is in an error state.
+    pub(crate) error: Option<RuntimeError>,
 }
 
 impl Default for Chip8Cpu {
@@ -59,10 +88,20 @@ impl Default for Chip8Cpu {
             buzzer_state: false,
             key_wait: false,
             key_state: 0,
+            key_events: VecDeque::with_capacity(KEY_EVENT_CAPACITY),
+            key_wait_press: None,
+            key_pending_release: 0,
+            key_stamps: [0; KEY_COUNT as usize],
+            key_stamp: 0,
 
             ram: Box::new([0; MEM_SIZE]),
             stack: Box::new([0; STACK_SIZE]),
-            display: Box::new([false; DISPLAY_BUFFER_SIZE]),
+            stack_depth: DEFAULT_STACK_DEPTH,
+            display: Box::new([DisplayPlane::new(), DisplayPlane::new()]),
+            planes: PLANE_DEFAULT,
+            audio_pattern: [0; AUDIO_PATTERN_SIZE],
+            audio_pattern_loaded: false,
+            pitch: DEFAULT_PITCH,
 
             trap: false,
             error: None,
@@ -79,7 +118,11 @@ impl Chip8Cpu {
     pub(crate) fn clear_memory(&mut self) {
         self.ram.fill(0);
         self.stack.fill(0);
-        self.display.fill(false);
+        self.display.iter_mut().for_each(DisplayPlane::clear);
+        self.planes = PLANE_DEFAULT;
+        self.audio_pattern.fill(0);
+        self.audio_pattern_loaded = false;
+        self.pitch = DEFAULT_PITCH;
     }
 
     pub fn interrupt(&mut self) {
@@ -87,22 +130,106 @@ impl Chip8Cpu {
     }
 
     pub fn set_error(&mut self, message: &'static str) {
+        let err = RuntimeError {
+            pc: self.pc,
+            opcode: u16::from_be_bytes(self.instr()),
+            message,
+            sp: self.sp,
+            address: self.address,
+            registers: self.registers,
+        };
+        tracing::debug!(
+            target: "chip8::error",
+            pc = err.pc,
+            opcode = err.opcode,
+            address = err.address,
+            "{message}"
+        );
+
         self.trap = true;
-        self.error = Some(message);
+        self.error = Some(err);
     }
 
     pub fn error(&self) -> Option<&str> {
-        self.error
+        self.error.map(|err| err.message)
+    }
+
+    /// Check that `len` bytes starting at the address fit in memory.
+    ///
+    /// Sets the error state when the range runs past the end of memory.
+    pub(crate) fn check_memory(&mut self, address: usize, len: usize) -> bool {
+        if address + len > MEM_SIZE {
+            self.set_error("memory access out of bounds");
+            false
+        } else {
+            true
+        }
+    }
+
+    /// Copy memory starting at the address into the buffer.
+    ///
+    /// Sets the error state when the range runs past the end of memory.
+    pub(crate) fn read_memory(&mut self, address: usize, buf: &mut [u8]) -> bool {
+        if !self.check_memory(address, buf.len()) {
+            return false;
+        }
+        buf.copy_from_slice(&self.ram[address..address + buf.len()]);
+        true
+    }
+
+    /// Copy the data into memory starting at the address.
+    ///
+    /// Sets the error state when the range runs past the end of memory.
+    pub(crate) fn write_memory(&mut self, address: usize, data: &[u8]) -> bool {
+        if !self.check_memory(address, data.len()) {
+            return false;
+        }
+        self.ram[address..address + data.len()].copy_from_slice(data);
+        true
+    }
+
+    /// Push a return address onto the call stack.
+    ///
+    /// Sets the error state when the stack is full.
+    pub(crate) fn push_stack(&mut self, address: Address) -> bool {
+        // The bottom slot is never used, so an empty stack has a pointer of zero.
+        if self.sp >= self.stack_depth || self.sp + 1 >= STACK_SIZE {
+            self.set_error("call stack overflow");
+            return false;
+        }
+        self.sp += 1;
+        self.stack[self.sp] = address;
+        true
+    }
+
+    /// Pop the return address from the top of the call stack.
+    ///
+    /// Sets the error state when the stack is empty.
+    pub(crate) fn pop_stack(&mut self) -> Option<Address> {
+        if self.sp == 0 || self.sp >= STACK_SIZE {
+            self.set_error("call stack underflow");
+            return None;
+        }
+        let address = self.stack[self.sp];
+        self.sp -= 1;
+        Some(address)
     }
 
+    /// Clear the currently selected display planes.
     pub fn clear_display(&mut self) {
-        self.display.fill(false);
+        for (index, plane) in self.display.iter_mut().enumerate() {
+            if self.planes & (1 << index) != 0 {
+                plane.clear();
+            }
+        }
     }
 
     pub fn set_key_state(&mut self, key_id: u8, state: bool) {
-        if key_id <= KEY_COUNT {
+        if key_id < KEY_COUNT {
             if state {
                 self.key_state |= 1 << key_id;
+                self.key_stamp = self.key_stamp.wrapping_add(1);
+                self.key_stamps[key_id as usize] = self.key_stamp;
             } else {
                 self.key_state &= !(1 << key_id);
             }
@@ -110,7 +237,7 @@ impl Chip8Cpu {
     }
 
     pub fn key_state(&self, key_id: u8) -> bool {
-        if key_id <= KEY_COUNT {
+        if key_id < KEY_COUNT {
             self.key_state & (1 << key_id) > 0
         } else {
             false
@@ -124,6 +251,9 @@ impl Chip8Cpu {
     }
 
     /// Retrieve the value of the first key that is pressed down.
+    ///
+    /// The lowest key is preferred, regardless of the order the keys were
+    /// pressed in, see [`Chip8Cpu::latest_key`].
     #[inline]
     pub fn first_key(&self) -> Option<u8> {
         if self.any_key() {
@@ -136,10 +266,38 @@ impl Chip8Cpu {
         None
     }
 
+    /// Retrieve the value of the most recently pressed key that is still down.
+    pub fn latest_key(&self) -> Option<u8> {
+        (0..KEY_COUNT)
+            .filter(|k| self.key_state(*k))
+            .max_by_key(|k| self.key_stamps[*k as usize])
+    }
+
+    /// Keys reported as pressed down by the policy, as a bitmask.
+    pub fn held_keys(&self, policy: HeldKeys) -> u16 {
+        let key = match policy {
+            HeldKeys::All => return self.key_state,
+            HeldKeys::Latest => self.latest_key(),
+            HeldKeys::Lowest => self.first_key(),
+        };
+        key.map(|k| 1 << k).unwrap_or(0)
+    }
+
     /// Clear the keyboard input state, setting all keys to up.
+    ///
+    /// No key events are generated.
     #[inline(always)]
     pub fn clear_keys(&mut self) {
         self.key_state = 0;
+        self.key_pending_release = 0;
+    }
+
+    /// Queue a key transition, discarding the oldest event if the queue is full.
+    pub(crate) fn push_key_event(&mut self, event: KeyEvent) {
+        if self.key_events.len() >= KEY_EVENT_CAPACITY {
+            self.key_events.pop_front();
+        }
+        self.key_events.push_back(event);
     }
 
     /// Count down the delay timer.
@@ -216,6 +374,134 @@ impl Chip8Cpu {
     }
 }
 
+/// Read-only view of the registers, timers, call stack and keys of the CPU.
+///
+/// Borrowed from the VM with [`Chip8Vm::state`](crate::Chip8Vm::state), and
+/// copied into an owned [`CpuSnapshot`] with [`CpuState::snapshot`].
+#[derive(Clone, Copy)]
+pub struct CpuState<'a> {
+    cpu: &'a Chip8Cpu,
+}
+
+impl<'a> CpuState<'a> {
+    pub(crate) fn new(cpu: &'a Chip8Cpu) -> Self {
+        Self { cpu }
+    }
+
+    /// Program counter, the address of the next instruction.
+    pub fn pc(&self) -> usize {
+        self.cpu.pc
+    }
+
+    /// Stack pointer, the number of subroutine calls in progress.
+    pub fn sp(&self) -> usize {
+        self.cpu.sp
+    }
+
+    /// General purpose registers `V0` to `VF`.
+    pub fn registers(&self) -> &'a [u8; REGISTER_COUNT] {
+        &self.cpu.registers
+    }
+
+    /// Value of the register `Vx`, or `None` when the index is out of range.
+    pub fn register(&self, index: usize) -> Option<u8> {
+        self.cpu.registers.get(index).copied()
+    }
+
+    /// Address register `I`.
+    pub fn address(&self) -> Address {
+        self.cpu.address
+    }
+
+    /// Delay timer `DT`.
+    pub fn delay_timer(&self) -> u8 {
+        self.cpu.delay_timer
+    }
+
+    /// Sound timer `ST`.
+    pub fn sound_timer(&self) -> u8 {
+        self.cpu.sound_timer
+    }
+
+    /// The buzzer is on.
+    pub fn buzzer(&self) -> bool {
+        self.cpu.buzzer_state
+    }
+
+    /// XO-CHIP pitch register, the playback rate of the audio pattern.
+    pub fn pitch(&self) -> u8 {
+        self.cpu.pitch
+    }
+
+    /// Return addresses on the call stack, from the bottom to the top.
+    pub fn stack(&self) -> &'a [Address] {
+        let sp = self.cpu.sp.min(STACK_SIZE - 1);
+        &self.cpu.stack[1..=sp]
+    }
+
+    /// Keys held down, as a bitmask with bit `n` set for key `n`.
+    pub fn keys(&self) -> u16 {
+        self.cpu.key_state
+    }
+
+    pub fn is_key_down(&self, key: KeyCode) -> bool {
+        self.cpu.key_state(key.as_u8())
+    }
+
+    /// Execution is stalled by `Fx0A` (`LD Vx, K`) until a key is released.
+    pub fn is_key_wait(&self) -> bool {
+        self.cpu.key_wait
+    }
+
+    /// Copy the state, so it can outlive the VM.
+    pub fn snapshot(&self) -> CpuSnapshot {
+        CpuSnapshot {
+            pc: self.pc(),
+            sp: self.sp(),
+            registers: *self.registers(),
+            address: self.address(),
+            delay_timer: self.delay_timer(),
+            sound_timer: self.sound_timer(),
+            buzzer: self.buzzer(),
+            pitch: self.pitch(),
+            stack: self.stack().to_vec(),
+            keys: self.keys(),
+            key_wait: self.is_key_wait(),
+        }
+    }
+}
+
+impl fmt::Debug for CpuState<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.snapshot(), f)
+    }
+}
+
+impl From<CpuState<'_>> for CpuSnapshot {
+    fn from(state: CpuState<'_>) -> Self {
+        state.snapshot()
+    }
+}
+
+/// Owned copy of the [`CpuState`], for comparing and keeping states.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CpuSnapshot {
+    pub pc: usize,
+    pub sp: usize,
+    pub registers: [u8; REGISTER_COUNT],
+    pub address: Address,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub buzzer: bool,
+    pub pitch: u8,
+    /// Return addresses on the call stack, from the bottom to the top.
+    pub stack: Vec<Address>,
+    /// Keys held down, as a bitmask with bit `n` set for key `n`.
+    pub keys: u16,
+    pub key_wait: bool,
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -249,4 +535,36 @@ mod test {
         assert!(cpu.key_state(7));
         assert!(cpu.key_state(15));
     }
+
+    #[test]
+    fn test_bounds_checks() {
+        let mut cpu = Chip8Cpu::default();
+
+        assert!(cpu.write_memory(MEM_SIZE - 2, &[1, 2]));
+        let mut buf = [0; 2];
+        assert!(cpu.read_memory(MEM_SIZE - 2, &mut buf));
+        assert_eq!(buf, [1, 2]);
+        assert!(cpu.error.is_none());
+
+        assert!(!cpu.write_memory(MEM_SIZE - 1, &[1, 2]));
+        assert_eq!(cpu.error(), Some("memory access out of bounds"));
+
+        let mut cpu = Chip8Cpu::default();
+        assert_eq!(cpu.pop_stack(), None);
+        assert_eq!(cpu.error(), Some("call stack underflow"));
+
+        for depth in [DEFAULT_STACK_DEPTH, STACK_SIZE - 1, STACK_SIZE] {
+            let mut cpu = Chip8Cpu {
+                stack_depth: depth,
+                ..Default::default()
+            };
+            let depth = depth.min(STACK_SIZE - 1);
+            for address in 1..=depth {
+                assert!(cpu.push_stack(address as Address));
+            }
+            assert!(!cpu.push_stack(0));
+            assert_eq!(cpu.error(), Some("call stack overflow"));
+            assert_eq!(cpu.pop_stack(), Some(depth as Address));
+        }
+    }
 }
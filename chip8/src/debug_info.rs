@@ -0,0 +1,217 @@
+//! Source-level debug information.
+//!
+//! Sidecar file emitted by the assembler, mapping the bytecode of a
+//! ROM back to its source code. The format is line based text, starting
+//! with a version header, followed by one record per line.
+//!
+//! ```text
+//! C8DBG 1
+//! label 0x0200 main
+//! line  0x0200 3
+//! data  0x0210 4
+//! ```
+use std::{fmt, fs, path::Path};
+
+use crate::{
+    asm::Listing,
+    constants::Address,
+    error::{Chip8Error, Chip8Result},
+};
+
+/// Identifies the file as Chip-8 debug information.
+const MAGIC: &str = "C8DBG";
+
+/// Version of the debug information format.
+pub const DEBUG_INFO_VERSION: u16 = 1;
+
+/// File extension of the debug information sidecar file.
+pub const DEBUG_INFO_EXT: &str = "c8dbg";
+
+/// Mapping of bytecode addresses to source-level information.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DebugInfo {
+    /// Label names, by address.
+    pub labels: Vec<(Address, String)>,
+    /// Source line numbers, by address of the first byte emitted by the line.
+    pub lines: Vec<(Address, usize)>,
+    /// Blocks of data, as starting address and size in bytes.
+    pub data: Vec<(Address, usize)>,
+}
+
+impl DebugInfo {
+    /// Name of the label at the given address.
+    pub fn label(&self, address: Address) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|(addr, _)| *addr == address)
+            .map(|(_, name)| name.as_str())
+    }
+
+    /// Source line number of the statement that emitted the given address.
+    pub fn line(&self, address: Address) -> Option<usize> {
+        self.lines
+            .iter()
+            .find(|(addr, _)| *addr == address)
+            .map(|(_, line)| *line)
+    }
+
+    /// Indicates whether the given address is within a block of data.
+    pub fn is_data(&self, address: Address) -> bool {
+        self.data.iter().any(|(start, size)| {
+            let start = *start as usize;
+            (start..start + size).contains(&(address as usize))
+        })
+    }
+
+    /// Parse debug information from its text format.
+    pub fn parse(text: &str) -> Chip8Result<Self> {
+        let mut lines = text.lines().enumerate();
+
+        match lines.next().map(|(_, line)| line.split_whitespace()) {
+            Some(mut header) => {
+                if header.next() != Some(MAGIC) {
+                    return Err(debug_info_error("missing header"));
+                }
+                let version = header.next().and_then(|v| v.parse::<u16>().ok());
+                if version != Some(DEBUG_INFO_VERSION) {
+                    return Err(debug_info_error(format!(
+                        "unsupported version, expected {DEBUG_INFO_VERSION}"
+                    )));
+                }
+            }
+            None => return Err(debug_info_error("missing header")),
+        }
+
+        let mut info = DebugInfo::default();
+
+        for (index, line) in lines {
+            let mut fields = line.split_whitespace();
+            let record = match fields.next() {
+                Some(record) => record,
+                None => continue, // Blank line
+            };
+            let address = fields.next().and_then(parse_address).ok_or_else(|| {
+                debug_info_error(format!("invalid address on line {}", index + 1))
+            })?;
+            let value = fields
+                .next()
+                .ok_or_else(|| debug_info_error(format!("missing value on line {}", index + 1)))?;
+            let number = || {
+                value
+                    .parse::<usize>()
+                    .map_err(|_| debug_info_error(format!("invalid number on line {}", index + 1)))
+            };
+
+            match record {
+                "label" => info.labels.push((address, value.to_owned())),
+                "line" => info.lines.push((address, number()?)),
+                "data" => info.data.push((address, number()?)),
+                _ => {
+                    return Err(debug_info_error(format!(
+                        "unknown record '{record}' on line {}",
+                        index + 1
+                    )))
+                }
+            }
+        }
+
+        Ok(info)
+    }
+
+    /// Load debug information from a file.
+    pub fn load(path: impl AsRef<Path>) -> Chip8Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text)
+    }
+
+    /// Write debug information to a file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Chip8Result<()> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+}
+
+impl From<&Listing> for DebugInfo {
+    fn from(listing: &Listing) -> Self {
+        let mut info = DebugInfo::default();
+
+        for entry in listing.entries() {
+            if let Some(ref label) = entry.label {
+                info.labels.push((entry.address, label.clone()));
+            }
+            info.lines.push((entry.address, entry.line));
+            if entry.data {
+                info.data.push((entry.address, entry.bytes.len()));
+            }
+        }
+
+        info
+    }
+}
+
+impl fmt::Display for DebugInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{MAGIC} {DEBUG_INFO_VERSION}")?;
+        for (address, name) in &self.labels {
+            writeln!(f, "label 0x{address:04X} {name}")?;
+        }
+        for (address, line) in &self.lines {
+            writeln!(f, "line  0x{address:04X} {line}")?;
+        }
+        for (address, size) in &self.data {
+            writeln!(f, "data  0x{address:04X} {size}")?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_address(text: &str) -> Option<Address> {
+    let digits = text.strip_prefix("0x")?;
+    Address::from_str_radix(digits, 16).ok()
+}
+
+#[inline(never)]
+#[cold]
+fn debug_info_error(message: impl ToString) -> Chip8Error {
+    Chip8Error::DebugInfo(message.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::asm::{Assembler, Lexer};
+
+    #[test]
+    fn test_debug_info_roundtrip() {
+        let source_code = ".main\n    JP .main\n.sprite\n    db 0x80, 0x40\n";
+        let (_, listing) = Assembler::new(Lexer::new(source_code))
+            .parse_with_listing()
+            .unwrap();
+        let info = DebugInfo::from(&listing);
+
+        assert_eq!(info.label(0x200), Some("main"));
+        assert_eq!(info.label(0x202), Some("sprite"));
+        assert_eq!(info.line(0x202), Some(4));
+        assert!(!info.is_data(0x200));
+        assert!(info.is_data(0x203));
+
+        let text = info.to_string();
+        assert_eq!(DebugInfo::parse(&text).unwrap(), info);
+    }
+
+    #[test]
+    fn test_debug_info_invalid() {
+        for text in [
+            "",
+            "C8DBG 99\n",
+            "C8DBG 1\nlabel 200 main\n",
+            "C8DBG 1\nline 0x0200\n",
+            "C8DBG 1\nstack 0x0200 1\n",
+        ] {
+            assert!(
+                matches!(DebugInfo::parse(text), Err(Chip8Error::DebugInfo(_))),
+                "invalid debug info must fail: {text:?}"
+            );
+        }
+    }
+}
@@ -1,20 +1,4 @@
 //! IO device interface
-use crate::constants::*;
-
-/// Hooks to provide IO devices to the virtual machine.
-pub trait Devices {
-    /// Wait for keyboard input.
-    fn input_wait(&self) -> KeyCode;
-
-    /// Checks immediately whether the given key is currently pressed.
-    fn is_pressed(&self, key: KeyCode) -> bool;
-
-    /// Blit the display buffer to screen output.
-    fn draw(&self, display: &[bool; DISPLAY_BUFFER_SIZE]);
-
-    /// Turn the sound buzzer on or off.
-    fn buzz(&self, state: bool);
-}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
@@ -37,6 +21,20 @@ pub enum KeyCode {
     KeyF = 0xF,
 }
 
+/// Transition of a key on the keypad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyState {
+    Pressed,
+    Released,
+}
+
+/// Key transition queued for the interpreter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: KeyCode,
+    pub state: KeyState,
+}
+
 impl KeyCode {
     pub fn as_u8(&self) -> u8 {
         match self {
@@ -99,6 +97,118 @@ impl TryFrom<u8> for KeyCode {
     }
 }
 
+/// Handling of held keys, and of keys held down together.
+///
+/// The defaults report the keypad as it is, which suits programs written
+/// for the COSMAC VIP.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct KeyPolicy {
+    /// Keep a key held when it's released and pressed again within the same
+    /// frame, hiding the release and press pairs of OS auto-repeat.
+    /// Default: `false`
+    pub suppress_repeat: bool,
+    /// Keys reported as held down to `Ex9E (SKP Vx)` and `ExA1 (SKNP Vx)`.
+    /// Default: [`HeldKeys::All`]
+    pub held: HeldKeys,
+    /// Transition that resolves the wait of `Fx0A (LD Vx, K)`.
+    /// Default: [`KeyWait::Release`]
+    pub wait: KeyWait,
+}
+
+/// Keys reported as held down, when several keys are held together.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum HeldKeys {
+    /// Every held key.
+    #[default]
+    All,
+    /// Only the most recently pressed key.
+    Latest,
+    /// Only the lowest key, like the keypad scan of simple interpreters.
+    Lowest,
+}
+
+/// Transition that resolves the wait of `Fx0A (LD Vx, K)`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum KeyWait {
+    /// The release of the last key pressed, as on the COSMAC VIP.
+    #[default]
+    Release,
+    /// The first key pressed while waiting, as in many modern interpreters.
+    Press,
+}
+
+/// Pointer and buttons of the mouse, as seen by programs using the
+/// mouse extension, see [`Chip8Vm::set_mouse`](crate::Chip8Vm::set_mouse).
+///
+/// Stored in memory at [`MOUSE_START`](crate::constants::MOUSE_START),
+/// in the order of the fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mouse {
+    /// Column of the display pixel under the pointer,
+    /// or [`Mouse::OUTSIDE`] when it's off the display.
+    pub x: u8,
+    /// Row of the display pixel under the pointer,
+    /// or [`Mouse::OUTSIDE`] when it's off the display.
+    pub y: u8,
+    /// Buttons held down, as a combination of [`Mouse::LEFT`],
+    /// [`Mouse::RIGHT`] and [`Mouse::MIDDLE`].
+    pub buttons: u8,
+}
+
+impl Mouse {
+    /// Coordinate of a pointer that isn't over the display.
+    pub const OUTSIDE: u8 = 0xFF;
+
+    pub const LEFT: u8 = 0b001;
+    pub const RIGHT: u8 = 0b010;
+    pub const MIDDLE: u8 = 0b100;
+
+    /// Move the pointer to a pixel of the display, or off the display.
+    pub fn set_position(&mut self, position: Option<(u8, u8)>) {
+        let (x, y) = position.unwrap_or((Self::OUTSIDE, Self::OUTSIDE));
+        self.x = x;
+        self.y = y;
+    }
+
+    /// Press or release the buttons of the mask.
+    pub fn set_button(&mut self, mask: u8, state: KeyState) {
+        match state {
+            KeyState::Pressed => self.buttons |= mask,
+            KeyState::Released => self.buttons &= !mask,
+        }
+    }
+
+    pub fn to_bytes(self) -> [u8; 3] {
+        [self.x, self.y, self.buttons]
+    }
+}
+
+impl Default for Mouse {
+    /// Pointer off the display, with no buttons down.
+    fn default() -> Self {
+        Self {
+            x: Self::OUTSIDE,
+            y: Self::OUTSIDE,
+            buttons: 0,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct InvalidKeyCode;
 
@@ -181,3 +291,10 @@ mod de {
         }
     }
 }
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for KeyCode {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(self.as_u8())
+    }
+}
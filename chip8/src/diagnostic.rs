@@ -0,0 +1,281 @@
+//! Diagnostics rendered with excerpts of the source code.
+//!
+//! ```text
+//! error: label 'lop' is undefined
+//!   --> game.asm:7:8
+//!     |
+//!   7 |     JP .lop
+//!     |        ^^^^
+//!     |
+//!     = help: did you mean '.loop'?
+//! ```
+use std::fmt::{self, Display, Formatter};
+
+use crate::asm::Span;
+
+/// Minimum width of the line number margin.
+const MIN_MARGIN: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Note,
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Note => write!(f, "note"),
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Secondary location of a diagnostic, with an explanation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// Problem found in source code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// Primary location of the problem, if it relates to the source code.
+    pub span: Option<Span>,
+    /// Secondary locations, like the previous definition of a duplicate.
+    pub labels: Vec<Label>,
+    /// Additional context.
+    pub notes: Vec<String>,
+    /// Suggestion for fixing the problem.
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl ToString) -> Self {
+        Self {
+            severity,
+            message: message.to_string(),
+            span: None,
+            labels: Vec::new(),
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn error(message: impl ToString) -> Self {
+        Self::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl ToString) -> Self {
+        Self::new(Severity::Warning, message)
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl ToString) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.to_string(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl ToString) -> Self {
+        self.notes.push(note.to_string());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl ToString) -> Self {
+        self.help = Some(help.to_string());
+        self
+    }
+
+    /// Returns an adaptor that displays the diagnostic with excerpts from the source code.
+    ///
+    /// The file name is shown in the location of the problem, when given.
+    pub fn display_with_source<'a>(
+        &'a self,
+        source: &'a str,
+        filename: Option<&'a str>,
+    ) -> impl Display + 'a {
+        DiagnosticDisplay {
+            diagnostic: self,
+            source,
+            filename,
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+/// Adaptor for displaying a diagnostic with its source code context.
+struct DiagnosticDisplay<'a> {
+    diagnostic: &'a Diagnostic,
+    source: &'a str,
+    filename: Option<&'a str>,
+}
+
+impl Display for DiagnosticDisplay<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let Self {
+            diagnostic,
+            source,
+            filename,
+        } = self;
+        writeln!(f, "{diagnostic}")?;
+
+        // Spans that don't fit the source are left out.
+        let in_bounds = |span: &Span| {
+            (span.end() as usize) <= source.len() && source.is_char_boundary(span.index as usize)
+        };
+        let primary = diagnostic.span.as_ref().filter(|span| in_bounds(span));
+        let labels: Vec<_> = diagnostic
+            .labels
+            .iter()
+            .filter(|label| in_bounds(&label.span))
+            .collect();
+
+        let line_no = |span: &Span| 1 + source[..span.index as usize].matches('\n').count();
+        let width = primary
+            .into_iter()
+            .chain(labels.iter().map(|label| &label.span))
+            .map(|span| line_no(span).to_string().len())
+            .max()
+            .unwrap_or(0)
+            .max(MIN_MARGIN);
+        let margin = " ".repeat(width);
+
+        if let Some(span) = primary {
+            let (_, line_span) = span.surrounding_line(source);
+            let column = 1 + (span.index - line_span.index) as usize;
+            writeln!(
+                f,
+                "{margin}--> {}:{}:{column}",
+                filename.unwrap_or("<source>"),
+                line_no(span)
+            )?;
+        }
+
+        if primary.is_some() || !labels.is_empty() {
+            writeln!(f, "{margin} |")?;
+            let excerpts = primary.map(|span| (span, '^', "")).into_iter().chain(
+                labels
+                    .iter()
+                    .map(|label| (&label.span, '-', label.message.as_str())),
+            );
+            for (span, marker, message) in excerpts {
+                let (line, line_span) = span.surrounding_line(source);
+                write_excerpt(
+                    f,
+                    width,
+                    line_no(span),
+                    line,
+                    (span.index - line_span.index) as usize,
+                    span.size as usize,
+                    marker,
+                    message,
+                )?;
+            }
+            writeln!(f, "{margin} |")?;
+        }
+
+        for note in &diagnostic.notes {
+            writeln!(f, "{margin} = note: {note}")?;
+        }
+        if let Some(help) = &diagnostic.help {
+            writeln!(f, "{margin} = help: {help}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Write a numbered source line, with markers under the
+/// `size` characters starting at the relative index.
+///
+/// ```text
+///   2 | LD v0, 0xFFFF
+///     |        ^^^^^^ message
+/// ```
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn write_excerpt(
+    f: &mut Formatter,
+    width: usize,
+    line_no: usize,
+    line: &str,
+    relative_index: usize,
+    size: usize,
+    marker: char,
+    message: &str,
+) -> fmt::Result {
+    let margin = " ".repeat(width);
+    writeln!(f, "{line_no:width$} | {}", line.trim_end())?;
+
+    // An end-of-file span has size 0, so we clamp to 1 for a minimal marker to show up.
+    let markers = marker.to_string().repeat(size.max(1));
+    let indent = " ".repeat(relative_index);
+    if message.is_empty() {
+        writeln!(f, "{margin} | {indent}{markers}")
+    } else {
+        writeln!(f, "{margin} | {indent}{markers} {message}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_diagnostic_display() {
+        let source = ".loop\n    CLS\n    JP .lop\n";
+        let diagnostic = Diagnostic::error("label 'lop' is undefined")
+            .with_span(Span::new(21, 4))
+            .with_label(Span::new(0, 5), "similar label defined here")
+            .with_note("labels are resolved after the whole file is parsed")
+            .with_help("did you mean '.loop'?");
+
+        assert_eq!(
+            diagnostic
+                .display_with_source(source, Some("game.asm"))
+                .to_string(),
+            "error: label 'lop' is undefined\n\
+             \x20  --> game.asm:3:8\n\
+             \x20   |\n\
+             \x20 3 |     JP .lop\n\
+             \x20   |        ^^^^\n\
+             \x20 1 | .loop\n\
+             \x20   | ----- similar label defined here\n\
+             \x20   |\n\
+             \x20   = note: labels are resolved after the whole file is parsed\n\
+             \x20   = help: did you mean '.loop'?\n"
+        );
+    }
+
+    #[test]
+    fn test_diagnostic_without_span() {
+        let diagnostic = Diagnostic::warning("program is empty");
+        assert_eq!(
+            diagnostic.display_with_source("", None).to_string(),
+            "warning: program is empty\n"
+        );
+
+        // Out of bounds spans are left out.
+        let diagnostic = Diagnostic::error("stale").with_span(Span::new(100, 1));
+        assert_eq!(
+            diagnostic.display_with_source("CLS", None).to_string(),
+            "error: stale\n"
+        );
+    }
+}
@@ -1,8 +1,12 @@
 //! Disassembler.
+mod cfg;
 mod disasm2;
-mod ir;
+pub mod ir;
+mod lint;
 
+pub use cfg::{BasicBlock, ControlFlowGraph, FlowEdge, FlowKind};
 pub use disasm2::DisassemblerV2;
+pub use lint::{Lint, LintKind, Linter};
 
 use std::fmt::{self, Write as FmtWrite};
 
@@ -21,6 +25,19 @@ impl<'a> Disassembler<'a> {
         }
     }
 
+    /// Decode the bytecode into structured instructions, in address order.
+    ///
+    /// Control flow is followed from the entry point, so bytes that are never
+    /// executed are returned as data.
+    pub fn decode_all(bytecode: &[u8]) -> Vec<ir::Instr> {
+        DisassemblerV2::new(bytecode).instructions()
+    }
+
+    /// Decode the single instruction at the given index in the bytecode.
+    pub fn decode_at(bytecode: &[u8], index: usize) -> Option<ir::Instr> {
+        disasm2::decode_at(bytecode, index, MEM_START)
+    }
+
     pub fn print_bytecode(&mut self) {
         let mut s = String::new();
         while self.cursor < self.bytecode.len() {
@@ -0,0 +1,323 @@
+//! Control-flow graph of a ROM.
+//!
+//! The instructions reachable from the entry point, as traced by the
+//! [`DisassemblerV2`](super::DisassemblerV2), are split into basic blocks,
+//! and the blocks are grouped into the subroutines they belong to.
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::{self, Write as FmtWrite};
+
+use smol_str::SmolStr;
+
+use crate::constants::Address;
+
+use super::ir::{Instr, Op};
+
+/// Basic blocks of a program, connected by the flow of control.
+///
+/// ```
+/// # use chip8::{assemble, disasm::DisassemblerV2};
+/// let bytecode = assemble(".main\n    CALL .draw\n    JP .main\n.draw\n    RET\n").unwrap();
+/// let graph = DisassemblerV2::new(&bytecode).control_flow_graph();
+/// assert_eq!(graph.blocks.len(), 3);
+/// assert_eq!(graph.subroutines, [0x200, 0x204]);
+///
+/// let mut dot = String::new();
+/// graph.write_dot(&mut dot).unwrap();
+/// assert!(dot.contains(r#""0x200" -> "0x204" [style=dashed, label="call"];"#));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ControlFlowGraph {
+    /// Basic blocks, by the address of their first instruction.
+    pub blocks: BTreeMap<Address, BasicBlock>,
+    /// Entry points of the program and its subroutines, starting with the program.
+    pub subroutines: Vec<Address>,
+}
+
+/// Straight run of instructions, which is only entered at its start
+/// and only left at its end.
+#[derive(Debug, Clone)]
+pub struct BasicBlock {
+    pub label: SmolStr,
+    pub instrs: Vec<Instr>,
+    /// Control flow out of the last instruction.
+    pub edges: Vec<FlowEdge>,
+    /// Entry point of the subroutine the block belongs to. Blocks shared
+    /// by subroutines belong to the first one that reaches them.
+    pub subroutine: Address,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlowEdge {
+    pub target: Address,
+    pub kind: FlowKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowKind {
+    /// Continue to the next instruction, including when a call returns,
+    /// or a skip isn't taken.
+    Next,
+    /// Skip over the next instruction.
+    Skip,
+    Jump,
+    Call,
+    /// Jump to a base address plus a register, `JP V0, addr`.
+    Computed,
+}
+
+impl ControlFlowGraph {
+    /// Split the traced instructions into basic blocks, named with `label`.
+    pub(crate) fn build(
+        instructions: &BTreeMap<Address, Instr>,
+        entry: Address,
+        mut label: impl FnMut(Address) -> SmolStr,
+    ) -> Self {
+        // Blocks start at the entry, at the targets of control flow,
+        // and after every instruction that branches.
+        let mut leaders = BTreeSet::from([entry]);
+        for instr in instructions.values() {
+            let edges = edges(instr, instructions);
+            if is_branch(instr, &edges) {
+                leaders.extend(edges.iter().map(|edge| edge.target));
+            }
+        }
+
+        let mut blocks = BTreeMap::new();
+        let mut current: Option<(Address, Vec<Instr>)> = None;
+        for (address, instr) in instructions {
+            let next = next_address(instr);
+            let (start, mut instrs) = match current.take() {
+                Some((start, instrs)) if !leaders.contains(address) => (start, instrs),
+                _ => (*address, vec![]),
+            };
+            let edges = edges(instr, instructions);
+            instrs.push(instr.clone());
+
+            if is_branch(instr, &edges)
+                || leaders.contains(&next)
+                || !instructions.contains_key(&next)
+            {
+                blocks.insert(start, block(label(start), instrs, edges));
+            } else {
+                current = Some((start, instrs));
+            }
+        }
+
+        // Calls start subroutines, which own the blocks they reach without calls.
+        let calls: BTreeSet<Address> = blocks
+            .values()
+            .flat_map(|block: &BasicBlock| &block.edges)
+            .filter(|edge| edge.kind == FlowKind::Call && blocks.contains_key(&edge.target))
+            .map(|edge| edge.target)
+            .collect();
+        let subroutines: Vec<Address> = std::iter::once(entry)
+            .chain(calls.into_iter().filter(|address| *address != entry))
+            .filter(|address| blocks.contains_key(address))
+            .collect();
+
+        let mut assigned = HashSet::new();
+        for subroutine in &subroutines {
+            let mut pending = vec![*subroutine];
+            while let Some(address) = pending.pop() {
+                let Some(block) = blocks.get_mut(&address) else {
+                    continue;
+                };
+                if !assigned.insert(address) {
+                    continue;
+                }
+                block.subroutine = *subroutine;
+                pending.extend(
+                    block
+                        .edges
+                        .iter()
+                        .filter(|edge| edge.kind != FlowKind::Call)
+                        .map(|edge| edge.target),
+                );
+            }
+        }
+
+        Self {
+            blocks,
+            subroutines,
+        }
+    }
+
+    /// Write the graph in the DOT language of Graphviz, with a cluster of
+    /// blocks for every subroutine.
+    ///
+    /// Calls are dashed, and targets outside of the traced code are
+    /// drawn as ellipses with their address.
+    pub fn write_dot<W: FmtWrite>(&self, w: &mut W) -> fmt::Result {
+        writeln!(w, "digraph cfg {{")?;
+        writeln!(w, "    node [shape=box, fontname=\"monospace\"];")?;
+
+        for subroutine in &self.subroutines {
+            let name = &self.blocks[subroutine].label;
+            writeln!(w)?;
+            writeln!(w, "    subgraph \"cluster_0x{subroutine:03X}\" {{")?;
+            writeln!(w, "        label=\"{}\";", escape(name))?;
+            for (address, block) in &self.blocks {
+                if block.subroutine != *subroutine {
+                    continue;
+                }
+                write!(
+                    w,
+                    "        \"0x{address:03X}\" [label=\"{}\\l",
+                    escape(&block.label)
+                )?;
+                for instr in &block.instrs {
+                    let repr = instr.repr().to_string();
+                    write!(w, "0x{:03X}  {}\\l", instr.addr, escape(&repr))?;
+                }
+                writeln!(w, "\"];")?;
+            }
+            writeln!(w, "    }}")?;
+        }
+
+        writeln!(w)?;
+        let mut external = BTreeSet::new();
+        for (address, block) in &self.blocks {
+            for edge in &block.edges {
+                let target = edge.target;
+                if !self.blocks.contains_key(&target) {
+                    external.insert(target);
+                }
+                write!(w, "    \"0x{address:03X}\" -> \"0x{target:03X}\"")?;
+                match edge.kind {
+                    FlowKind::Next => writeln!(w, ";")?,
+                    FlowKind::Skip => writeln!(w, " [label=\"skip\"];")?,
+                    FlowKind::Jump => writeln!(w, " [label=\"jump\"];")?,
+                    FlowKind::Call => writeln!(w, " [style=dashed, label=\"call\"];")?,
+                    FlowKind::Computed => writeln!(w, " [style=dotted, label=\"computed\"];")?,
+                }
+            }
+        }
+        for address in external {
+            writeln!(w, "    \"0x{address:03X}\" [shape=ellipse];")?;
+        }
+
+        writeln!(w, "}}")
+    }
+}
+
+fn block(label: SmolStr, instrs: Vec<Instr>, edges: Vec<FlowEdge>) -> BasicBlock {
+    BasicBlock {
+        label,
+        instrs,
+        edges,
+        subroutine: 0,
+    }
+}
+
+fn next_address(instr: &Instr) -> Address {
+    instr.addr + instr.size() as Address
+}
+
+/// Control flow out of the instruction.
+fn edges(instr: &Instr, instructions: &BTreeMap<Address, Instr>) -> Vec<FlowEdge> {
+    let next = next_address(instr);
+    let edge = |target, kind| FlowEdge { target, kind };
+
+    match instr.op {
+        Op::JumpAddress { ref address } => vec![edge(address.address, FlowKind::Jump)],
+        Op::Call { ref address } => vec![
+            edge(address.address, FlowKind::Call),
+            edge(next, FlowKind::Next),
+        ],
+        Op::Return => vec![],
+        Op::Jump_Vx { ref address } => vec![edge(address.address, FlowKind::Computed)],
+        Op::Skip_Eq_Byte { .. }
+        | Op::Skip_NotEq_Byte { .. }
+        | Op::Skip_Eq { .. }
+        | Op::Skip_NotEq { .. }
+        | Op::SkipKey { .. }
+        | Op::SkipKeyNot { .. } => {
+            let size = instructions.get(&next).map(Instr::size).unwrap_or(2);
+            vec![
+                edge(next, FlowKind::Next),
+                edge(next + size as Address, FlowKind::Skip),
+            ]
+        }
+        _ => vec![edge(next, FlowKind::Next)],
+    }
+}
+
+/// Indicates whether the instruction ends a basic block.
+fn is_branch(instr: &Instr, edges: &[FlowEdge]) -> bool {
+    !matches!(edges, [FlowEdge { target, kind: FlowKind::Next }] if *target == next_address(instr))
+}
+
+/// Escape a string for a quoted DOT identifier.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        asm::{Assembler, Lexer},
+        disasm::DisassemblerV2,
+        DebugInfo,
+    };
+
+    fn edge(target: Address, kind: FlowKind) -> FlowEdge {
+        FlowEdge { target, kind }
+    }
+
+    #[test]
+    fn test_cfg_blocks() {
+        let source_code = "
+        .main
+            LD   v0,  0
+            CALL .draw
+            SKP  v0
+            LD   I,   LONG .main
+            JP   .main
+        .draw
+            ADD  v0,  1
+            SE   v0,  5
+            RET
+            JP   v0,  0x300
+        ";
+        let (bytecode, listing) = Assembler::new(Lexer::new(source_code))
+            .parse_with_listing()
+            .unwrap();
+        let graph = DisassemblerV2::new(&bytecode)
+            .with_debug_info(&DebugInfo::from(&listing))
+            .control_flow_graph();
+
+        let starts: Vec<Address> = graph.blocks.keys().cloned().collect();
+        assert_eq!(starts, [0x200, 0x204, 0x206, 0x20A, 0x20C, 0x210, 0x212]);
+        assert_eq!(graph.subroutines, [0x200, 0x20C]);
+
+        let main = &graph.blocks[&0x200];
+        assert_eq!(main.label, "main");
+        assert_eq!(main.instrs.len(), 2);
+        assert_eq!(
+            main.edges,
+            [edge(0x20C, FlowKind::Call), edge(0x204, FlowKind::Next)]
+        );
+        // The skip jumps over the 4 byte instruction.
+        assert_eq!(
+            graph.blocks[&0x204].edges,
+            [edge(0x206, FlowKind::Next), edge(0x20A, FlowKind::Skip)]
+        );
+        assert_eq!(graph.blocks[&0x206].edges, [edge(0x20A, FlowKind::Next)]);
+        assert_eq!(graph.blocks[&0x20A].edges, [edge(0x200, FlowKind::Jump)]);
+        assert_eq!(graph.blocks[&0x210].subroutine, 0x20C);
+        assert_eq!(
+            graph.blocks[&0x212].edges,
+            [edge(0x300, FlowKind::Computed)]
+        );
+
+        let mut dot = String::new();
+        graph.write_dot(&mut dot).unwrap();
+        assert!(dot.starts_with("digraph cfg {\n"));
+        assert!(dot.contains("    subgraph \"cluster_0x20C\" {\n        label=\"draw\";\n"));
+        assert!(dot.contains("\"0x20A\" [label=\"L_0x20A\\l0x20A  JP .main\\l\"];"));
+        assert!(dot.contains("\"0x212\" -> \"0x300\" [style=dotted, label=\"computed\"];"));
+        assert!(dot.contains("\"0x300\" [shape=ellipse];"));
+        assert!(dot.ends_with("}\n"));
+    }
+}
@@ -1,39 +1,46 @@
 //! Rewrite of disassembler which is more structured in its analyses.
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{self, Write as FmtWrite};
-use std::iter::Enumerate;
 
 use smol_str::SmolStr;
 
+use crate::bytecode::opcodes::LD_I_LONG;
 use crate::constants::{Address, MEM_SIZE, MEM_START};
+use crate::debug_info::DebugInfo;
+use crate::memory_map::{MemoryMap, RegionKind};
+use crate::variant::Variant;
 
+use super::cfg::ControlFlowGraph;
 use super::ir::{Instr, LabelAddr, Op};
 
 pub struct DisassemblerV2<'a> {
     /// Original bytecode input.
     bytecode: &'a [u8],
-    cursor: usize,
     /// Resulting instructions are organised into blocks.
     blocks: Vec<Block>,
-    /// Mapping of memory addresses to instructions.
-    ///
-    /// Should map to blocks?
-    addresses: Box<[usize]>,
-    /// The current subroutine being analysed.
-    subroutine: Block,
-    /// Stack of subroutines.
-    subroutines: Vec<Block>,
     /// Monotonically increasing block counter.
     block_id: usize,
-    /// Monotonically increasing subroutine counter.
-    subroutine_id: usize,
-    instructions: Vec<Instr>,
+    /// Instructions reachable from the entry point, by address.
+    instructions: BTreeMap<Address, Instr>,
     /// Mapping of target jump addresses indices to labels.
     labels: HashMap<Address, SmolStr>,
-    /// Bytecode indices that are candidates for data blocks.
+    /// Addresses that are called as subroutines.
+    calls: HashSet<Address>,
+    /// Addresses that are candidates for data blocks.
     data_blocks: HashSet<usize>,
-    errors: (),
-    warnings: (),
+    /// Addresses loaded into `I` before a sprite is drawn, with the
+    /// largest number of rows drawn from them.
+    sprites: BTreeMap<Address, usize>,
+    /// Output source code that can be assembled back into the original bytecode.
+    assembler_syntax: bool,
+    /// Render the bytes of sprite blocks as ASCII art comments.
+    annotate: bool,
+    /// Regions of memory, for commenting on addresses outside the bytecode.
+    memory_map: Option<MemoryMap>,
+    /// Address the bytecode is loaded at.
+    load_address: usize,
+    /// Interpreter the bytecode is written for, or `None` for every variant.
+    variant: Option<Variant>,
 }
 
 struct Block {
@@ -46,7 +53,7 @@ struct Block {
     ops: Vec<Instr>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 enum BlockKind {
     /// Simplest control flow block, used for loops and conditionals.
     Simple,
@@ -55,221 +62,641 @@ enum BlockKind {
     Subroutine,
     /// Data intended to be drawn to screen.
     Sprite,
+    /// Bytes that are never reached by control flow.
+    Data,
 }
 
 impl<'a> DisassemblerV2<'a> {
     pub fn new(bytecode: &'a [u8]) -> Self {
-        // Starts with an implicit top level subroutine.
-        let subroutine = Block {
-            kind: BlockKind::Subroutine,
-            label: "__main__".into(),
-            ops: vec![],
-        };
-
         Self {
             bytecode,
-            cursor: 0,
             blocks: vec![],
-            addresses: Vec::new().into_boxed_slice(),
-            subroutine,
-            subroutines: vec![],
             block_id: 0,
-            subroutine_id: 0,
-            instructions: vec![],
+            instructions: BTreeMap::new(),
             labels: HashMap::new(),
+            calls: HashSet::new(),
             data_blocks: HashSet::new(),
-            errors: (),
-            warnings: (),
+            sprites: BTreeMap::new(),
+            assembler_syntax: false,
+            annotate: false,
+            memory_map: None,
+            load_address: MEM_START,
+            variant: None,
         }
     }
 
+    /// Use the label names and data blocks from the assembler's debug information,
+    /// instead of synthetic labels.
+    pub fn with_debug_info(mut self, debug_info: &DebugInfo) -> Self {
+        for (address, name) in &debug_info.labels {
+            self.labels.insert(*address, SmolStr::new(name));
+        }
+
+        for (address, size) in &debug_info.data {
+            let start = *address as usize;
+            self.data_blocks.extend(start..start + size);
+        }
+
+        self
+    }
+
+    /// Output only assembler-compatible source code, without addresses and
+    /// bytecode, so the result can be assembled back into identical bytes.
+    ///
+    /// Data with an odd number of bytes is emitted as single byte `db` statements,
+    /// which requires the assembler to be configured without data padding.
+    pub fn with_assembler_syntax(mut self) -> Self {
+        self.assembler_syntax = true;
+        self
+    }
+
+    /// Render the bytes of detected sprites as comments, one row of
+    /// `#` and `.` per byte, so graphics can be recognised in the listing.
+    pub fn with_annotations(mut self) -> Self {
+        self.annotate = true;
+        self
+    }
+
+    /// Address the bytecode is loaded at, when it's not [`MEM_START`],
+    /// like the `0x600` of ETI-660 programs.
+    pub fn with_load_address(mut self, load_address: usize) -> Self {
+        self.load_address = load_address;
+        self
+    }
+
+    /// Only decode the instructions supported by the variant,
+    /// so the instructions of other variants are treated as data.
+    pub fn with_variant(mut self, variant: Variant) -> Self {
+        self.variant = Some(variant);
+        self
+    }
+
+    /// Comment on operands addressing memory outside of the bytecode with
+    /// the region they point into, like `; font`.
+    ///
+    /// Comments are left out of the assembler syntax.
+    pub fn with_memory_map(mut self, memory_map: &MemoryMap) -> Self {
+        self.memory_map = Some(memory_map.clone());
+        self
+    }
+
     pub fn disassemble<W: FmtWrite>(&mut self, w: &mut W) -> fmt::Result {
-        for mut instr in Decoder::new(self.bytecode.iter().cloned()) {
-            // TODO: Label jump destinations
-            // TODO: Mark data blocks
+        self.trace();
+        self.classify_sprites();
+        self.build_blocks();
+
+        // Format blocks
+        for block in &self.blocks {
+            let sprite = self.annotate && block.kind == BlockKind::Sprite;
+
+            if self.assembler_syntax {
+                writeln!(w, ".{}", block.label)?;
+                for instr in &block.ops {
+                    write_source(w, instr)?;
+                    if sprite {
+                        write_sprite_rows(w, "    ", instr)?;
+                    }
+                }
+                continue;
+            }
+
+            writeln!(w, "       .{}", block.label)?;
+
+            for instr in &block.ops {
+                write!(
+                    w,
+                    "0x{:04X} {:04X} {}",
+                    instr.addr,
+                    instr.bytecode(),
+                    instr.repr(),
+                )?;
+                match self.region_comment(instr) {
+                    Some(region) => writeln!(w, " ; {region}")?,
+                    None => writeln!(w)?,
+                }
+                if sprite {
+                    write_sprite_rows(w, "       ", instr)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Decode the bytecode into instructions and data, in address order.
+    pub fn instructions(&mut self) -> Vec<Instr> {
+        self.trace();
+        self.build_blocks();
+
+        self.blocks.drain(..).flat_map(|block| block.ops).collect()
+    }
+
+    /// Split the instructions reachable from the entry point into basic
+    /// blocks, and connect them by the flow of control.
+    ///
+    /// Blocks are named by the labels of the debug information, or
+    /// synthetic labels, which operands refer to.
+    pub fn control_flow_graph(&mut self) -> ControlFlowGraph {
+        self.trace();
+
+        let entry = self.load_address as Address;
+        let instructions = std::mem::take(&mut self.instructions);
+        let mut graph = ControlFlowGraph::build(&instructions, entry, |address| {
+            SmolStr::new(self.get_label(address))
+        });
+        self.instructions = instructions;
+
+        for block in graph.blocks.values_mut() {
+            for instr in &mut block.instrs {
+                match instr.op {
+                    Op::JumpAddress { ref mut address }
+                    | Op::Call { ref mut address }
+                    | Op::Jump_Vx { ref mut address }
+                    | Op::Load_Address { ref mut address }
+                    | Op::Load_Address_Long { ref mut address }
+                        if self.is_placed(address.address) =>
+                    {
+                        address.label = self.labels.get(&address.address).cloned();
+                    }
+                    _ => { /* Do Nothing */ }
+                }
+            }
+        }
+
+        graph
+    }
+
+    /// Follow control flow from the entry point, decoding every
+    /// reachable instruction.
+    ///
+    /// Bytes that are never reached are treated as data.
+    fn trace(&mut self) {
+        self.instructions.clear();
+        self.calls.clear();
+
+        let mut pending = vec![self.load_address as Address];
+
+        while let Some(address) = pending.pop() {
+            if self.instructions.contains_key(&address) {
+                continue;
+            }
+
+            let instr = match (address as usize)
+                .checked_sub(self.load_address)
+                .and_then(|index| decode_at(self.bytecode, index, self.load_address))
+            {
+                Some(instr)
+                    if !self.data_blocks.contains(&(instr.addr as usize))
+                        && self.supports(instr.bytes) =>
+                {
+                    instr
+                }
+                _ => continue,
+            };
+            let next = address + instr.size() as Address;
+
             match instr.op {
-                Op::JumpAddress { ref mut address } => {
-                    let label = self.get_label(address.address);
-                    address.label = Some(label.into());
+                // Zeroed memory and unknown opcodes are most likely
+                // data that control flow falls into.
+                Op::NoOp | Op::Unknown | Op::Data | Op::Byte | Op::Sprite => continue,
+                Op::JumpAddress { ref address } => {
+                    self.get_label(address.address);
+                    pending.push(address.address);
+                }
+                Op::Call { ref address } => {
+                    self.get_label(address.address);
+                    self.calls.insert(address.address);
+                    pending.push(address.address);
+                    pending.push(next);
+                }
+                Op::Return => { /* End of subroutine */ }
+                // The target is computed at runtime, so only the base address is known.
+                Op::Jump_Vx { ref address } => {
+                    self.get_label(address.address);
                 }
-                Op::Load_Address { address } => {
-                    self.data_blocks.insert((address as usize) - MEM_START);
+                Op::Skip_Eq_Byte { .. }
+                | Op::Skip_NotEq_Byte { .. }
+                | Op::Skip_Eq { .. }
+                | Op::Skip_NotEq { .. }
+                | Op::SkipKey { .. }
+                | Op::SkipKeyNot { .. } => {
+                    pending.push(next);
+                    pending.push(next + self.instr_size(next) as Address);
                 }
-                Op::Draw { vx, vy, n } => {
-                    // TODO: Mark all rows as data
+                Op::Load_Address { ref address } | Op::Load_Address_Long { ref address } => {
+                    if self.contains(address.address) {
+                        self.get_label(address.address);
+                    }
+                    pending.push(next);
                 }
-                _ => { /* Do Nothing */ }
+                _ => pending.push(next),
             }
 
-            if self.data_blocks.contains(&instr.index) {
-                instr.op = Op::Data;
+            self.instructions.insert(address, instr);
+        }
+    }
+
+    /// Find the data that is drawn as sprites, by pairing every `LD I, addr`
+    /// with the `DRW` instructions that follow it in a straight line.
+    ///
+    /// This is a heuristic, so sprites that are selected by adding
+    /// to `I` are not found.
+    fn classify_sprites(&mut self) {
+        let mut sprites = BTreeMap::new();
+
+        // Addresses that I may hold. A load that can be skipped over
+        // adds to the candidates, instead of replacing them.
+        let mut loaded: Vec<Address> = vec![];
+        let mut conditional = false;
+
+        for instr in self.instructions.values() {
+            match instr.op {
+                Op::Load_Address { ref address } | Op::Load_Address_Long { ref address } => {
+                    if !conditional {
+                        loaded.clear();
+                    }
+                    loaded.push(address.address);
+                }
+                Op::Draw { n, .. } => {
+                    // DRW with zero rows draws a 16x16 sprite.
+                    let rows = if n == 0 { 32 } else { n as usize };
+                    for address in loaded.iter().filter(|address| self.contains(**address)) {
+                        let entry = sprites.entry(*address).or_default();
+                        *entry = rows.max(*entry);
+                    }
+                }
+                // The value of I is unknown after these.
+                Op::Add_I_Vx { .. }
+                | Op::Load_Char { .. }
+                | Op::Store_Array { .. }
+                | Op::Load_Array { .. }
+                | Op::JumpAddress { .. }
+                | Op::Jump_Vx { .. }
+                | Op::Return => loaded.clear(),
+                _ => {}
             }
 
-            self.instructions.push(instr);
+            conditional = matches!(
+                instr.op,
+                Op::Skip_Eq_Byte { .. }
+                    | Op::Skip_NotEq_Byte { .. }
+                    | Op::Skip_Eq { .. }
+                    | Op::Skip_NotEq { .. }
+                    | Op::SkipKey { .. }
+                    | Op::SkipKeyNot { .. }
+            );
         }
 
-        // Format instructions
-        for instr in &self.instructions {
-            if let Some(label) = self.labels.get(&instr.addr) {
-                writeln!(w, "       .{label}")?;
+        self.sprites = sprites;
+    }
+
+    /// Split the bytecode into blocks of code and data, starting a new
+    /// block at every label.
+    fn build_blocks(&mut self) {
+        self.blocks.clear();
+
+        let end = self.load_address + self.bytecode.len();
+        let mut address = self.load_address;
+
+        // Labels are only printed when they are placed on an instruction,
+        // otherwise the operand falls back to the raw address.
+        let placed: HashSet<Address> = self
+            .labels
+            .keys()
+            .filter(|address| self.is_placed(**address))
+            .cloned()
+            .collect();
+
+        while address < end {
+            let code = self.instructions.contains_key(&(address as Address));
+            let kind = if self.calls.contains(&(address as Address)) {
+                BlockKind::Subroutine
+            } else if code {
+                BlockKind::Simple
+            } else if self.sprites.contains_key(&(address as Address)) {
+                BlockKind::Sprite
+            } else {
+                BlockKind::Data
+            };
+            let label = self.get_label(address as Address).to_string();
+            let mut ops = vec![];
+
+            loop {
+                let mut instr = match self.instructions.remove(&(address as Address)) {
+                    Some(instr) if code => instr,
+                    Some(instr) => {
+                        self.instructions.insert(address as Address, instr);
+                        break;
+                    }
+                    None if !code => self.data_at(address - self.load_address),
+                    None => break,
+                };
+
+                match instr.op {
+                    Op::JumpAddress { ref mut address }
+                    | Op::Call { ref mut address }
+                    | Op::Jump_Vx { ref mut address }
+                    | Op::Load_Address { ref mut address }
+                    | Op::Load_Address_Long { ref mut address }
+                        if placed.contains(&address.address) =>
+                    {
+                        address.label = self.labels.get(&address.address).cloned();
+                    }
+                    _ => { /* Do Nothing */ }
+                }
+
+                address += instr.size();
+                ops.push(instr);
+
+                if address >= end || self.labels.contains_key(&(address as Address)) {
+                    break;
+                }
             }
-            writeln!(
-                w,
-                "0x{:04X} {:04X} {} {:?}",
-                instr.addr,
-                instr.bytecode(),
-                instr.repr(),
-                instr.op
-            )?;
+
+            self.blocks.push(Block { kind, label, ops });
         }
+    }
 
-        Ok(())
+    /// Indicates whether a label at the given address will be printed at the
+    /// start of an instruction or data.
+    fn is_placed(&self, address: Address) -> bool {
+        if !self.contains(address) {
+            return false;
+        }
+
+        // Labels inside an instruction are lost.
+        let inside = |offset: Address| {
+            address
+                .checked_sub(offset)
+                .and_then(|start| self.instructions.get(&start))
+                .is_some_and(|instr| instr.size() > offset as usize)
+        };
+
+        !(1..4).any(inside)
+    }
+
+    /// Region of memory outside the bytecode that the instruction addresses.
+    fn region_comment(&self, instr: &Instr) -> Option<RegionKind> {
+        let memory_map = self.memory_map.as_ref()?;
+        match instr.op {
+            Op::JumpAddress { ref address }
+            | Op::Call { ref address }
+            | Op::Jump_Vx { ref address }
+            | Op::Load_Address { ref address }
+            | Op::Load_Address_Long { ref address }
+                if !self.contains(address.address) =>
+            {
+                memory_map.kind_at(address.address as usize)
+            }
+            _ => None,
+        }
     }
 
-    fn address(&self) -> Address {
-        MEM_START as u16 + self.cursor as u16
+    /// Indicates whether the address is within the bytecode.
+    fn contains(&self, address: Address) -> bool {
+        (self.load_address..self.load_address + self.bytecode.len()).contains(&(address as usize))
     }
 
-    fn op(&self) -> [u8; 2] {
-        [self.bytecode[self.cursor], self.bytecode[self.cursor + 1]]
+    /// Indicates whether the variant supports the instruction.
+    fn supports(&self, instr: [u8; 2]) -> bool {
+        self.variant.is_none_or(|variant| variant.supports(instr))
     }
 
-    fn bump(&mut self) {
-        self.cursor += 2;
+    /// Size of the instruction at the given address, used to skip over it.
+    ///
+    /// `F000` is only the four byte long load when the variant supports it,
+    /// and its trailing address is within the bytecode.
+    fn instr_size(&self, address: Address) -> usize {
+        let index = (address as usize).saturating_sub(self.load_address);
+        match decode_at(self.bytecode, index, self.load_address) {
+            Some(instr) if self.supports(LD_I_LONG) => instr.size(),
+            _ => 2,
+        }
+    }
+
+    /// Data at the given index, which is split into a single byte when the
+    /// next byte belongs to a block of code, or a label.
+    fn data_at(&self, index: usize) -> Instr {
+        let a = self.bytecode[index];
+        let next = (self.load_address + index + 1) as Address;
+        let single = index + 1 >= self.bytecode.len()
+            || self.instructions.contains_key(&next)
+            || self.labels.contains_key(&next);
+
+        let (bytes, op) = if single {
+            ([a, 0], Op::Byte)
+        } else {
+            ([a, self.bytecode[index + 1]], Op::Data)
+        };
+
+        Instr {
+            addr: (self.load_address + index) as Address,
+            index,
+            bytes,
+            op,
+        }
     }
 
     fn get_label(&mut self, address: Address) -> &str {
         self.labels.entry(address).or_insert_with(|| {
-            let label = SmolStr::new(format!("block-{}", self.block_id));
+            let label = SmolStr::new(format!("L_0x{address:03X}"));
             self.block_id += 1;
             label
         })
     }
 }
 
-struct Decoder<I> {
-    iter: Enumerate<I>,
-}
+/// Write the instruction as a statement that the assembler accepts.
+fn write_source<W: FmtWrite>(w: &mut W, instr: &Instr) -> fmt::Result {
+    let [a, b] = instr.bytes;
 
-impl<I: Iterator> Decoder<I> {
-    fn new(iter: I) -> Self {
-        Self {
-            iter: iter.enumerate(),
+    match instr.op {
+        Op::Byte => writeln!(w, "    db 0b{a:08b}"),
+        Op::Data | Op::NoOp | Op::Unknown | Op::Sprite => {
+            writeln!(w, "    db 0b{a:08b}, 0b{b:08b}")
         }
+        _ => writeln!(w, "    {}", instr.repr()),
     }
 }
 
-impl<I> Decoder<I> {
-    #[inline(always)]
-    fn decode(&self, bytecode: [u8; 2]) -> Op {
-        let [a, b] = bytecode;
-        let op = a >> 4; // 0xF000
-        let vx = a & 0xF; // 0x0F00
-        let vy = b >> 4; // 0x00F0
-        let n = b & 0xF; // 0x000F
-        let nn = b; // 0x00FF
-        let nnn = (((a as u16) & 0xF) << 8) | b as u16; // 0x0FFF
-
-        match op {
-            // Miscellaneous instructions identified by nn
-            0x0 => {
-                match nn {
-                    0x0 => Op::NoOp,
-                    // 00E0 (CLS)
-                    //
-                    // Clear display
-                    0xE0 => Op::ClearScreen,
-                    // 00EE (RET)
-                    //
-                    // Return from a subroutine.
-                    0xEE => Op::Return,
-                    _ => Op::Unknown,
-                }
-            }
-            // 1nnn (JP addr)
-            //
-            // Jump to address.
-            0x1 => Op::JumpAddress {
-                address: LabelAddr::new(nnn),
-            },
-            // 2nnn (CALL addr)
-            //
-            // Call subroutine at NNN.
-            0x2 => Op::Call { address: nnn },
-            // 3xnn (SE Vx, byte)
-            //
-            // Skip the next instruction if register VX equals value NN.
-            0x3 => Op::Skip_Eq_Byte { vx, nn },
-            // 4xnn (SNE Vx, byte)
-            //
-            // Skip the next instruction if register VX does not equal value NN.
-            0x4 => Op::Skip_NotEq_Byte { vx, nn },
-            // 5xy0 (SE Vx, Vy)
-            //
-            // Skip the next instruction if register VX equals value VY.
-            0x5 => Op::Skip_Eq { vx, vy },
-            // 6xnn (LD Vx, byte)
-            //
-            // Set register VX to value NN.
-            0x6 => Op::Load_Byte { vx, nn },
-            // 7xnn (ADD Vx, byte)
-            //
-            // Add byte to the value in register `Vx`, store the result in `Vx`.
-            // Carry bit is not set.
-            0x7 => Op::Add_Byte { vx, nn },
-            // Arithmetic.
-            0x8 => match n {
-                0x0 => Op::Load_Vx_Vy { vx, vy },
-                0x1 => Op::Or_Vx_Vy { vx, vy },
-                0x2 => Op::And_Vx_Vy { vx, vy },
-                0x3 => Op::Xor_Vx_Vy { vx, vy },
-                0x4 => Op::Add_Vx_Vy { vx, vy },
-                0x5 => Op::Sub_Vx_Vy { vx, vy },
-                0x6 => Op::ShiftRight { vx },
-                0x7 => Op::SubReverse_Vx_Vy { vx, vy },
-                0x8 => Op::ShiftLeft { vx },
-                _ => Op::Unknown,
-            },
-            /// Annn (LD I, addr)
-            //
-            // Set address register I to value NNN.
-            0xA => Op::Load_Address { address: nnn },
-            // Bnnn (JP V0, addr)
-            //
-            // Jump to location nnn + V0.
-            0xB => Op::Jump_Vx { address: nnn },
-            // Cxnn (RND Vx, byte)
-            //
-            // Generate random number.
-            0xC => Op::Random { vx, nn },
-            // Dxyn (DRW Vx, Vy, byte)
-            //
-            // Draw sprite to the display buffer.
-            0xD => Op::Draw { vx, vy, n },
-            _ => Op::Unknown,
-        }
+/// Write the bytes of a data instruction as rows of sprite pixels.
+fn write_sprite_rows<W: FmtWrite>(w: &mut W, indent: &str, instr: &Instr) -> fmt::Result {
+    let rows = match instr.op {
+        Op::Byte => &instr.bytes[..1],
+        Op::Data => &instr.bytes[..],
+        _ => return Ok(()),
+    };
+
+    for row in rows {
+        let pixels: String = (0..8)
+            .map(|bit| if row & (0x80 >> bit) != 0 { '#' } else { '.' })
+            .collect();
+        writeln!(w, "{indent}; {pixels}")?;
     }
-}
 
-impl<I: Iterator<Item = u8>> Iterator for Decoder<I> {
-    type Item = Instr;
+    Ok(())
+}
 
-    fn next(&mut self) -> Option<Instr> {
-        let (index, a) = self.iter.next()?;
-        let (_, b) = self.iter.next()?;
+/// Decode the instruction at the given index in the bytecode, loaded at the address.
+///
+/// A long load without its trailing address is decoded as data.
+pub(crate) fn decode_at(bytecode: &[u8], index: usize, load_address: usize) -> Option<Instr> {
+    let a = *bytecode.get(index)?;
+    let b = *bytecode.get(index + 1)?;
 
-        let op = self.decode([a, b]);
+    let mut op = decode([a, b]);
 
-        let addr = MEM_START + index;
-        if addr >= MEM_SIZE {
-            panic!("program size exceeds chip-8 memory limit");
+    // XO-CHIP long load is a four byte instruction.
+    if let Op::Load_Address_Long { ref mut address } = op {
+        match bytecode.get(index + 2..index + 4) {
+            Some(&[hi, lo]) => address.address = ((hi as u16) << 8) | lo as u16,
+            _ => op = Op::Data,
         }
+    }
 
-        Some(Instr {
-            addr: addr as Address,
-            index,
-            bytes: [a, b],
-            op,
-        })
+    let addr = load_address + index;
+    if addr >= MEM_SIZE {
+        panic!("program size exceeds chip-8 memory limit");
+    }
+
+    Some(Instr {
+        addr: addr as Address,
+        index,
+        bytes: [a, b],
+        op,
+    })
+}
+
+#[inline(always)]
+fn decode(bytecode: [u8; 2]) -> Op {
+    let [a, b] = bytecode;
+    let op = a >> 4; // 0xF000
+    let vx = a & 0xF; // 0x0F00
+    let vy = b >> 4; // 0x00F0
+    let n = b & 0xF; // 0x000F
+    let nn = b; // 0x00FF
+    let nnn = (((a as u16) & 0xF) << 8) | b as u16; // 0x0FFF
+
+    match op {
+        // Miscellaneous instructions identified by nn
+        0x0 => {
+            match nnn {
+                0x0 => Op::NoOp,
+                // 00E0 (CLS)
+                //
+                // Clear display
+                0xE0 => Op::ClearScreen,
+                // 00EE (RET)
+                //
+                // Return from a subroutine.
+                0xEE => Op::Return,
+                // 0nnn (SYS addr)
+                //
+                // Jump to machine code routine.
+                _ => Op::System { address: nnn },
+            }
+        }
+        // 1nnn (JP addr)
+        //
+        // Jump to address.
+        0x1 => Op::JumpAddress {
+            address: LabelAddr::new(nnn),
+        },
+        // 2nnn (CALL addr)
+        //
+        // Call subroutine at NNN.
+        0x2 => Op::Call {
+            address: LabelAddr::new(nnn),
+        },
+        // 3xnn (SE Vx, byte)
+        //
+        // Skip the next instruction if register VX equals value NN.
+        0x3 => Op::Skip_Eq_Byte { vx, nn },
+        // 4xnn (SNE Vx, byte)
+        //
+        // Skip the next instruction if register VX does not equal value NN.
+        0x4 => Op::Skip_NotEq_Byte { vx, nn },
+        // 5xy0 (SE Vx, Vy)
+        //
+        // Skip the next instruction if register VX equals value VY.
+        0x5 if n == 0 => Op::Skip_Eq { vx, vy },
+        // 6xnn (LD Vx, byte)
+        //
+        // Set register VX to value NN.
+        0x6 => Op::Load_Byte { vx, nn },
+        // 7xnn (ADD Vx, byte)
+        //
+        // Add byte to the value in register `Vx`, store the result in `Vx`.
+        // Carry bit is not set.
+        0x7 => Op::Add_Byte { vx, nn },
+        // Arithmetic.
+        0x8 => match n {
+            0x0 => Op::Load_Vx_Vy { vx, vy },
+            0x1 => Op::Or_Vx_Vy { vx, vy },
+            0x2 => Op::And_Vx_Vy { vx, vy },
+            0x3 => Op::Xor_Vx_Vy { vx, vy },
+            0x4 => Op::Add_Vx_Vy { vx, vy },
+            0x5 => Op::Sub_Vx_Vy { vx, vy },
+            0x6 => Op::ShiftRight { vx, vy },
+            0x7 => Op::SubReverse_Vx_Vy { vx, vy },
+            0xE => Op::ShiftLeft { vx, vy },
+            _ => Op::Unknown,
+        },
+        // 9xy0 (SNE Vx, Vy)
+        //
+        // Skip the next instruction if register VX does not equal VY.
+        0x9 if n == 0 => Op::Skip_NotEq { vx, vy },
+        // Annn (LD I, addr)
+        //
+        // Set address register I to value NNN.
+        0xA => Op::Load_Address {
+            address: LabelAddr::new(nnn),
+        },
+        // Bnnn (JP V0, addr)
+        //
+        // Jump to location nnn + V0.
+        0xB => Op::Jump_Vx {
+            address: LabelAddr::new(nnn),
+        },
+        // Cxnn (RAND Vx, byte)
+        //
+        // Generate random number.
+        0xC => Op::Random { vx, nn },
+        // Dxyn (DRW Vx, Vy, byte)
+        //
+        // Draw sprite to the display buffer.
+        0xD => Op::Draw { vx, vy, n },
+        0xE => match nn {
+            // Ex9E (SKP Vx)
+            0x9E => Op::SkipKey { vx },
+            // ExA1 (SKNP Vx)
+            0xA1 => Op::SkipKeyNot { vx },
+            _ => Op::Unknown,
+        },
+        0xF => match nn {
+            // F000 nnnn (LD I, LONG addr)
+            //
+            // The address is read from the following two bytes by the caller.
+            0x00 if vx == 0 => Op::Load_Address_Long {
+                address: LabelAddr::new(0),
+            },
+            // Fn01 (PLANE n)
+            0x01 => Op::Plane { n: vx },
+            // F002 (AUDIO)
+            0x02 if vx == 0 => Op::Audio,
+            0x07 => Op::Load_Vx_Delay { vx },
+            0x0A => Op::Load_Vx_Key { vx },
+            0x15 => Op::Load_Delay_Vx { vx },
+            0x18 => Op::Load_Sound_Vx { vx },
+            0x1E => Op::Add_I_Vx { vx },
+            0x29 => Op::Load_Char { vx },
+            0x33 => Op::Load_Decimal { vx },
+            // Fx3A (PITCH Vx)
+            0x3A => Op::Pitch { vx },
+            0x55 => Op::Store_Array { vx },
+            0x65 => Op::Load_Array { vx },
+            _ => Op::Unknown,
+        },
+        _ => Op::Unknown,
     }
 }
@@ -9,6 +9,8 @@ use smol_str::SmolStr;
 
 use crate::constants::Address;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Instr {
     /// Index in the buffer where the instruction was read from.
     pub index: usize,
@@ -20,6 +22,16 @@ pub struct Instr {
 }
 
 impl Instr {
+    /// Number of bytes the instruction occupies in memory.
+    #[inline(always)]
+    pub fn size(&self) -> usize {
+        match self.op {
+            Op::Load_Address_Long { .. } => 4,
+            Op::Byte => 1,
+            _ => 2,
+        }
+    }
+
     /// Original bytes encoded into a `u16`.
     #[inline(always)]
     pub fn bytecode(&self) -> u16 {
@@ -27,12 +39,14 @@ impl Instr {
     }
 
     #[inline(always)]
-    pub fn repr(&self) -> InstrRepr {
+    pub fn repr(&self) -> InstrRepr<'_> {
         InstrRepr { instr: self }
     }
 }
 
 /// Address and optional label pair.
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LabelAddr {
     pub address: Address,
     pub label: Option<SmolStr>,
@@ -81,8 +95,9 @@ impl fmt::Debug for LabelAddr {
     }
 }
 
-#[derive(Debug)]
-#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[allow(non_camel_case_types, clippy::enum_variant_names)]
 pub enum Op {
     /// 0000
     ///
@@ -90,6 +105,12 @@ pub enum Op {
     /// but dead space needs to be handled by the
     /// disassembler.
     NoOp,
+    /// 0nnn (SYS addr)
+    ///
+    /// Jump to a machine code routine at `nnn`. Ignored by modern interpreters.
+    System {
+        address: Address,
+    },
     /// 00E0 (CLS)
     ///
     /// Clear the screen.
@@ -108,7 +129,7 @@ pub enum Op {
     ///
     /// Call the sub-routine at address `nnn`.
     Call {
-        address: Address,
+        address: LabelAddr,
     },
     /// 3xnn (SE Vx, byte)
     ///
@@ -193,9 +214,10 @@ pub enum Op {
     /// 8xy6 (SHR Vx)
     ///
     /// If the least-significant bit of Vx is 1, then VF is set to 1, otherwise 0.
-    /// Shift VX right by 1. VY is unused.
+    /// Shift VX right by 1. VY is unused, but kept so the bytecode can be reproduced.
     ShiftRight {
         vx: u8,
+        vy: u8,
     },
     /// 8xy7 (SUBN Vx, Vy)
     ///
@@ -209,24 +231,32 @@ pub enum Op {
     ///
     /// If the least-significant bit of Vx is 1, then VF is set to 1, otherwise 0.
     /// Shift VX left by 1.
-    /// VY is unused.
+    /// VY is unused, but kept so the bytecode can be reproduced.
     ShiftLeft {
         vx: u8,
+        vy: u8,
     },
 
+    /// 9xy0 (SNE Vx, Vy)
+    ///
+    /// Skip the next instruction if register `Vx` does not equal register `Vy`.
+    Skip_NotEq {
+        vx: u8,
+        vy: u8,
+    },
     /// Annn (LD I, addr)
     ///
     /// Load address into register `I`.
     Load_Address {
-        address: Address,
+        address: LabelAddr,
     },
-    // Bnnn (JP V0, addr)
-    //
-    // Jump to location nnn + V0.
+    /// Bnnn (JP V0, addr)
+    ///
+    /// Jump to location nnn + V0.
     Jump_Vx {
-        address: Address,
+        address: LabelAddr,
     },
-    /// Cxnn (RND Vx, byte)
+    /// Cxnn (RAND Vx, byte)
     ///
     /// Generate random number.
     Random {
@@ -241,10 +271,99 @@ pub enum Op {
         vy: u8,
         n: u8,
     },
+    /// Ex9E (SKP Vx)
+    ///
+    /// Skip the next instruction if the key with the value of `Vx` is pressed.
+    SkipKey {
+        vx: u8,
+    },
+    /// ExA1 (SKNP Vx)
+    ///
+    /// Skip the next instruction if the key with the value of `Vx` is not pressed.
+    SkipKeyNot {
+        vx: u8,
+    },
+
+    // ------------------------------------------------------------------------
+    // Miscellaneous
+    /// Fx07 (LD Vx, DT)
+    Load_Vx_Delay {
+        vx: u8,
+    },
+    /// Fx0A (LD Vx, K)
+    ///
+    /// Wait for a key press, and store the key in `Vx`.
+    Load_Vx_Key {
+        vx: u8,
+    },
+    /// Fx15 (LD DT, Vx)
+    Load_Delay_Vx {
+        vx: u8,
+    },
+    /// Fx18 (LD ST, Vx)
+    Load_Sound_Vx {
+        vx: u8,
+    },
+    /// Fx1E (ADD I, Vx)
+    Add_I_Vx {
+        vx: u8,
+    },
+    /// Fx29 (LD F, Vx)
+    ///
+    /// Load the address of the font character in `Vx` into register `I`.
+    Load_Char {
+        vx: u8,
+    },
+    /// Fx33 (LD B, Vx)
+    ///
+    /// Store the binary-coded decimal of `Vx` in memory at `I`.
+    Load_Decimal {
+        vx: u8,
+    },
+    /// Fx55 (LD [I], Vx)
+    ///
+    /// Store registers `V0` through `Vx` in memory starting at `I`.
+    Store_Array {
+        vx: u8,
+    },
+    /// Fx65 (LD Vx, [I])
+    ///
+    /// Read registers `V0` through `Vx` from memory starting at `I`.
+    Load_Array {
+        vx: u8,
+    },
+
+    // ------------------------------------------------------------------------
+    // XO-CHIP
+    /// F000 nnnn (LD I, LONG addr)
+    ///
+    /// Load the 16-bit address following the instruction into register `I`.
+    Load_Address_Long {
+        address: LabelAddr,
+    },
+    /// Fn01 (PLANE n)
+    ///
+    /// Select the display planes affected by drawing and clearing.
+    Plane {
+        n: u8,
+    },
+    /// F002 (AUDIO)
+    ///
+    /// Load the 16 byte audio pattern buffer from memory at `I`.
+    Audio,
+    /// Fx3A (PITCH Vx)
+    ///
+    /// Set the playback rate of the audio pattern.
+    Pitch {
+        vx: u8,
+    },
 
     // ------------------------------------------------------------------------
     // Meta ops
+    /// Two bytes of data that are not executed.
     Data,
+    /// Single trailing byte of a data region.
+    Byte,
     /// Data region that is drawn to the display.
     Sprite,
     Unknown,
@@ -281,15 +400,14 @@ impl<'a> fmt::Display for InstrRepr<'a> {
             Op::NoOp => Ok(()),
             Op::ClearScreen => write!(f, "CLS"),
             Op::Return => write!(f, "RET"),
-            // TODO: Replace with label
+            Op::System { address } => write!(f, "SYS 0x{address:03X}"),
             Op::JumpAddress { address } => write!(f, "JP {address}"),
-            // TODO: Replace with label
-            Op::Call { address } => write!(f, "CALL 0x{address:03X}"),
+            Op::Call { address } => write!(f, "CALL {address}"),
             Op::Skip_Eq_Byte { vx, nn } => write!(f, "SE v{vx}, {nn}"),
             Op::Skip_NotEq_Byte { vx, nn } => write!(f, "SNE v{vx}, {nn}"),
             Op::Skip_Eq { vx, vy } => write!(f, "SE v{vx}, v{vy}"),
             Op::Load_Byte { vx, nn } => write!(f, "LD v{vx}, {nn}"),
-            Op::Add_Byte { vx, nn } => write!(f, "ADD v{vx} {nn}"),
+            Op::Add_Byte { vx, nn } => write!(f, "ADD v{vx}, {nn}"),
             // ------
             Op::Load_Vx_Vy { vx, vy } => write!(f, "LD v{vx}, v{vy}"),
             Op::Or_Vx_Vy { vx, vy } => write!(f, "OR v{vx}, v{vy}"),
@@ -297,16 +415,37 @@ impl<'a> fmt::Display for InstrRepr<'a> {
             Op::Xor_Vx_Vy { vx, vy } => write!(f, "XOR v{vx}, v{vy}"),
             Op::Add_Vx_Vy { vx, vy } => write!(f, "ADD v{vx}, v{vy}"),
             Op::Sub_Vx_Vy { vx, vy } => write!(f, "SUB v{vx}, v{vy}"),
-            Op::ShiftRight { vx } => write!(f, "SHR v{vx}"),
+            Op::ShiftRight { vx, vy } if vx == vy => write!(f, "SHR v{vx}"),
+            Op::ShiftRight { vx, vy } => write!(f, "SHR v{vx}, v{vy}"),
             Op::SubReverse_Vx_Vy { vx, vy } => write!(f, "SUBN v{vx}, v{vy}"),
-            Op::ShiftLeft { vx } => write!(f, "SHL v{vx}"),
+            Op::ShiftLeft { vx, vy } if vx == vy => write!(f, "SHL v{vx}"),
+            Op::ShiftLeft { vx, vy } => write!(f, "SHL v{vx}, v{vy}"),
             // ------
-            Op::Load_Address { address } => write!(f, "LD I, 0x{address:03X}"),
-            Op::Jump_Vx { address } => write!(f, "JP 0x{address:03X}"),
-            Op::Random { vx, nn } => write!(f, "RND v{vx}, {nn}"),
+            Op::Skip_NotEq { vx, vy } => write!(f, "SNE v{vx}, v{vy}"),
+            Op::Load_Address { address } => write!(f, "LD I, {address}"),
+            Op::Jump_Vx { address } => write!(f, "JP v0, {address}"),
+            Op::Random { vx, nn } => write!(f, "RAND v{vx}, {nn}"),
             Op::Draw { vx, vy, n } => write!(f, "DRW v{vx}, v{vy}, {n}"),
+            Op::SkipKey { vx } => write!(f, "SKP v{vx}"),
+            Op::SkipKeyNot { vx } => write!(f, "SKNP v{vx}"),
+            // ------
+            Op::Load_Vx_Delay { vx } => write!(f, "LD v{vx}, DT"),
+            Op::Load_Vx_Key { vx } => write!(f, "LD v{vx}, K"),
+            Op::Load_Delay_Vx { vx } => write!(f, "LD DT, v{vx}"),
+            Op::Load_Sound_Vx { vx } => write!(f, "LD ST, v{vx}"),
+            Op::Add_I_Vx { vx } => write!(f, "ADD I, v{vx}"),
+            Op::Load_Char { vx } => write!(f, "LD F, v{vx}"),
+            Op::Load_Decimal { vx } => write!(f, "LD B, v{vx}"),
+            Op::Store_Array { vx } => write!(f, "LD [I], v{vx}"),
+            Op::Load_Array { vx } => write!(f, "LD v{vx}, [I]"),
+            // ------
+            Op::Load_Address_Long { address } => write!(f, "LD I, LONG {address}"),
+            Op::Plane { n } => write!(f, "PLANE {n}"),
+            Op::Audio => write!(f, "AUDIO"),
+            Op::Pitch { vx } => write!(f, "PITCH v{vx}"),
 
             Op::Data => write!(f, "0b{a:08b} 0b{b:08b}"),
+            Op::Byte => write!(f, "0b{a:08b}"),
             Op::Unknown => write!(f, "0x{a:02X}{b:02X}"),
             _ => todo!(),
         }
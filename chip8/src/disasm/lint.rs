@@ -0,0 +1,697 @@
+//! Static analysis of ROMs.
+//!
+//! Control flow is followed from the entry point, like the disassembler does,
+//! and the reachable instructions are checked for common mistakes.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::ops::Range;
+
+use crate::constants::{Address, DEFAULT_STACK_DEPTH, MEM_START};
+use crate::debug_info::DebugInfo;
+use crate::quirks::Quirks;
+
+use super::disasm2::decode_at;
+use super::ir::{Instr, Op};
+
+/// Bit of the index register `I` in a register mask.
+/// The general purpose registers `V0`-`VF` are the low 16 bits.
+const INDEX_BIT: u32 = 1 << 16;
+
+/// Problem found in a ROM, at the address of the offending instruction.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Lint {
+    pub address: Address,
+    pub kind: LintKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LintKind {
+    /// Instructions that no control flow reaches.
+    UnreachableCode { size: usize },
+    /// Jump or call to an address that holds data.
+    JumpIntoData { target: Address },
+    /// Jump or call to an address outside of the program.
+    JumpOutOfBounds { target: Address },
+    /// Execution continues from the instruction into data.
+    FallsIntoData { target: Address },
+    /// Execution continues from the instruction past the end of the program.
+    RunsPastEnd,
+    /// Register `Vx` is read before any instruction writes it.
+    UninitializedRegister { register: u8 },
+    /// Register `I` is read before any instruction writes it.
+    UninitializedIndex,
+    /// Return reachable from the entry point, when the stack is empty.
+    ReturnWithoutCall,
+    /// Subroutine without a reachable return, which leaks its stack entry.
+    NoReturn,
+    /// Call that can reach itself, without a bound on the stack depth.
+    Recursion { target: Address },
+    /// Nested calls deeper than the stack.
+    StackOverflow { depth: usize },
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:04X}: {}", self.address, self.kind)
+    }
+}
+
+impl fmt::Display for LintKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnreachableCode { size } => write!(f, "unreachable code, {size} bytes"),
+            Self::JumpIntoData { target } => write!(f, "jump into data at 0x{target:04X}"),
+            Self::JumpOutOfBounds { target } => {
+                write!(f, "jump to 0x{target:04X}, outside of the program")
+            }
+            Self::FallsIntoData { target } => {
+                write!(f, "execution continues into data at 0x{target:04X}")
+            }
+            Self::RunsPastEnd => write!(f, "execution runs past the end of the program"),
+            Self::UninitializedRegister { register } => {
+                write!(f, "register v{register:x} is read before it's written")
+            }
+            Self::UninitializedIndex => write!(f, "register I is read before it's written"),
+            Self::ReturnWithoutCall => write!(f, "return without a subroutine call"),
+            Self::NoReturn => write!(f, "subroutine never returns"),
+            Self::Recursion { target } => {
+                write!(f, "recursive call to 0x{target:04X} can overflow the stack")
+            }
+            Self::StackOverflow { depth } => {
+                write!(f, "{depth} nested calls overflow the stack")
+            }
+        }
+    }
+}
+
+/// Static analyzer that reports problems in a ROM.
+///
+/// ```
+/// use chip8::disasm::{LintKind, Linter};
+///
+/// // LD v1, v0
+/// // JP 0x200
+/// let lints = Linter::new(&[0x81, 0x00, 0x12, 0x00]).lint();
+/// assert_eq!(lints[0].kind, LintKind::UninitializedRegister { register: 0 });
+/// ```
+pub struct Linter<'a> {
+    bytecode: &'a [u8],
+    quirks: Quirks,
+    stack_depth: usize,
+    /// Blocks of data from the assembler's debug information.
+    data: Vec<Range<usize>>,
+    /// Address the bytecode is loaded at.
+    load_address: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EdgeKind {
+    /// Continue to the next instruction, or skip it.
+    Fall,
+    Jump,
+    Call,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    target: Address,
+    kind: EdgeKind,
+}
+
+impl<'a> Linter<'a> {
+    pub fn new(bytecode: &'a [u8]) -> Self {
+        Self {
+            bytecode,
+            quirks: Quirks::default(),
+            stack_depth: DEFAULT_STACK_DEPTH,
+            data: vec![],
+            load_address: MEM_START,
+        }
+    }
+
+    /// Interpret instructions with the semantics of the given quirks.
+    pub fn with_quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    /// Number of return addresses the stack can hold.
+    pub fn with_stack_depth(mut self, stack_depth: usize) -> Self {
+        self.stack_depth = stack_depth;
+        self
+    }
+
+    /// Address the bytecode is loaded at, when it's not [`MEM_START`].
+    pub fn with_load_address(mut self, load_address: usize) -> Self {
+        self.load_address = load_address;
+        self
+    }
+
+    /// Use the data blocks from the assembler's debug information,
+    /// so they are not mistaken for unreachable code.
+    pub fn with_debug_info(mut self, debug_info: &DebugInfo) -> Self {
+        for (address, size) in &debug_info.data {
+            let start = *address as usize;
+            self.data.push(start..start + size);
+        }
+        self
+    }
+
+    /// Analyse the ROM, and return the problems ordered by address.
+    pub fn lint(&self) -> Vec<Lint> {
+        let mut analysis = Analysis::trace(self);
+        let mut lints = BTreeSet::new();
+
+        analysis.check_edges(&mut lints);
+        analysis.check_unreachable(&mut lints);
+        analysis.check_registers(&mut lints);
+        analysis.check_stack(&mut lints);
+
+        lints.into_iter().collect()
+    }
+
+    /// Indicates whether the address is within the bytecode.
+    fn contains(&self, address: Address) -> bool {
+        (self.load_address..self.load_address + self.bytecode.len()).contains(&(address as usize))
+    }
+
+    fn decode(&self, address: Address) -> Option<Instr> {
+        (address as usize)
+            .checked_sub(self.load_address)
+            .and_then(|index| decode_at(self.bytecode, index, self.load_address))
+    }
+
+    /// Indicates whether the instruction at the address can be executed.
+    fn is_code(&self, address: Address) -> bool {
+        self.decode(address)
+            .is_some_and(|instr| !matches!(instr.op, Op::NoOp | Op::Unknown | Op::Data))
+    }
+
+    /// Control flow out of the instruction.
+    fn edges(&self, instr: &Instr) -> Vec<Edge> {
+        let next = instr.addr + instr.size() as Address;
+        let fall = |target| Edge {
+            target,
+            kind: EdgeKind::Fall,
+        };
+        let jump = |target| Edge {
+            target,
+            kind: EdgeKind::Jump,
+        };
+
+        match instr.op {
+            Op::JumpAddress { ref address } => vec![jump(address.address)],
+            Op::Call { ref address } => vec![
+                Edge {
+                    target: address.address,
+                    kind: EdgeKind::Call,
+                },
+                fall(next),
+            ],
+            Op::Return => vec![],
+            // The target is computed at runtime. Assume the base address is
+            // a jump table, and follow all its entries.
+            Op::Jump_Vx { ref address } => {
+                let mut edges = vec![jump(address.address)];
+                let mut entry = address.address;
+                while let Some(Op::JumpAddress { address }) = self.decode(entry).map(|i| i.op) {
+                    edges.push(jump(address.address));
+                    entry += 2;
+                }
+                edges
+            }
+            Op::Skip_Eq_Byte { .. }
+            | Op::Skip_NotEq_Byte { .. }
+            | Op::Skip_Eq { .. }
+            | Op::Skip_NotEq { .. }
+            | Op::SkipKey { .. }
+            | Op::SkipKeyNot { .. } => {
+                let size = self.decode(next).map(|instr| instr.size()).unwrap_or(2);
+                vec![fall(next), fall(next + size as Address)]
+            }
+            _ => vec![fall(next)],
+        }
+    }
+
+    /// Registers read and written by the instruction, as masks with
+    /// `V0`-`VF` in the low bits, and `I` in [`INDEX_BIT`].
+    fn registers(&self, op: &Op) -> (u32, u32) {
+        let v = |x: u8| 1u32 << x;
+        let vf = v(0xF);
+        // V0 through Vx
+        let range = |x: u8| (1u32 << (x + 1)) - 1;
+
+        match *op {
+            Op::Skip_Eq_Byte { vx, .. } | Op::Skip_NotEq_Byte { vx, .. } => (v(vx), 0),
+            Op::Skip_Eq { vx, vy } | Op::Skip_NotEq { vx, vy } => (v(vx) | v(vy), 0),
+            Op::Load_Byte { vx, .. } | Op::Random { vx, .. } => (0, v(vx)),
+            Op::Add_Byte { vx, .. } => (v(vx), v(vx)),
+            Op::Load_Vx_Vy { vx, vy } => (v(vy), v(vx)),
+            Op::Or_Vx_Vy { vx, vy } | Op::And_Vx_Vy { vx, vy } | Op::Xor_Vx_Vy { vx, vy } => {
+                let flag = if self.quirks.vf_reset { vf } else { 0 };
+                (v(vx) | v(vy), v(vx) | flag)
+            }
+            Op::Add_Vx_Vy { vx, vy }
+            | Op::Sub_Vx_Vy { vx, vy }
+            | Op::SubReverse_Vx_Vy { vx, vy } => (v(vx) | v(vy), v(vx) | vf),
+            Op::ShiftRight { vx, vy } | Op::ShiftLeft { vx, vy } => {
+                let src = if self.quirks.shift_vy { vy } else { vx };
+                (v(src), v(vx) | vf)
+            }
+            Op::Load_Address { .. } | Op::Load_Address_Long { .. } => (0, INDEX_BIT),
+            Op::Jump_Vx { ref address } => {
+                let x = if self.quirks.jump_vx {
+                    (address.address >> 8) as u8 & 0xF
+                } else {
+                    0
+                };
+                (v(x), 0)
+            }
+            Op::Draw { vx, vy, .. } => (v(vx) | v(vy) | INDEX_BIT, vf),
+            Op::SkipKey { vx } | Op::SkipKeyNot { vx } => (v(vx), 0),
+            Op::Load_Vx_Delay { vx } | Op::Load_Vx_Key { vx } => (0, v(vx)),
+            Op::Load_Delay_Vx { vx } | Op::Load_Sound_Vx { vx } => (v(vx), 0),
+            Op::Add_I_Vx { vx } => (v(vx) | INDEX_BIT, INDEX_BIT),
+            Op::Load_Char { vx } => (v(vx), INDEX_BIT),
+            Op::Load_Decimal { vx } => (v(vx) | INDEX_BIT, 0),
+            Op::Store_Array { vx } => {
+                let index = if self.quirks.load_store_increment_i {
+                    INDEX_BIT
+                } else {
+                    0
+                };
+                (range(vx) | INDEX_BIT, index)
+            }
+            Op::Load_Array { vx } => {
+                let index = if self.quirks.load_store_increment_i {
+                    INDEX_BIT
+                } else {
+                    0
+                };
+                (INDEX_BIT, range(vx) | index)
+            }
+            Op::Audio => (INDEX_BIT, 0),
+            Op::Pitch { vx } => (v(vx), 0),
+            _ => (0, 0),
+        }
+    }
+}
+
+/// Reachable instructions of a ROM, and the analyses over them.
+struct Analysis<'a, 'b> {
+    linter: &'b Linter<'a>,
+    /// Reachable instructions, by address.
+    code: BTreeMap<Address, Instr>,
+    /// Control flow out of every reachable instruction.
+    edges: BTreeMap<Address, Vec<Edge>>,
+    /// Addresses loaded into `I`, which are most likely data.
+    data: BTreeSet<Address>,
+    /// Registers written by each subroutine, and the subroutines it calls.
+    writes: HashMap<Address, u32>,
+}
+
+impl<'a, 'b> Analysis<'a, 'b> {
+    /// Follow control flow from the entry point, through every jump and call.
+    fn trace(linter: &'b Linter<'a>) -> Self {
+        let mut analysis = Self {
+            linter,
+            code: BTreeMap::new(),
+            edges: BTreeMap::new(),
+            data: BTreeSet::new(),
+            writes: HashMap::new(),
+        };
+
+        let mut pending = vec![linter.load_address as Address];
+        while let Some(address) = pending.pop() {
+            if analysis.code.contains_key(&address) || !linter.is_code(address) {
+                continue;
+            }
+            let Some(instr) = linter.decode(address) else {
+                continue;
+            };
+
+            if let Op::Load_Address { ref address } | Op::Load_Address_Long { ref address } =
+                instr.op
+            {
+                analysis.data.insert(address.address);
+            }
+
+            let edges = linter.edges(&instr);
+            pending.extend(edges.iter().map(|edge| edge.target));
+            analysis.edges.insert(address, edges);
+            analysis.code.insert(address, instr);
+        }
+
+        analysis
+    }
+
+    /// Indicates whether the address holds data, rather than code.
+    fn is_data(&self, address: Address) -> bool {
+        self.data.contains(&address)
+            || self
+                .linter
+                .data
+                .iter()
+                .any(|range| range.contains(&(address as usize)))
+    }
+
+    fn check_edges(&self, lints: &mut BTreeSet<Lint>) {
+        for (address, edges) in &self.edges {
+            for edge in edges {
+                let target = edge.target;
+                let in_bounds = self.linter.contains(target);
+                let is_data = !self.linter.is_code(target) || self.is_data(target);
+
+                let kind = match edge.kind {
+                    EdgeKind::Fall if !in_bounds => LintKind::RunsPastEnd,
+                    EdgeKind::Fall if is_data => LintKind::FallsIntoData { target },
+                    EdgeKind::Jump | EdgeKind::Call if !in_bounds => {
+                        LintKind::JumpOutOfBounds { target }
+                    }
+                    EdgeKind::Jump | EdgeKind::Call if is_data => LintKind::JumpIntoData { target },
+                    _ => continue,
+                };
+
+                lints.insert(Lint {
+                    address: *address,
+                    kind,
+                });
+            }
+        }
+    }
+
+    /// Report the regions that aren't reached, unless they look like data.
+    fn check_unreachable(&self, lints: &mut BTreeSet<Lint>) {
+        let load_address = self.linter.load_address;
+        let end = (load_address + self.linter.bytecode.len()) as Address;
+        let mut address = load_address as Address;
+
+        while address < end {
+            if let Some(instr) = self.code.get(&address) {
+                address += instr.size() as Address;
+                continue;
+            }
+
+            // Region runs until the next reachable instruction, or data.
+            let start = address;
+            while address < end && !self.code.contains_key(&address) && !self.is_data(address) {
+                address += 1;
+            }
+
+            let index = start as usize - load_address..address as usize - load_address;
+            let zeroed = self.linter.bytecode[index].iter().all(|byte| *byte == 0);
+            if address > start && !zeroed && self.linter.is_code(start) {
+                lints.insert(Lint {
+                    address: start,
+                    kind: LintKind::UnreachableCode {
+                        size: (address - start) as usize,
+                    },
+                });
+            }
+
+            // Skip over the data, up to the next reachable instruction.
+            while address < end && !self.code.contains_key(&address) {
+                address += 1;
+            }
+        }
+    }
+
+    /// Report registers that are read before they're written, on any path
+    /// from the entry point.
+    fn check_registers(&mut self, lints: &mut BTreeSet<Lint>) {
+        // Registers that are written on every path to the instruction.
+        let mut written: BTreeMap<Address, u32> = BTreeMap::new();
+        let mut pending = vec![(self.linter.load_address as Address, 0)];
+
+        while let Some((address, incoming)) = pending.pop() {
+            let Some(instr) = self.code.get(&address) else {
+                continue;
+            };
+            let state = match written.get(&address) {
+                Some(old) if old & incoming == *old => continue,
+                Some(old) => old & incoming,
+                None => incoming,
+            };
+            written.insert(address, state);
+
+            let (_, writes) = self.linter.registers(&instr.op);
+            let out = state | writes;
+            let edges = self.edges[&address].clone();
+            let callee = edges
+                .iter()
+                .find(|edge| edge.kind == EdgeKind::Call)
+                .map(|edge| edge.target);
+
+            for edge in edges {
+                match (edge.kind, callee) {
+                    // Registers written by the subroutine are
+                    // initialized after it returns.
+                    (EdgeKind::Fall, Some(callee)) => {
+                        pending.push((edge.target, out | self.subroutine_writes(callee)))
+                    }
+                    _ => pending.push((edge.target, out)),
+                }
+            }
+        }
+
+        for (address, state) in written {
+            let (reads, _) = self.linter.registers(&self.code[&address].op);
+            let missing = reads & !state;
+
+            for register in 0..16 {
+                if missing & (1 << register) != 0 {
+                    lints.insert(Lint {
+                        address,
+                        kind: LintKind::UninitializedRegister { register },
+                    });
+                }
+            }
+            if missing & INDEX_BIT != 0 {
+                lints.insert(Lint {
+                    address,
+                    kind: LintKind::UninitializedIndex,
+                });
+            }
+        }
+    }
+
+    /// Registers that may be written by the subroutine, including
+    /// the subroutines it calls.
+    fn subroutine_writes(&mut self, entry: Address) -> u32 {
+        if let Some(writes) = self.writes.get(&entry) {
+            return *writes;
+        }
+        // Guard against recursion.
+        self.writes.insert(entry, 0);
+
+        let mut writes = 0;
+        for address in self.body(entry) {
+            writes |= self.linter.registers(&self.code[&address].op).1;
+            for edge in self.edges[&address].clone() {
+                if edge.kind == EdgeKind::Call {
+                    writes |= self.subroutine_writes(edge.target);
+                }
+            }
+        }
+
+        self.writes.insert(entry, writes);
+        writes
+    }
+
+    /// Instructions reachable from the entry of a subroutine, stepping over calls.
+    fn body(&self, entry: Address) -> BTreeSet<Address> {
+        let mut body = BTreeSet::new();
+        let mut pending = vec![entry];
+
+        while let Some(address) = pending.pop() {
+            if !self.code.contains_key(&address) || !body.insert(address) {
+                continue;
+            }
+            pending.extend(
+                self.edges[&address]
+                    .iter()
+                    .filter(|edge| edge.kind != EdgeKind::Call)
+                    .map(|edge| edge.target),
+            );
+        }
+
+        body
+    }
+
+    fn check_stack(&self, lints: &mut BTreeSet<Lint>) {
+        let entry = self.linter.load_address as Address;
+        let is_return = |address: &Address| matches!(self.code[address].op, Op::Return);
+
+        for address in self.body(entry).iter().filter(|a| is_return(a)) {
+            lints.insert(Lint {
+                address: *address,
+                kind: LintKind::ReturnWithoutCall,
+            });
+        }
+
+        let subroutines: BTreeSet<Address> = self
+            .edges
+            .values()
+            .flatten()
+            .filter(|edge| edge.kind == EdgeKind::Call && self.code.contains_key(&edge.target))
+            .map(|edge| edge.target)
+            .collect();
+        for subroutine in &subroutines {
+            if !self.body(*subroutine).iter().any(is_return) {
+                lints.insert(Lint {
+                    address: *subroutine,
+                    kind: LintKind::NoReturn,
+                });
+            }
+        }
+
+        // Deepest stack seen at the entry of each subroutine.
+        let mut deepest = HashMap::new();
+        self.check_calls(entry, 0, &mut vec![entry], &mut deepest, lints);
+    }
+
+    fn check_calls(
+        &self,
+        subroutine: Address,
+        depth: usize,
+        path: &mut Vec<Address>,
+        deepest: &mut HashMap<Address, usize>,
+        lints: &mut BTreeSet<Lint>,
+    ) {
+        if deepest.get(&subroutine).is_some_and(|d| *d >= depth) {
+            return;
+        }
+        deepest.insert(subroutine, depth);
+
+        for address in self.body(subroutine) {
+            for edge in &self.edges[&address] {
+                if edge.kind != EdgeKind::Call || !self.code.contains_key(&edge.target) {
+                    continue;
+                }
+
+                let kind = if path.contains(&edge.target) {
+                    LintKind::Recursion {
+                        target: edge.target,
+                    }
+                } else if depth + 1 > self.linter.stack_depth {
+                    LintKind::StackOverflow { depth: depth + 1 }
+                } else {
+                    path.push(edge.target);
+                    self.check_calls(edge.target, depth + 1, path, deepest, lints);
+                    path.pop();
+                    continue;
+                };
+
+                lints.insert(Lint { address, kind });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::asm::assemble;
+
+    fn lint(source_code: &str) -> Vec<LintKind> {
+        let bytecode = assemble(source_code).unwrap();
+        Linter::new(&bytecode)
+            .lint()
+            .into_iter()
+            .map(|lint| lint.kind)
+            .collect()
+    }
+
+    #[test]
+    fn test_lint_clean() {
+        let source_code = "
+        .main
+            LD  v0, 0
+            LD  I,  .sprite
+            CALL .draw
+            ADD v0, 1
+            JP  .main
+        .draw
+            DRW v0, v0, 1
+            RET
+        .sprite
+            0xFF
+        ";
+        assert_eq!(lint(source_code), vec![]);
+    }
+
+    #[test]
+    fn test_lint_control_flow() {
+        let source_code = "
+        .main
+            JP  .sprite
+            CLS
+        .sprite
+            LD  I,  .sprite
+            JP  0xFFE
+        ";
+        assert_eq!(
+            lint(source_code),
+            vec![
+                LintKind::JumpIntoData { target: 0x204 },
+                LintKind::UnreachableCode { size: 2 },
+                LintKind::JumpOutOfBounds { target: 0xFFE },
+            ]
+        );
+
+        assert_eq!(lint("LD v0, 1"), vec![LintKind::RunsPastEnd]);
+    }
+
+    #[test]
+    fn test_lint_registers() {
+        let source_code = "
+        .main
+            SE  v1, 0
+            LD  v2, 1
+            CALL .init
+            ADD v2, v3
+            DRW v2, v4, 1
+        .loop
+            JP  .loop
+        .init
+            LD  v3, 5
+            RET
+        ";
+        assert_eq!(
+            lint(source_code),
+            vec![
+                LintKind::UninitializedRegister { register: 1 },
+                LintKind::UninitializedRegister { register: 2 },
+                LintKind::UninitializedRegister { register: 4 },
+                LintKind::UninitializedIndex,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lint_stack() {
+        let source_code = "
+        .main
+            CALL .recurse
+            CALL .forever
+            RET
+        .recurse
+            CALL .recurse
+            RET
+        .forever
+            JP  .forever
+        ";
+        assert_eq!(
+            lint(source_code),
+            vec![
+                LintKind::ReturnWithoutCall,
+                LintKind::Recursion { target: 0x206 },
+                LintKind::NoReturn,
+            ]
+        );
+
+        let bytecode = assemble(".a\n CALL .b\n.b\n CALL .c\n.c\n RET").unwrap();
+        let lints = Linter::new(&bytecode).with_stack_depth(1).lint();
+        assert_eq!(lints[0].kind, LintKind::StackOverflow { depth: 2 });
+    }
+}
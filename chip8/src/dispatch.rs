@@ -0,0 +1,255 @@
+//! Table of instruction handlers, looked up by opcode pattern.
+//!
+//! The built-in instructions are registered like any other handler, so
+//! extensions and experiments can replace them, or add new ones, with
+//! [`Chip8Vm::register_opcode`](crate::Chip8Vm::register_opcode).
+use std::{fmt, ops::Range};
+
+use crate::{
+    constants::MEM_SIZE,
+    error::{Chip8Error, Chip8Result},
+    vm::{Chip8Vm, Flow},
+};
+
+/// Function executing an instruction.
+///
+/// The program counter has already moved past the instruction when it's called.
+/// Handlers return [`Flow::Error`] after setting an error to stop the VM.
+pub type OpHandler = fn(&mut Chip8Vm, Operands) -> Flow;
+
+/// Fields of an instruction, named after the letters of opcode patterns.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Operands {
+    /// Highest nibble, identifying the group of the instruction.
+    pub op: u8,
+    pub vx: u8,
+    pub vy: u8,
+    /// Lowest nibble.
+    pub n: u8,
+    /// Lowest byte.
+    pub nn: u8,
+    /// Lowest 12 bits, usually an address.
+    pub nnn: u16,
+}
+
+impl Operands {
+    #[inline]
+    pub fn decode(instr: [u8; 2]) -> Self {
+        let [a, b] = instr;
+        Self {
+            op: a >> 4,
+            vx: a & 0xF,
+            vy: b >> 4,
+            n: b & 0xF,
+            nn: b,
+            nnn: (((a as u16) & 0xF) << 8) | b as u16,
+        }
+    }
+
+    /// Opcode the fields were decoded from.
+    #[inline]
+    pub fn opcode(&self) -> u16 {
+        ((self.op as u16) << 12) | self.nnn
+    }
+}
+
+/// Opcode with some of its nibbles left open, like `8xy4` or `Fx33`.
+///
+/// Hexadecimal digits must match, while the letters `x`, `y`, `n`, `k`
+/// and `_` match any nibble. The first nibble is always a digit.
+///
+/// ```
+/// # use chip8::OpPattern;
+/// let pattern = OpPattern::parse("8xy4").unwrap();
+/// assert!(pattern.matches(0x8124));
+/// assert!(!pattern.matches(0x8125));
+/// assert!(OpPattern::parse("xy00").is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpPattern {
+    /// Bits that must match.
+    mask: u16,
+    value: u16,
+}
+
+impl OpPattern {
+    pub fn parse(pattern: &str) -> Chip8Result<Self> {
+        let chars: Vec<char> = pattern.chars().collect();
+        if chars.len() != 4 {
+            return Err(pattern_error(pattern, "expected four nibbles"));
+        }
+        if !chars[0].is_ascii_hexdigit() {
+            return Err(pattern_error(pattern, "the first nibble must be a digit"));
+        }
+
+        let (mut mask, mut value) = (0, 0);
+        for c in chars {
+            mask <<= 4;
+            value <<= 4;
+            match c {
+                'x' | 'y' | 'n' | 'k' | '_' => {}
+                _ => {
+                    let digit = c.to_digit(16).ok_or_else(|| {
+                        pattern_error(pattern, "expected a digit or x, y, n, k, _")
+                    })?;
+                    mask |= 0xF;
+                    value |= digit as u16;
+                }
+            }
+        }
+
+        Ok(Self { mask, value })
+    }
+
+    #[inline]
+    pub fn matches(&self, opcode: u16) -> bool {
+        opcode & self.mask == self.value
+    }
+
+    /// Group of instructions the pattern belongs to, by the first nibble.
+    fn group(&self) -> usize {
+        (self.value >> 12) as usize
+    }
+
+    /// Number of nibbles that must match.
+    fn fixed_nibbles(&self) -> u32 {
+        self.mask.count_ones() / 4
+    }
+}
+
+impl fmt::Display for OpPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for shift in [12, 8, 4, 0] {
+            if (self.mask >> shift) & 0xF == 0 {
+                write!(f, "_")?;
+            } else {
+                write!(f, "{:X}", (self.value >> shift) & 0xF)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Handlers grouped by the first nibble of their pattern.
+///
+/// Within a group the most specific patterns come first, so `00E0 (CLS)`
+/// is found before `0nnn (SYS)`.
+#[derive(Default, Clone)]
+pub(crate) struct DispatchTable {
+    groups: [Vec<(OpPattern, OpHandler)>; 16],
+}
+
+impl DispatchTable {
+    /// Add a handler, replacing the handler of the same pattern.
+    ///
+    /// Handlers take precedence over previously registered handlers
+    /// of overlapping patterns that are equally specific.
+    pub(crate) fn register(&mut self, pattern: OpPattern, handler: OpHandler) {
+        let group = &mut self.groups[pattern.group()];
+        group.retain(|(other, _)| *other != pattern);
+        let index = group
+            .iter()
+            .position(|(other, _)| other.fixed_nibbles() <= pattern.fixed_nibbles())
+            .unwrap_or(group.len());
+        group.insert(index, (pattern, handler));
+    }
+
+    /// Handler of the opcode, if any matches.
+    #[inline]
+    pub(crate) fn lookup(&self, opcode: u16) -> Option<OpHandler> {
+        self.groups[(opcode >> 12) as usize]
+            .iter()
+            .find(|(pattern, _)| pattern.matches(opcode))
+            .map(|(_, handler)| *handler)
+    }
+}
+
+/// Handlers of decoded instructions, by address,
+/// enabled with [`Chip8Conf::decode_cache`](crate::Chip8Conf::decode_cache).
+///
+/// Entries are filled when an instruction is first executed, and must be
+/// invalidated when the memory of the instruction or the table changes.
+/// Operands are cheaper to decode again than to keep, so only the handler
+/// is stored, which keeps the cache small enough for the CPU caches.
+pub(crate) struct DecodeCache {
+    entries: Box<[Option<OpHandler>]>,
+}
+
+impl DecodeCache {
+    pub(crate) fn new() -> Self {
+        Self {
+            entries: vec![None; MEM_SIZE].into_boxed_slice(),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn get(&self, address: usize) -> Option<OpHandler> {
+        self.entries[address]
+    }
+
+    #[inline]
+    pub(crate) fn insert(&mut self, address: usize, handler: OpHandler) {
+        self.entries[address] = Some(handler);
+    }
+
+    /// Forget the instructions overlapping the range of memory,
+    /// including the one starting a byte before it.
+    pub(crate) fn invalidate(&mut self, range: Range<usize>) {
+        let start = range.start.saturating_sub(1).min(MEM_SIZE);
+        let end = range.end.min(MEM_SIZE);
+        if start < end {
+            self.entries[start..end].fill(None);
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.fill(None);
+    }
+}
+
+#[inline(never)]
+#[cold]
+fn pattern_error(pattern: &str, message: &str) -> Chip8Error {
+    Chip8Error::Opcode(format!("'{pattern}', {message}"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn first(_: &mut Chip8Vm, _: Operands) -> Flow {
+        Flow::Ok
+    }
+
+    fn second(_: &mut Chip8Vm, _: Operands) -> Flow {
+        Flow::Jump
+    }
+
+    #[test]
+    fn test_dispatch_table() {
+        let mut table = DispatchTable::default();
+        table.register(OpPattern::parse("0nnn").unwrap(), first);
+        table.register(OpPattern::parse("00E0").unwrap(), second);
+
+        // The most specific pattern wins, regardless of the order of registration.
+        let mut vm = Chip8Vm::new(Default::default());
+        let cls = table.lookup(0x00E0).unwrap();
+        assert_eq!(cls(&mut vm, Operands::decode([0x00, 0xE0])), Flow::Jump);
+        let sys = table.lookup(0x0123).unwrap();
+        assert_eq!(sys(&mut vm, Operands::decode([0x01, 0x23])), Flow::Ok);
+        assert!(table.lookup(0x1234).is_none());
+
+        // The same pattern is replaced.
+        table.register(OpPattern::parse("00E0").unwrap(), first);
+        assert_eq!(table.groups[0].len(), 2);
+
+        assert_eq!(Operands::decode([0xD1, 0x2F]).opcode(), 0xD12F);
+        assert_eq!(OpPattern::parse("Fx33").unwrap().to_string(), "F_33");
+        for pattern in ["", "8xy", "8xy44", "8xg4", "nnnn"] {
+            assert!(
+                matches!(OpPattern::parse(pattern), Err(Chip8Error::Opcode(_))),
+                "{pattern:?}"
+            );
+        }
+    }
+}
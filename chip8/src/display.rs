@@ -0,0 +1,203 @@
+//! Packed display buffer.
+use std::{fmt, iter::FusedIterator, ops::Index};
+
+use crate::constants::*;
+
+/// Bits in a packed row, one for each pixel.
+const ROW_BITS: usize = u64::BITS as usize;
+
+// Each row of pixels must fit into a single word.
+const _: () = assert!(DISPLAY_WIDTH == ROW_BITS);
+
+/// One display bitplane, packed as a `u64` per row.
+///
+/// The leftmost pixel of a row is stored in the most significant bit,
+/// so a sprite row can be drawn with a single shift and XOR.
+#[derive(Clone, PartialEq, Eq)]
+pub struct DisplayPlane {
+    rows: [u64; DISPLAY_HEIGHT],
+}
+
+impl DisplayPlane {
+    pub const fn new() -> Self {
+        Self {
+            rows: [0; DISPLAY_HEIGHT],
+        }
+    }
+
+    /// Plane with the given rows of packed pixels, from top to bottom.
+    pub const fn from_rows(rows: [u64; DISPLAY_HEIGHT]) -> Self {
+        Self { rows }
+    }
+
+    /// Rows of packed pixels, from top to bottom.
+    #[inline(always)]
+    pub fn rows(&self) -> &[u64; DISPLAY_HEIGHT] {
+        &self.rows
+    }
+
+    /// State of the pixel at the given coordinate.
+    ///
+    /// # Panics
+    ///
+    /// When the coordinate is outside the display.
+    #[inline]
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        assert!(x < DISPLAY_WIDTH, "pixel x coordinate out of bounds");
+        (self.rows[y] >> (ROW_BITS - 1 - x)) & 1 != 0
+    }
+
+    /// Set the state of the pixel at the given coordinate.
+    ///
+    /// # Panics
+    ///
+    /// When the coordinate is outside the display.
+    #[inline]
+    pub fn set(&mut self, x: usize, y: usize, state: bool) {
+        assert!(x < DISPLAY_WIDTH, "pixel x coordinate out of bounds");
+        let mask = 1 << (ROW_BITS - 1 - x);
+        if state {
+            self.rows[y] |= mask;
+        } else {
+            self.rows[y] &= !mask;
+        }
+    }
+
+    /// States of the pixels of the given row, from left to right.
+    ///
+    /// # Panics
+    ///
+    /// When the row is outside the display.
+    #[inline]
+    pub fn unpack_row(&self, y: usize) -> [bool; DISPLAY_WIDTH] {
+        let row = self.rows[y];
+        std::array::from_fn(|x| (row >> (ROW_BITS - 1 - x)) & 1 != 0)
+    }
+
+    /// Turn off all pixels.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.rows = [0; DISPLAY_HEIGHT];
+    }
+
+    /// Replace all the pixels of the given row.
+    #[inline(always)]
+    pub(crate) fn set_row(&mut self, y: usize, pixels: u64) {
+        self.rows[y] = pixels;
+    }
+
+    /// XOR the pixels into the given row.
+    ///
+    /// Returns `true` when any pixel was erased.
+    #[inline(always)]
+    pub(crate) fn xor_row(&mut self, y: usize, pixels: u64) -> bool {
+        let row = &mut self.rows[y];
+        let is_erased = *row & pixels != 0;
+        *row ^= pixels;
+        is_erased
+    }
+
+    /// Iterate the state of every pixel, row by row.
+    pub fn iter(&self) -> Pixels<'_> {
+        Pixels {
+            plane: self,
+            index: 0,
+        }
+    }
+}
+
+impl Default for DisplayPlane {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for DisplayPlane {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.rows.iter().map(|row| format!("{row:064b}")))
+            .finish()
+    }
+}
+
+/// Pixel at the given row-major index, for compatibility with
+/// consumers of the unpacked `[bool; DISPLAY_BUFFER_SIZE]` layout.
+impl Index<usize> for DisplayPlane {
+    type Output = bool;
+
+    fn index(&self, index: usize) -> &bool {
+        assert!(index < DISPLAY_BUFFER_SIZE, "pixel index out of bounds");
+        if self.get(index % DISPLAY_WIDTH, index / DISPLAY_WIDTH) {
+            &true
+        } else {
+            &false
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a DisplayPlane {
+    type Item = bool;
+    type IntoIter = Pixels<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the pixels of a display plane, row by row.
+pub struct Pixels<'a> {
+    plane: &'a DisplayPlane,
+    index: usize,
+}
+
+impl<'a> Iterator for Pixels<'a> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        if self.index < DISPLAY_BUFFER_SIZE {
+            let pixel = self.plane[self.index];
+            self.index += 1;
+            Some(pixel)
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = DISPLAY_BUFFER_SIZE - self.index;
+        (len, Some(len))
+    }
+}
+
+impl<'a> ExactSizeIterator for Pixels<'a> {}
+
+impl<'a> FusedIterator for Pixels<'a> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_display_plane_pixels() {
+        let mut plane = DisplayPlane::new();
+        plane.set(0, 0, true);
+        plane.set(63, 1, true);
+
+        assert_eq!(plane.rows()[0], 1 << 63);
+        assert_eq!(plane.rows()[1], 1);
+        assert!(plane.get(0, 0));
+        assert!(plane[DISPLAY_WIDTH + 63]);
+        assert_eq!(plane.iter().filter(|px| *px).count(), 2);
+        assert_eq!(plane.iter().len(), DISPLAY_BUFFER_SIZE);
+        assert!(plane.unpack_row(1)[63]);
+        assert_eq!(plane.unpack_row(1).iter().filter(|px| **px).count(), 1);
+
+        assert!(!plane.xor_row(2, 0b11 << 62));
+        assert!(plane.xor_row(0, 0b11 << 62));
+        assert!(!plane.get(0, 0));
+        assert!(plane.get(1, 0));
+
+        plane.clear();
+        assert_eq!(plane, DisplayPlane::default());
+    }
+}
@@ -6,14 +6,19 @@ use std::{
     string::FromUtf8Error,
 };
 
-use crate::asm::{Span, TokenKind};
+use crate::{
+    asm::{Span, TokenKind},
+    constants::{Address, REGISTER_COUNT},
+    diagnostic::{write_excerpt, Diagnostic},
+    disasm::{ir::Op, Disassembler},
+};
 
 pub type Chip8Result<T> = std::result::Result<T, Chip8Error>;
 
 #[derive(Debug)]
 pub enum Chip8Error {
     /// VM error during interpreter loop.
-    Runtime(&'static str),
+    Runtime(RuntimeError),
     /// Attempt to load a bytecode program that can't fit in memory.
     LargeProgram,
     Asm(AsmError),
@@ -24,13 +29,40 @@ pub enum Chip8Error {
     Fmt(fmt::Error),
     Io(io::Error),
     Utf8(FromUtf8Error),
+    /// Attempt to load an invalid save state.
+    State(String),
+    /// Attempt to load an invalid input replay.
+    Replay(String),
+    /// Attempt to load invalid debug information.
+    DebugInfo(String),
+    /// Attempt to load an invalid ROM descriptor.
+    RomInfo(String),
+    /// Attempt to load an invalid ROM archive.
+    Archive(String),
+    /// Attempt to load an invalid or empty ROM.
+    Rom(String),
+    /// Attempt to parse an invalid message of the network protocol.
+    Remote(String),
+    /// Attempt to register a handler for an invalid opcode pattern.
+    Opcode(String),
+    /// Attempt to load an invalid configuration file.
+    Config(String),
+    /// Access through a [`MemoryView`](crate::MemoryView) outside of memory.
+    OutOfBounds {
+        address: usize,
+        len: usize,
+    },
     Multi(Vec<Chip8Error>),
 }
 
 impl Display for Chip8Error {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match self {
-            Self::Runtime(msg) => write!(f, "runtime error: {}", msg),
+            Self::Runtime(err) => {
+                // Pass the alternate flag on, for the crash report.
+                write!(f, "runtime error: ")?;
+                Display::fmt(err, f)
+            }
             Self::LargeProgram => write!(f, "program too large for VM memory"),
             Self::Asm(err) => write!(f, "parser error: {}", err),
             Self::NumberParse(err) => write!(f, "failed to parse number literal: {err}"),
@@ -40,6 +72,21 @@ impl Display for Chip8Error {
             Self::Fmt(err) => write!(f, "{}", err),
             Self::Io(err) => write!(f, "{}", err),
             Self::Utf8(err) => write!(f, "{}", err),
+            Self::State(msg) => write!(f, "invalid save state: {msg}"),
+            Self::Replay(msg) => write!(f, "invalid replay: {msg}"),
+            Self::DebugInfo(msg) => write!(f, "invalid debug info: {msg}"),
+            Self::RomInfo(msg) => write!(f, "invalid rom info: {msg}"),
+            Self::Archive(msg) => write!(f, "invalid rom archive: {msg}"),
+            Self::Rom(msg) => write!(f, "invalid rom: {msg}"),
+            Self::Remote(msg) => write!(f, "invalid remote message: {msg}"),
+            Self::Opcode(msg) => write!(f, "invalid opcode pattern: {msg}"),
+            Self::Config(msg) => write!(f, "invalid config: {msg}"),
+            Self::OutOfBounds { address, len } => {
+                write!(
+                    f,
+                    "memory access out of bounds: {len} byte(s) at 0x{address:04X}"
+                )
+            }
             Self::Multi(errors) => {
                 // Print all errors separated with a newline
                 let count = errors.len();
@@ -57,6 +104,61 @@ impl Display for Chip8Error {
 
 impl std::error::Error for Chip8Error {}
 
+impl Chip8Error {
+    /// Flatten the error into diagnostics, one for each contained error.
+    ///
+    /// Assembler errors point at the source code, while other errors
+    /// only carry their message.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            Self::Asm(err) => vec![err.to_diagnostic()],
+            Self::Multi(errors) => errors.iter().flat_map(Self::diagnostics).collect(),
+            err => vec![Diagnostic::error(err)],
+        }
+    }
+
+    /// Returns an adaptor that displays the error with an excerpt
+    /// from the given source code.
+    ///
+    /// Assembler errors are formatted by extracting the offending line from
+    /// the source using the error's span. Errors that don't relate to source
+    /// code are displayed as normal.
+    pub fn display_with_source<'a>(&'a self, source: &'a str) -> impl Display + 'a {
+        SourceDisplay {
+            error: self,
+            source,
+        }
+    }
+}
+
+/// Adaptor for displaying an error with its source code context.
+struct SourceDisplay<'a> {
+    error: &'a Chip8Error,
+    source: &'a str,
+}
+
+impl<'a> Display for SourceDisplay<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.error {
+            Chip8Error::Asm(err) => {
+                write!(f, "parser error: ")?;
+                err.display_with_source(self.source, f)
+            }
+            Chip8Error::Multi(errors) => {
+                // Print all errors separated with a blank line
+                for (index, err) in errors.iter().enumerate() {
+                    if index > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", err.display_with_source(self.source))?;
+                }
+                Ok(())
+            }
+            err => write!(f, "{err}"),
+        }
+    }
+}
+
 impl From<fmt::Error> for Chip8Error {
     fn from(err: fmt::Error) -> Self {
         Chip8Error::Fmt(err)
@@ -82,12 +184,11 @@ pub struct AsmError {
     pub line_span: Span,
     pub line_no: usize,
     pub message: String,
+    /// Suggestion for fixing the error.
+    pub help: Option<String>,
 }
 
 impl AsmError {
-    const MARKER: u8 = 0x5E; // caret (^)
-    const SPACE: u8 = 0x20; // space ( )
-
     pub fn new(source_code: impl AsRef<str>, span: Span, message: impl ToString) -> Self {
         let (line, line_span) = span.surrounding_line(source_code.as_ref());
 
@@ -100,6 +201,21 @@ impl AsmError {
             line_span,
             line_no,
             message: message.to_string(),
+            help: None,
+        }
+    }
+
+    pub fn with_help(mut self, help: impl ToString) -> Self {
+        self.help = Some(help.to_string());
+        self
+    }
+
+    /// Convert into a diagnostic, for rendering with the file name.
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::error(&self.message).with_span(self.span.clone());
+        match &self.help {
+            Some(help) => diagnostic.with_help(help),
+            None => diagnostic,
         }
     }
 
@@ -120,47 +236,123 @@ impl AsmError {
     }
 }
 
-impl Display for AsmError {
-    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        writeln!(f, "{}", self.message)?;
-
-        let lineno = format!("{:3}", self.line_no);
-        let margin = String::from_utf8(vec![Self::SPACE; lineno.len()]).unwrap_or_default();
-        writeln!(f, "{} |", margin)?;
+impl AsmError {
+    /// Format the error using the given source code to extract the line
+    /// surrounding the error's span.
+    ///
+    /// Falls back to the line stored in the error if the span is
+    /// out of bounds for the given source.
+    pub fn display_with_source(&self, source: &str, f: &mut Formatter) -> fmt::Result {
+        let in_bounds = (self.span.end() as usize) <= source.len()
+            && source.is_char_boundary(self.span.index as usize);
 
-        writeln!(f, "{} | {}", lineno, self.line.trim_end())?;
-        //           ^^ margin
-        //               ^ padding
+        if in_bounds {
+            let (line, line_span) = self.span.surrounding_line(source);
+            self.fmt_excerpt(f, line, &line_span)
+        } else {
+            self.fmt_excerpt(f, &self.line, &self.line_span)
+        }
+    }
 
-        const PADDING: usize = 1;
-        let relative_index = (self.span.index - self.line_span.index) as usize;
-        log::trace!(
-            "token span index: {}, line span index: {}",
-            self.span.index,
-            self.line_span.index
-        );
-        log::trace!("relative index: {}", relative_index);
+    fn fmt_excerpt(&self, f: &mut Formatter, line: &str, line_span: &Span) -> fmt::Result {
+        writeln!(f, "{}", self.message)?;
 
-        let indent =
-            String::from_utf8(vec![Self::SPACE; PADDING + relative_index]).unwrap_or_default();
-        log::trace!("indent: {}", indent);
+        let width = self.line_no.to_string().len().max(3);
+        let margin = " ".repeat(width);
+        writeln!(f, "{margin} |")?;
 
-        // EOF span has size 0, so we clamp to 1 for a minimal marker to show up.
-        let marker_width = usize::max(1, self.span.size as usize);
-        let marker = String::from_utf8(vec![Self::MARKER; marker_width]).unwrap_or_default();
-        writeln!(f, "{margin} |{indent}{marker}")?; // marker
+        let relative_index = (self.span.index - line_span.index) as usize;
+        write_excerpt(
+            f,
+            width,
+            self.line_no,
+            line,
+            relative_index,
+            self.span.size as usize,
+            '^',
+            "",
+        )?;
         writeln!(f, "{margin} |")?; // empty line
 
+        if let Some(help) = &self.help {
+            writeln!(f, "{margin} = help: {help}")?;
+        }
+
         Ok(())
     }
 }
 
+impl Display for AsmError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.fmt_excerpt(f, &self.line, &self.line_span)
+    }
+}
+
 impl From<AsmError> for Chip8Error {
     fn from(err: AsmError) -> Self {
         Chip8Error::Asm(err)
     }
 }
 
+/// Error raised by the virtual machine while executing bytecode.
+///
+/// Carries a snapshot of the machine state when the error was raised,
+/// so frontends can report what the program was doing.
+///
+/// The alternate format `{:#}` prints the snapshot as a crash report.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RuntimeError {
+    /// Address of the instruction that caused the error.
+    pub pc: usize,
+    /// Raw bytecode of the instruction that caused the error.
+    pub opcode: u16,
+    pub message: &'static str,
+    /// Stack pointer.
+    pub sp: usize,
+    /// Address register `I`.
+    pub address: Address,
+    /// General purpose registers `V0` to `VF`.
+    pub registers: [u8; REGISTER_COUNT],
+}
+
+impl RuntimeError {
+    /// Assembly mnemonic of the faulting instruction,
+    /// or `None` if the opcode isn't a known instruction.
+    pub fn mnemonic(&self) -> Option<String> {
+        // The trailing address of a long load isn't kept, so it decodes as data.
+        let instr = Disassembler::decode_at(&self.opcode.to_be_bytes(), 0)
+            .filter(|instr| instr.op != Op::Data)?;
+        Some(instr.repr().to_string()).filter(|mnemonic| !mnemonic.is_empty())
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} at 0x{:04X}", self.message, self.pc)?;
+
+        if f.alternate() {
+            let mnemonic = self.mnemonic().unwrap_or_else(|| "???".to_string());
+            writeln!(f)?;
+            writeln!(f, "  instruction: {:04X}  {mnemonic}", self.opcode)?;
+            writeln!(f, "  I: 0x{:04X}  SP: {}", self.address, self.sp)?;
+            write!(f, "  V:")?;
+            for value in self.registers {
+                write!(f, " {value:02X}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl From<RuntimeError> for Chip8Error {
+    fn from(err: RuntimeError) -> Self {
+        Chip8Error::Runtime(err)
+    }
+}
+
 /// Error returned when an unexpected token type is encountered.
 #[derive(Debug)]
 pub struct TokenError {
@@ -185,3 +377,70 @@ impl From<TokenError> for Chip8Error {
         Chip8Error::Token(err)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_asm_display_with_source() {
+        let source = "CLS\nLD v0, 0xFFFF\nRET\n";
+        let err = Chip8Error::Asm(AsmError::new(source, Span::new(10, 6), "number too large"));
+
+        let output = err.display_with_source(source).to_string();
+        assert!(output.starts_with("parser error: number too large"));
+        assert!(output.contains("  2 | LD v0, 0xFFFF"));
+        assert!(output.contains("    |       ^^^^^^"));
+    }
+
+    #[test]
+    fn test_multi_display_with_source() {
+        let source = "FOO\nBAR\n";
+        let err = Chip8Error::Multi(vec![
+            Chip8Error::Asm(AsmError::new(source, Span::new(0, 3), "unknown mnemonic")),
+            Chip8Error::Asm(AsmError::new(source, Span::new(4, 3), "unknown mnemonic")),
+        ]);
+
+        let output = err.display_with_source(source).to_string();
+        assert!(output.contains("  1 | FOO"));
+        assert!(output.contains("  2 | BAR"));
+        // Blank line separates the errors.
+        assert!(output.contains("    |\n\nparser error"));
+    }
+
+    #[test]
+    fn test_runtime_display_with_source() {
+        let err = Chip8Error::Runtime(RuntimeError {
+            pc: 0x204,
+            message: "unsupported opcode",
+            ..Default::default()
+        });
+
+        assert_eq!(
+            err.display_with_source("").to_string(),
+            "runtime error: unsupported opcode at 0x0204"
+        );
+    }
+
+    #[test]
+    fn test_runtime_crash_report() {
+        let mut registers = [0; REGISTER_COUNT];
+        registers[0xF] = 0xAB;
+        let err = Chip8Error::Runtime(RuntimeError {
+            pc: 0x202,
+            opcode: 0x00EE,
+            message: "call stack underflow",
+            sp: 0,
+            address: 0x300,
+            registers,
+        });
+
+        assert_eq!(
+            format!("{err:#}"),
+            "runtime error: call stack underflow at 0x0202\n\
+             \x20 instruction: 00EE  RET\n\
+             \x20 I: 0x0300  SP: 0\n\
+             \x20 V: 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 AB"
+        );
+    }
+}
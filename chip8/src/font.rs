@@ -0,0 +1,163 @@
+//! Character sprites for `Fx29 (LD F, Vx)` and `Fx30 (LD HF, Vx)`.
+use std::path::Path;
+
+use crate::{
+    asm::{assemble_with, AsmConf},
+    constants::*,
+    error::{Chip8Error, Chip8Result},
+};
+
+/// Hexadecimal digit sprites, loaded into the interpreter area of memory.
+///
+/// The small font has a 5 byte high sprite for each of the 16 digits. The
+/// optional large font, used by SUPER-CHIP, has 10 byte high sprites for the
+/// decimal digits, and optionally the hexadecimal letters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FontSet {
+    small: Vec<u8>,
+    large: Vec<u8>,
+}
+
+impl Default for FontSet {
+    fn default() -> Self {
+        Self::builtin("default").expect("default font is built in")
+    }
+}
+
+impl FontSet {
+    /// Names of the built-in fonts, accepted by [`FontSet::builtin`].
+    pub const BUILTIN: &'static [&'static str] =
+        &["default", "vip", "dream6800", "eti660", "schip"];
+
+    /// Create a font from the sprite data.
+    ///
+    /// The small font must be [`FONTSET_DATA_LENGTH`] bytes. The large
+    /// font may be empty, or hold up to [`FONTSET_COUNT`] sprites
+    /// of [`LARGE_FONTSET_HEIGHT`] bytes.
+    pub fn new(small: Vec<u8>, large: Vec<u8>) -> Chip8Result<Self> {
+        if small.len() != FONTSET_DATA_LENGTH {
+            return Err(Chip8Error::Font(format!(
+                "fontset data must be {FONTSET_DATA_LENGTH} bytes, got {}",
+                small.len()
+            )));
+        }
+        if large.len() > LARGE_FONTSET_DATA_LENGTH
+            || !large.len().is_multiple_of(LARGE_FONTSET_HEIGHT)
+        {
+            return Err(Chip8Error::Font(format!(
+                "large fontset data must be a multiple of {LARGE_FONTSET_HEIGHT} bytes, \
+                 up to {LARGE_FONTSET_DATA_LENGTH} bytes, got {}",
+                large.len()
+            )));
+        }
+
+        Ok(Self { small, large })
+    }
+
+    /// Look up a built-in font by name.
+    ///
+    /// - `default`: the font of this VM.
+    /// - `vip`: the font of the COSMAC VIP interpreter.
+    /// - `dream6800`: the font of the Dream 6800 interpreter.
+    /// - `eti660`: the font of the ETI-660 interpreter.
+    /// - `schip`: the default font, with the SUPER-CHIP large decimal digits.
+    pub fn builtin(name: &str) -> Option<Self> {
+        let (small, large) = match name {
+            "default" => (include_str!("fonts/default.asm"), None),
+            "vip" => (include_str!("fonts/cosmac_vip.asm"), None),
+            "dream6800" => (include_str!("fonts/dream_6800.asm"), None),
+            "eti660" => (include_str!("fonts/eti_660.asm"), None),
+            "schip" => (
+                include_str!("fonts/default.asm"),
+                Some(include_str!("fonts/schip_large.asm")),
+            ),
+            _ => return None,
+        };
+
+        let small = assemble_font(small).expect("built-in font assembles");
+        let large = large
+            .map(|source_code| assemble_font(source_code).expect("built-in font assembles"))
+            .unwrap_or_default();
+        Some(Self::new(small, large).expect("built-in font is valid"))
+    }
+
+    /// Assemble a font from source code.
+    ///
+    /// The small font sprites come first, optionally followed by the large font.
+    pub fn from_asm(source_code: &str) -> Chip8Result<Self> {
+        Self::from_bytes(&assemble_font(source_code)?)
+    }
+
+    /// Create a font from binary data.
+    ///
+    /// The small font sprites come first, optionally followed by the large font.
+    pub fn from_bytes(data: &[u8]) -> Chip8Result<Self> {
+        let split = data.len().min(FONTSET_DATA_LENGTH);
+        Self::new(data[..split].to_vec(), data[split..].to_vec())
+    }
+
+    /// Load a font from a file.
+    ///
+    /// Files with the `.asm` extension are assembled, others are read as binary data.
+    pub fn load(filepath: impl AsRef<Path>) -> Chip8Result<Self> {
+        let filepath = filepath.as_ref();
+        if filepath.extension().is_some_and(|ext| ext == "asm") {
+            Self::from_asm(&std::fs::read_to_string(filepath)?)
+        } else {
+            Self::from_bytes(&std::fs::read(filepath)?)
+        }
+    }
+
+    /// Sprites of the small font, 5 bytes per digit.
+    pub fn small(&self) -> &[u8] {
+        &self.small
+    }
+
+    /// Sprites of the large font, 10 bytes per digit.
+    pub fn large(&self) -> &[u8] {
+        &self.large
+    }
+}
+
+fn assemble_font(source_code: &str) -> Chip8Result<Vec<u8>> {
+    let conf = AsmConf {
+        // Fonts are 5 bytes high, and packed together for historical reasons.
+        pad_data: false,
+        ..Default::default()
+    };
+    assemble_with(source_code, conf)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_builtin_fonts() {
+        for name in FontSet::BUILTIN {
+            let font = FontSet::builtin(name).unwrap();
+            assert_eq!(font.small().len(), FONTSET_DATA_LENGTH, "{name}");
+        }
+        assert!(FontSet::builtin("unknown").is_none());
+
+        let schip = FontSet::builtin("schip").unwrap();
+        assert_eq!(schip.small(), FontSet::default().small());
+        assert_eq!(schip.large().len(), 10 * LARGE_FONTSET_HEIGHT);
+        // Large zero
+        assert_eq!(&schip.large()[..2], &[0x3C, 0x7E]);
+
+        // Fonts differ in the digit 1
+        let vip = FontSet::builtin("vip").unwrap();
+        assert_eq!(&vip.small()[5..10], &[0x60, 0x20, 0x20, 0x20, 0x70]);
+    }
+
+    #[test]
+    fn test_font_from_bytes() {
+        let data = vec![0xF0; FONTSET_DATA_LENGTH + 2 * LARGE_FONTSET_HEIGHT];
+        let font = FontSet::from_bytes(&data).unwrap();
+        assert_eq!(font.large().len(), 2 * LARGE_FONTSET_HEIGHT);
+
+        assert!(FontSet::from_bytes(&data[..FONTSET_DATA_LENGTH - 1]).is_err());
+        assert!(FontSet::from_bytes(&data[..FONTSET_DATA_LENGTH + 3]).is_err());
+    }
+}
@@ -0,0 +1,115 @@
+; ===================== ;
+;    COSMAC VIP Font    ;
+; ===================== ;
+
+.sprite_0
+    0b11110000
+    0b10010000
+    0b10010000
+    0b10010000
+    0b11110000
+
+.sprite_1
+    0b01100000
+    0b00100000
+    0b00100000
+    0b00100000
+    0b01110000
+
+.sprite_2
+    0b11110000
+    0b00010000
+    0b11110000
+    0b10000000
+    0b11110000
+
+.sprite_3
+    0b11110000
+    0b00010000
+    0b11110000
+    0b00010000
+    0b11110000
+
+.sprite_4
+    0b10100000
+    0b10100000
+    0b11110000
+    0b00100000
+    0b00100000
+
+.sprite_5
+    0b11110000
+    0b10000000
+    0b11110000
+    0b00010000
+    0b11110000
+
+.sprite_6
+    0b11110000
+    0b10000000
+    0b11110000
+    0b10010000
+    0b11110000
+
+.sprite_7
+    0b11110000
+    0b00010000
+    0b00100000
+    0b01000000
+    0b01000000
+
+.sprite_8
+    0b11110000
+    0b10010000
+    0b11110000
+    0b10010000
+    0b11110000
+
+.sprite_9
+    0b11110000
+    0b10010000
+    0b11110000
+    0b00010000
+    0b11110000
+
+.sprite_A
+    0b11110000
+    0b10010000
+    0b11110000
+    0b10010000
+    0b10010000
+
+.sprite_B
+    0b11100000
+    0b10010000
+    0b11100000
+    0b10010000
+    0b11100000
+
+.sprite_C
+    0b11110000
+    0b10000000
+    0b10000000
+    0b10000000
+    0b11110000
+
+.sprite_D
+    0b11100000
+    0b10010000
+    0b10010000
+    0b10010000
+    0b11100000
+
+.sprite_E
+    0b11110000
+    0b10000000
+    0b11110000
+    0b10000000
+    0b11110000
+
+.sprite_F
+    0b11110000
+    0b10000000
+    0b11110000
+    0b10000000
+    0b10000000
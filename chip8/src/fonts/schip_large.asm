@@ -0,0 +1,123 @@
+; =========================== ;
+;    SUPER-CHIP Large Font    ;
+; =========================== ;
+
+.sprite_0
+    0b00111100
+    0b01111110
+    0b11100111
+    0b11000011
+    0b11000011
+    0b11000011
+    0b11000011
+    0b11100111
+    0b01111110
+    0b00111100
+
+.sprite_1
+    0b00011000
+    0b00111000
+    0b01011000
+    0b00011000
+    0b00011000
+    0b00011000
+    0b00011000
+    0b00011000
+    0b00011000
+    0b00111100
+
+.sprite_2
+    0b00111110
+    0b01111111
+    0b11000011
+    0b00000110
+    0b00001100
+    0b00011000
+    0b00110000
+    0b01100000
+    0b11111111
+    0b11111111
+
+.sprite_3
+    0b00111100
+    0b01111110
+    0b11000011
+    0b00000011
+    0b00001110
+    0b00001110
+    0b00000011
+    0b11000011
+    0b01111110
+    0b00111100
+
+.sprite_4
+    0b00000110
+    0b00001110
+    0b00011110
+    0b00110110
+    0b01100110
+    0b11000110
+    0b11111111
+    0b11111111
+    0b00000110
+    0b00000110
+
+.sprite_5
+    0b11111111
+    0b11111111
+    0b11000000
+    0b11000000
+    0b11111100
+    0b11111110
+    0b00000011
+    0b11000011
+    0b01111110
+    0b00111100
+
+.sprite_6
+    0b00111110
+    0b01111100
+    0b11000000
+    0b11000000
+    0b11111100
+    0b11111110
+    0b11000011
+    0b11000011
+    0b01111110
+    0b00111100
+
+.sprite_7
+    0b11111111
+    0b11111111
+    0b00000011
+    0b00000110
+    0b00001100
+    0b00011000
+    0b00110000
+    0b01100000
+    0b01100000
+    0b01100000
+
+.sprite_8
+    0b00111100
+    0b01111110
+    0b11000011
+    0b11000011
+    0b01111110
+    0b01111110
+    0b11000011
+    0b11000011
+    0b01111110
+    0b00111100
+
+.sprite_9
+    0b00111100
+    0b01111110
+    0b11000011
+    0b11000011
+    0b01111111
+    0b00111111
+    0b00000011
+    0b00000011
+    0b00111110
+    0b01111100
@@ -0,0 +1,127 @@
+//! Export of the display as an image.
+use crate::constants::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+#[cfg(feature = "png")]
+use crate::error::Chip8Result;
+
+/// Colours of the combined bitplane states, indexed by
+/// `plane0 | (plane1 << 1)`, as RGBA.
+///
+/// A single plane program is drawn as white on black.
+pub const DISPLAY_PALETTE: [[u8; 4]; 4] = [
+    [0x00, 0x00, 0x00, 0xFF],
+    [0xFF, 0xFF, 0xFF, 0xFF],
+    [0xAA, 0xAA, 0xAA, 0xFF],
+    [0x55, 0x55, 0x55, 0xFF],
+];
+
+/// RGBA pixel buffer of the display.
+///
+/// Rows are laid out top to bottom, with 4 bytes per pixel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayImage {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl DisplayImage {
+    /// Build the image from the palette indices of the display pixels,
+    /// with every display pixel scaled into a square of `scale` pixels.
+    pub(crate) fn new(scale: usize, color_index: impl Fn(usize, usize) -> usize) -> Self {
+        let scale = scale.max(1);
+        let width = DISPLAY_WIDTH * scale;
+        let height = DISPLAY_HEIGHT * scale;
+        let mut pixels = Vec::with_capacity(width * height * 4);
+
+        for y in 0..height {
+            for x in 0..width {
+                let color = DISPLAY_PALETTE[color_index(x / scale, y / scale)];
+                pixels.extend_from_slice(&color);
+            }
+        }
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn into_pixels(self) -> Vec<u8> {
+        self.pixels
+    }
+
+    /// Encode the image as PNG.
+    #[cfg(feature = "png")]
+    pub fn write_png(&self, writer: impl std::io::Write) -> Chip8Result<()> {
+        let to_io = |err: png::EncodingError| std::io::Error::other(err);
+
+        let mut encoder = png::Encoder::new(writer, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header().map_err(to_io)?;
+        writer.write_image_data(&self.pixels).map_err(to_io)?;
+        writer.finish().map_err(to_io)?;
+
+        Ok(())
+    }
+
+    /// Encode the image as PNG, and write it to the given file.
+    #[cfg(feature = "png")]
+    pub fn save_png(&self, filepath: impl AsRef<std::path::Path>) -> Chip8Result<()> {
+        let file = std::fs::File::create(filepath)?;
+        self.write_png(std::io::BufWriter::new(file))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_display_image() {
+        let image = DisplayImage::new(2, |x, y| if (x, y) == (1, 0) { 1 } else { 0 });
+        assert_eq!(image.width(), DISPLAY_WIDTH * 2);
+        assert_eq!(image.height(), DISPLAY_HEIGHT * 2);
+        assert_eq!(image.pixels().len(), image.width() * image.height() * 4);
+
+        let pixel = |x: usize, y: usize| {
+            let offset = (x + y * image.width()) * 4;
+            &image.pixels()[offset..offset + 4]
+        };
+        assert_eq!(pixel(0, 0), DISPLAY_PALETTE[0]);
+        assert_eq!(pixel(2, 0), DISPLAY_PALETTE[1]);
+        assert_eq!(pixel(3, 1), DISPLAY_PALETTE[1]);
+        assert_eq!(pixel(4, 0), DISPLAY_PALETTE[0]);
+        assert_eq!(pixel(2, 2), DISPLAY_PALETTE[0]);
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_write_png() {
+        let image = DisplayImage::new(1, |x, _| x % 2);
+        let mut buf = vec![];
+        image.write_png(&mut buf).unwrap();
+
+        let decoder = png::Decoder::new(buf.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(info.width as usize, DISPLAY_WIDTH);
+        assert_eq!(info.height as usize, DISPLAY_HEIGHT);
+        assert_eq!(pixels, image.into_pixels());
+    }
+}
@@ -0,0 +1,68 @@
+//! Common interface of the execution backends.
+use crate::{
+    error::{Chip8Error, Chip8Result},
+    vm::{Chip8Vm, Flow},
+};
+
+/// Execution backend for Chip-8 programs.
+///
+/// Backends differ in how instructions are executed, but share the machine
+/// state of a [`Chip8Vm`], so they can be used interchangeably.
+pub trait Interpreter {
+    type Error: From<Chip8Error>;
+
+    /// Execute a single instruction.
+    fn step(&mut self) -> Result<Flow, Self::Error>;
+
+    /// Execute a single instruction of a frame, without counting down the
+    /// timers in real time, see [`Debugger::exec`](crate::Debugger::exec).
+    ///
+    /// The frame is ended with [`Chip8Vm::finish_frame`]. Backends that
+    /// don't override this step the instruction instead.
+    fn exec(&mut self) -> Result<Flow, Self::Error> {
+        self.step()
+    }
+
+    /// Execute the given number of instructions.
+    ///
+    /// Execution stops early when the VM is interrupted.
+    fn run_steps(&mut self, step_count: usize) -> Result<Flow, Self::Error> {
+        for _ in 0..step_count {
+            if let Flow::Interrupt = self.step()? {
+                return Ok(Flow::Interrupt);
+            }
+        }
+
+        Ok(Flow::Ok)
+    }
+
+    /// Machine state, for inspecting the registers, memory and display.
+    fn machine(&self) -> &Chip8Vm;
+
+    /// Mutable machine state, for input and debugging.
+    fn machine_mut(&mut self) -> &mut Chip8Vm;
+}
+
+impl Interpreter for Chip8Vm {
+    type Error = Chip8Error;
+
+    fn step(&mut self) -> Chip8Result<Flow> {
+        self.debugger().step()
+    }
+
+    fn exec(&mut self) -> Chip8Result<Flow> {
+        self.debugger().exec()
+    }
+
+    fn run_steps(&mut self, step_count: usize) -> Chip8Result<Flow> {
+        Chip8Vm::run_steps(self, step_count)
+    }
+
+    fn machine(&self) -> &Chip8Vm {
+        self
+    }
+
+    fn machine_mut(&mut self) -> &mut Chip8Vm {
+        self
+    }
+}
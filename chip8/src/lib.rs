@@ -1,20 +1,78 @@
+mod archive;
 pub mod asm;
+mod async_vm;
+mod audio;
+mod builder;
 mod bytecode;
 mod clock;
+#[cfg(feature = "config")]
+mod config;
 pub mod constants;
 mod cpu;
+mod debug_info;
 mod devices;
-mod disasm;
+pub mod diagnostic;
+pub mod disasm;
+mod dispatch;
+mod display;
 mod error;
+mod font;
+mod image;
+mod interpreter;
+mod memory_map;
+mod memory_view;
+mod observer;
+mod peripheral;
+mod profile;
+mod quirks;
+mod random;
+pub mod remote;
+mod replay;
+pub mod rom;
+pub mod rom_db;
+mod rom_info;
+mod snapshot;
+mod state;
+pub mod testing;
+mod variant;
 mod vm;
 
+#[cfg(feature = "rand")]
+pub use self::random::StdRandom;
 pub use self::{
+    archive::{RomArchive, ARCHIVE_EXT, ARCHIVE_VERSION},
     asm::{assemble, AsmConf},
-    cpu::{Chip8Cpu, Chip8DisplayBuffer},
-    devices::KeyCode,
-    error::{Chip8Error, Chip8Result},
+    async_vm::{Chip8AsyncVm, DisplayFrame, VmCommand, VmEvent},
+    audio::{AudioConf, AudioState, Synth, Waveform},
+    builder::Chip8VmBuilder,
+    cpu::{Chip8Cpu, Chip8DisplayBuffer, CpuSnapshot, CpuState},
+    debug_info::{DebugInfo, DEBUG_INFO_EXT, DEBUG_INFO_VERSION},
+    devices::{HeldKeys, KeyCode, KeyEvent, KeyPolicy, KeyState, KeyWait, Mouse},
+    diagnostic::{Diagnostic, Severity},
+    dispatch::{OpHandler, OpPattern, Operands},
+    display::{DisplayPlane, Pixels},
+    error::{AsmError, Chip8Error, Chip8Result, RuntimeError},
+    font::FontSet,
+    image::{DisplayImage, DISPLAY_PALETTE},
+    interpreter::Interpreter,
+    memory_map::{MemoryMap, MemoryProtection, MemoryRegion, RegionKind},
+    memory_view::{ChangeOrigin, MemoryChange, MemoryView, PAGE_COUNT, PAGE_SIZE},
+    observer::{DrawRegion, VmObserver},
+    peripheral::Peripheral,
+    profile::Profiler,
+    quirks::{Quirks, SpriteEdge},
+    random::{DefaultRandom, RandomSource, XorShiftRandom},
+    replay::{Replay, ReplayEvent, REPLAY_VERSION},
+    rom::Rom,
+    rom_info::{RomInfo, ROM_INFO_EXT},
+    snapshot::{capture_display, DisplayDiff},
+    state::STATE_VERSION,
+    variant::{Extension, Variant},
     vm::Hz,
-    vm::{Chip8Conf, Chip8Vm, Flow},
+    vm::{
+        Chip8Conf, Chip8Vm, Debugger, Flow, FrameReport, RewindConf, SelfModify, SelfModifyPolicy,
+        TimerEvent,
+    },
 };
 
 /// Version of *this* implementation.
@@ -22,9 +80,17 @@ pub const IMPL_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 pub mod prelude {
     pub use super::{
-        cpu::Chip8Cpu,
+        archive::RomArchive,
+        builder::Chip8VmBuilder,
+        cpu::{Chip8Cpu, CpuState},
+        debug_info::DebugInfo,
         disasm::{Disassembler, DisassemblerV2},
         error::{Chip8Error, Chip8Result},
-        vm::{Chip8Conf, Chip8Vm},
+        interpreter::Interpreter,
+        memory_view::MemoryView,
+        quirks::Quirks,
+        rom_info::RomInfo,
+        variant::Variant,
+        vm::{Chip8Conf, Chip8Vm, Debugger},
     };
 }
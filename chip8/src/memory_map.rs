@@ -0,0 +1,194 @@
+//! Layout of memory, with the purpose and protection of every region.
+use std::{fmt, ops::Range};
+
+use crate::constants::{LARGE_FONTSET_DATA_LENGTH, LARGE_FONTSET_START, MEM_SIZE, MEM_START};
+
+/// End of the font area, after the small and large character sprites.
+const FONT_END: usize = LARGE_FONTSET_START as usize + LARGE_FONTSET_DATA_LENGTH;
+
+/// Purpose of a region of memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    /// Character sprites of the fontset.
+    Font,
+    /// Reserved for the interpreter on the original hardware,
+    /// between the font and the program.
+    Interpreter,
+    /// Bytecode loaded from the ROM.
+    Program,
+    /// Memory after the program, free for it to use.
+    WorkRam,
+}
+
+impl fmt::Display for RegionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Font => write!(f, "font"),
+            Self::Interpreter => write!(f, "interpreter"),
+            Self::Program => write!(f, "program"),
+            Self::WorkRam => write!(f, "work ram"),
+        }
+    }
+}
+
+/// Contiguous range of memory with a single purpose.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryRegion {
+    pub kind: RegionKind,
+    pub range: Range<usize>,
+    /// Writes by the program stop the VM with an error.
+    pub read_only: bool,
+    /// Executing an instruction in the region stops the VM with an error.
+    pub no_execute: bool,
+}
+
+/// Protection of memory regions, configured with
+/// [`Chip8Conf::memory_protection`](crate::Chip8Conf::memory_protection).
+///
+/// Catches common bugs in ROMs, like jumping into the font area,
+/// or storing registers through an uninitialised `I`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct MemoryProtection {
+    /// Writes to the font stop the VM with an error.
+    pub read_only_font: bool,
+    /// Executing below the start of the program, usually `0x200`, stops the VM with an error.
+    pub trap_low_execute: bool,
+}
+
+impl MemoryProtection {
+    /// All protections enabled.
+    pub const ALL: Self = Self {
+        read_only_font: true,
+        trap_low_execute: true,
+    };
+}
+
+/// Regions covering all of memory, in address order.
+///
+/// ```
+/// # use chip8::{MemoryMap, MemoryProtection, RegionKind};
+/// let map = MemoryMap::new(0x200, 0x100, MemoryProtection::ALL);
+/// assert_eq!(map.kind_at(0x000), Some(RegionKind::Font));
+/// assert_eq!(map.kind_at(0x2FF), Some(RegionKind::Program));
+/// assert_eq!(map.kind_at(0x300), Some(RegionKind::WorkRam));
+/// assert!(!map.is_executable(0x100));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryMap {
+    regions: Vec<MemoryRegion>,
+}
+
+impl Default for MemoryMap {
+    fn default() -> Self {
+        Self::new(MEM_START, 0, MemoryProtection::default())
+    }
+}
+
+impl MemoryMap {
+    /// Standard layout for a program of the given size, loaded at the address.
+    pub fn new(load_address: usize, program_len: usize, protection: MemoryProtection) -> Self {
+        let load_address = load_address.clamp(FONT_END, MEM_SIZE);
+        let program_end = (load_address + program_len).min(MEM_SIZE);
+        let layout = [
+            (RegionKind::Font, 0..FONT_END),
+            (RegionKind::Interpreter, FONT_END..load_address),
+            (RegionKind::Program, load_address..program_end),
+            (RegionKind::WorkRam, program_end..MEM_SIZE),
+        ];
+
+        let regions = layout
+            .into_iter()
+            .filter(|(_, range)| !range.is_empty())
+            .map(|(kind, range)| MemoryRegion {
+                kind,
+                read_only: kind == RegionKind::Font && protection.read_only_font,
+                no_execute: range.end <= load_address && protection.trap_low_execute,
+                range,
+            })
+            .collect();
+
+        Self { regions }
+    }
+
+    pub fn regions(&self) -> &[MemoryRegion] {
+        &self.regions
+    }
+
+    /// Region containing the address, or `None` outside of memory.
+    pub fn region(&self, address: usize) -> Option<&MemoryRegion> {
+        self.regions
+            .iter()
+            .find(|region| region.range.contains(&address))
+    }
+
+    pub fn kind_at(&self, address: usize) -> Option<RegionKind> {
+        self.region(address).map(|region| region.kind)
+    }
+
+    /// Indicates whether an instruction at the address may be executed.
+    pub fn is_executable(&self, address: usize) -> bool {
+        self.region(address)
+            .map(|region| !region.no_execute)
+            .unwrap_or(false)
+    }
+
+    /// First read-only region overlapping the range, if any.
+    pub fn read_only_region(&self, range: Range<usize>) -> Option<&MemoryRegion> {
+        self.regions.iter().find(|region| {
+            region.read_only && region.range.start < range.end && range.start < region.range.end
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::constants::ETI660_START;
+
+    #[test]
+    fn test_memory_map() {
+        let map = MemoryMap::new(MEM_START, 4, MemoryProtection::default());
+        let kinds: Vec<_> = map.regions().iter().map(|region| region.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                RegionKind::Font,
+                RegionKind::Interpreter,
+                RegionKind::Program,
+                RegionKind::WorkRam
+            ]
+        );
+        assert_eq!(map.region(0x203).unwrap().range, 0x200..0x204);
+        assert_eq!(map.kind_at(0x204), Some(RegionKind::WorkRam));
+        assert_eq!(map.kind_at(MEM_SIZE), None);
+        assert!(map.is_executable(0x000));
+        assert!(map.read_only_region(0..MEM_SIZE).is_none());
+
+        // Empty regions are left out.
+        let map = MemoryMap::default();
+        assert_eq!(map.kind_at(0x200), Some(RegionKind::WorkRam));
+
+        let map = MemoryMap::new(MEM_START, 4, MemoryProtection::ALL);
+        assert!(!map.is_executable(0x000));
+        assert!(!map.is_executable(0x1FE));
+        assert!(map.is_executable(0x200));
+        assert!(map.is_executable(0x300));
+        assert_eq!(
+            map.read_only_region(FONT_END - 1..FONT_END + 2)
+                .map(|region| region.kind),
+            Some(RegionKind::Font)
+        );
+        assert!(map.read_only_region(FONT_END..MEM_SIZE).is_none());
+
+        // The interpreter area grows with the load address.
+        let map = MemoryMap::new(ETI660_START, 4, MemoryProtection::ALL);
+        assert_eq!(map.kind_at(0x200), Some(RegionKind::Interpreter));
+        assert_eq!(map.kind_at(ETI660_START), Some(RegionKind::Program));
+        assert!(!map.is_executable(0x200));
+    }
+}
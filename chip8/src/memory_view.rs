@@ -0,0 +1,163 @@
+//! Paged, bounds-checked access to the memory of a VM.
+use crate::{
+    constants::{Address, MEM_SIZE},
+    error::{Chip8Error, Chip8Result},
+    vm::Chip8Vm,
+};
+
+/// Number of bytes in a page of memory.
+pub const PAGE_SIZE: usize = 256;
+
+/// Number of pages in the memory of a VM.
+pub const PAGE_COUNT: usize = MEM_SIZE / PAGE_SIZE;
+
+/// Write to a range of memory, passed to the listeners added with
+/// [`Chip8Vm::add_memory_listener`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryChange {
+    /// Start of the written memory.
+    pub address: Address,
+    /// Number of bytes written.
+    pub len: usize,
+    pub origin: ChangeOrigin,
+}
+
+impl MemoryChange {
+    /// Indicates whether the change includes the address.
+    pub fn contains(&self, address: usize) -> bool {
+        let start = self.address as usize;
+        (start..start + self.len).contains(&address)
+    }
+}
+
+/// What made a change to memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeOrigin {
+    /// Stored by an instruction of the program, like `Fx55`.
+    Program,
+    /// Edited through a [`MemoryView`].
+    View,
+}
+
+/// Editor of the memory of a VM, for frontends like debuggers and hex viewers.
+///
+/// Reads and writes are checked against the size of memory, and writes
+/// are reported to the listeners of the VM, so other views can refresh.
+///
+/// ```
+/// # use chip8::{prelude::*, constants::MEM_START};
+/// let mut vm = Chip8Vm::new(Chip8Conf::default());
+/// let mut view = vm.memory_view();
+/// view.write(MEM_START, &[0x12, 0x00]).unwrap();
+/// assert_eq!(view.read(MEM_START, 2).unwrap(), &[0x12, 0x00]);
+/// assert!(view.read_byte(0x1000).is_err());
+/// ```
+pub struct MemoryView<'a> {
+    vm: &'a mut Chip8Vm,
+}
+
+impl<'a> MemoryView<'a> {
+    pub(crate) fn new(vm: &'a mut Chip8Vm) -> Self {
+        Self { vm }
+    }
+
+    /// Index of the page that contains the address.
+    pub fn page_of(address: usize) -> usize {
+        address / PAGE_SIZE
+    }
+
+    /// Bytes of the page at the index.
+    ///
+    /// Returns `None` when the index is out of bounds.
+    pub fn page(&self, index: usize) -> Option<&[u8]> {
+        let start = index.checked_mul(PAGE_SIZE)?;
+        self.vm.ram().get(start..start + PAGE_SIZE)
+    }
+
+    pub fn read_byte(&self, address: usize) -> Chip8Result<u8> {
+        Ok(self.read(address, 1)?[0])
+    }
+
+    /// Read the range of memory starting at the address.
+    pub fn read(&self, address: usize, len: usize) -> Chip8Result<&[u8]> {
+        check_bounds(address, len)?;
+        Ok(&self.vm.ram()[address..address + len])
+    }
+
+    /// Write a byte, returning the previous value.
+    pub fn write_byte(&mut self, address: usize, value: u8) -> Chip8Result<u8> {
+        let previous = self.read_byte(address)?;
+        self.write(address, &[value])?;
+        Ok(previous)
+    }
+
+    /// Write the bytes to memory starting at the address.
+    ///
+    /// Nothing is written when the range doesn't fit in memory.
+    pub fn write(&mut self, address: usize, data: &[u8]) -> Chip8Result<()> {
+        check_bounds(address, data.len())?;
+        self.vm.ram_mut()[address..address + data.len()].copy_from_slice(data);
+        self.vm.notify_memory_change(MemoryChange {
+            address: address as Address,
+            len: data.len(),
+            origin: ChangeOrigin::View,
+        });
+        Ok(())
+    }
+}
+
+fn check_bounds(address: usize, len: usize) -> Chip8Result<()> {
+    match address.checked_add(len) {
+        Some(end) if end <= MEM_SIZE => Ok(()),
+        _ => Err(Chip8Error::OutOfBounds { address, len }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::{constants::MEM_START, vm::Chip8Conf};
+
+    #[test]
+    fn test_memory_view() {
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+        vm.load_bytecode(&[
+            0x60, 0x07, // LD v0, 7
+            0xA3, 0x00, // LD I, 0x300
+            0xF0, 0x55, // LD [I], v0
+        ])
+        .unwrap();
+
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let sink = changes.clone();
+        vm.add_memory_listener(move |change| sink.borrow_mut().push(*change));
+
+        let mut view = vm.memory_view();
+        assert_eq!(view.page(MemoryView::page_of(MEM_START)).unwrap()[0], 0x60);
+        assert!(view.page(PAGE_COUNT).is_none());
+        assert_eq!(view.write_byte(MEM_START + 1, 0x09).unwrap(), 0x07);
+        assert!(view.write(MEM_SIZE - 1, &[0, 0]).is_err());
+        assert!(view.read(usize::MAX, 2).is_err());
+
+        vm.run_steps(3).unwrap();
+        assert_eq!(vm.memory_view().read_byte(0x300).unwrap(), 0x09);
+
+        assert_eq!(
+            *changes.borrow(),
+            [
+                MemoryChange {
+                    address: (MEM_START + 1) as Address,
+                    len: 1,
+                    origin: ChangeOrigin::View,
+                },
+                MemoryChange {
+                    address: 0x300,
+                    len: 1,
+                    origin: ChangeOrigin::Program,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,44 @@
+//! Runtime hooks into the execution of the VM.
+use crate::constants::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// Observer of the execution of a VM, registered with
+/// [`Chip8Vm::add_observer`](crate::Chip8Vm::add_observer).
+///
+/// Unlike the `tracing` events of the VM, observers receive the values
+/// directly, so profilers and debuggers don't need to parse log output.
+/// All callbacks do nothing by default.
+pub trait VmObserver {
+    /// Called before the instruction at the address is executed.
+    fn on_instruction(&mut self, _pc: usize, _opcode: u16) {}
+
+    /// Called after a sprite is drawn, or the display is cleared.
+    fn on_draw(&mut self, _region: DrawRegion) {}
+
+    /// Called when the buzzer turns on or off.
+    fn on_sound(&mut self, _on: bool) {}
+
+    /// Called when the program starts waiting for a keypress.
+    fn on_key_wait(&mut self) {}
+}
+
+/// Area of the display changed by an instruction, in pixels.
+///
+/// Sprites wrap around the edges of the display, so the
+/// region can extend past the right and bottom edges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrawRegion {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl DrawRegion {
+    /// The whole display.
+    pub const FULL: Self = Self {
+        x: 0,
+        y: 0,
+        width: DISPLAY_WIDTH,
+        height: DISPLAY_HEIGHT,
+    };
+}
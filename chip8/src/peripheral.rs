@@ -0,0 +1,43 @@
+//! Hooks for custom hardware extensions.
+use std::ops::Range;
+
+use crate::constants::REGISTER_COUNT;
+
+/// Custom hardware attached to the virtual machine,
+/// registered with [`Chip8Vm::add_peripheral`](crate::Chip8Vm::add_peripheral).
+///
+/// A peripheral can claim a range of memory as memory-mapped IO. Bytes
+/// stored to the range by `Fx33` and `Fx55` are passed to [`Peripheral::write`],
+/// and bytes loaded from it by `Fx65` and `Dxyn` are taken from [`Peripheral::read`].
+///
+/// It can also provide the machine code routines called with `0nnn (SYS addr)`.
+pub trait Peripheral {
+    /// Addresses claimed for memory-mapped IO.
+    /// Default: no memory is claimed.
+    fn memory_range(&self) -> Range<usize> {
+        0..0
+    }
+
+    /// Byte loaded by the program from a claimed address.
+    ///
+    /// Returns `None` to leave the byte in memory as it is.
+    fn read(&mut self, _address: usize) -> Option<u8> {
+        None
+    }
+
+    /// Byte stored by the program to a claimed address.
+    ///
+    /// The byte is also kept in memory.
+    fn write(&mut self, _address: usize, _value: u8) {}
+
+    /// Machine code routine called by `0nnn (SYS addr)`.
+    ///
+    /// Returns whether the routine was handled. Unhandled calls are passed
+    /// on to the next peripheral, and finally to the interpreter.
+    fn sys(&mut self, _nnn: u16, _registers: &mut [u8; REGISTER_COUNT]) -> bool {
+        false
+    }
+
+    /// Called after every executed instruction.
+    fn step(&mut self) {}
+}
@@ -0,0 +1,194 @@
+//! Instruction execution profiler.
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::{constants::*, disasm::Disassembler};
+
+/// Execution counts per address and per kind of instruction.
+///
+/// Enabled with [`Chip8Conf::profile`](crate::Chip8Conf::profile).
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    /// Number of times the instruction at each address was executed.
+    addresses: Box<[u64]>,
+    /// Number of executed instructions by opcode pattern.
+    kinds: BTreeMap<&'static str, u64>,
+    total: u64,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            addresses: vec![0; MEM_SIZE].into_boxed_slice(),
+            kinds: BTreeMap::new(),
+            total: 0,
+        }
+    }
+
+    /// Count the execution of an instruction.
+    #[inline]
+    pub(crate) fn record(&mut self, pc: usize, instr: [u8; 2]) {
+        if let Some(count) = self.addresses.get_mut(pc) {
+            *count += 1;
+        }
+        *self.kinds.entry(opcode_kind(instr)).or_default() += 1;
+        self.total += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.addresses.fill(0);
+        self.kinds.clear();
+        self.total = 0;
+    }
+
+    /// Total number of executed instructions.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Number of times the instruction at the address was executed.
+    pub fn count_at(&self, address: usize) -> u64 {
+        self.addresses.get(address).copied().unwrap_or_default()
+    }
+
+    /// Executed addresses with their counts, from most to least executed.
+    pub fn hot_spots(&self) -> Vec<(usize, u64)> {
+        let mut hot_spots: Vec<_> = self
+            .addresses
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(address, count)| (address, *count))
+            .collect();
+        // Stable sort keeps equally hot addresses in memory order.
+        hot_spots.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        hot_spots
+    }
+
+    /// Opcode patterns with their counts, from most to least executed.
+    pub fn opcode_kinds(&self) -> Vec<(&'static str, u64)> {
+        let mut kinds: Vec<_> = self.kinds.iter().map(|(k, c)| (*k, *c)).collect();
+        kinds.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        kinds
+    }
+
+    /// Format the hottest addresses and the opcode patterns as a table.
+    ///
+    /// When the memory is given, the addresses are annotated with the disassembly
+    /// of the instruction currently in memory.
+    pub fn report(&self, memory: Option<&[u8]>, limit: usize) -> String {
+        let mut buf = String::new();
+        let percent = |count: u64| count as f64 * 100.0 / self.total.max(1) as f64;
+
+        writeln!(buf, "executed instructions: {}", self.total).unwrap();
+
+        writeln!(buf, "\nhot spots:").unwrap();
+        for (address, count) in self.hot_spots().into_iter().take(limit) {
+            write!(
+                buf,
+                "  0x{address:04X} {count:>12} {:>6.2}%",
+                percent(count)
+            )
+            .unwrap();
+            if let Some(memory) = memory {
+                let instr = address
+                    .checked_sub(MEM_START)
+                    .and_then(|index| Disassembler::decode_at(&memory[MEM_START..], index));
+                if let Some(instr) = instr {
+                    write!(buf, "  {}", instr.repr()).unwrap();
+                }
+            }
+            writeln!(buf).unwrap();
+        }
+
+        writeln!(buf, "\nopcodes:").unwrap();
+        for (kind, count) in self.opcode_kinds() {
+            writeln!(buf, "  {kind:<6} {count:>12} {:>6.2}%", percent(count)).unwrap();
+        }
+
+        buf
+    }
+}
+
+/// Pattern of the instruction, with the operands left as placeholders.
+fn opcode_kind([a, b]: [u8; 2]) -> &'static str {
+    match (a >> 4, a & 0xF, b >> 4, b & 0xF) {
+        (0x0, 0x0, 0xE, 0x0) => "00E0",
+        (0x0, 0x0, 0xE, 0xE) => "00EE",
+        (0x0, ..) => "0nnn",
+        (0x1, ..) => "1nnn",
+        (0x2, ..) => "2nnn",
+        (0x3, ..) => "3xnn",
+        (0x4, ..) => "4xnn",
+        (0x5, ..) => "5xy0",
+        (0x6, ..) => "6xnn",
+        (0x7, ..) => "7xnn",
+        (0x8, _, _, 0x0) => "8xy0",
+        (0x8, _, _, 0x1) => "8xy1",
+        (0x8, _, _, 0x2) => "8xy2",
+        (0x8, _, _, 0x3) => "8xy3",
+        (0x8, _, _, 0x4) => "8xy4",
+        (0x8, _, _, 0x5) => "8xy5",
+        (0x8, _, _, 0x6) => "8xy6",
+        (0x8, _, _, 0x7) => "8xy7",
+        (0x8, _, _, 0xE) => "8xyE",
+        (0x9, ..) => "9xy0",
+        (0xA, ..) => "Annn",
+        (0xB, ..) => "Bnnn",
+        (0xC, ..) => "Cxnn",
+        (0xD, ..) => "Dxyn",
+        (0xE, _, 0x9, 0xE) => "Ex9E",
+        (0xE, _, 0xA, 0x1) => "ExA1",
+        (0xF, 0x0, 0x0, 0x0) => "F000",
+        (0xF, _, 0x0, 0x1) => "Fn01",
+        (0xF, 0x0, 0x0, 0x2) => "F002",
+        (0xF, _, 0x0, 0x7) => "Fx07",
+        (0xF, _, 0x0, 0xA) => "Fx0A",
+        (0xF, _, 0x1, 0x5) => "Fx15",
+        (0xF, _, 0x1, 0x8) => "Fx18",
+        (0xF, _, 0x1, 0xE) => "Fx1E",
+        (0xF, _, 0x2, 0x9) => "Fx29",
+        (0xF, _, 0x3, 0x0) => "Fx30",
+        (0xF, _, 0x3, 0x3) => "Fx33",
+        (0xF, _, 0x5, 0x5) => "Fx55",
+        (0xF, _, 0x6, 0x5) => "Fx65",
+        _ => "????",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_profiler() {
+        let mut profiler = Profiler::new();
+        profiler.record(0x200, [0x60, 0x01]);
+        profiler.record(0x202, [0x70, 0x01]);
+        profiler.record(0x202, [0x70, 0x01]);
+        profiler.record(0x204, [0x12, 0x02]);
+        profiler.record(0x202, [0x70, 0x01]);
+
+        assert_eq!(profiler.total(), 5);
+        assert_eq!(profiler.count_at(0x202), 3);
+        assert_eq!(
+            profiler.hot_spots(),
+            vec![(0x202, 3), (0x200, 1), (0x204, 1)]
+        );
+        assert_eq!(
+            profiler.opcode_kinds(),
+            vec![("7xnn", 3), ("1nnn", 1), ("6xnn", 1)]
+        );
+
+        let mut memory = vec![0; MEM_SIZE];
+        memory[0x202..0x204].copy_from_slice(&[0x70, 0x01]);
+        let report = profiler.report(Some(&memory), 1);
+        assert!(report.contains("0x0202            3  60.00%  ADD v0, 1"));
+        assert!(!report.contains("0x0200"));
+    }
+}
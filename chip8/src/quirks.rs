@@ -0,0 +1,256 @@
+//! Behavioural differences between Chip-8 implementations.
+
+/// Toggles for known behavioural differences between interpreters.
+///
+/// The original COSMAC VIP interpreter, CHIP-48 and SUPER-CHIP disagree
+/// on the semantics of a handful of instructions. Programs are often
+/// written against a specific interpreter, and depend on its quirks.
+///
+/// The defaults match the behaviour of this VM before quirks were
+/// configurable.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// `8xy6 (SHR Vx, Vy)` and `8xyE (SHL Vx, Vy)` shift the value of `Vy`,
+    /// and store the result in `Vx`.
+    /// Default: `false`
+    ///
+    /// When disabled, `Vx` is shifted in place and `Vy` is ignored,
+    /// as done by CHIP-48 and SUPER-CHIP.
+    pub shift_vy: bool,
+    /// `Fx55 (LD [I], Vx)` and `Fx65 (LD Vx, [I])` increment `I`
+    /// by `x + 1`, as done by the COSMAC VIP.
+    /// Default: `false`
+    pub load_store_increment_i: bool,
+    /// `Bnnn (JP V0, addr)` is interpreted as `Bxnn (JP Vx, addr)`,
+    /// using the register in the high nibble of the address as the
+    /// offset, as done by CHIP-48 and SUPER-CHIP.
+    /// Default: `false`
+    pub jump_vx: bool,
+    /// Handling of sprites drawn over the edge of the display.
+    /// Default: [`SpriteEdge::Wrap`]
+    ///
+    /// Toggled by the `clip_sprites` quirk name.
+    pub sprite_edge: SpriteEdge,
+    /// `8xy1 (OR)`, `8xy2 (AND)` and `8xy3 (XOR)` reset `VF` to zero,
+    /// as done by the COSMAC VIP.
+    /// Default: `false`
+    pub vf_reset: bool,
+    /// Mouse clicks on the display are readable by the program at
+    /// [`MOUSE_START`](crate::constants::MOUSE_START), see
+    /// [`Chip8Vm::set_mouse`](crate::Chip8Vm::set_mouse).
+    /// Default: `false`
+    ///
+    /// A custom extension of this VM, which no original interpreter has,
+    /// so no profile enables it.
+    pub mouse: bool,
+}
+
+impl Quirks {
+    /// Behaviour of the original COSMAC VIP interpreter.
+    pub const COSMAC_VIP: Self = Self {
+        shift_vy: true,
+        load_store_increment_i: true,
+        jump_vx: false,
+        sprite_edge: SpriteEdge::Clip,
+        vf_reset: true,
+        mouse: false,
+    };
+
+    /// Behaviour of CHIP-48 on the HP-48 calculators.
+    pub const CHIP48: Self = Self {
+        shift_vy: false,
+        load_store_increment_i: false,
+        jump_vx: true,
+        sprite_edge: SpriteEdge::Clip,
+        vf_reset: false,
+        mouse: false,
+    };
+
+    /// Behaviour of SUPER-CHIP 1.1.
+    pub const SUPER_CHIP: Self = Self {
+        shift_vy: false,
+        load_store_increment_i: false,
+        jump_vx: true,
+        sprite_edge: SpriteEdge::Clip,
+        vf_reset: false,
+        mouse: false,
+    };
+
+    /// Behaviour of XO-CHIP, which returns to the shifts and loads of the COSMAC VIP.
+    pub const XO_CHIP: Self = Self {
+        shift_vy: true,
+        load_store_increment_i: true,
+        jump_vx: false,
+        sprite_edge: SpriteEdge::Wrap,
+        vf_reset: false,
+        mouse: false,
+    };
+
+    /// Names of the known quirk profiles, accepted by [`Quirks::profile`].
+    pub const PROFILES: &'static [&'static str] = &["default", "vip", "chip48", "schip", "xochip"];
+
+    /// Look up a quirks profile by name.
+    pub fn profile(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Self::default()),
+            "vip" => Some(Self::COSMAC_VIP),
+            "chip48" => Some(Self::CHIP48),
+            "schip" => Some(Self::SUPER_CHIP),
+            "xochip" => Some(Self::XO_CHIP),
+            _ => None,
+        }
+    }
+
+    /// Names of the individual quirks, accepted by [`Quirks::set`].
+    pub const NAMES: &'static [&'static str] = &[
+        "shift_vy",
+        "load_store_increment_i",
+        "jump_vx",
+        "clip_sprites",
+        "vf_reset",
+        "mouse",
+    ];
+
+    /// Turn a quirk on or off by its name.
+    ///
+    /// Every name is the name of a field, except `clip_sprites`,
+    /// which toggles [`Quirks::sprite_edge`] between clipping and wrapping.
+    ///
+    /// Returns `false` when the name is unknown.
+    pub fn set(&mut self, name: &str, enabled: bool) -> bool {
+        let quirk = match name {
+            "shift_vy" => &mut self.shift_vy,
+            "load_store_increment_i" => &mut self.load_store_increment_i,
+            "jump_vx" => &mut self.jump_vx,
+            "clip_sprites" => {
+                self.sprite_edge = if enabled {
+                    SpriteEdge::Clip
+                } else {
+                    SpriteEdge::Wrap
+                };
+                return true;
+            }
+            "vf_reset" => &mut self.vf_reset,
+            "mouse" => &mut self.mouse,
+            _ => return false,
+        };
+        *quirk = enabled;
+        true
+    }
+
+    /// Whether a quirk is on, by its name, see [`Quirks::set`].
+    ///
+    /// Returns `None` when the name is unknown.
+    pub fn get(&self, name: &str) -> Option<bool> {
+        match name {
+            "shift_vy" => Some(self.shift_vy),
+            "load_store_increment_i" => Some(self.load_store_increment_i),
+            "jump_vx" => Some(self.jump_vx),
+            "clip_sprites" => Some(self.sprite_edge == SpriteEdge::Clip),
+            "vf_reset" => Some(self.vf_reset),
+            "mouse" => Some(self.mouse),
+            _ => None,
+        }
+    }
+}
+
+/// Handling of the pixels of a sprite that fall past the edge of the display.
+///
+/// The starting coordinate of a sprite always wraps around the display,
+/// so a sprite drawn at `x = 70` starts at column 6 in both modes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SpriteEdge {
+    /// Pixels past the right edge are drawn on the left side, and rows
+    /// past the bottom edge are drawn at the top.
+    #[default]
+    Wrap,
+    /// Pixels past the right and bottom edges are not drawn,
+    /// as done by the COSMAC VIP and SUPER-CHIP.
+    Clip,
+}
+
+/// Quirks are written with the names of [`Quirks::NAMES`], as a table that
+/// may start from a `profile`, or as the name of a profile alone:
+///
+/// ```toml
+/// quirks = "vip"
+///
+/// [quirks]
+/// profile = "vip"
+/// clip_sprites = false
+/// ```
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use std::{collections::BTreeMap, fmt};
+
+    use serde::{
+        de::{self, MapAccess, Visitor},
+        ser::SerializeMap,
+        Deserialize, Deserializer, Serialize, Serializer,
+    };
+
+    use super::Quirks;
+
+    impl Serialize for Quirks {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(Some(Quirks::NAMES.len()))?;
+            for name in Quirks::NAMES {
+                map.serialize_entry(name, &self.get(name).unwrap_or_default())?;
+            }
+            map.end()
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Quirks {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_any(QuirksVisitor)
+        }
+    }
+
+    struct QuirksVisitor;
+
+    impl<'de> Visitor<'de> for QuirksVisitor {
+        type Value = Quirks;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a quirks profile name, or a table of quirks")
+        }
+
+        fn visit_str<E: de::Error>(self, name: &str) -> Result<Quirks, E> {
+            profile(name)
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Quirks, A::Error> {
+            // The profile is applied first, wherever it's written in the table.
+            let mut base = None;
+            let mut toggles = BTreeMap::new();
+            while let Some(name) = map.next_key::<String>()? {
+                if name == "profile" {
+                    base = Some(profile(&map.next_value::<String>()?)?);
+                } else if Quirks::NAMES.contains(&name.as_str()) {
+                    toggles.insert(name, map.next_value::<bool>()?);
+                } else {
+                    return Err(de::Error::custom(format!(
+                        "unknown quirk '{name}', expected one of: profile, {}",
+                        Quirks::NAMES.join(", ")
+                    )));
+                }
+            }
+
+            let mut quirks = base.unwrap_or_default();
+            for (name, enabled) in toggles {
+                quirks.set(&name, enabled);
+            }
+            Ok(quirks)
+        }
+    }
+
+    fn profile<E: de::Error>(name: &str) -> Result<Quirks, E> {
+        Quirks::profile(name).ok_or_else(|| {
+            de::Error::custom(format!(
+                "unknown quirks profile '{name}', expected one of: {}",
+                Quirks::PROFILES.join(", ")
+            ))
+        })
+    }
+}
@@ -0,0 +1,145 @@
+//! Sources of random numbers for `Cxnn (RND Vx, byte)`.
+//!
+//! The `rand` feature, enabled by default, backs the VM with the generator
+//! of the `rand` crate. Without it a small built-in generator is used, and
+//! targets without an operating system can supply their own entropy with
+//! [`Chip8Vm::set_random_source`](crate::Chip8Vm::set_random_source).
+
+/// Generator of the random bytes drawn by `Cxnn (RND Vx, byte)`.
+///
+/// Sources must be deterministic for a given seed, so recorded
+/// sessions can be replayed. Hardware entropy can ignore the seed,
+/// at the cost of replays.
+///
+/// ```
+/// # use chip8::{Chip8Conf, Chip8Vm, RandomSource};
+/// /// Always rolls the same number.
+/// struct Loaded(u8);
+///
+/// impl RandomSource for Loaded {
+///     fn reseed(&mut self, _seed: u64) {}
+///
+///     fn next_byte(&mut self) -> u8 {
+///         self.0
+///     }
+/// }
+///
+/// let mut vm = Chip8Vm::new(Chip8Conf::default());
+/// vm.set_random_source(Loaded(0x5A));
+/// vm.load_bytecode(&[0xC0, 0x0F]).unwrap(); // RND v0, 0x0F
+/// vm.run_steps(1).unwrap();
+/// assert_eq!(vm.debugger().registers()[0], 0x0A);
+/// ```
+pub trait RandomSource {
+    /// Restart the sequence of bytes from the seed.
+    fn reseed(&mut self, seed: u64);
+
+    fn next_byte(&mut self) -> u8;
+}
+
+/// Source used by the VM unless another one is set.
+#[cfg(feature = "rand")]
+pub type DefaultRandom = StdRandom;
+
+/// Source used by the VM unless another one is set.
+#[cfg(not(feature = "rand"))]
+pub type DefaultRandom = XorShiftRandom;
+
+/// Standard generator of the `rand` crate.
+#[cfg(feature = "rand")]
+#[derive(Debug, Clone)]
+pub struct StdRandom(rand::rngs::StdRng);
+
+#[cfg(feature = "rand")]
+impl StdRandom {
+    pub fn new(seed: u64) -> Self {
+        use rand::SeedableRng;
+        Self(rand::rngs::StdRng::seed_from_u64(seed))
+    }
+}
+
+#[cfg(feature = "rand")]
+impl RandomSource for StdRandom {
+    fn reseed(&mut self, seed: u64) {
+        *self = Self::new(seed);
+    }
+
+    fn next_byte(&mut self) -> u8 {
+        use rand::Rng;
+        self.0.gen()
+    }
+}
+
+/// Small `xorshift64*` generator without dependencies.
+///
+/// Good enough for games, and the same on every platform.
+#[derive(Debug, Clone)]
+pub struct XorShiftRandom {
+    state: u64,
+}
+
+impl XorShiftRandom {
+    pub fn new(seed: u64) -> Self {
+        // Scramble the seed with SplitMix64, because the
+        // state of xorshift must never be zero.
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        Self { state: z.max(1) }
+    }
+}
+
+impl RandomSource for XorShiftRandom {
+    fn reseed(&mut self, seed: u64) {
+        *self = Self::new(seed);
+    }
+
+    fn next_byte(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        // The high bits are the most random.
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+}
+
+/// Seed for VMs configured without one.
+#[cfg(feature = "rand")]
+pub(crate) fn random_seed() -> u64 {
+    rand::random()
+}
+
+/// Seed for VMs configured without one, from the randomly
+/// keyed hasher of the standard library.
+#[cfg(not(feature = "rand"))]
+pub(crate) fn random_seed() -> u64 {
+    use std::hash::{BuildHasher, Hasher};
+    std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_random_sources() {
+        let sources: [Box<dyn RandomSource>; 2] = [
+            Box::new(DefaultRandom::new(7)),
+            Box::new(XorShiftRandom::new(7)),
+        ];
+        for mut source in sources {
+            let first: Vec<u8> = (0..16).map(|_| source.next_byte()).collect();
+            source.reseed(7);
+            let again: Vec<u8> = (0..16).map(|_| source.next_byte()).collect();
+            assert_eq!(first, again);
+            assert!(first.iter().any(|byte| *byte != first[0]));
+        }
+
+        // Zero is a valid seed.
+        let mut zero = XorShiftRandom::new(0);
+        assert!((0..16).map(|_| zero.next_byte()).any(|byte| byte != 0));
+    }
+}
@@ -0,0 +1,203 @@
+//! Text protocol for playing over the network.
+//!
+//! Every message is a single line. Clients send key events and
+//! pause the program, and the server streams the display and sound.
+//!
+//! ```text
+//! key 5 down            client to server
+//! key 5 up
+//! pause
+//! resume
+//! frame 42 8000000000000000 0000000000000000 ...   server to client, 32 rows
+//! sound on
+//! sound off
+//! stopped call stack overflow at 0x0204
+//! ```
+//!
+//! Rows of a frame are packed as hexadecimal, with the leftmost
+//! pixel in the most significant bit. The protocol is simple enough
+//! to drive the emulator from other languages with a plain socket.
+use std::fmt;
+
+use crate::{
+    async_vm::{DisplayFrame, VmCommand, VmEvent},
+    constants::DISPLAY_HEIGHT,
+    devices::{KeyCode, KeyState},
+    error::{Chip8Error, Chip8Result},
+};
+
+/// Port served by `chip8 serve`, unless configured otherwise.
+pub const DEFAULT_PORT: u16 = 7878;
+
+/// Line of the network protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteMessage {
+    /// Press or release a key of the keypad.
+    Key(KeyCode, KeyState),
+    Pause,
+    Resume,
+    /// The display was drawn to. Boxed, because frames are much larger than other messages.
+    Frame(Box<DisplayFrame>),
+    /// The buzzer turned on or off.
+    Sound(bool),
+    /// The program stopped, with the error that stopped it, if any.
+    Stopped(Option<String>),
+}
+
+impl RemoteMessage {
+    /// Parse a line of the protocol, without the line ending.
+    pub fn parse(line: &str) -> Chip8Result<Self> {
+        let mut words = line.split_whitespace();
+        let message = match words.next() {
+            Some("key") => {
+                let key = words
+                    .next()
+                    .and_then(|key| u8::from_str_radix(key, 16).ok())
+                    .and_then(|key| KeyCode::try_from(key).ok())
+                    .ok_or_else(|| remote_error("expected a key from 0 to F"))?;
+                let state = match words.next() {
+                    Some("down") => KeyState::Pressed,
+                    Some("up") => KeyState::Released,
+                    _ => return Err(remote_error("expected key state 'down' or 'up'")),
+                };
+                Self::Key(key, state)
+            }
+            Some("pause") => Self::Pause,
+            Some("resume") => Self::Resume,
+            Some("frame") => {
+                let number = words
+                    .next()
+                    .and_then(|number| number.parse().ok())
+                    .ok_or_else(|| remote_error("expected a frame number"))?;
+                let mut rows = [0; DISPLAY_HEIGHT];
+                for row in rows.iter_mut() {
+                    *row = words
+                        .next()
+                        .and_then(|row| u64::from_str_radix(row, 16).ok())
+                        .ok_or_else(|| remote_error("expected a row of pixels"))?;
+                }
+                Self::Frame(Box::new(DisplayFrame { number, rows }))
+            }
+            Some("sound") => match words.next() {
+                Some("on") => Self::Sound(true),
+                Some("off") => Self::Sound(false),
+                _ => return Err(remote_error("expected sound 'on' or 'off'")),
+            },
+            Some("stopped") => {
+                // The rest of the line is the error message.
+                let message = line
+                    .trim()
+                    .strip_prefix("stopped")
+                    .unwrap_or_default()
+                    .trim();
+                return Ok(Self::Stopped(
+                    (!message.is_empty()).then(|| message.to_string()),
+                ));
+            }
+            Some(other) => return Err(Chip8Error::Remote(format!("unknown message '{other}'"))),
+            None => return Err(remote_error("empty message")),
+        };
+
+        if words.next().is_some() {
+            return Err(remote_error("unexpected trailing words"));
+        }
+
+        Ok(message)
+    }
+
+    /// Command for the VM, when the message is sent by a client.
+    pub fn to_command(&self) -> Option<VmCommand> {
+        match *self {
+            Self::Key(key, state) => Some(VmCommand::Key(key, state)),
+            Self::Pause => Some(VmCommand::Pause),
+            Self::Resume => Some(VmCommand::Resume),
+            _ => None,
+        }
+    }
+}
+
+impl From<VmEvent> for RemoteMessage {
+    fn from(event: VmEvent) -> Self {
+        match event {
+            VmEvent::Frame(frame) => Self::Frame(Box::new(frame)),
+            VmEvent::Sound(on) => Self::Sound(on),
+            VmEvent::Stopped(err) => Self::Stopped(err.map(|err| err.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for RemoteMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Key(key, state) => {
+                let state = match state {
+                    KeyState::Pressed => "down",
+                    KeyState::Released => "up",
+                };
+                write!(f, "key {:X} {state}", key.as_u8())
+            }
+            Self::Pause => write!(f, "pause"),
+            Self::Resume => write!(f, "resume"),
+            Self::Frame(frame) => {
+                write!(f, "frame {}", frame.number)?;
+                for row in &frame.rows {
+                    write!(f, " {row:016X}")?;
+                }
+                Ok(())
+            }
+            Self::Sound(on) => write!(f, "sound {}", if *on { "on" } else { "off" }),
+            // Messages are single lines.
+            Self::Stopped(Some(message)) => write!(f, "stopped {}", message.replace('\n', " ")),
+            Self::Stopped(None) => write!(f, "stopped"),
+        }
+    }
+}
+
+#[inline(never)]
+#[cold]
+fn remote_error(message: &str) -> Chip8Error {
+    Chip8Error::Remote(message.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_remote_message_roundtrip() {
+        let mut rows = [0; DISPLAY_HEIGHT];
+        rows[0] = 1 << 63;
+        rows[31] = 0xFF;
+        let messages = [
+            RemoteMessage::Key(KeyCode::KeyA, KeyState::Pressed),
+            RemoteMessage::Key(KeyCode::Key0, KeyState::Released),
+            RemoteMessage::Pause,
+            RemoteMessage::Resume,
+            RemoteMessage::Frame(Box::new(DisplayFrame { number: 42, rows })),
+            RemoteMessage::Sound(true),
+            RemoteMessage::Stopped(Some("program counter out of bounds".to_string())),
+            RemoteMessage::Stopped(None),
+        ];
+        for message in messages {
+            assert_eq!(RemoteMessage::parse(&message.to_string()).unwrap(), message);
+        }
+
+        assert_eq!(
+            RemoteMessage::parse("key f down").unwrap(),
+            RemoteMessage::Key(KeyCode::KeyF, KeyState::Pressed)
+        );
+        for line in [
+            "",
+            "key 10 down",
+            "key 5",
+            "pause now",
+            "frame 1 00",
+            "jump",
+        ] {
+            assert!(
+                matches!(RemoteMessage::parse(line), Err(Chip8Error::Remote(_))),
+                "{line:?}"
+            );
+        }
+    }
+}
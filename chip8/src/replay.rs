@@ -0,0 +1,226 @@
+//! Input recording and playback.
+//!
+//! A replay holds the seed of the random number generator, and the key
+//! transitions of a session timestamped with the frame they happened before.
+//! Because [`Chip8Vm::run_frame`](crate::Chip8Vm::run_frame) counts down the
+//! timers per frame instead of in real time, playing the replay back on the
+//! same program reproduces the session exactly.
+//!
+//! Replays are stored as plain text, one event per line:
+//!
+//! ```text
+//! chip8-replay 1
+//! seed 12345
+//! frames 600
+//! 42 5 down
+//! 48 5 up
+//! ```
+use std::{fmt, path::Path, str::FromStr};
+
+use crate::{
+    devices::{KeyCode, KeyState},
+    error::{Chip8Error, Chip8Result},
+};
+
+/// Header identifying a replay file.
+const MAGIC: &str = "chip8-replay";
+
+/// Version of the replay text format.
+///
+/// Must be incremented when the format changes.
+pub const REPLAY_VERSION: u16 = 1;
+
+/// Recorded input of a session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replay {
+    /// Seed of the random number generator at the start of the session.
+    pub seed: u64,
+    /// Number of frames executed during the session.
+    pub frames: u64,
+    /// Key transitions, ordered by frame.
+    pub events: Vec<ReplayEvent>,
+}
+
+/// Key transition pushed before the given frame was executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayEvent {
+    /// Number of frames executed before the event.
+    pub frame: u64,
+    pub key: KeyCode,
+    pub state: KeyState,
+}
+
+impl Replay {
+    /// Create an empty replay.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            frames: 0,
+            events: Vec::new(),
+        }
+    }
+
+    /// Key transitions that must be pushed before the given frame.
+    pub fn events_at(&self, frame: u64) -> impl Iterator<Item = &ReplayEvent> {
+        let start = self.events.partition_point(|event| event.frame < frame);
+        self.events[start..]
+            .iter()
+            .take_while(move |event| event.frame == frame)
+    }
+
+    /// Read a replay from a text file.
+    pub fn load(filepath: impl AsRef<Path>) -> Chip8Result<Self> {
+        std::fs::read_to_string(filepath)?.parse()
+    }
+
+    /// Write the replay to a text file.
+    pub fn save(&self, filepath: impl AsRef<Path>) -> Chip8Result<()> {
+        std::fs::write(filepath, self.to_string())?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{MAGIC} {REPLAY_VERSION}")?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "frames {}", self.frames)?;
+
+        for event in &self.events {
+            let state = match event.state {
+                KeyState::Pressed => "down",
+                KeyState::Released => "up",
+            };
+            writeln!(f, "{} {:X} {state}", event.frame, event.key.as_u8())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Replay {
+    type Err = Chip8Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = |line: usize, msg: &str| Chip8Error::Replay(format!("line {line}: {msg}"));
+
+        let mut lines = s
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty());
+
+        // Header fields, in a fixed order.
+        let mut header = |name: &str| -> Chip8Result<(usize, String)> {
+            let (number, line) = lines
+                .next()
+                .ok_or_else(|| Chip8Error::Replay(format!("missing '{name}'")))?;
+            match line.split_once(' ') {
+                Some((key, value)) if key == name => Ok((number, value.trim().to_string())),
+                _ => Err(error(number, &format!("expected '{name}'"))),
+            }
+        };
+
+        let (number, version) = header(MAGIC)?;
+        if version != REPLAY_VERSION.to_string() {
+            return Err(error(number, &format!("unsupported version {version}")));
+        }
+        let (number, seed) = header("seed")?;
+        let seed = seed.parse().map_err(|_| error(number, "invalid seed"))?;
+        let (number, frames) = header("frames")?;
+        let frames = frames
+            .parse()
+            .map_err(|_| error(number, "invalid frame count"))?;
+
+        let mut replay = Replay::new(seed);
+        replay.frames = frames;
+
+        for (number, line) in lines {
+            let mut parts = line.split_whitespace();
+
+            let frame: u64 = parts
+                .next()
+                .and_then(|part| part.parse().ok())
+                .ok_or_else(|| error(number, "invalid frame"))?;
+            let key = parts
+                .next()
+                .and_then(|part| u8::from_str_radix(part, 16).ok())
+                .and_then(|key| KeyCode::try_from(key).ok())
+                .ok_or_else(|| error(number, "invalid key"))?;
+            let state = match parts.next() {
+                Some("down") => KeyState::Pressed,
+                Some("up") => KeyState::Released,
+                _ => return Err(error(number, "expected 'down' or 'up'")),
+            };
+            if parts.next().is_some() {
+                return Err(error(number, "unexpected trailing input"));
+            }
+
+            if frame > frames || replay.events.last().is_some_and(|last| last.frame > frame) {
+                return Err(error(number, "event out of order"));
+            }
+
+            replay.events.push(ReplayEvent { frame, key, state });
+        }
+
+        Ok(replay)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_replay_text_roundtrip() {
+        let mut replay = Replay::new(42);
+        replay.frames = 10;
+        replay.events = vec![
+            ReplayEvent {
+                frame: 2,
+                key: KeyCode::KeyA,
+                state: KeyState::Pressed,
+            },
+            ReplayEvent {
+                frame: 2,
+                key: KeyCode::Key5,
+                state: KeyState::Pressed,
+            },
+            ReplayEvent {
+                frame: 7,
+                key: KeyCode::KeyA,
+                state: KeyState::Released,
+            },
+        ];
+
+        let text = replay.to_string();
+        assert_eq!(
+            text,
+            "chip8-replay 1\nseed 42\nframes 10\n2 A down\n2 5 down\n7 A up\n"
+        );
+        assert_eq!(text.parse::<Replay>().unwrap(), replay);
+
+        assert_eq!(replay.events_at(2).count(), 2);
+        assert_eq!(replay.events_at(3).count(), 0);
+        assert_eq!(replay.events_at(7).next(), Some(&replay.events[2]));
+    }
+
+    #[test]
+    fn test_replay_parse_errors() {
+        let parse = |s: &str| s.parse::<Replay>().unwrap_err().to_string();
+
+        assert_eq!(parse(""), "invalid replay: missing 'chip8-replay'");
+        assert_eq!(
+            parse("chip8-replay 2\n"),
+            "invalid replay: line 1: unsupported version 2"
+        );
+        assert_eq!(
+            parse("chip8-replay 1\nseed 1\nframes 5\n3 G down\n"),
+            "invalid replay: line 4: invalid key"
+        );
+        assert_eq!(
+            parse("chip8-replay 1\nseed 1\nframes 5\n3 1 down\n2 1 up\n"),
+            "invalid replay: line 5: event out of order"
+        );
+    }
+}
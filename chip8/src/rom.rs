@@ -0,0 +1,300 @@
+//! Loading of ROM files.
+//!
+//! Besides plain bytecode, like `.ch8` and `.c8` files, ROMs are found
+//! wrapped in a few containers. These are detected by their contents,
+//! and unwrapped:
+//!
+//! - ROM archives, see [`RomArchive`].
+//! - Intel HEX records, as written by `chip8 asm --format hex`.
+//! - Memory images, starting with the interpreter area of memory.
+//!
+//! Files with the `.asm` extension are assembled.
+use std::{
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    archive::RomArchive,
+    bytecode::check_program_size,
+    constants::{FONTSET_DATA_LENGTH, FONTSET_START, MEM_SIZE, MEM_START},
+    error::{Chip8Error, Chip8Result},
+    font::FontSet,
+    rom_db::sha1_hex,
+};
+
+/// Bytecode of a program, unwrapped from its container and checked
+/// to fit in memory.
+///
+/// Dereferences to the bytecode, so it can be passed to
+/// [`Chip8Vm::load_bytecode`](crate::Chip8Vm::load_bytecode).
+///
+/// ```
+/// # use chip8::{rom::Rom, Chip8Conf, Chip8Vm};
+/// let rom = Rom::from_bytes(b":0202000000E01C\n:00000001FF\n").unwrap();
+/// assert_eq!(rom.bytes, [0x00, 0xE0]);
+///
+/// let mut vm = Chip8Vm::new(Chip8Conf::default());
+/// vm.load_bytecode(&rom).unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rom {
+    pub bytes: Vec<u8>,
+    /// File the ROM was loaded from, if any.
+    pub source_path: Option<PathBuf>,
+    /// SHA-1 hash of the bytecode, in lowercase hex, as used by the [`rom_db`](crate::rom_db).
+    pub hash: String,
+}
+
+impl Rom {
+    /// Check the bytecode of a program loaded at the given address.
+    ///
+    /// Programs with an odd length are accepted with a warning, since many
+    /// published ROMs end with sprite data that doesn't fill a whole instruction.
+    pub fn new(bytes: Vec<u8>, load_address: usize) -> Chip8Result<Self> {
+        if bytes.is_empty() {
+            return Err(rom_error("program is empty"));
+        }
+        if !check_program_size(&bytes, load_address) {
+            return Err(Chip8Error::LargeProgram);
+        }
+        if !bytes.len().is_multiple_of(2) {
+            tracing::warn!(
+                "program has an odd length of {} bytes, its last instruction is incomplete",
+                bytes.len()
+            );
+        }
+
+        Ok(Self {
+            hash: sha1_hex(&bytes),
+            bytes,
+            source_path: None,
+        })
+    }
+
+    /// Unwrap the program from its container, loaded at [`MEM_START`].
+    pub fn from_bytes(bytes: &[u8]) -> Chip8Result<Self> {
+        Self::from_bytes_at(bytes, MEM_START)
+    }
+
+    /// Unwrap the program from its container, loaded at the given address.
+    pub fn from_bytes_at(bytes: &[u8], load_address: usize) -> Chip8Result<Self> {
+        if RomArchive::is_archive(bytes) {
+            return Self::new(RomArchive::from_bytes(bytes)?.rom, load_address);
+        }
+        Self::new(strip_header(bytes, load_address)?, load_address)
+    }
+}
+
+impl Deref for Rom {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.bytes
+    }
+}
+
+/// Load a ROM file, for a program loaded at [`MEM_START`].
+pub fn load(path: impl AsRef<Path>) -> Chip8Result<Rom> {
+    load_at(path, MEM_START)
+}
+
+/// Load a ROM file, for a program loaded at the given address.
+///
+/// Source files are assembled for the address.
+pub fn load_at(path: impl AsRef<Path>, load_address: usize) -> Chip8Result<Rom> {
+    let path = path.as_ref();
+    let archive = RomArchive::read_file_at(path, load_address)?;
+    let mut rom = Rom::new(archive.rom, load_address)?;
+    rom.source_path = Some(path.to_path_buf());
+    Ok(rom)
+}
+
+/// Unwrap the bytecode of a file that isn't an archive, or return it as is.
+pub(crate) fn strip_header(bytes: &[u8], load_address: usize) -> Chip8Result<Vec<u8>> {
+    if is_intel_hex(bytes) {
+        tracing::info!("decoding rom from Intel HEX");
+        return decode_intel_hex(bytes, load_address);
+    }
+    if is_memory_image(bytes, load_address) {
+        tracing::info!("stripping interpreter area from memory image");
+        return Ok(bytes[load_address..].to_vec());
+    }
+    Ok(bytes.to_vec())
+}
+
+/// Intel HEX files only contain records of hex digits, each starting with a colon.
+///
+/// Bytecode can start with a colon too, `3A` is `SE VA, NN`, but
+/// won't consist of only printable characters.
+fn is_intel_hex(bytes: &[u8]) -> bool {
+    bytes.first() == Some(&b':')
+        && bytes
+            .iter()
+            .all(|byte| byte.is_ascii_hexdigit() || matches!(byte, b':' | b'\r' | b'\n'))
+}
+
+/// Memory images are too large to be a program, and have a blank or
+/// font filled interpreter area.
+fn is_memory_image(bytes: &[u8], load_address: usize) -> bool {
+    if check_program_size(bytes, load_address) || bytes.len() > MEM_SIZE {
+        return false;
+    }
+
+    let interpreter = &bytes[..load_address];
+    let font_start = FONTSET_START as usize;
+    let font = interpreter.get(font_start..font_start + FONTSET_DATA_LENGTH);
+    interpreter.iter().all(|byte| *byte == 0)
+        || FontSet::BUILTIN
+            .iter()
+            .filter_map(|name| FontSet::builtin(name))
+            .any(|set| Some(set.small()) == font)
+}
+
+/// Decode the records into the memory from the load address onwards.
+///
+/// Data in the interpreter area, below the load address, is dropped.
+fn decode_intel_hex(bytes: &[u8], load_address: usize) -> Chip8Result<Vec<u8>> {
+    let text = std::str::from_utf8(bytes).expect("hex records are ascii");
+    let mut memory = vec![];
+    let mut base = 0;
+
+    for (index, line) in text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+    {
+        let record = parse_hex_record(line).ok_or_else(|| {
+            rom_error(format!("malformed Intel HEX record on line {}", index + 1))
+        })?;
+        let [len, hi, lo, kind] = record[..4] else {
+            unreachable!("record has a header");
+        };
+        let data = &record[4..4 + len as usize];
+
+        match kind {
+            // Data
+            0x00 => {
+                let address = base + u16::from_be_bytes([hi, lo]) as usize;
+                let end = address + data.len();
+                if end > MEM_SIZE {
+                    return Err(Chip8Error::LargeProgram);
+                }
+                if end > memory.len() {
+                    memory.resize(end, 0);
+                }
+                memory[address..end].copy_from_slice(data);
+            }
+            // End of file
+            0x01 => break,
+            // Extended segment address
+            0x02 if len == 2 => base = (u16::from_be_bytes([data[0], data[1]]) as usize) << 4,
+            // Extended linear address
+            0x04 if len == 2 => base = (u16::from_be_bytes([data[0], data[1]]) as usize) << 16,
+            // Start addresses don't apply to Chip-8 programs.
+            0x03 | 0x05 => {}
+            _ => {
+                return Err(rom_error(format!(
+                    "unsupported Intel HEX record type {kind:02X} on line {}",
+                    index + 1
+                )))
+            }
+        }
+    }
+
+    Ok(memory.get(load_address..).unwrap_or_default().to_vec())
+}
+
+/// Bytes of the record, after checking its length and checksum.
+fn parse_hex_record(line: &str) -> Option<Vec<u8>> {
+    let digits = line.strip_prefix(':')?;
+    if digits.len() % 2 != 0 {
+        return None;
+    }
+    let record = (0..digits.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&digits[index..index + 2], 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+
+    let valid = record.len() >= 5
+        && record.len() == 5 + record[0] as usize
+        && record
+            .iter()
+            .fold(0_u8, |sum, byte| sum.wrapping_add(*byte))
+            == 0;
+    valid.then_some(record)
+}
+
+#[inline(never)]
+#[cold]
+fn rom_error(message: impl ToString) -> Chip8Error {
+    Chip8Error::Rom(message.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::asm::OutputFormat;
+
+    #[test]
+    fn test_rom_raw() {
+        let rom = Rom::from_bytes(&[0x12, 0x00]).unwrap();
+        assert_eq!(rom.bytes, [0x12, 0x00]);
+        assert_eq!(rom.hash, sha1_hex(&[0x12, 0x00]));
+        assert_eq!(rom.source_path, None);
+
+        // Odd lengths are only warned about.
+        assert!(Rom::from_bytes(&[0x12, 0x00, 0xFF]).is_ok());
+
+        assert!(matches!(Rom::from_bytes(&[]), Err(Chip8Error::Rom(_))));
+        assert!(matches!(
+            Rom::from_bytes(&[0x12; MEM_SIZE - MEM_START + 2]),
+            Err(Chip8Error::LargeProgram)
+        ));
+    }
+
+    #[test]
+    fn test_rom_intel_hex() {
+        let bytecode: Vec<u8> = (0..40).collect();
+        for load_address in [MEM_START, 0x600] {
+            let mut hex = vec![];
+            OutputFormat::Hex
+                .write(&bytecode, load_address, "test", &mut hex)
+                .unwrap();
+            let rom = Rom::from_bytes_at(&hex, load_address).unwrap();
+            assert_eq!(rom.bytes, bytecode);
+        }
+
+        // Records are placed at their address, relative to the load address.
+        let rom = Rom::from_bytes(b":0102040012E7\r\n:00000001FF\r\n").unwrap();
+        assert_eq!(rom.bytes, [0x00, 0x00, 0x00, 0x00, 0x12]);
+
+        for hex in [&b":0102040012E8\n"[..], b":0102040012\n", b":01020400\n"] {
+            assert!(
+                matches!(Rom::from_bytes(hex), Err(Chip8Error::Rom(_))),
+                "{hex:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_rom_memory_image() {
+        let mut memory = vec![0; MEM_SIZE];
+        memory[MEM_START..MEM_START + 2].copy_from_slice(&[0x12, 0x00]);
+        let rom = Rom::from_bytes(&memory).unwrap();
+        assert_eq!(rom.bytes.len(), MEM_SIZE - MEM_START);
+        assert_eq!(rom.bytes[..2], [0x12, 0x00]);
+
+        // With the font loaded into the interpreter area.
+        let font = FontSet::default();
+        memory[..FONTSET_DATA_LENGTH].copy_from_slice(font.small());
+        assert_eq!(Rom::from_bytes(&memory).unwrap(), rom);
+
+        // Anything else is too large to be a program.
+        memory[0] = 0xFF;
+        assert!(matches!(
+            Rom::from_bytes(&memory),
+            Err(Chip8Error::LargeProgram)
+        ));
+    }
+}
@@ -0,0 +1,79 @@
+//! Database of well-known ROMs.
+//!
+//! Programs are identified by the SHA-1 hash of their bytecode, and
+//! carry the settings they're known to run correctly with.
+//!
+//! Only ROMs that are in the public domain, and whose hash was taken
+//! from the actual file, are listed.
+use crate::{
+    quirks::Quirks,
+    vm::{Chip8Conf, Hz},
+};
+
+/// Metadata and recommended settings of a known ROM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RomEntry {
+    /// SHA-1 hash of the bytecode, as lowercase hexadecimal.
+    pub sha1: &'static str,
+    pub name: &'static str,
+    pub author: &'static str,
+    /// Quirks the program is written for, when it depends on any.
+    pub quirks: Option<Quirks>,
+    /// CPU clock frequency the program is meant to run at, in hertz.
+    pub clock_hz: Option<u64>,
+}
+
+impl RomEntry {
+    /// Apply the recommended settings to the configuration.
+    pub fn apply(&self, conf: &mut Chip8Conf) {
+        tracing::info!("recognised rom: {} by {}", self.name, self.author);
+        if let Some(quirks) = self.quirks {
+            conf.quirks = quirks;
+        }
+        if let Some(clock_hz) = self.clock_hz {
+            conf.clock_frequency = Some(Hz(clock_hz));
+        }
+    }
+}
+
+/// Known ROMs.
+pub const ENTRIES: &[RomEntry] = &[
+    // chip8/programs/maze
+    RomEntry {
+        sha1: "8b70080adbac44513ec60005734a816372b845ec",
+        name: "Maze",
+        author: "David Winter",
+        quirks: None,
+        clock_hz: None,
+    },
+];
+
+/// Look up the entry of a program by its bytecode.
+pub fn lookup(bytecode: &[u8]) -> Option<&'static RomEntry> {
+    let hash = sha1_hex(bytecode);
+    ENTRIES.iter().find(|entry| entry.sha1 == hash)
+}
+
+/// SHA-1 hash of the bytecode, as lowercase hexadecimal.
+pub fn sha1_hex(bytecode: &[u8]) -> String {
+    sha1_smol::Sha1::from(bytecode).digest().to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rom_db_lookup() {
+        let entry = lookup(include_bytes!("../programs/maze")).unwrap();
+        assert_eq!(entry.name, "Maze");
+
+        assert!(lookup(&[0x12, 0x00]).is_none());
+
+        // Hashes must be comparable with the output of `sha1_hex`.
+        for entry in ENTRIES {
+            assert_eq!(entry.sha1.len(), 40, "{}", entry.name);
+            assert_eq!(entry.sha1, entry.sha1.to_lowercase(), "{}", entry.name);
+        }
+    }
+}
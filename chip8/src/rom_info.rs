@@ -0,0 +1,423 @@
+//! Per-ROM settings.
+//!
+//! Sidecar descriptor file next to a ROM, telling the VM how the program
+//! expects to be run. For `game.ch8`, the descriptor is `game.ch8.toml`,
+//! written in a small subset of TOML:
+//!
+//! ```toml
+//! title = "Breakout"
+//! profile = "vip"     # quirks profile
+//! clock_hz = 700
+//!
+//! [quirks]            # overrides of the profile
+//! clip_sprites = false
+//!
+//! [keys]              # what the program uses the keys for
+//! 4 = "left"
+//! 6 = "right"
+//! ```
+//!
+//! Only strings, integers and booleans are supported, one per line.
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    devices::KeyCode,
+    error::{Chip8Error, Chip8Result},
+    quirks::Quirks,
+};
+
+/// File extension appended to the ROM file name for its descriptor.
+pub const ROM_INFO_EXT: &str = "toml";
+
+/// Settings of a ROM, overriding the defaults of the VM.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct RomInfo {
+    /// Name of the program.
+    pub title: Option<String>,
+    /// Quirks profile the program is written for, see [`Quirks::PROFILES`].
+    pub profile: Option<String>,
+    /// Individual quirks by name, applied on top of the profile.
+    pub quirks: BTreeMap<String, bool>,
+    /// CPU clock frequency the program is meant to run at, in hertz.
+    pub clock_hz: Option<u64>,
+    /// Descriptions of what the program uses keys for, by key code.
+    pub keys: BTreeMap<u8, String>,
+}
+
+impl RomInfo {
+    /// Path of the descriptor of the given ROM file.
+    pub fn sidecar_path(rom_path: impl AsRef<Path>) -> PathBuf {
+        let mut path = OsString::from(rom_path.as_ref());
+        path.push(".");
+        path.push(ROM_INFO_EXT);
+        PathBuf::from(path)
+    }
+
+    /// Load the descriptor of the given ROM file, if it exists.
+    pub fn discover(rom_path: impl AsRef<Path>) -> Chip8Result<Option<Self>> {
+        let path = Self::sidecar_path(rom_path);
+        if path.exists() {
+            Self::load(path).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Quirks of the program, when the descriptor specifies any.
+    ///
+    /// Individual quirks without a profile are applied on top of the defaults.
+    pub fn quirks(&self) -> Chip8Result<Option<Quirks>> {
+        if self.profile.is_none() && self.quirks.is_empty() {
+            return Ok(None);
+        }
+
+        let mut quirks = match self.profile.as_deref() {
+            Some(name) => Quirks::profile(name).ok_or_else(|| {
+                rom_info_error(format!(
+                    "unknown profile '{name}', expected one of: {}",
+                    Quirks::PROFILES.join(", ")
+                ))
+            })?,
+            None => Quirks::default(),
+        };
+        for (name, enabled) in &self.quirks {
+            if !quirks.set(name, *enabled) {
+                return Err(rom_info_error(format!("unknown quirk '{name}'")));
+            }
+        }
+
+        Ok(Some(quirks))
+    }
+
+    /// Description of what the program uses the key for.
+    pub fn key_hint(&self, key: KeyCode) -> Option<&str> {
+        self.keys.get(&key.as_u8()).map(String::as_str)
+    }
+
+    /// Check that the quirks and keys are known.
+    ///
+    /// Parsing validates the descriptor, this is for descriptors
+    /// created by other means, like deserializing.
+    pub fn validate(&self) -> Chip8Result<()> {
+        self.quirks()?;
+        match self
+            .keys
+            .keys()
+            .find(|key| KeyCode::try_from(**key).is_err())
+        {
+            Some(key) => Err(rom_info_error(format!("invalid key {key}"))),
+            None => Ok(()),
+        }
+    }
+
+    /// Parse a descriptor from its TOML text format.
+    pub fn parse(text: &str) -> Chip8Result<Self> {
+        let mut info = RomInfo::default();
+        let mut table = Table::Root;
+
+        for (index, line) in text.lines().enumerate() {
+            let line_no = index + 1;
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+                table = match name.trim() {
+                    "quirks" => Table::Quirks,
+                    "keys" => Table::Keys,
+                    name => {
+                        return Err(rom_info_error(format!(
+                            "unknown table '{name}' on line {line_no}"
+                        )))
+                    }
+                };
+                continue;
+            }
+
+            let (key, value) = line.split_once('=').ok_or_else(|| {
+                rom_info_error(format!("expected 'key = value' on line {line_no}"))
+            })?;
+            let key = key.trim();
+            let key = key
+                .strip_prefix('"')
+                .and_then(|s| s.strip_suffix('"'))
+                .unwrap_or(key);
+            let value = parse_value(value.trim())
+                .ok_or_else(|| rom_info_error(format!("invalid value on line {line_no}")))?;
+            let mismatch = || {
+                rom_info_error(format!(
+                    "expected {} for '{key}' on line {line_no}",
+                    table.expected(key)
+                ))
+            };
+
+            let duplicate = match (table, key, value) {
+                (Table::Root, "title", Value::String(s)) => info.title.replace(s).is_some(),
+                (Table::Root, "profile", Value::String(s)) => info.profile.replace(s).is_some(),
+                (Table::Root, "clock_hz", Value::Integer(n)) => info.clock_hz.replace(n).is_some(),
+                (Table::Root, "title" | "profile" | "clock_hz", _) => return Err(mismatch()),
+                (Table::Quirks, _, Value::Boolean(b)) => {
+                    if !Quirks::NAMES.contains(&key) {
+                        return Err(rom_info_error(format!(
+                            "unknown quirk '{key}' on line {line_no}"
+                        )));
+                    }
+                    info.quirks.insert(key.to_owned(), b).is_some()
+                }
+                (Table::Keys, _, Value::String(s)) => {
+                    let code = parse_key(key).ok_or_else(|| {
+                        rom_info_error(format!("invalid key '{key}' on line {line_no}"))
+                    })?;
+                    info.keys.insert(code, s).is_some()
+                }
+                (Table::Quirks | Table::Keys, _, _) => return Err(mismatch()),
+                (Table::Root, _, _) => {
+                    return Err(rom_info_error(format!(
+                        "unknown key '{key}' on line {line_no}"
+                    )))
+                }
+            };
+            if duplicate {
+                return Err(rom_info_error(format!(
+                    "duplicate key '{key}' on line {line_no}"
+                )));
+            }
+        }
+
+        info.quirks()?;
+        Ok(info)
+    }
+
+    /// Load a descriptor from a file.
+    pub fn load(path: impl AsRef<Path>) -> Chip8Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text)
+    }
+
+    /// Write the descriptor to a file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Chip8Result<()> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for RomInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(title) = &self.title {
+            writeln!(f, "title = {}", quote(title))?;
+        }
+        if let Some(profile) = &self.profile {
+            writeln!(f, "profile = {}", quote(profile))?;
+        }
+        if let Some(clock_hz) = self.clock_hz {
+            writeln!(f, "clock_hz = {clock_hz}")?;
+        }
+        if !self.quirks.is_empty() {
+            writeln!(f, "\n[quirks]")?;
+            for (name, enabled) in &self.quirks {
+                writeln!(f, "{name} = {enabled}")?;
+            }
+        }
+        if !self.keys.is_empty() {
+            writeln!(f, "\n[keys]")?;
+            for (key, hint) in &self.keys {
+                writeln!(f, "{key:X} = {}", quote(hint))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Table of the descriptor that key value pairs belong to.
+#[derive(Debug, Clone, Copy)]
+enum Table {
+    Root,
+    Quirks,
+    Keys,
+}
+
+impl Table {
+    /// Expected type of the value of the key.
+    fn expected(self, key: &str) -> &'static str {
+        match (self, key) {
+            (Self::Root, "clock_hz") => "an integer",
+            (Self::Root, _) | (Self::Keys, _) => "a string",
+            (Self::Quirks, _) => "a boolean",
+        }
+    }
+}
+
+enum Value {
+    String(String),
+    Integer(u64),
+    Boolean(bool),
+}
+
+fn parse_value(text: &str) -> Option<Value> {
+    if let Some(quoted) = text.strip_prefix('"') {
+        return unquote(quoted.strip_suffix('"')?).map(Value::String);
+    }
+
+    match text {
+        "true" => Some(Value::Boolean(true)),
+        "false" => Some(Value::Boolean(false)),
+        _ => {
+            let digits = text.replace('_', "");
+            match digits.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16).ok(),
+                None => digits.parse().ok(),
+            }
+            .map(Value::Integer)
+        }
+    }
+}
+
+/// Key codes are written as a single hexadecimal digit.
+fn parse_key(text: &str) -> Option<u8> {
+    if text.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(text, 16).ok()
+}
+
+/// Remove a trailing comment, which can't start inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (index, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+
+    line
+}
+
+/// Resolve the escape sequences of a string, without its quotes.
+fn unquote(text: &str) -> Option<String> {
+    let mut string = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next()? {
+                '\\' => string.push('\\'),
+                '"' => string.push('"'),
+                'n' => string.push('\n'),
+                't' => string.push('\t'),
+                _ => return None,
+            },
+            // An unescaped quote ends the string early.
+            '"' => return None,
+            _ => string.push(c),
+        }
+    }
+
+    Some(string)
+}
+
+fn quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[inline(never)]
+#[cold]
+fn rom_info_error(message: impl ToString) -> Chip8Error {
+    Chip8Error::RomInfo(message.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::quirks::SpriteEdge;
+
+    #[test]
+    fn test_rom_info_parse() {
+        let text = r#"
+# Breakout (Brix hack)
+title = "Breakout \"Brix\" # 2"
+profile = "vip"     # quirks profile
+clock_hz = 1_000
+
+[quirks]
+clip_sprites = false
+
+[keys]
+4 = "left"
+6 = "right"
+"#;
+        let info = RomInfo::parse(text).unwrap();
+        assert_eq!(info.title.as_deref(), Some("Breakout \"Brix\" # 2"));
+        assert_eq!(info.clock_hz, Some(1000));
+        assert_eq!(info.key_hint(KeyCode::Key4), Some("left"));
+        assert_eq!(info.key_hint(KeyCode::Key5), None);
+
+        let quirks = info.quirks().unwrap().unwrap();
+        assert_eq!(
+            quirks,
+            Quirks {
+                sprite_edge: SpriteEdge::Wrap,
+                ..Quirks::COSMAC_VIP
+            }
+        );
+
+        assert_eq!(RomInfo::parse(&info.to_string()).unwrap(), info);
+        assert_eq!(RomInfo::parse("").unwrap().quirks().unwrap(), None);
+    }
+
+    #[test]
+    fn test_rom_info_invalid() {
+        for text in [
+            "title = Breakout\n",
+            "title = \"Breakout\n",
+            "clock_hz = \"fast\"\n",
+            "speed = 700\n",
+            "title = \"a\"\ntitle = \"b\"\n",
+            "profile = \"xo\"\n",
+            "[quirks]\nwrap_sprites = true\n",
+            "[keys]\n10 = \"up\"\n",
+            "[display]\n",
+        ] {
+            assert!(
+                matches!(RomInfo::parse(text), Err(Chip8Error::RomInfo(_))),
+                "invalid rom info must fail: {text:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_rom_info_sidecar_path() {
+        assert_eq!(
+            RomInfo::sidecar_path("roms/game.ch8"),
+            PathBuf::from("roms/game.ch8.toml")
+        );
+    }
+}
@@ -0,0 +1,127 @@
+//! Display snapshots for regression testing.
+//!
+//! A program is run headless for a fixed number of steps, and the
+//! display is compared against the output of [`Chip8Vm::dump_display`]
+//! stored in a snapshot file.
+use std::fmt;
+
+use crate::{
+    error::Chip8Result,
+    vm::{Chip8Conf, Chip8Vm},
+};
+
+/// Run the program for the given number of steps, and capture the display.
+pub fn capture_display(bytecode: &[u8], steps: usize, conf: Chip8Conf) -> Chip8Result<String> {
+    let mut vm = Chip8Vm::new(conf);
+    vm.load_bytecode(bytecode)?;
+    vm.run_steps(steps)?;
+    Ok(vm.dump_display()?)
+}
+
+/// Pixel differences between an expected and actual display dump.
+///
+/// Rendered as an ASCII image of the display, where matching
+/// pixels are kept as-is, and mismatched pixels are marked.
+///
+/// ```text
+/// -  pixel expected to be on, but is off
+/// *  pixel expected to be off, but is on
+/// !  pixel is on, but in a different plane
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayDiff {
+    rows: Vec<String>,
+    mismatches: usize,
+}
+
+impl DisplayDiff {
+    /// Compare two display dumps.
+    ///
+    /// Returns `None` when the displays are identical.
+    pub fn new(expected: &str, actual: &str) -> Option<Self> {
+        let mut expected_rows = expected.lines();
+        let mut actual_rows = actual.lines();
+        let mut rows = vec![];
+        let mut mismatches = 0;
+
+        loop {
+            let (expected_row, actual_row) = match (expected_rows.next(), actual_rows.next()) {
+                (None, None) => break,
+                (expected_row, actual_row) => (
+                    expected_row.unwrap_or_default(),
+                    actual_row.unwrap_or_default(),
+                ),
+            };
+
+            let mut expected_pixels = expected_row.chars();
+            let mut actual_pixels = actual_row.chars();
+            let mut row = String::new();
+
+            loop {
+                let (a, b) = match (expected_pixels.next(), actual_pixels.next()) {
+                    (None, None) => break,
+                    // Missing pixels are compared as blank.
+                    (a, b) => (a.unwrap_or('.'), b.unwrap_or('.')),
+                };
+                if a == b {
+                    row.push(a);
+                    continue;
+                }
+
+                mismatches += 1;
+                row.push(match (is_lit(a), is_lit(b)) {
+                    (true, false) => '-',
+                    (false, true) => '*',
+                    _ => '!',
+                });
+            }
+
+            rows.push(row);
+        }
+
+        if mismatches == 0 {
+            None
+        } else {
+            Some(Self { rows, mismatches })
+        }
+    }
+
+    /// Number of pixels that differ.
+    pub fn mismatches(&self) -> usize {
+        self.mismatches
+    }
+}
+
+impl fmt::Display for DisplayDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} pixels differ", self.mismatches)?;
+        for row in &self.rows {
+            writeln!(f, "{row}")?;
+        }
+        write!(f, "- expected on  * expected off  ! different plane")
+    }
+}
+
+/// Pixel is lit in any plane.
+fn is_lit(pixel: char) -> bool {
+    matches!(pixel, '#' | '+' | '@')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_display_diff() {
+        assert_eq!(DisplayDiff::new("#..\n.#.\n", "#..\n.#.\n"), None);
+
+        let diff = DisplayDiff::new("#..\n.#.\n", "...\n.+#\n").unwrap();
+        assert_eq!(diff.mismatches(), 3);
+        assert_eq!(diff.rows, vec!["-..", ".!*"]);
+
+        // Missing rows are compared as blank.
+        let diff = DisplayDiff::new("#..\n", "#..\n..#\n").unwrap();
+        assert_eq!(diff.mismatches(), 1);
+        assert_eq!(diff.rows, vec!["#..", "..*"]);
+    }
+}
@@ -0,0 +1,299 @@
+//! Save state serialization.
+//!
+//! The full CPU state is written into a versioned binary format,
+//! so a running program can be restored later.
+//!
+//! Multi-byte values are stored big-endian, like Chip-8 bytecode.
+use crate::{
+    constants::*,
+    cpu::Chip8Cpu,
+    devices::{KeyCode, KeyEvent, KeyState},
+    error::{Chip8Error, Chip8Result},
+};
+
+/// Magic bytes identifying a save state.
+const MAGIC: &[u8; 4] = b"C8SS";
+
+/// Version of the save state binary format.
+///
+/// Must be incremented when the layout changes.
+pub const STATE_VERSION: u16 = 3;
+
+/// Stored in place of the key pressed during an `Fx0A` wait, when there's none.
+const NO_KEY: u8 = 0xFF;
+
+/// Size of one display plane, packed as bits.
+const PACKED_PLANE_SIZE: usize = DISPLAY_BUFFER_SIZE / 8;
+
+impl Chip8Cpu {
+    /// Serialize the CPU state into the buffer.
+    pub(crate) fn write_state(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(MAGIC);
+        buf.extend_from_slice(&STATE_VERSION.to_be_bytes());
+
+        // Registers
+        buf.extend_from_slice(&(self.pc as u16).to_be_bytes());
+        buf.extend_from_slice(&(self.sp as u16).to_be_bytes());
+        buf.extend_from_slice(&self.registers);
+        buf.extend_from_slice(&self.address.to_be_bytes());
+        buf.push(self.delay_timer);
+        buf.push(self.sound_timer);
+        buf.push(self.buzzer_state as u8);
+        buf.push(self.key_wait as u8);
+        buf.extend_from_slice(&self.key_state.to_be_bytes());
+        buf.push(self.planes);
+        buf.extend_from_slice(&self.audio_pattern);
+        buf.push(self.audio_pattern_loaded as u8);
+        buf.push(self.pitch);
+
+        // Input
+        buf.push(self.key_wait_press.map_or(NO_KEY, |key| key.as_u8()));
+        buf.extend_from_slice(&self.key_pending_release.to_be_bytes());
+        buf.extend_from_slice(&self.key_stamp.to_be_bytes());
+        for stamp in self.key_stamps.iter() {
+            buf.extend_from_slice(&stamp.to_be_bytes());
+        }
+        buf.push(self.key_events.len() as u8);
+        for event in self.key_events.iter() {
+            buf.push(event.key.as_u8());
+            buf.push((event.state == KeyState::Pressed) as u8);
+        }
+
+        // Memory
+        buf.extend_from_slice(&self.ram[..]);
+        for address in self.stack.iter() {
+            buf.extend_from_slice(&address.to_be_bytes());
+        }
+        for plane in self.display.iter() {
+            for row in plane.rows() {
+                buf.extend_from_slice(&row.to_be_bytes());
+            }
+        }
+    }
+
+    /// Deserialize CPU state from the buffer.
+    ///
+    /// The current state is left untouched if the buffer is invalid.
+    pub(crate) fn read_state(&mut self, state: &[u8]) -> Chip8Result<()> {
+        let mut reader = StateReader { state, cursor: 0 };
+
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            return Err(state_error("not a chip8 save state"));
+        }
+
+        let version = reader.u16()?;
+        if version != STATE_VERSION {
+            return Err(Chip8Error::State(format!(
+                "unsupported save state version {version}, expected {STATE_VERSION}"
+            )));
+        }
+
+        let mut cpu = Chip8Cpu::new();
+
+        // Registers
+        cpu.pc = reader.u16()? as usize;
+        cpu.sp = reader.u16()? as usize;
+        cpu.registers.copy_from_slice(reader.bytes(REGISTER_COUNT)?);
+        cpu.address = reader.u16()?;
+        cpu.delay_timer = reader.u8()?;
+        cpu.sound_timer = reader.u8()?;
+        cpu.buzzer_state = reader.u8()? != 0;
+        cpu.key_wait = reader.u8()? != 0;
+        cpu.key_state = reader.u16()?;
+        cpu.planes = reader.u8()?;
+        cpu.audio_pattern
+            .copy_from_slice(reader.bytes(AUDIO_PATTERN_SIZE)?);
+        cpu.audio_pattern_loaded = reader.u8()? != 0;
+        cpu.pitch = reader.u8()?;
+
+        if cpu.pc >= MEM_SIZE {
+            return Err(state_error("program counter out of bounds"));
+        }
+        if cpu.sp >= STACK_SIZE {
+            return Err(state_error("stack pointer out of bounds"));
+        }
+
+        // Input
+        cpu.key_wait_press = match reader.u8()? {
+            NO_KEY => None,
+            key => Some(read_key(key)?),
+        };
+        cpu.key_pending_release = reader.u16()?;
+        cpu.key_stamp = reader.u32()?;
+        for stamp in cpu.key_stamps.iter_mut() {
+            *stamp = reader.u32()?;
+        }
+        let event_count = reader.u8()? as usize;
+        if event_count > KEY_EVENT_CAPACITY {
+            return Err(state_error("too many key events"));
+        }
+        for _ in 0..event_count {
+            let key = read_key(reader.u8()?)?;
+            let state = match reader.u8()? {
+                0 => KeyState::Released,
+                _ => KeyState::Pressed,
+            };
+            cpu.key_events.push_back(KeyEvent { key, state });
+        }
+
+        // Memory
+        cpu.ram.copy_from_slice(reader.bytes(MEM_SIZE)?);
+        for address in cpu.stack.iter_mut() {
+            *address = reader.u16()?;
+        }
+        for plane in cpu.display.iter_mut() {
+            let packed = reader.bytes(PACKED_PLANE_SIZE)?;
+            for (y, row) in packed.chunks(8).enumerate() {
+                let row = u64::from_be_bytes(row.try_into().unwrap());
+                plane.set_row(y, row);
+            }
+        }
+
+        if reader.cursor != state.len() {
+            return Err(state_error("unexpected trailing data"));
+        }
+
+        // The stack depth is configured, rather than saved.
+        cpu.stack_depth = self.stack_depth;
+        *self = cpu;
+
+        Ok(())
+    }
+}
+
+#[inline(never)]
+#[cold]
+fn state_error(message: &str) -> Chip8Error {
+    Chip8Error::State(message.to_string())
+}
+
+fn read_key(key: u8) -> Chip8Result<KeyCode> {
+    KeyCode::try_from(key).map_err(|_| state_error("key out of range"))
+}
+
+/// Cursor over a save state buffer.
+struct StateReader<'a> {
+    state: &'a [u8],
+    cursor: usize,
+}
+
+impl<'a> StateReader<'a> {
+    fn bytes(&mut self, count: usize) -> Chip8Result<&'a [u8]> {
+        let end = self.cursor + count;
+        let bytes = self
+            .state
+            .get(self.cursor..end)
+            .ok_or_else(|| state_error("unexpected end of save state"))?;
+        self.cursor = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Chip8Result<u8> {
+        self.bytes(1).map(|bytes| bytes[0])
+    }
+
+    fn u16(&mut self) -> Chip8Result<u16> {
+        self.bytes(2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Chip8Result<u32> {
+        self.bytes(4)
+            .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{prelude::*, Flow, KeyCode, KeyState};
+
+    #[test]
+    fn test_state_roundtrip() {
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+        let program = concat!(
+            "LD v3, 0x42     \n",
+            "LD DT, v3       \n",
+            "LD I, .sprite   \n",
+            "DRW v0, v0, 1   \n",
+            ".loop           \n",
+            "JP .loop        \n",
+            ".sprite         \n",
+            "0b10100000      \n",
+        );
+        vm.load_rom_asm(program).unwrap();
+        vm.run_steps(5).unwrap();
+
+        let state = vm.save_state();
+        let display = vm.dump_display().unwrap();
+        let delay_timer = vm.debugger().delay_timer();
+
+        let mut other = Chip8Vm::new(Chip8Conf::default());
+        other.load_state(&state).unwrap();
+        assert_eq!(other.save_state(), state);
+        assert_eq!(other.dump_display().unwrap(), display);
+
+        let debugger = other.debugger();
+        assert_eq!(debugger.registers()[3], 0x42);
+        assert_eq!(debugger.delay_timer(), delay_timer);
+        assert_eq!(debugger.pc(), 0x208);
+    }
+
+    /// Input of an `Fx0A` wait carries over, so the wait resolves after loading.
+    #[test]
+    fn test_state_key_wait() {
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+        vm.load_rom_asm("LD v0, K\n.loop\nJP .loop\n").unwrap();
+        assert_eq!(vm.tick().unwrap(), Flow::KeyWait);
+        vm.push_key_event(KeyCode::Key5, KeyState::Pressed);
+        assert_eq!(vm.tick().unwrap(), Flow::KeyWait);
+        // Queued, and consumed after loading.
+        vm.push_key_event(KeyCode::Key5, KeyState::Released);
+
+        let state = vm.save_state();
+        let mut other = Chip8Vm::new(Chip8Conf::default());
+        other.load_state(&state).unwrap();
+        assert_eq!(other.save_state(), state);
+
+        assert_eq!(other.tick().unwrap(), Flow::Ok);
+        let debugger = other.debugger();
+        assert_eq!(debugger.registers()[0], 5);
+        assert_eq!(debugger.pc(), 0x202);
+    }
+
+    #[test]
+    fn test_state_invalid() {
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+        vm.load_bytecode(&[0x60, 0x01]).unwrap();
+        let state = vm.save_state();
+
+        // Truncated
+        assert!(matches!(
+            vm.load_state(&state[..state.len() - 1]),
+            Err(Chip8Error::State(_))
+        ));
+
+        // Trailing data
+        let mut long = state.clone();
+        long.push(0);
+        assert!(matches!(vm.load_state(&long), Err(Chip8Error::State(_))));
+
+        // Wrong magic
+        let mut bad_magic = state.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(
+            vm.load_state(&bad_magic),
+            Err(Chip8Error::State(_))
+        ));
+
+        // Unknown version
+        let mut bad_version = state.clone();
+        bad_version[5] = 0xFF;
+        assert!(matches!(
+            vm.load_state(&bad_version),
+            Err(Chip8Error::State(_))
+        ));
+
+        // VM is untouched after a failed load.
+        assert_eq!(vm.save_state(), state);
+    }
+}
@@ -0,0 +1,394 @@
+//! Deterministic headless runs, for testing the behaviour of programs.
+//!
+//! A [`HeadlessRunner`] executes a program frame by frame, pushing the key
+//! events of a scripted timeline before the frames they're scheduled for.
+//! With the same seed, timeline and step budget, every run ends in the
+//! same state, so games can be tested in plain Rust tests.
+//!
+//! ```
+//! use chip8::{testing::HeadlessRunner, KeyCode};
+//!
+//! // Counts the key presses in `v1`.
+//! let rom = chip8::assemble("
+//! .loop
+//!     LD  v0, K
+//!     ADD v1, 1
+//!     JP  .loop
+//! ").unwrap();
+//!
+//! let outcome = HeadlessRunner::new(&rom)
+//!     .tap(10, KeyCode::Key5)
+//!     .tap(20, KeyCode::Key5)
+//!     .run()
+//!     .unwrap();
+//! assert_eq!(outcome.registers[1], 2);
+//! ```
+//!
+//! Backends are compared with [`lockstep`], which finds the first
+//! instruction they execute differently.
+use std::fmt;
+
+use crate::{
+    constants::{Address, PLANE_COUNT, REGISTER_COUNT},
+    cpu::CpuSnapshot,
+    devices::{KeyCode, KeyState},
+    error::Chip8Result,
+    interpreter::Interpreter,
+    replay::{Replay, ReplayEvent},
+    snapshot::DisplayDiff,
+    vm::{Chip8Conf, Chip8Vm, Flow, FrameReport},
+};
+
+/// Number of instructions executed by a runner, unless configured otherwise.
+pub const DEFAULT_STEP_BUDGET: usize = 100_000;
+
+/// Number of differing memory bytes listed by a [`Divergence`].
+const MEMORY_DIFF_LIMIT: usize = 16;
+
+/// Seed of the random number generator, unless configured otherwise.
+pub const DEFAULT_SEED: u64 = 0;
+
+/// Runs a program without a window, driven by a timeline of key events.
+#[derive(Clone)]
+pub struct HeadlessRunner {
+    rom: Vec<u8>,
+    conf: Chip8Conf,
+    seed: u64,
+    /// Key events, in the order they were scheduled.
+    timeline: Vec<ReplayEvent>,
+    step_budget: usize,
+}
+
+/// State of the VM at the end of a run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeadlessOutcome {
+    /// SHA-1 hash of all display planes, as lowercase hexadecimal.
+    pub display_hash: String,
+    /// General purpose registers `V0` to `VF`.
+    pub registers: [u8; REGISTER_COUNT],
+    /// Address register `I`.
+    pub address: Address,
+    pub pc: usize,
+    /// Number of frames executed.
+    pub frames: u64,
+    /// Number of instructions executed.
+    pub steps: usize,
+    /// The program stopped on its own, instead of exhausting the budget.
+    pub stopped: bool,
+}
+
+impl HeadlessRunner {
+    pub fn new(rom: &[u8]) -> Self {
+        Self {
+            rom: rom.to_vec(),
+            conf: Chip8Conf::default(),
+            seed: DEFAULT_SEED,
+            timeline: Vec::new(),
+            step_budget: DEFAULT_STEP_BUDGET,
+        }
+    }
+
+    /// Configuration of the VM. The seed is always set by the runner.
+    pub fn with_conf(mut self, conf: Chip8Conf) -> Self {
+        self.conf = conf;
+        self
+    }
+
+    /// Seed of the random number generator.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Maximum number of instructions to execute.
+    ///
+    /// The budget is checked between frames, so the last frame
+    /// is always executed completely.
+    pub fn with_step_budget(mut self, steps: usize) -> Self {
+        self.step_budget = steps;
+        self
+    }
+
+    /// Schedule a key event before the given frame is executed.
+    pub fn with_event(mut self, frame: u64, key: KeyCode, state: KeyState) -> Self {
+        self.timeline.push(ReplayEvent { frame, key, state });
+        self
+    }
+
+    /// Schedule the seed and key events of a recorded session.
+    pub fn with_replay(mut self, replay: &Replay) -> Self {
+        self.seed = replay.seed;
+        self.timeline.extend_from_slice(&replay.events);
+        self
+    }
+
+    pub fn press(self, frame: u64, key: KeyCode) -> Self {
+        self.with_event(frame, key, KeyState::Pressed)
+    }
+
+    pub fn release(self, frame: u64, key: KeyCode) -> Self {
+        self.with_event(frame, key, KeyState::Released)
+    }
+
+    /// Press the key before the given frame, and release it before the next.
+    pub fn tap(self, frame: u64, key: KeyCode) -> Self {
+        self.press(frame, key).release(frame + 1, key)
+    }
+
+    /// Run the program until the budget is exhausted, or the program stops.
+    pub fn run(&self) -> Chip8Result<HeadlessOutcome> {
+        self.run_with(|_, _| {})
+    }
+
+    /// Run the program, calling the inspector with the number of executed
+    /// frames and the VM after every frame.
+    ///
+    /// The program stops when it's interrupted, or when it waits for a
+    /// key and no more events are scheduled.
+    pub fn run_with(
+        &self,
+        mut inspect: impl FnMut(u64, &mut Chip8Vm),
+    ) -> Chip8Result<HeadlessOutcome> {
+        let mut vm = Chip8Vm::new(Chip8Conf {
+            seed: Some(self.seed),
+            ..self.conf.clone()
+        });
+        vm.load_bytecode(&self.rom)?;
+
+        // Events of the same frame keep their scheduled order.
+        let mut replay = Replay::new(self.seed);
+        replay.events = self.timeline.clone();
+        replay.events.sort_by_key(|event| event.frame);
+        let last_frame = replay.events.last().map(|event| event.frame);
+
+        let mut frames = 0;
+        let mut steps = 0;
+        let mut stopped = false;
+        while steps < self.step_budget {
+            for event in replay.events_at(frames) {
+                vm.push_key_event(event.key, event.state);
+            }
+
+            let report = vm.run_frame()?;
+            frames += 1;
+            steps += report.instructions;
+            inspect(frames, &mut vm);
+
+            let starved = report.key_wait && last_frame.is_none_or(|last| last < frames);
+            if report.interrupt || starved {
+                stopped = true;
+                break;
+            }
+        }
+
+        Ok(HeadlessOutcome {
+            display_hash: display_hash(&vm),
+            registers: *vm.debugger().registers(),
+            address: vm.debugger().address(),
+            pc: vm.debugger().pc(),
+            frames,
+            steps,
+            stopped,
+        })
+    }
+}
+
+fn display_hash(vm: &Chip8Vm) -> String {
+    let mut hasher = sha1_smol::Sha1::new();
+    for plane in (0..PLANE_COUNT).filter_map(|plane| vm.plane_buffer(plane)) {
+        for row in plane.rows() {
+            hasher.update(&row.to_be_bytes());
+        }
+    }
+    hasher.digest().to_string()
+}
+
+/// Run the program on two backends in lockstep, and find the first
+/// instruction after which their machine states differ.
+///
+/// The program is loaded into both machines, which should be created with
+/// the same configuration and seed. The backends execute one instruction
+/// at a time, comparing the CPU, memory and display after each, and count
+/// down the timers at the end of every frame, like [`Chip8Vm::run_frame`].
+///
+/// Returns `None` when the backends agree for all the steps, or until both
+/// stop by waiting for a key, being interrupted, or raising an error.
+///
+/// ```
+/// use chip8::{prelude::*, testing::lockstep, Quirks};
+///
+/// let rom = chip8::assemble("
+///     LD  v1, 0x03
+///     SHR v0, v1
+/// ").unwrap();
+/// let vm = |shift_vy| Chip8Vm::new(Chip8Conf {
+///     quirks: Quirks { shift_vy, ..Default::default() },
+///     ..Default::default()
+/// });
+///
+/// let divergence = lockstep(&mut vm(true), &mut vm(false), &rom, 10)
+///     .unwrap()
+///     .unwrap();
+/// assert_eq!(divergence.step, 1);
+/// assert_eq!(divergence.pc, 0x202);
+/// assert_eq!(divergence.a.registers[0], 0x01);
+/// assert_eq!(divergence.b.registers[0], 0x00);
+/// ```
+pub fn lockstep<A, B>(
+    vm_a: &mut A,
+    vm_b: &mut B,
+    rom: &[u8],
+    steps: usize,
+) -> Chip8Result<Option<Divergence>>
+where
+    A: Interpreter,
+    B: Interpreter,
+    A::Error: fmt::Display,
+    B::Error: fmt::Display,
+{
+    vm_a.machine_mut().load_bytecode(rom)?;
+    vm_b.machine_mut().load_bytecode(rom)?;
+    let frame_steps = vm_a.machine().instructions_per_frame().max(1);
+
+    for step in 0..steps {
+        let pc = vm_a.machine().state().pc();
+        let ram = vm_a.machine().ram();
+        let opcode = match ram.get(pc..pc + 2) {
+            Some(&[hi, lo]) => u16::from_be_bytes([hi, lo]),
+            _ => 0,
+        };
+
+        let flow_a = vm_a.exec().map_err(|err| err.to_string());
+        let flow_b = vm_b.exec().map_err(|err| err.to_string());
+
+        // Both backends end their frames at the same step, so the timers agree.
+        if (step + 1) % frame_steps == 0 {
+            vm_a.machine_mut().finish_frame(&mut FrameReport::default());
+            vm_b.machine_mut().finish_frame(&mut FrameReport::default());
+        }
+
+        let (stopped_a, stopped_b) = (is_stopped(&flow_a), is_stopped(&flow_b));
+        let (a, b) = (vm_a.machine(), vm_b.machine());
+        let cpu = (a.state().snapshot(), b.state().snapshot());
+        let memory = memory_diff(a.ram(), b.ram());
+        let display = DisplayDiff::new(&a.dump_display()?, &b.dump_display()?);
+
+        if cpu.0 != cpu.1 || !memory.is_empty() || display.is_some() || stopped_a != stopped_b {
+            return Ok(Some(Divergence {
+                step,
+                pc,
+                opcode,
+                flow_a,
+                flow_b,
+                a: cpu.0,
+                b: cpu.1,
+                memory,
+                display,
+            }));
+        }
+        if stopped_a {
+            break;
+        }
+    }
+
+    Ok(None)
+}
+
+/// The backend can't continue without help from the caller.
+fn is_stopped(flow: &Result<Flow, String>) -> bool {
+    matches!(flow, Err(_) | Ok(Flow::Interrupt | Flow::KeyWait))
+}
+
+/// Bytes that differ between the memories, as `(address, a, b)`.
+fn memory_diff(a: &[u8], b: &[u8]) -> Vec<(usize, u8, u8)> {
+    a.iter()
+        .zip(b)
+        .enumerate()
+        .filter(|(_, (a, b))| a != b)
+        .map(|(address, (a, b))| (address, *a, *b))
+        .collect()
+}
+
+/// First instruction executed differently by two backends, found by [`lockstep`].
+///
+/// Formatted as a diff of the state after the instruction, listing only
+/// what differs, with the state of backend `a` first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Number of instructions both backends executed before this one.
+    pub step: usize,
+    /// Address of the instruction.
+    pub pc: usize,
+    pub opcode: u16,
+    /// Result of the instruction on each backend, with errors formatted.
+    pub flow_a: Result<Flow, String>,
+    pub flow_b: Result<Flow, String>,
+    /// CPU state of each backend after the instruction.
+    pub a: CpuSnapshot,
+    pub b: CpuSnapshot,
+    /// Bytes of memory that differ, as `(address, a, b)`.
+    pub memory: Vec<(usize, u8, u8)>,
+    /// Pixels that differ, when the displays do, with the display of `a` as expected.
+    pub display: Option<DisplayDiff>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "diverged at step {}, instruction 0x{:04X} at 0x{:04X}",
+            self.step, self.opcode, self.pc
+        )?;
+
+        if self.flow_a != self.flow_b {
+            writeln!(f, "  flow: {:?} != {:?}", self.flow_a, self.flow_b)?;
+        }
+
+        let (a, b) = (&self.a, &self.b);
+        if a.pc != b.pc {
+            writeln!(f, "  PC: 0x{:04X} != 0x{:04X}", a.pc, b.pc)?;
+        }
+        for (index, (x, y)) in a.registers.iter().zip(&b.registers).enumerate() {
+            if x != y {
+                writeln!(f, "  V{index:X}: 0x{x:02X} != 0x{y:02X}")?;
+            }
+        }
+        if a.address != b.address {
+            writeln!(f, "  I: 0x{:04X} != 0x{:04X}", a.address, b.address)?;
+        }
+        if a.delay_timer != b.delay_timer {
+            writeln!(f, "  DT: {} != {}", a.delay_timer, b.delay_timer)?;
+        }
+        if a.sound_timer != b.sound_timer {
+            writeln!(f, "  ST: {} != {}", a.sound_timer, b.sound_timer)?;
+        }
+        if a.stack != b.stack {
+            writeln!(f, "  stack: {:03X?} != {:03X?}", a.stack, b.stack)?;
+        }
+        if (a.buzzer, a.pitch, a.keys, a.key_wait) != (b.buzzer, b.pitch, b.keys, b.key_wait) {
+            writeln!(
+                f,
+                "  buzzer, pitch, keys, key wait: {:?} != {:?}",
+                (a.buzzer, a.pitch, a.keys, a.key_wait),
+                (b.buzzer, b.pitch, b.keys, b.key_wait)
+            )?;
+        }
+
+        for (address, x, y) in self.memory.iter().take(MEMORY_DIFF_LIMIT) {
+            writeln!(f, "  [0x{address:04X}]: 0x{x:02X} != 0x{y:02X}")?;
+        }
+        if self.memory.len() > MEMORY_DIFF_LIMIT {
+            writeln!(
+                f,
+                "  ... and {} more bytes",
+                self.memory.len() - MEMORY_DIFF_LIMIT
+            )?;
+        }
+
+        if let Some(display) = &self.display {
+            writeln!(f, "  display: {display}")?;
+        }
+
+        Ok(())
+    }
+}
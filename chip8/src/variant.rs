@@ -0,0 +1,116 @@
+//! Interpreters of the Chip-8 family, with their instruction sets and quirks.
+use std::fmt;
+
+use crate::quirks::Quirks;
+
+/// Interpreter a program is written for, configured with
+/// [`Chip8Conf::variant`](crate::Chip8Conf::variant).
+///
+/// Selects the quirks and the instructions of the interpreter. Instructions
+/// of extensions the variant doesn't know stop the VM with an error, like
+/// they would on the original hardware.
+///
+/// ```
+/// # use chip8::{Extension, Quirks, Variant};
+/// let variant = Variant::from_name("chip48").unwrap();
+/// assert_eq!(variant.quirks(), Quirks::CHIP48);
+/// assert!(!variant.supports([0xF3, 0x30]));
+/// assert!(Variant::SuperChip.supports([0xF3, 0x30]));
+/// assert_eq!(Extension::of([0xF0, 0x00]), Some(Extension::XoChip));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Variant {
+    /// The original interpreter of the COSMAC VIP.
+    #[cfg_attr(feature = "serde", serde(rename = "chip8"))]
+    Chip8,
+    /// CHIP-48 on the HP-48 calculators.
+    #[cfg_attr(feature = "serde", serde(rename = "chip48"))]
+    Chip48,
+    /// SUPER-CHIP 1.1.
+    #[cfg_attr(feature = "serde", serde(rename = "schip"))]
+    SuperChip,
+    /// XO-CHIP, which extends SUPER-CHIP.
+    #[cfg_attr(feature = "serde", serde(rename = "xochip"))]
+    XoChip,
+}
+
+/// Group of instructions added to the original instruction set by a later interpreter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Extension {
+    /// `Fx30 (LD HF, Vx)`
+    SuperChip,
+    /// `F000 nnnn (LD I, LONG addr)`, `Fn01 (PLANE n)`, `F002 (AUDIO)` and `Fx3A (PITCH Vx)`
+    XoChip,
+}
+
+impl Extension {
+    /// Extension that added the instruction, or `None` for the
+    /// instructions of the original interpreter.
+    pub fn of(instr: [u8; 2]) -> Option<Self> {
+        let [a, b] = instr;
+        match (a >> 4, a & 0xF, b) {
+            (0xF, _, 0x30) => Some(Self::SuperChip),
+            (0xF, 0, 0x00 | 0x02) | (0xF, _, 0x01 | 0x3A) => Some(Self::XoChip),
+            _ => None,
+        }
+    }
+}
+
+/// Extensions known by each variant, in the order of [`Variant::ALL`].
+const EXTENSIONS: [&[Extension]; 4] = [
+    &[],
+    &[],
+    &[Extension::SuperChip],
+    &[Extension::SuperChip, Extension::XoChip],
+];
+
+impl Variant {
+    pub const ALL: [Self; 4] = [Self::Chip8, Self::Chip48, Self::SuperChip, Self::XoChip];
+
+    /// Names of the variants, accepted by [`Variant::from_name`].
+    pub const NAMES: &'static [&'static str] = &["chip8", "chip48", "schip", "xochip"];
+
+    /// Look up a variant by name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES
+            .iter()
+            .position(|other| *other == name)
+            .map(|index| Self::ALL[index])
+    }
+
+    pub fn name(self) -> &'static str {
+        Self::NAMES[self as usize]
+    }
+
+    /// Quirks of the interpreter.
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Self::Chip8 => Quirks::COSMAC_VIP,
+            Self::Chip48 => Quirks::CHIP48,
+            Self::SuperChip => Quirks::SUPER_CHIP,
+            Self::XoChip => Quirks::XO_CHIP,
+        }
+    }
+
+    /// Extensions of the original instruction set known by the interpreter.
+    pub fn extensions(self) -> &'static [Extension] {
+        EXTENSIONS[self as usize]
+    }
+
+    /// Indicates whether the interpreter knows the instruction.
+    pub fn supports(self, instr: [u8; 2]) -> bool {
+        Extension::of(instr).is_none_or(|extension| self.extensions().contains(&extension))
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Chip8 => write!(f, "CHIP-8"),
+            Self::Chip48 => write!(f, "CHIP-48"),
+            Self::SuperChip => write!(f, "SUPER-CHIP"),
+            Self::XoChip => write!(f, "XO-CHIP"),
+        }
+    }
+}
@@ -1,18 +1,33 @@
 //! Virtual machine.
 use std::{
+    collections::{BTreeSet, VecDeque},
     fmt::{self, Write},
+    path::Path,
     time::Duration,
 };
 
-use rand::prelude::*;
-
 use crate::{
-    bytecode::*,
+    asm::AsmConf,
+    audio::{AudioConf, AudioState},
+    bytecode::{opcodes::LD_I_LONG, *},
     clock::Clock,
     constants::*,
-    cpu::Chip8Cpu,
-    devices::KeyCode,
-    error::{Chip8Error, Chip8Result},
+    cpu::{Chip8Cpu, CpuState},
+    devices::{KeyCode, KeyEvent, KeyPolicy, KeyState, Mouse},
+    dispatch::{DecodeCache, DispatchTable, OpHandler, OpPattern, Operands},
+    error::{Chip8Error, Chip8Result, RuntimeError},
+    font::FontSet,
+    image::DisplayImage,
+    memory_map::{MemoryMap, MemoryProtection},
+    memory_view::{ChangeOrigin, MemoryChange, MemoryView},
+    observer::VmObserver,
+    peripheral::Peripheral,
+    profile::Profiler,
+    quirks::Quirks,
+    random::{self, DefaultRandom, RandomSource},
+    replay::{Replay, ReplayEvent},
+    rom, rom_db,
+    variant::{Extension, Variant},
     Chip8DisplayBuffer,
 };
 
@@ -20,18 +35,73 @@ pub struct Chip8Vm {
     cpu: Chip8Cpu,
     clock: Clock,
     timer: Clock,
-    loop_counter: usize,
+    /// Consecutive frames without drawing or waiting for a key, for the watchdog.
+    idle_frames: u64,
     conf: Chip8Conf,
+    /// Addresses where the debugger will pause execution.
+    breakpoints: BTreeSet<usize>,
+    /// Recorded snapshots for rewinding.
+    history: History,
+    /// Execution counts, when profiling is enabled.
+    profiler: Option<Profiler>,
+    /// Random number generator for `Cxnn` (RND).
+    rng: Box<dyn RandomSource>,
+    /// Seed the random number generator was last seeded with.
+    seed: u64,
+    /// Number of frames executed by [`Chip8Vm::run_frame`].
+    frame: u64,
+    /// Input of the session, while recording.
+    recording: Option<Replay>,
+    /// Custom hardware extensions.
+    peripherals: Vec<Box<dyn Peripheral>>,
+    /// Hooks into execution.
+    observers: Vec<Box<dyn VmObserver>>,
+    /// Character sprites, reloaded with every program.
+    fontset: FontSet,
+    /// Addresses of memory that have been executed as instructions.
+    code: Box<[bool]>,
+    /// Regions of memory of the loaded program, and their protection.
+    memory_map: MemoryMap,
+    /// Called when the program writes to code, with the `Warn` policy.
+    on_self_modify: Option<SelfModifyCallback>,
+    /// Called for every write to memory.
+    memory_listeners: Vec<MemoryListener>,
+    /// Called when the timers fire, see [`TimerEvent`].
+    on_timer: Option<TimerHook>,
+    /// Handlers of the instructions, by opcode pattern.
+    dispatch: DispatchTable,
+    /// Handlers of executed instructions by address, when enabled.
+    decode_cache: Option<DecodeCache>,
 }
 
 impl Chip8Vm {
     pub fn new(conf: Chip8Conf) -> Self {
+        let seed = conf.seed.unwrap_or_else(random::random_seed);
+        let mut cpu = Chip8Cpu::new();
+        cpu.stack_depth = conf.stack_depth();
         Chip8Vm {
-            cpu: Chip8Cpu::new(),
+            cpu,
             clock: Clock::new(conf.clock_frequency.unwrap_or_default().into()),
             timer: Clock::from_nanos(DELAY_FREQUENCY),
-            loop_counter: 0,
+            idle_frames: 0,
+            history: History::new(conf.rewind),
+            profiler: conf.profile.then(Profiler::new),
+            rng: Box::new(DefaultRandom::new(seed)),
+            seed,
+            frame: 0,
+            recording: None,
+            peripherals: Vec::new(),
+            observers: Vec::new(),
+            fontset: FontSet::default(),
+            code: vec![false; MEM_SIZE].into_boxed_slice(),
+            memory_map: MemoryMap::new(conf.load_address(), 0, conf.memory_protection),
+            on_self_modify: None,
+            memory_listeners: Vec::new(),
+            on_timer: None,
+            dispatch: ops::builtin_table(conf.variant),
+            decode_cache: conf.decode_cache.then(DecodeCache::new),
             conf,
+            breakpoints: BTreeSet::new(),
         }
     }
 
@@ -40,52 +110,342 @@ impl Chip8Vm {
         &self.conf
     }
 
-    pub fn load_builtin_font(&mut self) -> Chip8Result<()> {
-        let conf = crate::asm::AsmConf {
-            // Fonts are 5 bytes high, and packed together for historical reasons.
-            pad_data: false,
-        };
-        let fontset = crate::asm::assemble_with(include_str!("fontset.asm"), conf)?;
-        self.load_font(&fontset)
+    /// Set the callback for writes to code, when the self-modify policy is
+    /// [`SelfModifyPolicy::Warn`].
+    ///
+    /// Caches of decoded instructions can use this to invalidate their entries.
+    pub fn on_self_modify(&mut self, callback: impl FnMut(&SelfModify) + 'static) {
+        self.on_self_modify = Some(Box::new(callback));
     }
 
-    pub fn load_font(&mut self, fontset: &[u8]) -> Chip8Result<()> {
-        if fontset.len() != FONTSET_DATA_LENGTH {
-            return Err(Chip8Error::Font(format!(
-                "fontset data must be {FONTSET_DATA_LENGTH} bytes, got {}",
-                fontset.len()
-            )));
+    /// Set the callback for the delay timer running out, and the
+    /// buzzer turning on or off.
+    ///
+    /// Frontends can start and stop their audio from the hook,
+    /// instead of polling the state of the buzzer every frame.
+    pub fn set_timer_hook(&mut self, hook: impl FnMut(TimerEvent) + 'static) {
+        self.on_timer = Some(Box::new(hook));
+    }
+
+    /// Execute instructions matching the pattern with the handler, like
+    /// `"8xy4"` or `"Fx75"`, see [`OpPattern`].
+    ///
+    /// Replaces the handler of the same pattern, including the built-in ones.
+    /// More specific patterns take precedence, so a handler for `"F075"`
+    /// is picked over one for `"Fx75"`.
+    ///
+    /// ```
+    /// # use chip8::{Chip8Conf, Chip8Vm, Flow, Operands};
+    /// // Fx75: store the registers to the RPL flags, as a no-op.
+    /// let mut vm = Chip8Vm::new(Chip8Conf::default());
+    /// vm.register_opcode("Fx75", |_vm: &mut Chip8Vm, _: Operands| Flow::Ok)
+    ///     .unwrap();
+    /// vm.load_bytecode(&[0xF3, 0x75]).unwrap();
+    /// vm.run_steps(1).unwrap();
+    /// ```
+    pub fn register_opcode(&mut self, pattern: &str, handler: OpHandler) -> Chip8Result<()> {
+        self.dispatch.register(OpPattern::parse(pattern)?, handler);
+        self.clear_decode_cache();
+        Ok(())
+    }
+
+    /// Add a callback for writes to memory, by the program or through a [`MemoryView`].
+    ///
+    /// Loading a program or a save state replaces all of memory, and isn't reported.
+    pub fn add_memory_listener(&mut self, callback: impl FnMut(&MemoryChange) + 'static) {
+        self.memory_listeners.push(Box::new(callback));
+    }
+
+    pub(crate) fn notify_memory_change(&mut self, change: MemoryChange) {
+        if let Some(cache) = self.decode_cache.as_mut() {
+            let start = change.address as usize;
+            cache.invalidate(start..start + change.len);
+        }
+        for listener in self.memory_listeners.iter_mut() {
+            listener(&change);
+        }
+    }
+
+    /// Indicates whether the byte at the address has been executed as part of an instruction.
+    pub fn is_code(&self, address: usize) -> bool {
+        self.code.get(address).copied().unwrap_or(false)
+    }
+
+    /// Regions of memory of the loaded program, and their protection.
+    pub fn memory_map(&self) -> &MemoryMap {
+        &self.memory_map
+    }
+
+    /// Attach custom hardware to the VM.
+    ///
+    /// Peripherals are called in the order they were added.
+    pub fn add_peripheral(&mut self, peripheral: impl Peripheral + 'static) {
+        self.peripherals.push(Box::new(peripheral));
+    }
+
+    /// Replace the generator of the random numbers drawn by `Cxnn (RND Vx, byte)`.
+    ///
+    /// The source is seeded with the current [`Chip8Vm::seed`].
+    pub fn set_random_source(&mut self, source: impl RandomSource + 'static) {
+        self.rng = Box::new(source);
+        self.rng.reseed(self.seed);
+    }
+
+    /// Attach an observer of the execution.
+    ///
+    /// Observers are called in the order they were added.
+    pub fn add_observer(&mut self, observer: impl VmObserver + 'static) {
+        self.observers.push(Box::new(observer));
+    }
+
+    pub fn clear_observers(&mut self) {
+        self.observers.clear();
+    }
+
+    /// Pass an event to every observer.
+    fn observe(&mut self, mut event: impl FnMut(&mut dyn VmObserver)) {
+        for observer in self.observers.iter_mut() {
+            event(observer.as_mut());
         }
+    }
 
-        self.cpu.ram[0..FONTSET_DATA_LENGTH].copy_from_slice(fontset);
+    pub fn load_builtin_font(&mut self) -> Chip8Result<()> {
+        self.set_fontset(FontSet::default());
+        Ok(())
+    }
 
+    /// Load the small font sprites, without a large font.
+    pub fn load_font(&mut self, fontset: &[u8]) -> Chip8Result<()> {
+        self.set_fontset(FontSet::new(fontset.to_vec(), Vec::new())?);
         Ok(())
     }
 
+    /// Replace the character sprites, which are kept when another program is loaded.
+    pub fn set_fontset(&mut self, fontset: FontSet) {
+        self.fontset = fontset;
+        self.write_fontset();
+    }
+
+    pub fn fontset(&self) -> &FontSet {
+        &self.fontset
+    }
+
+    /// Copy the character sprites into the interpreter area of memory.
+    fn write_fontset(&mut self) {
+        self.clear_decode_cache();
+
+        let small = self.fontset.small();
+        let start = FONTSET_START as usize;
+        self.cpu.ram[start..start + small.len()].copy_from_slice(small);
+
+        let large = self.fontset.large();
+        let start = LARGE_FONTSET_START as usize;
+        self.cpu.ram[start..start + LARGE_FONTSET_DATA_LENGTH].fill(0);
+        self.cpu.ram[start..start + large.len()].copy_from_slice(large);
+    }
+
     pub fn load_bytecode(&mut self, bytecode: &[u8]) -> Chip8Result<()> {
-        if !check_program_size(bytecode) {
+        let load_address = self.conf.load_address();
+        if !check_program_size(bytecode, load_address) {
             return Err(Chip8Error::LargeProgram);
         }
 
+        if self.conf.use_rom_db {
+            self.apply_rom_db(bytecode);
+        }
+
         // Start with clean memory to avoid leaking previous program.
         self.cpu.clear_memory();
 
         // Reset fonts
-        self.load_builtin_font()?;
+        self.write_fontset();
+        if self.conf.quirks.mouse {
+            self.cpu.ram[MOUSE_START..MOUSE_START + MOUSE_SIZE]
+                .copy_from_slice(&Mouse::default().to_bytes());
+        }
 
         // Load program into virtual RAM
-        self.cpu.ram[MEM_START..MEM_START + bytecode.len()].copy_from_slice(bytecode);
+        self.cpu.ram[load_address..load_address + bytecode.len()].copy_from_slice(bytecode);
+        self.code.fill(false);
+        self.clear_decode_cache();
+        self.memory_map = MemoryMap::new(load_address, bytecode.len(), self.conf.memory_protection);
 
         // Reset the program counter to prepare for execution.
-        self.cpu.pc = MEM_START;
+        self.cpu.pc = load_address;
+
+        // Snapshots of the previous program are meaningless now.
+        self.history.clear();
 
         self.reset();
 
         Ok(())
     }
 
-    pub fn display_buffer(&self) -> Chip8DisplayBuffer {
-        &self.cpu.display
+    /// Apply the recommended settings of a well-known ROM.
+    fn apply_rom_db(&mut self, bytecode: &[u8]) {
+        if let Some(entry) = rom_db::lookup(bytecode) {
+            entry.apply(&mut self.conf);
+            self.clock = Clock::new(self.conf.clock_frequency.unwrap_or_default().into());
+        }
+    }
+
+    /// Assemble the given source code and load the resulting bytecode.
+    pub fn load_rom_asm(&mut self, source: &str) -> Chip8Result<()> {
+        let conf = AsmConf {
+            load_address: self.conf.load_address(),
+            ..Default::default()
+        };
+        let bytecode = crate::asm::assemble_with(source, conf)?;
+        self.load_bytecode(&bytecode)
+    }
+
+    /// Load a ROM from a file.
+    ///
+    /// Files with the `.asm` extension are treated as assembly source code,
+    /// and are assembled before loading. The program of a [`RomArchive`] is
+    /// unpacked, and other containers are unwrapped, see [`rom::load`].
+    /// All other files are loaded as bytecode.
+    ///
+    /// [`RomArchive`]: crate::RomArchive
+    pub fn load_rom_file(&mut self, path: impl AsRef<Path>) -> Chip8Result<()> {
+        let rom = rom::load_at(path, self.conf.load_address())?;
+        self.load_bytecode(&rom)
+    }
+
+    /// Read-only view of the registers, timers, call stack and keys.
+    pub fn state(&self) -> CpuState<'_> {
+        CpuState::new(&self.cpu)
+    }
+
+    /// Return addresses of the subroutine calls in progress,
+    /// from the outermost to the innermost call.
+    ///
+    /// The `CALL` instruction of each frame is just before its return address.
+    pub fn call_stack(&self) -> &[Address] {
+        let sp = self.cpu.sp.min(STACK_SIZE - 1);
+        &self.cpu.stack[1..=sp]
+    }
+
+    /// Serialize the full machine state into a versioned binary format.
+    ///
+    /// See [`Chip8Vm::load_state`]
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.cpu.write_state(&mut buf);
+        buf
+    }
+
+    /// Restore the machine state from a buffer created by [`Chip8Vm::save_state`].
+    ///
+    /// The VM is left untouched if the state is invalid.
+    pub fn load_state(&mut self, state: &[u8]) -> Chip8Result<()> {
+        self.cpu.read_state(state)?;
+        self.clear_decode_cache();
+        self.reset();
+        Ok(())
+    }
+
+    /// Go back in time by the given number of recorded snapshots.
+    ///
+    /// Returns the number of snapshots that were actually rewound, which
+    /// is less than requested when the history runs out. The snapshots that
+    /// are rewound past are discarded.
+    pub fn rewind(&mut self, steps: usize) -> Chip8Result<usize> {
+        let steps = steps.min(self.history.snapshots.len());
+        if steps == 0 {
+            return Ok(0);
+        }
+
+        let index = self.history.snapshots.len() - steps;
+        let snapshot = self.history.snapshots.drain(index..).next().unwrap();
+        self.load_state(&snapshot)?;
+        self.history.counter = 0;
+
+        Ok(steps)
+    }
+
+    /// Number of snapshots available for rewinding.
+    pub fn rewind_len(&self) -> usize {
+        self.history.snapshots.len()
+    }
+
+    pub fn display_buffer(&self) -> Chip8DisplayBuffer<'_> {
+        &self.cpu.display[0]
+    }
+
+    /// Rows of pixels of the display, from top to bottom.
+    ///
+    /// The display is stored packed, so every row is unpacked
+    /// as it's iterated, without allocating.
+    pub fn display_rows(&self) -> impl Iterator<Item = [bool; DISPLAY_WIDTH]> + '_ {
+        (0..DISPLAY_HEIGHT).map(|y| self.cpu.display[0].unpack_row(y))
+    }
+
+    /// Coordinates and states of the pixels of the display, row by row.
+    pub fn display_pixels(&self) -> impl Iterator<Item = (usize, usize, bool)> + '_ {
+        self.display_rows().enumerate().flat_map(|(y, row)| {
+            row.into_iter()
+                .enumerate()
+                .map(move |(x, pixel)| (x, y, pixel))
+        })
+    }
+
+    /// Copy of the display, packed as a `u64` per row with
+    /// the leftmost pixel in the most significant bit.
+    pub fn to_packed_u64_rows(&self) -> [u64; DISPLAY_HEIGHT] {
+        *self.cpu.display[0].rows()
+    }
+
+    /// Display buffer of the given XO-CHIP bitplane.
+    ///
+    /// Plane 0 is the same buffer returned by [`Chip8Vm::display_buffer`].
+    pub fn plane_buffer(&self, plane: usize) -> Option<Chip8DisplayBuffer<'_>> {
+        self.cpu.display.get(plane)
+    }
+
+    /// Render the display into an RGBA image, with every pixel
+    /// scaled into a square of `scale` pixels.
+    ///
+    /// The bitplanes are combined using [`DISPLAY_PALETTE`](crate::DISPLAY_PALETTE).
+    pub fn display_to_image(&self, scale: usize) -> DisplayImage {
+        let [plane0, plane1] = &*self.cpu.display;
+        DisplayImage::new(scale, |x, y| {
+            plane0.get(x, y) as usize | (plane1.get(x, y) as usize) << 1
+        })
+    }
+
+    /// Execution counts, or `None` when profiling isn't enabled in the configuration.
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    pub fn profiler_mut(&mut self) -> Option<&mut Profiler> {
+        self.profiler.as_mut()
+    }
+
+    /// Report of the most executed instructions, annotated with their disassembly.
+    pub fn profile_report(&self, limit: usize) -> Option<String> {
+        self.profiler
+            .as_ref()
+            .map(|profiler| profiler.report(Some(&self.cpu.ram[..]), limit))
+    }
+
+    /// State of the buzzer and the XO-CHIP audio pattern, for rendering
+    /// samples with a [`Synth`](crate::Synth).
+    pub fn audio_state(&self) -> AudioState {
+        AudioState {
+            buzzer: self.cpu.buzzer_state,
+            pattern: self
+                .cpu
+                .audio_pattern_loaded
+                .then_some(self.cpu.audio_pattern),
+            pitch: self.cpu.pitch,
+        }
+    }
+
+    /// The XO-CHIP audio pattern buffer, loaded by `F002` (AUDIO).
+    pub fn audio_pattern(&self) -> &[u8; AUDIO_PATTERN_SIZE] {
+        &self.cpu.audio_pattern
     }
 }
 
@@ -113,45 +473,317 @@ pub enum Flow {
     /// This is triggered by the opcode `Fx0A` (`LD Vx, K`), which stops
     /// execution until a key is pressed, and loads the key value into `Vx`.
     KeyWait,
+    /// Program counter has reached a breakpoint set with the [`Debugger`].
+    Breakpoint,
+}
+
+/// Summary of a frame executed by [`Chip8Vm::run_frame`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FrameReport {
+    /// Number of instructions executed during the frame.
+    pub instructions: usize,
+    /// The display was drawn to, and should be presented.
+    pub draw: bool,
+    /// The buzzer is on at the end of the frame.
+    pub sound: bool,
+    /// Execution is stalled by `Fx0A` (`LD Vx, K`) until a key is released.
+    pub key_wait: bool,
+    /// The interrupt signal is set, and the program won't advance.
+    pub interrupt: bool,
+    /// The interrupt was raised by the watchdog, see [`Chip8Conf::watchdog_frames`].
+    pub watchdog: bool,
 }
 
 /// VM Configuration Parameters.
+///
+/// With the `config` feature, it can be loaded from a TOML or YAML
+/// file with [`Chip8Conf::from_file`].
 #[derive(Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
 pub struct Chip8Conf {
     pub clock_frequency: Option<Hz>,
+    /// Compatibility toggles for instruction semantics.
+    pub quirks: Quirks,
+    /// Interpreter whose instruction set is executed, see [`Chip8Conf::with_variant`].
+    /// Default: `None`, which executes the instructions of every variant.
+    pub variant: Option<Variant>,
+    /// Recording of past states for rewinding.
+    pub rewind: RewindConf,
+    /// Count the executions of every instruction, see [`Chip8Vm::profiler`].
+    pub profile: bool,
+    /// Seed of the random number generator.
+    /// Default: `None`, which picks a random seed.
+    pub seed: Option<u64>,
+    /// Maximum depth of nested subroutine calls.
+    /// Calling deeper stops the VM with a "call stack overflow" error.
+    /// Default: `None`, which is [`DEFAULT_STACK_DEPTH`].
+    ///
+    /// Limited to `STACK_SIZE - 1`.
+    pub stack_size: Option<usize>,
+    /// Handling of programs that write to memory they have executed.
+    pub self_modify: SelfModifyPolicy,
+    /// Protection of the font and the memory below the program.
+    /// Default: no protection.
+    pub memory_protection: MemoryProtection,
+    /// Address the program is loaded at, and starts executing from.
+    /// Default: `None`, which is [`MEM_START`]. ETI-660 programs load at [`ETI660_START`].
+    pub load_address: Option<usize>,
+    /// Look up loaded programs in the database of well-known ROMs, and
+    /// apply their recommended quirks and clock frequency.
+    /// Default: `false`
+    pub use_rom_db: bool,
+    /// Upper limit of instructions executed by [`Chip8Vm::run_frame`], so
+    /// high clock frequencies can't stall the frontend.
+    /// Default: `None`, which executes as many as the clock frequency asks for.
+    pub max_instructions_per_frame: Option<usize>,
+    /// Number of consecutive frames a program may run without drawing or
+    /// waiting for a key, before [`Chip8Vm::run_frame`] interrupts it.
+    /// Default: `None`, which disables the watchdog.
+    pub watchdog_frames: Option<u64>,
+    /// Keep the handlers of executed instructions by address, instead of
+    /// looking them up in the dispatch table every time. Writes to memory
+    /// through the program or a [`MemoryView`] invalidate the entries they overlap.
+    /// Default: `false`
+    pub decode_cache: bool,
+    /// Sound of the buzzer and playback of XO-CHIP audio patterns,
+    /// rendered by frontends with a [`Synth`](crate::Synth).
+    pub audio: AudioConf,
+    /// Handling of held keys, and of keys held down together.
+    /// Default: every key transition is reported as it arrives.
+    pub keys: KeyPolicy,
 }
 
-/// CPU clock frequency, in hertz (per second)
+impl Chip8Conf {
+    /// Configuration for programs written for the variant,
+    /// with its instruction set and quirks.
+    pub fn with_variant(variant: Variant) -> Self {
+        Self {
+            quirks: variant.quirks(),
+            variant: Some(variant),
+            ..Default::default()
+        }
+    }
+
+    /// Maximum depth of nested subroutine calls, with the default applied.
+    pub fn stack_depth(&self) -> usize {
+        self.stack_size
+            .unwrap_or(DEFAULT_STACK_DEPTH)
+            .min(STACK_SIZE - 1)
+    }
+
+    /// Address the program is loaded at, with the default applied.
+    pub fn load_address(&self) -> usize {
+        self.load_address.unwrap_or(MEM_START)
+    }
+}
+
+/// Handling of self-modifying code.
+///
+/// Memory is marked as code when it's executed. Writes by `Fx33 (LD B, Vx)`
+/// and `Fx55 (LD [I], Vx)` into code are detected.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum SelfModifyPolicy {
+    /// Writes to code are allowed silently.
+    #[default]
+    Allow,
+    /// Writes to code are allowed, and reported to the callback set with
+    /// [`Chip8Vm::on_self_modify`], or logged as a warning without one.
+    Warn,
+    /// Writes to code stop the VM with an error.
+    Trap,
+}
+
+type SelfModifyCallback = Box<dyn FnMut(&SelfModify)>;
+
+type MemoryListener = Box<dyn FnMut(&MemoryChange)>;
+
+type TimerHook = Box<dyn FnMut(TimerEvent)>;
+
+/// Timer transition reported to the hook set with [`Chip8Vm::set_timer_hook`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerEvent {
+    /// The delay timer counted down to zero.
+    DelayExpired,
+    /// The sound timer was set, and the buzzer turned on.
+    SoundOn,
+    /// The sound timer reached zero, and the buzzer turned off.
+    SoundOff,
+}
+
+/// Write by the program into memory that was executed as code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelfModify {
+    /// Address of the instruction that wrote to memory.
+    pub pc: Address,
+    /// Start of the written memory.
+    pub address: Address,
+    /// Number of bytes written.
+    pub len: usize,
+}
+
+/// Rewind configuration.
+///
+/// Snapshots of the machine state are recorded periodically into a bounded
+/// ring buffer. When the buffer is full, the oldest snapshot is discarded.
 #[derive(Debug, Default, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct RewindConf {
+    /// Maximum number of snapshots kept in memory.
+    /// Default: `0`, which disables rewinding.
+    pub capacity: usize,
+    /// Number of executed instructions between snapshots.
+    /// Default: `0`, which is treated as `1`.
+    pub interval: usize,
+}
+
+/// Bounded ring buffer of past machine states.
+struct History {
+    conf: RewindConf,
+    snapshots: VecDeque<Vec<u8>>,
+    /// Instructions executed since the last snapshot.
+    counter: usize,
+}
+
+impl History {
+    fn new(conf: RewindConf) -> Self {
+        Self {
+            conf,
+            snapshots: VecDeque::with_capacity(conf.capacity),
+            counter: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.snapshots.clear();
+        self.counter = 0;
+    }
+
+    /// Count an executed instruction, and return whether a snapshot is due.
+    #[inline]
+    fn tick(&mut self) -> bool {
+        if self.conf.capacity == 0 {
+            return false;
+        }
+
+        let due = self.counter == 0;
+        self.counter += 1;
+        if self.counter >= self.conf.interval.max(1) {
+            self.counter = 0;
+        }
+        due
+    }
+
+    fn push(&mut self, snapshot: Vec<u8>) {
+        if self.snapshots.len() >= self.conf.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+    }
+}
+
+/// CPU clock frequency, in hertz (per second)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 pub struct Hz(pub u64);
 
 impl From<Hz> for Duration {
     fn from(freq: Hz) -> Self {
-        if freq.0 == 0 {
-            Duration::from_nanos(0)
-        } else {
-            Duration::from_nanos(NANOS_IN_SECOND / freq.0)
-        }
+        // A frequency of zero means the clock is disabled.
+        Duration::from_nanos(NANOS_IN_SECOND.checked_div(freq.0).unwrap_or(0))
     }
 }
 
+/// Instruction event, enabled with the `chip8::instruction=trace` filter.
 macro_rules! trace_op {
     ($($arg:tt)*) => {
-        if cfg!(feature="trace_opcodes") {
-            log::trace!($($arg)*);
-        }
+        tracing::trace!(target: "chip8::instruction", $($arg)*)
     };
 }
 
+mod ops;
+
 /// Interpreter
 impl Chip8Vm {
     /// Sets the keyboard key input state.
     ///
-    /// If the VM is waiting for keyboard input, the `key_wait` flag will
-    /// be cleared, so it can be resumed.
+    /// See [`Chip8Vm::push_key_event`]
     pub fn set_key(&mut self, key: KeyCode, pressed: bool) {
-        self.cpu.set_key_state(key as u8, pressed);
-        self.cpu.key_wait = false;
+        let state = if pressed {
+            KeyState::Pressed
+        } else {
+            KeyState::Released
+        };
+        self.push_key_event(key, state);
+    }
+
+    /// Update the keyboard state with a key transition.
+    ///
+    /// The transition is queued for `Fx0A` (LD Vx, K), which waits for a key
+    /// to be pressed and released. Events that don't change the state of the
+    /// key are ignored.
+    ///
+    /// With [`KeyPolicy::suppress_repeat`], releases take effect at the end of
+    /// the frame, unless the key is pressed again before.
+    pub fn push_key_event(&mut self, key: KeyCode, state: KeyState) {
+        let pressed = state == KeyState::Pressed;
+        let mask = 1 << key.as_u8();
+        let pending = self.cpu.key_pending_release & mask != 0;
+
+        if pending {
+            // Pressing the key again cancels its pending release.
+            if !pressed {
+                return;
+            }
+            self.cpu.key_pending_release &= !mask;
+        } else if self.cpu.key_state(key.as_u8()) == pressed {
+            return;
+        } else if self.conf.keys.suppress_repeat && !pressed {
+            self.cpu.key_pending_release |= mask;
+        } else {
+            self.cpu.set_key_state(key.as_u8(), pressed);
+            self.cpu.push_key_event(KeyEvent { key, state });
+        }
+
+        if let Some(replay) = self.recording.as_mut() {
+            replay.events.push(ReplayEvent {
+                frame: self.frame,
+                key,
+                state,
+            });
+        }
+    }
+
+    /// Update the state of the mouse, readable by the program at
+    /// [`MOUSE_START`] when the [`Quirks::mouse`] extension is enabled.
+    ///
+    /// Without the extension, the mouse is ignored and memory is left untouched.
+    /// The update is reported to memory listeners like a write through a [`MemoryView`].
+    pub fn set_mouse(&mut self, mouse: Mouse) {
+        if self.conf.quirks.mouse {
+            self.cpu.ram[MOUSE_START..MOUSE_START + MOUSE_SIZE].copy_from_slice(&mouse.to_bytes());
+            self.notify_memory_change(MemoryChange {
+                address: MOUSE_START as Address,
+                len: MOUSE_SIZE,
+                origin: ChangeOrigin::View,
+            });
+        }
     }
 
     /// Clear the keyboard input state, setting all keys to up.
@@ -166,7 +798,7 @@ impl Chip8Vm {
 
     /// Clear internal state in preparation for a fresh startup.
     fn reset(&mut self) {
-        self.loop_counter = 0;
+        self.idle_frames = 0;
         self.clock.reset();
         self.timer.reset();
     }
@@ -206,561 +838,689 @@ impl Chip8Vm {
     }
 
     fn resume(&mut self) -> Flow {
+        self.clear_interrupt();
+        self.step()
+    }
+
+    /// Clear the interrupt and runtime error, so execution can continue.
+    fn clear_interrupt(&mut self) {
         self.cpu.trap = false;
         self.cpu.error = None;
-        self.step()
     }
 
     pub fn tick(&mut self) -> Result<Flow, Chip8Error> {
         match self.step() {
-            Flow::Error => self
-                .cpu
-                .error
-                .map(Chip8Error::Runtime)
-                .map(Result::Err)
-                .unwrap_or_else(|| Err(Chip8Error::Runtime("unspecified VM error"))),
+            Flow::Error => Err(self.runtime_error()),
             flow => Ok(flow),
         }
     }
 
-    #[inline]
-    fn step(&mut self) -> Flow {
-        let mut rng = thread_rng();
-
-        let mut control_flow = Flow::Ok;
-
-        /*loop*/
-        {
-            if self.cpu.trap {
-                // Interrupt signal is set.
-                return Flow::Interrupt;
+    /// Execute instructions until the next 60Hz display tick.
+    ///
+    /// The number of instructions in a frame is derived from the configured
+    /// clock frequency. The timers are counted down once at the end of the
+    /// frame, instead of in real time, so the caller is responsible for pacing
+    /// frames, for example against vsync.
+    ///
+    /// The frame ends early when the program waits for a key, or is interrupted.
+    /// With [`Chip8Conf::watchdog_frames`] configured, programs that stop
+    /// drawing and waiting for keys for too long are interrupted.
+    pub fn run_frame(&mut self) -> Chip8Result<FrameReport> {
+        let _span =
+            tracing::trace_span!(target: "chip8::frame", "frame", number = self.frame).entered();
+        let mut report = FrameReport::default();
+
+        for _ in 0..self.instructions_per_frame() {
+            match self.exec() {
+                Flow::Error => return Err(self.runtime_error()),
+                Flow::Interrupt => {
+                    report.interrupt = true;
+                    break;
+                }
+                // The instruction didn't complete, and will be retried next frame.
+                Flow::KeyWait => {
+                    report.key_wait = true;
+                    break;
+                }
+                Flow::Draw => {
+                    report.instructions += 1;
+                    report.draw = true;
+                }
+                _ => report.instructions += 1,
             }
+        }
 
-            #[cfg(feature = "throttle")]
-            self.clock.wait();
-
-            // Count down timers
-            if self.timer.tick() {
-                self.cpu.tick_sound();
-                self.cpu.tick_delay();
+        self.finish_frame(&mut report);
+        Ok(report)
+    }
 
-                // Buzzer should be on while sound timer counts down,
-                // then turned off when the timer reaches zero.
-                if self.cpu.sound_timer > 0 && !self.cpu.buzzer_state {
-                    self.cpu.buzzer_state = true;
-                    // self.devices.buzz(true);
-                } else if self.cpu.sound_timer == 0 && self.cpu.buzzer_state {
-                    self.cpu.buzzer_state = false;
-                    // self.deviecs.buzz(false);
-                }
+    /// Count down the timers and end the frame, after its instructions were
+    /// executed, updating the report with the state of the buzzer and the watchdog.
+    ///
+    /// Called by [`Chip8Vm::run_frame`], and by backends that execute the
+    /// instructions of a frame themselves.
+    pub fn finish_frame(&mut self, report: &mut FrameReport) {
+        self.tick_timers();
+        self.release_pending_keys();
+        report.sound = self.cpu.buzzer_state;
+
+        if report.draw || report.key_wait {
+            self.idle_frames = 0;
+        } else if !report.interrupt {
+            self.idle_frames += 1;
+            if self
+                .conf
+                .watchdog_frames
+                .is_some_and(|limit| self.idle_frames >= limit)
+            {
+                tracing::warn!(
+                    "watchdog: program at 0x{:04X} ran {} frames without drawing or waiting for a key",
+                    self.cpu.pc,
+                    self.idle_frames
+                );
+                self.cpu.interrupt();
+                report.interrupt = true;
+                report.watchdog = true;
             }
+        }
 
-            // Each instruction is two bytes, with the opcode identity in the first 4-bit nibble.
-            let code = self.cpu.op_code();
+        self.frame += 1;
+        if let Some(replay) = self.recording.as_mut() {
+            replay.frames = self.frame;
+        }
+    }
 
-            let [a, b] = self.cpu.instr();
-            let op = a >> 4; // 0xF000
-            let vx = a & 0xF; // 0x0F00
-            let vy = b >> 4; // 0x00F0
-            let n = b & 0xF; // 0x000F
-            let nn = b; // 0x00FF
-            let nnn = (((a as u16) & 0xF) << 8) | b as u16; // 0x0FFF
+    /// Number of frames executed by [`Chip8Vm::run_frame`].
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
 
-            self.cpu.pc += 2;
+    /// Seed of the random number generator, as it was last seeded.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
 
-            match code {
-                // Miscellaneous instructions identified by nn
-                0x0 | 0xE | 0xF => control_flow = self.exec_misc(op, vx, nn),
-                // 1nnn (JP addr)
-                //
-                // Jump to address.
-                0x1 => {
-                    trace_op!("0x{:04X}  JP    0x{nnn:03X}", self.cpu.pc);
+    /// Restart the random number generator with the given seed.
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng.reseed(seed);
+    }
 
-                    self.cpu.pc = nnn as usize;
+    /// Start recording the key events of the session.
+    ///
+    /// The random number generator is reseeded and the frame count restarted,
+    /// so the recording should start right after the program is loaded.
+    /// Any recording in progress is discarded.
+    pub fn start_recording(&mut self) {
+        self.reseed(self.seed);
+        self.frame = 0;
+        self.recording = Some(Replay::new(self.seed));
+    }
 
-                    control_flow = Flow::Jump;
-                }
-                // 2nnn (CALL addr)
-                //
-                // Call subroutine at NNN.
-                0x2 => {
-                    trace_op!("0x{:04X}  CALL  0x{nnn:03X}", self.cpu.pc);
+    /// Stop recording, and return the recorded session.
+    pub fn stop_recording(&mut self) -> Option<Replay> {
+        self.recording.take()
+    }
 
-                    self.cpu.sp += 1;
-                    self.cpu.stack[self.cpu.sp] = self.cpu.pc as u16;
-                    self.cpu.pc = nnn as usize;
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
 
-                    control_flow = Flow::Jump;
-                }
-                // 3xnn (SE Vx, byte)
-                //
-                // Skip the next instruction if register VX equals value NN.
-                0x3 => {
-                    trace_op!("0x{:04X}  SE    v{vx:x},  0x{nn:02X}", self.cpu.pc);
-
-                    if self.cpu.registers[vx as usize] == nn {
-                        self.cpu.pc += 2;
-                    }
-                }
-                // 4xnn (SNE Vx, byte)
-                //
-                // Skip the next instruction if register VX does not equal value NN.
-                0x4 => {
-                    trace_op!("0x{:04X}  SNE   v{vx:x},  0x{nn:02X}", self.cpu.pc);
-
-                    if self.cpu.registers[vx as usize] != nn {
-                        self.cpu.pc += 2;
-                    }
-                }
-                // 5xy0 (SE Vx, Vy)
-                //
-                // Skip the next instruction if register VX equals value VY.
-                0x5 => {
-                    trace_op!("0x{:04X}  SE    v{vx:x},  v{vy:x}", self.cpu.pc);
-
-                    let x = self.cpu.registers[vx as usize];
-                    let y = self.cpu.registers[vy as usize];
-                    if x == y {
-                        self.cpu.pc += 2;
-                    }
-                }
-                // 6xnn (LD Vx, byte)
-                //
-                // Set register VX to value NN.
-                0x6 => {
-                    trace_op!("0x{:04X}  LD    v{vx:x},  0x{nn:02X}", self.cpu.pc);
+    /// Play back a recorded session, executing frames until the end of the replay.
+    ///
+    /// The program must be freshly loaded, in the same state as when the
+    /// recording was started. Playback stops early when the program is interrupted.
+    pub fn run_with_replay(&mut self, replay: &Replay) -> Chip8Result<()> {
+        self.reseed(replay.seed);
+        self.clear_keys();
+        self.frame = 0;
+
+        while self.frame < replay.frames {
+            for event in replay.events_at(self.frame) {
+                self.push_key_event(event.key, event.state);
+            }
 
-                    self.cpu.registers[vx as usize] = nn;
-                }
-                // 7xnn (ADD Vx, byte)
-                //
-                // Add value NN to register VX. Carry flag is not set.
-                0x7 => {
-                    trace_op!("0x{:04X}  ADD   v{vx:x},  0x{nn:02X}", self.cpu.pc);
-
-                    let x = self.cpu.registers[vx as usize];
-                    self.cpu.registers[vx as usize] = x.wrapping_add(nn);
-                }
-                // Arithmetic instructions indentified by n
-                0x8 => control_flow = self.exec_math(op, vx, vy, n),
-                // 9xy0 (SNE Vx, Vy)
-                //
-                // Skip next instruction if Vx != Vy.
-                // The values of Vx and Vy are compared, and if they are not equal, the program counter is increased by 2.
-                0x9 => {
-                    trace_op!("0x{:04X}  SNE   v{vx:x},  v{vy:x}", self.cpu.pc);
-
-                    let x = self.cpu.registers[vx as usize];
-                    let y = self.cpu.registers[vy as usize];
-                    if x != y {
-                        self.cpu.pc += 2;
-                    }
-                }
-                // Annn (LD I, addr)
-                //
-                // Set address register I to value NNN.
-                0xA => {
-                    trace_op!("0x{:04X}  LD    I,   0x{nnn:03X}", self.cpu.pc);
+            if self.run_frame()?.interrupt {
+                break;
+            }
+        }
 
-                    self.cpu.address = nnn;
-                }
-                // Bnnn (JP V0, addr)
-                //
-                // Jump to location nnn + V0.
-                0xB => {
-                    trace_op!("0x{:04X}  JP    v0,  0x{nnn:03X}", self.cpu.pc);
+        Ok(())
+    }
 
-                    self.cpu.pc = nnn as usize + self.cpu.registers[0] as usize;
-                }
-                // CXNN (RND Vx, byte)
-                //
-                // Generate random number.
-                // Set register VX to the result of bitwise AND between a random number and NN.
-                0xC => {
-                    trace_op!("0x{:04X}  RND   v{vx:x},  0x{nn:02X}", self.cpu.pc);
-
-                    self.cpu.registers[vx as usize] = nn & rng.gen::<u8>();
-                }
-                // Dxyn (DRW Vx, Vy, nibble)
-                //
-                // Draw sprite to the display buffer, at coordinate as per registers Vx and Vy.
-                // Sprite is encoded as 8 pixels wide, N pixels high, stored in bits located in
-                // memory pointed to by address register I.
-                //
-                // If the sprite is drawn outside the display area, it is wrapped around to the other side.
-                //
-                // If the drawing operation erases existing pixels in the display buffer, register VF is set to
-                // 1, and set to 0 if no display bits are unset. This is used for collision detection.
-                0xD => {
-                    trace_op!("0x{:04X}  DRAW  v{vx:x},  v{vy:x}", self.cpu.pc);
-
-                    let (x, y) = (
-                        self.cpu.registers[vx as usize] as usize,
-                        self.cpu.registers[vy as usize] as usize,
-                    );
-                    let mut is_erased = false;
-
-                    // Iteration from pointer in address register I to number of rows specified by opcode value N.
-                    self.cpu
-                        .ram
-                        .iter()
-                        .skip(self.cpu.address as usize)
-                        .take(n as usize)
-                        .enumerate()
-                        .for_each(|(r, row)| {
-                            // Each row is 8 bits representing the 8 pixels of the sprite.
-                            for c in 0..8 {
-                                let d = (((x + c) & DISPLAY_WIDTH_MASK)
-                                    + ((y + r) & DISPLAY_HEIGHT_MASK) * DISPLAY_WIDTH)
-                                    & (MEM_SIZE - 1);
-
-                                let old_px = self.cpu.display[d];
-                                let new_px = (row >> (7 - c) & 1) != 0;
-
-                                // XOR erases a pixel when both the old and new values are both 1.
-                                is_erased |= old_px && new_px;
-
-                                // Write to display buffer
-                                self.cpu.display[d] = old_px ^ new_px;
-                            }
-                        });
-
-                    // If a pixel was erased, then a collision occurred.
-                    self.cpu.registers[0xF] = is_erased as u8;
-                    control_flow = Flow::Draw;
-                }
-                // Unsupported operation.
-                _ => {
-                    trace_op!("0x{:04X}  UNKNOWN 0x{a:02X}{b:02X}", self.cpu.pc);
-                    self.cpu.set_error("unsupported opcode");
-                    control_flow = Flow::Error;
-                }
-            }
+    /// Number of instructions executed by [`Chip8Vm::run_frame`].
+    ///
+    /// Limited by [`Chip8Conf::max_instructions_per_frame`].
+    pub fn instructions_per_frame(&self) -> usize {
+        let count = match self.conf.clock_frequency {
+            Some(Hz(freq)) if freq >= DELAY_FREQUENCY => (freq / DELAY_FREQUENCY) as usize,
+            _ => DEFAULT_INSTRUCTIONS_PER_FRAME,
+        };
+        match self.conf.max_instructions_per_frame {
+            Some(max) => count.min(max),
+            None => count,
         }
+    }
 
-        control_flow
+    /// Convert the CPU's error state into an error result.
+    fn runtime_error(&self) -> Chip8Error {
+        let err = self.cpu.error.unwrap_or(RuntimeError {
+            pc: self.cpu.pc,
+            opcode: u16::from_be_bytes(self.cpu.instr()),
+            message: "unspecified VM error",
+            sp: self.cpu.sp,
+            address: self.cpu.address,
+            registers: self.cpu.registers,
+        });
+        Chip8Error::Runtime(err)
     }
 
-    /// Execute an arithmetic instruction
+    /// Execute the next instruction, counting down the timers in real time.
     #[inline]
-    #[must_use]
-    fn exec_math(&mut self, op: u8, vx: u8, vy: u8, n: u8) -> Flow {
-        debug_assert_eq!(op, 0x8);
-        assert!((vx as usize) < self.cpu.registers.len());
-        assert!((vy as usize) < self.cpu.registers.len());
-
-        let mut control_flow = Flow::Ok;
-
-        match n {
-            // 8xy0 (LD Vx, Vy)
-            //
-            // Store the value of register VY in register VX.
-            0x0 => {
-                trace_op!("0x{:04X}  LD    v{vx:x},  v{vy:x}", self.cpu.pc);
-
-                self.cpu.registers[vx as usize] = self.cpu.registers[vy as usize];
-            }
-            // 8xy1 (OR Vx, Vy)
-            //
-            // Performs bitwise OR on VX and VY, and stores the result in VX.
-            0x1 => {
-                trace_op!("0x{:04X}  OR    v{vx:x},  v{vy:x}", self.cpu.pc);
-
-                self.cpu.registers[vx as usize] |= self.cpu.registers[vy as usize];
-            }
-            // 8xy2 (AND Vx, Vy)
-            //
-            // Performs bitwise AND on VX and VY, and stores the result in VX.
-            0x2 => {
-                trace_op!("0x{:04X}  AND   v{vx:x},  v{vy:x}", self.cpu.pc);
-
-                self.cpu.registers[vx as usize] &= self.cpu.registers[vy as usize];
-            }
-            // 8xy3 (XOR Vx, Vy)
-            //
-            // Performs bitwise XOR on VX and VY, and stores the result in VX.
-            0x3 => {
-                trace_op!("0x{:04X}  XOR   v{vx:x},  v{vy:x}", self.cpu.pc);
-
-                self.cpu.registers[vx as usize] ^= self.cpu.registers[vy as usize];
-            }
-            // 8xy4 (ADD Vx, Vy)
-            //
-            // ADDs VX to VY, and stores the result in VX.
-            // Overflow is wrapped.
-            // If overflow, set VF to 1, else 0.
-            0x4 => {
-                trace_op!("0x{:04X}  ADD   v{vx:x},  v{vy:x}", self.cpu.pc);
-
-                let (x, y) = (
-                    self.cpu.registers[vx as usize],
-                    self.cpu.registers[vy as usize],
-                );
-                let result = x as usize + y as usize;
-                self.cpu.registers[vx as usize] = (result & 0xFF) as u8; // Overflow wrap
-                self.cpu.registers[0xF] = if result > 0x255 { 1 } else { 0 };
-            }
-            // 8xy5 (SUB Vx, Vy)
-            //
-            // Subtracts VY from VX, and stores the result in VX.
-            // VF is set to 0 when there is a borrow, set to 1 when there isn't.
-            0x5 => {
-                trace_op!("0x{:04X}  SUB   v{vx:x},  v{vy:x}", self.cpu.pc);
-
-                let (x, y) = (
-                    self.cpu.registers[vx as usize],
-                    self.cpu.registers[vy as usize],
-                );
-                let result = x as isize - y as isize;
-                self.cpu.registers[vx as usize] = (result & 0xF) as u8; // Overflow wrap
-                self.cpu.registers[0xF] = if y > x { 0 } else { 1 };
-            }
-            // 8xy6 (SHR Vx)
-            //
-            // If the least-significant bit of Vx is 1, then VF is set to 1, otherwise 0.
-            // Shift VX right by 1.
-            // VY is unused.
-            0x6 => {
-                trace_op!("0x{:04X}  SHR   v{vx:x},  v{vy:x}", self.cpu.pc);
-
-                let x = self.cpu.registers[vx as usize];
-                self.cpu.registers[0xF] = x & 1;
-                self.cpu.registers[vx as usize] = x >> 1;
-            }
-            // 8xy7 (SUBN Vx, Vy)
-            //
-            // Subtracts VX from VY, and stores the result in VX.
-            // VF is set to 0 when there is a borrow, set to 1 when there isn't.
-            0x7 => {
-                trace_op!("0x{:04X}  SUBN  v{vx:x},  v{vy:x}", self.cpu.pc);
-
-                let (x, y) = (
-                    self.cpu.registers[vx as usize],
-                    self.cpu.registers[vy as usize],
-                );
-                let result = y as isize - x as isize;
-                self.cpu.registers[vx as usize] = (result & 0xF) as u8; // Overflow wrap
-                self.cpu.registers[0xF] = if x > y { 0 } else { 1 };
-            }
-            // 8xyE (SHL Vx)
-            //
-            // If the least-significant bit of Vx is 1, then VF is set to 1, otherwise 0.
-            // Shift VX left by 1.
-            // VY is unused.
-            0xE => {
-                trace_op!("0x{:04X}  SHL   v{vx:x},  v{vy:x}", self.cpu.pc);
-
-                let x = self.cpu.registers[vx as usize];
-                self.cpu.registers[0xF] = (x >> 7) & 1;
-                self.cpu.registers[vx as usize] = x << 1;
-            }
-            // ----------------------------------------------------------------
-            // Unsupported operation.
-            _ => {
-                trace_op!(
-                    "0x{:04X}  UNKNOWN MATH 0x{op:X} v{vx:x}, v{vy:x}, 0x{n:X}",
-                    self.cpu.pc
-                );
-                self.cpu.set_error("unsupported math opcode");
-                control_flow = Flow::Error;
-            }
+    fn step(&mut self) -> Flow {
+        #[cfg(feature = "throttle")]
+        self.clock.wait();
+
+        // Count down timers
+        if self.timer.tick() {
+            self.tick_timers();
+            self.release_pending_keys();
         }
 
-        control_flow
+        self.exec()
     }
 
-    /// Execute a miscellaneous instruction
-    #[inline]
-    #[must_use]
-    fn exec_misc(&mut self, op: u8, vx: u8, nn: u8) -> Flow {
-        assert!((vx as usize) < self.cpu.registers.len());
-
-        let mut control_flow = Flow::Ok;
-
-        match nn {
-            0x0 => { /* No Op */ }
-            // ----------------------------------------------------------------
-            // 00E0 (CLS)
-            //
-            // Clear display
-            0xE0 => {
-                trace_op!("0x{:04X}  CLS", self.cpu.pc);
-                debug_assert_eq!(op, 0x0);
-
-                self.cpu.clear_display();
-            }
-            // 00EE (RET)
-            //
-            // Return from a subroutine.
-            // Set the program counter to the value at the top of the stack.
-            // Subtract 1 from the stack pointer.
-            0xEE => {
-                trace_op!("0x{:04X}  RET", self.cpu.pc);
-                debug_assert_eq!(op, 0x0);
-
-                self.cpu.pc = self.cpu.stack[self.cpu.sp] as usize;
-                let (sp, underflow) = self.cpu.sp.overflowing_sub(1);
-
-                if underflow {
-                    self.cpu.set_error("call stack underflow");
-                    control_flow = Flow::Error;
-                } else {
-                    self.cpu.sp = sp;
-                    control_flow = Flow::Jump;
-                }
-            }
-            // ----------------------------------------------------------------
-            // Ex9E (SKP Vx)
-            0x9E => {
-                trace_op!("0x{:04X}  SKP   v{vx:x}", self.cpu.pc);
-                debug_assert_eq!(op, 0xE);
+    /// Apply the key releases held back by [`KeyPolicy::suppress_repeat`].
+    fn release_pending_keys(&mut self) {
+        let pending = std::mem::take(&mut self.cpu.key_pending_release);
+        for key_id in (0..KEY_COUNT).filter(|k| pending & (1 << k) != 0) {
+            let key = KeyCode::try_from(key_id).expect("keypad index");
+            self.cpu.set_key_state(key_id, false);
+            self.cpu.push_key_event(KeyEvent {
+                key,
+                state: KeyState::Released,
+            });
+        }
+    }
 
-                if self.cpu.key_state(self.cpu.registers[vx as usize & 0xF]) {
-                    self.cpu.pc += 2;
-                }
-            }
-            // ExA1 (SKNP Vx)
-            0xA1 => {
-                trace_op!("0x{:04X}  SKNP  v{vx:x}", self.cpu.pc);
-                debug_assert_eq!(op, 0xE);
+    /// Count down the delay and sound timers by one.
+    fn tick_timers(&mut self) {
+        let delay = self.cpu.delay_timer;
+        self.cpu.tick_sound();
+        self.cpu.tick_delay();
 
-                if !self.cpu.key_state(self.cpu.registers[vx as usize & 0xF]) {
-                    self.cpu.pc += 2;
-                }
-            }
-            // ----------------------------------------------------------------
-            // Fx07 (LD Vx, DT)
-            //
-            // Set Vx = delay timer value.
-            // The value of DT is placed into Vx.
-            0x07 => {
-                trace_op!("0x{:04X}  LD    v{vx:x},  DT", self.cpu.pc);
-                debug_assert_eq!(op, 0xF);
-
-                self.cpu.registers[vx as usize] = self.cpu.delay_timer;
-            }
-            // Fx0A (LD Vx, K)
-            //
-            // Wait for a key press, store the value of the key in Vx.
-            // All execution stops until a key is pressed, then the value of that key is stored in Vx.
-            0x0A => {
-                trace_op!("0x{:04X}  LD    v{vx:x},  K", self.cpu.pc);
-                debug_assert_eq!(op, 0xF);
-
-                if let Some(k) = self.cpu.first_key() {
-                    self.cpu.registers[vx as usize] = k;
-                    self.cpu.key_wait = false;
-                } else {
-                    // rewind the program counter to stall the machine
-                    self.cpu.pc -= 2;
-                    self.cpu.key_wait = true;
-                    control_flow = Flow::KeyWait;
-                }
-            }
-            // Fx15 (LD DT, Vx)
-            //
-            // Set delay timer = Vx.
-            // DT is set equal to the value of Vx.
-            0x15 => {
-                trace_op!("0x{:04X}  LD    DT,  v{vx:x}", self.cpu.pc);
-                debug_assert_eq!(op, 0xF);
-
-                self.cpu.delay_timer = self.cpu.registers[vx as usize];
-            }
-            // Fx18 (LD ST, Vx)
-            //
-            // Set sound timer = Vx.
-            // ST is set equal to the value of Vx.
-            0x18 => {
-                trace_op!("0x{:04X}  LD    ST,  v{vx:x}", self.cpu.pc);
-                debug_assert_eq!(op, 0xF);
-
-                let vx = self.cpu.op_x();
-                self.cpu.sound_timer = self.cpu.registers[vx as usize];
-                self.cpu.buzzer_state = self.cpu.sound_timer > 0;
-                control_flow = Flow::Sound;
-            }
-            // Fx1E (ADD I, Vx)
-            //
-            // Add Vx to I
-            0x1E => {
-                trace_op!("0x{:04X}  LD    I,  v{vx:x}", self.cpu.pc);
-                debug_assert_eq!(op, 0xF);
-
-                let addr = self.cpu.address;
-                let x = self.cpu.registers[vx as usize & 0xF] as u16;
-                self.cpu.address = addr.wrapping_add(x);
-            }
-            // Fx29 (LD F, Vx)
-            //
-            // Set I = location of sprite for digit Vx.
-            0x29 => {
-                trace_op!("0x{:04X}  LD    F,  v{vx:x}", self.cpu.pc);
-                debug_assert_eq!(op, 0xF);
-
-                let x = self.cpu.registers[vx as usize];
-                self.cpu.address = FONTSET_START + (x as u16) * FONTSET_HEIGHT as u16;
-            }
-            // Fx33 (LD B, Vx)
-            //
-            // Store the binary-coded decimal representation of Vx
-            // in the memory locations I, I+1, and I+2.
-            #[rustfmt::skip]
-            0x33 => {
-                trace_op!("0x{:04X}  LD    BCD,  v{vx:x}", self.cpu.pc);
-                debug_assert_eq!(op, 0xF);
-
-                let addr = self.cpu.address as usize;
-                let x = self.cpu.registers[vx as usize];
-                self.cpu.ram[addr + 2] = x       % 10;
-                self.cpu.ram[addr + 1] = x / 10  % 10;
-                self.cpu.ram[addr]     = x / 100 % 10;
-            }
-            // Fx55 (LD [I], Vx)
-            //
-            // Store registers V0 through Vx in memory starting at location I.
-            0x55 => {
-                trace_op!("0x{:04X}  LD    [I],  v{vx:x}", self.cpu.pc);
-                debug_assert_eq!(op, 0xF);
-
-                let addr = self.cpu.address as usize;
-                self.cpu.registers[0..=vx as usize]
-                    .iter_mut()
-                    .enumerate()
-                    .for_each(|(v, x)| {
-                        self.cpu.ram[addr + v] = *x;
-                    });
+        if delay == 1 {
+            self.fire_timer(TimerEvent::DelayExpired);
+        }
+
+        // Buzzer should be on while sound timer counts down,
+        // then turned off when the timer reaches zero.
+        self.set_buzzer(self.cpu.sound_timer > 0);
+    }
+
+    /// Turn the buzzer on or off, notifying the observers of changes.
+    fn set_buzzer(&mut self, on: bool) {
+        if self.cpu.buzzer_state != on {
+            self.cpu.buzzer_state = on;
+            self.observe(|observer| observer.on_sound(on));
+            self.fire_timer(if on {
+                TimerEvent::SoundOn
+            } else {
+                TimerEvent::SoundOff
+            });
+        }
+    }
+
+    fn fire_timer(&mut self, event: TimerEvent) {
+        if let Some(hook) = self.on_timer.as_mut() {
+            hook(event);
+        }
+    }
+
+    /// Execute the instruction at the program counter.
+    #[inline]
+    fn exec(&mut self) -> Flow {
+        let control_flow;
+
+        /*loop*/
+        {
+            if self.cpu.trap {
+                // Interrupt signal is set.
+                return Flow::Interrupt;
+            }
+
+            // Record the state before the instruction executes. Retries of a
+            // key wait don't change the state, and would flood the history.
+            if !self.cpu.key_wait && self.history.tick() {
+                let snapshot = self.save_state();
+                self.history.push(snapshot);
+            }
+
+            // The whole instruction must be in memory.
+            if self.cpu.pc + 1 >= MEM_SIZE {
+                self.cpu.set_error("program counter out of bounds");
+                return Flow::Error;
+            }
+
+            if !self.memory_map.is_executable(self.cpu.pc) {
+                self.cpu.set_error("execute in protected memory");
+                return Flow::Error;
             }
-            // Fx65 (LD Vx, [I])
-            //
-            // Read registers V0 through Vx from memory starting at location I.
-            0x65 => {
-                trace_op!("0x{:04X}  LD    v{vx:x},  [I]", self.cpu.pc);
-                debug_assert_eq!(op, 0xF);
-
-                let addr = self.cpu.address as usize;
-                self.cpu.registers[0..=vx as usize]
-                    .iter_mut()
-                    .enumerate()
-                    .for_each(|(v, x)| {
-                        *x = self.cpu.ram[addr + v];
+
+            // Each instruction is two bytes, with the opcode identity in the first 4-bit nibble.
+            let [a, b] = self.cpu.instr();
+            let code = u16::from_be_bytes([a, b]);
+
+            if let Some(ref mut profiler) = self.profiler {
+                profiler.record(self.cpu.pc, [a, b]);
+            }
+            let pc = self.cpu.pc;
+            self.observe(|observer| observer.on_instruction(pc, code));
+
+            // Address of the current instruction, for error reporting.
+            let instr_pc = self.cpu.pc;
+            self.cpu.pc += 2;
+
+            let size = self.instr_size([a, b]);
+            self.code[instr_pc..(instr_pc + size).min(MEM_SIZE)].fill(true);
+
+            // The handler is copied out of the table, so it can borrow the VM.
+            control_flow = match self.decode(instr_pc, [a, b]) {
+                Some((operands, handler)) => handler(self, operands),
+                None => {
+                    trace_op!("0x{:04X}  UNKNOWN 0x{code:04X}", self.cpu.pc);
+                    self.cpu.set_error(if Extension::of([a, b]).is_some() {
+                        "instruction not supported by variant"
+                    } else {
+                        "unsupported opcode"
                     });
+                    Flow::Error
+                }
+            };
+
+            for peripheral in self.peripherals.iter_mut() {
+                peripheral.step();
             }
-            // ----------------------------------------------------------------
-            // Unsupported operation.
-            _ => {
-                trace_op!(
-                    "0x{:04X}  UNKNOWN MISC 0x{op:X} v{vx:x}, 0x{nn:02X}",
-                    self.cpu.pc
-                );
-                self.cpu.set_error("unsupported misc opcode");
-                control_flow = Flow::Error;
+
+            // The program counter may have moved by the time an instruction
+            // raises an error, so point the error at the faulting instruction.
+            if matches!(control_flow, Flow::Error) {
+                if let Some(ref mut err) = self.cpu.error {
+                    err.pc = instr_pc;
+                    err.opcode = code;
+                }
             }
         }
 
         control_flow
     }
+
+    /// Operands and handler of the instruction at the address,
+    /// from the decode cache when enabled.
+    #[inline]
+    fn decode(&mut self, address: usize, instr: [u8; 2]) -> Option<(Operands, OpHandler)> {
+        let handler = match self.decode_cache.as_mut() {
+            Some(cache) => match cache.get(address) {
+                Some(handler) => handler,
+                None => {
+                    let handler = self.dispatch.lookup(u16::from_be_bytes(instr))?;
+                    cache.insert(address, handler);
+                    handler
+                }
+            },
+            None => self.dispatch.lookup(u16::from_be_bytes(instr))?,
+        };
+        Some((Operands::decode(instr), handler))
+    }
+
+    /// Turn the cache of decoded instructions on or off, see [`Chip8Conf::decode_cache`].
+    ///
+    /// Execution is unaffected, so the cache can be switched while a program runs.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.conf.decode_cache = enabled;
+        if enabled != self.decode_cache.is_some() {
+            self.decode_cache = enabled.then(DecodeCache::new);
+        }
+    }
+
+    /// Forget all decoded instructions, after memory or the dispatch table changed.
+    fn clear_decode_cache(&mut self) {
+        if let Some(cache) = self.decode_cache.as_mut() {
+            cache.clear();
+        }
+    }
+
+    /// Offer the `0nnn (SYS addr)` call to the peripherals, until one handles it.
+    fn sys_peripherals(&mut self, nnn: u16) -> bool {
+        let registers = &mut self.cpu.registers;
+        self.peripherals
+            .iter_mut()
+            .any(|peripheral| peripheral.sys(nnn, registers))
+    }
+
+    /// Load the bytes of memory-mapped peripherals into memory,
+    /// before the range is read by an instruction.
+    fn read_peripherals(&mut self, address: usize, len: usize) {
+        for peripheral in self.peripherals.iter_mut() {
+            let range = peripheral.memory_range();
+            let end = range.end.min(address + len).min(MEM_SIZE);
+            for addr in range.start.max(address)..end {
+                if let Some(value) = peripheral.read(addr) {
+                    self.cpu.ram[addr] = value;
+                    if let Some(cache) = self.decode_cache.as_mut() {
+                        cache.invalidate(addr..addr + 1);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Apply the memory protection to a write of the memory range.
+    ///
+    /// Returns `false` when the range overlaps read-only memory, and the VM must stop.
+    fn check_read_only(&mut self, address: usize, len: usize) -> bool {
+        if self
+            .memory_map
+            .read_only_region(address..address + len)
+            .is_none()
+        {
+            return true;
+        }
+
+        self.cpu.set_error("write to read-only memory");
+        false
+    }
+
+    /// Apply the self-modify policy to a write of the memory range.
+    ///
+    /// Returns `false` when the write is trapped, and the VM must stop.
+    fn check_self_modify(&mut self, address: usize, len: usize) -> bool {
+        if self.conf.self_modify == SelfModifyPolicy::Allow {
+            return true;
+        }
+
+        let end = (address + len).min(MEM_SIZE);
+        if !self.code[address.min(end)..end].contains(&true) {
+            return true;
+        }
+
+        match self.conf.self_modify {
+            SelfModifyPolicy::Allow => true,
+            SelfModifyPolicy::Warn => {
+                let event = SelfModify {
+                    // The program counter has moved past the instruction.
+                    pc: (self.cpu.pc - 2) as Address,
+                    address: address as Address,
+                    len,
+                };
+                match self.on_self_modify.as_mut() {
+                    Some(callback) => callback(&event),
+                    None => tracing::warn!(
+                        "self-modifying code: 0x{:04X} wrote {} byte(s) to 0x{:04X}",
+                        event.pc,
+                        event.len,
+                        event.address
+                    ),
+                }
+                true
+            }
+            SelfModifyPolicy::Trap => {
+                self.cpu.set_error("write to code");
+                false
+            }
+        }
+    }
+
+    /// Pass a write by the program on to the peripherals and memory listeners.
+    fn memory_written(&mut self, address: usize, len: usize) {
+        self.write_peripherals(address, len);
+        self.notify_memory_change(MemoryChange {
+            address: address as Address,
+            len,
+            origin: ChangeOrigin::Program,
+        });
+    }
+
+    /// Pass the bytes stored to memory by an instruction on to memory-mapped peripherals.
+    fn write_peripherals(&mut self, address: usize, len: usize) {
+        for peripheral in self.peripherals.iter_mut() {
+            let range = peripheral.memory_range();
+            let end = range.end.min(address + len).min(MEM_SIZE);
+            for addr in range.start.max(address)..end {
+                peripheral.write(addr, self.cpu.ram[addr]);
+            }
+        }
+    }
+
+    /// Skip over the next instruction.
+    ///
+    /// The XO-CHIP long load `F000 nnnn` is four bytes wide,
+    /// so skipping it must also skip its trailing address.
+    #[inline]
+    fn skip_next(&mut self) {
+        self.cpu.pc += self.instr_size(self.cpu.instr());
+    }
+
+    /// Size of the instruction in bytes.
+    ///
+    /// `F000` is only the four byte long load when the variant supports
+    /// XO-CHIP, and an unknown two byte instruction otherwise.
+    #[inline]
+    fn instr_size(&self, instr: [u8; 2]) -> usize {
+        let long = self
+            .conf
+            .variant
+            .is_none_or(|variant| variant.supports(LD_I_LONG));
+        if instr == LD_I_LONG && long {
+            4
+        } else {
+            2
+        }
+    }
+
+    /// Value to be shifted by `8xy6 (SHR)` and `8xyE (SHL)`,
+    /// depending on the `shift_vy` quirk.
+    #[inline]
+    fn shift_operand(&self, vx: u8, vy: u8) -> u8 {
+        if self.conf.quirks.shift_vy {
+            self.cpu.registers[vy as usize]
+        } else {
+            self.cpu.registers[vx as usize]
+        }
+    }
+
+    /// Store the result of an arithmetic instruction in Vx, and the flag in VF.
+    ///
+    /// The flag is written last, so it overwrites the result when Vx is VF.
+    /// All known interpreters agree on this order.
+    #[inline]
+    fn set_result_with_flag(&mut self, vx: u8, result: u8, flag: bool) {
+        self.cpu.registers[vx as usize] = result;
+        self.cpu.registers[0xF] = flag as u8;
+    }
+}
+
+/// Debugging
+impl Chip8Vm {
+    /// Returns a debugger facade for stepping and inspecting the VM.
+    ///
+    /// Breakpoints are stored in the VM and persist between calls.
+    pub fn debugger(&mut self) -> Debugger<'_> {
+        Debugger { vm: self }
+    }
+
+    /// Returns a view for reading and editing memory.
+    pub fn memory_view(&mut self) -> MemoryView<'_> {
+        MemoryView::new(self)
+    }
+
+    pub(crate) fn ram(&self) -> &[u8; MEM_SIZE] {
+        &self.cpu.ram
+    }
+
+    pub(crate) fn ram_mut(&mut self) -> &mut [u8; MEM_SIZE] {
+        &mut self.cpu.ram
+    }
+}
+
+/// Step-level debugger for a [`Chip8Vm`].
+///
+/// Supports breakpoints on addresses, single-stepping,
+/// and inspecting or modifying the registers and memory.
+pub struct Debugger<'a> {
+    vm: &'a mut Chip8Vm,
+}
+
+impl<'a> Debugger<'a> {
+    /// Pause execution when the program counter reaches the given address.
+    pub fn add_breakpoint(&mut self, address: usize) {
+        self.vm.breakpoints.insert(address);
+    }
+
+    /// Returns `true` if a breakpoint was set at the address.
+    pub fn remove_breakpoint(&mut self, address: usize) -> bool {
+        self.vm.breakpoints.remove(&address)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.vm.breakpoints.clear();
+    }
+
+    pub fn has_breakpoint(&self, address: usize) -> bool {
+        self.vm.breakpoints.contains(&address)
+    }
+
+    /// Addresses of all breakpoints, in ascending order.
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.vm.breakpoints.iter().copied()
+    }
+
+    /// Execute a single instruction, ignoring breakpoints.
+    ///
+    /// Once the VM is interrupted, nothing is executed and [`Flow::Interrupt`]
+    /// is returned, until execution is resumed with [`Debugger::clear_interrupt`].
+    pub fn step(&mut self) -> Chip8Result<Flow> {
+        match self.vm.step() {
+            Flow::Error => Err(self.vm.runtime_error()),
+            flow => Ok(flow),
+        }
+    }
+
+    /// Execute a single instruction of a frame, like [`Chip8Vm::run_frame`],
+    /// without counting down the timers in real time.
+    ///
+    /// For backends that execute frames themselves, and end them with
+    /// [`Chip8Vm::finish_frame`].
+    pub fn exec(&mut self) -> Chip8Result<Flow> {
+        match self.vm.exec() {
+            Flow::Error => Err(self.vm.runtime_error()),
+            flow => Ok(flow),
+        }
+    }
+
+    /// The VM was interrupted, by the program, a runtime error or the watchdog.
+    pub fn is_interrupted(&self) -> bool {
+        self.vm.cpu.trap
+    }
+
+    /// Clear the interrupt and runtime error of the VM, so the next step
+    /// executes the instruction at the program counter.
+    pub fn clear_interrupt(&mut self) {
+        self.vm.clear_interrupt();
+    }
+
+    /// Run the program until a breakpoint is reached, or the step limit is exhausted.
+    ///
+    /// The instruction at the current program counter is always executed,
+    /// so resuming while paused on a breakpoint will move past it.
+    ///
+    /// Execution also stops when the VM is interrupted or waiting for a keypress,
+    /// in which case the returned [`Flow`] is passed on to the caller.
+    pub fn resume(&mut self, step_limit: usize) -> Chip8Result<Flow> {
+        for _ in 0..step_limit {
+            let flow = self.step()?;
+            if matches!(flow, Flow::Interrupt | Flow::KeyWait) {
+                return Ok(flow);
+            }
+
+            if self.vm.breakpoints.contains(&self.vm.cpu.pc) {
+                return Ok(Flow::Breakpoint);
+            }
+        }
+
+        Ok(Flow::Ok)
+    }
+
+    pub fn pc(&self) -> usize {
+        self.vm.cpu.pc
+    }
+
+    pub fn set_pc(&mut self, address: usize) {
+        self.vm.cpu.pc = address;
+    }
+
+    pub fn sp(&self) -> usize {
+        self.vm.cpu.sp
+    }
+
+    /// General purpose registers `V0` to `VF`.
+    pub fn registers(&self) -> &[u8; REGISTER_COUNT] {
+        &self.vm.cpu.registers
+    }
+
+    pub fn registers_mut(&mut self) -> &mut [u8; REGISTER_COUNT] {
+        &mut self.vm.cpu.registers
+    }
+
+    /// Address register `I`.
+    pub fn address(&self) -> Address {
+        self.vm.cpu.address
+    }
+
+    pub fn set_address(&mut self, address: Address) {
+        self.vm.cpu.address = address;
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.vm.cpu.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.vm.cpu.sound_timer
+    }
+
+    /// Return addresses on the call stack, from the bottom to the top.
+    pub fn stack(&self) -> &[Address] {
+        self.vm.call_stack()
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.vm.cpu.ram[..]
+    }
+
+    /// Memory for editing.
+    ///
+    /// Edits aren't tracked, so the decode cache is cleared. Prefer
+    /// [`Chip8Vm::memory_view`] for small edits while the program runs.
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        self.vm.clear_decode_cache();
+        &mut self.vm.cpu.ram[..]
+    }
+
+    /// Read a byte from memory.
+    ///
+    /// Returns `None` when the address is out of bounds.
+    pub fn peek(&self, address: usize) -> Option<u8> {
+        self.vm.cpu.ram.get(address).copied()
+    }
+
+    /// Write a byte to memory, see [`MemoryView::write_byte`].
+    ///
+    /// Returns `None` when the address is out of bounds,
+    /// otherwise the previous value.
+    pub fn poke(&mut self, address: usize, value: u8) -> Option<u8> {
+        self.vm.memory_view().write_byte(address, value).ok()
+    }
 }
 
 /// Troubleshooting
@@ -774,7 +1534,7 @@ impl Chip8Vm {
             .ram
             .iter()
             .enumerate()
-            .skip(MEM_START)
+            .skip(self.conf.load_address())
             .take(count)
             .step_by(2);
         let mut buf = String::new();
@@ -791,10 +1551,11 @@ impl Chip8Vm {
 
         for y in 0..DISPLAY_HEIGHT {
             for x in 0..DISPLAY_WIDTH {
-                if self.cpu.display[x + y * DISPLAY_WIDTH] {
-                    write!(buf, "#")?;
-                } else {
-                    write!(buf, ".")?;
+                match (self.cpu.display[0].get(x, y), self.cpu.display[1].get(x, y)) {
+                    (false, false) => write!(buf, ".")?,
+                    (true, false) => write!(buf, "#")?,
+                    (false, true) => write!(buf, "+")?,
+                    (true, true) => write!(buf, "@")?,
                 }
             }
             writeln!(buf)?;
@@ -822,6 +1583,7 @@ impl Chip8Vm {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::{memory_map::RegionKind, observer::DrawRegion, quirks::SpriteEdge};
 
     #[test]
     fn test_clock_hz() {
@@ -857,13 +1619,23 @@ mod test {
         assert_eq!(vm.step(), Flow::KeyWait);
 
         // machine has yielded, waiting for any key to be pressed.
-        vm.set_key(KeyCode::Key5, true);
+        vm.push_key_event(KeyCode::Key5, KeyState::Pressed);
+
+        // machine must stall until the key is released
+        assert_eq!(vm.step(), Flow::KeyWait);
+        assert_eq!(vm.cpu.pc, MEM_START);
+        assert!(vm.cpu.key_state(0x05));
+
+        // the wait is resolved by the release of the last key pressed
+        vm.push_key_event(KeyCode::Key3, KeyState::Pressed);
+        vm.push_key_event(KeyCode::Key3, KeyState::Released);
+        vm.push_key_event(KeyCode::Key5, KeyState::Released);
 
-        // machine will now advance
+        // machine will now advance, with the last key pressed before release
         vm.step();
         assert_eq!(vm.cpu.pc, MEM_START + 2);
-        assert!(vm.cpu.key_state(0x05));
-        assert_eq!(vm.cpu.registers[1], 0x05);
+        assert!(!vm.cpu.key_state(0x05));
+        assert_eq!(vm.cpu.registers[1], 0x03);
 
         // Ensure the machine is continuing
         vm.step();
@@ -872,6 +1644,1289 @@ mod test {
         assert_eq!(vm.cpu.registers[2], 0x42); // sentinal
     }
 
+    /// A frame must execute a fixed number of instructions, then count down the timers.
+    #[test]
+    #[rustfmt::skip]
+    fn test_run_frame() {
+        let mut vm = Chip8Vm::new(Chip8Conf {
+            clock_frequency: Some(Hz(240)),
+            ..Default::default()
+        });
+        vm.load_bytecode(&[
+            0x60, 0x02, // LD v0, 2
+            0xF0, 0x18, // LD ST, v0
+            0xD0, 0x05, // DRW v0, v0, 5
+            0x12, 0x06, // JP 0x206
+        ]).unwrap();
+        assert_eq!(vm.instructions_per_frame(), 4);
+
+        let report = vm.run_frame().unwrap();
+        assert_eq!(report.instructions, 4);
+        assert!(report.draw);
+        assert!(report.sound);
+        assert!(!report.key_wait);
+        assert_eq!(vm.cpu.sound_timer, 1);
+
+        let report = vm.run_frame().unwrap();
+        assert_eq!(report.instructions, 4);
+        assert!(!report.draw);
+        assert!(!report.sound);
+        assert_eq!(vm.cpu.sound_timer, 0);
+    }
+
+    /// A frame must end early when the program waits for a key.
+    #[test]
+    fn test_run_frame_key_wait() {
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+        vm.load_bytecode(&[0xF1, 0x0A]).unwrap(); // LD v1, K
+
+        let report = vm.run_frame().unwrap();
+        assert_eq!(report.instructions, 0);
+        assert!(report.key_wait);
+        assert_eq!(vm.cpu.pc, MEM_START);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_key_policy() {
+        use crate::devices::{HeldKeys, KeyWait};
+
+        // Keys 3 and 5 are held, with 5 pressed last.
+        let program = [
+            0x60, 0x05, // LD  v0, 5
+            0x61, 0x03, // LD  v1, 3
+            0x62, 0x00, // LD  v2, 0
+            0xE0, 0x9E, // SKP v0
+            0x72, 0x01, // ADD v2, 1
+            0xE1, 0x9E, // SKP v1
+            0x72, 0x02, // ADD v2, 2
+            0x00, 0x00,
+        ];
+        let skipped = |held| {
+            let mut vm = Chip8Vm::new(Chip8Conf {
+                keys: KeyPolicy { held, ..Default::default() },
+                ..Default::default()
+            });
+            vm.load_bytecode(&program).unwrap();
+            vm.set_key(KeyCode::Key3, true);
+            vm.set_key(KeyCode::Key5, true);
+            vm.run_steps(7).unwrap();
+            vm.cpu.registers[2]
+        };
+        assert_eq!(skipped(HeldKeys::All), 0);
+        assert_eq!(skipped(HeldKeys::Latest), 2);
+        assert_eq!(skipped(HeldKeys::Lowest), 1);
+
+        // Auto-repeat releases and presses within a frame are hidden.
+        let mut vm = Chip8Vm::new(Chip8Conf {
+            keys: KeyPolicy { suppress_repeat: true, ..Default::default() },
+            ..Default::default()
+        });
+        vm.load_bytecode(&[
+            0xF1, 0x0A, // LD v1, K
+            0x12, 0x02, // JP 0x202
+        ]).unwrap();
+        assert!(vm.run_frame().unwrap().key_wait);
+        vm.push_key_event(KeyCode::Key5, KeyState::Pressed);
+        vm.push_key_event(KeyCode::Key5, KeyState::Released);
+        vm.push_key_event(KeyCode::Key5, KeyState::Pressed);
+        vm.push_key_event(KeyCode::Key5, KeyState::Released);
+        assert!(vm.state().is_key_down(KeyCode::Key5));
+
+        // The release takes effect at the end of the frame, resolving the wait next frame.
+        assert!(vm.run_frame().unwrap().key_wait);
+        assert!(!vm.state().is_key_down(KeyCode::Key5));
+        assert!(!vm.run_frame().unwrap().key_wait);
+        assert_eq!(vm.cpu.pc, MEM_START + 2);
+        assert_eq!(vm.cpu.registers[1], 5);
+
+        // Waiting for a press resolves without the release.
+        let mut vm = Chip8Vm::new(Chip8Conf {
+            keys: KeyPolicy { wait: KeyWait::Press, ..Default::default() },
+            ..Default::default()
+        });
+        vm.load_bytecode(&[0xF1, 0x0A]).unwrap(); // LD v1, K
+        assert_eq!(vm.step(), Flow::KeyWait);
+        vm.push_key_event(KeyCode::KeyA, KeyState::Pressed);
+        assert_eq!(vm.step(), Flow::Ok);
+        assert_eq!(vm.cpu.registers[1], 0xA);
+    }
+
+    /// Runtime errors must point at the faulting instruction,
+    /// not the instruction after it.
+    #[test]
+    #[rustfmt::skip]
+    fn test_runtime_error_pc() {
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+        vm.load_bytecode(&[
+            0x60, 0x01, // LD v0, 0x01
+            0x00, 0xEE, // RET  ; stack underflow
+        ]).unwrap();
+
+        match vm.run_steps(2) {
+            Err(Chip8Error::Runtime(err)) => {
+                assert_eq!(err.pc, MEM_START + 2);
+                assert_eq!(err.opcode, 0x00EE);
+                assert_eq!(err.mnemonic().as_deref(), Some("RET"));
+                assert_eq!(err.message, "call stack underflow");
+                assert_eq!(err.registers[0], 0x01);
+            }
+            result => panic!("expected runtime error, got {result:?}"),
+        }
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_profiler() {
+        let mut vm = Chip8Vm::new(Chip8Conf { profile: true, ..Default::default() });
+        vm.load_bytecode(&[
+            0x60, 0x00, // LD  v0, 0
+            0x70, 0x01, // ADD v0, 1
+            0x12, 0x02, // JP  0x202
+        ]).unwrap();
+        vm.run_steps(9).unwrap();
+
+        let profiler = vm.profiler().unwrap();
+        assert_eq!(profiler.total(), 9);
+        assert_eq!(profiler.count_at(MEM_START), 1);
+        assert_eq!(profiler.count_at(MEM_START + 2), 4);
+        assert_eq!(profiler.count_at(MEM_START + 4), 4);
+        assert!(vm.profile_report(1).unwrap().contains("ADD v0, 1"));
+
+        assert!(Chip8Vm::new(Chip8Conf::default()).profiler().is_none());
+    }
+
+    /// Calls must stop with an error past the configured stack depth.
+    #[test]
+    #[rustfmt::skip]
+    fn test_stack_depth() {
+        let program = &[
+            0x22, 0x02, // CALL 0x202
+            0x22, 0x04, // CALL 0x204
+            0x22, 0x06, // CALL 0x206
+            0x12, 0x06, // JP   0x206
+        ];
+
+        let mut vm = Chip8Vm::new(Chip8Conf { stack_size: Some(3), ..Default::default() });
+        vm.load_bytecode(program).unwrap();
+        vm.run_steps(4).unwrap();
+        assert_eq!(vm.call_stack(), &[0x202, 0x204, 0x206]);
+        assert_eq!(vm.debugger().stack(), &[0x202, 0x204, 0x206]);
+
+        let mut vm = Chip8Vm::new(Chip8Conf { stack_size: Some(2), ..Default::default() });
+        vm.load_bytecode(program).unwrap();
+        match vm.run_steps(4) {
+            Err(Chip8Error::Runtime(err)) => {
+                assert_eq!(err.message, "call stack overflow");
+                assert_eq!(err.pc, 0x204);
+            }
+            result => panic!("expected call stack overflow, got {result:?}"),
+        }
+        assert_eq!(vm.call_stack(), &[0x202, 0x204]);
+
+        // Loading a state keeps the configured depth.
+        let mut vm = Chip8Vm::new(Chip8Conf { stack_size: Some(2), ..Default::default() });
+        vm.load_bytecode(program).unwrap();
+        let state = vm.save_state();
+        vm.load_state(&state).unwrap();
+        match vm.run_steps(4) {
+            Err(Chip8Error::Runtime(err)) => assert_eq!(err.message, "call stack overflow"),
+            result => panic!("expected call stack overflow after loading state, got {result:?}"),
+        }
+        assert_eq!(vm.call_stack(), &[0x202, 0x204]);
+
+        assert_eq!(Chip8Conf::default().stack_depth(), DEFAULT_STACK_DEPTH);
+    }
+
+    /// The font must be kept when a program is loaded, and the
+    /// large font sprites must be addressable with Fx30.
+    #[test]
+    #[rustfmt::skip]
+    fn test_set_fontset() {
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+        vm.set_fontset(FontSet::builtin("schip").unwrap());
+        vm.load_bytecode(&[
+            0x60, 0x02, // LD v0, 0x02
+            0xF0, 0x30, // LD HF, v0
+        ]).unwrap();
+        vm.run_steps(2).unwrap();
+
+        let address = vm.cpu.address as usize;
+        assert_eq!(address, LARGE_FONTSET_START as usize + 2 * LARGE_FONTSET_HEIGHT);
+        assert_eq!(
+            vm.cpu.ram[address..address + LARGE_FONTSET_HEIGHT],
+            vm.fontset().large()[2 * LARGE_FONTSET_HEIGHT..3 * LARGE_FONTSET_HEIGHT]
+        );
+
+        let vip = FontSet::builtin("vip").unwrap();
+        vm.set_fontset(vip.clone());
+        assert_eq!(vm.cpu.ram[..FONTSET_DATA_LENGTH], *vip.small());
+        // The previous large font is erased.
+        assert!(vm.cpu.ram[address..address + LARGE_FONTSET_HEIGHT].iter().all(|b| *b == 0));
+    }
+
+    /// Peripherals must receive stores to their memory, provide loads
+    /// from it, and handle SYS calls.
+    #[test]
+    #[rustfmt::skip]
+    fn test_peripheral() {
+        use std::{cell::RefCell, ops::Range, rc::Rc};
+
+        /// Serial port at 0xF00, with a SYS routine that adds v1 to v0.
+        #[derive(Default)]
+        struct Serial {
+            output: Rc<RefCell<Vec<u8>>>,
+            steps: Rc<RefCell<usize>>,
+        }
+
+        impl Peripheral for Serial {
+            fn memory_range(&self) -> Range<usize> {
+                0xF00..0xF02
+            }
+
+            fn read(&mut self, address: usize) -> Option<u8> {
+                (address == 0xF01).then_some(0x42)
+            }
+
+            fn write(&mut self, _address: usize, value: u8) {
+                self.output.borrow_mut().push(value);
+            }
+
+            fn sys(&mut self, nnn: u16, registers: &mut [u8; REGISTER_COUNT]) -> bool {
+                if nnn == 0x123 {
+                    registers[0] += registers[1];
+                    true
+                } else {
+                    false
+                }
+            }
+
+            fn step(&mut self) {
+                *self.steps.borrow_mut() += 1;
+            }
+        }
+
+        let serial = Serial::default();
+        let (output, steps) = (serial.output.clone(), serial.steps.clone());
+
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+        vm.add_peripheral(serial);
+        vm.load_bytecode(&[
+            0x60, 0x07, // LD  v0, 0x07
+            0x61, 0x03, // LD  v1, 0x03
+            0x01, 0x23, // SYS 0x123
+            0xAE, 0xFF, // LD  I, 0xEFF
+            0xF2, 0x55, // LD  [I], v2
+            0xAE, 0xFF, // LD  I, 0xEFF
+            0xF2, 0x65, // LD  v2, [I]
+        ]).unwrap();
+        vm.run_steps(7).unwrap();
+
+        assert_eq!(vm.cpu.registers[0], 0x0A);
+        // Only the bytes stored inside the claimed range are passed on.
+        assert_eq!(*output.borrow(), vec![0x03, 0x00]);
+        assert_eq!(vm.cpu.registers[..3], [0x0A, 0x03, 0x42]);
+        assert_eq!(*steps.borrow(), 7);
+    }
+
+    /// Writes to executed code must be reported according to the policy.
+    #[test]
+    #[rustfmt::skip]
+    fn test_self_modify() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let program = &[
+            0xA3, 0x00, // LD I, 0x300
+            0xF0, 0x33, // LD B, v0     ; data is not code
+            0xA2, 0x00, // LD I, 0x200
+            0xF0, 0x33, // LD B, v0     ; overwrites the first instructions
+            0x12, 0x08, // JP 0x208
+        ];
+
+        let run = |self_modify| {
+            let mut vm = Chip8Vm::new(Chip8Conf { self_modify, ..Default::default() });
+            let events = Rc::new(RefCell::new(Vec::new()));
+            let sink = events.clone();
+            vm.on_self_modify(move |event| sink.borrow_mut().push(*event));
+            vm.load_bytecode(program).unwrap();
+            let result = vm.run_steps(5).map(|_| ());
+            assert!(vm.is_code(0x206) && !vm.is_code(0x300));
+            (result, events.take())
+        };
+
+        let (result, events) = run(SelfModifyPolicy::Allow);
+        assert!(result.is_ok());
+        assert!(events.is_empty());
+
+        let (result, events) = run(SelfModifyPolicy::Warn);
+        assert!(result.is_ok());
+        assert_eq!(events, vec![SelfModify { pc: 0x206, address: 0x200, len: 3 }]);
+
+        match run(SelfModifyPolicy::Trap) {
+            (Err(Chip8Error::Runtime(err)), events) => {
+                assert_eq!(err.message, "write to code");
+                assert_eq!(err.pc, 0x206);
+                assert!(events.is_empty());
+            }
+            (result, _) => panic!("expected write to code error, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn test_memory_protection() {
+        let run = |program: &[u8], memory_protection| {
+            let mut vm = Chip8Vm::new(Chip8Conf {
+                memory_protection,
+                ..Default::default()
+            });
+            vm.load_bytecode(program).unwrap();
+            vm.run_steps(3).map(|_| ())
+        };
+        let expect_error = |result: Chip8Result<()>, message: &str| match result {
+            Err(Chip8Error::Runtime(err)) => assert_eq!(err.message, message),
+            result => panic!("expected {message} error, got {result:?}"),
+        };
+
+        let store_font = &[
+            0xA0, 0x00, // LD I, 0x000
+            0xF0, 0x55, // LD [I], v0
+            0x12, 0x04, // JP 0x204
+        ];
+        let jump_font = &[
+            0x10, 0x50, // JP 0x050
+        ];
+
+        assert!(run(store_font, MemoryProtection::default()).is_ok());
+        assert!(run(jump_font, MemoryProtection::default()).is_ok());
+
+        let read_only_font = MemoryProtection {
+            read_only_font: true,
+            ..Default::default()
+        };
+        expect_error(run(store_font, read_only_font), "write to read-only memory");
+        assert!(run(jump_font, read_only_font).is_ok());
+
+        let trap_low_execute = MemoryProtection {
+            trap_low_execute: true,
+            ..Default::default()
+        };
+        assert!(run(store_font, trap_low_execute).is_ok());
+        expect_error(
+            run(jump_font, trap_low_execute),
+            "execute in protected memory",
+        );
+
+        // The map follows the loaded program.
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+        vm.load_bytecode(store_font).unwrap();
+        assert_eq!(vm.memory_map().kind_at(0x205), Some(RegionKind::Program));
+        assert_eq!(vm.memory_map().kind_at(0x206), Some(RegionKind::WorkRam));
+    }
+
+    #[test]
+    fn test_load_address() {
+        let mut vm = Chip8Vm::new(Chip8Conf {
+            load_address: Some(ETI660_START),
+            ..Default::default()
+        });
+        vm.load_rom_asm(".start\n    LD v0, 7\n    JP .start\n")
+            .unwrap();
+        assert_eq!(vm.cpu.pc, ETI660_START);
+        assert_eq!(
+            &vm.cpu.ram[ETI660_START..ETI660_START + 4],
+            &[0x60, 0x07, 0x16, 0x00]
+        );
+
+        vm.run_steps(3).unwrap();
+        assert_eq!(vm.cpu.pc, ETI660_START + 2);
+        assert_eq!(vm.cpu.registers[0], 7);
+        assert_eq!(
+            vm.memory_map().kind_at(0x200),
+            Some(RegionKind::Interpreter)
+        );
+
+        // The program must still fit in memory.
+        let program = vec![0; MEM_SIZE - ETI660_START + 2];
+        assert!(matches!(
+            vm.load_bytecode(&program),
+            Err(Chip8Error::LargeProgram)
+        ));
+    }
+
+    #[test]
+    fn test_timer_hook() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let sink = events.clone();
+        vm.set_timer_hook(move |event| sink.borrow_mut().push(event));
+        vm.load_bytecode(&[
+            0x60, 0x02, // LD v0, 2
+            0xF0, 0x15, // LD DT, v0
+            0x61, 0x01, // LD v1, 1
+            0xF1, 0x18, // LD ST, v1
+        ])
+        .unwrap();
+
+        for _ in 0..4 {
+            vm.exec();
+        }
+        for _ in 0..3 {
+            vm.tick_timers();
+        }
+
+        assert_eq!(
+            *events.borrow(),
+            [
+                TimerEvent::SoundOn,
+                TimerEvent::SoundOff,
+                TimerEvent::DelayExpired,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_observer() {
+        use std::{cell::RefCell, rc::Rc};
+
+        struct Recorder(Rc<RefCell<Vec<String>>>);
+
+        impl VmObserver for Recorder {
+            fn on_instruction(&mut self, pc: usize, opcode: u16) {
+                self.0.borrow_mut().push(format!("0x{pc:04X} {opcode:04X}"));
+            }
+
+            fn on_draw(&mut self, region: DrawRegion) {
+                self.0.borrow_mut().push(format!("draw {region:?}"));
+            }
+
+            fn on_sound(&mut self, on: bool) {
+                self.0.borrow_mut().push(format!("sound {on}"));
+            }
+
+            fn on_key_wait(&mut self) {
+                self.0.borrow_mut().push("key wait".to_string());
+            }
+        }
+
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+        let events = Rc::new(RefCell::new(Vec::new()));
+        vm.add_observer(Recorder(events.clone()));
+        vm.load_bytecode(&[
+            0x00, 0xE0, // CLS
+            0x60, 0x02, // LD v0, 2
+            0xF0, 0x29, // LD F, v0
+            0xD0, 0x05, // DRW v0, v0, 5
+            0xF0, 0x18, // LD ST, v0
+            0xF1, 0x0A, // LD v1, K
+        ])
+        .unwrap();
+
+        for _ in 0..7 {
+            vm.exec();
+        }
+        vm.tick_timers();
+        vm.tick_timers();
+
+        assert_eq!(
+            *events.borrow(),
+            [
+                "0x0200 00E0",
+                "draw DrawRegion { x: 0, y: 0, width: 64, height: 32 }",
+                "0x0202 6002",
+                "0x0204 F029",
+                "0x0206 D005",
+                "draw DrawRegion { x: 2, y: 2, width: 8, height: 5 }",
+                "0x0208 F018",
+                "sound true",
+                "0x020A F10A",
+                "key wait",
+                "0x020A F10A",
+                "sound false",
+            ]
+        );
+    }
+
+    /// Malformed programs raise runtime errors, instead of panicking.
+    #[test]
+    #[rustfmt::skip]
+    fn test_runtime_error_bounds() {
+        let cases: &[(&[u8], &str)] = &[
+            // CALL 0x200 ; recurses until the stack is full
+            (&[0x22, 0x00], "call stack overflow"),
+            // LD I, 0xFFE ; LD [I], v3
+            (&[0xAF, 0xFE, 0xF3, 0x55], "memory access out of bounds"),
+            // LD I, 0xFFF ; LD v1, [I]
+            (&[0xAF, 0xFF, 0xF1, 0x65], "memory access out of bounds"),
+            // LD I, 0xFFE ; LD B, v0
+            (&[0xAF, 0xFE, 0xF0, 0x33], "memory access out of bounds"),
+            // LD I, 0xFFC ; DRW v0, v0, 5
+            (&[0xAF, 0xFC, 0xD0, 0x05], "memory access out of bounds"),
+            // JP 0xFFF
+            (&[0x1F, 0xFF], "program counter out of bounds"),
+        ];
+
+        for (bytecode, message) in cases {
+            let mut vm = Chip8Vm::new(Chip8Conf::default());
+            vm.load_bytecode(bytecode).unwrap();
+
+            match vm.run_steps(STACK_SIZE + 1) {
+                Err(Chip8Error::Runtime(err)) => assert_eq!(err.message, *message),
+                result => panic!("expected runtime error '{message}', got {result:?}"),
+            }
+        }
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_debugger_breakpoint() {
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+        vm.load_bytecode(&[
+            0x60, 0x01, // LD v0, 0x01
+            0x70, 0x01, // ADD v0, 0x01
+            0x12, 0x02, // JP 0x202
+        ]).unwrap();
+
+        let mut debugger = vm.debugger();
+        debugger.add_breakpoint(MEM_START + 2);
+
+        assert_eq!(debugger.resume(100).unwrap(), Flow::Breakpoint);
+        assert_eq!(debugger.pc(), MEM_START + 2);
+        assert_eq!(debugger.registers()[0], 1);
+
+        // Resuming must move past the current breakpoint.
+        assert_eq!(debugger.resume(100).unwrap(), Flow::Breakpoint);
+        assert_eq!(debugger.registers()[0], 2);
+
+        debugger.registers_mut()[0] = 0x40;
+        assert_eq!(debugger.step().unwrap(), Flow::Ok);
+        assert_eq!(debugger.registers()[0], 0x41);
+
+        // Without breakpoints, execution stops at the step limit.
+        debugger.clear_breakpoints();
+        assert_eq!(debugger.resume(10).unwrap(), Flow::Ok);
+    }
+
+    /// An interrupt stops stepping, until it's explicitly cleared.
+    #[test]
+    #[rustfmt::skip]
+    fn test_debugger_interrupt() {
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+        vm.load_bytecode(&[
+            0x60, 0x01, // LD v0, 0x01
+            0x00, 0xEE, // RET, with an empty stack
+        ]).unwrap();
+
+        let mut debugger = vm.debugger();
+        assert_eq!(debugger.step().unwrap(), Flow::Ok);
+        assert!(debugger.step().is_err());
+        assert!(debugger.is_interrupted());
+        assert_eq!(debugger.step().unwrap(), Flow::Interrupt);
+        assert_eq!(debugger.resume(10).unwrap(), Flow::Interrupt);
+        assert_eq!(debugger.pc(), MEM_START + 4);
+
+        debugger.clear_interrupt();
+        assert!(!debugger.is_interrupted());
+        debugger.set_pc(MEM_START);
+        assert_eq!(debugger.step().unwrap(), Flow::Ok);
+    }
+
+    #[test]
+    fn test_debugger_memory() {
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+        vm.load_bytecode(&[0x60, 0x01]).unwrap();
+
+        let mut debugger = vm.debugger();
+        assert_eq!(debugger.peek(MEM_START), Some(0x60));
+        assert_eq!(debugger.poke(MEM_START + 1, 0x42), Some(0x01));
+        assert_eq!(debugger.peek(MEM_START + 1), Some(0x42));
+        assert_eq!(debugger.peek(MEM_SIZE), None);
+        assert_eq!(debugger.poke(MEM_SIZE, 0x00), None);
+
+        debugger.step().unwrap();
+        assert_eq!(debugger.registers()[0], 0x42);
+    }
+
+    #[test]
+    fn test_xochip_planes() {
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+        let program = concat!(
+            "PLANE 3         \n",
+            "LD I, .sprite   \n",
+            "DRW v0, v0, 1   \n",
+            "PLANE 2         \n",
+            "CLS             \n",
+            ".sprite         \n",
+            "0b10000000 0b01000000 \n",
+        );
+        vm.load_rom_asm(program).unwrap();
+
+        vm.run_steps(3).unwrap();
+        // Each selected plane is drawn with the next row of sprite data.
+        assert!(vm.plane_buffer(0).unwrap()[0]);
+        assert!(!vm.plane_buffer(0).unwrap()[1]);
+        assert!(!vm.plane_buffer(1).unwrap()[0]);
+        assert!(vm.plane_buffer(1).unwrap()[1]);
+
+        // Clearing only affects the selected planes.
+        vm.run_steps(2).unwrap();
+        assert!(vm.plane_buffer(0).unwrap()[0]);
+        assert!(!vm.plane_buffer(1).unwrap()[1]);
+        assert!(vm.plane_buffer(2).is_none());
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_xochip_long_load() {
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+        vm.load_bytecode(&[
+            0x30, 0x00,             // SE v0, 0x00
+            0xF0, 0x00, 0x0A, 0xBC, // LD I, LONG 0x0ABC  ; skipped
+            0xF0, 0x00, 0x01, 0x23, // LD I, LONG 0x0123
+        ]).unwrap();
+
+        // Skip must step over the whole four byte instruction.
+        vm.run_steps(1).unwrap();
+        assert_eq!(vm.cpu.pc, MEM_START + 6);
+
+        vm.run_steps(1).unwrap();
+        assert_eq!(vm.cpu.address, 0x0123);
+        assert_eq!(vm.cpu.pc, MEM_START + 10);
+
+        // Without XO-CHIP, F000 is an unknown two byte instruction.
+        let mut vm = Chip8Vm::new(Chip8Conf::with_variant(Variant::SuperChip));
+        vm.load_bytecode(&[
+            0x30, 0x00,             // SE v0, 0x00
+            0xF0, 0x00,             // skipped
+            0x60, 0x05,             // LD v0, 0x05
+        ]).unwrap();
+        vm.run_steps(2).unwrap();
+        assert_eq!(vm.cpu.registers[0], 0x05);
+
+        // The trailing address must be in memory.
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+        vm.load_bytecode(&[0x1F, 0xFE]).unwrap(); // JP 0xFFE
+        vm.memory_view().write(MEM_SIZE - 2, &[0xF0, 0x00]).unwrap();
+        match vm.run_steps(2) {
+            Err(Chip8Error::Runtime(err)) => assert_eq!(err.message, "program counter out of bounds"),
+            result => panic!("expected runtime error, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn test_xochip_audio() {
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+        let program = concat!(
+            "LD I, .pattern  \n",
+            "AUDIO           \n",
+            "LD v0, 112      \n",
+            "PITCH v0        \n",
+            ".pattern        \n",
+            "0x00 0x11 0x22 0x33 0x44 0x55 0x66 0x77 \n",
+            "0x88 0x99 0xAA 0xBB 0xCC 0xDD 0xEE 0xFF \n",
+        );
+        vm.load_rom_asm(program).unwrap();
+
+        assert_eq!(vm.audio_state().pattern, None);
+        assert_eq!(vm.audio_state().pitch, DEFAULT_PITCH);
+
+        vm.run_steps(2).unwrap();
+        assert_eq!(vm.audio_pattern()[0], 0x00);
+        assert_eq!(vm.audio_pattern()[1], 0x11);
+        assert_eq!(vm.audio_pattern()[15], 0xFF);
+        assert_eq!(vm.audio_state().pattern, Some(*vm.audio_pattern()));
+
+        vm.run_steps(2).unwrap();
+        assert_eq!(vm.audio_state().pitch, 112);
+        assert_eq!(vm.audio_state().pattern_rate(), 8000.0);
+
+        // The sound hardware is part of the saved state.
+        let state = vm.save_state();
+        vm.load_rom_asm("CLS").unwrap();
+        assert_eq!(vm.audio_state().pattern, None);
+        vm.load_state(&state).unwrap();
+        assert_eq!(vm.audio_state().pitch, 112);
+        assert!(vm.audio_state().pattern.is_some());
+    }
+
+    /// Execute the handler of an instruction, without fetching it from memory.
+    fn exec_handler(vm: &mut Chip8Vm, instr: [u8; 2]) -> Flow {
+        let handler = vm.dispatch.lookup(u16::from_be_bytes(instr)).unwrap();
+        handler(vm, Operands::decode(instr))
+    }
+
+    /// Execute a register arithmetic instruction `8xyn`
+    /// with v0 and v1 as operands, returning (v0, vF).
+    fn exec_arithmetic(vm: &mut Chip8Vm, n: u8, x: u8, y: u8) -> (u8, u8) {
+        vm.cpu.registers[0] = x;
+        vm.cpu.registers[1] = y;
+        vm.cpu.registers[0xF] = 0xAA;
+        assert_eq!(exec_handler(vm, [0x80, 0x10 | n]), Flow::Ok);
+        (vm.cpu.registers[0], vm.cpu.registers[0xF])
+    }
+
+    /// ADD, SUB and SUBN must wrap the result and set the carry flag
+    /// for every pair of operands.
+    #[test]
+    fn test_arithmetic_exhaustive() {
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+
+        for x in 0..=u8::MAX {
+            for y in 0..=u8::MAX {
+                let sum = x as u16 + y as u16;
+                assert_eq!(
+                    exec_arithmetic(&mut vm, 0x4, x, y),
+                    ((sum & 0xFF) as u8, (sum > 0xFF) as u8),
+                    "ADD 0x{x:02X}, 0x{y:02X}"
+                );
+                assert_eq!(
+                    exec_arithmetic(&mut vm, 0x5, x, y),
+                    (
+                        (x as i16 - y as i16).rem_euclid(0x100) as u8,
+                        (x >= y) as u8
+                    ),
+                    "SUB 0x{x:02X}, 0x{y:02X}"
+                );
+                assert_eq!(
+                    exec_arithmetic(&mut vm, 0x7, x, y),
+                    (
+                        (y as i16 - x as i16).rem_euclid(0x100) as u8,
+                        (y >= x) as u8
+                    ),
+                    "SUBN 0x{x:02X}, 0x{y:02X}"
+                );
+            }
+        }
+    }
+
+    /// When VF is an operand of 8xy4, 8xy5, 8xy6, 8xy7 or 8xyE, the operands
+    /// must be read before VF is written, and the flag must overwrite the result.
+    #[test]
+    fn test_arithmetic_vf_operand() {
+        // Expected (result, flag) of the instruction, for the operands.
+        fn reference(n: u8, x: u8, y: u8, quirks: Quirks) -> (u8, u8) {
+            let shifted = if quirks.shift_vy { y } else { x };
+            match n {
+                0x4 => (x.wrapping_add(y), (x as u16 + y as u16 > 0xFF) as u8),
+                0x5 => (x.wrapping_sub(y), (x >= y) as u8),
+                0x6 => (shifted >> 1, shifted & 1),
+                0x7 => (y.wrapping_sub(x), (y >= x) as u8),
+                0xE => (shifted << 1, shifted >> 7),
+                _ => unreachable!(),
+            }
+        }
+
+        for quirks in [Quirks::default(), Quirks::COSMAC_VIP, Quirks::SUPER_CHIP] {
+            let mut vm = quirks_vm(quirks);
+
+            for n in [0x4, 0x5, 0x6, 0x7, 0xE] {
+                for (vx, vy) in [(0xF, 0x1), (0x1, 0xF), (0xF, 0xF)] {
+                    for (a, b) in [(0x81, 0x02), (0x02, 0x81), (0xFF, 0xFF), (0x00, 0x01)] {
+                        vm.cpu.registers[vx as usize] = a;
+                        vm.cpu.registers[vy as usize] = b;
+                        // VF is read as both operands when Vx and Vy are the same register.
+                        let y = vm.cpu.registers[vy as usize];
+                        let x = vm.cpu.registers[vx as usize];
+
+                        let instr = [0x80 | vx, (vy << 4) | n];
+                        assert_eq!(exec_handler(&mut vm, instr), Flow::Ok);
+
+                        let (result, flag) = reference(n, x, y, quirks);
+                        let op =
+                            format!("8{vx:X}{vy:X}{n:X} with 0x{x:02X}, 0x{y:02X}, {quirks:?}");
+                        assert_eq!(vm.cpu.registers[0xF], flag, "flag of {op}");
+                        if vx != 0xF {
+                            assert_eq!(vm.cpu.registers[vx as usize], result, "result of {op}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn quirks_vm(quirks: Quirks) -> Chip8Vm {
+        Chip8Vm::new(Chip8Conf {
+            quirks,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_quirk_shift_vy() {
+        let program = &[
+            0x60, 0x01, // LD v0, 0x01
+            0x61, 0x82, // LD v1, 0x82
+            0x80, 0x1E, // SHL v0, v1
+        ];
+
+        let mut vm = quirks_vm(Quirks::default());
+        vm.load_bytecode(program).unwrap();
+        vm.run_steps(3).unwrap();
+        assert_eq!(vm.cpu.registers[0], 0x02);
+        assert_eq!(vm.cpu.registers[0xF], 0);
+
+        let mut vm = quirks_vm(Quirks { shift_vy: true, ..Default::default() });
+        vm.load_bytecode(program).unwrap();
+        vm.run_steps(3).unwrap();
+        assert_eq!(vm.cpu.registers[0], 0x04);
+        assert_eq!(vm.cpu.registers[0xF], 1);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_quirk_load_store_increment_i() {
+        let program = &[
+            0xA3, 0x00, // LD I, 0x300
+            0xF2, 0x55, // LD [I], v2
+        ];
+
+        let mut vm = quirks_vm(Quirks::default());
+        vm.load_bytecode(program).unwrap();
+        vm.run_steps(2).unwrap();
+        assert_eq!(vm.cpu.address, 0x300);
+
+        let mut vm = quirks_vm(Quirks { load_store_increment_i: true, ..Default::default() });
+        vm.load_bytecode(program).unwrap();
+        vm.run_steps(2).unwrap();
+        assert_eq!(vm.cpu.address, 0x303);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_quirk_jump_vx() {
+        let program = &[
+            0x60, 0x02, // LD v0, 0x02
+            0x63, 0x04, // LD v3, 0x04
+            0xB3, 0x00, // JP v0, 0x300
+        ];
+
+        let mut vm = quirks_vm(Quirks::default());
+        vm.load_bytecode(program).unwrap();
+        vm.run_steps(3).unwrap();
+        assert_eq!(vm.cpu.pc, 0x302);
+
+        let mut vm = quirks_vm(Quirks { jump_vx: true, ..Default::default() });
+        vm.load_bytecode(program).unwrap();
+        vm.run_steps(3).unwrap();
+        assert_eq!(vm.cpu.pc, 0x304);
+    }
+
+    #[test]
+    fn test_quirk_clip_sprites() {
+        // Sprite drawn at the bottom right corner.
+        let program = concat!(
+            "LD v0, 60       \n",
+            "LD v1, 31       \n",
+            "LD I, .sprite   \n",
+            "DRW v0, v1, 2   \n",
+            ".sprite         \n",
+            "0xFF 0xFF       \n",
+        );
+        let mut vm = quirks_vm(Quirks::default());
+        vm.load_rom_asm(program).unwrap();
+        vm.run_steps(4).unwrap();
+        let last_row = vm.display_rows().last().unwrap();
+        assert!(last_row[63]);
+        assert!(last_row[0]); // wrapped horizontally
+        assert!(vm.display_buffer().get(60, 0)); // wrapped vertically
+
+        let mut vm = quirks_vm(Quirks {
+            sprite_edge: SpriteEdge::Clip,
+            ..Default::default()
+        });
+        vm.load_rom_asm(program).unwrap();
+        vm.run_steps(4).unwrap();
+        let last_row = vm.display_rows().last().unwrap();
+        assert!(last_row[63]);
+        assert!(!last_row[0]);
+        assert!(!vm.display_buffer().get(60, 0));
+    }
+
+    #[test]
+    fn test_watchdog() {
+        let mut vm = Chip8Vm::new(Chip8Conf {
+            clock_frequency: Some(Hz(60_000)),
+            max_instructions_per_frame: Some(100),
+            watchdog_frames: Some(3),
+            ..Default::default()
+        });
+        assert_eq!(vm.instructions_per_frame(), 100);
+
+        vm.load_bytecode(&[
+            0x12, 0x00, // JP 0x200
+        ])
+        .unwrap();
+        for _ in 0..2 {
+            let report = vm.run_frame().unwrap();
+            assert_eq!(report.instructions, 100);
+            assert!(!report.interrupt);
+        }
+        let report = vm.run_frame().unwrap();
+        assert!(report.interrupt && report.watchdog);
+
+        // The program stays interrupted.
+        let report = vm.run_frame().unwrap();
+        assert_eq!(report.instructions, 0);
+        assert!(report.interrupt && !report.watchdog);
+
+        // Drawing keeps the watchdog at bay.
+        let mut vm = Chip8Vm::new(Chip8Conf {
+            watchdog_frames: Some(3),
+            ..Default::default()
+        });
+        vm.load_bytecode(&[
+            0xD0, 0x01, // DRW v0, v0, 1
+            0x12, 0x00, // JP 0x200
+        ])
+        .unwrap();
+        for _ in 0..10 {
+            assert!(!vm.run_frame().unwrap().interrupt);
+        }
+    }
+
+    #[test]
+    fn test_display_iterators() {
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+        vm.load_bytecode(&[
+            0x60, 0x3F, // LD v0, 63
+            0x61, 0x01, // LD v1, 1
+            0xA2, 0x0A, // LD I, 0x20A
+            0xD0, 0x11, // DRW v0, v1, 1
+            0x12, 0x08, // JP 0x208
+            0x80, 0x00, // sprite
+        ])
+        .unwrap();
+        vm.run_steps(4).unwrap();
+
+        assert_eq!(vm.display_rows().count(), DISPLAY_HEIGHT);
+        let lit: Vec<_> = vm
+            .display_pixels()
+            .filter(|(_, _, pixel)| *pixel)
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert_eq!(lit, [(63, 1)]);
+        assert_eq!(vm.display_pixels().count(), DISPLAY_BUFFER_SIZE);
+
+        let rows = vm.to_packed_u64_rows();
+        assert_eq!(rows[1], 1);
+        assert_eq!(rows.iter().filter(|row| **row != 0).count(), 1);
+    }
+
+    #[test]
+    fn test_sprite_edge_coordinates() {
+        // Draws a 2x2 square with the top left corner at (v0, v1).
+        fn draw(sprite_edge: SpriteEdge, x: u8, y: u8) -> Chip8Vm {
+            let mut vm = quirks_vm(Quirks {
+                sprite_edge,
+                ..Default::default()
+            });
+            vm.load_bytecode(&[
+                0x60, x, // LD v0, x
+                0x61, y, // LD v1, y
+                0xA2, 0x0A, // LD I, 0x20A
+                0xD0, 0x12, // DRW v0, v1, 2
+                0x12, 0x08, // JP 0x208
+                0xC0, 0xC0, // sprite
+            ])
+            .unwrap();
+            vm.run_steps(4).unwrap();
+            vm
+        }
+        let lit = |vm: &Chip8Vm, x: usize, y: usize| vm.display_buffer().get(x, y);
+
+        for edge in [SpriteEdge::Wrap, SpriteEdge::Clip] {
+            // The starting coordinate wraps in both modes.
+            let vm = draw(edge, 70, 33);
+            assert!(lit(&vm, 6, 1) && lit(&vm, 7, 2));
+
+            // Sprites that fit are drawn whole.
+            let vm = draw(edge, 62, 30);
+            assert!(lit(&vm, 62, 30) && lit(&vm, 63, 31));
+        }
+
+        // Past the right edge.
+        let vm = draw(SpriteEdge::Wrap, 63, 0);
+        assert!(lit(&vm, 63, 0) && lit(&vm, 0, 0) && lit(&vm, 0, 1));
+        let vm = draw(SpriteEdge::Clip, 63, 0);
+        assert!(lit(&vm, 63, 0) && !lit(&vm, 0, 0) && !lit(&vm, 0, 1));
+
+        // Past the bottom edge.
+        let vm = draw(SpriteEdge::Wrap, 0, 31);
+        assert!(lit(&vm, 0, 31) && lit(&vm, 0, 0) && lit(&vm, 1, 0));
+        let vm = draw(SpriteEdge::Clip, 0, 31);
+        assert!(lit(&vm, 0, 31) && !lit(&vm, 0, 0) && !lit(&vm, 1, 0));
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_quirk_vf_reset() {
+        let program = &[
+            0x6F, 0x01, // LD vF, 0x01
+            0x80, 0x11, // OR v0, v1
+        ];
+
+        let mut vm = quirks_vm(Quirks::default());
+        vm.load_bytecode(program).unwrap();
+        vm.run_steps(2).unwrap();
+        assert_eq!(vm.cpu.registers[0xF], 1);
+
+        let mut vm = quirks_vm(Quirks { vf_reset: true, ..Default::default() });
+        vm.load_bytecode(program).unwrap();
+        vm.run_steps(2).unwrap();
+        assert_eq!(vm.cpu.registers[0xF], 0);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_mouse() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let program = &[
+            0xA1, 0xF0, // LD I, 0x1F0
+            0xF2, 0x65, // LD v2, [I]
+        ];
+        let mouse = Mouse { x: 10, y: 20, buttons: Mouse::LEFT };
+
+        // The extension is off by default, and leaves memory alone.
+        let mut vm = quirks_vm(Quirks::default());
+        vm.load_bytecode(program).unwrap();
+        vm.set_mouse(mouse);
+        vm.run_steps(2).unwrap();
+        assert_eq!(vm.cpu.registers[0..3], [0, 0, 0]);
+
+        let mut vm = quirks_vm(Quirks { mouse: true, ..Default::default() });
+        vm.load_bytecode(program).unwrap();
+        vm.run_steps(2).unwrap();
+        assert_eq!(vm.cpu.registers[0..3], [Mouse::OUTSIDE, Mouse::OUTSIDE, 0]);
+
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let sink = changes.clone();
+        vm.add_memory_listener(move |change| sink.borrow_mut().push(*change));
+
+        vm.load_bytecode(program).unwrap();
+        vm.set_mouse(mouse);
+        vm.run_steps(2).unwrap();
+        assert_eq!(vm.cpu.registers[0..3], [10, 20, Mouse::LEFT]);
+        assert_eq!(
+            *changes.borrow(),
+            [MemoryChange { address: MOUSE_START as Address, len: MOUSE_SIZE, origin: ChangeOrigin::View }]
+        );
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_variant() {
+        let program = &[
+            0x60, 0x01, // LD v0, 0x01
+            0xF0, 0x30, // LD HF, v0
+            0xF1, 0x01, // PLANE 1
+        ];
+
+        let mut vm = Chip8Vm::new(Chip8Conf::with_variant(Variant::XoChip));
+        assert_eq!(vm.config().quirks, Quirks::XO_CHIP);
+        vm.load_bytecode(program).unwrap();
+        vm.run_steps(3).unwrap();
+        assert_eq!(vm.cpu.address, LARGE_FONTSET_START + LARGE_FONTSET_HEIGHT as u16);
+
+        let mut vm = Chip8Vm::new(Chip8Conf::with_variant(Variant::SuperChip));
+        vm.load_bytecode(program).unwrap();
+        match vm.run_steps(3) {
+            Err(Chip8Error::Runtime(err)) => {
+                assert_eq!(err.pc, 0x204);
+                assert_eq!(err.message, "instruction not supported by variant");
+            }
+            result => panic!("expected a runtime error, got {result:?}"),
+        }
+
+        let mut vm = Chip8Vm::new(Chip8Conf::with_variant(Variant::Chip48));
+        assert_eq!(vm.config().quirks, Quirks::CHIP48);
+        vm.load_bytecode(program).unwrap();
+        match vm.run_steps(2) {
+            Err(Chip8Error::Runtime(err)) => assert_eq!(err.pc, 0x202),
+            result => panic!("expected a runtime error, got {result:?}"),
+        }
+
+        // Fx3A (PITCH Vx) is only known by XO-CHIP.
+        let pitch = [0xF3, 0x3A];
+        assert!(!Variant::Chip8.supports(pitch));
+        assert!(!Variant::SuperChip.supports(pitch));
+        assert!(Variant::XoChip.supports(pitch));
+
+        let mut vm = Chip8Vm::new(Chip8Conf::with_variant(Variant::SuperChip));
+        vm.load_bytecode(&pitch).unwrap();
+        match vm.run_steps(1) {
+            Err(Chip8Error::Runtime(err)) => {
+                assert_eq!(err.message, "instruction not supported by variant");
+            }
+            result => panic!("expected a runtime error, got {result:?}"),
+        }
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_register_opcode() {
+        // Fx75: store registers in the RPL flags of SUPER-CHIP, here the top of memory.
+        fn store_flags(vm: &mut Chip8Vm, operands: Operands) -> Flow {
+            let count = operands.vx as usize + 1;
+            let registers = vm.cpu.registers;
+            vm.cpu.ram[MEM_SIZE - count..].copy_from_slice(&registers[..count]);
+            Flow::Ok
+        }
+        // Replaces the built-in addition with a saturating one.
+        fn add_saturating(vm: &mut Chip8Vm, Operands { vx, nn, .. }: Operands) -> Flow {
+            let x = &mut vm.cpu.registers[vx as usize];
+            *x = x.saturating_add(nn);
+            Flow::Ok
+        }
+
+        let program = &[
+            0x60, 0xF0, // LD v0, 0xF0
+            0x70, 0x20, // ADD v0, 0x20
+            0x61, 0x01, // LD v1, 0x01
+            0xF1, 0x75, // LD R, v1
+        ];
+
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+        vm.load_bytecode(program).unwrap();
+        assert!(vm.run_steps(4).is_err());
+        assert_eq!(vm.cpu.registers[0], 0x10);
+
+        vm.register_opcode("Fx75", store_flags).unwrap();
+        vm.register_opcode("7xnn", add_saturating).unwrap();
+        vm.load_bytecode(program).unwrap();
+        vm.run_steps(4).unwrap();
+        assert_eq!(vm.cpu.registers[0], 0xFF);
+        assert_eq!(&vm.cpu.ram[MEM_SIZE - 2..], &[0xFF, 0x01]);
+
+        assert!(matches!(vm.register_opcode("Fx7", store_flags), Err(Chip8Error::Opcode(_))));
+    }
+
+    #[test]
+    fn test_decode_cache() {
+        // Rewrites its first instruction from `LD vA, 0x00` to `LD vA, 0x2A`.
+        let program = &[
+            0x6A, 0x00, // LD vA, 0x00
+            0x60, 0x6A, // LD v0, 0x6A
+            0x61, 0x2A, // LD v1, 0x2A
+            0xA2, 0x00, // LD I, 0x200
+            0xF1, 0x55, // LD [I], v1
+            0x12, 0x00, // JP 0x200
+        ];
+
+        for decode_cache in [false, true] {
+            let mut vm = Chip8Vm::new(Chip8Conf {
+                decode_cache,
+                ..Default::default()
+            });
+            vm.load_bytecode(program).unwrap();
+            vm.run_steps(7).unwrap();
+            assert_eq!(vm.cpu.registers[0xA], 0x2A, "decode cache: {decode_cache}");
+
+            // Writes through a view are seen too.
+            vm.memory_view().write(0x200, &[0x6A, 0x07]).unwrap();
+            vm.cpu.pc = MEM_START;
+            vm.run_steps(1).unwrap();
+            assert_eq!(vm.cpu.registers[0xA], 0x07, "decode cache: {decode_cache}");
+        }
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_rewind() {
+        let mut vm = Chip8Vm::new(Chip8Conf {
+            rewind: RewindConf { capacity: 4, interval: 2 },
+            ..Default::default()
+        });
+        vm.load_bytecode(&[
+            0x70, 0x01, // ADD v0, 0x01
+            0x12, 0x00, // JP 0x200
+        ]).unwrap();
+
+        // Nothing recorded yet.
+        assert_eq!(vm.rewind(1).unwrap(), 0);
+
+        // Snapshots are taken before every second instruction.
+        vm.run_steps(10).unwrap();
+        assert_eq!(vm.cpu.registers[0], 5);
+        assert_eq!(vm.rewind_len(), 4);
+
+        // Snapshot before step 9, where 4 increments had been executed.
+        assert_eq!(vm.rewind(1).unwrap(), 1);
+        assert_eq!(vm.cpu.registers[0], 4);
+        assert_eq!(vm.cpu.pc, MEM_START);
+
+        // Rewinding further than the history goes back to the oldest snapshot.
+        assert_eq!(vm.rewind(10).unwrap(), 3);
+        assert_eq!(vm.cpu.registers[0], 1);
+        assert_eq!(vm.rewind_len(), 0);
+    }
+
+    /// Waiting for a key must not push the earlier snapshots out of the history.
+    #[test]
+    #[rustfmt::skip]
+    fn test_rewind_key_wait() {
+        let mut vm = Chip8Vm::new(Chip8Conf {
+            rewind: RewindConf { capacity: 4, interval: 1 },
+            ..Default::default()
+        });
+        vm.load_bytecode(&[
+            0x70, 0x01, // ADD v0, 0x01
+            0x70, 0x01, // ADD v0, 0x01
+            0xF1, 0x0A, // LD v1, K
+        ]).unwrap();
+
+        vm.run_steps(1_000).unwrap();
+        assert!(vm.cpu.key_wait);
+        assert_eq!(vm.rewind_len(), 3);
+
+        assert_eq!(vm.rewind(10).unwrap(), 3);
+        assert_eq!(vm.cpu.registers[0], 0);
+        assert_eq!(vm.cpu.pc, MEM_START);
+    }
+
+    const COLLISION_TEST_ASM: &str = include_str!("../../programs/collision_test.asm");
+
+    #[test]
+    fn test_load_rom_asm() {
+        let bytecode = crate::assemble(COLLISION_TEST_ASM).unwrap();
+
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+        vm.load_rom_asm(COLLISION_TEST_ASM).unwrap();
+
+        assert_eq!(vm.cpu.pc, MEM_START);
+        assert_eq!(
+            &vm.cpu.ram[MEM_START..MEM_START + bytecode.len()],
+            bytecode.as_slice()
+        );
+        vm.run_steps(100).unwrap();
+    }
+
+    #[test]
+    fn test_load_rom_file() {
+        // Assembly source is detected by file extension.
+        let asm_path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../programs/collision_test.asm"
+        );
+        let bytecode = crate::assemble(COLLISION_TEST_ASM).unwrap();
+
+        let mut vm = Chip8Vm::new(Chip8Conf::default());
+        vm.load_rom_file(asm_path).unwrap();
+        assert_eq!(
+            &vm.cpu.ram[MEM_START..MEM_START + bytecode.len()],
+            bytecode.as_slice()
+        );
+
+        // Everything else is loaded as binary.
+        let rom_path = concat!(env!("CARGO_MANIFEST_DIR"), "/programs/maze");
+        let maze = include_bytes!("../programs/maze");
+
+        vm.load_rom_file(rom_path).unwrap();
+        assert_eq!(&vm.cpu.ram[MEM_START..MEM_START + maze.len()], maze);
+    }
+
     /// Booleans must be cast to u8 1 or 0
     #[test]
     fn test_assert_bool_cast() {
@@ -914,8 +2969,8 @@ mod test {
 
         vm.run_steps(5).unwrap();
 
-        assert_eq!(vm.display_buffer()[0], false); // sprite 1
-        assert_eq!(vm.display_buffer()[4], true); // sprite 2
+        assert!(!vm.display_buffer()[0]); // sprite 1
+        assert!(vm.display_buffer()[4]); // sprite 2
         assert_eq!(vm.cpu.registers[0xF], 0);
     }
 }
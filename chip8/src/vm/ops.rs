@@ -0,0 +1,732 @@
+//! Handlers of the built-in instructions.
+
+use super::{Chip8Vm, Flow};
+use crate::{
+    constants::*,
+    devices::{KeyState, KeyWait},
+    dispatch::{DispatchTable, OpHandler, OpPattern, Operands},
+    observer::DrawRegion,
+    quirks::SpriteEdge,
+    variant::{Extension, Variant},
+};
+
+/// Built-in instructions, and the extension that added them.
+#[rustfmt::skip]
+const BUILTIN: &[(&str, Option<Extension>, OpHandler)] = &[
+    ("0nnn", None, Chip8Vm::op_sys),
+    ("00E0", None, Chip8Vm::op_cls),
+    ("00EE", None, Chip8Vm::op_ret),
+    ("1nnn", None, Chip8Vm::op_jp),
+    ("2nnn", None, Chip8Vm::op_call),
+    ("3xnn", None, Chip8Vm::op_se_byte),
+    ("4xnn", None, Chip8Vm::op_sne_byte),
+    ("5xy0", None, Chip8Vm::op_se_reg),
+    ("6xnn", None, Chip8Vm::op_ld_byte),
+    ("7xnn", None, Chip8Vm::op_add_byte),
+    ("8xy0", None, Chip8Vm::op_ld_reg),
+    ("8xy1", None, Chip8Vm::op_or),
+    ("8xy2", None, Chip8Vm::op_and),
+    ("8xy3", None, Chip8Vm::op_xor),
+    ("8xy4", None, Chip8Vm::op_add_reg),
+    ("8xy5", None, Chip8Vm::op_sub),
+    ("8xy6", None, Chip8Vm::op_shr),
+    ("8xy7", None, Chip8Vm::op_subn),
+    ("8xyE", None, Chip8Vm::op_shl),
+    ("9xy0", None, Chip8Vm::op_sne_reg),
+    ("Annn", None, Chip8Vm::op_ld_i),
+    ("Bnnn", None, Chip8Vm::op_jp_offset),
+    ("Cxnn", None, Chip8Vm::op_rnd),
+    ("Dxyn", None, Chip8Vm::op_drw),
+    ("Ex9E", None, Chip8Vm::op_skp),
+    ("ExA1", None, Chip8Vm::op_sknp),
+    ("Fx07", None, Chip8Vm::op_ld_vx_dt),
+    ("Fx0A", None, Chip8Vm::op_ld_vx_k),
+    ("Fx15", None, Chip8Vm::op_ld_dt_vx),
+    ("Fx18", None, Chip8Vm::op_ld_st_vx),
+    ("Fx1E", None, Chip8Vm::op_add_i),
+    ("Fx29", None, Chip8Vm::op_ld_f),
+    ("Fx33", None, Chip8Vm::op_ld_b),
+    ("Fx55", None, Chip8Vm::op_ld_arr_vx),
+    ("Fx65", None, Chip8Vm::op_ld_vx_arr),
+    ("Fx30", Some(Extension::SuperChip), Chip8Vm::op_ld_hf),
+    ("F000", Some(Extension::XoChip), Chip8Vm::op_ld_i_long),
+    ("Fn01", Some(Extension::XoChip), Chip8Vm::op_plane),
+    ("F002", Some(Extension::XoChip), Chip8Vm::op_audio),
+    ("Fx3A", Some(Extension::XoChip), Chip8Vm::op_pitch),
+];
+
+/// Table of the instructions known by the variant,
+/// or of every built-in instruction without one.
+pub(super) fn builtin_table(variant: Option<Variant>) -> DispatchTable {
+    let mut table = DispatchTable::default();
+    for (pattern, extension, handler) in BUILTIN {
+        let known = match (variant, extension) {
+            (Some(variant), Some(extension)) => variant.extensions().contains(extension),
+            _ => true,
+        };
+        if known {
+            let pattern = OpPattern::parse(pattern).expect("built-in opcode pattern");
+            table.register(pattern, *handler);
+        }
+    }
+    table
+}
+
+impl Chip8Vm {
+    // 0nnn (SYS addr)
+    //
+    // Call a machine code routine, when one is provided by a peripheral.
+    fn op_sys(&mut self, Operands { nnn, nn, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  SYS   0x{nnn:03X}", self.cpu.pc);
+
+        if self.sys_peripherals(nnn) || nn == 0x00 {
+            // Without a routine, calls to 0x?00 are a No Op.
+            Flow::Ok
+        } else {
+            self.cpu.set_error("unsupported opcode");
+            Flow::Error
+        }
+    }
+
+    // 00E0 (CLS)
+    //
+    // Clear display
+    fn op_cls(&mut self, _: Operands) -> Flow {
+        trace_op!("0x{:04X}  CLS", self.cpu.pc);
+
+        self.cpu.clear_display();
+        self.observe(|observer| observer.on_draw(DrawRegion::FULL));
+        Flow::Ok
+    }
+
+    // 00EE (RET)
+    //
+    // Return from a subroutine.
+    // Set the program counter to the value at the top of the stack.
+    // Subtract 1 from the stack pointer.
+    fn op_ret(&mut self, _: Operands) -> Flow {
+        trace_op!("0x{:04X}  RET", self.cpu.pc);
+
+        match self.cpu.pop_stack() {
+            Some(address) => {
+                self.cpu.pc = address as usize;
+                Flow::Jump
+            }
+            None => Flow::Error,
+        }
+    }
+
+    // 1nnn (JP addr)
+    //
+    // Jump to address.
+    fn op_jp(&mut self, Operands { nnn, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  JP    0x{nnn:03X}", self.cpu.pc);
+
+        self.cpu.pc = nnn as usize;
+        Flow::Jump
+    }
+
+    // 2nnn (CALL addr)
+    //
+    // Call subroutine at NNN.
+    fn op_call(&mut self, Operands { nnn, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  CALL  0x{nnn:03X}", self.cpu.pc);
+
+        if self.cpu.push_stack(self.cpu.pc as Address) {
+            self.cpu.pc = nnn as usize;
+            Flow::Jump
+        } else {
+            Flow::Error
+        }
+    }
+
+    // 3xnn (SE Vx, byte)
+    //
+    // Skip the next instruction if register VX equals value NN.
+    fn op_se_byte(&mut self, Operands { vx, nn, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  SE    v{vx:x},  0x{nn:02X}", self.cpu.pc);
+
+        if self.cpu.registers[vx as usize] == nn {
+            self.skip_next();
+        }
+        Flow::Ok
+    }
+
+    // 4xnn (SNE Vx, byte)
+    //
+    // Skip the next instruction if register VX does not equal value NN.
+    fn op_sne_byte(&mut self, Operands { vx, nn, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  SNE   v{vx:x},  0x{nn:02X}", self.cpu.pc);
+
+        if self.cpu.registers[vx as usize] != nn {
+            self.skip_next();
+        }
+        Flow::Ok
+    }
+
+    // 5xy0 (SE Vx, Vy)
+    //
+    // Skip the next instruction if register VX equals value VY.
+    fn op_se_reg(&mut self, Operands { vx, vy, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  SE    v{vx:x},  v{vy:x}", self.cpu.pc);
+
+        let x = self.cpu.registers[vx as usize];
+        let y = self.cpu.registers[vy as usize];
+        if x == y {
+            self.skip_next();
+        }
+        Flow::Ok
+    }
+
+    // 6xnn (LD Vx, byte)
+    //
+    // Set register VX to value NN.
+    fn op_ld_byte(&mut self, Operands { vx, nn, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  LD    v{vx:x},  0x{nn:02X}", self.cpu.pc);
+
+        self.cpu.registers[vx as usize] = nn;
+        Flow::Ok
+    }
+
+    // 7xnn (ADD Vx, byte)
+    //
+    // Add value NN to register VX. Carry flag is not set.
+    fn op_add_byte(&mut self, Operands { vx, nn, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  ADD   v{vx:x},  0x{nn:02X}", self.cpu.pc);
+
+        let x = self.cpu.registers[vx as usize];
+        self.cpu.registers[vx as usize] = x.wrapping_add(nn);
+        Flow::Ok
+    }
+
+    // 8xy0 (LD Vx, Vy)
+    //
+    // Store the value of register VY in register VX.
+    fn op_ld_reg(&mut self, Operands { vx, vy, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  LD    v{vx:x},  v{vy:x}", self.cpu.pc);
+
+        self.cpu.registers[vx as usize] = self.cpu.registers[vy as usize];
+        Flow::Ok
+    }
+
+    // 8xy1 (OR Vx, Vy)
+    //
+    // Performs bitwise OR on VX and VY, and stores the result in VX.
+    fn op_or(&mut self, Operands { vx, vy, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  OR    v{vx:x},  v{vy:x}", self.cpu.pc);
+
+        self.cpu.registers[vx as usize] |= self.cpu.registers[vy as usize];
+
+        if self.conf.quirks.vf_reset {
+            self.cpu.registers[0xF] = 0;
+        }
+        Flow::Ok
+    }
+
+    // 8xy2 (AND Vx, Vy)
+    //
+    // Performs bitwise AND on VX and VY, and stores the result in VX.
+    fn op_and(&mut self, Operands { vx, vy, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  AND   v{vx:x},  v{vy:x}", self.cpu.pc);
+
+        self.cpu.registers[vx as usize] &= self.cpu.registers[vy as usize];
+
+        if self.conf.quirks.vf_reset {
+            self.cpu.registers[0xF] = 0;
+        }
+        Flow::Ok
+    }
+
+    // 8xy3 (XOR Vx, Vy)
+    //
+    // Performs bitwise XOR on VX and VY, and stores the result in VX.
+    fn op_xor(&mut self, Operands { vx, vy, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  XOR   v{vx:x},  v{vy:x}", self.cpu.pc);
+
+        self.cpu.registers[vx as usize] ^= self.cpu.registers[vy as usize];
+
+        if self.conf.quirks.vf_reset {
+            self.cpu.registers[0xF] = 0;
+        }
+        Flow::Ok
+    }
+
+    // 8xy4 (ADD Vx, Vy)
+    //
+    // ADDs VX to VY, and stores the result in VX.
+    // Overflow is wrapped.
+    // If overflow, set VF to 1, else 0.
+    fn op_add_reg(&mut self, Operands { vx, vy, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  ADD   v{vx:x},  v{vy:x}", self.cpu.pc);
+
+        let (x, y) = (
+            self.cpu.registers[vx as usize],
+            self.cpu.registers[vy as usize],
+        );
+        let (result, carry) = x.overflowing_add(y);
+        self.set_result_with_flag(vx, result, carry);
+        Flow::Ok
+    }
+
+    // 8xy5 (SUB Vx, Vy)
+    //
+    // Subtracts VY from VX, and stores the result in VX.
+    // Underflow is wrapped.
+    // VF is set to 0 when there is a borrow, set to 1 when there isn't.
+    fn op_sub(&mut self, Operands { vx, vy, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  SUB   v{vx:x},  v{vy:x}", self.cpu.pc);
+
+        let (x, y) = (
+            self.cpu.registers[vx as usize],
+            self.cpu.registers[vy as usize],
+        );
+        let (result, borrow) = x.overflowing_sub(y);
+        self.set_result_with_flag(vx, result, !borrow);
+        Flow::Ok
+    }
+
+    // 8xy6 (SHR Vx, Vy)
+    //
+    // Shift VX right by 1, and set VF to the least-significant bit shifted out.
+    // With the `shift_vy` quirk VY is shifted into VX, otherwise VY is unused.
+    fn op_shr(&mut self, Operands { vx, vy, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  SHR   v{vx:x},  v{vy:x}", self.cpu.pc);
+
+        let x = self.shift_operand(vx, vy);
+        self.set_result_with_flag(vx, x >> 1, x & 1 == 1);
+        Flow::Ok
+    }
+
+    // 8xy7 (SUBN Vx, Vy)
+    //
+    // Subtracts VX from VY, and stores the result in VX.
+    // Underflow is wrapped.
+    // VF is set to 0 when there is a borrow, set to 1 when there isn't.
+    fn op_subn(&mut self, Operands { vx, vy, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  SUBN  v{vx:x},  v{vy:x}", self.cpu.pc);
+
+        let (x, y) = (
+            self.cpu.registers[vx as usize],
+            self.cpu.registers[vy as usize],
+        );
+        let (result, borrow) = y.overflowing_sub(x);
+        self.set_result_with_flag(vx, result, !borrow);
+        Flow::Ok
+    }
+
+    // 8xyE (SHL Vx, Vy)
+    //
+    // Shift VX left by 1, and set VF to the most-significant bit shifted out.
+    // With the `shift_vy` quirk VY is shifted into VX, otherwise VY is unused.
+    fn op_shl(&mut self, Operands { vx, vy, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  SHL   v{vx:x},  v{vy:x}", self.cpu.pc);
+
+        let x = self.shift_operand(vx, vy);
+        self.set_result_with_flag(vx, x << 1, x >> 7 == 1);
+        Flow::Ok
+    }
+
+    // 9xy0 (SNE Vx, Vy)
+    //
+    // Skip next instruction if Vx != Vy.
+    // The values of Vx and Vy are compared, and if they are not equal, the program counter is increased by 2.
+    fn op_sne_reg(&mut self, Operands { vx, vy, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  SNE   v{vx:x},  v{vy:x}", self.cpu.pc);
+
+        let x = self.cpu.registers[vx as usize];
+        let y = self.cpu.registers[vy as usize];
+        if x != y {
+            self.skip_next();
+        }
+        Flow::Ok
+    }
+
+    // Annn (LD I, addr)
+    //
+    // Set address register I to value NNN.
+    fn op_ld_i(&mut self, Operands { nnn, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  LD    I,   0x{nnn:03X}", self.cpu.pc);
+
+        self.cpu.address = nnn;
+        Flow::Ok
+    }
+
+    // Bnnn (JP V0, addr)
+    //
+    // Jump to location nnn + V0.
+    //
+    // With the `jump_vx` quirk this is Bxnn (JP Vx, addr), jump to location xnn + Vx.
+    fn op_jp_offset(&mut self, Operands { vx, nnn, .. }: Operands) -> Flow {
+        let offset_register = if self.conf.quirks.jump_vx { vx } else { 0 };
+        trace_op!(
+            "0x{:04X}  JP    v{offset_register:x},  0x{nnn:03X}",
+            self.cpu.pc
+        );
+
+        let offset = self.cpu.registers[offset_register as usize];
+        self.cpu.pc = nnn as usize + offset as usize;
+        Flow::Ok
+    }
+
+    // CXNN (RND Vx, byte)
+    //
+    // Generate random number.
+    // Set register VX to the result of bitwise AND between a random number and NN.
+    fn op_rnd(&mut self, Operands { vx, nn, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  RND   v{vx:x},  0x{nn:02X}", self.cpu.pc);
+
+        self.cpu.registers[vx as usize] = nn & self.rng.next_byte();
+        Flow::Ok
+    }
+
+    // Dxyn (DRW Vx, Vy, nibble)
+    //
+    // Draw sprite to the display buffer, at coordinate as per registers Vx and Vy.
+    // Sprite is encoded as 8 pixels wide, N pixels high, stored in bits located in
+    // memory pointed to by address register I.
+    //
+    // If the sprite is drawn over the edge of the display area, it is either wrapped around to the
+    // other side or clipped, depending on the sprite edge quirk.
+    //
+    // If the drawing operation erases existing pixels in the display buffer, register VF is set to
+    // 1, and set to 0 if no display bits are unset. This is used for collision detection.
+    fn op_drw(&mut self, Operands { vx, vy, n, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  DRAW  v{vx:x},  v{vy:x}", self.cpu.pc);
+
+        // The starting coordinate always wraps around the display.
+        let (x, y) = (
+            self.cpu.registers[vx as usize] as usize & DISPLAY_WIDTH_MASK,
+            self.cpu.registers[vy as usize] as usize & DISPLAY_HEIGHT_MASK,
+        );
+        let clip = self.conf.quirks.sprite_edge == SpriteEdge::Clip;
+        let mut is_erased = false;
+        // Shift the sprite's 8 pixels to the left edge of a display row.
+        let sprite_shift = DISPLAY_WIDTH - 8;
+        let mut offset = self.cpu.address as usize;
+
+        // The sprite data of every selected plane must be in memory.
+        let plane_count = self.cpu.planes.count_ones() as usize;
+        if !self.cpu.check_memory(offset, n as usize * plane_count) {
+            return Flow::Error;
+        }
+        self.read_peripherals(offset, n as usize * plane_count);
+
+        for (p, plane) in self.cpu.display.iter_mut().enumerate() {
+            // Only the planes selected by `Fn01` (PLANE n) are drawn to.
+            if self.cpu.planes & (1 << p) == 0 {
+                continue;
+            }
+
+            // Iteration from pointer in address register I to number of rows specified by opcode value N.
+            // When multiple planes are selected, the sprite data for each plane follows the previous.
+            self.cpu
+                .ram
+                .iter()
+                .skip(offset)
+                .take(n as usize)
+                .enumerate()
+                .for_each(|(r, row)| {
+                    // Clipped rows beyond the bottom edge are not drawn.
+                    if clip && y + r >= DISPLAY_HEIGHT {
+                        return;
+                    }
+
+                    // Each row is 8 bits representing the 8 pixels of the sprite.
+                    // Clipped pixels are shifted out past the right edge,
+                    // while wrapped pixels are rotated around to the left.
+                    let pixels = (*row as u64) << sprite_shift;
+                    let pixels = if clip {
+                        pixels >> x
+                    } else {
+                        pixels.rotate_right(x as u32)
+                    };
+
+                    // XOR erases a pixel when both the old and new values are both 1.
+                    is_erased |= plane.xor_row((y + r) & DISPLAY_HEIGHT_MASK, pixels);
+                });
+
+            offset += n as usize;
+        }
+
+        // If a pixel was erased, then a collision occurred.
+        self.cpu.registers[0xF] = is_erased as u8;
+
+        tracing::trace!(
+            target: "chip8::draw",
+            x,
+            y,
+            height = n,
+            collision = is_erased,
+            "draw"
+        );
+        let region = DrawRegion {
+            x,
+            y,
+            width: 8,
+            height: n as usize,
+        };
+        self.observe(|observer| observer.on_draw(region));
+
+        Flow::Draw
+    }
+
+    /// Check whether the key is reported as held down by the key policy.
+    fn is_key_held(&self, key_id: u8) -> bool {
+        key_id < KEY_COUNT && self.cpu.held_keys(self.conf.keys.held) & (1 << key_id) != 0
+    }
+
+    // Ex9E (SKP Vx)
+    fn op_skp(&mut self, Operands { vx, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  SKP   v{vx:x}", self.cpu.pc);
+
+        if self.is_key_held(self.cpu.registers[vx as usize & 0xF]) {
+            self.skip_next();
+        }
+        Flow::Ok
+    }
+
+    // ExA1 (SKNP Vx)
+    fn op_sknp(&mut self, Operands { vx, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  SKNP  v{vx:x}", self.cpu.pc);
+
+        if !self.is_key_held(self.cpu.registers[vx as usize & 0xF]) {
+            self.skip_next();
+        }
+        Flow::Ok
+    }
+
+    // Fx07 (LD Vx, DT)
+    //
+    // Set Vx = delay timer value.
+    // The value of DT is placed into Vx.
+    fn op_ld_vx_dt(&mut self, Operands { vx, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  LD    v{vx:x},  DT", self.cpu.pc);
+
+        self.cpu.registers[vx as usize] = self.cpu.delay_timer;
+        Flow::Ok
+    }
+
+    // Fx0A (LD Vx, K)
+    //
+    // Wait for a key press, store the value of the key in Vx.
+    // All execution stops until a key is pressed and released, then the value of that key is stored in Vx.
+    // With `KeyWait::Press`, the wait ends as soon as the key is pressed.
+    fn op_ld_vx_k(&mut self, Operands { vx, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  LD    v{vx:x},  K", self.cpu.pc);
+
+        if !self.cpu.key_wait {
+            // Only transitions that happen while waiting are considered.
+            self.cpu.key_events.clear();
+            self.cpu.key_wait_press = None;
+        }
+
+        let mut released = None;
+        while let Some(event) = self.cpu.key_events.pop_front() {
+            match event.state {
+                KeyState::Pressed if self.conf.keys.wait == KeyWait::Press => {
+                    released = Some(event.key);
+                    break;
+                }
+                KeyState::Pressed => self.cpu.key_wait_press = Some(event.key),
+                KeyState::Released if self.cpu.key_wait_press == Some(event.key) => {
+                    released = Some(event.key);
+                    break;
+                }
+                KeyState::Released => {}
+            }
+        }
+
+        if let Some(k) = released {
+            self.cpu.registers[vx as usize] = k.as_u8();
+            self.cpu.key_wait = false;
+            self.cpu.key_wait_press = None;
+            Flow::Ok
+        } else {
+            // rewind the program counter to stall the machine
+            self.cpu.pc -= 2;
+            if !self.cpu.key_wait {
+                self.observe(|observer| observer.on_key_wait());
+            }
+            self.cpu.key_wait = true;
+            Flow::KeyWait
+        }
+    }
+
+    // Fx15 (LD DT, Vx)
+    //
+    // Set delay timer = Vx.
+    // DT is set equal to the value of Vx.
+    fn op_ld_dt_vx(&mut self, Operands { vx, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  LD    DT,  v{vx:x}", self.cpu.pc);
+
+        self.cpu.delay_timer = self.cpu.registers[vx as usize];
+        Flow::Ok
+    }
+
+    // Fx18 (LD ST, Vx)
+    //
+    // Set sound timer = Vx.
+    // ST is set equal to the value of Vx.
+    fn op_ld_st_vx(&mut self, Operands { vx, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  LD    ST,  v{vx:x}", self.cpu.pc);
+
+        self.cpu.sound_timer = self.cpu.registers[vx as usize];
+        self.set_buzzer(self.cpu.sound_timer > 0);
+        Flow::Sound
+    }
+
+    // Fx1E (ADD I, Vx)
+    //
+    // Add Vx to I
+    fn op_add_i(&mut self, Operands { vx, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  LD    I,  v{vx:x}", self.cpu.pc);
+
+        let addr = self.cpu.address;
+        let x = self.cpu.registers[vx as usize & 0xF] as u16;
+        self.cpu.address = addr.wrapping_add(x);
+        Flow::Ok
+    }
+
+    // Fx29 (LD F, Vx)
+    //
+    // Set I = location of sprite for digit Vx.
+    fn op_ld_f(&mut self, Operands { vx, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  LD    F,  v{vx:x}", self.cpu.pc);
+
+        let x = self.cpu.registers[vx as usize];
+        self.cpu.address = FONTSET_START + (x as u16) * FONTSET_HEIGHT as u16;
+        Flow::Ok
+    }
+
+    // Fx30 (LD HF, Vx)
+    //
+    // SUPER-CHIP: Set I = location of large sprite for digit Vx.
+    fn op_ld_hf(&mut self, Operands { vx, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  LD    HF, v{vx:x}", self.cpu.pc);
+
+        let x = self.cpu.registers[vx as usize];
+        self.cpu.address = LARGE_FONTSET_START + (x as u16) * LARGE_FONTSET_HEIGHT as u16;
+        Flow::Ok
+    }
+
+    // Fx33 (LD B, Vx)
+    //
+    // Store the binary-coded decimal representation of Vx
+    // in the memory locations I, I+1, and I+2.
+    fn op_ld_b(&mut self, Operands { vx, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  LD    BCD,  v{vx:x}", self.cpu.pc);
+
+        let addr = self.cpu.address as usize;
+        let x = self.cpu.registers[vx as usize];
+        let digits = [x / 100 % 10, x / 10 % 10, x % 10];
+        if !self.check_read_only(addr, digits.len())
+            || !self.check_self_modify(addr, digits.len())
+            || !self.cpu.write_memory(addr, &digits)
+        {
+            return Flow::Error;
+        }
+        self.memory_written(addr, digits.len());
+        Flow::Ok
+    }
+
+    // Fx55 (LD [I], Vx)
+    //
+    // Store registers V0 through Vx in memory starting at location I.
+    fn op_ld_arr_vx(&mut self, Operands { vx, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  LD    [I],  v{vx:x}", self.cpu.pc);
+
+        let addr = self.cpu.address as usize;
+        let registers = self.cpu.registers;
+        if !self.check_read_only(addr, vx as usize + 1)
+            || !self.check_self_modify(addr, vx as usize + 1)
+        {
+            return Flow::Error;
+        }
+        if !self.cpu.write_memory(addr, &registers[0..=vx as usize]) {
+            return Flow::Error;
+        }
+        self.memory_written(addr, vx as usize + 1);
+
+        if self.conf.quirks.load_store_increment_i {
+            self.cpu.address = self.cpu.address.wrapping_add(vx as u16 + 1);
+        }
+        Flow::Ok
+    }
+
+    // Fx65 (LD Vx, [I])
+    //
+    // Read registers V0 through Vx from memory starting at location I.
+    fn op_ld_vx_arr(&mut self, Operands { vx, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  LD    v{vx:x},  [I]", self.cpu.pc);
+
+        let addr = self.cpu.address as usize;
+        self.read_peripherals(addr, vx as usize + 1);
+        let mut registers = self.cpu.registers;
+        if !self.cpu.read_memory(addr, &mut registers[0..=vx as usize]) {
+            return Flow::Error;
+        }
+        self.cpu.registers = registers;
+
+        if self.conf.quirks.load_store_increment_i {
+            self.cpu.address = self.cpu.address.wrapping_add(vx as u16 + 1);
+        }
+        Flow::Ok
+    }
+
+    // F000 nnnn (LD I, LONG addr)
+    //
+    // XO-CHIP: Load the 16-bit address in the following two bytes into I.
+    // The instruction is four bytes wide.
+    //
+    // Memory stays at 4KB, so I may hold an address beyond it. Loading one is
+    // allowed, and only the instructions that access memory through I fail.
+    fn op_ld_i_long(&mut self, _: Operands) -> Flow {
+        // The address must be in memory too.
+        if self.cpu.pc + 1 >= MEM_SIZE {
+            self.cpu.set_error("program counter out of bounds");
+            return Flow::Error;
+        }
+
+        let [hi, lo] = self.cpu.instr();
+        let nnnn = ((hi as u16) << 8) | lo as u16;
+        trace_op!("0x{:04X}  LD    I,  LONG 0x{nnnn:04X}", self.cpu.pc);
+
+        self.cpu.address = nnnn;
+        self.cpu.pc += 2;
+        Flow::Ok
+    }
+
+    // Fn01 (PLANE n)
+    //
+    // XO-CHIP: Select the display planes affected by drawing and clearing.
+    fn op_plane(&mut self, Operands { vx, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  PLANE {vx}", self.cpu.pc);
+
+        self.cpu.planes = vx & ((1 << PLANE_COUNT) - 1) as u8;
+        Flow::Ok
+    }
+
+    // F002 (AUDIO)
+    //
+    // XO-CHIP: Load the 16 byte audio pattern buffer from memory starting at location I.
+    fn op_audio(&mut self, _: Operands) -> Flow {
+        trace_op!("0x{:04X}  AUDIO", self.cpu.pc);
+
+        let addr = self.cpu.address as usize;
+        for (i, byte) in self.cpu.audio_pattern.iter_mut().enumerate() {
+            *byte = self.cpu.ram[(addr + i) & (MEM_SIZE - 1)];
+        }
+        self.cpu.audio_pattern_loaded = true;
+        Flow::Sound
+    }
+
+    // Fx3A (PITCH Vx)
+    //
+    // XO-CHIP: Set the playback rate of the audio pattern to 4000*2^((Vx-64)/48) bits per second.
+    fn op_pitch(&mut self, Operands { vx, .. }: Operands) -> Flow {
+        trace_op!("0x{:04X}  PITCH v{vx:x}", self.cpu.pc);
+
+        self.cpu.pitch = self.cpu.registers[vx as usize];
+        Flow::Sound
+    }
+}
@@ -0,0 +1,368 @@
+; ================================================ ;
+;                 Opcode Conformance               ;
+;                                                  ;
+; Checks the results and flags of the instruction  ;
+; set, in the manner of the public domain opcode   ;
+; test ROMs.                                       ;
+;                                                  ;
+; Every check draws a glyph in a grid of 8 columns ;
+; from the top left of the display. A filled box   ;
+; is a pass, and a cross is a failure.             ;
+;                                                  ;
+; The checks avoid behaviour that differs between  ;
+; quirk profiles, so every profile must pass all   ;
+; of them.                                         ;
+; ================================================ ;
+
+; Registers
+;   v8, v9  position of the next glyph
+;   va, vb  result and its expected value
+;   vc, vd  flag and its expected value
+
+; -----------------------------------------------------------------------------
+.main
+    CLS
+    LD      v8,  0
+    LD      v9,  0
+
+    ; 3xnn (SE Vx, byte)
+    LD      v0,  5
+    LD      va,  1
+    SE      v0,  5
+    LD      va,  0
+    LD      vb,  1
+    CALL    .check_value
+
+    ; 4xnn (SNE Vx, byte)
+    LD      v0,  5
+    LD      va,  1
+    SNE     v0,  6
+    LD      va,  0
+    LD      vb,  1
+    CALL    .check_value
+
+    ; 5xy0 (SE Vx, Vy)
+    LD      v0,  7
+    LD      v1,  7
+    LD      va,  1
+    SE      v0,  v1
+    LD      va,  0
+    LD      vb,  1
+    CALL    .check_value
+
+    ; 9xy0 (SNE Vx, Vy)
+    LD      v0,  7
+    LD      v1,  8
+    LD      va,  1
+    SNE     v0,  v1
+    LD      va,  0
+    LD      vb,  1
+    CALL    .check_value
+
+    ; 7xnn (ADD Vx, byte) wraps, and leaves the flag alone
+    LD      v0,  0xFF
+    LD      vf,  7
+    ADD     v0,  2
+    LD      va,  v0
+    LD      vb,  1
+    LD      vc,  vf
+    LD      vd,  7
+    CALL    .check
+
+    ; 8xy0 (LD Vx, Vy)
+    LD      v0,  0x12
+    LD      v1,  0x34
+    LD      v0,  v1
+    LD      va,  v0
+    LD      vb,  0x34
+    CALL    .check_value
+
+    ; 8xy1 (OR Vx, Vy)
+    LD      v0,  0x0C
+    LD      v1,  0x0A
+    OR      v0,  v1
+    LD      va,  v0
+    LD      vb,  0x0E
+    CALL    .check_value
+
+    ; 8xy2 (AND Vx, Vy)
+    LD      v0,  0x0C
+    LD      v1,  0x0A
+    AND     v0,  v1
+    LD      va,  v0
+    LD      vb,  0x08
+    CALL    .check_value
+
+    ; 8xy3 (XOR Vx, Vy)
+    LD      v0,  0x0C
+    LD      v1,  0x0A
+    XOR     v0,  v1
+    LD      va,  v0
+    LD      vb,  0x06
+    CALL    .check_value
+
+    ; 8xy4 (ADD Vx, Vy) without carry
+    LD      v0,  0x10
+    LD      v1,  0x20
+    ADD     v0,  v1
+    LD      va,  v0
+    LD      vb,  0x30
+    LD      vc,  vf
+    LD      vd,  0
+    CALL    .check
+
+    ; 8xy4 (ADD Vx, Vy) with carry
+    LD      v0,  0xF0
+    LD      v1,  0x20
+    ADD     v0,  v1
+    LD      va,  v0
+    LD      vb,  0x10
+    LD      vc,  vf
+    LD      vd,  1
+    CALL    .check
+
+    ; 8xy4 (ADD VF, Vy) keeps the flag over the result
+    LD      vf,  0xF0
+    LD      v1,  0x20
+    ADD     vf,  v1
+    LD      va,  vf
+    LD      vb,  1
+    CALL    .check_value
+
+    ; 8xy4 (ADD Vx, VF) reads the operand before the flag is set
+    LD      v0,  0xF0
+    LD      vf,  0x20
+    ADD     v0,  vf
+    LD      va,  v0
+    LD      vb,  0x10
+    LD      vc,  vf
+    LD      vd,  1
+    CALL    .check
+
+    ; 8xy5 (SUB Vx, Vy) without borrow
+    LD      v0,  0x30
+    LD      v1,  0x10
+    SUB     v0,  v1
+    LD      va,  v0
+    LD      vb,  0x20
+    LD      vc,  vf
+    LD      vd,  1
+    CALL    .check
+
+    ; 8xy5 (SUB Vx, Vy) with borrow
+    LD      v0,  0x10
+    LD      v1,  0x30
+    SUB     v0,  v1
+    LD      va,  v0
+    LD      vb,  0xE0
+    LD      vc,  vf
+    LD      vd,  0
+    CALL    .check
+
+    ; 8xy5 (SUB Vx, Vy) of equal values doesn't borrow
+    LD      v0,  5
+    LD      v1,  5
+    SUB     v0,  v1
+    LD      va,  v0
+    LD      vb,  0
+    LD      vc,  vf
+    LD      vd,  1
+    CALL    .check
+
+    ; 8xy7 (SUBN Vx, Vy) without borrow
+    LD      v0,  0x10
+    LD      v1,  0x30
+    SUBN    v0,  v1
+    LD      va,  v0
+    LD      vb,  0x20
+    LD      vc,  vf
+    LD      vd,  1
+    CALL    .check
+
+    ; 8xy7 (SUBN Vx, Vy) with borrow
+    LD      v0,  0x30
+    LD      v1,  0x10
+    SUBN    v0,  v1
+    LD      va,  v0
+    LD      vb,  0xE0
+    LD      vc,  vf
+    LD      vd,  0
+    CALL    .check
+
+    ; 8xx6 (SHR Vx)
+    LD      v0,  0x05
+    SHR     v0
+    LD      va,  v0
+    LD      vb,  0x02
+    LD      vc,  vf
+    LD      vd,  1
+    CALL    .check
+
+    ; 8xxE (SHL Vx)
+    LD      v0,  0x81
+    SHL     v0
+    LD      va,  v0
+    LD      vb,  0x02
+    LD      vc,  vf
+    LD      vd,  1
+    CALL    .check
+
+    ; 8xx6 (SHR VF) keeps the flag over the result
+    LD      vf,  0x04
+    SHR     vf
+    LD      va,  vf
+    LD      vb,  0
+    CALL    .check_value
+
+    ; Annn (LD I, addr), Fx1E (ADD I, Vx) and Fx65 (LD Vx, [I])
+    LD      I,   .data
+    LD      v0,  2
+    ADD     I,   v0
+    LD      v0,  [I]
+    LD      va,  v0
+    LD      vb,  0x33
+    CALL    .check_value
+
+    ; Fx33 (LD B, Vx)
+    LD      v0,  234
+    LD      I,   .scratch
+    LD      B,   v0
+    LD      I,   .scratch
+    LD      v2,  [I]
+    LD      va,  v0
+    LD      vb,  2
+    LD      vc,  v1
+    LD      vd,  3
+    CALL    .check
+    LD      va,  v2
+    LD      vb,  4
+    CALL    .check_value
+
+    ; Fx55 (LD [I], Vx) and Fx65 (LD Vx, [I]) stop at Vx
+    LD      v0,  1
+    LD      v1,  2
+    LD      v2,  3
+    LD      I,   .scratch
+    LD      [I], v2
+    LD      v0,  0
+    LD      v1,  0
+    LD      v2,  0
+    LD      I,   .scratch
+    LD      v1,  [I]
+    LD      va,  v1
+    LD      vb,  2
+    LD      vc,  v2
+    LD      vd,  0
+    CALL    .check
+
+    ; Fx29 (LD F, Vx) points at the glyph of the digit
+    LD      v0,  0xA
+    LD      F,   v0
+    LD      v0,  [I]
+    LD      va,  v0
+    LD      vb,  0xF0
+    CALL    .check_value
+
+    ; Cxnn (RAND Vx, byte) is masked
+    RAND    v0,  0x0F
+    LD      v1,  0xF0
+    AND     v1,  v0
+    LD      va,  v1
+    LD      vb,  0
+    CALL    .check_value
+
+    ; Fx15 (LD DT, Vx) and Fx07 (LD Vx, DT)
+    LD      v0,  60
+    LD      DT,  v0
+    LD      v1,  DT
+    LD      va,  1
+    SNE     v1,  0
+    LD      va,  0
+    LD      vb,  1
+    CALL    .check_value
+
+    ; 2nnn (CALL addr) and 00EE (RET)
+    LD      v0,  0
+    CALL    .subroutine
+    LD      va,  v0
+    LD      vb,  0x42
+    CALL    .check_value
+
+    ; Bnnn (JP V0, addr), with the same offset in the registers
+    ; used by the jump quirk
+    LD      v0,  2
+    LD      v1,  2
+    LD      v2,  2
+    LD      v3,  2
+    LD      va,  0
+    JP      v0,  .jump_table
+.jump_done
+    LD      vb,  1
+    CALL    .check_value
+
+    ; Dxyn (DRW Vx, Vy, n) sets the flag when pixels are erased
+    LD      I,   .pass
+    LD      v0,  56
+    LD      v1,  26
+    DRW     v0,  v1,  4
+    LD      va,  vf
+    DRW     v0,  v1,  4
+    LD      vb,  0
+    LD      vc,  vf
+    LD      vd,  1
+    CALL    .check
+
+.done
+    JP      .done
+
+; -----------------------------------------------------------------------------
+; Check the result, with no flag.
+.check_value
+    LD      vc,  0
+    LD      vd,  0
+
+; Draw a pass when the result and the flag match their expected values.
+.check
+    LD      I,   .fail
+    SNE     va,  vb
+    SE      vc,  vd
+    JP      .draw
+    LD      I,   .pass
+.draw
+    DRW     v8,  v9,  4
+    ADD     v8,  8
+    SE      v8,  64
+    RET
+    LD      v8,  0
+    ADD     v9,  6
+    RET
+
+; -----------------------------------------------------------------------------
+.subroutine
+    LD      v0,  0x42
+    RET
+
+.jump_table
+    JP      .jump_done
+    LD      va,  1
+    JP      .jump_done
+
+; -----------------------------------------------------------------------------
+.pass
+    0xF0
+    0xF0
+    0xF0
+    0xF0
+.fail
+    0x90
+    0x60
+    0x60
+    0x90
+.data
+    0x11
+    0x22
+    0x33
+.scratch
+    0x00
+    0x00
+    0x00
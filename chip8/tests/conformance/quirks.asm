@@ -0,0 +1,158 @@
+; ================================================ ;
+;                 Quirks Conformance               ;
+;                                                  ;
+; Checks the instructions that behave differently  ;
+; between interpreters, in the manner of the       ;
+; public domain quirks test ROMs.                  ;
+;                                                  ;
+; Every check draws a glyph in a grid of 8 columns ;
+; from the top left of the display. A filled box   ;
+; means the quirk is on, and a cross means it's    ;
+; off. The checks are in the order:                ;
+;                                                  ;
+;   vf_reset                                       ;
+;   vf_reset                                       ;
+;   vf_reset                                       ;
+;   shift_vy                                       ;
+;   shift_vy                                       ;
+;   load_store_increment_i                         ;
+;   load_store_increment_i                         ;
+;   jump_vx                                        ;
+;   clip_sprites                                   ;
+; ================================================ ;
+
+; Registers
+;   v8, v9  position of the next glyph
+;   va, vb  result and its expected value with the quirk on
+
+; -----------------------------------------------------------------------------
+.main
+    CLS
+    LD      v8,  0
+    LD      v9,  0
+
+    ; 8xy1 (OR Vx, Vy) resets the flag
+    LD      vf,  5
+    OR      v0,  v1
+    LD      va,  vf
+    LD      vb,  0
+    CALL    .check
+
+    ; 8xy2 (AND Vx, Vy) resets the flag
+    LD      vf,  5
+    AND     v0,  v1
+    LD      va,  vf
+    LD      vb,  0
+    CALL    .check
+
+    ; 8xy3 (XOR Vx, Vy) resets the flag
+    LD      vf,  5
+    XOR     v0,  v1
+    LD      va,  vf
+    LD      vb,  0
+    CALL    .check
+
+    ; 8xy6 (SHR Vx, Vy) shifts Vy
+    LD      v0,  0x10
+    LD      v1,  0x04
+    SHR     v0,  v1
+    LD      va,  v0
+    LD      vb,  0x02
+    CALL    .check
+
+    ; 8xyE (SHL Vx, Vy) shifts Vy
+    LD      v0,  0x10
+    LD      v1,  0x04
+    SHL     v0,  v1
+    LD      va,  v0
+    LD      vb,  0x08
+    CALL    .check
+
+    ; Fx65 (LD Vx, [I]) increments I
+    LD      I,   .data
+    LD      v1,  [I]
+    LD      v0,  [I]
+    LD      va,  v0
+    LD      vb,  0x33
+    CALL    .check
+
+    ; Fx55 (LD [I], Vx) increments I
+    LD      I,   .scratch
+    LD      v0,  0xAA
+    LD      [I], v0
+    LD      v0,  0xBB
+    LD      [I], v0
+    LD      I,   .scratch
+    LD      v1,  [I]
+    LD      va,  v1
+    LD      vb,  0xBB
+    CALL    .check
+
+    ; Bnnn (JP V0, addr) offsets by the register in the high nibble
+    LD      v0,  0
+    LD      v1,  2
+    LD      v2,  2
+    LD      v3,  2
+    LD      va,  0
+    JP      v0,  .jump_table
+.jump_done
+    LD      vb,  1
+    CALL    .check
+
+    ; Dxyn (DRW Vx, Vy, n) clips sprites at the right edge, so the
+    ; line doesn't collide with the line at the left edge
+    LD      I,   .line
+    LD      v0,  60
+    LD      v1,  28
+    LD      v2,  0
+    DRW     v0,  v1,  1
+    DRW     v2,  v1,  1
+    LD      va,  vf
+    DRW     v2,  v1,  1
+    DRW     v0,  v1,  1
+    LD      vb,  0
+    CALL    .check
+
+.done
+    JP      .done
+
+; -----------------------------------------------------------------------------
+; Draw a box when the result matches its expected value with the quirk on.
+.check
+    LD      I,   .off
+    SNE     va,  vb
+    LD      I,   .on
+    DRW     v8,  v9,  4
+    ADD     v8,  8
+    SE      v8,  64
+    RET
+    LD      v8,  0
+    ADD     v9,  6
+    RET
+
+; -----------------------------------------------------------------------------
+.jump_table
+    JP      .jump_done
+    LD      va,  1
+    JP      .jump_done
+
+; -----------------------------------------------------------------------------
+.on
+    0xF0
+    0xF0
+    0xF0
+    0xF0
+.off
+    0x90
+    0x60
+    0x60
+    0x90
+.line
+    0xFF
+.data
+    0x11
+    0x22
+    0x33
+.scratch
+    0x00
+    0x00
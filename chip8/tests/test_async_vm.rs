@@ -0,0 +1,72 @@
+use std::{thread, time::Duration};
+
+use chip8::{Chip8AsyncVm, Chip8Conf, KeyCode, KeyState, VmCommand, VmEvent};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time for the VM thread to run a few frames.
+const SETTLE: Duration = Duration::from_millis(100);
+
+/// Draws a dot in the top left corner for every key press,
+/// and spins after the second, until the watchdog stops it.
+const PROGRAM: &str = "
+    LD  I, .dot
+.loop
+    LD  v1, K
+    DRW v0, v0, 1
+    ADD v2, 1
+    SE  v2, 2
+    JP  .loop
+.end
+    JP  .end
+.dot
+    0x80 0x00
+";
+
+fn next_frame(vm: &Chip8AsyncVm) -> [u64; 32] {
+    loop {
+        match vm.recv_event_timeout(TIMEOUT) {
+            Some(VmEvent::Frame(frame)) => return frame.rows,
+            Some(VmEvent::Sound(_)) => continue,
+            event => panic!("expected a frame, got {event:?}"),
+        }
+    }
+}
+
+#[test]
+fn test_async_vm() {
+    let rom = chip8::assemble(PROGRAM).unwrap();
+    let conf = Chip8Conf {
+        watchdog_frames: Some(2),
+        ..Default::default()
+    };
+    let vm = Chip8AsyncVm::spawn(conf, &rom).unwrap();
+
+    // Only keys pressed while the program waits are counted.
+    thread::sleep(SETTLE);
+    vm.push_key_event(KeyCode::Key5, KeyState::Pressed);
+    thread::sleep(SETTLE);
+    vm.push_key_event(KeyCode::Key5, KeyState::Released);
+    assert_eq!(next_frame(&vm)[0], 1 << 63);
+
+    // Stepping while paused reports every step.
+    vm.pause();
+    vm.push_key_event(KeyCode::Key5, KeyState::Pressed);
+    vm.push_key_event(KeyCode::Key5, KeyState::Released);
+    vm.send(VmCommand::StepInstr);
+    assert_eq!(next_frame(&vm)[0], 1 << 63);
+    vm.send(VmCommand::StepInstr);
+    assert_eq!(next_frame(&vm)[0], 0);
+
+    vm.resume();
+    match vm.recv_event_timeout(TIMEOUT) {
+        Some(VmEvent::Stopped(None)) => {}
+        event => panic!("expected the program to stop, got {event:?}"),
+    }
+}
+
+#[test]
+fn test_async_vm_load_error() {
+    let rom = vec![0; 0x1000];
+    assert!(Chip8AsyncVm::spawn(Chip8Conf::default(), &rom).is_err());
+}
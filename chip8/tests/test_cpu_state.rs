@@ -0,0 +1,59 @@
+//! The CPU state must be readable from outside the crate.
+use chip8::{Chip8Conf, Chip8Vm, CpuSnapshot, KeyCode};
+
+#[test]
+#[rustfmt::skip]
+fn test_cpu_state() {
+    let mut vm = Chip8Vm::new(Chip8Conf::default());
+    vm.load_bytecode(&[
+        0x60, 0x2A, // LD   v0, 0x2A
+        0xA3, 0x00, // LD   I, 0x300
+        0x61, 0x05, // LD   v1, 5
+        0xF1, 0x15, // LD   DT, v1
+        0x22, 0x0C, // CALL 0x20C
+        0x00, 0x00,
+        0x12, 0x0C, // JP   0x20C
+    ]).unwrap();
+    vm.set_key(KeyCode::Key7, true);
+    vm.run_frame().unwrap();
+
+    let state = vm.state();
+    assert_eq!(state.pc(), 0x20C);
+    assert_eq!(state.sp(), 1);
+    assert_eq!(state.register(0), Some(0x2A));
+    assert_eq!(state.register(16), None);
+    assert_eq!(state.registers()[1], 5);
+    assert_eq!(state.address(), 0x300);
+    // The timers count down once at the end of the frame.
+    assert_eq!(state.delay_timer(), 4);
+    assert_eq!(state.sound_timer(), 0);
+    assert!(!state.buzzer());
+    assert_eq!(state.stack(), &[0x20A]);
+    assert!(state.is_key_down(KeyCode::Key7));
+    assert!(!state.is_key_down(KeyCode::Key8));
+    assert_eq!(state.keys(), 1 << 7);
+    assert!(!state.is_key_wait());
+
+    // Snapshots outlive the VM, and keep the state at the time they were taken.
+    let snapshot = state.snapshot();
+    vm.run_frame().unwrap();
+    assert_eq!(vm.state().delay_timer(), 3);
+    drop(vm);
+
+    assert_eq!(
+        snapshot,
+        CpuSnapshot {
+            pc: 0x20C,
+            sp: 1,
+            registers: [0x2A, 5, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            address: 0x300,
+            delay_timer: 4,
+            sound_timer: 0,
+            buzzer: false,
+            pitch: 64,
+            stack: vec![0x20A],
+            keys: 1 << 7,
+            key_wait: false,
+        }
+    );
+}
@@ -2,10 +2,200 @@ use chip8::prelude::*;
 
 #[test]
 fn test_disassemblerv2() {
-    const ROM: &[u8] = include_bytes!("maze.rom");
+    const ROM: &[u8] = include_bytes!("../programs/maze");
     let mut disasm = DisassemblerV2::new(ROM);
 
     let mut buf = String::new();
     disasm.disassemble(&mut buf).unwrap();
     println!("{buf}");
+
+    // Jump targets are labelled.
+    assert!(buf.contains("       .L_0x204\n0x0204 A222 LD I, .L_0x222"));
+    assert!(buf.contains("0x0212 1204 JP .L_0x204"));
+    // Sprites loaded into I are data, not instructions.
+    assert!(buf.contains("       .L_0x21E\n0x021E 8040 0b10000000 0b01000000"));
+    assert!(buf.contains("       .L_0x222\n0x0222 2040 0b00100000 0b01000000"));
+}
+
+#[test]
+fn test_disassemblerv2_control_flow() {
+    #[rustfmt::skip]
+    const ROM: &[u8] = &[
+        0x22, 0x08, // CALL .sub
+        0x3F, 0x01, // SE vF, 1
+        0x12, 0x0A, // JP .end    ; skipped instruction is still reachable
+        0x12, 0x06, // JP 0x206
+        0x00, 0xEE, // .sub RET
+        0x12, 0x0A, // .end JP .end
+        0xFF,       // unreachable odd byte
+    ];
+    let mut buf = String::new();
+    DisassemblerV2::new(ROM).disassemble(&mut buf).unwrap();
+    println!("{buf}");
+
+    assert!(buf.contains("0x0200 2208 CALL .L_0x208"));
+    assert!(buf.contains("0x0206 1206 JP .L_0x206"));
+    assert!(buf.contains("       .L_0x208\n0x0208 00EE RET"));
+    assert!(buf.contains("0x0204 120A JP .L_0x20A"));
+    assert!(buf.contains("0x020C FF00 0b11111111"));
+}
+
+#[test]
+fn test_disassemblerv2_debug_info() {
+    let source_code = ".main\n    LD I, .sprite\n    JP .main\n.sprite\n    db 0x80, 0x40\n";
+    let (bytecode, listing) = chip8::asm::Assembler::new(chip8::asm::Lexer::new(source_code))
+        .parse_with_listing()
+        .unwrap();
+    let debug_info = DebugInfo::from(&listing);
+
+    let mut buf = String::new();
+    DisassemblerV2::new(bytecode.as_slice())
+        .with_debug_info(&debug_info)
+        .disassemble(&mut buf)
+        .unwrap();
+    println!("{buf}");
+
+    assert!(buf.contains(".main"));
+    assert!(buf.contains(".sprite"));
+    assert!(!buf.contains("block-"));
+    assert!(buf.contains("0x0204 8040 0b10000000 0b01000000"));
+}
+
+#[test]
+fn test_decode_all() {
+    use chip8::disasm::ir::{LabelAddr, Op};
+
+    const ROM: &[u8] = include_bytes!("../programs/maze");
+    let instrs = Disassembler::decode_all(ROM);
+
+    assert_eq!(instrs[0].addr, 0x200);
+    assert_eq!(instrs[0].op, Op::Load_Byte { vx: 0, nn: 0 });
+    assert_eq!(
+        instrs[9].op,
+        Op::JumpAddress {
+            address: LabelAddr {
+                address: 0x204,
+                label: Some("L_0x204".into())
+            }
+        }
+    );
+    assert!(instrs[15..].iter().all(|instr| instr.op == Op::Data));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_decode_all_serde() {
+    use chip8::disasm::ir::Instr;
+
+    const ROM: &[u8] = include_bytes!("../programs/maze");
+    let instrs = Disassembler::decode_all(ROM);
+
+    let json = serde_json::to_string(&instrs).unwrap();
+    let decoded: Vec<Instr> = serde_json::from_str(&json).unwrap();
+    assert_eq!(decoded, instrs);
+}
+
+#[test]
+fn test_disassemblerv2_annotate() {
+    const ROM: &[u8] = include_bytes!("../programs/maze");
+    let mut buf = String::new();
+    DisassemblerV2::new(ROM)
+        .with_annotations()
+        .disassemble(&mut buf)
+        .unwrap();
+    println!("{buf}");
+
+    // Both diagonals are drawn, so their rows are rendered.
+    assert!(
+        buf.contains("0x021E 8040 0b10000000 0b01000000\n       ; #.......\n       ; .#......\n")
+    );
+    assert!(
+        buf.contains("0x0222 2040 0b00100000 0b01000000\n       ; ..#.....\n       ; .#......\n")
+    );
+    // Instructions are not annotated.
+    assert!(buf.contains("0x021C 121C JP .L_0x21C\n       .L_0x21E"));
+
+    let mut buf = String::new();
+    DisassemblerV2::new(ROM)
+        .with_assembler_syntax()
+        .with_annotations()
+        .disassemble(&mut buf)
+        .unwrap();
+    assert!(buf.contains("    db 0b10000000, 0b01000000\n    ; #.......\n    ; .#......\n"));
+}
+
+#[test]
+fn test_disassemblerv2_memory_map() {
+    use chip8::{MemoryMap, MemoryProtection};
+
+    #[rustfmt::skip]
+    const ROM: &[u8] = &[
+        0xA0, 0x0A, // LD I, 0x00A   ; font
+        0xA3, 0x00, // LD I, 0x300   ; work ram
+        0x12, 0x04, // JP 0x204
+    ];
+    let memory_map = MemoryMap::new(0x200, ROM.len(), MemoryProtection::default());
+
+    let mut buf = String::new();
+    DisassemblerV2::new(ROM)
+        .with_memory_map(&memory_map)
+        .disassemble(&mut buf)
+        .unwrap();
+    println!("{buf}");
+
+    assert!(buf.contains("0x0200 A00A LD I, 0x00A ; font\n"));
+    assert!(buf.contains("0x0202 A300 LD I, 0x300 ; work ram\n"));
+    // Addresses inside the bytecode are labelled instead.
+    assert!(buf.contains("0x0204 1204 JP .L_0x204\n"));
+}
+
+#[test]
+fn test_disassemblerv2_load_address() {
+    let conf = chip8::AsmConf {
+        load_address: chip8::constants::ETI660_START,
+        ..Default::default()
+    };
+    let source_code = ".main\n    LD I, .sprite\n    JP .main\n.sprite\n    db 0x80, 0x40\n";
+    let rom = chip8::asm::assemble_with(source_code, conf).unwrap();
+    assert_eq!(&rom[..4], &[0xA6, 0x04, 0x16, 0x00]);
+
+    let mut buf = String::new();
+    DisassemblerV2::new(rom.as_slice())
+        .with_load_address(chip8::constants::ETI660_START)
+        .disassemble(&mut buf)
+        .unwrap();
+    println!("{buf}");
+
+    assert!(buf.contains("0x0600 A604 LD I, .L_0x604"));
+    assert!(buf.contains("0x0602 1600 JP .L_0x600"));
+    assert!(buf.contains("0x0604 8040 0b10000000 0b01000000"));
+}
+
+#[test]
+fn test_disassemblerv2_long_load() {
+    #[rustfmt::skip]
+    const ROM: &[u8] = &[
+        0x30, 0x00,             // SE v0, 0
+        0xF0, 0x00, 0x12, 0x08, // LD I, LONG 0x1208  ; skipped as a whole
+        0x12, 0x06,             // JP 0x206
+        0xF0, 0x00,             // truncated long load
+    ];
+    let mut buf = String::new();
+    DisassemblerV2::new(ROM).disassemble(&mut buf).unwrap();
+    println!("{buf}");
+
+    assert!(buf.contains("0x0202 F000 LD I, LONG 0x1208"));
+    assert!(buf.contains("       .L_0x206\n0x0206 1206 JP .L_0x206"));
+    assert!(buf.contains("0x0208 F000 0b11110000 0b00000000"));
+
+    // Without XO-CHIP, F000 is two bytes of data, and the skip lands after it.
+    let mut buf = String::new();
+    DisassemblerV2::new(ROM)
+        .with_variant(Variant::SuperChip)
+        .disassemble(&mut buf)
+        .unwrap();
+    println!("{buf}");
+
+    assert!(buf.contains("0x0202 F000 0b11110000 0b00000000"));
+    assert!(buf.contains("       .L_0x204\n0x0204 1208 JP .L_0x208"));
 }
@@ -0,0 +1,84 @@
+//! Backends run in lockstep must report where they part ways.
+use chip8::{prelude::*, testing::lockstep, Flow, Operands, Quirks};
+
+/// Counts the instructions until the delay timer runs out, and draws the stored registers.
+const COUNTER: &str = "
+.main
+    LD  v0, 3
+    LD  DT, v0
+.wait
+    LD  v1, DT
+    ADD v2, 1
+    SE  v1, 0
+    JP  .wait
+    LD  I,  .store
+    LD  [I], v2
+    DRW v3, v3, 3
+.forever
+    JP  .forever
+.store
+";
+
+fn vm(quirks: Quirks) -> Chip8Vm {
+    Chip8Vm::new(Chip8Conf {
+        quirks,
+        seed: Some(1),
+        ..Default::default()
+    })
+}
+
+#[test]
+fn test_lockstep_agrees() {
+    let rom = chip8::assemble(COUNTER).unwrap();
+    let (mut a, mut b) = (vm(Quirks::default()), vm(Quirks::default()));
+
+    let divergence = lockstep(&mut a, &mut b, &rom, 1_000).unwrap();
+    assert_eq!(divergence, None);
+    assert_eq!(a.state().delay_timer(), 0);
+    assert_eq!(a.state().snapshot(), b.state().snapshot());
+}
+
+#[test]
+fn test_lockstep_divergence() {
+    let rom = chip8::assemble(COUNTER).unwrap();
+    let quirks = Quirks {
+        load_store_increment_i: true,
+        ..Default::default()
+    };
+    let (mut a, mut b) = (vm(Quirks::default()), vm(quirks));
+
+    let divergence = lockstep(&mut a, &mut b, &rom, 1_000)
+        .unwrap()
+        .expect("the increment quirk changes I");
+    assert_eq!(divergence.opcode, 0xF255);
+    assert_eq!(divergence.a.pc, divergence.b.pc);
+    assert_eq!(divergence.b.address, divergence.a.address + 3);
+    assert!(divergence.memory.is_empty());
+    assert_eq!(divergence.display, None);
+
+    let report = divergence.to_string();
+    assert!(report.starts_with(&format!(
+        "diverged at step {}, instruction 0xF255 at 0x{:04X}\n",
+        divergence.step, divergence.pc
+    )));
+    assert!(report.contains(&format!(
+        "  I: 0x{:04X} != 0x{:04X}\n",
+        divergence.a.address, divergence.b.address
+    )));
+    assert!(!report.contains("PC"));
+}
+
+#[test]
+fn test_lockstep_custom_opcode() {
+    let rom = chip8::assemble(COUNTER).unwrap();
+    let (mut a, mut b) = (vm(Quirks::default()), vm(Quirks::default()));
+    // A faulty store, which writes nothing.
+    b.register_opcode("Fx55", |_: &mut Chip8Vm, _: Operands| Flow::Ok)
+        .unwrap();
+
+    let divergence = lockstep(&mut a, &mut b, &rom, 1_000).unwrap().unwrap();
+    assert_eq!(divergence.opcode, 0xF255);
+    // V1 is stored as zero, which matches the untouched memory.
+    assert_eq!(divergence.memory.len(), 2);
+    assert!(divergence.to_string().contains("  [0x"));
+}
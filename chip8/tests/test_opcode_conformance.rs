@@ -0,0 +1,145 @@
+//! Interpreter conformance, checked by test ROMs under every quirk profile.
+//!
+//! The ROMs in `conformance/` follow the layout of the public domain opcode
+//! and quirks test suites: they draw a glyph for every check in a grid on
+//! the display, which is read back to find the checks that failed.
+use chip8::{
+    constants::{DISPLAY_HEIGHT, DISPLAY_WIDTH},
+    testing::HeadlessRunner,
+    Chip8Conf, Quirks,
+};
+
+const OPCODES: &str = include_str!("conformance/opcodes.asm");
+const QUIRKS: &str = include_str!("conformance/quirks.asm");
+
+/// Checks of `opcodes.asm`, in the order they're drawn.
+const OPCODE_CHECKS: &[&str] = &[
+    "3xnn SE",
+    "4xnn SNE",
+    "5xy0 SE",
+    "9xy0 SNE",
+    "7xnn ADD",
+    "8xy0 LD",
+    "8xy1 OR",
+    "8xy2 AND",
+    "8xy3 XOR",
+    "8xy4 ADD",
+    "8xy4 ADD carry",
+    "8xy4 ADD VF, Vy",
+    "8xy4 ADD Vx, VF",
+    "8xy5 SUB",
+    "8xy5 SUB borrow",
+    "8xy5 SUB equal",
+    "8xy7 SUBN",
+    "8xy7 SUBN borrow",
+    "8xx6 SHR",
+    "8xxE SHL",
+    "8xx6 SHR VF",
+    "Fx1E ADD I",
+    "Fx33 LD B tens",
+    "Fx33 LD B ones",
+    "Fx55 LD [I]",
+    "Fx29 LD F",
+    "Cxnn RAND",
+    "Fx07 LD DT",
+    "2nnn CALL",
+    "Bnnn JP V0",
+    "Dxyn DRW",
+];
+
+/// Quirks tested by `quirks.asm`, in the order they're drawn.
+const QUIRK_CHECKS: &[&str] = &[
+    "vf_reset",
+    "vf_reset",
+    "vf_reset",
+    "shift_vy",
+    "shift_vy",
+    "load_store_increment_i",
+    "load_store_increment_i",
+    "jump_vx",
+    "clip_sprites",
+];
+
+const GRID_COLUMNS: usize = 8;
+const CELL_WIDTH: usize = 8;
+const CELL_HEIGHT: usize = 6;
+const PASS: [u8; 4] = [0xF, 0xF, 0xF, 0xF];
+const FAIL: [u8; 4] = [0x9, 0x6, 0x6, 0x9];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Glyph {
+    Pass,
+    Fail,
+    /// The check didn't run, or drew something unexpected.
+    Missing,
+}
+
+/// Run the ROM until it settles, and read the glyphs of its checks.
+fn run_checks(source_code: &str, quirks: Quirks, count: usize) -> Vec<Glyph> {
+    let rom = chip8::assemble(source_code).unwrap();
+    let conf = Chip8Conf {
+        quirks,
+        ..Default::default()
+    };
+    let mut rows = [0; DISPLAY_HEIGHT];
+    HeadlessRunner::new(&rom)
+        .with_conf(conf)
+        .with_step_budget(5_000)
+        .run_with(|_, vm| rows = vm.to_packed_u64_rows())
+        .unwrap();
+
+    (0..count)
+        .map(|index| {
+            let x = (index % GRID_COLUMNS) * CELL_WIDTH;
+            let y = (index / GRID_COLUMNS) * CELL_HEIGHT;
+            let shift = DISPLAY_WIDTH - 4 - x;
+            let glyph: Vec<u8> = rows[y..y + 4]
+                .iter()
+                .map(|row| ((row >> shift) & 0xF) as u8)
+                .collect();
+            match glyph.as_slice() {
+                g if g == PASS => Glyph::Pass,
+                g if g == FAIL => Glyph::Fail,
+                _ => Glyph::Missing,
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_opcode_conformance() {
+    for profile in Quirks::PROFILES {
+        let quirks = Quirks::profile(profile).unwrap();
+        let glyphs = run_checks(OPCODES, quirks, OPCODE_CHECKS.len());
+
+        let failed: Vec<String> = OPCODE_CHECKS
+            .iter()
+            .zip(glyphs)
+            .filter(|(_, glyph)| *glyph != Glyph::Pass)
+            .map(|(name, glyph)| format!("{name} ({glyph:?})"))
+            .collect();
+        assert!(failed.is_empty(), "profile {profile}: {failed:?}");
+    }
+}
+
+#[test]
+fn test_quirks_conformance() {
+    for profile in Quirks::PROFILES {
+        let quirks = Quirks::profile(profile).unwrap();
+        let glyphs = run_checks(QUIRKS, quirks, QUIRK_CHECKS.len());
+
+        let failed: Vec<String> = QUIRK_CHECKS
+            .iter()
+            .zip(glyphs)
+            .filter(|(name, glyph)| {
+                let expected = match quirks.get(name).unwrap() {
+                    true => Glyph::Pass,
+                    false => Glyph::Fail,
+                };
+                *glyph != expected
+            })
+            .map(|(name, glyph)| format!("{name} ({glyph:?})"))
+            .collect();
+        assert!(failed.is_empty(), "profile {profile}: {failed:?}");
+    }
+}
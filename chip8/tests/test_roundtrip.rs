@@ -0,0 +1,95 @@
+//! Disassembled ROMs must assemble back into identical bytes.
+use std::fs;
+
+use chip8::{
+    asm::{assemble_with, AsmConf},
+    prelude::*,
+};
+
+fn disassemble(bytecode: &[u8]) -> String {
+    let mut buf = String::new();
+    DisassemblerV2::new(bytecode)
+        .with_assembler_syntax()
+        .disassemble(&mut buf)
+        .unwrap();
+    buf
+}
+
+#[test]
+fn test_roundtrip_programs() {
+    for entry in fs::read_dir("programs").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some() {
+            // Documentation, not a ROM.
+            continue;
+        }
+
+        let bytecode = fs::read(&path).unwrap();
+        let source_code = disassemble(&bytecode);
+        let reassembled = chip8::assemble(&source_code).unwrap_or_else(|err| {
+            panic!(
+                "{}: {}",
+                path.display(),
+                err.display_with_source(&source_code)
+            )
+        });
+
+        assert_eq!(reassembled, bytecode, "{}:\n{source_code}", path.display());
+    }
+}
+
+#[test]
+fn test_roundtrip_instructions() {
+    #[rustfmt::skip]
+    const ROM: &[u8] = &[
+        0x00, 0xE0, // CLS
+        0x06, 0x00, // SYS 0x600
+        0x61, 0x2A, // LD v1, 42
+        0x71, 0x01, // ADD v1, 1
+        0x81, 0x20, // LD v1, v2
+        0x81, 0x21, // OR v1, v2
+        0x81, 0x22, // AND v1, v2
+        0x81, 0x23, // XOR v1, v2
+        0x81, 0x24, // ADD v1, v2
+        0x81, 0x25, // SUB v1, v2
+        0x81, 0x16, // SHR v1
+        0x81, 0x27, // SUBN v1, v2
+        0x81, 0x2E, // SHL v1, v2
+        0x31, 0x01, // SE v1, 1
+        0x41, 0x01, // SNE v1, 1
+        0x51, 0x20, // SE v1, v2
+        0x91, 0x20, // SNE v1, v2
+        0xE1, 0x9E, // SKP v1
+        0xE1, 0xA1, // SKNP v1
+        0xA2, 0x4A, // LD I, .data
+        0xC1, 0x0F, // RAND v1, 15
+        0xD1, 0x21, // DRW v1, v2, 1
+        0xF1, 0x07, // LD v1, DT
+        0xF1, 0x0A, // LD v1, K
+        0xF1, 0x15, // LD DT, v1
+        0xF1, 0x18, // LD ST, v1
+        0xF1, 0x1E, // ADD I, v1
+        0xF1, 0x29, // LD F, v1
+        0xF1, 0x33, // LD B, v1
+        0xF1, 0x55, // LD [I], v1
+        0xF1, 0x65, // LD v1, [I]
+        0xF3, 0x01, // PLANE 3
+        0xF0, 0x02, // AUDIO
+        0xF0, 0x00, 0x02, 0x4A, // LD I, LONG .data
+        0x22, 0x4B, // CALL .sub
+        0xB2, 0x00, // JP v0, 0x200
+        0xFF,       // .data
+        0x00, 0xEE, // .sub RET
+    ];
+    let source_code = disassemble(ROM);
+    let conf = AsmConf {
+        pad_data: false,
+        ..Default::default()
+    };
+    let reassembled = assemble_with(&source_code, conf)
+        .unwrap_or_else(|err| panic!("{}", err.display_with_source(&source_code)));
+
+    assert_eq!(reassembled, ROM, "\n{source_code}");
+    assert!(source_code.contains("LD I, LONG .L_0x24A"));
+    assert!(source_code.contains(".L_0x24A\n    db 0b11111111\n.L_0x24B\n    RET"));
+}
@@ -0,0 +1,42 @@
+//! Display output of programs must match the stored snapshots.
+use std::fs;
+
+use chip8::{capture_display, Chip8Conf, DisplayDiff};
+
+/// Draws the whole font set, two rows of digits.
+const FONT_SET: &str = "
+.main
+    LD  va, 0
+    LD  vb, 0
+    LD  vc, 0
+.loop
+    LD  F,  vc
+    DRW va, vb, 5
+    ADD vc, 1
+    ADD va, 5
+    SNE vc, 0xa
+    JP  .next_row
+    SE  vc, 0x10
+    JP  .loop
+.forever
+    JP  .forever
+.next_row
+    LD  va, 0
+    ADD vb, 6
+    JP  .loop
+";
+
+fn assert_snapshot(source_code: &str, steps: usize, snapshot_path: &str) {
+    let bytecode = chip8::assemble(source_code).unwrap();
+    let actual = capture_display(&bytecode, steps, Chip8Conf::default()).unwrap();
+    let expected = fs::read_to_string(snapshot_path).unwrap();
+
+    if let Some(diff) = DisplayDiff::new(&expected, &actual) {
+        panic!("{snapshot_path}: snapshot mismatch\n{diff}");
+    }
+}
+
+#[test]
+fn test_snapshot_font_set() {
+    assert_snapshot(FONT_SET, 500, "tests/snapshots/font_set.snap");
+}